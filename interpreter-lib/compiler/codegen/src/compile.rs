@@ -51,7 +51,8 @@ struct Compiler {
     code_stack: Vec<ir::CodeInfo>,
     symbol_table_stack: Vec<SymbolTable>,
     source_path: String,
-    current_source_location: SourceLocation,
+    /// Start and end of the node whose instructions are being emitted.
+    current_source_range: (SourceLocation, SourceLocation),
     qualified_path: Vec<String>,
     done_with_future_stmts: bool,
     future_annotations: bool,
@@ -226,7 +227,7 @@ impl Compiler {
             code_stack: vec![module_code],
             symbol_table_stack: Vec::new(),
             source_path,
-            current_source_location: SourceLocation::default(),
+            current_source_range: Default::default(),
             qualified_path: Vec::new(),
             done_with_future_stmts: false,
             future_annotations: false,
@@ -241,7 +242,7 @@ impl Compiler {
     }
 
     fn error(&mut self, error: CodegenErrorType) -> CodegenError {
-        self.error_loc(error, self.current_source_location)
+        self.error_loc(error, self.current_source_range.0)
     }
     fn error_loc(&mut self, error: CodegenErrorType, location: SourceLocation) -> CodegenError {
         CodegenError {
@@ -309,10 +310,7 @@ impl Compiler {
     fn pop_code_object(&mut self) -> CodeObject {
         let table = self.symbol_table_stack.pop().unwrap();
         assert!(table.sub_tables.is_empty());
-        self.code_stack
-            .pop()
-            .unwrap()
-            .finalize_code(self.opts.optimize)
+        self.code_stack.pop().unwrap().finalize_code()
     }
 
     // could take impl Into<Cow<str>>, but everything is borrowed from ast structs; we never
@@ -558,7 +556,7 @@ impl Compiler {
         use located_ast::*;
 
         trace!("Compiling {:?}", statement);
-        self.set_source_location(statement.location());
+        self.set_source_range(statement);
 
         match &statement {
             // we do this here because `from __future__` still executes that `from` statement at runtime,
@@ -887,6 +885,9 @@ impl Compiler {
             Stmt::Pass(_) => {
                 // No need to emit any code here :)
             }
+            Stmt::Invalid(_) => {
+                return Err(self.error(CodegenErrorType::SyntaxError("invalid syntax".to_owned())));
+            }
             Stmt::TypeAlias(_) => {}
         }
         Ok(())
@@ -1059,6 +1060,7 @@ impl Compiler {
 
                 // Check exception type:
                 self.compile_expression(exc_type)?;
+                self.set_source_range(&**exc_type);
                 emit!(
                     self,
                     Instruction::TestOperation {
@@ -1505,7 +1507,7 @@ impl Compiler {
         body: &[located_ast::Stmt],
         is_async: bool,
     ) -> CompileResult<()> {
-        let with_location = self.current_source_location;
+        let with_range = self.current_source_range;
 
         let Some((item, items)) = items.split_first() else {
             return Err(self.error(CodegenErrorType::EmptyWithItems));
@@ -1515,7 +1517,7 @@ impl Compiler {
             let final_block = self.new_block();
             self.compile_expression(&item.context_expr)?;
 
-            self.set_source_location(with_location);
+            self.current_source_range = with_range;
             if is_async {
                 emit!(self, Instruction::BeforeAsyncWith);
                 emit!(self, Instruction::GetAwaitable);
@@ -1528,7 +1530,7 @@ impl Compiler {
 
             match &item.optional_vars {
                 Some(var) => {
-                    self.set_source_location(var.location());
+                    self.set_source_range(&**var);
                     self.compile_store(var)?;
                 }
                 None => {
@@ -1544,13 +1546,13 @@ impl Compiler {
             }
             self.compile_statements(body)?;
         } else {
-            self.set_source_location(with_location);
+            self.current_source_range = with_range;
             self.compile_with(items, body, is_async)?;
         }
 
         // sort of "stack up" the layers of with blocks:
         // with a, b: body -> start_with(a) start_with(b) body() end_with(b) end_with(a)
-        self.set_source_location(with_location);
+        self.current_source_range = with_range;
         emit!(self, Instruction::PopBlock);
 
         emit!(self, Instruction::EnterFinally);
@@ -2094,8 +2096,9 @@ impl Compiler {
     fn compile_expression(&mut self, expression: &located_ast::Expr) -> CompileResult<()> {
         use located_ast::*;
         trace!("Compiling {:?}", expression);
-        let location = expression.location();
-        self.set_source_location(location);
+        // the instructions emitted after the subexpressions belong to this expression
+        let parent_range = self.current_source_range;
+        self.set_source_range(expression);
 
         match &expression {
             Expr::Call(ExprCall {
@@ -2413,7 +2416,11 @@ impl Compiler {
                 emit!(self, Instruction::Duplicate);
                 self.compile_store(target)?;
             }
+            Expr::Invalid(_) => {
+                return Err(self.error(CodegenErrorType::SyntaxError("invalid syntax".to_owned())));
+            }
         }
+        self.current_source_range = parent_range;
         Ok(())
     }
 
@@ -2800,13 +2807,14 @@ impl Compiler {
 
     // Low level helper functions:
     fn _emit(&mut self, instr: Instruction, arg: OpArg, target: ir::BlockIdx) {
-        let location = self.current_source_location;
+        let (location, end_location) = self.current_source_range;
         // TODO: insert source filename
         self.current_block().instructions.push(ir::InstructionInfo {
             instr,
             arg,
             target,
             location,
+            end_location,
         });
     }
 
@@ -2865,12 +2873,13 @@ impl Compiler {
         code.current_block = block;
     }
 
-    fn set_source_location(&mut self, location: SourceLocation) {
-        self.current_source_location = location;
+    fn set_source_range(&mut self, node: &impl Located) {
+        let location = node.location();
+        self.current_source_range = (location, node.end_location().unwrap_or(location));
     }
 
     fn get_source_line_number(&mut self) -> LineNumber {
-        let location = self.current_source_location;
+        let (location, _) = self.current_source_range;
         location.row
     }
 
@@ -3041,6 +3050,46 @@ if (True and False) or (False and True):
         ));
     }
 
+    #[test]
+    fn test_fold_constants() {
+        assert_dis_snapshot!(compile_exec(
+            "\
+x = 1 + 2 * 3
+...
+y = -(4 - 6) ** 2
+z = 'ab' * 2 + 'c'
+w = not 0
+"
+        ));
+    }
+
+    #[test]
+    fn test_thread_jumps() {
+        assert_dis_snapshot!(compile_exec(
+            "\
+if a:
+    if b:
+        x = 1
+    else:
+        x = 2
+else:
+    x = 3
+"
+        ));
+    }
+
+    #[test]
+    fn test_reorder_blocks() {
+        assert_dis_snapshot!(compile_exec(
+            "\
+if a:
+    x = 1
+else:
+    raise b
+"
+        ));
+    }
+
     #[test]
     fn test_nested_double_async_with() {
         assert_dis_snapshot!(compile_exec(
@@ -3057,4 +3106,19 @@ for stop_exc in (StopIteration('spam'), StopAsyncIteration('ham')):
 "
         ));
     }
+
+    #[test]
+    fn test_stackdepth_after_folded_loop() {
+        // with the test of `while True` folded away, the code after the loop is only reached
+        // through the break
+        let code = compile_exec(
+            "\
+while True:
+    break
+for x in y:
+    z.append(x)
+",
+        );
+        assert!(code.max_stackdepth >= 4, "{}", code.max_stackdepth);
+    }
 }
@@ -1,8 +1,12 @@
 use std::ops;
 
 use crate::IndexSet;
+use malachite_bigint::BigInt;
+use num_traits::{One, Signed, ToPrimitive, Zero};
 use rustpython_compiler_core::bytecode::{
-    CodeFlags, CodeObject, CodeUnit, ConstantData, InstrDisplayContext, Instruction, Label, OpArg,
+    Arg, BinaryOperator, CodeFlags, CodeObject, CodeUnit, ConstantData, ExceptionHandler,
+    ExceptionTableEntry, HandlerKind, InstrDisplayContext, Instruction, Label, OpArg,
+    UnaryOperator,
 };
 use rustpython_parser_core::source_code::{LineNumber, SourceLocation};
 
@@ -43,6 +47,7 @@ pub struct InstructionInfo {
     pub arg: OpArg,
     pub target: BlockIdx,
     pub location: SourceLocation,
+    pub end_location: SourceLocation,
 }
 
 // spell-checker:ignore petgraph
@@ -80,12 +85,11 @@ pub struct CodeInfo {
     pub freevar_cache: IndexSet<String>,
 }
 impl CodeInfo {
-    pub fn finalize_code(mut self, optimize: u8) -> CodeObject {
-        if optimize > 0 {
-            self.dce();
-        }
+    pub fn finalize_code(mut self) -> CodeObject {
+        self.optimize();
 
-        let max_stackdepth = self.max_stackdepth();
+        let (handlers, block_handlers, start_depths) = self.build_exception_handlers();
+        let max_stackdepth = self.max_stackdepth(&start_depths);
         let cell2arg = self.cell2arg();
 
         let CodeInfo {
@@ -134,7 +138,10 @@ impl CodeInfo {
                         *arg = new_arg;
                     }
                     let (extras, lo_arg) = arg.split();
-                    locations.extend(std::iter::repeat(info.location).take(arg.instr_size()));
+                    locations.extend(
+                        std::iter::repeat((info.location, info.end_location))
+                            .take(arg.instr_size()),
+                    );
                     instructions.extend(
                         extras
                             .map(|byte| CodeUnit::new(Instruction::ExtendedArg, byte))
@@ -152,6 +159,40 @@ impl CodeInfo {
             locations.clear()
         }
 
+        let exception_handlers = handlers
+            .into_iter()
+            .map(|handler| ExceptionHandler {
+                kind: handler.kind,
+                target: if handler.target == BlockIdx::NULL {
+                    Label(0)
+                } else {
+                    block_to_offset[handler.target.idx()]
+                },
+                depth: handler.depth,
+                parent: handler.parent,
+            })
+            .collect();
+        let mut exception_table = Vec::<ExceptionTableEntry>::new();
+        let mut offset = 0;
+        for (idx, block) in iter_blocks(&blocks) {
+            for (info, &handler) in block.instructions.iter().zip(&block_handlers[idx.idx()]) {
+                let size = info.arg.instr_size() as u32;
+                if let Some(handler) = handler {
+                    match exception_table.last_mut() {
+                        Some(last) if last.handler == handler && last.end == offset => {
+                            last.end += size
+                        }
+                        _ => exception_table.push(ExceptionTableEntry {
+                            start: offset,
+                            end: offset + size,
+                            handler,
+                        }),
+                    }
+                }
+                offset += size;
+            }
+        }
+
         CodeObject {
             flags,
             posonlyarg_count,
@@ -170,6 +211,8 @@ impl CodeInfo {
             cellvars: cellvar_cache.into_iter().collect(),
             freevars: freevar_cache.into_iter().collect(),
             cell2arg,
+            exception_handlers,
+            exception_table: exception_table.into_boxed_slice(),
         }
     }
 
@@ -206,6 +249,240 @@ impl CodeInfo {
         }
     }
 
+    /// Peephole pass over the block graph: folds constant expressions and conditions, drops
+    /// dead code, and threads or removes jumps that only lead to another jump or to the block
+    /// that follows anyway.
+    fn optimize(&mut self) {
+        for block in &mut self.blocks {
+            fold_constants(&mut block.instructions, &mut self.constants);
+        }
+        self.dce();
+        self.thread_jumps();
+        self.remove_unreachable_blocks();
+        self.reorder_blocks();
+        self.remove_redundant_jumps();
+        self.remove_unused_constants();
+    }
+
+    /// The block `block` falls through to, skipping empty ones.
+    fn first_nonempty(&self, mut block: BlockIdx) -> BlockIdx {
+        while self.blocks[block].instructions.is_empty()
+            && self.blocks[block].next != BlockIdx::NULL
+        {
+            block = self.blocks[block].next;
+        }
+        block
+    }
+
+    /// Point every jump at the first non-empty block it reaches and, for plain jumps, past any
+    /// block that starts with an unconditional jump.
+    fn thread_jumps(&mut self) {
+        for block in 0..self.blocks.len() {
+            for i in 0..self.blocks[block].instructions.len() {
+                let info = self.blocks[block].instructions[i];
+                if info.target == BlockIdx::NULL {
+                    continue;
+                }
+                let mut target = self.first_nonempty(info.target);
+                if matches!(
+                    info.instr,
+                    Instruction::Jump { .. }
+                        | Instruction::JumpIfTrue { .. }
+                        | Instruction::JumpIfFalse { .. }
+                        | Instruction::JumpIfTrueOrPop { .. }
+                        | Instruction::JumpIfFalseOrPop { .. }
+                ) {
+                    // bounded, in case the jumps form a cycle
+                    for _ in 0..self.blocks.len() {
+                        let next = match self.blocks[target].instructions.first() {
+                            Some(first) if matches!(first.instr, Instruction::Jump { .. }) => {
+                                self.first_nonempty(first.target)
+                            }
+                            _ => break,
+                        };
+                        if next == target {
+                            break;
+                        }
+                        target = next;
+                    }
+                }
+                self.blocks[block].instructions[i].target = target;
+            }
+        }
+    }
+
+    fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        reachable[0] = true;
+        let mut stack = vec![BlockIdx(0)];
+        while let Some(block) = stack.pop() {
+            let block = &self.blocks[block];
+            let falls_through = !matches!(
+                block.instructions.last(),
+                Some(ins) if ins.instr.unconditional_branch()
+            );
+            let targets = block.instructions.iter().map(|ins| ins.target);
+            for target in targets.chain(falls_through.then_some(block.next)) {
+                if target != BlockIdx::NULL && !reachable[target.idx()] {
+                    reachable[target.idx()] = true;
+                    stack.push(target);
+                }
+            }
+        }
+
+        let mut prev = BlockIdx(0);
+        let mut next = self.blocks[prev].next;
+        while next != BlockIdx::NULL {
+            let after = self.blocks[next].next;
+            if reachable[next.idx()] {
+                self.blocks[prev].next = next;
+                prev = next;
+            }
+            next = after;
+        }
+        self.blocks[prev].next = BlockIdx::NULL;
+    }
+
+    /// Move a block that is only ever entered through an unconditional jump right behind that
+    /// jump, which then goes away. Only blocks that end in an unconditional branch are moved, so
+    /// that nothing falls through into or out of them.
+    fn reorder_blocks(&mut self) {
+        let mut predecessors = vec![0u32; self.blocks.len()];
+        let mut layout_prev = vec![BlockIdx::NULL; self.blocks.len()];
+        // the first block is entered from the start of the code
+        let mut falls_through = true;
+        let mut prev = BlockIdx::NULL;
+        for (idx, block) in iter_blocks(&self.blocks) {
+            layout_prev[idx.idx()] = prev;
+            prev = idx;
+            let Some(last) = block.instructions.last() else {
+                continue;
+            };
+            if falls_through {
+                predecessors[idx.idx()] += 1;
+            }
+            falls_through = !last.instr.unconditional_branch();
+            for ins in &block.instructions {
+                if ins.target != BlockIdx::NULL {
+                    predecessors[ins.target.idx()] += 1;
+                }
+            }
+        }
+
+        let mut block = BlockIdx(0);
+        while block != BlockIdx::NULL {
+            let movable = self.blocks[block].instructions.last().and_then(|last| {
+                let target = last.target;
+                let ends_in_branch = |b: &Block| {
+                    matches!(b.instructions.last(), Some(ins) if ins.instr.unconditional_branch())
+                };
+                (matches!(last.instr, Instruction::Jump { .. })
+                    && target != block
+                    && predecessors[target.idx()] == 1
+                    && ends_in_branch(&self.blocks[target]))
+                .then_some(target)
+            });
+            let Some(target) = movable else {
+                block = self.blocks[block].next;
+                continue;
+            };
+
+            // unlink the target from where it is now ...
+            let (target_prev, target_next) = (layout_prev[target.idx()], self.blocks[target].next);
+            self.blocks[target_prev].next = target_next;
+            if target_next != BlockIdx::NULL {
+                layout_prev[target_next.idx()] = target_prev;
+            }
+            // ... and put it right after the jump, which it now falls through to
+            let after = self.blocks[block].next;
+            self.blocks[target].next = after;
+            if after != BlockIdx::NULL {
+                layout_prev[after.idx()] = target;
+            }
+            self.blocks[block].next = target;
+            layout_prev[target.idx()] = block;
+            self.blocks[block].instructions.pop();
+            block = target;
+        }
+    }
+
+    /// Drop jumps to the block that follows anyway, and turn a conditional jump over an
+    /// unconditional one into a single inverted conditional jump.
+    fn remove_redundant_jumps(&mut self) {
+        let mut block = BlockIdx(0);
+        while block != BlockIdx::NULL {
+            let next = self.blocks[block].next;
+            let following = if next == BlockIdx::NULL {
+                BlockIdx::NULL
+            } else {
+                self.first_nonempty(next)
+            };
+            let instructions = &mut self.blocks[block].instructions;
+            if let [.., cond, jump] = instructions[..] {
+                let inverted = match cond.instr {
+                    Instruction::JumpIfTrue { .. } => Some(Instruction::JumpIfFalse {
+                        target: Arg::marker(),
+                    }),
+                    Instruction::JumpIfFalse { .. } => Some(Instruction::JumpIfTrue {
+                        target: Arg::marker(),
+                    }),
+                    _ => None,
+                };
+                if let (Some(instr), Instruction::Jump { .. }) = (inverted, jump.instr) {
+                    if cond.target == following {
+                        instructions.pop();
+                        *instructions.last_mut().unwrap() = InstructionInfo {
+                            instr,
+                            target: jump.target,
+                            ..cond
+                        };
+                    }
+                }
+            }
+            if let Some(last) = instructions.last() {
+                if matches!(last.instr, Instruction::Jump { .. }) && last.target == following {
+                    instructions.pop();
+                }
+            }
+            block = next;
+        }
+    }
+
+    /// Drop the constants that are not loaded any more, e.g. the operands of folded
+    /// expressions. The first one always stays, as it is where CPython keeps the docstring.
+    fn remove_unused_constants(&mut self) {
+        let mut used = vec![false; self.constants.len()];
+        if let Some(first) = used.first_mut() {
+            *first = true;
+        }
+        for (_, block) in iter_blocks(&self.blocks) {
+            for ins in &block.instructions {
+                if let Instruction::LoadConst { idx } = ins.instr {
+                    used[idx.get(ins.arg) as usize] = true;
+                }
+            }
+        }
+        if used.iter().all(|&used| used) {
+            return;
+        }
+
+        let mut new_index = vec![0; used.len()];
+        for (i, constant) in std::mem::take(&mut self.constants).into_iter().enumerate() {
+            if used[i] {
+                new_index[i] = self.constants.insert_full(constant).0 as u32;
+            }
+        }
+        let mut block = BlockIdx(0);
+        while block != BlockIdx::NULL {
+            for ins in &mut self.blocks[block].instructions {
+                if let Instruction::LoadConst { idx } = ins.instr {
+                    ins.arg = OpArg(new_index[idx.get(ins.arg) as usize]);
+                }
+            }
+            block = self.blocks[block].next;
+        }
+    }
+
     fn dce(&mut self) {
         for block in &mut self.blocks {
             let mut last_instr = None;
@@ -221,19 +498,132 @@ impl CodeInfo {
         }
     }
 
-    fn max_stackdepth(&self) -> u32 {
+    /// Follow the block setups and `PopBlock`s along the control flow to find the handler
+    /// blocks and which of them is the innermost one around every instruction, then drop those
+    /// pseudo-instructions. Returns the handlers, per block the innermost handler of each of the
+    /// remaining instructions, and the stack depth at the start of each reachable block.
+    #[allow(clippy::type_complexity)]
+    fn build_exception_handlers(
+        &mut self,
+    ) -> (Vec<HandlerInfo>, Vec<Vec<Option<u32>>>, Vec<Option<u32>>) {
+        let mut handlers: Vec<HandlerInfo> = Vec::new();
+        let mut block_handlers: Vec<Vec<Option<u32>>> = self
+            .blocks
+            .iter()
+            .map(|block| vec![None; block.instructions.len()])
+            .collect();
+        // the innermost handler and the stack depth at the start of each block
+        let mut start_states = vec![None; self.blocks.len()];
+        start_states[0] = Some((None, 0));
+        let mut stack = vec![BlockIdx(0)];
+        'process_blocks: while let Some(block) = stack.pop() {
+            let (mut handler, mut depth): (Option<u32>, u32) = start_states[block.idx()].unwrap();
+            for (i, ins) in self.blocks[block].instructions.iter().enumerate() {
+                block_handlers[block.idx()][i] = handler;
+                let jump_depth = depth
+                    .checked_add_signed(ins.instr.stack_effect(ins.arg, true))
+                    .unwrap();
+                if let Some(kind) = handler_kind(ins.instr) {
+                    handlers.push(HandlerInfo {
+                        kind,
+                        target: ins.target,
+                        // the exception an except handler starts with is not part of the stack
+                        // it unwinds to
+                        depth: jump_depth - (kind == HandlerKind::Except) as u32,
+                        parent: handler,
+                    });
+                    handler_state_push(
+                        &mut stack,
+                        &mut start_states,
+                        ins.target,
+                        handler,
+                        jump_depth,
+                    );
+                    handler = Some(handlers.len() as u32 - 1);
+                } else {
+                    match ins.instr {
+                        Instruction::PopBlock => {
+                            handler = handler.and_then(|h| handlers[h as usize].parent);
+                        }
+                        Instruction::Break { .. } => {
+                            // break leaves everything up to and including the innermost loop
+                            let mut h = handler;
+                            while let Some(info) = h.map(|h| &handlers[h as usize]) {
+                                if info.kind == HandlerKind::Loop {
+                                    handler_state_push(
+                                        &mut stack,
+                                        &mut start_states,
+                                        ins.target,
+                                        info.parent,
+                                        info.depth,
+                                    );
+                                    break;
+                                }
+                                h = info.parent;
+                            }
+                        }
+                        // the start of the loop is reached without the continue anyway
+                        Instruction::Continue { .. } => {}
+                        _ => handler_state_push(
+                            &mut stack,
+                            &mut start_states,
+                            ins.target,
+                            handler,
+                            jump_depth,
+                        ),
+                    }
+                }
+                depth = depth
+                    .checked_add_signed(ins.instr.stack_effect(ins.arg, false))
+                    .unwrap();
+                if ins.instr.unconditional_branch() {
+                    continue 'process_blocks;
+                }
+            }
+            let next = self.blocks[block].next;
+            handler_state_push(&mut stack, &mut start_states, next, handler, depth);
+        }
+
+        for (block, covered) in self.blocks.iter_mut().zip(&mut block_handlers) {
+            let (instructions, kept): (Vec<_>, Vec<_>) = block
+                .instructions
+                .drain(..)
+                .zip(covered.drain(..))
+                .filter(|(ins, _)| {
+                    !matches!(
+                        ins.instr,
+                        Instruction::SetupExcept { .. }
+                            | Instruction::SetupFinally { .. }
+                            | Instruction::SetupAsyncWith { .. }
+                            | Instruction::SetupLoop
+                            | Instruction::PopBlock
+                    )
+                })
+                .unzip();
+            block.instructions = instructions;
+            *covered = kept;
+        }
+        let start_depths = start_states
+            .into_iter()
+            .map(|state| state.map(|(_, depth)| depth))
+            .collect();
+        (handlers, block_handlers, start_depths)
+    }
+
+    /// The deepest the stack gets, given the depth at the start of every reachable block. Those
+    /// come from [`Self::build_exception_handlers`], which already follows every edge, including
+    /// `Break`s to blocks that are reachable no other way once the loop test is folded away.
+    fn max_stackdepth(&self, start_depths: &[Option<u32>]) -> u32 {
         let mut maxdepth = 0u32;
-        let mut stack = Vec::with_capacity(self.blocks.len());
-        let mut start_depths = vec![u32::MAX; self.blocks.len()];
-        start_depths[0] = 0;
-        stack.push(BlockIdx(0));
         const DEBUG: bool = false;
-        'process_blocks: while let Some(block) = stack.pop() {
-            let mut depth = start_depths[block.idx()];
+        for (idx, block) in iter_blocks(&self.blocks) {
+            let Some(mut depth) = start_depths[idx.idx()] else {
+                continue;
+            };
             if DEBUG {
-                eprintln!("===BLOCK {}===", block.0);
+                eprintln!("===BLOCK {}===", idx.0);
             }
-            let block = &self.blocks[block];
+            maxdepth = maxdepth.max(depth);
             for ins in &block.instructions {
                 let instr = &ins.instr;
                 let effect = instr.stack_effect(ins.arg, false);
@@ -250,12 +640,9 @@ impl CodeInfo {
                 if DEBUG {
                     eprintln!("{new_depth}");
                 }
-                if new_depth > maxdepth {
-                    maxdepth = new_depth
-                }
-                // we don't want to worry about Break/Continue, they use unwinding to jump to
-                // their targets and as such the stack size is taken care of in frame.rs by setting
-                // it back to the level it was at when SetupLoop was run
+                maxdepth = maxdepth.max(new_depth);
+                // Break/Continue unwind to the depth of their loop, which is where their
+                // targets start anyway
                 if ins.target != BlockIdx::NULL
                     && !matches!(
                         instr,
@@ -263,18 +650,13 @@ impl CodeInfo {
                     )
                 {
                     let effect = instr.stack_effect(ins.arg, true);
-                    let target_depth = depth.checked_add_signed(effect).unwrap();
-                    if target_depth > maxdepth {
-                        maxdepth = target_depth
-                    }
-                    stackdepth_push(&mut stack, &mut start_depths, ins.target, target_depth);
+                    maxdepth = maxdepth.max(depth.checked_add_signed(effect).unwrap());
                 }
                 depth = new_depth;
                 if instr.unconditional_branch() {
-                    continue 'process_blocks;
+                    break;
                 }
             }
-            stackdepth_push(&mut stack, &mut start_depths, block.next, depth);
         }
         if DEBUG {
             eprintln!("DONE: {maxdepth}");
@@ -302,15 +684,292 @@ impl InstrDisplayContext for CodeInfo {
     }
 }
 
-fn stackdepth_push(
+/// Limits that keep folding from blowing up the size of the constants, the same as CPython's.
+const MAX_INT_BITS: u64 = 128;
+const MAX_STR_LEN: usize = 4096;
+
+/// Fold constant expressions and conditions at the end of `instructions` as they are added
+/// back, so that nested expressions fold from the inside out.
+fn fold_constants(instructions: &mut Vec<InstructionInfo>, constants: &mut IndexSet<ConstantData>) {
+    let mut folded = Vec::with_capacity(instructions.len());
+    for info in instructions.drain(..) {
+        folded.push(info);
+        while fold_last(&mut folded, constants) {}
+    }
+    *instructions = folded;
+}
+
+fn fold_last(out: &mut Vec<InstructionInfo>, constants: &mut IndexSet<ConstantData>) -> bool {
+    let n = out.len();
+    let Some(&last) = out.last() else {
+        return false;
+    };
+    match last.instr {
+        Instruction::BinaryOperation { op } if n >= 3 => {
+            let (Some(a), Some(b)) = (const_index(&out[n - 3]), const_index(&out[n - 2])) else {
+                return false;
+            };
+            let Some(value) = fold_binary(op.get(last.arg), &constants[a], &constants[b]) else {
+                return false;
+            };
+            out.truncate(n - 3);
+            out.push(load_const(value, &last, constants));
+            true
+        }
+        Instruction::UnaryOperation { op } if n >= 2 => {
+            let Some(value) =
+                const_index(&out[n - 2]).and_then(|c| fold_unary(op.get(last.arg), &constants[c]))
+            else {
+                return false;
+            };
+            out.truncate(n - 2);
+            out.push(load_const(value, &last, constants));
+            true
+        }
+        Instruction::JumpIfTrue { .. } | Instruction::JumpIfFalse { .. } if n >= 2 => {
+            let jump_if_true = matches!(last.instr, Instruction::JumpIfTrue { .. });
+            let prev = out[n - 2];
+            if let Some(c) = const_index(&prev) {
+                // the condition is known: either always jump, or never
+                out.truncate(n - 2);
+                if is_truthy(&constants[c]) == jump_if_true {
+                    out.push(InstructionInfo {
+                        instr: Instruction::Jump {
+                            target: Arg::marker(),
+                        },
+                        ..last
+                    });
+                }
+                true
+            } else if matches!(
+                prev.instr,
+                Instruction::UnaryOperation { op } if op.get(prev.arg) == UnaryOperator::Not
+            ) {
+                let instr = if jump_if_true {
+                    Instruction::JumpIfFalse {
+                        target: Arg::marker(),
+                    }
+                } else {
+                    Instruction::JumpIfTrue {
+                        target: Arg::marker(),
+                    }
+                };
+                out.truncate(n - 2);
+                out.push(InstructionInfo { instr, ..last });
+                true
+            } else {
+                false
+            }
+        }
+        Instruction::Pop if n >= 2 && const_index(&out[n - 2]).is_some() => {
+            out.truncate(n - 2);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn const_index(info: &InstructionInfo) -> Option<usize> {
+    match info.instr {
+        Instruction::LoadConst { idx } => Some(idx.get(info.arg) as usize),
+        _ => None,
+    }
+}
+
+/// A `LoadConst` of `value` that takes the place of (and the source range of) `op`.
+fn load_const(
+    value: ConstantData,
+    op: &InstructionInfo,
+    constants: &mut IndexSet<ConstantData>,
+) -> InstructionInfo {
+    let idx = constants.insert_full(value).0;
+    InstructionInfo {
+        instr: Instruction::LoadConst { idx: Arg::marker() },
+        arg: OpArg(idx as u32),
+        target: BlockIdx::NULL,
+        ..*op
+    }
+}
+
+fn is_truthy(value: &ConstantData) -> bool {
+    match value {
+        ConstantData::Tuple { elements } => !elements.is_empty(),
+        ConstantData::Integer { value } => !value.is_zero(),
+        ConstantData::Float { value } => *value != 0.0,
+        ConstantData::Complex { value } => !value.is_zero(),
+        ConstantData::Boolean { value } => *value,
+        ConstantData::Str { value } => !value.is_empty(),
+        ConstantData::Bytes { value } => !value.is_empty(),
+        ConstantData::Code { .. } | ConstantData::Ellipsis => true,
+        ConstantData::None => false,
+    }
+}
+
+fn fold_unary(op: UnaryOperator, value: &ConstantData) -> Option<ConstantData> {
+    let value = match (op, value) {
+        (UnaryOperator::Not, value) => ConstantData::Boolean {
+            value: !is_truthy(value),
+        },
+        (UnaryOperator::Minus, ConstantData::Integer { value }) => {
+            ConstantData::Integer { value: -value }
+        }
+        (UnaryOperator::Minus, ConstantData::Float { value }) => {
+            ConstantData::Float { value: -*value }
+        }
+        (UnaryOperator::Minus, ConstantData::Complex { value }) => {
+            ConstantData::Complex { value: -*value }
+        }
+        (UnaryOperator::Plus, ConstantData::Integer { value }) => ConstantData::Integer {
+            value: value.clone(),
+        },
+        (UnaryOperator::Plus, ConstantData::Float { value }) => {
+            ConstantData::Float { value: *value }
+        }
+        (UnaryOperator::Invert, ConstantData::Integer { value }) => ConstantData::Integer {
+            value: -(value + BigInt::one()),
+        },
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn fold_binary(op: BinaryOperator, a: &ConstantData, b: &ConstantData) -> Option<ConstantData> {
+    let value = match (a, b) {
+        (ConstantData::Integer { value: a }, ConstantData::Integer { value: b }) => {
+            return fold_int_binary(op, a, b);
+        }
+        (
+            ConstantData::Integer { .. } | ConstantData::Float { .. },
+            ConstantData::Integer { .. } | ConstantData::Float { .. },
+        ) => {
+            let (a, b) = (exact_float(a)?, exact_float(b)?);
+            let value = match op {
+                BinaryOperator::Add => a + b,
+                BinaryOperator::Subtract => a - b,
+                BinaryOperator::Multiply => a * b,
+                BinaryOperator::Divide if b != 0.0 => a / b,
+                _ => return None,
+            };
+            ConstantData::Float { value }
+        }
+        (ConstantData::Str { value: a }, ConstantData::Str { value: b })
+            if op == BinaryOperator::Add && a.len() + b.len() <= MAX_STR_LEN =>
+        {
+            ConstantData::Str {
+                value: format!("{a}{b}"),
+            }
+        }
+        (ConstantData::Bytes { value: a }, ConstantData::Bytes { value: b })
+            if op == BinaryOperator::Add && a.len() + b.len() <= MAX_STR_LEN =>
+        {
+            ConstantData::Bytes {
+                value: [a.as_slice(), b].concat(),
+            }
+        }
+        (ConstantData::Str { value: s }, ConstantData::Integer { value: n })
+        | (ConstantData::Integer { value: n }, ConstantData::Str { value: s })
+            if op == BinaryOperator::Multiply =>
+        {
+            ConstantData::Str {
+                value: s.repeat(repeat_count(s.len(), n)?),
+            }
+        }
+        (ConstantData::Bytes { value: s }, ConstantData::Integer { value: n })
+        | (ConstantData::Integer { value: n }, ConstantData::Bytes { value: s })
+            if op == BinaryOperator::Multiply =>
+        {
+            ConstantData::Bytes {
+                value: s.repeat(repeat_count(s.len(), n)?),
+            }
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn fold_int_binary(op: BinaryOperator, a: &BigInt, b: &BigInt) -> Option<ConstantData> {
+    let value = match op {
+        BinaryOperator::Add => a + b,
+        BinaryOperator::Subtract => a - b,
+        BinaryOperator::Multiply if a.bits() + b.bits() <= MAX_INT_BITS => a * b,
+        // truncating division only agrees with floor division for non-negative operands
+        BinaryOperator::FloorDivide if !a.is_negative() && b.is_positive() => a / b,
+        BinaryOperator::Modulo if !a.is_negative() && b.is_positive() => a % b,
+        BinaryOperator::Divide if !b.is_zero() && a.bits() <= 53 && b.bits() <= 53 => {
+            return Some(ConstantData::Float {
+                value: a.to_f64()? / b.to_f64()?,
+            });
+        }
+        BinaryOperator::Power if !b.is_negative() => {
+            let exp = b.to_usize()?;
+            if a.bits().checked_mul(exp as u64)? > MAX_INT_BITS {
+                return None;
+            }
+            num_traits::pow(a.clone(), exp)
+        }
+        BinaryOperator::Lshift if !b.is_negative() => {
+            let shift = b.to_usize()?;
+            if a.bits() + shift as u64 > MAX_INT_BITS {
+                return None;
+            }
+            a * num_traits::pow(BigInt::from(2), shift)
+        }
+        BinaryOperator::Rshift if !a.is_negative() && !b.is_negative() => {
+            let shift = b.to_usize().filter(|&shift| shift as u64 <= MAX_INT_BITS)?;
+            a / num_traits::pow(BigInt::from(2), shift)
+        }
+        BinaryOperator::And => a & b,
+        BinaryOperator::Or => a | b,
+        BinaryOperator::Xor => a ^ b,
+        _ => return None,
+    };
+    (value.bits() <= MAX_INT_BITS).then_some(ConstantData::Integer { value })
+}
+
+/// `value` as a float, if the conversion is exact.
+fn exact_float(value: &ConstantData) -> Option<f64> {
+    match value {
+        ConstantData::Integer { value } if value.bits() <= 53 => value.to_f64(),
+        ConstantData::Float { value } => Some(*value),
+        _ => None,
+    }
+}
+
+/// How often a sequence of `len` items can be repeated `n` times without getting too large.
+fn repeat_count(len: usize, n: &BigInt) -> Option<usize> {
+    let n = if n.is_negative() { 0 } else { n.to_usize()? };
+    (len.checked_mul(n)? <= MAX_STR_LEN).then_some(n)
+}
+
+/// A handler block while the code is being finalized, with its target still a block.
+struct HandlerInfo {
+    kind: HandlerKind,
+    target: BlockIdx,
+    depth: u32,
+    parent: Option<u32>,
+}
+
+/// The kind of handler block that `instr` sets up, if any.
+fn handler_kind(instr: Instruction) -> Option<HandlerKind> {
+    match instr {
+        Instruction::SetupExcept { .. } => Some(HandlerKind::Except),
+        Instruction::SetupFinally { .. }
+        | Instruction::SetupWith { .. }
+        | Instruction::SetupAsyncWith { .. } => Some(HandlerKind::Finally),
+        Instruction::SetupLoop => Some(HandlerKind::Loop),
+        _ => None,
+    }
+}
+
+fn handler_state_push(
     stack: &mut Vec<BlockIdx>,
-    start_depths: &mut [u32],
+    start_states: &mut [Option<(Option<u32>, u32)>],
     target: BlockIdx,
+    handler: Option<u32>,
     depth: u32,
 ) {
-    let block_depth = &mut start_depths[target.idx()];
-    if *block_depth == u32::MAX || depth > *block_depth {
-        *block_depth = depth;
+    if target != BlockIdx::NULL && start_states[target.idx()].is_none() {
+        start_states[target.idx()] = Some((handler, depth));
         stack.push(target);
     }
 }
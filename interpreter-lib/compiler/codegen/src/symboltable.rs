@@ -741,7 +741,7 @@ impl SymbolTableBuilder {
                 self.scan_statements(body)?;
                 self.scan_statements(orelse)?;
             }
-            Stmt::Break(_) | Stmt::Continue(_) | Stmt::Pass(_) => {
+            Stmt::Break(_) | Stmt::Continue(_) | Stmt::Pass(_) | Stmt::Invalid(_) => {
                 // No symbols here.
             }
             Stmt::Import(StmtImport { names, range })
@@ -952,7 +952,7 @@ impl SymbolTableBuilder {
             }) => {
                 self.scan_expression(operand, context)?;
             }
-            Expr::Constant(ExprConstant { range: _, .. }) => {}
+            Expr::Constant(ExprConstant { range: _, .. }) | Expr::Invalid(_) => {}
             Expr::Starred(ExprStarred {
                 value, range: _, ..
             }) => {
@@ -91,7 +91,8 @@ impl ConstantBag for BasicBag {
 #[derive(Clone)]
 pub struct CodeObject<C: Constant = ConstantData> {
     pub instructions: Box<[CodeUnit]>,
-    pub locations: Box<[SourceLocation]>,
+    /// Start and end position of the source range each instruction was compiled from.
+    pub locations: Box<[(SourceLocation, SourceLocation)]>,
     pub flags: CodeFlags,
     pub posonlyarg_count: u32,
     // Number of positional-only arguments
@@ -108,6 +109,56 @@ pub struct CodeObject<C: Constant = ConstantData> {
     pub varnames: Box<[C::Name]>,
     pub cellvars: Box<[C::Name]>,
     pub freevars: Box<[C::Name]>,
+    /// The `try`, `with` and loop blocks of the code, referred to by `exception_table`.
+    pub exception_handlers: Box<[ExceptionHandler]>,
+    /// Which handler covers which instructions, sorted by offset and without overlaps.
+    pub exception_table: Box<[ExceptionTableEntry]>,
+}
+
+/// What a handler in the exception table does when the frame unwinds to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HandlerKind {
+    /// An `except` clause, entered only when an exception is raised.
+    Except = 0,
+    /// A `finally` clause or the exit of a `with` block, entered however the block is left.
+    Finally = 1,
+    /// A loop, where `break` and `continue` stop unwinding.
+    Loop = 2,
+}
+
+impl TryFrom<u8> for HandlerKind {
+    type Error = crate::marshal::MarshalError;
+
+    fn try_from(value: u8) -> Result<Self, crate::marshal::MarshalError> {
+        match value {
+            0 => Ok(HandlerKind::Except),
+            1 => Ok(HandlerKind::Finally),
+            2 => Ok(HandlerKind::Loop),
+            _ => Err(crate::marshal::MarshalError::InvalidBytecode),
+        }
+    }
+}
+
+/// A block that was set up by `SetupExcept`, `SetupFinally`, `SetupWith`, `SetupAsyncWith` or
+/// `SetupLoop` in the compiler. Handlers nest through `parent`, which is the handler the block
+/// itself is in, so the handlers around an instruction are found without a runtime block stack.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionHandler {
+    pub kind: HandlerKind,
+    /// Where the handler code starts; not used for loops.
+    pub target: Label,
+    /// The value stack depth to go back to before entering the handler.
+    pub depth: u32,
+    pub parent: Option<u32>,
+}
+
+/// The instructions `start..end` are covered by `handler` and none of its nested handlers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionTableEntry {
+    pub start: u32,
+    pub end: u32,
+    pub handler: u32,
 }
 
 bitflags! {
@@ -489,9 +540,12 @@ pub enum Instruction {
     YieldValue,
     YieldFrom,
     SetupAnnotation,
+    /// Pseudo-instruction: starts a loop block. Like the other block setups and `PopBlock`, it
+    /// only exists while compiling and is turned into an entry of the exception table.
     SetupLoop,
 
-    /// Setup a finally handler, which will be called whenever one of this events occurs:
+    /// Pseudo-instruction: setup a finally handler, which will be called whenever one of this
+    /// events occurs:
     /// - the block is popped
     /// - the function returns
     /// - an exception is returned
@@ -510,14 +564,18 @@ pub enum Instruction {
     /// - Do nothing at all, just continue
     EndFinally,
 
+    /// Pseudo-instruction: setup an except handler.
     SetupExcept {
         handler: Arg<Label>,
     },
+    /// Call `__enter__` of the context manager and keep its `__exit__` on the stack. The block
+    /// that calls it again at `end` goes into the exception table.
     SetupWith {
         end: Arg<Label>,
     },
     WithCleanupStart,
     WithCleanupFinish,
+    /// Pseudo-instruction: ends the innermost block.
     PopBlock,
     Raise {
         kind: Arg<RaiseKind>,
@@ -581,6 +639,7 @@ pub enum Instruction {
     },
     GetAwaitable,
     BeforeAsyncWith,
+    /// Pseudo-instruction: setup the block of an `async with`, once `__aenter__` has returned.
     SetupAsyncWith {
         end: Arg<Label>,
     },
@@ -968,9 +1027,26 @@ impl<C: Constant> CodeObject<C> {
                 label_targets.insert(l.get(arg));
             }
         }
+        for handler in &*self.exception_handlers {
+            if handler.kind != HandlerKind::Loop {
+                label_targets.insert(handler.target);
+            }
+        }
         label_targets
     }
 
+    /// The innermost handler that covers the instruction at `offset`, as an index into
+    /// `exception_handlers`.
+    pub fn handler_at(&self, offset: u32) -> Option<u32> {
+        let i = self
+            .exception_table
+            .partition_point(|entry| entry.end <= offset);
+        self.exception_table
+            .get(i)
+            .filter(|entry| entry.start <= offset)
+            .map(|entry| entry.handler)
+    }
+
     fn display_inner(
         &self,
         f: &mut fmt::Formatter,
@@ -978,14 +1054,14 @@ impl<C: Constant> CodeObject<C> {
         level: usize,
     ) -> fmt::Result {
         let label_targets = self.label_targets();
-        let line_digits = (3).max(self.locations.last().unwrap().row.to_string().len());
+        let line_digits = (3).max(self.locations.last().unwrap().0.row.to_string().len());
         let offset_digits = (4).max(self.instructions.len().to_string().len());
         let mut last_line = OneIndexed::MAX;
         let mut arg_state = OpArgState::default();
         for (offset, &instruction) in self.instructions.iter().enumerate() {
             let (instruction, arg) = arg_state.get(instruction);
             // optional line number
-            let line = self.locations[offset].row;
+            let line = self.locations[offset].0.row;
             if line != last_line {
                 if last_line != OneIndexed::MAX {
                     writeln!(f)?;
@@ -1016,6 +1092,25 @@ impl<C: Constant> CodeObject<C> {
             instruction.fmt_dis(arg, f, self, expand_code_objects, 21, level)?;
             writeln!(f)?;
         }
+
+        if !self.exception_table.is_empty() {
+            writeln!(f)?;
+            write!(f, "{:1$}", "", line_digits + 1 + 4 * level)?;
+            writeln!(f, "ExceptionTable:")?;
+        }
+        for entry in &*self.exception_table {
+            let handler = &self.exception_handlers[entry.handler as usize];
+            write!(f, "{:1$}", "", line_digits + 1 + 4 * level)?;
+            write!(f, "   {} to {} -> ", entry.start, entry.end - 1)?;
+            match handler.kind {
+                HandlerKind::Except => write!(f, "{} [{}] except", handler.target, handler.depth)?,
+                HandlerKind::Finally => {
+                    write!(f, "{} [{}] finally", handler.target, handler.depth)?
+                }
+                HandlerKind::Loop => write!(f, "loop [{}]", handler.depth)?,
+            }
+            writeln!(f)?;
+        }
         Ok(())
     }
 
@@ -1062,6 +1157,8 @@ impl<C: Constant> CodeObject<C> {
             first_line_number: self.first_line_number,
             max_stackdepth: self.max_stackdepth,
             cell2arg: self.cell2arg,
+            exception_handlers: self.exception_handlers,
+            exception_table: self.exception_table,
         }
     }
 
@@ -1091,6 +1188,8 @@ impl<C: Constant> CodeObject<C> {
             first_line_number: self.first_line_number,
             max_stackdepth: self.max_stackdepth,
             cell2arg: self.cell2arg.clone(),
+            exception_handlers: self.exception_handlers.clone(),
+            exception_table: self.exception_table.clone(),
         }
     }
 }
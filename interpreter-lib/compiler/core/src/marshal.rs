@@ -4,7 +4,7 @@ use num_complex::Complex64;
 use rustpython_parser_core::source_code::{OneIndexed, SourceLocation};
 use std::convert::Infallible;
 
-pub const FORMAT_VERSION: u32 = 4;
+pub const FORMAT_VERSION: u32 = 5;
 
 #[derive(Debug)]
 pub enum MarshalError {
@@ -182,14 +182,15 @@ pub fn deserialize_code<R: Read, Bag: ConstantBag>(
         .collect::<Result<Box<[CodeUnit]>>>()?;
 
     let len = rdr.read_u32()?;
-    let locations = (0..len)
-        .map(|_| {
-            Ok(SourceLocation {
-                row: OneIndexed::new(rdr.read_u32()?).ok_or(MarshalError::InvalidLocation)?,
-                column: OneIndexed::from_zero_indexed(rdr.read_u32()?),
-            })
+    let read_location = |rdr: &mut R| -> Result<SourceLocation> {
+        Ok(SourceLocation {
+            row: OneIndexed::new(rdr.read_u32()?).ok_or(MarshalError::InvalidLocation)?,
+            column: OneIndexed::from_zero_indexed(rdr.read_u32()?),
         })
-        .collect::<Result<Box<[SourceLocation]>>>()?;
+    };
+    let locations = (0..len)
+        .map(|_| Ok((read_location(rdr)?, read_location(rdr)?)))
+        .collect::<Result<Box<[(SourceLocation, SourceLocation)]>>>()?;
 
     let flags = CodeFlags::from_bits_truncate(rdr.read_u16()?);
 
@@ -235,6 +236,33 @@ pub fn deserialize_code<R: Read, Bag: ConstantBag>(
     let cellvars = read_names()?;
     let freevars = read_names()?;
 
+    let len = rdr.read_u32()?;
+    let exception_handlers = (0..len)
+        .map(|_| {
+            let kind = HandlerKind::try_from(rdr.read_u8()?)?;
+            let target = Label(rdr.read_u32()?);
+            let depth = rdr.read_u32()?;
+            let parent = rdr.read_u32()?;
+            Ok(ExceptionHandler {
+                kind,
+                target,
+                depth,
+                parent: (parent != u32::MAX).then_some(parent),
+            })
+        })
+        .collect::<Result<Box<[_]>>>()?;
+
+    let len = rdr.read_u32()?;
+    let exception_table = (0..len)
+        .map(|_| {
+            Ok(ExceptionTableEntry {
+                start: rdr.read_u32()?,
+                end: rdr.read_u32()?,
+                handler: rdr.read_u32()?,
+            })
+        })
+        .collect::<Result<Box<[_]>>>()?;
+
     Ok(CodeObject {
         instructions,
         locations,
@@ -252,6 +280,8 @@ pub fn deserialize_code<R: Read, Bag: ConstantBag>(
         varnames,
         cellvars,
         freevars,
+        exception_handlers,
+        exception_table,
     })
 }
 
@@ -588,9 +618,11 @@ pub fn serialize_code<W: Write, C: Constant>(buf: &mut W, code: &CodeObject<C>)
     buf.write_slice(instructions_bytes);
 
     write_len(buf, code.locations.len());
-    for loc in &*code.locations {
-        buf.write_u32(loc.row.get());
-        buf.write_u32(loc.column.to_zero_indexed());
+    for (start, end) in &*code.locations {
+        for loc in [start, end] {
+            buf.write_u32(loc.row.get());
+            buf.write_u32(loc.column.to_zero_indexed());
+        }
     }
 
     buf.write_u16(code.flags.bits());
@@ -628,4 +660,19 @@ pub fn serialize_code<W: Write, C: Constant>(buf: &mut W, code: &CodeObject<C>)
     write_names(&code.varnames);
     write_names(&code.cellvars);
     write_names(&code.freevars);
+
+    write_len(buf, code.exception_handlers.len());
+    for handler in &*code.exception_handlers {
+        buf.write_u8(handler.kind as u8);
+        buf.write_u32(handler.target.0);
+        buf.write_u32(handler.depth);
+        buf.write_u32(handler.parent.unwrap_or(u32::MAX));
+    }
+
+    write_len(buf, code.exception_table.len());
+    for entry in &*code.exception_table {
+        buf.write_u32(entry.start);
+        buf.write_u32(entry.end);
+        buf.write_u32(entry.handler);
+    }
 }
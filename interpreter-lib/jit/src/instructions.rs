@@ -1,18 +1,15 @@
 use cranelift::prelude::*;
 use num_traits::cast::ToPrimitive;
 use rustpython_compiler_core::bytecode::{
-    self, BinaryOperator, BorrowedConstant, CodeObject, ComparisonOperator, Instruction, Label,
-    OpArg, OpArgState, UnaryOperator,
+    self, BinaryOperator, BorrowedConstant, CodeObject, ComparisonOperator, HandlerKind,
+    Instruction, Label, OpArg, OpArgState, UnaryOperator,
 };
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use super::{JitCompileError, JitSig, JitType};
-
-#[repr(u16)]
-enum CustomTrapCode {
-    /// Raised when shifting by a negative number
-    NegativeShiftCount = 0,
-}
+use super::{
+    AbiValue, CompiledCode, JitCompileError, JitGlobal, JitSig, JitType, BAILOUT_OFFSET,
+    DEPTH_LEFT_OFFSET,
+};
 
 #[derive(Clone)]
 struct Local {
@@ -27,6 +24,22 @@ enum JitValue {
     Bool(Value),
     None,
     Tuple(Vec<JitValue>),
+    /// The `range` builtin, only usable as the callee of a call.
+    RangeType,
+    Range {
+        start: Value,
+        stop: Value,
+        step: Value,
+    },
+    /// The iterator of a `for` loop over a range; `current` is the next value to be produced.
+    RangeIter {
+        current: Variable,
+        stop: Value,
+        step: Value,
+    },
+    Function(Arc<CompiledCode>),
+    /// The function that is being compiled.
+    Recursive,
 }
 
 impl JitValue {
@@ -43,14 +56,14 @@ impl JitValue {
             JitValue::Int(_) => Some(JitType::Int),
             JitValue::Float(_) => Some(JitType::Float),
             JitValue::Bool(_) => Some(JitType::Bool),
-            JitValue::None | JitValue::Tuple(_) => None,
+            _ => None,
         }
     }
 
     fn into_value(self) -> Option<Value> {
         match self {
             JitValue::Int(val) | JitValue::Float(val) | JitValue::Bool(val) => Some(val),
-            JitValue::None | JitValue::Tuple(_) => None,
+            _ => None,
         }
     }
 }
@@ -59,8 +72,20 @@ pub struct FunctionCompiler<'a, 'b> {
     builder: &'a mut FunctionBuilder<'b>,
     stack: Vec<JitValue>,
     variables: Box<[Option<Local>]>,
+    /// Index of the next cranelift variable that doesn't belong to a python local.
+    next_temp_variable: usize,
     label_to_block: HashMap<Label, Block>,
+    /// Targets of `ForIter`; the exhausted iterator is popped when one of them is reached.
+    loop_exits: Vec<Label>,
+    pointer_type: types::Type,
+    /// The last parameter of every jitted function, a pointer to its
+    /// [`CallState`](super::CallState).
+    call_state: Value,
+    bailout_block: Option<Block>,
+    /// Address of the cell that holds the address of this function once it is finalized.
+    recursive_entry: i64,
     pub(crate) sig: JitSig,
+    pub(crate) dependencies: Vec<Arc<CompiledCode>>,
 }
 
 impl<'a, 'b> FunctionCompiler<'a, 'b> {
@@ -69,19 +94,29 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
         num_variables: usize,
         arg_types: &[JitType],
         entry_block: Block,
+        pointer_type: types::Type,
+        recursive_entry: i64,
     ) -> FunctionCompiler<'a, 'b> {
+        let params = builder.func.dfg.block_params(entry_block).to_vec();
+        let (&call_state, params) = params.split_last().expect("missing call state parameter");
         let mut compiler = FunctionCompiler {
             builder,
             stack: Vec::new(),
             variables: vec![None; num_variables].into_boxed_slice(),
+            next_temp_variable: num_variables,
             label_to_block: HashMap::new(),
+            loop_exits: Vec::new(),
+            pointer_type,
+            call_state,
+            bailout_block: None,
+            recursive_entry,
             sig: JitSig {
                 args: arg_types.to_vec(),
                 ret: None,
             },
+            dependencies: Vec::new(),
         };
-        let params = compiler.builder.func.dfg.block_params(entry_block).to_vec();
-        for (i, (ty, val)) in arg_types.iter().zip(params).enumerate() {
+        for (i, (ty, &val)) in arg_types.iter().zip(params).enumerate() {
             compiler
                 .store_variable(i as u32, JitValue::from_type_and_value(ty.clone(), val))
                 .unwrap();
@@ -132,10 +167,52 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
             }
             JitValue::Bool(val) => Ok(val),
             JitValue::None => Ok(self.builder.ins().iconst(types::I8, 0)),
-            JitValue::Tuple(_) => Err(JitCompileError::NotSupported),
+            _ => Err(JitCompileError::NotSupported),
         }
     }
 
+    fn new_temp_variable(&mut self, ty: types::Type) -> Variable {
+        let var = Variable::new(self.next_temp_variable);
+        self.next_temp_variable += 1;
+        self.builder.declare_var(var, ty);
+        var
+    }
+
+    /// Leave the function through the bailout block when `cond` is true. Jitted code has no side
+    /// effects, so the caller can redo the whole call in the interpreter, which then takes care
+    /// of overflowing ints, raising exceptions and so on.
+    fn bailout_if(&mut self, cond: Value) {
+        let bailout_block = match self.bailout_block {
+            Some(block) => block,
+            None => *self.bailout_block.insert(self.builder.create_block()),
+        };
+        self.builder.ins().brnz(cond, bailout_block, &[]);
+
+        let block = self.builder.create_block();
+        self.builder.ins().jump(block, &[]);
+        self.builder.switch_to_block(block);
+    }
+
+    fn finish_bailout_block(&mut self) -> Result<(), JitCompileError> {
+        let Some(block) = self.bailout_block else {
+            return Ok(());
+        };
+        self.builder.switch_to_block(block);
+        let one = self.builder.ins().iconst(types::I8, 1);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), one, self.call_state, BAILOUT_OFFSET);
+        // the return value is ignored by the caller, it only has to be of the right type
+        let ret = match self.sig.ret {
+            Some(JitType::Int) => self.builder.ins().iconst(types::I64, 0),
+            Some(JitType::Float) => self.builder.ins().f64const(0.0),
+            Some(JitType::Bool) => self.builder.ins().iconst(types::I8, 0),
+            None => return Err(JitCompileError::NotSupported),
+        };
+        self.builder.ins().return_(&[ret]);
+        Ok(())
+    }
+
     fn get_or_create_block(&mut self, label: Label) -> Block {
         let builder = &mut self.builder;
         *self
@@ -147,6 +224,7 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
     pub fn compile<C: bytecode::Constant>(
         &mut self,
         bytecode: &CodeObject<C>,
+        globals: &dyn Fn(&str) -> Option<JitGlobal>,
     ) -> Result<(), JitCompileError> {
         // TODO: figure out if this is sufficient -- previously individual labels were associated
         // pretty much per-bytecode that uses them, or at least per "type" of block -- in theory an
@@ -155,6 +233,15 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
         // label_targets alone
         let label_targets = bytecode.label_targets();
 
+        // there are no exceptions in jitted code, so there is nothing to handle them either
+        if bytecode
+            .exception_handlers
+            .iter()
+            .any(|handler| handler.kind != HandlerKind::Loop)
+        {
+            return Err(JitCompileError::NotSupported);
+        }
+
         let mut arg_state = OpArgState::default();
         for (offset, instruction) in bytecode.instructions.iter().enumerate() {
             let (instruction, arg) = arg_state.get(*instruction);
@@ -169,6 +256,14 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
                 }
 
                 self.builder.switch_to_block(block);
+
+                if self.loop_exits.contains(&label) {
+                    // the loop is done, drop its iterator
+                    match self.stack.pop() {
+                        Some(JitValue::RangeIter { .. }) => {}
+                        _ => return Err(JitCompileError::BadBytecode),
+                    }
+                }
             }
 
             // Sometimes the bytecode contains instructions after a return
@@ -177,10 +272,10 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
                 continue;
             }
 
-            self.add_instruction(instruction, arg, &bytecode.constants)?;
+            self.add_instruction(instruction, arg, bytecode, globals)?;
         }
 
-        Ok(())
+        self.finish_bailout_block()
     }
 
     fn load_const<C: bytecode::Constant>(
@@ -214,11 +309,168 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
         }
     }
 
+    fn load_global(&mut self, global: JitGlobal) -> Result<(), JitCompileError> {
+        let val = match global {
+            JitGlobal::Constant(AbiValue::Int(i)) => {
+                JitValue::Int(self.builder.ins().iconst(types::I64, i))
+            }
+            JitGlobal::Constant(AbiValue::Float(f)) => {
+                JitValue::Float(self.builder.ins().f64const(f))
+            }
+            JitGlobal::Constant(AbiValue::Bool(b)) => {
+                JitValue::Bool(self.builder.ins().iconst(types::I8, b as i64))
+            }
+            JitGlobal::Range => JitValue::RangeType,
+            JitGlobal::Function(code) => JitValue::Function(code),
+            JitGlobal::Recursive => JitValue::Recursive,
+        };
+        self.stack.push(val);
+        Ok(())
+    }
+
+    fn call_function(
+        &mut self,
+        callee: JitValue,
+        args: Vec<JitValue>,
+    ) -> Result<JitValue, JitCompileError> {
+        let (entry, sig) = match callee {
+            JitValue::RangeType => return self.build_range(args),
+            JitValue::Function(code) => {
+                let entry = self
+                    .builder
+                    .ins()
+                    .iconst(self.pointer_type, code.code as i64);
+                let sig = JitSig {
+                    args: code.sig.args.clone(),
+                    ret: code.sig.ret.clone(),
+                };
+                self.dependencies.push(code);
+                (entry, sig)
+            }
+            JitValue::Recursive => {
+                // the return type has to be known already, i.e. the base case has to come first
+                let ret = self.sig.ret.clone().ok_or(JitCompileError::NotSupported)?;
+                let cell = self
+                    .builder
+                    .ins()
+                    .iconst(self.pointer_type, self.recursive_entry);
+                let entry =
+                    self.builder
+                        .ins()
+                        .load(self.pointer_type, MemFlags::trusted(), cell, 0);
+                let sig = JitSig {
+                    args: self.sig.args.clone(),
+                    ret: Some(ret),
+                };
+                (entry, sig)
+            }
+            _ => return Err(JitCompileError::NotSupported),
+        };
+
+        if args.len() != sig.args.len() {
+            return Err(JitCompileError::NotSupported);
+        }
+        let mut call_args = args
+            .into_iter()
+            .zip(&sig.args)
+            .map(|(arg, ty)| {
+                if arg.to_jit_type().as_ref() == Some(ty) {
+                    Ok(arg.into_value().unwrap())
+                } else {
+                    Err(JitCompileError::NotSupported)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        call_args.push(self.call_state);
+
+        // the interpreter raises RecursionError when the limit is reached, so let it
+        let depth_left = self.builder.ins().load(
+            types::I64,
+            MemFlags::trusted(),
+            self.call_state,
+            DEPTH_LEFT_OFFSET,
+        );
+        let exhausted = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::SignedLessThanOrEqual, depth_left, 0);
+        self.bailout_if(exhausted);
+        let callee_depth_left = self.builder.ins().iadd_imm(depth_left, -1);
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            callee_depth_left,
+            self.call_state,
+            DEPTH_LEFT_OFFSET,
+        );
+
+        let mut signature = Signature::new(self.builder.func.signature.call_conv);
+        signature.params.extend(
+            sig.args
+                .iter()
+                .map(|ty| AbiParam::new(ty.to_cranelift()))
+                .chain(std::iter::once(AbiParam::new(self.pointer_type))),
+        );
+        signature
+            .returns
+            .extend(sig.ret.iter().map(|ty| AbiParam::new(ty.to_cranelift())));
+        let sig_ref = self.builder.import_signature(signature);
+        let call = self.builder.ins().call_indirect(sig_ref, entry, &call_args);
+        let ret = self.builder.inst_results(call).first().copied();
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            depth_left,
+            self.call_state,
+            DEPTH_LEFT_OFFSET,
+        );
+
+        // the callee bailed out, so this call has to be redone by the interpreter too
+        let flag = self.builder.ins().load(
+            types::I8,
+            MemFlags::trusted(),
+            self.call_state,
+            BAILOUT_OFFSET,
+        );
+        self.bailout_if(flag);
+
+        Ok(match (sig.ret, ret) {
+            (Some(ty), Some(val)) => JitValue::from_type_and_value(ty, val),
+            _ => JitValue::None,
+        })
+    }
+
+    fn build_range(&mut self, args: Vec<JitValue>) -> Result<JitValue, JitCompileError> {
+        let mut args = args.into_iter().map(|arg| match arg {
+            JitValue::Int(val) => Ok(val),
+            _ => Err(JitCompileError::NotSupported),
+        });
+        let mut next = || args.next().transpose();
+        let (start, stop, step) = match (next()?, next()?, next()?, next()?) {
+            (Some(stop), None, None, None) => {
+                let start = self.builder.ins().iconst(types::I64, 0);
+                let step = self.builder.ins().iconst(types::I64, 1);
+                (start, stop, step)
+            }
+            (Some(start), Some(stop), None, None) => {
+                let step = self.builder.ins().iconst(types::I64, 1);
+                (start, stop, step)
+            }
+            (Some(start), Some(stop), Some(step), None) => {
+                // range() raises ValueError for a zero step
+                let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, step, 0);
+                self.bailout_if(is_zero);
+                (start, stop, step)
+            }
+            _ => return Err(JitCompileError::NotSupported),
+        };
+        Ok(JitValue::Range { start, stop, step })
+    }
+
     pub fn add_instruction<C: bytecode::Constant>(
         &mut self,
         instruction: Instruction,
         arg: OpArg,
-        constants: &[C],
+        bytecode: &CodeObject<C>,
+        globals: &dyn Fn(&str) -> Option<JitGlobal>,
     ) -> Result<(), JitCompileError> {
         match instruction {
             Instruction::ExtendedArg => Ok(()),
@@ -248,7 +500,9 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
 
                 Ok(())
             }
-            Instruction::Jump { target } => {
+            Instruction::Jump { target }
+            | Instruction::Break { target }
+            | Instruction::Continue { target } => {
                 let target_block = self.get_or_create_block(target.get(arg));
                 self.builder.ins().jump(target_block, &[]);
 
@@ -269,7 +523,79 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
                 self.store_variable(idx.get(arg), val)
             }
             Instruction::LoadConst { idx } => {
-                self.load_const(constants[idx.get(arg) as usize].borrow_constant())
+                self.load_const(bytecode.constants[idx.get(arg) as usize].borrow_constant())
+            }
+            Instruction::LoadGlobal(idx) => {
+                let name = &bytecode.names[idx.get(arg) as usize];
+                let global = globals(name.as_ref()).ok_or(JitCompileError::NotSupported)?;
+                self.load_global(global)
+            }
+            Instruction::CallFunctionPositional { nargs } => {
+                let nargs = nargs.get(arg) as usize;
+                if self.stack.len() <= nargs {
+                    return Err(JitCompileError::BadBytecode);
+                }
+                let args = self.pop_multiple(nargs);
+                let callee = self.stack.pop().ok_or(JitCompileError::BadBytecode)?;
+                let ret = self.call_function(callee, args)?;
+                self.stack.push(ret);
+                Ok(())
+            }
+            Instruction::GetIter => {
+                let val = self.stack.pop().ok_or(JitCompileError::BadBytecode)?;
+                let JitValue::Range { start, stop, step } = val else {
+                    return Err(JitCompileError::NotSupported);
+                };
+                let current = self.new_temp_variable(types::I64);
+                self.builder.def_var(current, start);
+                self.stack.push(JitValue::RangeIter {
+                    current,
+                    stop,
+                    step,
+                });
+                Ok(())
+            }
+            Instruction::ForIter { target } => {
+                let target = target.get(arg);
+                let Some(&JitValue::RangeIter {
+                    current,
+                    stop,
+                    step,
+                }) = self.stack.last()
+                else {
+                    return Err(JitCompileError::NotSupported);
+                };
+                let value = self.builder.use_var(current);
+
+                // a range counts up while its step is positive, and down otherwise
+                let counts_up = self
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedGreaterThan, step, 0);
+                let below_stop = self.builder.ins().icmp(IntCC::SignedLessThan, value, stop);
+                let above_stop = self
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedGreaterThan, value, stop);
+                let has_next = self.builder.ins().select(counts_up, below_stop, above_stop);
+
+                let exit_block = self.get_or_create_block(target);
+                if !self.loop_exits.contains(&target) {
+                    self.loop_exits.push(target);
+                }
+                self.builder.ins().brz(has_next, exit_block, &[]);
+                let body_block = self.builder.create_block();
+                self.builder.ins().jump(body_block, &[]);
+                self.builder.switch_to_block(body_block);
+
+                let next = self.compile_add(value, step);
+                self.builder.def_var(current, next);
+                self.stack.push(JitValue::Int(value));
+                Ok(())
+            }
+            Instruction::Pop => {
+                self.stack.pop().ok_or(JitCompileError::BadBytecode)?;
+                Ok(())
             }
             Instruction::BuildTuple { size } => {
                 let elements = self.pop_multiple(size.get(arg) as usize);
@@ -403,22 +729,19 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
 
                 let val = match (op, a, b) {
                     (BinaryOperator::Add, JitValue::Int(a), JitValue::Int(b)) => {
-                        let (out, carry) = self.builder.ins().iadd_ifcout(a, b);
-                        self.builder.ins().trapif(
-                            IntCC::Overflow,
-                            carry,
-                            TrapCode::IntegerOverflow,
-                        );
-                        JitValue::Int(out)
+                        JitValue::Int(self.compile_add(a, b))
                     }
                     (BinaryOperator::Subtract, JitValue::Int(a), JitValue::Int(b)) => {
                         JitValue::Int(self.compile_sub(a, b))
                     }
+                    (BinaryOperator::Multiply, JitValue::Int(a), JitValue::Int(b)) => {
+                        JitValue::Int(self.compile_mul(a, b))
+                    }
                     (BinaryOperator::FloorDivide, JitValue::Int(a), JitValue::Int(b)) => {
-                        JitValue::Int(self.builder.ins().sdiv(a, b))
+                        JitValue::Int(self.compile_floor_divmod(a, b).0)
                     }
                     (BinaryOperator::Modulo, JitValue::Int(a), JitValue::Int(b)) => {
-                        JitValue::Int(self.builder.ins().srem(a, b))
+                        JitValue::Int(self.compile_floor_divmod(a, b).1)
                     }
                     (
                         BinaryOperator::Lshift | BinaryOperator::Rshift,
@@ -426,12 +749,9 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
                         JitValue::Int(b),
                     ) => {
                         // Shifts throw an exception if we have a negative shift count
-                        // Remove all bits except the sign bit, and trap if its 1 (i.e. negative).
+                        // Remove all bits except the sign bit, and bail out if its 1 (i.e. negative).
                         let sign = self.builder.ins().ushr_imm(b, 63);
-                        self.builder.ins().trapnz(
-                            sign,
-                            TrapCode::User(CustomTrapCode::NegativeShiftCount as u16),
-                        );
+                        self.bailout_if(sign);
 
                         let out = if op == BinaryOperator::Lshift {
                             self.builder.ins().ishl(a, b)
@@ -499,26 +819,76 @@ impl<'a, 'b> FunctionCompiler<'a, 'b> {
 
                 Ok(())
             }
-            Instruction::SetupLoop { .. } | Instruction::PopBlock => {
-                // TODO: block support
-                Ok(())
-            }
             _ => Err(JitCompileError::NotSupported),
         }
     }
 
+    fn compile_add(&mut self, a: Value, b: Value) -> Value {
+        let out = self.builder.ins().iadd(a, b);
+        // the sum overflowed if its sign differs from the sign of both operands
+        let a_diff = self.builder.ins().bxor(a, out);
+        let b_diff = self.builder.ins().bxor(b, out);
+        let overflow = self.builder.ins().band(a_diff, b_diff);
+        let overflow = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::SignedLessThan, overflow, 0);
+        self.bailout_if(overflow);
+        out
+    }
+
     fn compile_sub(&mut self, a: Value, b: Value) -> Value {
-        // TODO: this should be fine, but cranelift doesn't special-case isub_ifbout
-        // let (out, carry) = self.builder.ins().isub_ifbout(a, b);
-        // self.builder
-        //     .ins()
-        //     .trapif(IntCC::Overflow, carry, TrapCode::IntegerOverflow);
-        // TODO: this shouldn't wrap
-        let neg_b = self.builder.ins().ineg(b);
-        let (out, carry) = self.builder.ins().iadd_ifcout(a, neg_b);
-        self.builder
+        let out = self.builder.ins().isub(a, b);
+        // the difference overflowed if the operands' signs differ and the result's sign differs
+        // from the sign of `a`
+        let operands_diff = self.builder.ins().bxor(a, b);
+        let result_diff = self.builder.ins().bxor(a, out);
+        let overflow = self.builder.ins().band(operands_diff, result_diff);
+        let overflow = self
+            .builder
             .ins()
-            .trapif(IntCC::Overflow, carry, TrapCode::IntegerOverflow);
+            .icmp_imm(IntCC::SignedLessThan, overflow, 0);
+        self.bailout_if(overflow);
+        out
+    }
+
+    fn compile_mul(&mut self, a: Value, b: Value) -> Value {
+        let out = self.builder.ins().imul(a, b);
+        // the product fits in 64 bits iff the high half is the sign extension of the low half
+        let high = self.builder.ins().smulhi(a, b);
+        let sign = self.builder.ins().sshr_imm(out, 63);
+        let overflow = self.builder.ins().icmp(IntCC::NotEqual, high, sign);
+        self.bailout_if(overflow);
         out
     }
+
+    /// Division by zero raises and `i64::MIN // -1` overflows, leave both to the interpreter.
+    fn guard_int_division(&mut self, a: Value, b: Value) {
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, b, 0);
+        self.bailout_if(is_zero);
+        let is_min = self.builder.ins().icmp_imm(IntCC::Equal, a, i64::MIN);
+        let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, b, -1);
+        let overflow = self.builder.ins().band(is_min, is_minus_one);
+        self.bailout_if(overflow);
+    }
+
+    /// `a // b` and `a % b`. Python rounds the quotient towards negative infinity, so the
+    /// remainder has the sign of `b`, whereas `sdiv` and `srem` truncate towards zero.
+    fn compile_floor_divmod(&mut self, a: Value, b: Value) -> (Value, Value) {
+        self.guard_int_division(a, b);
+        let quotient = self.builder.ins().sdiv(a, b);
+        let remainder = self.builder.ins().srem(a, b);
+        let inexact = self.builder.ins().icmp_imm(IntCC::NotEqual, remainder, 0);
+        let signs = self.builder.ins().bxor(remainder, b);
+        let signs_differ = self.builder.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+        let adjust = self.builder.ins().band(inexact, signs_differ);
+        let floor_quotient = self.builder.ins().iadd_imm(quotient, -1);
+        let floor_remainder = self.builder.ins().iadd(remainder, b);
+        (
+            self.builder.ins().select(adjust, floor_quotient, quotient),
+            self.builder
+                .ins()
+                .select(adjust, floor_remainder, remainder),
+        )
+    }
 }
@@ -5,7 +5,7 @@ use cranelift_jit::{JITBuilder, JITModule};
 use cranelift_module::{FuncId, Linkage, Module, ModuleError};
 use instructions::FunctionCompiler;
 use rustpython_compiler_core::bytecode;
-use std::{fmt, mem::ManuallyDrop};
+use std::{cell::Cell, fmt, mem::ManuallyDrop, sync::Arc};
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
@@ -25,6 +25,24 @@ pub enum JitArgumentError {
     ArgumentTypeMismatch,
     #[error("wrong number of arguments")]
     WrongNumberOfArguments,
+    #[error("jitted code bailed out to the interpreter")]
+    Bailout,
+}
+
+/// What a global name refers to, as far as the jit is concerned.
+///
+/// The caller of [`compile_with_globals`] is responsible for checking that the names still refer
+/// to the same objects before invoking the compiled code.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum JitGlobal {
+    Constant(AbiValue),
+    /// The `range` builtin.
+    Range,
+    /// Another function that has already been jitted.
+    Function(Arc<CompiledCode>),
+    /// The function that is being compiled.
+    Recursive,
 }
 
 struct Jit {
@@ -49,7 +67,9 @@ impl Jit {
         &mut self,
         bytecode: &bytecode::CodeObject<C>,
         args: &[JitType],
-    ) -> Result<(FuncId, JitSig), JitCompileError> {
+        globals: &dyn Fn(&str) -> Option<JitGlobal>,
+        recursive_entry: &Cell<*const u8>,
+    ) -> Result<(FuncId, JitSig, Vec<Arc<CompiledCode>>), JitCompileError> {
         for arg in args {
             self.ctx
                 .func
@@ -57,19 +77,32 @@ impl Jit {
                 .params
                 .push(AbiParam::new(arg.to_cranelift()));
         }
+        // the call state
+        let pointer_type = self.module.target_config().pointer_type();
+        self.ctx
+            .func
+            .signature
+            .params
+            .push(AbiParam::new(pointer_type));
 
         let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
         let entry_block = builder.create_block();
         builder.append_block_params_for_function_params(entry_block);
         builder.switch_to_block(entry_block);
 
-        let sig = {
-            let mut compiler =
-                FunctionCompiler::new(&mut builder, bytecode.varnames.len(), args, entry_block);
+        let (sig, dependencies) = {
+            let mut compiler = FunctionCompiler::new(
+                &mut builder,
+                bytecode.varnames.len(),
+                args,
+                entry_block,
+                pointer_type,
+                recursive_entry.as_ptr() as i64,
+            );
 
-            compiler.compile(bytecode)?;
+            compiler.compile(bytecode, globals)?;
 
-            compiler.sig
+            (compiler.sig, compiler.dependencies)
         };
 
         builder.seal_all_blocks();
@@ -85,31 +118,66 @@ impl Jit {
 
         self.module.clear_context(&mut self.ctx);
 
-        Ok((id, sig))
+        Ok((id, sig, dependencies))
     }
 }
 
 pub fn compile<C: bytecode::Constant>(
     bytecode: &bytecode::CodeObject<C>,
     args: &[JitType],
+) -> Result<CompiledCode, JitCompileError> {
+    compile_with_globals(bytecode, args, &|_| None)
+}
+
+/// Like [`compile`], but global names are resolved through `globals`, which allows the
+/// compiled code to use constants, `range` and calls to other jitted functions.
+pub fn compile_with_globals<C: bytecode::Constant>(
+    bytecode: &bytecode::CodeObject<C>,
+    args: &[JitType],
+    globals: &dyn Fn(&str) -> Option<JitGlobal>,
 ) -> Result<CompiledCode, JitCompileError> {
     let mut jit = Jit::new();
 
-    let (id, sig) = jit.build_function(bytecode, args)?;
+    let recursive_entry = Box::new(Cell::new(std::ptr::null()));
+    let (id, sig, dependencies) = jit.build_function(bytecode, args, globals, &recursive_entry)?;
 
     jit.module.finalize_definitions();
 
     let code = jit.module.get_finalized_function(id);
+    recursive_entry.set(code);
     Ok(CompiledCode {
         sig,
         code,
+        _recursive_entry: recursive_entry,
+        _dependencies: dependencies,
         module: ManuallyDrop::new(jit.module),
     })
 }
 
+/// How deep jitted functions can call each other when the caller doesn't say, the default
+/// recursion limit of CPython.
+pub const DEFAULT_RECURSION_LIMIT: usize = 1000;
+
+/// What a jitted function shares with the jitted functions it calls. A pointer to it is passed as
+/// the last parameter of every jitted function.
+#[repr(C)]
+struct CallState {
+    /// How many more nested calls can be made before bailing out.
+    depth_left: i64,
+    /// Set when the code bails out to the interpreter.
+    bailout: u8,
+}
+
+const DEPTH_LEFT_OFFSET: i32 = 0;
+const BAILOUT_OFFSET: i32 = 8;
+
 pub struct CompiledCode {
     sig: JitSig,
     code: *const u8,
+    /// Recursive calls load the address of the function from here.
+    _recursive_entry: Box<Cell<*const u8>>,
+    /// Functions called by this one; they have to outlive it.
+    _dependencies: Vec<Arc<CompiledCode>>,
     module: ManuallyDrop<JITModule>,
 }
 
@@ -131,16 +199,32 @@ impl CompiledCode {
             .map(|(ty, val)| type_check(ty, val).map(|_| val))
             .map(|v| v.map(AbiValue::to_libffi_arg))
             .collect::<Result<Vec<_>, _>>()?;
-        Ok(unsafe { self.invoke_raw(&cif_args) })
+        unsafe { self.invoke_raw(&cif_args, DEFAULT_RECURSION_LIMIT) }
     }
 
-    unsafe fn invoke_raw(&self, cif_args: &[libffi::middle::Arg]) -> Option<AbiValue> {
+    /// Call the code; nested calls of jitted functions deeper than `recursion_limit` bail out.
+    unsafe fn invoke_raw(
+        &self,
+        cif_args: &[libffi::middle::Arg],
+        recursion_limit: usize,
+    ) -> Result<Option<AbiValue>, JitArgumentError> {
+        let state = Cell::new(CallState {
+            depth_left: i64::try_from(recursion_limit).unwrap_or(i64::MAX),
+            bailout: 0,
+        });
+        let state_ptr = state.as_ptr();
+        let mut cif_args = cif_args.to_vec();
+        cif_args.push(libffi::middle::Arg::new(&state_ptr));
+
         let cif = self.sig.to_cif();
         let value = cif.call::<UnTypedAbiValue>(
             libffi::middle::CodePtr::from_ptr(self.code as *const _),
-            cif_args,
+            &cif_args,
         );
-        self.sig.ret.as_ref().map(|ty| value.to_typed(ty))
+        if state.into_inner().bailout != 0 {
+            return Err(JitArgumentError::Bailout);
+        }
+        Ok(self.sig.ret.as_ref().map(|ty| value.to_typed(ty)))
     }
 }
 
@@ -155,7 +239,12 @@ impl JitSig {
             Some(ref ty) => ty.to_libffi(),
             None => libffi::middle::Type::void(),
         };
-        libffi::middle::Cif::new(self.args.iter().map(JitType::to_libffi), ret)
+        let args = self
+            .args
+            .iter()
+            .map(JitType::to_libffi)
+            .chain(std::iter::once(libffi::middle::Type::pointer()));
+        libffi::middle::Cif::new(args, ret)
     }
 }
 
@@ -342,7 +431,16 @@ pub struct Args<'a> {
 }
 
 impl<'a> Args<'a> {
-    pub fn invoke(&self) -> Option<AbiValue> {
-        unsafe { self.code.invoke_raw(&self.cif_args) }
+    pub fn invoke(&self) -> Result<Option<AbiValue>, JitArgumentError> {
+        self.invoke_with_recursion_limit(DEFAULT_RECURSION_LIMIT)
+    }
+
+    /// Like [`Self::invoke`], but bail out when jitted functions call each other more than
+    /// `recursion_limit` levels deep.
+    pub fn invoke_with_recursion_limit(
+        &self,
+        recursion_limit: usize,
+    ) -> Result<Option<AbiValue>, JitArgumentError> {
+        unsafe { self.code.invoke_raw(&self.cif_args, recursion_limit) }
     }
 }
@@ -0,0 +1,93 @@
+use rustpython_jit::{AbiValue, JitArgumentError};
+
+#[test]
+fn test_call_jitted_function() {
+    let hypot_squared = jit_function! { hypot_squared(a:i64, b:i64) -> i64 => r##"
+        def square(x: int):
+            return x * x
+
+        def hypot_squared(a: int, b: int):
+            return square(a) + square(b)
+    "## };
+
+    assert_eq!(hypot_squared(3, 4), Ok(25));
+    assert_eq!(hypot_squared(-2, 0), Ok(4));
+}
+
+#[test]
+fn test_recursive_call() {
+    let fib = jit_function! { fib(n:i64) -> i64 => r##"
+        def fib(n: int):
+            if n < 2:
+                return n
+            return fib(n - 1) + fib(n - 2)
+    "## };
+
+    assert_eq!(fib(0), Ok(0));
+    assert_eq!(fib(1), Ok(1));
+    assert_eq!(fib(10), Ok(55));
+}
+
+#[test]
+fn test_recursion_limit_bails_out() {
+    let code = jit_function! { depth => r##"
+        def depth(n: int):
+            if n == 0:
+                return 0
+            return depth(n - 1) + 1
+    "## };
+
+    assert_eq!(
+        code.invoke(&[AbiValue::Int(500)]),
+        Ok(Some(AbiValue::Int(500)))
+    );
+    assert_eq!(
+        code.invoke(&[AbiValue::Int(100_000)]),
+        Err(JitArgumentError::Bailout)
+    );
+
+    let mut args = code.args_builder();
+    args.set(0, AbiValue::Int(10)).unwrap();
+    let args = args.into_args().unwrap();
+    assert_eq!(
+        args.invoke_with_recursion_limit(10),
+        Ok(Some(AbiValue::Int(10)))
+    );
+    assert_eq!(
+        args.invoke_with_recursion_limit(9),
+        Err(JitArgumentError::Bailout)
+    );
+}
+
+#[test]
+fn test_callee_bailout_propagates() {
+    let ratio = jit_function! { ratio(a:i64, b:i64) -> i64 => r##"
+        def divide(a: int, b: int):
+            return a // b
+
+        def ratio(a: int, b: int):
+            return divide(a, b) + 1
+    "## };
+
+    assert_eq!(ratio(10, 5), Ok(3));
+    assert_eq!(ratio(10, 0), Err(JitArgumentError::Bailout));
+}
+
+#[test]
+fn test_overflow_bails_out() {
+    let mul = jit_function! { mul(a:i64, b:i64) -> i64 => r##"
+        def mul(a: int, b: int):
+            return a * b
+    "## };
+
+    assert_eq!(mul(6, -7), Ok(-42));
+    assert_eq!(mul(i64::MAX, 2), Err(JitArgumentError::Bailout));
+
+    let add = jit_function! { add(a:i64, b:i64) -> i64 => r##"
+        def add(a: int, b: int):
+            return a + b
+    "## };
+
+    assert_eq!(add(i64::MAX, 1), Err(JitArgumentError::Bailout));
+    assert_eq!(add(i64::MIN, -1), Err(JitArgumentError::Bailout));
+}
@@ -1,14 +1,17 @@
+use num_traits::ToPrimitive;
 use rustpython_compiler_core::bytecode::{
     CodeObject, ConstantData, Instruction, OpArg, OpArgState,
 };
-use rustpython_jit::{CompiledCode, JitType};
+use rustpython_jit::{AbiValue, CompiledCode, JitGlobal, JitType};
 use std::collections::HashMap;
 use std::ops::ControlFlow;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Function {
     code: Box<CodeObject>,
     annotations: HashMap<String, StackValue>,
+    globals: HashMap<String, StackValue>,
 }
 
 impl Function {
@@ -27,13 +30,32 @@ impl Function {
             arg_types.push(arg_type);
         }
 
-        rustpython_jit::compile(&self.code, &arg_types).expect("Compile failure")
+        let resolve = |name: &str| match self.globals.get(name) {
+            Some(StackValue::Int(i)) => Some(JitGlobal::Constant(AbiValue::Int(*i))),
+            Some(StackValue::Float(f)) => Some(JitGlobal::Constant(AbiValue::Float(*f))),
+            Some(StackValue::Function(function))
+                if function.code.obj_name == self.code.obj_name =>
+            {
+                Some(JitGlobal::Recursive)
+            }
+            Some(StackValue::Function(function)) => {
+                let mut function = function.clone();
+                function.globals = self.globals.clone();
+                Some(JitGlobal::Function(Arc::new(function.compile())))
+            }
+            None if name == "range" => Some(JitGlobal::Range),
+            _ => None,
+        };
+        rustpython_jit::compile_with_globals(&self.code, &arg_types, &resolve)
+            .expect("Compile failure")
     }
 }
 
 #[derive(Debug, Clone)]
 enum StackValue {
     String(String),
+    Int(i64),
+    Float(f64),
     None,
     Map(HashMap<String, StackValue>),
     Code(Box<CodeObject>),
@@ -44,6 +66,10 @@ impl From<ConstantData> for StackValue {
     fn from(value: ConstantData) -> Self {
         match value {
             ConstantData::Str { value } => StackValue::String(value),
+            ConstantData::Integer { value } => {
+                StackValue::Int(value.to_i64().expect("integer doesn't fit in an i64"))
+            }
+            ConstantData::Float { value } => StackValue::Float(value),
             ConstantData::None => StackValue::None,
             ConstantData::Code { code } => StackValue::Code(code),
             c => unimplemented!("constant {:?} isn't yet supported in py_function!", c),
@@ -126,8 +152,11 @@ impl StackMachine {
                 } else {
                     panic!("Expected function annotations")
                 };
-                self.stack
-                    .push(StackValue::Function(Function { code, annotations }));
+                self.stack.push(StackValue::Function(Function {
+                    code,
+                    annotations,
+                    globals: HashMap::new(),
+                }));
             }
             Instruction::Duplicate => {
                 let value = self.stack.last().unwrap().clone();
@@ -153,7 +182,9 @@ impl StackMachine {
 
     pub fn get_function(&self, name: &str) -> Function {
         if let Some(StackValue::Function(function)) = self.locals.get(name) {
-            function.clone()
+            let mut function = function.clone();
+            function.globals = self.locals.clone();
+            function
         } else {
             panic!("There was no function named {}", name)
         }
@@ -36,6 +36,11 @@ fn test_floor_div() {
     assert_eq!(floor_div(7, 10), Ok(0));
     assert_eq!(floor_div(-3, -1), Ok(3));
     assert_eq!(floor_div(-3, 1), Ok(-3));
+    assert_eq!(floor_div(-7, 2), Ok(-4));
+    assert_eq!(floor_div(7, -2), Ok(-4));
+    assert_eq!(floor_div(-7, -2), Ok(3));
+    assert_eq!(floor_div(-8, 2), Ok(-4));
+    assert_eq!(floor_div(i64::MIN, 1), Ok(i64::MIN));
 }
 
 #[test]
@@ -50,7 +55,11 @@ fn test_mod() {
     assert_eq!(modulo(12, 10), Ok(2));
     assert_eq!(modulo(7, 10), Ok(7));
     assert_eq!(modulo(-3, 1), Ok(0));
-    assert_eq!(modulo(-5, 10), Ok(-5));
+    assert_eq!(modulo(-5, 10), Ok(5));
+    assert_eq!(modulo(5, -10), Ok(-5));
+    assert_eq!(modulo(-5, -10), Ok(-5));
+    assert_eq!(modulo(-20, 10), Ok(0));
+    assert_eq!(modulo(i64::MIN, 3), Ok(1));
 }
 
 #[test]
@@ -1,7 +1,9 @@
 #[macro_use]
 mod common;
 mod bool_tests;
+mod call_tests;
 mod float_tests;
 mod int_tests;
+mod loop_tests;
 mod misc_tests;
 mod none_tests;
@@ -0,0 +1,80 @@
+#[test]
+fn test_range_sum() {
+    let sum = jit_function! { sum(n:i64) -> i64 => r##"
+        def sum(n: int):
+            total = 0
+            for i in range(n):
+                total += i
+            return total
+    "## };
+
+    assert_eq!(sum(0), Ok(0));
+    assert_eq!(sum(1), Ok(0));
+    assert_eq!(sum(10), Ok(45));
+    assert_eq!(sum(-3), Ok(0));
+}
+
+#[test]
+fn test_range_start_stop_step() {
+    let sum = jit_function! { sum(start:i64, stop:i64, step:i64) -> i64 => r##"
+        def sum(start: int, stop: int, step: int):
+            total = 0
+            for i in range(start, stop, step):
+                total += i
+            return total
+    "## };
+
+    assert_eq!(sum(2, 5, 1), Ok(9));
+    assert_eq!(sum(0, 10, 3), Ok(18));
+    assert_eq!(sum(10, 0, -2), Ok(30));
+    assert_eq!(sum(0, 10, -1), Ok(0));
+}
+
+#[test]
+fn test_range_zero_step_bails_out() {
+    let sum = jit_function! { sum(step:i64) -> i64 => r##"
+        def sum(step: int):
+            total = 0
+            for i in range(0, 10, step):
+                total += i
+            return total
+    "## };
+
+    assert_eq!(sum(5), Ok(5));
+    assert_eq!(sum(0), Err(rustpython_jit::JitArgumentError::Bailout));
+}
+
+#[test]
+fn test_nested_loops_with_break_and_continue() {
+    let count = jit_function! { count(n:i64) -> i64 => r##"
+        def count(n: int):
+            total = 0
+            for i in range(n):
+                if i % 2 == 1:
+                    continue
+                for j in range(n):
+                    if j > i:
+                        break
+                    total += 1
+            return total
+    "## };
+
+    assert_eq!(count(0), Ok(0));
+    assert_eq!(count(4), Ok(4));
+    assert_eq!(count(5), Ok(9));
+}
+
+#[test]
+fn test_range_with_global_constant() {
+    let sum = jit_function! { sum() -> i64 => r##"
+        LIMIT = 5
+
+        def sum():
+            total = 0
+            for i in range(LIMIT):
+                total += i * i
+            return total
+    "## };
+
+    assert_eq!(sum(), Ok(30));
+}
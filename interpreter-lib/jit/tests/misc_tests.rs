@@ -63,7 +63,7 @@ fn test_args_builder() {
 
     let args = args_builder.into_args();
     assert!(args.is_some());
-    assert_eq!(args.unwrap().invoke(), Some(AbiValue::Int(1)));
+    assert_eq!(args.unwrap().invoke(), Ok(Some(AbiValue::Int(1))));
 }
 
 #[test]
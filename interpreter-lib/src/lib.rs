@@ -290,4 +290,1531 @@ mod tests {
             })());
         })
     }
+
+    fn run_source(source: &str) {
+        interpreter().enter(|vm| {
+            // as __main__, so that classes defined in the source can be pickled
+            let scope = vm.unwrap_pyresult(setup_main_module(vm));
+            vm.unwrap_pyresult(vm.run_code_string(scope, source, "<test>".to_owned()));
+        })
+    }
+
+    #[test]
+    fn test_import_uses_pyc_cache() {
+        let dir = std::env::temp_dir().join(format!("rustpython-pyc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cached_module.py"), "x = 1\n").unwrap();
+        run_source(&format!(
+            r#"
+import sys, importlib._bootstrap_external as external
+sys.dont_write_bytecode = False
+sys.path.insert(0, {dir:?})
+compiled = []
+source_to_code = external.SourceLoader.source_to_code
+def counting_source_to_code(self, *args, **kwargs):
+    compiled.append(args)
+    return source_to_code(self, *args, **kwargs)
+external.SourceLoader.source_to_code = counting_source_to_code
+try:
+    import cached_module
+    assert cached_module.__cached__.endswith(".{tag}.pyc"), cached_module.__cached__
+    del sys.modules["cached_module"]
+    import cached_module
+    assert cached_module.x == 1
+    assert len(compiled) == 1, "the second import should load the cached bytecode"
+finally:
+    external.SourceLoader.source_to_code = source_to_code
+"#,
+            dir = dir.to_str().unwrap(),
+            tag = rustpython_vm::import::CACHE_TAG,
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cprofile_and_pstats() {
+        run_source(
+            r#"
+import _lsprof, contextlib, cProfile, io, pstats
+assert _lsprof.Profiler.__module__ == "_lsprof"
+
+def fib(n):
+    return n if n < 2 else fib(n - 1) + fib(n - 2)
+
+output = io.StringIO()
+with contextlib.redirect_stdout(output):
+    cProfile.run("sorted(range(10))", sort="cumulative")
+assert "function calls" in output.getvalue(), output.getvalue()
+
+profiler = cProfile.Profile()
+assert profiler.runcall(fib, 10) == 55
+output = io.StringIO()
+stats = pstats.Stats(profiler, stream=output)
+stats.sort_stats("cumulative").print_stats()
+assert "fib" in output.getvalue(), output.getvalue()
+(ncalls, primitive_calls), = [
+    (ncalls, primitive_calls)
+    for (_, _, name), (primitive_calls, ncalls, _, _, _) in stats.stats.items()
+    if name == "fib"
+]
+assert (ncalls, primitive_calls) == (177, 1), (ncalls, primitive_calls)
+"#,
+        );
+    }
+
+    #[test]
+    fn test_gc_while_thread_blocks_in_recv() {
+        run_source(
+            r#"
+import _socket, _thread, gc, time
+a, b = _socket.socketpair()
+done = _thread.allocate_lock()
+done.acquire()
+def reader():
+    a.recv(1)
+    done.release()
+_thread.start_new_thread(reader, ())
+time.sleep(0.1)
+
+class A:
+    def __del__(self):
+        finalized.append(True)
+finalized = []
+x = A()
+x.self = x
+assert not gc.is_finalized(x)
+del x
+assert gc.collect() >= 1, "a thread blocked in recv should not hold up the collection"
+assert finalized == [True]
+
+b.send(b"x")
+done.acquire()
+a.close()
+b.close()
+"#,
+        );
+    }
+
+    #[test]
+    fn test_pickle_nested_list() {
+        run_source(
+            r#"
+import _pickle, sys
+sys.setrecursionlimit(200)
+shallow = []
+for _ in range(50):
+    shallow = [shallow]
+assert _pickle.loads(_pickle.dumps(shallow)) == shallow
+deep = []
+for _ in range(1000):
+    deep = [deep]
+try:
+    _pickle.dumps(deep)
+except RecursionError:
+    pass
+else:
+    raise AssertionError("pickling a deeply nested list should raise RecursionError")
+"#,
+        );
+    }
+
+    #[test]
+    fn test_pickle_fast_mode_cycle() {
+        run_source(
+            r#"
+import _io, _pickle, sys
+sys.setrecursionlimit(200)
+cycle = []
+cycle.append(cycle)
+pickler = _pickle.Pickler(_io.BytesIO())
+pickler.fast = True
+try:
+    pickler.dump(cycle)
+except RecursionError:
+    pass
+else:
+    raise AssertionError("pickling a cycle in fast mode should raise RecursionError")
+"#,
+        );
+    }
+
+    #[test]
+    fn test_pickle_protocols_match_python() {
+        run_source(
+            r#"
+import _pickle, pickle
+
+class Point:
+    def __init__(self, x, y):
+        self.x, self.y = x, y
+    def __eq__(self, other):
+        return type(other) is Point and (self.x, self.y) == (other.x, other.y)
+
+shared = [1, 2]
+values = [
+    None, True, False, 0, 1, -1, 255, 256, 65536, 2**31, -2**31 - 1, 2**64, -(2**100),
+    0.5, -1e300, float("inf"), 1j, "", "abc", "héllo \U0001f600", "a" * 300,
+    "back\\slash\nnew line", b"", b"bytes", b"x" * 300, bytearray(b"ba"),
+    (), (1,), (1, 2), (1, 2, 3), tuple(range(10)), [], [1, [2, [3]]],
+    {}, {"a": 1, 2: "b"}, set(), {1, 2, 3}, frozenset({4}), [shared, shared],
+    Point(1, "2"), range(1, 10, 2), slice(1, None, 2), len, Point, ..., NotImplemented, type(None),
+]
+for protocol in range(0, pickle.HIGHEST_PROTOCOL + 1):
+    for value in values:
+        native = _pickle.dumps(value, protocol)
+        # the pure Python pickler escapes protocol 0 strings with a codec we don't have
+        if protocol > 0:
+            python = pickle._dumps(value, protocol)
+            assert native == python, (protocol, value, native, python)
+        for loads in (_pickle.loads, pickle._loads) if protocol > 0 else (_pickle.loads,):
+            loaded = loads(native)
+            assert loaded == value or loaded is value, (protocol, value)
+    loaded = _pickle.loads(_pickle.dumps([shared, shared], protocol))
+    assert loaded[0] is loaded[1]
+"#,
+        );
+    }
+
+    #[test]
+    fn test_pickle_buffers_and_hooks() {
+        run_source(
+            r#"
+import _pickle, pickle, io, copyreg
+
+class Point:
+    def __init__(self, x, y):
+        self.x, self.y = x, y
+    def __eq__(self, other):
+        return type(other) is Point and (self.x, self.y) == (other.x, other.y)
+
+data = bytearray(b"out of band")
+buffers = []
+dumped = _pickle.dumps(pickle.PickleBuffer(data), 5, buffer_callback=buffers.append)
+assert len(buffers) == 1 and bytes(buffers[0]) == b"out of band"
+assert b"out of band" not in dumped
+loaded = _pickle.loads(dumped, buffers=buffers)
+assert bytes(loaded) == b"out of band"
+assert bytes(pickle._loads(dumped, buffers=buffers)) == b"out of band"
+in_band = _pickle.dumps(pickle.PickleBuffer(b"in band"), 5)
+assert _pickle.loads(in_band) == b"in band"
+assert in_band == pickle._dumps(pickle.PickleBuffer(b"in band"), 5)
+try:
+    _pickle.loads(dumped)
+except _pickle.UnpicklingError:
+    pass
+else:
+    raise AssertionError("out-of-band data needs buffers")
+try:
+    _pickle.dumps(pickle.PickleBuffer(data), 4)
+except _pickle.PicklingError:
+    pass
+else:
+    raise AssertionError("PickleBuffer needs protocol 5")
+view = pickle.PickleBuffer(data).raw()
+assert view.format == "B" and bytes(view) == bytes(data)
+
+# persistent ids
+class DBPickler(_pickle.Pickler):
+    def persistent_id(self, obj):
+        return ("row", obj.x) if isinstance(obj, Point) else None
+class DBUnpickler(_pickle.Unpickler):
+    def persistent_load(self, pid):
+        kind, key = pid
+        assert kind == "row"
+        return Point(key, "loaded")
+for protocol in (1, 2, 5):
+    f = io.BytesIO()
+    DBPickler(f, protocol).dump([Point(7, "ignored"), 3])
+    f.seek(0)
+    assert DBUnpickler(f).load() == [Point(7, "loaded"), 3]
+try:
+    _pickle.loads(b"P0\n.")
+except _pickle.UnpicklingError:
+    pass
+else:
+    raise AssertionError("a persistent id needs persistent_load")
+
+# reducer_override and dispatch_table
+class Override(_pickle.Pickler):
+    def reducer_override(self, obj):
+        if isinstance(obj, Point):
+            return complex, (obj.x, 0)
+        return NotImplemented
+f = io.BytesIO()
+Override(f, 4).dump([Point(3, 4), "kept"])
+assert _pickle.loads(f.getvalue()) == [3 + 0j, "kept"]
+
+table = copyreg.dispatch_table.copy()
+table[Point] = lambda p: (tuple, ((p.x, p.y),))
+f = io.BytesIO()
+pickler = _pickle.Pickler(f, 3)
+pickler.dispatch_table = table
+pickler.dump(Point(5, 6))
+assert _pickle.loads(f.getvalue()) == (5, 6)
+class TablePickler(_pickle.Pickler):
+    dispatch_table = table
+f = io.BytesIO()
+TablePickler(f).dump(Point(8, 9))
+assert _pickle.loads(f.getvalue()) == (8, 9)
+assert _pickle.loads(_pickle.dumps(Point(8, 9))) == Point(8, 9)
+"#,
+        );
+    }
+
+    #[test]
+    fn test_datetime_matches_pure_python() {
+        run_source(
+            r#"
+import _datetime as c, _pydatetime as py, _pickle, operator
+
+# the calendar arithmetic, across leap years, centuries and the ends of the range
+ordinals = list(range(1, 800)) + list(range(730000, 731200)) + list(range(3651000, 3652060))
+ordinals += list(range(1, 3652060, 997))
+for n in ordinals:
+    d, e = c.date.fromordinal(n), py.date.fromordinal(n)
+    assert (d.year, d.month, d.day) == (e.year, e.month, e.day), n
+    assert d.toordinal() == n
+    assert d.weekday() == e.weekday() and d.isoweekday() == e.isoweekday(), n
+    assert tuple(d.isocalendar()) == tuple(e.isocalendar()), n
+    assert c.date.fromisocalendar(*d.isocalendar()) == d, n
+    assert d.isoformat() == e.isoformat() and d.ctime() == e.ctime(), n
+    assert c.date.fromisoformat(e.isoformat()) == d, n
+    assert d.timetuple() == e.timetuple(), n
+for text in ["2021-W01-1", "2020-W53-7", "20210104", "2021W011", "2021-01-04"]:
+    assert str(c.date.fromisoformat(text)) == str(py.date.fromisoformat(text)), text
+for bad in ["2021-02-29", "2021-13-01", "2021-W54-1", "2021-1-1", "10000-01-01", "2021-01-01x"]:
+    for module in (c, py):
+        try:
+            module.date.fromisoformat(bad)
+        except ValueError:
+            pass
+        else:
+            raise AssertionError((module, bad))
+
+# timedelta normalization and arithmetic
+deltas = [
+    (0, 0, 0), (1, 2, 3), (-1, 0, 0), (0, -1, 0), (0, 0, -1), (0, 86399, 999999),
+    (999999999, 86399, 999999), (-999999999, 0, 0), (3, -100000, 1500000), (0, 1, 1000001),
+]
+for args in deltas:
+    a, b = c.timedelta(*args), py.timedelta(*args)
+    assert (a.days, a.seconds, a.microseconds) == (b.days, b.seconds, b.microseconds), args
+    assert str(a) == str(b) and repr(a) == repr(b), args
+    assert a.total_seconds() == b.total_seconds() and bool(a) == bool(b), args
+    if abs(a.days) > 10**8:
+        continue
+    assert str(-a) == str(-b) and str(+a) == str(+b) and str(abs(a)) == str(abs(b)), args
+    for other in [(0, 25200, 3), (-2, 5, 0)]:
+        x, y = c.timedelta(*other), py.timedelta(*other)
+        for op in [operator.add, operator.sub, operator.floordiv, operator.truediv, operator.mod, divmod]:
+            assert str(op(a, x)) == str(op(b, y)), (args, other, op)
+    for factor in (2, -3, 0.5, 1 / 3, 2.5):
+        assert str(a * factor) == str(factor * b) and str(a / factor) == str(b / factor), (args, factor)
+    assert str(a // 7) == str(b // 7), args
+    kwargs = dict(weeks=1.5, hours=-0.25, milliseconds=1.5)
+    assert str(c.timedelta(*args, **kwargs)) == str(py.timedelta(*args, **kwargs)), args
+try:
+    c.timedelta(days=1000000000)
+except OverflowError:
+    pass
+else:
+    raise AssertionError("timedelta out of range")
+
+# datetimes and times, naive and aware
+zones = [None, c.timezone.utc, c.timezone(c.timedelta(hours=5, minutes=30)), c.timezone(-c.timedelta(hours=8), "PST")]
+pyzones = [None, py.timezone.utc, py.timezone(py.timedelta(hours=5, minutes=30)), py.timezone(-py.timedelta(hours=8), "PST")]
+formats = ["%Y-%m-%d %H:%M:%S.%f", "%a %A %b %B %j %U %W %w %y", "%z %Z %%", "%G-W%V-%u", "%I %p %c"]
+stamps = [(1970, 1, 1, 0, 0, 0, 0), (2000, 2, 29, 23, 59, 59, 999999), (1, 1, 3, 0, 0, 0, 0),
+          (9999, 12, 29, 23, 59, 59, 999999), (2021, 3, 14, 2, 30, 0, 500), (1899, 12, 31, 12, 0, 1, 10)]
+for stamp in stamps:
+    for tz, pytz in zip(zones, pyzones):
+        a, b = c.datetime(*stamp, tzinfo=tz), py.datetime(*stamp, tzinfo=pytz)
+        assert str(a) == str(b) and repr(a) == repr(b), (repr(a), repr(b))
+        assert _pickle.loads(_pickle.dumps(a)) == a
+        for sep, spec in [("T", "auto"), (" ", "hours"), ("T", "minutes"), ("_", "milliseconds")]:
+            assert a.isoformat(sep, spec) == b.isoformat(sep, spec), (stamp, sep, spec)
+        assert c.datetime.fromisoformat(b.isoformat()) == a
+        if stamp[0] >= 1000:
+            for fmt in formats:
+                assert a.strftime(fmt) == b.strftime(fmt), (stamp, fmt, a.strftime(fmt), b.strftime(fmt))
+        assert a.timetuple() == b.timetuple() and a.ctime() == b.ctime()
+        if tz is not None:
+            assert a.utctimetuple() == b.utctimetuple() and a.timestamp() == b.timestamp()
+            assert str(a.astimezone(zones[2])) == str(b.astimezone(pyzones[2]))
+        for days, hours, microseconds in [(1, 0, -1), (0, -36, 0)]:
+            delta, pydelta = c.timedelta(days, 0, microseconds, 0, 0, hours), py.timedelta(days, 0, microseconds, 0, 0, hours)
+            assert str(a + delta) == str(b + pydelta) and str(a - delta) == str(b - pydelta)
+        t, u = a.timetz(), b.timetz()
+        assert str(t) == str(u) and t.isoformat("milliseconds") == u.isoformat("milliseconds")
+        assert c.time.fromisoformat(u.isoformat()) == t
+        assert a.replace(year=2004, fold=1).fold == 1 and a.replace(microsecond=0).microsecond == 0
+for timestamp in (0, -1.5, 86400 * 365.25 * 30 + 0.25, 1e9 + 0.999999):
+    for tz, pytz in zip(zones, pyzones):
+        if tz is not None:
+            assert str(c.datetime.fromtimestamp(timestamp, tz)) == str(py.datetime.fromtimestamp(timestamp, pytz))
+# aware comparisons go through UTC, naive and aware never compare equal
+x = c.datetime(2020, 1, 1, 12, tzinfo=c.timezone.utc)
+y = c.datetime(2020, 1, 1, 17, 30, tzinfo=c.timezone(c.timedelta(hours=5, minutes=30)))
+assert x == y and hash(x) == hash(y) and not x < y
+assert x != x.replace(tzinfo=None)
+try:
+    x < x.replace(tzinfo=None)
+except TypeError:
+    pass
+else:
+    raise AssertionError("naive and aware datetimes can't be ordered")
+assert (y - x) == c.timedelta(0) and (x.replace(tzinfo=None) - c.datetime(2019, 12, 31)) == c.timedelta(days=1, hours=12)
+assert c.datetime.combine(c.date(2000, 1, 2), c.time(3, 4, tzinfo=c.timezone.utc)).tzinfo is c.timezone.utc
+try:
+    -c.timedelta.max
+except OverflowError:
+    pass
+else:
+    raise AssertionError("-timedelta.max is out of range")
+"#,
+        );
+    }
+
+    #[test]
+    fn test_zoneinfo_transitions_and_fold() {
+        run_source(
+            r#"
+import _zoneinfo, io, struct, calendar
+from zoneinfo import _zoneinfo as pyzoneinfo
+from datetime import datetime, timedelta, timezone
+
+def tzif(transitions, types, abbrs, footer):
+    def block(fmt):
+        header = b"TZif2" + bytes(15) + struct.pack(">6l", 0, 0, 0, len(transitions), len(types), len(abbrs))
+        data = b"".join(struct.pack(fmt, when) for when, _ in transitions)
+        data += bytes(index for _, index in transitions)
+        data += b"".join(struct.pack(">lBB", *ttinfo) for ttinfo in types)
+        return header + data + abbrs
+    return block(">l") + block(">q") + b"\n" + footer + b"\n"
+
+def utc(*fields):
+    return calendar.timegm(datetime(*fields).timetuple())
+
+# US Eastern with the rules before 2007 spelled out, and the current ones in the footer
+data = tzif(
+    [(utc(1999, 4, 4, 7), 1), (utc(1999, 10, 31, 6), 0), (utc(2000, 4, 2, 7), 1), (utc(2000, 10, 29, 6), 0)],
+    [(-18000, 0, 0), (-14400, 1, 4)],
+    b"EST\0EDT\0",
+    b"EST5EDT,M3.2.0,M11.1.0",
+)
+native = _zoneinfo.ZoneInfo.from_file(io.BytesIO(data), key="Test/Eastern")
+python = pyzoneinfo.ZoneInfo.from_file(io.BytesIO(data), key="Test/Eastern")
+assert native.key == "Test/Eastern" and str(native) == "Test/Eastern"
+
+windows = [(1998, 6, 1), (1999, 4, 3), (1999, 10, 30), (2000, 4, 1), (2000, 10, 28), (2021, 3, 13), (2021, 11, 6), (2100, 3, 13), (2100, 11, 6)]
+for start in windows:
+    first = datetime(*start)
+    for step in range(0, 3 * 48):
+        wall = first + timedelta(minutes=30 * step)
+        for fold in (0, 1):
+            dt = wall.replace(fold=fold)
+            for method in ("utcoffset", "dst", "tzname"):
+                got, want = getattr(native, method)(dt), getattr(python, method)(dt)
+                assert got == want, (dt, fold, method, got, want)
+        moment = wall.replace(tzinfo=timezone.utc)
+        got = moment.astimezone(native)
+        want = moment.astimezone(python)
+        assert (got.replace(tzinfo=None), got.fold) == (want.replace(tzinfo=None), want.fold), (moment, got, want)
+        assert got.astimezone(timezone.utc) == moment
+
+# the repeated hour belongs to DST the first time and to standard time with fold=1
+ambiguous = datetime(2021, 11, 7, 1, 30, tzinfo=native)
+assert ambiguous.tzname() == "EDT" and ambiguous.replace(fold=1).tzname() == "EST"
+assert ambiguous.utcoffset() == timedelta(hours=-4) and ambiguous.replace(fold=1).utcoffset() == timedelta(hours=-5)
+assert ambiguous.dst() == timedelta(hours=1) and ambiguous.replace(fold=1).dst() == timedelta(0)
+assert (ambiguous.replace(fold=1) - ambiguous) == timedelta(0)
+assert ambiguous.astimezone(timezone.utc) + timedelta(hours=1) == ambiguous.replace(fold=1).astimezone(timezone.utc)
+# in the skipped hour fold=0 uses the offset from before the gap
+missing = datetime(2021, 3, 14, 2, 30, tzinfo=native)
+assert missing.utcoffset() == timedelta(hours=-5) and missing.replace(fold=1).utcoffset() == timedelta(hours=-4)
+second = datetime(2021, 11, 7, 6, 30, tzinfo=timezone.utc).astimezone(native)
+assert (second.hour, second.fold, second.tzname()) == (1, 1, "EST")
+
+fixed = _zoneinfo.ZoneInfo.from_file(io.BytesIO(tzif([], [(19800, 0, 0)], b"IST\0", b"IST-5:30")))
+assert fixed.utcoffset(None) == timedelta(hours=5, minutes=30) and fixed.tzname(datetime(2000, 1, 1)) == "IST"
+assert fixed.key is None
+for bad in (b"", b"TZif", b"NOPE" + data[4:], data[:60]):
+    try:
+        _zoneinfo.ZoneInfo.from_file(io.BytesIO(bad))
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(bad)
+"#,
+        );
+    }
+
+    #[test]
+    fn test_heapq_matches_pure_python() {
+        run_source(
+            r#"
+import _heapq, sys
+sys.modules["_heapq"] = None
+sys.modules.pop("heapq", None)
+import heapq as pyheapq
+sys.modules["_heapq"] = _heapq
+assert pyheapq.heappush is not _heapq.heappush
+
+seed = 14
+def randrange(n):
+    global seed
+    seed = (seed * 1103515245 + 12345) % 2**31
+    return seed % n
+for size in (0, 1, 2, 3, 7, 64, 257):
+    items = [randrange(50) for _ in range(size)]
+    a, b = list(items), list(items)
+    _heapq.heapify(a)
+    pyheapq.heapify(b)
+    assert a == b, items
+    for _ in range(size):
+        item = randrange(50)
+        op = randrange(3)
+        if op == 0:
+            _heapq.heappush(a, item)
+            pyheapq.heappush(b, item)
+        elif op == 1 and a:
+            assert _heapq.heapreplace(a, item) == pyheapq.heapreplace(b, item)
+        else:
+            assert _heapq.heappushpop(a, item) == pyheapq.heappushpop(b, item)
+        assert a == b
+    assert [_heapq.heappop(a) for _ in range(len(a))] == [pyheapq.heappop(b) for _ in range(len(b))]
+    assert a == b == []
+    a, b = list(items), list(items)
+    _heapq._heapify_max(a)
+    pyheapq._heapify_max(b)
+    assert a == b
+    if a:
+        assert _heapq._heapreplace_max(a, 25) == pyheapq._heapreplace_max(b, 25) and a == b
+        popped = [_heapq._heappop_max(a) for _ in range(len(a))]
+        assert popped == [pyheapq._heappop_max(b) for _ in range(len(b))]
+        assert popped == sorted(popped, reverse=True) and a == b == []
+
+# merge, nlargest and nsmallest use the native primitives
+runs = [sorted(randrange(100) for _ in range(n)) for n in (0, 5, 9, 3)]
+flat = sorted(x for run in runs for x in run)
+assert list(pyheapq.merge(*runs)) == flat
+assert list(pyheapq.merge(*[run[::-1] for run in runs], reverse=True)) == flat[::-1]
+assert list(pyheapq.merge(["bb", "a"], ["ccc"], key=len, reverse=True)) == ["ccc", "bb", "a"]
+assert pyheapq.nlargest(3, flat) == flat[-3:][::-1] and pyheapq.nsmallest(2, flat, key=lambda x: -x) == flat[-2:][::-1]
+
+# ties keep the heap stable, errors leave it valid
+pairs = [(1, "b"), (1, "a"), (0, "z")]
+heap = []
+for pair in pairs:
+    _heapq.heappush(heap, pair)
+assert [_heapq.heappop(heap) for _ in pairs] == sorted(pairs)
+heap = [1, 2, 3]
+try:
+    _heapq.heappush(heap, "x")
+except TypeError:
+    pass
+else:
+    raise AssertionError("str and int don't compare")
+assert heap[:3] == [1, 2, 3]
+for call in (lambda: _heapq.heappop([]), lambda: _heapq.heapreplace([], 1)):
+    try:
+        call()
+    except IndexError:
+        pass
+    else:
+        raise AssertionError("empty heap")
+for call in (lambda: _heapq.heapify((1, 2)), lambda: _heapq.heappush(None, 1)):
+    try:
+        call()
+    except TypeError:
+        pass
+    else:
+        raise AssertionError("a heap must be a list")
+
+class Evil:
+    def __lt__(self, other):
+        heap.clear()
+        return False
+heap = [Evil() for _ in range(5)]
+try:
+    _heapq.heappush(heap, Evil())
+except (IndexError, RuntimeError):
+    pass
+"#,
+        );
+    }
+
+    #[test]
+    fn test_asyncio_futures_and_tasks() {
+        // the native futures and tasks must behave exactly like the pure Python ones
+        run_source(
+            r#"
+import asyncio, _asyncio, collections
+from asyncio import futures, tasks, CancelledError, InvalidStateError
+
+class Loop:
+    """Just enough of an event loop to drive futures and tasks step by step."""
+    def __init__(self):
+        self.ready = collections.deque()
+        self.errors = []
+    def call_soon(self, callback, *args, context=None):
+        self.ready.append((callback, args, context))
+    def get_debug(self):
+        return False
+    def is_running(self):
+        return True
+    def call_exception_handler(self, context):
+        self.errors.append(context["message"])
+    def create_future(self):
+        return self.Future(loop=self)
+    def run(self):
+        asyncio.events._set_running_loop(self)
+        try:
+            while self.ready:
+                callback, args, context = self.ready.popleft()
+                if context is None:
+                    callback(*args)
+                else:
+                    context.run(callback, *args)
+        finally:
+            asyncio.events._set_running_loop(None)
+
+def scenarios(Future, Task):
+    log = []
+    loop = Loop()
+    loop.Future = Future
+
+    # results, exceptions and their tracebacks travel through awaits
+    async def produce(fut):
+        return await fut + 1
+    async def fail():
+        raise ValueError("boom")
+    async def consume():
+        fut = loop.create_future()
+        loop.call_soon(fut.set_result, 41)
+        log.append(await Task(produce(fut), loop=loop))
+        try:
+            await Task(fail(), loop=loop)
+        except ValueError as e:
+            log.append(("caught", e.args, e.__traceback__ is not None))
+        return "done"
+    main = Task(consume(), loop=loop)
+    loop.run()
+    log.append((main.done(), main.result()))
+    failed = Task(fail(), loop=loop)
+    loop.run()
+    log.append((type(failed.exception()).__name__, failed.cancelled()))
+
+    # cancellation reaches the awaited future and the coroutine, with its message
+    waiting = loop.create_future()
+    async def wait_and_recover():
+        try:
+            await waiting
+        except CancelledError as e:
+            log.append(("cancelled", e.args, asyncio.current_task().cancelling()))
+            asyncio.current_task().uncancel()
+        return "recovered"
+    recovering = Task(wait_and_recover(), loop=loop)
+    loop.run()
+    log.append(recovering.cancel("stop"))
+    loop.run()
+    log.append((waiting.cancelled(), recovering.result(), recovering.cancelling()))
+    async def never():
+        log.append("never runs")
+    early = Task(never(), loop=loop)
+    early.cancel()
+    loop.run()
+    log.append((early.cancelled(), early.cancel()))
+    try:
+        early.result()
+    except CancelledError:
+        log.append("result raises CancelledError")
+    outer_waiting = loop.create_future()
+    async def inner():
+        await outer_waiting
+    async def outer():
+        await Task(inner(), loop=loop)
+    outer_task = Task(outer(), loop=loop)
+    loop.run()
+    outer_waiting.cancel()
+    loop.run()
+    log.append(("propagated", outer_task.cancelled()))
+
+    # done callbacks run soon, in order, in their context, and can be removed
+    class Context:
+        def __init__(self, name):
+            self.name = name
+        def run(self, callback, *args):
+            log.append(("in context", self.name))
+            return callback(*args)
+    fut = loop.create_future()
+    def record(name):
+        return lambda f: log.append((name, f.result()))
+    second = record("second")
+    fut.add_done_callback(record("first"))
+    fut.add_done_callback(second)
+    fut.add_done_callback(second)
+    fut.add_done_callback(record("third"), context=Context("third"))
+    log.append(("removed", fut.remove_done_callback(second), fut.remove_done_callback(second)))
+    fut.set_result("value")
+    log.append("set")
+    loop.run()
+    fut.add_done_callback(record("late"))
+    loop.run()
+    async def in_context():
+        return "stepped"
+    stepped = Task(in_context(), loop=loop, context=Context("task"))
+    loop.run()
+    log.append(stepped.result())
+
+    # current_task and all_tasks see the running task and the pending ones
+    log.append(asyncio.current_task(loop) is None)
+    blocker = loop.create_future()
+    async def inspect():
+        me = asyncio.current_task()
+        log.append((me is inspecting, me in asyncio.all_tasks(loop)))
+        await blocker
+    inspecting = Task(inspect(), loop=loop, name="inspector")
+    loop.run()
+    log.append((inspecting.get_name(), inspecting in asyncio.all_tasks(loop), asyncio.current_task(loop)))
+    blocker.set_result(None)
+    loop.run()
+    log.append(inspecting in asyncio.all_tasks(loop))
+
+    # misuse of futures
+    fut = loop.create_future()
+    for call in (fut.result, fut.exception):
+        try:
+            call()
+        except InvalidStateError as e:
+            log.append(type(e).__name__)
+    fut.set_result(1)
+    try:
+        fut.set_result(2)
+    except InvalidStateError:
+        log.append("set twice")
+    log.append((fut.cancel(), list(fut.__await__()) == [] if fut.done() else None))
+    stopped = loop.create_future()
+    try:
+        stopped.set_exception(StopIteration)
+    except TypeError:
+        log.append(("StopIteration refused", stopped.done()))
+    log.append(loop.errors)
+    return log
+
+native = scenarios(_asyncio.Future, _asyncio.Task)
+python = scenarios(futures._PyFuture, tasks._PyTask)
+assert native == python, (native, python)
+assert native[:4] == [42, ("caught", ("boom",), True), (True, "done"), ("ValueError", False)], native
+assert ("cancelled", ("stop",), 1) in native and (True, "recovered", 0) in native
+assert "never runs" not in native and ("propagated", True) in native
+start = native.index("set")
+assert native[start + 1:start + 5] == [("first", "value"), ("in context", "third"), ("third", "value"), ("late", "value")], native
+assert ("removed", 2, 0) in native and ("in context", "task") in native and "stepped" in native
+assert ("inspector", True, None) in native and (True, True) in native
+assert ("StopIteration refused", False) in native and native[-1] == [], native
+"#,
+        );
+    }
+
+    #[cfg(feature = "lzma")]
+    #[test]
+    fn test_lzma_formats_filters_and_errors() {
+        run_source(
+            r#"
+import io, lzma, tarfile, _lzma
+
+data = b"".join(b"line %d of some fairly repetitive text\n" % (i % 97) for i in range(3000))
+data += bytes(range(256)) * 8
+
+# every container format round-trips, in one go and in pieces
+for fmt, check in [(lzma.FORMAT_XZ, lzma.CHECK_CRC64), (lzma.FORMAT_XZ, lzma.CHECK_SHA256),
+                   (lzma.FORMAT_XZ, lzma.CHECK_NONE), (lzma.FORMAT_ALONE, -1)]:
+    packed = lzma.compress(data, format=fmt, check=check)
+    assert len(packed) < len(data) // 10, (fmt, len(packed))
+    assert lzma.decompress(packed) == data, fmt
+    assert lzma.decompress(packed, format=fmt) == data, fmt
+    d = lzma.LZMADecompressor(format=fmt)
+    out = b"".join(d.decompress(packed[i:i + 100]) for i in range(0, len(packed), 100))
+    assert out == data and d.eof and d.unused_data == b"", fmt
+    if fmt == lzma.FORMAT_XZ:
+        assert d.check == check, (d.check, check)
+    c = lzma.LZMACompressor(format=fmt, check=check)
+    chunks = [c.compress(data[i:i + 1000]) for i in range(0, len(data), 1000)]
+    chunks.append(c.flush())
+    assert lzma.decompress(b"".join(chunks)) == data, fmt
+
+# a stream produced by CPython's liblzma decodes to the same bytes
+blob = bytes.fromhex(
+    "fd377a585a000004e6d6b4460200210116000000742fe5a301000568656c6c6f0a000000"
+    "a56097f194f6fde000011e06c12fa41d1fb6f37d010000000004595a")
+assert lzma.decompress(blob) == b"hello\n"
+
+# concatenated streams and trailing data
+two = lzma.compress(b"abc") + lzma.compress(b"def")
+assert lzma.decompress(two) == b"abcdef"
+d = lzma.LZMADecompressor()
+assert d.decompress(lzma.compress(b"abc") + b"tail") == b"abc" and d.eof and d.unused_data == b"tail"
+try:
+    d.decompress(b"more")
+except EOFError:
+    pass
+else:
+    raise AssertionError("decompressing after the end of the stream")
+
+# max_length limits the output and needs_input follows it
+d = lzma.LZMADecompressor()
+packed = lzma.compress(data)
+head = d.decompress(packed, max_length=10)
+assert head == data[:10] and not d.needs_input
+rest = d.decompress(b"")
+while not d.eof:
+    rest += d.decompress(b"")
+assert head + rest == data
+
+# raw streams with custom filter chains
+chains = [
+    [{"id": lzma.FILTER_LZMA2, "preset": 6}],
+    [{"id": lzma.FILTER_LZMA2, "preset": 9 | lzma.PRESET_EXTREME, "dict_size": 1 << 20}],
+    [{"id": lzma.FILTER_DELTA, "dist": 4}, {"id": lzma.FILTER_LZMA2, "preset": 1}],
+    [{"id": lzma.FILTER_X86}, {"id": lzma.FILTER_LZMA2, "lc": 0, "lp": 2, "pb": 2,
+                                 "mode": lzma.MODE_FAST, "mf": lzma.MF_HC4, "nice_len": 64}],
+]
+for filters in chains:
+    packed = lzma.compress(data, format=lzma.FORMAT_RAW, filters=filters)
+    assert lzma.decompress(packed, format=lzma.FORMAT_RAW, filters=filters) == data, filters
+    packed = lzma.compress(data, filters=filters)
+    assert lzma.decompress(packed) == data, filters
+lzma1 = [{"id": lzma.FILTER_LZMA1, "preset": 2}]
+packed = lzma.compress(data, format=lzma.FORMAT_RAW, filters=lzma1)
+assert lzma.decompress(packed, format=lzma.FORMAT_RAW, filters=lzma1) == data
+
+# filter properties
+props = _lzma._encode_filter_properties({"id": lzma.FILTER_LZMA1, "lc": 3, "lp": 0, "pb": 2, "dict_size": 1 << 16})
+assert props == b"]\x00\x00\x01\x00", props
+spec = _lzma._decode_filter_properties(lzma.FILTER_LZMA1, props)
+assert (spec["id"], spec["lc"], spec["lp"], spec["pb"], spec["dict_size"]) == (lzma.FILTER_LZMA1, 3, 0, 2, 1 << 16), spec
+spec = _lzma._decode_filter_properties(lzma.FILTER_DELTA, b"\x03")
+assert spec == {"id": lzma.FILTER_DELTA, "dist": 4}, spec
+
+assert lzma.is_check_supported(lzma.CHECK_NONE) and lzma.is_check_supported(lzma.CHECK_CRC32)
+assert not lzma.is_check_supported(lzma.CHECK_UNKNOWN)
+
+# bad arguments
+bad_calls = [
+    (ValueError, lambda: lzma.LZMADecompressor(format=lzma.FORMAT_RAW)),
+    (ValueError, lambda: lzma.LZMADecompressor(format=lzma.FORMAT_XZ, filters=chains[0])),
+    (ValueError, lambda: lzma.LZMADecompressor(format=lzma.FORMAT_RAW, memlimit=1 << 20, filters=chains[0])),
+    (ValueError, lambda: lzma.LZMACompressor(format=lzma.FORMAT_ALONE, check=lzma.CHECK_CRC32)),
+    (ValueError, lambda: lzma.LZMACompressor(preset=3, filters=chains[0])),
+    (ValueError, lambda: lzma.LZMACompressor(format=42)),
+    (ValueError, lambda: lzma.compress(b"x", format=lzma.FORMAT_RAW, filters=[{"id": 12345}])),
+    (ValueError, lambda: lzma.compress(b"x", format=lzma.FORMAT_RAW, filters=[{}])),
+    (TypeError, lambda: lzma.compress(b"x", format=lzma.FORMAT_RAW, filters=[7])),
+    (lzma.LZMAError, lambda: lzma.compress(b"x", format=lzma.FORMAT_RAW, filters=[{"id": lzma.FILTER_LZMA2, "preset": 10}])),
+]
+for error, call in bad_calls:
+    try:
+        call()
+    except error:
+        pass
+    else:
+        raise AssertionError(error)
+
+# truncated and corrupt input
+packed = lzma.compress(data)
+try:
+    lzma.decompress(packed[:-20])
+except lzma.LZMAError:
+    pass
+else:
+    raise AssertionError("truncated stream")
+d = lzma.LZMADecompressor()
+d.decompress(packed[:len(packed) // 2])
+assert not d.eof and d.needs_input
+corrupt = bytearray(packed)
+corrupt[len(corrupt) // 2] ^= 0xFF
+for bad in [bytes(corrupt), b"not an xz stream at all", packed[:6] + b"\xff" * 20]:
+    try:
+        lzma.decompress(bad)
+    except lzma.LZMAError:
+        pass
+    else:
+        raise AssertionError(bad[:10])
+tampered = bytearray(lzma.compress(b"checked payload", check=lzma.CHECK_CRC32))
+tampered[-20] ^= 1
+try:
+    lzma.decompress(bytes(tampered))
+except lzma.LZMAError:
+    pass
+else:
+    raise AssertionError("checksum mismatch")
+d = lzma.LZMADecompressor(memlimit=1 << 10)
+try:
+    d.decompress(lzma.compress(data, preset=9))
+except lzma.LZMAError:
+    pass
+else:
+    raise AssertionError("memory limit")
+
+# the file and tarfile layers on top of the module
+buf = io.BytesIO()
+payload = b"hello from an xz tarball\n" * 50
+with tarfile.open(fileobj=buf, mode="w:xz") as tar:
+    info = tarfile.TarInfo("hello.txt")
+    info.size = len(payload)
+    tar.addfile(info, io.BytesIO(payload))
+buf.seek(0)
+with tarfile.open(fileobj=buf, mode="r:xz") as tar:
+    assert tar.getnames() == ["hello.txt"] and tar.extractfile("hello.txt").read() == payload
+with lzma.LZMAFile(io.BytesIO(lzma.compress(b"abc" * 1000, format=lzma.FORMAT_ALONE))) as f:
+    assert f.read(5) == b"abcab" and f.seek(2999) == 2999 and f.read() == b"c"
+c = lzma.LZMACompressor()
+c.flush()
+for call in (lambda: c.compress(b"x"), c.flush):
+    try:
+        call()
+    except ValueError:
+        pass
+    else:
+        raise AssertionError("using a flushed compressor")
+"#,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_faulthandler_crash_and_watchdog() {
+        run_source(
+            r#"
+import faulthandler, os, signal, time
+
+def read_all(fd):
+    chunks = []
+    while True:
+        chunk = os.read(fd, 4096)
+        if not chunk:
+            return b"".join(chunks).decode()
+        chunks.append(chunk)
+
+def crash_in_child(body):
+    """Run body() in a forked child and return its output and wait status."""
+    r, w = os.pipe()
+    pid = os.fork()
+    if pid == 0:
+        os.close(r)
+        try:
+            body(w)
+        finally:
+            os._exit(99)
+    os.close(w)
+    output = read_all(r)
+    os.close(r)
+    return output, os.waitpid(pid, 0)[1]
+
+# a fatal signal dumps the stack of the crashing thread and still kills the process
+def segfault(fd):
+    faulthandler.enable(fd, all_threads=False)
+    def inner_function():
+        faulthandler._sigsegv()
+    inner_function()
+output, status = crash_in_child(segfault)
+assert os.WIFSIGNALED(status) and os.WTERMSIG(status) == signal.SIGSEGV, status
+assert output.startswith("Fatal Python error: Segmentation fault\n\n"), output
+assert "Stack (most recent call first):" in output, output
+lines = [line.strip() for line in output.splitlines() if line.strip().startswith("File")]
+assert lines[0].endswith("in inner_function") and lines[1].endswith("in segfault"), output
+
+def abort(fd):
+    faulthandler.enable(file=fd)
+    faulthandler._sigabrt()
+output, status = crash_in_child(abort)
+assert os.WIFSIGNALED(status) and os.WTERMSIG(status) == signal.SIGABRT, status
+assert "Fatal Python error: Aborted" in output and "Current thread 0x" in output, output
+
+# once disabled, the signal goes straight to the default handler
+def disabled(fd):
+    faulthandler.enable(fd)
+    assert faulthandler.is_enabled()
+    assert faulthandler.disable() and not faulthandler.is_enabled() and not faulthandler.disable()
+    faulthandler._sigsegv()
+output, status = crash_in_child(disabled)
+assert os.WIFSIGNALED(status) and os.WTERMSIG(status) == signal.SIGSEGV and output == "", (status, output)
+
+# dump_traceback writes the calling thread's stack
+r, w = os.pipe()
+def dumping():
+    faulthandler.dump_traceback(w, all_threads=False)
+dumping()
+os.close(w)
+output = read_all(r)
+os.close(r)
+assert output.startswith("Stack (most recent call first):\n"), output
+assert output.splitlines()[1].strip().endswith("in dumping"), output
+
+# the watchdog fires after the timeout, repeatedly if asked to, and not at all once cancelled
+r, w = os.pipe()
+faulthandler.dump_traceback_later(0.05, repeat=True, file=w)
+time.sleep(0.5)
+faulthandler.cancel_dump_traceback_later()
+time.sleep(0.1)
+os.close(w)
+output = read_all(r)
+os.close(r)
+assert output.count("Timeout (0:00:00.050000)!\n") >= 2, output
+
+r, w = os.pipe()
+faulthandler.dump_traceback_later(0.2, file=w)
+faulthandler.dump_traceback_later(0.3, file=w)
+faulthandler.cancel_dump_traceback_later()
+time.sleep(0.5)
+os.close(w)
+assert read_all(r) == ""
+os.close(r)
+
+def hang(fd):
+    faulthandler.dump_traceback_later(0.05, exit=True, file=fd)
+    time.sleep(5)
+output, status = crash_in_child(hang)
+assert os.WIFEXITED(status) and os.WEXITSTATUS(status) == 1, status
+assert output.startswith("Timeout (0:00:00.050000)!\n"), output
+
+for bad in (0, -1, float("nan")):
+    try:
+        faulthandler.dump_traceback_later(bad)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(bad)
+try:
+    faulthandler.dump_traceback(-1)
+except ValueError:
+    pass
+else:
+    raise AssertionError("negative file descriptor")
+"#,
+        );
+    }
+
+    #[cfg(all(target_os = "linux", feature = "threading"))]
+    #[test]
+    fn test_select_epoll() {
+        run_source(
+            r#"
+import os, select, selectors, socket, threading, time
+
+a, b = socket.socketpair()
+r, w = os.pipe()
+ep = select.epoll()
+assert not ep.closed and ep.fileno() >= 0
+assert ep.poll(0) == []
+
+# a connected socket is writable straight away, and readable once the peer writes
+ep.register(a.fileno(), select.EPOLLIN | select.EPOLLOUT)
+ep.register(r, select.EPOLLIN)
+assert ep.poll(0) == [(a.fileno(), select.EPOLLOUT)]
+b.send(b"ping")
+os.write(w, b"x")
+events = dict(ep.poll(1))
+assert events == {a.fileno(): select.EPOLLIN | select.EPOLLOUT, r: select.EPOLLIN}, events
+assert len(ep.poll(1, maxevents=1)) == 1
+
+# modify and unregister change what is reported
+ep.modify(a, select.EPOLLIN)
+assert dict(ep.poll(0.5))[a.fileno()] == select.EPOLLIN
+assert a.recv(4) == b"ping"
+assert os.read(r, 1) == b"x"
+assert ep.poll(0) == []
+ep.unregister(r)
+os.write(w, b"y")
+assert ep.poll(0.01) == []
+
+# hanging up the other end
+b.close()
+fd, mask = ep.poll(1)[0]
+assert fd == a.fileno() and mask & select.EPOLLIN, mask
+ep.modify(a.fileno(), select.EPOLLIN | select.EPOLLRDHUP)
+assert ep.poll(0)[0][1] & select.EPOLLRDHUP
+ep.unregister(a)
+
+# one-shot and edge-triggered registrations
+ep.register(r, select.EPOLLIN | select.EPOLLONESHOT)
+assert ep.poll(0) == [(r, select.EPOLLIN)]
+assert ep.poll(0) == []
+ep.modify(r, select.EPOLLIN | select.EPOLLET)
+assert ep.poll(0) == [(r, select.EPOLLIN)]
+assert ep.poll(0) == []
+os.write(w, b"z")
+assert ep.poll(0) == [(r, select.EPOLLIN)]
+
+# errors from the kernel come out as the matching OSError subclasses
+for call, error in [
+    (lambda: ep.register(r), FileExistsError),
+    (lambda: ep.modify(w, select.EPOLLOUT), FileNotFoundError),
+    (lambda: ep.unregister(w), FileNotFoundError),
+    (lambda: ep.register(-1), ValueError),
+    (lambda: ep.poll(maxevents=0), ValueError),
+    (lambda: select.epoll(sizehint=0), ValueError),
+    (lambda: select.epoll(sizehint=-2), ValueError),
+]:
+    try:
+        call()
+    except error:
+        pass
+    else:
+        raise AssertionError(error)
+
+# closing, more than once, makes every operation fail
+ep.close()
+ep.close()
+assert ep.closed
+for call in (ep.fileno, lambda: ep.poll(0), lambda: ep.register(r), lambda: ep.unregister(r)):
+    try:
+        call()
+    except ValueError:
+        pass
+    else:
+        raise AssertionError("operation on a closed epoll")
+
+# as a context manager, and through fromfd
+with select.epoll() as ep:
+    assert not ep.closed
+    ep.register(w, select.EPOLLOUT)
+    other = select.epoll.fromfd(os.dup(ep.fileno()))
+    assert other.poll(0) == [(w, select.EPOLLOUT)]
+    other.close()
+assert ep.closed
+try:
+    with ep:
+        pass
+except ValueError:
+    pass
+else:
+    raise AssertionError("entering a closed epoll")
+
+# selectors picks epoll on Linux
+with selectors.DefaultSelector() as sel:
+    assert isinstance(sel, selectors.EpollSelector)
+    sel.register(r, selectors.EVENT_READ, "data")
+    [(key, mask)] = sel.select(1)
+    assert key.fd == r and key.data == "data" and mask == selectors.EVENT_READ
+
+# a blocking poll lets other threads run and wakes up when they write
+os.read(r, 10)
+with select.epoll() as ep:
+    ep.register(r, select.EPOLLIN)
+    start = time.monotonic()
+    assert ep.poll(0.1) == [] and time.monotonic() - start >= 0.09
+    writer = threading.Timer(0.1, os.write, (w, b"t"))
+    writer.start()
+    assert ep.poll(5) == [(r, select.EPOLLIN)]
+    writer.join()
+a.close()
+for fd in (r, w):
+    os.close(fd)
+"#,
+        );
+    }
+
+    #[test]
+    fn test_tracemalloc_snapshots() {
+        // tracing covers the whole process, so the source gets a file name of its own to keep
+        // the allocations of the tests running next to it out of the statistics
+        interpreter().enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            let source = r#"
+import sys, tracemalloc
+
+here = sys._getframe().f_code.co_filename
+
+def ours(snapshot):
+    return snapshot.filter_traces([tracemalloc.Filter(True, here)])
+
+assert not tracemalloc.is_tracing() and tracemalloc.get_traced_memory() == (0, 0)
+assert tracemalloc.take_snapshot is not None
+try:
+    tracemalloc.take_snapshot()
+except RuntimeError:
+    pass
+else:
+    raise AssertionError("snapshot without tracing")
+
+tracemalloc.start()
+assert tracemalloc.is_tracing() and tracemalloc.get_traceback_limit() == 1
+before, _ = tracemalloc.get_traced_memory()
+
+def allocate_here(n):
+    objects = []
+    for _ in range(n):
+        objects.append(object())
+    return objects
+kept = allocate_here(2000)
+line_kept = allocate_here.__code__.co_firstlineno + 3
+current, peak = tracemalloc.get_traced_memory()
+assert current > before and peak >= current, (before, current, peak)
+assert tracemalloc.get_object_traceback(kept[0])[0].lineno == line_kept
+assert tracemalloc.get_object_traceback(kept[0])[0].filename == here
+assert tracemalloc.get_tracemalloc_memory() > 0
+
+# statistics by line and by file
+snapshot = ours(tracemalloc.take_snapshot())
+assert snapshot.traceback_limit == 1
+by_line = snapshot.statistics("lineno")
+top = by_line[0]
+assert top.traceback[0].lineno == line_kept and top.count >= 2000, top
+assert top.size >= 2000 * sys.getsizeof(object()) // 2, top
+by_file = snapshot.statistics("filename")
+assert len(by_file) == 1 and by_file[0].traceback[0].filename == here
+assert by_file[0].count == sum(stat.count for stat in by_line)
+assert by_file[0].size == sum(stat.size for stat in by_line)
+
+# freed objects leave the traces, and the peak remembers them
+current, peak = tracemalloc.get_traced_memory()
+del kept
+after_free, peak_after = tracemalloc.get_traced_memory()
+assert after_free < current and peak_after >= peak, (after_free, current, peak_after, peak)
+snapshot2 = ours(tracemalloc.take_snapshot())
+assert all(stat.traceback[0].lineno != line_kept for stat in snapshot2.statistics("lineno"))
+diff = snapshot2.compare_to(snapshot, "lineno")
+freed = [stat for stat in diff if stat.traceback[0].lineno == line_kept]
+assert freed and freed[0].count_diff <= -2000 and freed[0].size_diff < 0, freed
+tracemalloc.reset_peak()
+assert tracemalloc.get_traced_memory()[1] <= peak_after
+
+# deeper tracebacks with start(nframe)
+tracemalloc.stop()
+assert not tracemalloc.is_tracing() and tracemalloc.get_traced_memory() == (0, 0)
+tracemalloc.start(5)
+def outer():
+    return middle()
+def middle():
+    return allocate_here(10)
+deep = outer()
+frames = tracemalloc.get_object_traceback(deep[0])
+# oldest frame first
+assert [frame.lineno for frame in frames][-3:] == [
+    outer.__code__.co_firstlineno + 1, middle.__code__.co_firstlineno + 1, line_kept
+], frames
+snapshot = ours(tracemalloc.take_snapshot())
+assert snapshot.traceback_limit == 5
+stats = snapshot.statistics("traceback")
+assert any(len(stat.traceback) >= 3 for stat in stats)
+
+# clear_traces forgets what was traced so far but keeps tracing
+tracemalloc.clear_traces()
+assert tracemalloc.get_object_traceback(deep[0]) is None and tracemalloc.is_tracing()
+again = allocate_here(10)
+assert tracemalloc.get_object_traceback(again[0]) is not None
+tracemalloc.stop()
+assert tracemalloc.get_object_traceback(again[0]) is None
+
+for bad in (0, -1, 65536):
+    try:
+        tracemalloc.start(bad)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(bad)
+assert not tracemalloc.is_tracing()
+"#;
+            vm.unwrap_pyresult(vm.run_code_string(scope, source, "<tracemalloc>".to_owned()));
+        })
+    }
+
+    #[test]
+    fn test_decimal_rounding_and_signals() {
+        run_source(
+            r#"
+import _decimal as decimal
+from _decimal import Decimal, Context
+
+expected = {
+    decimal.ROUND_UP: ["3", "3", "-3", "-3"],
+    decimal.ROUND_DOWN: ["2", "2", "-2", "-2"],
+    decimal.ROUND_CEILING: ["3", "3", "-2", "-2"],
+    decimal.ROUND_FLOOR: ["2", "2", "-3", "-3"],
+    decimal.ROUND_HALF_UP: ["3", "3", "-3", "-3"],
+    decimal.ROUND_HALF_DOWN: ["2", "3", "-2", "-3"],
+    decimal.ROUND_HALF_EVEN: ["2", "3", "-2", "-3"],
+    decimal.ROUND_05UP: ["2", "2", "-2", "-2"],
+}
+for rounding, results in expected.items():
+    ctx = Context(prec=1, rounding=rounding)
+    got = [str(ctx.plus(Decimal(x))) for x in ["2.5", "2.6", "-2.5", "-2.6"]]
+    assert got == results, (rounding, got)
+assert str(Context(prec=2, rounding=decimal.ROUND_05UP).plus(Decimal("5.1"))) == "5.1"
+assert str(Context(prec=1, rounding=decimal.ROUND_05UP).plus(Decimal("0.51"))) == "0.6"
+
+ctx = Context(prec=3, traps=[])
+assert str(ctx.divide(Decimal(1), Decimal(3))) == "0.333"
+assert ctx.flags[decimal.Inexact] and ctx.flags[decimal.Rounded]
+assert not ctx.flags[decimal.DivisionByZero]
+assert str(ctx.divide(Decimal(1), Decimal(0))) == "Infinity"
+assert ctx.flags[decimal.DivisionByZero]
+ctx.clear_flags()
+assert not any(ctx.flags.values())
+assert ctx.divide(Decimal(0), Decimal(0)).is_nan()
+assert ctx.flags[decimal.InvalidOperation]
+
+ctx = Context(prec=3, traps=[decimal.Inexact])
+try:
+    ctx.divide(Decimal(1), Decimal(3))
+except decimal.Inexact as e:
+    assert isinstance(e, decimal.DecimalException) and isinstance(e, ArithmeticError)
+else:
+    raise AssertionError("Inexact should be trapped")
+assert str(ctx.divide(Decimal(1), Decimal(4))) == "0.25"
+ctx = Context(traps=[decimal.DivisionByZero])
+try:
+    ctx.divide(Decimal(1), Decimal(0))
+except ZeroDivisionError:
+    pass
+else:
+    raise AssertionError("DivisionByZero should be trapped")
+ctx = Context(Emax=10, traps=[decimal.Overflow])
+try:
+    ctx.multiply(Decimal("1e10"), Decimal(10))
+except decimal.Overflow:
+    pass
+else:
+    raise AssertionError("Overflow should be trapped")
+with decimal.localcontext() as local:
+    local.traps[decimal.InvalidOperation] = False
+    assert Decimal("sNaN").sqrt().is_qnan()
+try:
+    Decimal("sNaN") + 1
+except decimal.InvalidOperation:
+    pass
+else:
+    raise AssertionError("the default context traps InvalidOperation")
+"#,
+        );
+    }
+
+    #[test]
+    fn test_decimal_quantize_hash_and_str() {
+        run_source(
+            r#"
+import _decimal as decimal
+from _decimal import Decimal, Context
+assert str(Decimal("1.41421356").quantize(Decimal("1.000"))) == "1.414"
+assert str(Decimal("7.325").quantize(Decimal(".01"), rounding=decimal.ROUND_DOWN)) == "7.32"
+assert str(Decimal("7.325").quantize(Decimal("1."), rounding=decimal.ROUND_UP)) == "8"
+assert str(Decimal("-0").quantize(Decimal("1e-2"))) == "-0.00"
+assert str(Decimal("123").quantize(Decimal("1e1"))) == "1.2E+2"
+try:
+    Context(prec=3).quantize(Decimal("123456"), Decimal("1e-2"))
+except decimal.InvalidOperation:
+    pass
+else:
+    raise AssertionError("the result of quantize must fit the precision")
+assert Decimal("2.50").same_quantum(Decimal("1.11"))
+
+for n in [0, 1, -1, 7, 2**64, -(10**30)]:
+    assert hash(Decimal(n)) == hash(n), n
+    assert Decimal(n) == n
+for f in [0.5, -2.25, 1e100, 1.1]:
+    assert hash(Decimal(f)) == hash(f), f
+    assert Decimal(f) == f
+assert hash(Decimal("1.000")) == hash(Decimal("1")) == hash(1)
+assert hash(Decimal("Infinity")) == hash(float("inf"))
+assert hash(Decimal("-1e30")) == hash(-(10**30))
+assert Decimal("0.1") != 0.1
+try:
+    hash(Decimal("sNaN"))
+except TypeError:
+    pass
+else:
+    raise AssertionError("signaling NaNs are unhashable")
+
+for s in ["0", "-0", "1.50", "-1.23E+45", "1E-7", "0.000001", "123.456E-10", "Infinity", "-Infinity", "NaN123", "-sNaN", "1E+2"]:
+    d = Decimal(s)
+    assert str(Decimal(str(d))) == str(d), s
+    assert repr(d) == "Decimal('%s')" % d, repr(d)
+    assert eval(repr(d), {"Decimal": Decimal}).compare_total(d) == 0, s
+assert str(Decimal("1e-7")) == "1E-7"
+assert str(Decimal("0.0000001")) == "1E-7"
+assert str(Decimal("100E-2")) == "1.00"
+assert str(Decimal("1E+2")) == "1E+2"
+assert Decimal("1E+2").to_eng_string() == "100"
+assert str(Decimal(" 3.14 \n")) == "3.14"
+assert str(Decimal(0.1)) == "0.1000000000000000055511151231257827021181583404541015625"
+"#,
+        );
+    }
+
+    #[test]
+    fn test_decimal_huge_exponents() {
+        // 10**(2**32) has too many digits to compute, the exponent must not wrap around
+        run_source(
+            r#"
+from _decimal import Decimal
+big = Decimal("1e4294967296")
+assert big > Decimal("1e4294967295") and big == Decimal("10e4294967295")
+assert int(Decimal("1e-4294967296")) == 0
+assert pow(Decimal("5e4294967296"), 4, 7) == pow(pow(10, 4294967296, 7) * 5, 4, 7)
+for convert in [int, Decimal.as_integer_ratio, lambda d: format(d, ".2f")]:
+    try:
+        convert(big)
+    except MemoryError:
+        pass
+    else:
+        raise AssertionError("the integer has too many digits to compute")
+try:
+    Decimal("1e-4294967296").as_integer_ratio()
+except MemoryError:
+    pass
+else:
+    raise AssertionError("the denominator has too many digits to compute")
+"#,
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_ctypes() {
+        run_source(
+            r#"
+import ctypes, sys
+from ctypes import (CDLL, CFUNCTYPE, POINTER, Structure, Union, byref, c_char, c_char_p,
+                    c_double, c_int, c_int8, c_int16, c_int32, c_int64, c_long, c_size_t,
+                    c_uint8, c_void_p, cast, create_string_buffer, get_errno, pointer, set_errno,
+                    sizeof, alignment, addressof)
+
+libc = CDLL(None, use_errno=True)
+libc.strlen.restype = c_size_t
+libc.strlen.argtypes = [c_char_p]
+assert libc.strlen(b"hello") == 5
+libc.abs.argtypes = [c_int]
+assert libc.abs(-7) == 7
+buf = create_string_buffer(32)
+libc.snprintf(buf, 32, b"%d-%s-%.2f", 42, b"x", c_double(1.5))
+assert buf.value == b"42-x-1.50", buf.value
+libc.strtol.restype = c_long
+number = create_string_buffer(b"123abc")
+end = c_char_p()
+assert libc.strtol(number, byref(end), 10) == 123
+assert end.value == b"abc"
+
+class Point(Structure):
+    _fields_ = [("x", c_int32), ("y", c_int32)]
+class Mixed(Structure):
+    _fields_ = [("a", c_int8), ("b", c_int64), ("c", c_int16)]
+class Packed(Structure):
+    _pack_ = 1
+    _fields_ = [("a", c_int8), ("b", c_int64), ("c", c_int16)]
+class Number(Union):
+    _fields_ = [("i", c_int32), ("f", ctypes.c_float), ("bytes", c_uint8 * 4)]
+assert (sizeof(Point), alignment(Point)) == (8, 4)
+assert (sizeof(Mixed), alignment(Mixed)) == (24, 8)
+assert (Mixed.a.offset, Mixed.b.offset, Mixed.c.offset) == (0, 8, 16)
+assert (sizeof(Packed), alignment(Packed)) == (11, 1)
+assert (Packed.a.offset, Packed.b.offset, Packed.c.offset) == (0, 1, 9)
+assert sizeof(Number) == 4 and Number.f.offset == 0
+n = Number(i=1)
+assert list(n.bytes) == ([1, 0, 0, 0] if sys.byteorder == "little" else [0, 0, 0, 1])
+p = Point(1, 2)
+p.y = -5
+assert (p.x, p.y) == (1, -5)
+assert bytes(p) == (1).to_bytes(4, sys.byteorder) + (-5).to_bytes(4, sys.byteorder, signed=True)
+
+IntArray = c_int * 5
+array = IntArray(5, 1, 4, 2, 3)
+assert len(array) == 5 and list(array) == [5, 1, 4, 2, 3]
+assert array[1:3] == [1, 4]
+array[0] = 9
+assert array[0] == 9
+try:
+    array[5]
+except IndexError:
+    pass
+else:
+    raise AssertionError("array index out of range")
+points = (Point * 2)(Point(1, 2), Point(3, 4))
+assert points[1].y == 4
+
+value = c_int(10)
+ptr = pointer(value)
+assert ptr.contents.value == 10
+ptr.contents.value = 11
+assert value.value == 11
+ptr[0] = 12
+assert value.value == 12
+assert isinstance(ptr, POINTER(c_int))
+assert not POINTER(c_int)()
+as_bytes = cast(ptr, POINTER(c_uint8 * 4)).contents
+assert bytes(as_bytes) == (12).to_bytes(4, sys.byteorder)
+assert cast(c_void_p(addressof(value)), POINTER(c_int)).contents.value == 12
+
+libc.sscanf.argtypes = [c_char_p, c_char_p, POINTER(c_int), POINTER(c_int)]
+x, y = c_int(), c_int()
+assert libc.sscanf(b"3 4", b"%d %d", byref(x), byref(y)) == 2
+assert (x.value, y.value) == (3, 4)
+target = Point()
+libc.memcpy.argtypes = [c_void_p, c_void_p, c_size_t]
+libc.memcpy(byref(target), byref(p), sizeof(Point))
+assert (target.x, target.y) == (1, -5)
+
+CMPFUNC = CFUNCTYPE(c_int, POINTER(c_int), POINTER(c_int))
+calls = []
+def compare(a, b):
+    calls.append((a[0], b[0]))
+    return a[0] - b[0]
+libc.qsort.argtypes = [c_void_p, c_size_t, c_size_t, CMPFUNC]
+libc.qsort.restype = None
+libc.qsort(array, len(array), sizeof(c_int), CMPFUNC(compare))
+assert list(array) == [1, 2, 3, 4, 9] and calls
+
+unraisable = []
+old_hook = sys.unraisablehook
+sys.unraisablehook = unraisable.append
+try:
+    def failing(a, b):
+        raise ValueError("from the callback")
+    libc.qsort(IntArray(2, 1), 2, sizeof(c_int), CMPFUNC(failing))
+finally:
+    sys.unraisablehook = old_hook
+assert unraisable and isinstance(unraisable[0].exc_value, ValueError)
+
+set_errno(0)
+libc.close.argtypes = [c_int]
+assert libc.close(-1) == -1
+import errno
+assert get_errno() == errno.EBADF
+assert set_errno(5) == errno.EBADF
+assert get_errno() == 5
+"#,
+        );
+    }
+
+    #[cfg(all(unix, feature = "threading"))]
+    #[test]
+    fn test_ctypes_callback_from_c_thread() {
+        // the thread is started by libc, so it has no vm until the callback gives it one
+        run_source(
+            r#"
+import ctypes, sys, _thread
+from ctypes import CDLL, CFUNCTYPE, byref, c_ulong, c_void_p
+libc = CDLL(None)
+seen = []
+THREAD_FUNC = CFUNCTYPE(c_void_p, c_void_p)
+def in_thread(arg):
+    seen.append((arg, _thread.get_ident()))
+    raise KeyError("from a C thread")
+callback = THREAD_FUNC(in_thread)
+unraisable = []
+old_hook = sys.unraisablehook
+sys.unraisablehook = unraisable.append
+try:
+    tid = c_ulong()
+    libc.pthread_create.argtypes = [ctypes.POINTER(c_ulong), c_void_p, THREAD_FUNC, c_void_p]
+    assert libc.pthread_create(byref(tid), None, callback, 42) == 0
+    assert libc.pthread_join(tid, None) == 0
+finally:
+    sys.unraisablehook = old_hook
+assert len(seen) == 1 and seen[0][0] == 42 and seen[0][1] != _thread.get_ident(), seen
+assert len(unraisable) == 1 and isinstance(unraisable[0].exc_value, KeyError), unraisable
+"#,
+        );
+    }
 }
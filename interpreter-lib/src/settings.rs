@@ -2,6 +2,9 @@ use clap::{App, AppSettings, Arg, ArgMatches};
 use rustpython_vm::Settings;
 use std::{env, str::FromStr};
 
+/// Used for a bare `-X jit`: calls plus loop iterations before a function gets jitted.
+const DEFAULT_JIT_THRESHOLD: u32 = 1000;
+
 pub enum RunMode {
     ScriptInteractive(Option<String>, bool),
     Command(String),
@@ -16,6 +19,10 @@ pub fn opts_with_clap() -> (Settings, RunMode) {
 }
 
 fn parse_arguments<'a>(app: App<'a, '_>) -> ArgMatches<'a> {
+    with_arguments(app).get_matches()
+}
+
+fn with_arguments<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
     let app = app
         .setting(AppSettings::TrailingVarArg)
         .version(crate_version!())
@@ -166,7 +173,7 @@ fn parse_arguments<'a>(app: App<'a, '_>) -> ArgMatches<'a> {
                 .takes_value(true)
                 .help("the profile format to output the profiling information in"),
         );
-    app.get_matches()
+    app
 }
 
 /// Create settings by examining command line arguments and environment
@@ -279,6 +286,16 @@ fn settings_from(matches: &ArgMatches) -> (Settings, RunMode) {
                     },
                 };
             }
+            if name == "jit" {
+                settings.jit_threshold = match value.as_deref() {
+                    None => DEFAULT_JIT_THRESHOLD,
+                    Some("off") => 0,
+                    Some(threshold) => threshold.parse().unwrap_or_else(|_| {
+                        error!("Fatal Python error: -X jit: invalid value {threshold:?}; must be a number of calls or 'off'.");
+                        std::process::exit(1);
+                    }),
+                };
+            }
             (name, value)
         }));
     }
@@ -394,3 +411,23 @@ pub(crate) fn split_paths<T: AsRef<std::ffi::OsStr> + ?Sized>(
     s.split(|b| *b == b':')
         .map(|x| std::ffi::OsStr::from_bytes(x).to_owned().into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jit_threshold(args: &[&str]) -> u32 {
+        let app = with_arguments(App::new("RustPython"));
+        let matches =
+            app.get_matches_from(std::iter::once("rustpython").chain(args.iter().copied()));
+        settings_from(&matches).0.jit_threshold
+    }
+
+    #[test]
+    fn test_jit_option() {
+        assert_eq!(jit_threshold(&[]), 0);
+        assert_eq!(jit_threshold(&["-X", "jit"]), DEFAULT_JIT_THRESHOLD);
+        assert_eq!(jit_threshold(&["-X", "jit=5"]), 5);
+        assert_eq!(jit_threshold(&["-X", "jit=off"]), 0);
+    }
+}
@@ -0,0 +1,1385 @@
+use crate::vm::{builtins::PyModule, class::PyClassImpl, PyRef, VirtualMachine};
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyRef<PyModule> {
+    _asyncio::PyFutureIter::make_class(&vm.ctx);
+    _asyncio::TaskStepMethWrapper::make_class(&vm.ctx);
+    _asyncio::TaskWakeupMethWrapper::make_class(&vm.ctx);
+    _asyncio::make_module(vm)
+}
+
+#[pymodule]
+mod _asyncio {
+    use crate::common::lock::PyMutex;
+    use crate::vm::{
+        builtins::{
+            PyBaseExceptionRef, PyCoroutine, PyDictRef, PyGenerator, PyGenericAlias, PySet,
+            PyTraceback, PyType, PyTypeRef,
+        },
+        function::{FuncArgs, KwArgs, OptionalOption},
+        protocol::PyIterReturn,
+        types::{
+            Callable, DefaultConstructor, Destructor, Initializer, IterNext, Iterable,
+            Representable, SelfIter,
+        },
+        AsObject, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, VirtualMachine,
+    };
+    use std::cell::RefCell;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    thread_local! {
+        static RUNNING_LOOP: RefCell<Option<PyObjectRef>> = RefCell::default();
+    }
+
+    static TASK_NAME_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    /// `getattr(sys.modules[module], name)`, importing `module` first if needed.
+    fn module_attr(module: &'static str, name: &'static str, vm: &VirtualMachine) -> PyResult {
+        vm.import(module, 0)?;
+        vm.sys_module
+            .get_attr("modules", vm)?
+            .get_item(module, vm)?
+            .get_attr(name, vm)
+    }
+
+    fn cancelled_error(vm: &VirtualMachine) -> PyResult<PyTypeRef> {
+        module_attr("asyncio.exceptions", "CancelledError", vm)?.try_into_value(vm)
+    }
+
+    fn new_invalid_state_error(msg: &str, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        match module_attr("asyncio.exceptions", "InvalidStateError", vm)
+            .and_then(|cls| cls.try_into_value(vm))
+        {
+            Ok(cls) => vm.new_exception_msg(cls, msg.to_owned()),
+            Err(e) => e,
+        }
+    }
+
+    fn copy_context(vm: &VirtualMachine) -> PyResult {
+        module_attr("contextvars", "copy_context", vm)?.call((), vm)
+    }
+
+    /// `loop.call_soon(callback, *arg, context=context)`
+    fn call_soon(
+        event_loop: &PyObject,
+        callback: PyObjectRef,
+        arg: Option<PyObjectRef>,
+        context: Option<PyObjectRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let args: Vec<_> = std::iter::once(callback).chain(arg).collect();
+        let kwargs: KwArgs = context
+            .map(|context| ("context".to_owned(), context))
+            .into_iter()
+            .collect();
+        vm.call_method(event_loop, "call_soon", FuncArgs::new(args, kwargs))?;
+        Ok(())
+    }
+
+    fn running_loop() -> Option<PyObjectRef> {
+        RUNNING_LOOP.with(|running| running.borrow().clone())
+    }
+
+    #[pyfunction]
+    fn _get_running_loop(vm: &VirtualMachine) -> PyObjectRef {
+        running_loop().unwrap_or_else(|| vm.ctx.none())
+    }
+
+    #[pyfunction]
+    fn _set_running_loop(event_loop: PyObjectRef, vm: &VirtualMachine) {
+        let event_loop = (!vm.is_none(&event_loop)).then_some(event_loop);
+        RUNNING_LOOP.with(|running| *running.borrow_mut() = event_loop);
+    }
+
+    #[pyfunction]
+    fn get_running_loop(vm: &VirtualMachine) -> PyResult {
+        running_loop().ok_or_else(|| vm.new_runtime_error("no running event loop".to_owned()))
+    }
+
+    #[pyfunction]
+    fn get_event_loop(vm: &VirtualMachine) -> PyResult {
+        if let Some(event_loop) = running_loop() {
+            return Ok(event_loop);
+        }
+        let policy = module_attr("asyncio.events", "get_event_loop_policy", vm)?.call((), vm)?;
+        vm.call_method(&policy, "get_event_loop", ())
+    }
+
+    /// The task running in each event loop.
+    #[pyattr(name = "_current_tasks", once)]
+    fn current_tasks(vm: &VirtualMachine) -> PyDictRef {
+        vm.ctx.new_dict()
+    }
+
+    /// The pending tasks, held weakly so that forgotten tasks can still be collected.
+    #[pyattr(name = "_scheduled_tasks", once)]
+    fn scheduled_tasks(vm: &VirtualMachine) -> PyObjectRef {
+        module_attr("_weakrefset", "WeakSet", vm)
+            .and_then(|weak_set| weak_set.call((), vm))
+            .unwrap_or_else(|_| PySet::new_ref(&vm.ctx).into())
+    }
+
+    #[pyattr(name = "_eager_tasks", once)]
+    fn eager_tasks(vm: &VirtualMachine) -> PyRef<PySet> {
+        PySet::new_ref(&vm.ctx)
+    }
+
+    #[pyfunction]
+    fn _register_task(task: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        vm.call_method(&scheduled_tasks(vm), "add", (task,))?;
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn _unregister_task(task: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        vm.call_method(&scheduled_tasks(vm), "discard", (task,))?;
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn _register_eager_task(task: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        eager_tasks(vm).add(task, vm)
+    }
+
+    #[pyfunction]
+    fn _unregister_eager_task(task: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        vm.call_method(eager_tasks(vm).as_object(), "discard", (task,))?;
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn _enter_task(
+        event_loop: PyObjectRef,
+        task: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let current_tasks = current_tasks(vm);
+        if let Some(current) = current_tasks.get_item_opt(&*event_loop, vm)? {
+            return Err(vm.new_runtime_error(format!(
+                "Cannot enter into task {} while another task {} is being executed.",
+                task.repr(vm)?,
+                current.repr(vm)?
+            )));
+        }
+        current_tasks.set_item(&*event_loop, task, vm)
+    }
+
+    #[pyfunction]
+    fn _leave_task(
+        event_loop: PyObjectRef,
+        task: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let current_tasks = current_tasks(vm);
+        let current = current_tasks
+            .get_item_opt(&*event_loop, vm)?
+            .unwrap_or_else(|| vm.ctx.none());
+        if !current.is(&task) {
+            return Err(vm.new_runtime_error(format!(
+                "Leaving task {} does not match the current task {}.",
+                task.repr(vm)?,
+                current.repr(vm)?
+            )));
+        }
+        current_tasks.del_item(&*event_loop, vm)
+    }
+
+    #[pyfunction]
+    fn _swap_current_task(
+        event_loop: PyObjectRef,
+        task: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let current_tasks = current_tasks(vm);
+        let prev = current_tasks
+            .get_item_opt(&*event_loop, vm)?
+            .unwrap_or_else(|| vm.ctx.none());
+        if vm.is_none(&task) {
+            if !vm.is_none(&prev) {
+                current_tasks.del_item(&*event_loop, vm)?;
+            }
+        } else {
+            current_tasks.set_item(&*event_loop, task, vm)?;
+        }
+        Ok(prev)
+    }
+
+    #[pyfunction]
+    fn current_task(event_loop: OptionalOption, vm: &VirtualMachine) -> PyResult {
+        let event_loop = match event_loop.flatten() {
+            Some(event_loop) => event_loop,
+            None => get_running_loop(vm)?,
+        };
+        Ok(current_tasks(vm)
+            .get_item_opt(&*event_loop, vm)?
+            .unwrap_or_else(|| vm.ctx.none()))
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    enum FutureState {
+        #[default]
+        Pending,
+        Cancelled,
+        Finished,
+    }
+
+    impl FutureState {
+        fn as_str(self) -> &'static str {
+            match self {
+                FutureState::Pending => "PENDING",
+                FutureState::Cancelled => "CANCELLED",
+                FutureState::Finished => "FINISHED",
+            }
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct FutureInner {
+        state: FutureState,
+        event_loop: Option<PyObjectRef>,
+        /// Pairs of a callback and the context to run it in.
+        callbacks: Vec<(PyObjectRef, PyObjectRef)>,
+        result: Option<PyObjectRef>,
+        exception: Option<PyBaseExceptionRef>,
+        exception_tb: Option<PyRef<PyTraceback>>,
+        /// Whether the future is being awaited; see `_asyncio_future_blocking`.
+        blocking: bool,
+        /// Whether an exception nobody has retrieved should be logged on destruction.
+        log_traceback: bool,
+        source_traceback: Option<PyObjectRef>,
+        cancel_message: Option<PyObjectRef>,
+        cancelled_exc: Option<PyBaseExceptionRef>,
+    }
+
+    #[derive(Debug)]
+    struct TaskInner {
+        coro: Option<PyObjectRef>,
+        context: PyObjectRef,
+        name: PyObjectRef,
+        fut_waiter: Option<PyObjectRef>,
+        must_cancel: bool,
+        log_destroy_pending: bool,
+        num_cancels_requested: usize,
+    }
+
+    /// The payload of both `Future` and `Task` objects; the task half is filled in by
+    /// `Task.__init__()`.
+    #[pyattr]
+    #[pyclass(module = "_asyncio", name = "Future")]
+    #[derive(Debug, Default, PyPayload)]
+    pub(super) struct PyFuture {
+        inner: PyMutex<FutureInner>,
+        task: PyMutex<Option<TaskInner>>,
+    }
+
+    #[derive(FromArgs)]
+    struct FutureArgs {
+        #[pyarg(named, name = "loop", optional)]
+        event_loop: OptionalOption,
+    }
+
+    #[derive(FromArgs)]
+    struct TaskArgs {
+        #[pyarg(any)]
+        coro: PyObjectRef,
+        #[pyarg(named, name = "loop", optional)]
+        event_loop: OptionalOption,
+        #[pyarg(named, optional)]
+        name: OptionalOption,
+        #[pyarg(named, optional)]
+        context: OptionalOption,
+        #[pyarg(named, default = "false")]
+        eager_start: bool,
+    }
+
+    #[derive(FromArgs)]
+    struct CancelArgs {
+        #[pyarg(any, optional)]
+        msg: OptionalOption,
+    }
+
+    #[derive(FromArgs)]
+    struct AddDoneCallbackArgs {
+        #[pyarg(positional)]
+        func: PyObjectRef,
+        #[pyarg(named, optional)]
+        context: OptionalOption,
+    }
+
+    impl PyFuture {
+        fn event_loop(&self, vm: &VirtualMachine) -> PyResult {
+            self.inner
+                .lock()
+                .event_loop
+                .clone()
+                .ok_or_else(|| vm.new_runtime_error("Future object is not initialized.".to_owned()))
+        }
+
+        fn is_done(&self) -> bool {
+            self.inner.lock().state != FutureState::Pending
+        }
+
+        fn with_task<R>(
+            &self,
+            vm: &VirtualMachine,
+            f: impl FnOnce(&mut TaskInner) -> R,
+        ) -> PyResult<R> {
+            match &mut *self.task.lock() {
+                Some(task) => Ok(f(task)),
+                None => Err(vm.new_runtime_error("Task object is not initialized.".to_owned())),
+            }
+        }
+
+        fn init_future(
+            zelf: &Py<Self>,
+            event_loop: OptionalOption,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let event_loop = match event_loop.flatten() {
+                Some(event_loop) => event_loop,
+                None => get_event_loop(vm)?,
+            };
+            let debug = vm
+                .call_method(&event_loop, "get_debug", ())?
+                .try_to_bool(vm)?;
+            let source_traceback = if debug {
+                Some(module_attr("traceback", "extract_stack", vm)?.call((), vm)?)
+            } else {
+                None
+            };
+            *zelf.inner.lock() = FutureInner {
+                event_loop: Some(event_loop),
+                source_traceback,
+                ..Default::default()
+            };
+            Ok(())
+        }
+
+        fn init_task(zelf: &Py<Self>, args: TaskArgs, vm: &VirtualMachine) -> PyResult<()> {
+            Self::init_future(zelf, args.event_loop, vm)?;
+            let is_coroutine = args.coro.payload_is::<PyCoroutine>()
+                || module_attr("asyncio.coroutines", "iscoroutine", vm)?
+                    .call((args.coro.clone(),), vm)?
+                    .try_to_bool(vm)?;
+            if !is_coroutine {
+                return Err(vm.new_type_error(format!(
+                    "a coroutine was expected, got {}",
+                    args.coro.repr(vm)?
+                )));
+            }
+            let name = match args.name.flatten() {
+                Some(name) if name.class().is(vm.ctx.types.str_type) => name,
+                Some(name) => name.str(vm)?.into(),
+                None => {
+                    let n = TASK_NAME_COUNTER.fetch_add(1, Ordering::Relaxed) + 1;
+                    vm.ctx.new_str(format!("Task-{n}")).into()
+                }
+            };
+            let context = match args.context.flatten() {
+                Some(context) => context,
+                None => copy_context(vm)?,
+            };
+            *zelf.task.lock() = Some(TaskInner {
+                coro: Some(args.coro),
+                context,
+                name,
+                fut_waiter: None,
+                must_cancel: false,
+                log_destroy_pending: true,
+                num_cancels_requested: 0,
+            });
+
+            let event_loop = zelf.event_loop(vm)?;
+            let eager = args.eager_start
+                && vm
+                    .call_method(&event_loop, "is_running", ())?
+                    .try_to_bool(vm)?;
+            if eager {
+                task_eager_start(zelf, vm)
+            } else {
+                task_call_step_soon(zelf, None, vm)?;
+                _register_task(zelf.to_owned().into(), vm)
+            }
+        }
+
+        fn schedule_callbacks(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<()> {
+            let (event_loop, callbacks) = {
+                let mut inner = zelf.inner.lock();
+                (
+                    inner.event_loop.clone(),
+                    std::mem::take(&mut inner.callbacks),
+                )
+            };
+            let Some(event_loop) = event_loop else {
+                return Ok(());
+            };
+            for (callback, context) in callbacks {
+                call_soon(
+                    &event_loop,
+                    callback,
+                    Some(zelf.to_owned().into()),
+                    Some(context),
+                    vm,
+                )?;
+            }
+            Ok(())
+        }
+
+        fn make_cancelled_error(&self, vm: &VirtualMachine) -> PyBaseExceptionRef {
+            let (cancelled_exc, msg) = {
+                let mut inner = self.inner.lock();
+                (inner.cancelled_exc.take(), inner.cancel_message.clone())
+            };
+            if let Some(exc) = cancelled_exc {
+                return exc;
+            }
+            let args: Vec<_> = msg.filter(|msg| !vm.is_none(msg)).into_iter().collect();
+            match cancelled_error(vm) {
+                Ok(cls) => vm.new_exception(cls, args),
+                Err(e) => e,
+            }
+        }
+
+        fn get_result(&self, vm: &VirtualMachine) -> PyResult {
+            let mut inner = self.inner.lock();
+            match inner.state {
+                FutureState::Cancelled => {
+                    drop(inner);
+                    Err(self.make_cancelled_error(vm))
+                }
+                FutureState::Pending => {
+                    drop(inner);
+                    Err(new_invalid_state_error("Result is not set.", vm))
+                }
+                FutureState::Finished => {
+                    inner.log_traceback = false;
+                    if let Some(exc) = inner.exception.clone() {
+                        exc.set_traceback(inner.exception_tb.clone());
+                        return Err(exc);
+                    }
+                    Ok(inner.result.clone().unwrap_or_else(|| vm.ctx.none()))
+                }
+            }
+        }
+
+        fn set_result_impl(
+            zelf: &Py<Self>,
+            result: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            {
+                let mut inner = zelf.inner.lock();
+                if inner.state != FutureState::Pending {
+                    drop(inner);
+                    return Err(new_invalid_state_error("invalid state", vm));
+                }
+                inner.result = Some(result);
+                inner.state = FutureState::Finished;
+            }
+            Self::schedule_callbacks(zelf, vm)
+        }
+
+        fn set_exception_impl(
+            zelf: &Py<Self>,
+            exception: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            if zelf.is_done() {
+                return Err(new_invalid_state_error("invalid state", vm));
+            }
+            let exception = if exception.downcast_ref::<PyType>().map_or(false, |cls| {
+                cls.fast_issubclass(vm.ctx.exceptions.base_exception_type)
+            }) {
+                exception.call((), vm)?
+            } else {
+                exception
+            };
+            let exception: PyBaseExceptionRef = exception
+                .downcast()
+                .map_err(|_| vm.new_type_error("invalid exception object".to_owned()))?;
+            if exception.class().is(vm.ctx.exceptions.stop_iteration) {
+                return Err(vm.new_type_error(
+                    "StopIteration interacts badly with generators and cannot be raised into a Future"
+                        .to_owned(),
+                ));
+            }
+            {
+                let mut inner = zelf.inner.lock();
+                if inner.state != FutureState::Pending {
+                    drop(inner);
+                    return Err(new_invalid_state_error("invalid state", vm));
+                }
+                inner.exception_tb = exception.traceback();
+                inner.exception = Some(exception);
+                inner.state = FutureState::Finished;
+            }
+            Self::schedule_callbacks(zelf, vm)?;
+            zelf.inner.lock().log_traceback = true;
+            Ok(())
+        }
+
+        fn cancel_impl(
+            zelf: &Py<Self>,
+            msg: Option<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult<bool> {
+            {
+                let mut inner = zelf.inner.lock();
+                inner.log_traceback = false;
+                if inner.state != FutureState::Pending {
+                    return Ok(false);
+                }
+                inner.state = FutureState::Cancelled;
+                inner.cancel_message = msg;
+            }
+            Self::schedule_callbacks(zelf, vm)?;
+            Ok(true)
+        }
+
+        fn add_done_callback_impl(
+            zelf: &Py<Self>,
+            func: PyObjectRef,
+            context: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let event_loop = zelf.event_loop(vm)?;
+            {
+                let mut inner = zelf.inner.lock();
+                if inner.state == FutureState::Pending {
+                    inner.callbacks.push((func, context));
+                    return Ok(());
+                }
+            }
+            call_soon(
+                &event_loop,
+                func,
+                Some(zelf.to_owned().into()),
+                Some(context),
+                vm,
+            )
+        }
+    }
+
+    impl DefaultConstructor for PyFuture {}
+
+    impl Initializer for PyFuture {
+        type Args = FuncArgs;
+
+        fn init(zelf: PyRef<Self>, args: Self::Args, vm: &VirtualMachine) -> PyResult<()> {
+            if zelf.class().fast_issubclass(PyTask::class(&vm.ctx)) {
+                Self::init_task(&zelf, args.bind(vm)?, vm)
+            } else {
+                let FutureArgs { event_loop } = args.bind(vm)?;
+                Self::init_future(&zelf, event_loop, vm)
+            }
+        }
+    }
+
+    impl Representable for PyFuture {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let repr = if zelf.task.lock().is_some() {
+                module_attr("asyncio.base_tasks", "_task_repr", vm)?
+            } else {
+                module_attr("asyncio.base_futures", "_future_repr", vm)?
+            };
+            let repr = repr.call((zelf.to_owned(),), vm)?;
+            Ok(repr.str(vm)?.as_str().to_owned())
+        }
+    }
+
+    impl Destructor for PyFuture {
+        fn del(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<()> {
+            let pending_task = matches!(
+                &*zelf.task.lock(),
+                Some(task) if task.log_destroy_pending
+            ) && !zelf.is_done();
+            let (event_loop, source_traceback, exception) = {
+                let mut inner = zelf.inner.lock();
+                let exception = if inner.log_traceback {
+                    inner.log_traceback = false;
+                    inner.exception.clone()
+                } else {
+                    None
+                };
+                (
+                    inner.event_loop.clone(),
+                    inner.source_traceback.clone(),
+                    exception,
+                )
+            };
+            let Some(event_loop) = event_loop else {
+                return Ok(());
+            };
+            let report = |message: String, items: &[(&'static str, PyObjectRef)]| {
+                let context = vm.ctx.new_dict();
+                context.set_item("message", vm.ctx.new_str(message).into(), vm)?;
+                for (key, value) in items {
+                    context.set_item(*key, value.clone(), vm)?;
+                }
+                if let Some(source_traceback) = &source_traceback {
+                    context.set_item("source_traceback", source_traceback.clone(), vm)?;
+                }
+                vm.call_method(&event_loop, "call_exception_handler", (context,))
+                    .map(drop)
+            };
+            if pending_task {
+                report(
+                    "Task was destroyed but it is pending!".to_owned(),
+                    &[("task", zelf.to_owned().into())],
+                )?;
+            }
+            if let Some(exception) = exception {
+                report(
+                    format!("{} exception was never retrieved", zelf.class().name()),
+                    &[
+                        ("exception", exception.into()),
+                        ("future", zelf.to_owned().into()),
+                    ],
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Iterable for PyFuture {
+        fn iter(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            Ok(PyFutureIter::new(zelf).into_ref(&vm.ctx).into())
+        }
+    }
+
+    #[pyclass(
+        with(DefaultConstructor, Initializer, Representable, Destructor, Iterable),
+        flags(BASETYPE, HAS_DICT)
+    )]
+    impl PyFuture {
+        #[pymethod(name = "__await__")]
+        fn r#await(zelf: PyRef<Self>) -> PyFutureIter {
+            PyFutureIter::new(zelf)
+        }
+
+        #[pymethod]
+        fn result(&self, vm: &VirtualMachine) -> PyResult {
+            self.event_loop(vm)?;
+            self.get_result(vm)
+        }
+
+        #[pymethod]
+        fn exception(&self, vm: &VirtualMachine) -> PyResult {
+            self.event_loop(vm)?;
+            let mut inner = self.inner.lock();
+            match inner.state {
+                FutureState::Cancelled => {
+                    drop(inner);
+                    Err(self.make_cancelled_error(vm))
+                }
+                FutureState::Pending => {
+                    drop(inner);
+                    Err(new_invalid_state_error("Exception is not set.", vm))
+                }
+                FutureState::Finished => {
+                    inner.log_traceback = false;
+                    Ok(inner
+                        .exception
+                        .clone()
+                        .map_or_else(|| vm.ctx.none(), Into::into))
+                }
+            }
+        }
+
+        #[pymethod]
+        fn set_result(zelf: &Py<Self>, result: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            zelf.event_loop(vm)?;
+            Self::set_result_impl(zelf, result, vm)
+        }
+
+        #[pymethod]
+        fn set_exception(
+            zelf: &Py<Self>,
+            exception: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            zelf.event_loop(vm)?;
+            Self::set_exception_impl(zelf, exception, vm)
+        }
+
+        #[pymethod]
+        fn add_done_callback(
+            zelf: &Py<Self>,
+            args: AddDoneCallbackArgs,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let context = match args.context.flatten() {
+                Some(context) => context,
+                None => copy_context(vm)?,
+            };
+            Self::add_done_callback_impl(zelf, args.func, context, vm)
+        }
+
+        #[pymethod]
+        fn remove_done_callback(&self, func: PyObjectRef, vm: &VirtualMachine) -> PyResult<usize> {
+            self.event_loop(vm)?;
+            let callbacks = self.inner.lock().callbacks.clone();
+            let mut retained = Vec::with_capacity(callbacks.len());
+            for (callback, context) in callbacks {
+                if !vm.identical_or_equal(&callback, &func)? {
+                    retained.push((callback, context));
+                }
+            }
+            let mut inner = self.inner.lock();
+            let removed = inner.callbacks.len().saturating_sub(retained.len());
+            if removed > 0 {
+                inner.callbacks = retained;
+            }
+            Ok(removed)
+        }
+
+        #[pymethod]
+        fn cancel(zelf: &Py<Self>, args: CancelArgs, vm: &VirtualMachine) -> PyResult<bool> {
+            zelf.event_loop(vm)?;
+            Self::cancel_impl(zelf, args.msg.flatten(), vm)
+        }
+
+        #[pymethod]
+        fn cancelled(&self, vm: &VirtualMachine) -> PyResult<bool> {
+            self.event_loop(vm)?;
+            Ok(self.inner.lock().state == FutureState::Cancelled)
+        }
+
+        #[pymethod]
+        fn done(&self, vm: &VirtualMachine) -> PyResult<bool> {
+            self.event_loop(vm)?;
+            Ok(self.is_done())
+        }
+
+        #[pymethod]
+        fn get_loop(&self, vm: &VirtualMachine) -> PyResult {
+            self.event_loop(vm)
+        }
+
+        #[pymethod]
+        fn _make_cancelled_error(&self, vm: &VirtualMachine) -> PyBaseExceptionRef {
+            self.make_cancelled_error(vm)
+        }
+
+        #[pyclassmethod(magic)]
+        fn class_getitem(cls: PyTypeRef, args: PyObjectRef, vm: &VirtualMachine) -> PyGenericAlias {
+            PyGenericAlias::new(cls, args, vm)
+        }
+
+        #[pygetset]
+        fn _state(&self) -> &'static str {
+            self.inner.lock().state.as_str()
+        }
+
+        #[pygetset]
+        fn _loop(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.inner
+                .lock()
+                .event_loop
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset]
+        fn _callbacks(&self, vm: &VirtualMachine) -> PyObjectRef {
+            let callbacks = self.inner.lock().callbacks.clone();
+            if callbacks.is_empty() {
+                return vm.ctx.none();
+            }
+            let callbacks = callbacks
+                .into_iter()
+                .map(|(callback, context)| vm.new_tuple((callback, context)).into())
+                .collect();
+            vm.ctx.new_list(callbacks).into()
+        }
+
+        #[pygetset]
+        fn _result(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.inner
+                .lock()
+                .result
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset]
+        fn _exception(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.inner
+                .lock()
+                .exception
+                .clone()
+                .map_or_else(|| vm.ctx.none(), Into::into)
+        }
+
+        #[pygetset]
+        fn _asyncio_future_blocking(&self) -> bool {
+            self.inner.lock().blocking
+        }
+
+        #[pygetset(setter, name = "_asyncio_future_blocking")]
+        fn set_asyncio_future_blocking(&self, value: bool) {
+            self.inner.lock().blocking = value;
+        }
+
+        #[pygetset]
+        fn _log_traceback(&self) -> bool {
+            self.inner.lock().log_traceback
+        }
+
+        #[pygetset(setter, name = "_log_traceback")]
+        fn set_log_traceback(&self, value: bool, vm: &VirtualMachine) -> PyResult<()> {
+            if value {
+                return Err(
+                    vm.new_value_error("_log_traceback can only be set to False".to_owned())
+                );
+            }
+            self.inner.lock().log_traceback = false;
+            Ok(())
+        }
+
+        #[pygetset]
+        fn _source_traceback(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.inner
+                .lock()
+                .source_traceback
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset]
+        fn _cancel_message(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.inner
+                .lock()
+                .cancel_message
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset(setter, name = "_cancel_message")]
+        fn set_cancel_message(&self, value: PyObjectRef, vm: &VirtualMachine) {
+            self.inner.lock().cancel_message = (!vm.is_none(&value)).then_some(value);
+        }
+    }
+
+    /// The iterator behind `await future`: yields the future until it is done.
+    #[pyclass(no_attr, module = "_asyncio", name = "FutureIter")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyFutureIter {
+        future: PyMutex<Option<PyRef<PyFuture>>>,
+    }
+
+    impl PyFutureIter {
+        fn new(future: PyRef<PyFuture>) -> Self {
+            PyFutureIter {
+                future: PyMutex::new(Some(future)),
+            }
+        }
+    }
+
+    impl SelfIter for PyFutureIter {}
+
+    impl IterNext for PyFutureIter {
+        fn next(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyIterReturn> {
+            let Some(future) = zelf.future.lock().clone() else {
+                return Ok(PyIterReturn::StopIteration(None));
+            };
+            {
+                let mut inner = future.inner.lock();
+                if inner.state == FutureState::Pending {
+                    if inner.blocking {
+                        return Err(
+                            vm.new_runtime_error("await wasn't used with future".to_owned())
+                        );
+                    }
+                    inner.blocking = true;
+                    drop(inner);
+                    return Ok(PyIterReturn::Return(future.into()));
+                }
+            }
+            *zelf.future.lock() = None;
+            future
+                .get_result(vm)
+                .map(|result| PyIterReturn::StopIteration(Some(result)))
+        }
+    }
+
+    #[pyclass(with(IterNext, Iterable))]
+    impl PyFutureIter {
+        #[pymethod]
+        fn send(
+            zelf: &Py<Self>,
+            _value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyIterReturn> {
+            Self::next(zelf, vm)
+        }
+
+        #[pymethod]
+        fn throw(
+            &self,
+            exc_type: PyObjectRef,
+            exc_val: OptionalOption,
+            exc_tb: OptionalOption,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let exc_val = exc_val.flatten().unwrap_or_else(|| vm.ctx.none());
+            let exc_tb = exc_tb.flatten().unwrap_or_else(|| vm.ctx.none());
+            let exc = vm.normalize_exception(exc_type, exc_val, exc_tb)?;
+            *self.future.lock() = None;
+            Err(exc)
+        }
+
+        #[pymethod]
+        fn close(&self) {
+            *self.future.lock() = None;
+        }
+    }
+
+    /// Task objects carry a `PyFuture` payload; this type only holds the class.
+    #[pyattr]
+    #[pyclass(module = "_asyncio", name = "Task", base = "PyFuture")]
+    #[derive(Debug, PyPayload)]
+    struct PyTask;
+
+    #[derive(FromArgs)]
+    struct StackArgs {
+        #[pyarg(named, optional)]
+        limit: OptionalOption,
+        #[pyarg(named, optional)]
+        file: OptionalOption,
+    }
+
+    #[pyclass(flags(BASETYPE, HAS_DICT))]
+    impl PyTask {
+        #[pymethod]
+        fn cancel(zelf: PyRef<PyFuture>, args: CancelArgs, vm: &VirtualMachine) -> PyResult<bool> {
+            let msg = args.msg.flatten();
+            zelf.inner.lock().log_traceback = false;
+            if zelf.is_done() {
+                return Ok(false);
+            }
+            let fut_waiter = zelf.with_task(vm, |task| {
+                task.num_cancels_requested += 1;
+                task.fut_waiter.clone()
+            })?;
+            if let Some(fut_waiter) = fut_waiter {
+                let kwargs: KwArgs = std::iter::once((
+                    "msg".to_owned(),
+                    msg.clone().unwrap_or_else(|| vm.ctx.none()),
+                ))
+                .collect();
+                let cancelled =
+                    vm.call_method(&fut_waiter, "cancel", FuncArgs::new(Vec::new(), kwargs))?;
+                if cancelled.try_to_bool(vm)? {
+                    return Ok(true);
+                }
+            }
+            zelf.with_task(vm, |task| task.must_cancel = true)?;
+            zelf.inner.lock().cancel_message = msg;
+            Ok(true)
+        }
+
+        #[pymethod]
+        fn cancelling(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult<usize> {
+            zelf.with_task(vm, |task| task.num_cancels_requested)
+        }
+
+        #[pymethod]
+        fn uncancel(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult<usize> {
+            zelf.with_task(vm, |task| {
+                task.num_cancels_requested = task.num_cancels_requested.saturating_sub(1);
+                task.num_cancels_requested
+            })
+        }
+
+        #[pymethod]
+        fn set_result(
+            _zelf: PyRef<PyFuture>,
+            _result: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Err(vm.new_runtime_error("Task does not support set_result operation".to_owned()))
+        }
+
+        #[pymethod]
+        fn set_exception(
+            _zelf: PyRef<PyFuture>,
+            _exception: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Err(vm.new_runtime_error("Task does not support set_exception operation".to_owned()))
+        }
+
+        #[pymethod]
+        fn get_coro(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult {
+            zelf.with_task(vm, |task| {
+                task.coro.clone().unwrap_or_else(|| vm.ctx.none())
+            })
+        }
+
+        #[pymethod]
+        fn get_context(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult {
+            zelf.with_task(vm, |task| task.context.clone())
+        }
+
+        #[pymethod]
+        fn get_name(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult {
+            zelf.with_task(vm, |task| task.name.clone())
+        }
+
+        #[pymethod]
+        fn set_name(
+            zelf: PyRef<PyFuture>,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let name = if value.class().is(vm.ctx.types.str_type) {
+                value
+            } else {
+                value.str(vm)?.into()
+            };
+            zelf.with_task(vm, |task| task.name = name)
+        }
+
+        #[pymethod]
+        fn get_stack(zelf: PyRef<PyFuture>, args: StackArgs, vm: &VirtualMachine) -> PyResult {
+            let limit = args.limit.flatten().unwrap_or_else(|| vm.ctx.none());
+            module_attr("asyncio.base_tasks", "_task_get_stack", vm)?.call((zelf, limit), vm)
+        }
+
+        #[pymethod]
+        fn print_stack(zelf: PyRef<PyFuture>, args: StackArgs, vm: &VirtualMachine) -> PyResult {
+            let limit = args.limit.flatten().unwrap_or_else(|| vm.ctx.none());
+            let file = args.file.flatten().unwrap_or_else(|| vm.ctx.none());
+            module_attr("asyncio.base_tasks", "_task_print_stack", vm)?
+                .call((zelf, limit, file), vm)
+        }
+
+        #[pygetset]
+        fn _log_destroy_pending(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult<bool> {
+            zelf.with_task(vm, |task| task.log_destroy_pending)
+        }
+
+        #[pygetset(setter, name = "_log_destroy_pending")]
+        fn set_log_destroy_pending(
+            zelf: PyRef<PyFuture>,
+            value: bool,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            zelf.with_task(vm, |task| task.log_destroy_pending = value)
+        }
+
+        #[pygetset]
+        fn _must_cancel(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult<bool> {
+            zelf.with_task(vm, |task| task.must_cancel)
+        }
+
+        #[pygetset]
+        fn _coro(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult {
+            Self::get_coro(zelf, vm)
+        }
+
+        #[pygetset]
+        fn _fut_waiter(zelf: PyRef<PyFuture>, vm: &VirtualMachine) -> PyResult {
+            zelf.with_task(vm, |task| {
+                task.fut_waiter.clone().unwrap_or_else(|| vm.ctx.none())
+            })
+        }
+    }
+
+    /// `Task.__step()`, bound to its task and the exception to throw into the coroutine.
+    #[pyclass(no_attr, module = "_asyncio", name = "TaskStepMethWrapper")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct TaskStepMethWrapper {
+        task: PyRef<PyFuture>,
+        exc: Option<PyBaseExceptionRef>,
+        /// Run the step without entering the task, as `__eager_start()` does.
+        eager: bool,
+    }
+
+    impl Callable for TaskStepMethWrapper {
+        type Args = ();
+
+        fn call(zelf: &Py<Self>, _args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            if zelf.eager {
+                task_step_impl(&zelf.task, zelf.exc.clone(), vm)?;
+            } else {
+                task_step(&zelf.task, zelf.exc.clone(), vm)?;
+            }
+            Ok(vm.ctx.none())
+        }
+    }
+
+    #[pyclass(with(Callable))]
+    impl TaskStepMethWrapper {}
+
+    /// `Task.__wakeup()`, the done callback of the future a task is waiting on.
+    #[pyclass(no_attr, module = "_asyncio", name = "TaskWakeupMethWrapper")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct TaskWakeupMethWrapper {
+        task: PyRef<PyFuture>,
+    }
+
+    impl Callable for TaskWakeupMethWrapper {
+        type Args = (PyObjectRef,);
+
+        fn call(zelf: &Py<Self>, (future,): Self::Args, vm: &VirtualMachine) -> PyResult {
+            let result = match future.payload::<PyFuture>() {
+                Some(future) => future.get_result(vm),
+                None => vm.call_method(&future, "result", ()),
+            };
+            task_step(&zelf.task, result.err(), vm)?;
+            Ok(vm.ctx.none())
+        }
+    }
+
+    #[pyclass(with(Callable))]
+    impl TaskWakeupMethWrapper {}
+
+    fn task_call_step_soon(
+        task: &Py<PyFuture>,
+        exc: Option<PyBaseExceptionRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let event_loop = task.event_loop(vm)?;
+        let context = task.with_task(vm, |task| task.context.clone())?;
+        let step = TaskStepMethWrapper {
+            task: task.to_owned(),
+            exc,
+            eager: false,
+        };
+        call_soon(
+            &event_loop,
+            step.into_ref(&vm.ctx).into(),
+            None,
+            Some(context),
+            vm,
+        )
+    }
+
+    fn task_eager_start(task: &Py<PyFuture>, vm: &VirtualMachine) -> PyResult<()> {
+        let event_loop = task.event_loop(vm)?;
+        let task_obj: PyObjectRef = task.to_owned().into();
+        let prev_task = _swap_current_task(event_loop.clone(), task_obj.clone(), vm)?;
+        _register_eager_task(task_obj.clone(), vm)?;
+
+        let context = task.with_task(vm, |task| task.context.clone())?;
+        let step = TaskStepMethWrapper {
+            task: task.to_owned(),
+            exc: None,
+            eager: true,
+        };
+        let result = vm.call_method(&context, "run", (step.into_ref(&vm.ctx),));
+
+        let swapped = _swap_current_task(event_loop, prev_task, vm);
+        let unregistered = _unregister_eager_task(task_obj.clone(), vm);
+        let registered = if task.is_done() {
+            // the coroutine is finished with; drop it early
+            task.with_task(vm, |task| task.coro = None)
+        } else {
+            _register_task(task_obj, vm)
+        };
+        result?;
+        swapped?;
+        unregistered?;
+        registered
+    }
+
+    fn task_step(
+        task: &Py<PyFuture>,
+        exc: Option<PyBaseExceptionRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let event_loop = task.event_loop(vm)?;
+        let task_obj: PyObjectRef = task.to_owned().into();
+        _enter_task(event_loop.clone(), task_obj.clone(), vm)?;
+        let result = task_step_impl(task, exc, vm);
+        let left = _leave_task(event_loop, task_obj, vm);
+        result?;
+        left
+    }
+
+    fn task_step_impl(
+        task: &Py<PyFuture>,
+        exc: Option<PyBaseExceptionRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if task.is_done() {
+            let exc = exc.map_or_else(|| vm.ctx.none(), Into::into);
+            return Err(new_invalid_state_error(
+                &format!(
+                    "_step(): already done: {}, {}",
+                    task.as_object().repr(vm)?,
+                    exc.repr(vm)?
+                ),
+                vm,
+            ));
+        }
+
+        let mut exc = exc;
+        if task.with_task(vm, |task| task.must_cancel)? {
+            let cancelled_error = cancelled_error(vm)?;
+            if !exc
+                .as_ref()
+                .map_or(false, |exc| exc.fast_isinstance(&cancelled_error))
+            {
+                exc = Some(task.make_cancelled_error(vm));
+            }
+            task.with_task(vm, |task| task.must_cancel = false)?;
+        }
+        let coro = task.with_task(vm, |task| {
+            task.fut_waiter = None;
+            task.coro.clone()
+        })?;
+        let Some(coro) = coro else {
+            return Err(vm.new_runtime_error("Task has no coroutine to run".to_owned()));
+        };
+
+        let result = match (coro.payload::<PyCoroutine>(), exc) {
+            (Some(coroutine), None) => coroutine.as_coro().send(&coro, vm.ctx.none(), vm),
+            (Some(coroutine), Some(exc)) => {
+                coroutine
+                    .as_coro()
+                    .throw(&coro, exc.into(), vm.ctx.none(), vm.ctx.none(), vm)
+            }
+            (None, None) => {
+                PyIterReturn::from_pyresult(vm.call_method(&coro, "send", (vm.ctx.none(),)), vm)
+            }
+            (None, Some(exc)) => {
+                PyIterReturn::from_pyresult(vm.call_method(&coro, "throw", (exc,)), vm)
+            }
+        };
+
+        match result {
+            Ok(PyIterReturn::StopIteration(value)) => {
+                if task.with_task(vm, |task| std::mem::take(&mut task.must_cancel))? {
+                    let msg = task.inner.lock().cancel_message.clone();
+                    PyFuture::cancel_impl(task, msg, vm)?;
+                } else {
+                    let value = value.unwrap_or_else(|| vm.ctx.none());
+                    PyFuture::set_result_impl(task, value, vm)?;
+                }
+                Ok(())
+            }
+            Ok(PyIterReturn::Return(result)) => task_handle_yield(task, result, vm),
+            Err(e) => {
+                let cancelled = cancelled_error(vm)?;
+                if e.fast_isinstance(&cancelled) {
+                    task.inner.lock().cancelled_exc = Some(e);
+                    PyFuture::cancel_impl(task, None, vm)?;
+                    Ok(())
+                } else if e.fast_isinstance(vm.ctx.exceptions.keyboard_interrupt)
+                    || e.fast_isinstance(vm.ctx.exceptions.system_exit)
+                {
+                    PyFuture::set_exception_impl(task, e.clone().into(), vm)?;
+                    Err(e)
+                } else {
+                    PyFuture::set_exception_impl(task, e.into(), vm)
+                }
+            }
+        }
+    }
+
+    /// Decide what to do with what the task's coroutine yielded.
+    fn task_handle_yield(
+        task: &Py<PyFuture>,
+        result: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let task_obj = task.as_object();
+        let blocking = match result.payload::<PyFuture>() {
+            Some(future) => Some(future.inner.lock().blocking),
+            None => match vm.get_attribute_opt(result.clone(), "_asyncio_future_blocking")? {
+                Some(blocking) if !vm.is_none(&blocking) => Some(blocking.try_to_bool(vm)?),
+                _ => None,
+            },
+        };
+
+        let error = match blocking {
+            Some(false) => format!(
+                "yield was used instead of yield from in task {} with {}",
+                task_obj.repr(vm)?,
+                result.repr(vm)?
+            ),
+            Some(true) => {
+                let result_loop = match result.payload::<PyFuture>() {
+                    Some(future) => future.event_loop(vm)?,
+                    None => module_attr("asyncio.futures", "_get_loop", vm)?
+                        .call((result.clone(),), vm)?,
+                };
+                if !result_loop.is(&task.event_loop(vm)?) {
+                    format!(
+                        "Task {} got Future {} attached to a different loop",
+                        task_obj.repr(vm)?,
+                        result.repr(vm)?
+                    )
+                } else if result.is(task_obj) {
+                    format!("Task cannot await on itself: {}", task_obj.repr(vm)?)
+                } else {
+                    return task_wait_for(task, result, vm);
+                }
+            }
+            None if vm.is_none(&result) => {
+                // a bare yield gives up control for one event loop iteration
+                return task_call_step_soon(task, None, vm);
+            }
+            None if result.payload_is::<PyGenerator>() => format!(
+                "yield was used instead of yield from for generator in task {} with {}",
+                task_obj.repr(vm)?,
+                result.repr(vm)?
+            ),
+            None => format!("Task got bad yield: {}", result.repr(vm)?),
+        };
+        task_call_step_soon(task, Some(vm.new_runtime_error(error)), vm)
+    }
+
+    /// Suspend the task until `future` is done.
+    fn task_wait_for(
+        task: &Py<PyFuture>,
+        future: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let context = task.with_task(vm, |task| task.context.clone())?;
+        let wakeup: PyObjectRef = TaskWakeupMethWrapper {
+            task: task.to_owned(),
+        }
+        .into_ref(&vm.ctx)
+        .into();
+        // subclasses may override add_done_callback(), so only exact types take the fast path
+        let native = future.class().is(PyFuture::class(&vm.ctx))
+            || future.class().is(PyTask::class(&vm.ctx));
+        match future.downcast_ref::<PyFuture>().filter(|_| native) {
+            Some(fut) => {
+                fut.inner.lock().blocking = false;
+                PyFuture::add_done_callback_impl(fut, wakeup, context, vm)?;
+            }
+            _ => {
+                future.set_attr("_asyncio_future_blocking", vm.ctx.new_bool(false), vm)?;
+                let kwargs: KwArgs = std::iter::once(("context".to_owned(), context)).collect();
+                vm.call_method(
+                    &future,
+                    "add_done_callback",
+                    FuncArgs::new(vec![wakeup], kwargs),
+                )?;
+            }
+        }
+
+        let must_cancel = task.with_task(vm, |task| {
+            task.fut_waiter = Some(future.clone());
+            task.must_cancel
+        })?;
+        if must_cancel {
+            let msg = task
+                .inner
+                .lock()
+                .cancel_message
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none());
+            let kwargs: KwArgs = std::iter::once(("msg".to_owned(), msg)).collect();
+            let cancelled = vm.call_method(&future, "cancel", FuncArgs::new(Vec::new(), kwargs))?;
+            if cancelled.try_to_bool(vm)? {
+                task.with_task(vm, |task| task.must_cancel = false)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,3339 @@
+// spell-checker:ignore cdata cfield stginfo cparam needsfree wstring funcptr errcheck restype argtypes ctype hresult
+
+use crate::vm::{builtins::PyModule, class::PyClassImpl, PyRef, VirtualMachine};
+
+mod ffi;
+mod simple;
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyRef<PyModule> {
+    let ctx = &vm.ctx;
+    _ctypes::PyCData::make_class(ctx);
+    _ctypes::PyCSimpleType::make_class(ctx);
+    _ctypes::PyCStructType::make_class(ctx);
+    _ctypes::PyCUnionType::make_class(ctx);
+    _ctypes::PyCArrayType::make_class(ctx);
+    _ctypes::PyCPointerType::make_class(ctx);
+    _ctypes::PyCFuncPtrType::make_class(ctx);
+    _ctypes::PyCField::make_class(ctx);
+    _ctypes::PyCArgObject::make_class(ctx);
+    _ctypes::PyStgInfo::make_class(ctx);
+    _ctypes::make_module(vm)
+}
+
+#[pymodule]
+mod _ctypes {
+    use super::{
+        ffi::{self, FfiType},
+        simple::{self, SimpleKind, WChar},
+    };
+    use crate::common::lock::{PyMutex, PyRwLock};
+    #[cfg(feature = "threading")]
+    use crate::vm::vm::thread::ThreadedVirtualMachine;
+    use crate::vm::{
+        builtins::{
+            PyBaseExceptionRef, PyByteArray, PyBytes, PyDictRef, PyFloat, PyInt, PySlice, PyStr,
+            PyStrRef, PyTuple, PyTupleRef, PyType, PyTypeRef,
+        },
+        class::StaticType,
+        convert::ToPyException,
+        function::{ArgBytesLike, FuncArgs, OptionalArg, PySetterValue},
+        protocol::{BufferDescriptor, BufferMethods, PyBuffer, PyNumberMethods},
+        types::{AsBuffer, AsNumber, Callable, Constructor, GetDescriptor, Representable, SetAttr},
+        vm::thread,
+        AsObject, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, TryFromBorrowedObject,
+        TryFromObject, VirtualMachine,
+    };
+    use malachite_bigint::BigInt;
+    use num_traits::{Signed, ToPrimitive};
+    use std::{
+        alloc::Layout,
+        cell::{Cell, RefCell},
+        ffi::{c_int, c_void, CString},
+        fmt, mem, ptr, slice,
+    };
+
+    #[pyattr(name = "__version__")]
+    const VERSION: &str = "1.1.0";
+
+    #[cfg(unix)]
+    #[pyattr]
+    use libc::{RTLD_GLOBAL, RTLD_LOCAL};
+    #[cfg(windows)]
+    #[pyattr]
+    const RTLD_LOCAL: i32 = 0;
+    #[cfg(windows)]
+    #[pyattr]
+    const RTLD_GLOBAL: i32 = 0;
+
+    #[pyattr]
+    const FUNCFLAG_CDECL: u32 = 0x1;
+    #[pyattr]
+    const FUNCFLAG_HRESULT: u32 = 0x2;
+    #[pyattr]
+    const FUNCFLAG_PYTHONAPI: u32 = 0x4;
+    #[pyattr]
+    const FUNCFLAG_USE_ERRNO: u32 = 0x8;
+    #[pyattr]
+    const FUNCFLAG_USE_LASTERROR: u32 = 0x10;
+    #[cfg(windows)]
+    #[pyattr]
+    const FUNCFLAG_STDCALL: u32 = 0x0;
+
+    #[pyattr]
+    const CTYPES_MAX_ARGCOUNT: usize = 1024;
+    #[pyattr]
+    const SIZEOF_TIME_T: usize = mem::size_of::<libc::time_t>();
+
+    /// The size of the register libffi widens small integer results to.
+    const ARG_SIZE: usize = mem::size_of::<libffi::low::ffi_arg>();
+
+    #[pyattr(name = "ArgumentError", once)]
+    fn argument_error_type(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "_ctypes",
+            "ArgumentError",
+            Some(vec![vm.ctx.exceptions.exception_type.to_owned()]),
+        )
+    }
+
+    #[pyattr(once)]
+    fn _pointer_type_cache(vm: &VirtualMachine) -> PyDictRef {
+        vm.ctx.new_dict()
+    }
+
+    #[pyattr(once)]
+    fn _array_type_cache(vm: &VirtualMachine) -> PyDictRef {
+        vm.ctx.new_dict()
+    }
+
+    fn module_dict(name: &'static str, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        vm.import("_ctypes", 0)?
+            .get_attr(name, vm)?
+            .downcast()
+            .map_err(|_| vm.new_type_error(format!("_ctypes.{name} must be a dict")))
+    }
+
+    thread_local! {
+        /// The private `errno` swapped in and out around calls made with `use_errno=True`.
+        static CTYPES_ERRNO: Cell<i32> = const { Cell::new(0) };
+        /// An error raised by one of the helpers called through `PYFUNCTYPE`, to be raised
+        /// again by the call that invoked it.
+        static PENDING_ERROR: RefCell<Option<PyBaseExceptionRef>> = const { RefCell::new(None) };
+    }
+
+    #[cfg(windows)]
+    thread_local! {
+        static CTYPES_LAST_ERROR: Cell<u32> = Cell::new(0);
+    }
+
+    fn swap_errno() {
+        CTYPES_ERRNO.with(|saved| {
+            let current = ffi::errno();
+            ffi::set_errno(saved.replace(current));
+        })
+    }
+
+    #[cfg(windows)]
+    fn swap_last_error() {
+        use windows_sys::Win32::Foundation::{GetLastError, SetLastError};
+        CTYPES_LAST_ERROR.with(|saved| {
+            let current = unsafe { GetLastError() };
+            unsafe { SetLastError(saved.replace(current)) };
+        })
+    }
+
+    // Storage info
+
+    #[derive(Debug, Clone)]
+    enum StgKind {
+        Simple {
+            kind: SimpleKind,
+            swapped: bool,
+        },
+        /// A structure or union, with the names of its fields in order.
+        Struct {
+            fields: Vec<String>,
+        },
+        Array {
+            item: PyTypeRef,
+            length: usize,
+        },
+        Pointer {
+            target: Option<PyTypeRef>,
+        },
+        Function {
+            argtypes: Option<Vec<PyObjectRef>>,
+            restype: Option<PyObjectRef>,
+            flags: u32,
+        },
+    }
+
+    /// The layout of a C type, kept on its class as `__stginfo__`.
+    #[derive(Debug, Clone)]
+    struct StgInfo {
+        size: usize,
+        align: usize,
+        kind: StgKind,
+        ffi: FfiType,
+        /// Whether instances hold pointers, which keeps them from being pickled.
+        has_pointer: bool,
+        /// Set once the layout is in use, after which `_fields_` may not change.
+        finalized: bool,
+    }
+
+    impl StgInfo {
+        fn simple(kind: SimpleKind, swapped: bool) -> Self {
+            Self {
+                size: kind.size(),
+                align: kind.align(),
+                kind: StgKind::Simple { kind, swapped },
+                ffi: FfiType::Simple(kind),
+                has_pointer: matches!(
+                    kind,
+                    SimpleKind::CharP | SimpleKind::WCharP | SimpleKind::VoidP | SimpleKind::Object
+                ),
+                finalized: false,
+            }
+        }
+
+        fn pointer_sized(kind: StgKind) -> Self {
+            Self {
+                size: mem::size_of::<usize>(),
+                align: mem::align_of::<usize>(),
+                kind,
+                ffi: FfiType::Pointer,
+                has_pointer: true,
+                finalized: false,
+            }
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "StgInfo")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyStgInfo {
+        info: PyRwLock<StgInfo>,
+    }
+
+    #[pyclass]
+    impl PyStgInfo {}
+
+    fn stginfo(cls: &Py<PyType>, vm: &VirtualMachine) -> Option<PyRef<PyStgInfo>> {
+        cls.get_attr(vm.ctx.intern_str("__stginfo__"))?
+            .downcast()
+            .ok()
+    }
+
+    fn set_stginfo(cls: &Py<PyType>, info: StgInfo, vm: &VirtualMachine) {
+        let info = PyStgInfo {
+            info: PyRwLock::new(info),
+        };
+        cls.set_attr(
+            vm.ctx.intern_str("__stginfo__"),
+            info.into_ref(&vm.ctx).into(),
+        );
+    }
+
+    /// The storage info of `obj`, when it is a C type.
+    fn type_info(obj: &PyObject, vm: &VirtualMachine) -> Option<(PyTypeRef, StgInfo)> {
+        let cls = obj.downcast_ref::<PyType>()?;
+        let info = stginfo(cls, vm)?.info.read().clone();
+        Some((cls.to_owned(), info))
+    }
+
+    fn required_info(cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult<StgInfo> {
+        stginfo(cls, vm)
+            .map(|info| info.info.read().clone())
+            .ok_or_else(|| vm.new_type_error("abstract class".to_owned()))
+    }
+
+    /// Mark the layout of `cls` as in use.
+    fn finalize(cls: &Py<PyType>, vm: &VirtualMachine) {
+        if let Some(info) = cls
+            .get_direct_attr(vm.ctx.intern_str("__stginfo__"))
+            .and_then(|info| info.downcast::<PyStgInfo>().ok())
+        {
+            info.info.write().finalized = true;
+        }
+    }
+
+    fn simple_kind(cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult<(SimpleKind, bool)> {
+        match required_info(cls, vm)?.kind {
+            StgKind::Simple { kind, swapped } => Ok((kind, swapped)),
+            _ => Err(vm.new_type_error("abstract class".to_owned())),
+        }
+    }
+
+    /// The kind of a fundamental type such as `c_int`, whose values convert to and from
+    /// Python objects rather than being returned as instances, as those of its subclasses are.
+    fn fundamental_kind(cls: &Py<PyType>, vm: &VirtualMachine) -> Option<(SimpleKind, bool)> {
+        if !cls.base.as_ref()?.is(PyCSimple::static_type()) {
+            return None;
+        }
+        simple_kind(cls, vm).ok()
+    }
+
+    // Instance memory
+
+    /// The buffer behind a C data object, either owned or borrowed from elsewhere.
+    #[derive(Debug)]
+    struct Memory {
+        ptr: *mut u8,
+        size: usize,
+        layout: Option<Layout>,
+    }
+
+    // the memory is only accessed through the object that owns it
+    unsafe impl Send for Memory {}
+    unsafe impl Sync for Memory {}
+
+    impl Memory {
+        fn alloc(size: usize, align: usize, vm: &VirtualMachine) -> PyResult<Self> {
+            let layout = Layout::from_size_align(size.max(1), align.max(1))
+                .map_err(|_| vm.new_memory_error("C data is too large".to_owned()))?;
+            let ptr = unsafe { std::alloc::alloc_zeroed(layout) };
+            if ptr.is_null() {
+                std::alloc::handle_alloc_error(layout);
+            }
+            Ok(Self {
+                ptr,
+                size,
+                layout: Some(layout),
+            })
+        }
+
+        fn borrowed(ptr: *mut u8, size: usize) -> Self {
+            Self {
+                ptr,
+                size,
+                layout: None,
+            }
+        }
+    }
+
+    impl Drop for Memory {
+        fn drop(&mut self) {
+            if let Some(layout) = self.layout {
+                unsafe { std::alloc::dealloc(self.ptr, layout) };
+            }
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "_CData")]
+    #[derive(PyPayload)]
+    pub(super) struct PyCData {
+        memory: PyRwLock<Memory>,
+        /// The object whose memory this one borrows.
+        base: Option<PyObjectRef>,
+        /// Objects that must outlive the memory, as it points into them.
+        objects: PyMutex<Option<PyDictRef>>,
+        /// The buffer export behind an object made by `from_buffer()`.
+        export: Option<PyBuffer>,
+    }
+
+    impl fmt::Debug for PyCData {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("_CData")
+        }
+    }
+
+    impl PyCData {
+        fn new(size: usize, align: usize, vm: &VirtualMachine) -> PyResult<Self> {
+            Ok(Self::with_memory(Memory::alloc(size, align, vm)?, None))
+        }
+
+        fn with_memory(memory: Memory, base: Option<PyObjectRef>) -> Self {
+            Self {
+                memory: PyRwLock::new(memory),
+                base,
+                objects: PyMutex::new(None),
+                export: None,
+            }
+        }
+
+        fn from_bytes(data: &[u8], align: usize, vm: &VirtualMachine) -> PyResult<Self> {
+            let memory = Memory::alloc(data.len(), align, vm)?;
+            unsafe { ptr::copy_nonoverlapping(data.as_ptr(), memory.ptr, data.len()) };
+            Ok(Self::with_memory(memory, None))
+        }
+
+        fn ptr(&self) -> *mut u8 {
+            self.memory.read().ptr
+        }
+
+        fn size(&self) -> usize {
+            self.memory.read().size
+        }
+
+        fn to_bytes(&self) -> Vec<u8> {
+            let memory = self.memory.read();
+            unsafe { slice::from_raw_parts(memory.ptr, memory.size) }.to_vec()
+        }
+
+        /// Keep `obj` alive for as long as the memory this object belongs to.
+        fn keep_alive(&self, key: usize, obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            if let Some(base) = self.base.as_deref().and_then(cdata) {
+                return base.keep_alive(key, obj, vm);
+            }
+            let objects = self
+                .objects
+                .lock()
+                .get_or_insert_with(|| vm.ctx.new_dict())
+                .clone();
+            objects.set_item(vm.ctx.new_int(key).as_object(), obj, vm)
+        }
+    }
+
+    fn cdata(obj: &PyObject) -> Option<&PyCData> {
+        obj.payload::<PyCData>()
+            .or_else(|| obj.payload::<PyCFuncPtr>().map(|func| &func.cdata))
+    }
+
+    static BUFFER_METHODS: BufferMethods = BufferMethods {
+        obj_bytes: |buffer| {
+            let memory = cdata(&buffer.obj).unwrap().memory.read();
+            unsafe { slice::from_raw_parts(memory.ptr, memory.size) }.into()
+        },
+        obj_bytes_mut: |buffer| {
+            let memory = cdata(&buffer.obj).unwrap().memory.read();
+            unsafe { slice::from_raw_parts_mut(memory.ptr, memory.size) }.into()
+        },
+        release: |_| {},
+        retain: |_| {},
+    };
+
+    fn as_buffer(obj: &PyObject, data: &PyCData) -> PyBuffer {
+        PyBuffer::new(
+            obj.to_owned(),
+            BufferDescriptor::simple(data.size(), false),
+            &BUFFER_METHODS,
+        )
+    }
+
+    impl AsBuffer for PyCData {
+        fn as_buffer(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyBuffer> {
+            Ok(as_buffer(zelf.as_object(), zelf))
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(AsBuffer))]
+    impl PyCData {
+        #[pygetset(name = "_b_base_")]
+        fn b_base(zelf: PyObjectRef, vm: &VirtualMachine) -> PyObjectRef {
+            cdata(&zelf)
+                .and_then(|data| data.base.clone())
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset(name = "_b_needsfree_")]
+        fn b_needsfree(zelf: PyObjectRef) -> bool {
+            cdata(&zelf).map_or(false, |data| data.memory.read().layout.is_some())
+        }
+
+        #[pygetset(name = "_objects")]
+        fn objects(zelf: PyObjectRef, vm: &VirtualMachine) -> PyObjectRef {
+            cdata(&zelf)
+                .and_then(|data| data.objects.lock().clone())
+                .map_or_else(|| vm.ctx.none(), Into::into)
+        }
+
+        #[pymethod(magic)]
+        #[allow(clippy::type_complexity)]
+        fn reduce(
+            zelf: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<(PyObjectRef, (PyTypeRef, (PyObjectRef, PyObjectRef)))> {
+            let data = cdata(&zelf).ok_or_else(|| vm.new_type_error("invalid type".to_owned()))?;
+            if required_info(zelf.class(), vm)?.has_pointer {
+                return Err(vm.new_value_error(
+                    "ctypes objects containing pointers cannot be pickled".to_owned(),
+                ));
+            }
+            let unpickle = vm.import("_ctypes", 0)?.get_attr("_unpickle", vm)?;
+            let dict = zelf.dict().unwrap_or_else(|| vm.ctx.new_dict());
+            let bytes = vm.ctx.new_bytes(data.to_bytes());
+            Ok((
+                unpickle,
+                (zelf.class().to_owned(), (dict.into(), bytes.into())),
+            ))
+        }
+
+        #[pymethod(magic)]
+        fn setstate(
+            zelf: PyObjectRef,
+            dict: PyDictRef,
+            state: ArgBytesLike,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let data = cdata(&zelf).ok_or_else(|| vm.new_type_error("invalid type".to_owned()))?;
+            {
+                let state = state.borrow_buf();
+                let len = state.len().min(data.size());
+                unsafe { ptr::copy_nonoverlapping(state.as_ptr(), data.ptr(), len) };
+            }
+            if let Some(own) = zelf.dict() {
+                for (key, value) in &dict {
+                    own.set_item(&*key, value, vm)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // Conversions between C values and Python objects
+
+    /// The low 64 bits of an integer, since C's conversions wrap rather than fail.
+    fn wrapping_u64(value: &BigInt) -> u64 {
+        value
+            .to_u64()
+            .or_else(|| value.to_i64().map(|value| value as u64))
+            .unwrap_or_else(|| {
+                let modulus = BigInt::from(u128::from(u64::MAX) + 1);
+                let mut low = value % &modulus;
+                if low.is_negative() {
+                    low += &modulus;
+                }
+                low.to_u64().unwrap_or_default()
+            })
+    }
+
+    fn int_value(value: &PyObject, vm: &VirtualMachine) -> PyResult<u64> {
+        if value.payload_is::<PyFloat>() {
+            return Err(vm.new_type_error("int expected instead of float".to_owned()));
+        }
+        Ok(wrapping_u64(value.try_index(vm)?.as_bigint()))
+    }
+
+    fn address_value(value: &PyObject, vm: &VirtualMachine) -> PyResult<usize> {
+        Ok(int_value(value, vm)? as usize)
+    }
+
+    fn char_value(value: &PyObject, vm: &VirtualMachine) -> PyResult<u8> {
+        if let Some(bytes) = value.payload::<PyBytes>() {
+            if let [c] = bytes.as_bytes() {
+                return Ok(*c);
+            }
+        } else if let Some(bytes) = value.payload::<PyByteArray>() {
+            if let [c] = &*bytes.borrow_buf() {
+                return Ok(*c);
+            }
+        } else if let Some(int) = value.payload::<PyInt>() {
+            if let Some(c) = int.as_bigint().to_u8() {
+                return Ok(c);
+            }
+        }
+        Err(vm.new_type_error("one character bytes, bytearray or integer expected".to_owned()))
+    }
+
+    unsafe fn read_usize(ptr: *const u8) -> usize {
+        ptr::read_unaligned(ptr as *const usize)
+    }
+
+    unsafe fn write_usize(ptr: *mut u8, value: usize) {
+        ptr::write_unaligned(ptr as *mut usize, value)
+    }
+
+    /// An owned, NUL terminated copy of `data` for a `char *` argument.
+    fn c_string(data: &[u8], vm: &VirtualMachine) -> PyResult<PyRef<PyCData>> {
+        let mut bytes = data.to_vec();
+        bytes.push(0);
+        Ok(PyCData::from_bytes(&bytes, 1, vm)?.into_ref(&vm.ctx))
+    }
+
+    /// An owned, NUL terminated copy of `s` for a `wchar_t *` argument.
+    fn wide_string(s: &str, vm: &VirtualMachine) -> PyResult<PyRef<PyCData>> {
+        let mut units = simple::encode_wide(s);
+        units.push(0);
+        let bytes: Vec<u8> = units.iter().flat_map(|unit| unit.to_ne_bytes()).collect();
+        Ok(PyCData::from_bytes(&bytes, mem::align_of::<WChar>(), vm)?.into_ref(&vm.ctx))
+    }
+
+    fn simple_get(
+        kind: SimpleKind,
+        swapped: bool,
+        ptr: *const u8,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let size = kind.size();
+        let mut buf = [0u8; 16];
+        unsafe { ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), size) };
+        if swapped {
+            buf[..size].reverse();
+        }
+        let address = || usize::from_ne_bytes(buf[..mem::size_of::<usize>()].try_into().unwrap());
+        Ok(match kind {
+            SimpleKind::Char => vm.ctx.new_bytes(vec![buf[0]]).into(),
+            SimpleKind::Bool => vm.ctx.new_bool(buf[0] != 0).into(),
+            SimpleKind::Float => vm
+                .ctx
+                .new_float(f32::from_ne_bytes(buf[..4].try_into().unwrap()) as f64)
+                .into(),
+            SimpleKind::Double | SimpleKind::LongDouble => vm
+                .ctx
+                .new_float(f64::from_ne_bytes(buf[..8].try_into().unwrap()))
+                .into(),
+            SimpleKind::WChar => {
+                let unit = WChar::from_ne_bytes(buf[..size].try_into().unwrap());
+                vm.ctx.new_str(simple::decode_wide(&[unit])).into()
+            }
+            SimpleKind::CharP => match address() {
+                0 => vm.ctx.none(),
+                address => {
+                    let bytes = unsafe { simple::c_string_at(address as *const u8, None) };
+                    vm.ctx.new_bytes(bytes).into()
+                }
+            },
+            SimpleKind::WCharP => match address() {
+                0 => vm.ctx.none(),
+                address => {
+                    let s = unsafe { simple::wide_string_at(address as *const WChar, None) };
+                    vm.ctx.new_str(s).into()
+                }
+            },
+            SimpleKind::VoidP => match address() {
+                0 => vm.ctx.none(),
+                address => vm.ctx.new_int(address).into(),
+            },
+            SimpleKind::Object => match address() {
+                0 => return Err(vm.new_value_error("PyObject is NULL".to_owned())),
+                address => unsafe { &*(address as *const PyObject) }.to_owned(),
+            },
+            _ if kind.is_signed() => vm.ctx.new_int(kind.decode_int(&buf) as i64).into(),
+            _ => vm.ctx.new_int(kind.decode_int(&buf) as u64).into(),
+        })
+    }
+
+    /// Store `value` as a `kind` at `ptr`, returning any object the stored value points into.
+    fn simple_set(
+        kind: SimpleKind,
+        swapped: bool,
+        ptr: *mut u8,
+        value: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<PyObjectRef>> {
+        let size = kind.size();
+        let mut buf = [0u8; 16];
+        let mut keep = None;
+        let set_address = |buf: &mut [u8], address: usize| {
+            buf[..mem::size_of::<usize>()].copy_from_slice(&address.to_ne_bytes())
+        };
+        match kind {
+            SimpleKind::Char => buf[0] = char_value(&value, vm)?,
+            SimpleKind::Bool => buf[0] = value.is_true(vm)? as u8,
+            SimpleKind::Float => {
+                let float = value.try_float(vm)?.to_f64() as f32;
+                buf[..4].copy_from_slice(&float.to_ne_bytes());
+            }
+            SimpleKind::Double | SimpleKind::LongDouble => {
+                let float = value.try_float(vm)?.to_f64();
+                buf[..8].copy_from_slice(&float.to_ne_bytes());
+            }
+            SimpleKind::WChar => {
+                let s = value.payload::<PyStr>().ok_or_else(|| {
+                    vm.new_type_error(format!(
+                        "unicode string expected instead of {} instance",
+                        value.class().name()
+                    ))
+                })?;
+                let units = simple::encode_wide(s.as_str());
+                let [unit] = units[..] else {
+                    return Err(
+                        vm.new_type_error("one character unicode string expected".to_owned())
+                    );
+                };
+                buf[..size].copy_from_slice(&unit.to_ne_bytes());
+            }
+            SimpleKind::CharP => {
+                if let Some(bytes) = value.payload::<PyBytes>() {
+                    let holder = c_string(bytes.as_bytes(), vm)?;
+                    set_address(&mut buf, holder.ptr() as usize);
+                    keep = Some(holder.into());
+                } else if value.payload_is::<PyInt>() {
+                    set_address(&mut buf, address_value(&value, vm)?);
+                } else if !vm.is_none(&value) {
+                    return Err(vm.new_type_error(format!(
+                        "bytes or integer address expected instead of {} instance",
+                        value.class().name()
+                    )));
+                }
+            }
+            SimpleKind::WCharP => {
+                if let Some(s) = value.payload::<PyStr>() {
+                    let holder = wide_string(s.as_str(), vm)?;
+                    set_address(&mut buf, holder.ptr() as usize);
+                    keep = Some(holder.into());
+                } else if value.payload_is::<PyInt>() {
+                    set_address(&mut buf, address_value(&value, vm)?);
+                } else if !vm.is_none(&value) {
+                    return Err(vm.new_type_error(format!(
+                        "unicode string or integer address expected instead of {} instance",
+                        value.class().name()
+                    )));
+                }
+            }
+            SimpleKind::VoidP => {
+                if value.payload_is::<PyInt>() {
+                    set_address(&mut buf, address_value(&value, vm)?);
+                } else if !vm.is_none(&value) {
+                    return Err(vm.new_type_error("cannot be converted to pointer".to_owned()));
+                }
+            }
+            SimpleKind::Object => {
+                set_address(&mut buf, value.as_raw() as usize);
+                keep = Some(value);
+            }
+            _ => simple::write_uint(int_value(&value, vm)?, &mut buf[..size]),
+        }
+        if swapped {
+            buf[..size].reverse();
+        }
+        unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), ptr, size) };
+        Ok(keep)
+    }
+
+    fn get_char_array(
+        ptr: *const u8,
+        kind: SimpleKind,
+        length: usize,
+        vm: &VirtualMachine,
+    ) -> PyObjectRef {
+        if kind == SimpleKind::Char {
+            let data = unsafe { slice::from_raw_parts(ptr, length) };
+            let end = data.iter().position(|&c| c == 0).unwrap_or(length);
+            vm.ctx.new_bytes(data[..end].to_vec()).into()
+        } else {
+            let data = unsafe { slice::from_raw_parts(ptr as *const WChar, length) };
+            let end = data.iter().position(|&c| c == 0).unwrap_or(length);
+            vm.ctx.new_str(simple::decode_wide(&data[..end])).into()
+        }
+    }
+
+    fn set_char_array(
+        ptr: *mut u8,
+        kind: SimpleKind,
+        length: usize,
+        value: &PyObject,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let bytes = if kind == SimpleKind::Char {
+            let data = value.payload::<PyBytes>().ok_or_else(|| {
+                vm.new_type_error(format!(
+                    "bytes expected instead of {} instance",
+                    value.class().name()
+                ))
+            })?;
+            if data.len() > length {
+                return Err(vm.new_value_error(format!(
+                    "bytes too long ({}, maximum length {length})",
+                    data.len()
+                )));
+            }
+            data.as_bytes().to_vec()
+        } else {
+            let s = value.payload::<PyStr>().ok_or_else(|| {
+                vm.new_type_error(format!(
+                    "unicode string expected instead of {} instance",
+                    value.class().name()
+                ))
+            })?;
+            let units = simple::encode_wide(s.as_str());
+            if units.len() > length {
+                return Err(vm.new_value_error(format!(
+                    "string too long ({}, maximum length {length})",
+                    units.len()
+                )));
+            }
+            units.iter().flat_map(|unit| unit.to_ne_bytes()).collect()
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, bytes.len());
+            // terminate the string when there is room, as strncpy() would
+            if bytes.len() < length * kind.size() {
+                ptr::write_bytes(ptr.add(bytes.len()), 0, kind.size());
+            }
+        }
+        Ok(())
+    }
+
+    /// Make an instance of `cls` around `data`, without calling `__init__`.
+    fn new_instance(cls: PyTypeRef, data: PyCData, vm: &VirtualMachine) -> PyResult {
+        if cls.fast_issubclass(PyCFuncPtr::static_type()) {
+            PyCFuncPtr::new(data)
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        } else {
+            data.into_ref_with_type(vm, cls).map(Into::into)
+        }
+    }
+
+    /// Read the `cls` at `ptr`: fundamental types become Python values, anything else a view
+    /// of `owner`'s memory.
+    fn get_value(
+        cls: &PyTypeRef,
+        info: &StgInfo,
+        ptr: *mut u8,
+        owner: Option<&PyObjectRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        if let Some((kind, swapped)) = fundamental_kind(cls, vm) {
+            return simple_get(kind, swapped, ptr, vm);
+        }
+        let data = PyCData::with_memory(Memory::borrowed(ptr, info.size), owner.cloned());
+        new_instance(cls.clone(), data, vm)
+    }
+
+    /// Store `value` as a `cls` at `ptr`, within `owner`'s memory.
+    fn set_value(
+        cls: &PyTypeRef,
+        info: &StgInfo,
+        ptr: *mut u8,
+        value: PyObjectRef,
+        owner: &PyCData,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if value.fast_isinstance(cls) {
+            if let Some(src) = cdata(&value) {
+                unsafe { ptr::copy(src.ptr(), ptr, info.size) };
+                if info.has_pointer {
+                    owner.keep_alive(ptr as usize, value, vm)?;
+                }
+                return Ok(());
+            }
+        }
+        match &info.kind {
+            StgKind::Simple { kind, swapped } => {
+                if let Some(keep) = simple_set(*kind, *swapped, ptr, value, vm)? {
+                    owner.keep_alive(ptr as usize, keep, vm)?;
+                }
+                return Ok(());
+            }
+            StgKind::Pointer { target } => {
+                if vm.is_none(&value) {
+                    unsafe { write_usize(ptr, 0) };
+                    return Ok(());
+                }
+                // an array decays to a pointer to its first item
+                if let (Some(target), Some(array)) = (target, cdata(&value)) {
+                    if let Some(StgKind::Array { item, .. }) =
+                        stginfo(value.class(), vm).map(|info| info.info.read().kind.clone())
+                    {
+                        if item.fast_issubclass(target) {
+                            unsafe { write_usize(ptr, array.ptr() as usize) };
+                            return owner.keep_alive(ptr as usize, value, vm);
+                        }
+                    }
+                }
+            }
+            StgKind::Struct { .. } | StgKind::Array { .. } => {
+                if let Some(tuple) = value.payload::<PyTuple>() {
+                    let value = cls.as_object().call(tuple.as_slice().to_vec(), vm)?;
+                    return set_value(cls, info, ptr, value, owner, vm);
+                }
+            }
+            StgKind::Function { .. } => {}
+        }
+        Err(vm.new_type_error(format!(
+            "incompatible types, {} instance instead of {} instance",
+            value.class().name(),
+            cls.name()
+        )))
+    }
+
+    /// Build a bytes, str or list from the `item`s at `indices` from `ptr`.
+    fn get_items(
+        item: &PyTypeRef,
+        info: &StgInfo,
+        ptr: *mut u8,
+        indices: impl Iterator<Item = isize>,
+        owner: &PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let item_ptr = |index: isize| ptr.wrapping_offset(index * info.size as isize);
+        match fundamental_kind(item, vm) {
+            Some((SimpleKind::Char, _)) => {
+                let bytes = indices.map(|index| unsafe { *item_ptr(index) }).collect();
+                Ok(vm.ctx.new_bytes(bytes).into())
+            }
+            Some((SimpleKind::WChar, _)) => {
+                let units: Vec<WChar> = indices
+                    .map(|index| unsafe { ptr::read_unaligned(item_ptr(index) as *const WChar) })
+                    .collect();
+                Ok(vm.ctx.new_str(simple::decode_wide(&units)).into())
+            }
+            _ => {
+                let items = indices
+                    .map(|index| get_value(item, info, item_ptr(index), Some(owner), vm))
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok(vm.ctx.new_list(items).into())
+            }
+        }
+    }
+
+    // Metaclasses
+
+    /// Run `type.__new__` on behalf of one of the metaclasses.
+    fn new_class(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult<PyTypeRef> {
+        let type_new = vm
+            .ctx
+            .types
+            .type_type
+            .slots
+            .new
+            .load()
+            .expect("type has a __new__ slot");
+        type_new(metatype, args, vm)?
+            .downcast()
+            .map_err(|_| vm.new_type_error("type.__new__() did not return a type".to_owned()))
+    }
+
+    fn array_type(item: PyTypeRef, length: isize, vm: &VirtualMachine) -> PyResult {
+        let length = usize::try_from(length)
+            .map_err(|_| vm.new_value_error(format!("Array length must be >= 0, not {length}")))?;
+        let cache = module_dict("_array_type_cache", vm)?;
+        let key: PyObjectRef = vm
+            .ctx
+            .new_tuple(vec![item.clone().into(), vm.ctx.new_int(length).into()])
+            .into();
+        if let Some(array) = cache.get_item_opt(&*key, vm)? {
+            return Ok(array);
+        }
+        let dict = vm.ctx.new_dict();
+        dict.set_item("_type_", item.clone().into(), vm)?;
+        dict.set_item("_length_", vm.ctx.new_int(length).into(), vm)?;
+        let name = format!("{}_Array_{}", item.name(), length);
+        let array = PyCArrayType::static_type()
+            .as_object()
+            .call((name, (PyCArray::static_type().to_owned(),), dict), vm)?;
+        cache.set_item(&*key, array.clone(), vm)?;
+        Ok(array)
+    }
+
+    static TYPE_AS_NUMBER: PyNumberMethods = PyNumberMethods {
+        multiply: Some(|a, b, vm| {
+            let (cls, length) = if a.payload_is::<PyType>() {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let (Some(cls), Ok(length)) = (cls.downcast_ref::<PyType>(), length.try_index(vm))
+            else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            array_type(cls.to_owned(), length.try_to_primitive(vm)?, vm)
+        }),
+        // keep `c_int | None` working like it does for any other type
+        or: Some(|a, b, vm| (PyType::as_number().or.unwrap())(a, b, vm)),
+        ..PyNumberMethods::NOT_IMPLEMENTED
+    };
+
+    /// The constructors shared by the metaclasses of every C type.
+    #[pyclass]
+    trait PyCDataType: PyPayload {
+        #[pymethod]
+        fn from_address(cls: PyTypeRef, address: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let info = required_info(&cls, vm)?;
+            let address = address_value(&address, vm)?;
+            let data = PyCData::with_memory(Memory::borrowed(address as *mut u8, info.size), None);
+            new_instance(cls, data, vm)
+        }
+
+        #[pymethod]
+        fn from_buffer(
+            cls: PyTypeRef,
+            source: PyObjectRef,
+            offset: OptionalArg<isize>,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let info = required_info(&cls, vm)?;
+            let buffer = PyBuffer::try_from_borrowed_object(vm, &source)?;
+            if buffer.desc.readonly {
+                return Err(vm.new_type_error("underlying buffer is not writable".to_owned()));
+            }
+            let offset = buffer_offset(offset, buffer.desc.len, info.size, vm)?;
+            let ptr = buffer
+                .as_contiguous_mut()
+                .ok_or_else(|| {
+                    vm.new_type_error("underlying buffer is not C contiguous".to_owned())
+                })?
+                .as_mut_ptr();
+            let mut data = PyCData::with_memory(
+                Memory::borrowed(unsafe { ptr.add(offset) }, info.size),
+                None,
+            );
+            data.export = Some(buffer);
+            new_instance(cls, data, vm)
+        }
+
+        #[pymethod]
+        fn from_buffer_copy(
+            cls: PyTypeRef,
+            source: ArgBytesLike,
+            offset: OptionalArg<isize>,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let info = required_info(&cls, vm)?;
+            let source = source.borrow_buf();
+            let offset = buffer_offset(offset, source.len(), info.size, vm)?;
+            let data = PyCData::from_bytes(&source[offset..offset + info.size], info.align, vm)?;
+            new_instance(cls, data, vm)
+        }
+
+        #[pymethod]
+        fn in_dll(
+            cls: PyTypeRef,
+            dll: PyObjectRef,
+            name: PyStrRef,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let handle = dll.get_attr("_handle", vm)?;
+            let handle = address_value(&handle, vm)?;
+            let address = symbol_address(handle, name.as_str(), vm)
+                .map_err(|_| vm.new_value_error(format!("symbol '{name}' not found")))?;
+            Self::from_address(cls, vm.ctx.new_int(address).into(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn mul(cls: PyTypeRef, length: isize, vm: &VirtualMachine) -> PyResult {
+            array_type(cls, length, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rmul(cls: PyTypeRef, length: isize, vm: &VirtualMachine) -> PyResult {
+            array_type(cls, length, vm)
+        }
+    }
+
+    fn buffer_offset(
+        offset: OptionalArg<isize>,
+        len: usize,
+        size: usize,
+        vm: &VirtualMachine,
+    ) -> PyResult<usize> {
+        let offset = usize::try_from(offset.unwrap_or(0))
+            .map_err(|_| vm.new_value_error("offset cannot be negative".to_owned()))?;
+        if len < offset + size {
+            return Err(vm.new_value_error(format!(
+                "Buffer size too small ({len} instead of at least {} bytes)",
+                offset + size
+            )));
+        }
+        Ok(offset)
+    }
+
+    /// `from_param()` for types whose arguments must be instances.
+    fn instance_from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        if value.fast_isinstance(&cls) {
+            return Ok(value);
+        }
+        if let Some(arg) = value.payload::<PyCArgObject>() {
+            if arg.obj.fast_isinstance(&cls) {
+                return Ok(value);
+            }
+        }
+        if let Some(param) = vm.get_attribute_opt(value.clone(), "_as_parameter_")? {
+            return instance_from_param(cls, param, vm);
+        }
+        Err(vm.new_type_error(format!(
+            "expected {} instance instead of {}",
+            cls.name(),
+            value.class().name()
+        )))
+    }
+
+    /// Whether `obj` is an array of, a pointer to, or a reference to a fundamental `kind`.
+    fn refers_to_kind(obj: &PyObject, kind: SimpleKind, vm: &VirtualMachine) -> bool {
+        if let Some(arg) = obj.payload::<PyCArgObject>() {
+            return fundamental_kind(arg.obj.class(), vm).map_or(false, |(k, _)| k == kind);
+        }
+        let item = match stginfo(obj.class(), vm).map(|info| info.info.read().kind.clone()) {
+            Some(StgKind::Array { item, .. }) => item,
+            Some(StgKind::Pointer {
+                target: Some(target),
+            }) => target,
+            _ => return false,
+        };
+        fundamental_kind(&item, vm).map_or(false, |(k, _)| k == kind)
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "PyCSimpleType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCSimpleType;
+
+    impl Constructor for PyCSimpleType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let cls = new_class(metatype, args, vm)?;
+            let code = cls
+                .get_attr(vm.ctx.intern_str("_type_"))
+                .ok_or_else(|| {
+                    vm.new_attribute_error("class must define a '_type_' attribute".to_owned())
+                })?
+                .downcast::<PyStr>()
+                .map_err(|_| {
+                    vm.new_type_error("class must define a '_type_' string attribute".to_owned())
+                })?;
+            let mut chars = code.as_str().chars();
+            let kind = match (chars.next(), chars.next()) {
+                (Some(c), None) => SimpleKind::from_code(c),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                vm.new_attribute_error(format!(
+                    "class must define a '_type_' attribute which must be\n\
+                     a single character string containing one of '{}'.",
+                    simple::TYPE_CODES
+                ))
+            })?;
+            set_stginfo(&cls, StgInfo::simple(kind, false), vm);
+            if cls
+                .base
+                .as_ref()
+                .map_or(false, |base| base.is(PyCSimple::static_type()))
+            {
+                add_byte_order_types(&cls, kind, vm)?;
+            }
+            Ok(cls.into())
+        }
+    }
+
+    /// Give a fundamental type its `__ctype_be__` and `__ctype_le__` variants.
+    fn add_byte_order_types(
+        cls: &PyTypeRef,
+        kind: SimpleKind,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if !kind.is_integral() && !matches!(kind, SimpleKind::Float | SimpleKind::Double) {
+            return Ok(());
+        }
+        let swapped = if kind.size() == 1 {
+            cls.clone()
+        } else {
+            let suffix = if cfg!(target_endian = "little") {
+                "_be"
+            } else {
+                "_le"
+            };
+            let dict = vm.ctx.new_dict();
+            dict.set_item("_type_", vm.ctx.new_str(kind.code().to_string()).into(), vm)?;
+            let bases = vm
+                .ctx
+                .new_tuple(cls.bases.iter().map(|base| base.clone().into()).collect());
+            let args = FuncArgs::from(vec![
+                vm.ctx.new_str(format!("{}{suffix}", cls.name())).into(),
+                bases.into(),
+                dict.into(),
+            ]);
+            let swapped = new_class(cls.class().to_owned(), args, vm)?;
+            set_stginfo(&swapped, StgInfo::simple(kind, true), vm);
+            swapped
+        };
+        let (native, other) = if cfg!(target_endian = "little") {
+            ("__ctype_le__", "__ctype_be__")
+        } else {
+            ("__ctype_be__", "__ctype_le__")
+        };
+        for cls in [cls, &swapped] {
+            cls.set_attr(vm.ctx.intern_str(native), cls.clone().into());
+            cls.set_attr(vm.ctx.intern_str(other), swapped.clone().into());
+        }
+        Ok(())
+    }
+
+    impl AsNumber for PyCSimpleType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCSimpleType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCSimpleType {
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            if value.fast_isinstance(&cls) {
+                return Ok(value);
+            }
+            let (kind, _) = simple_kind(&cls, vm)?;
+            let accepted = match kind {
+                SimpleKind::CharP => {
+                    vm.is_none(&value)
+                        || value.payload_is::<PyBytes>()
+                        || refers_to_kind(&value, SimpleKind::Char, vm)
+                }
+                SimpleKind::WCharP => {
+                    vm.is_none(&value)
+                        || value.payload_is::<PyStr>()
+                        || refers_to_kind(&value, SimpleKind::WChar, vm)
+                }
+                SimpleKind::VoidP => {
+                    if value.payload_is::<PyInt>() {
+                        return cls.as_object().call((value,), vm);
+                    }
+                    vm.is_none(&value)
+                        || value.payload_is::<PyBytes>()
+                        || value.payload_is::<PyStr>()
+                        || value.payload_is::<PyCArgObject>()
+                        || cdata(&value).is_some()
+                            && stginfo(value.class(), vm).map_or(false, |info| {
+                                match info.info.read().kind {
+                                    StgKind::Array { .. }
+                                    | StgKind::Pointer { .. }
+                                    | StgKind::Function { .. } => true,
+                                    StgKind::Simple { kind, .. } => matches!(
+                                        kind,
+                                        SimpleKind::CharP | SimpleKind::WCharP | SimpleKind::VoidP
+                                    ),
+                                    StgKind::Struct { .. } => false,
+                                }
+                            })
+                }
+                _ => {
+                    if let Ok(obj) = cls.as_object().call((value.clone(),), vm) {
+                        return Ok(obj);
+                    }
+                    false
+                }
+            };
+            if accepted {
+                return Ok(value);
+            }
+            if let Some(param) = vm.get_attribute_opt(value, "_as_parameter_")? {
+                return Self::from_param(cls, param, vm);
+            }
+            Err(vm.new_type_error("wrong type".to_owned()))
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "PyCStructType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCStructType;
+
+    #[pyclass(no_attr, module = "_ctypes", name = "UnionType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCUnionType;
+
+    fn struct_type_new(
+        metatype: PyTypeRef,
+        args: FuncArgs,
+        union: bool,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let cls = new_class(metatype, args, vm)?;
+        // a subclass fixes the layout of its base
+        if let Some(base) = &cls.base {
+            finalize(base, vm);
+        }
+        let info = match cls.get_direct_attr(vm.ctx.intern_str("_fields_")) {
+            Some(fields) => struct_layout(&cls, &fields, union, vm)?,
+            None => base_struct_info(&cls, vm).unwrap_or(StgInfo {
+                size: 0,
+                align: 1,
+                kind: StgKind::Struct { fields: vec![] },
+                ffi: FfiType::Struct(vec![]),
+                has_pointer: false,
+                finalized: false,
+            }),
+        };
+        set_stginfo(&cls, info, vm);
+        Ok(cls.into())
+    }
+
+    fn base_struct_info(cls: &Py<PyType>, vm: &VirtualMachine) -> Option<StgInfo> {
+        let info = stginfo(cls.base.as_ref()?, vm)?.info.read().clone();
+        matches!(info.kind, StgKind::Struct { .. }).then_some(StgInfo {
+            finalized: false,
+            ..info
+        })
+    }
+
+    fn struct_type_setattro(
+        obj: &PyObject,
+        name: &Py<PyStr>,
+        value: PySetterValue,
+        union: bool,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let cls = obj
+            .downcast_ref::<PyType>()
+            .ok_or_else(|| vm.new_type_error("expected a type".to_owned()))?;
+        let fields = match &value {
+            PySetterValue::Assign(fields) if name.as_str() == "_fields_" => {
+                let final_ = cls
+                    .get_direct_attr(vm.ctx.intern_str("__stginfo__"))
+                    .and_then(|info| info.downcast::<PyStgInfo>().ok())
+                    .map_or(false, |info| info.info.read().finalized);
+                if final_ {
+                    return Err(vm.new_attribute_error("_fields_ is final".to_owned()));
+                }
+                Some(fields.clone())
+            }
+            _ => None,
+        };
+        <PyType as SetAttr>::setattro(cls, name, value, vm)?;
+        if let Some(fields) = fields {
+            let info = struct_layout(cls, &fields, union, vm)?;
+            set_stginfo(cls, info, vm);
+        }
+        Ok(())
+    }
+
+    fn round_up(value: usize, align: usize) -> usize {
+        (value + align - 1) / align * align
+    }
+
+    /// Lay out a structure or union from its `_fields_`, adding a `CField` descriptor to `cls`
+    /// for each field.
+    fn struct_layout(
+        cls: &Py<PyType>,
+        fields: &PyObject,
+        union: bool,
+        vm: &VirtualMachine,
+    ) -> PyResult<StgInfo> {
+        let pairs_error = || {
+            vm.new_type_error("'_fields_' must be a sequence of (name, C type) pairs".to_owned())
+        };
+        let fields: Vec<PyObjectRef> = TryFromBorrowedObject::try_from_borrowed_object(vm, fields)
+            .map_err(|_| vm.new_type_error("_fields_ must be a sequence of pairs".to_owned()))?;
+        let pack = match cls.get_attr(vm.ctx.intern_str("_pack_")) {
+            Some(pack) => {
+                let pack = pack
+                    .try_index(vm)
+                    .ok()
+                    .and_then(|pack| pack.as_bigint().to_usize())
+                    .ok_or_else(|| {
+                        vm.new_value_error("_pack_ must be a non-negative integer".to_owned())
+                    })?;
+                if pack != 0 && !pack.is_power_of_two() {
+                    return Err(vm.new_value_error("_pack_ must be a power of two".to_owned()));
+                }
+                pack
+            }
+            None => 0,
+        };
+
+        let base = base_struct_info(cls, vm);
+        let (mut size, mut align, mut names, mut ffi_fields, mut has_pointer) = match base {
+            Some(StgInfo {
+                size,
+                align,
+                kind: StgKind::Struct { fields },
+                ffi,
+                has_pointer,
+                ..
+            }) => (size, align, fields, vec![ffi], has_pointer),
+            _ => (0, 1, vec![], vec![], false),
+        };
+        let mut offset = if union { 0 } else { size };
+        // the size, bits used and offset of the storage unit bit fields are being packed into
+        let mut bitfield: Option<(usize, u32, usize)> = None;
+
+        for (index, field) in fields.iter().enumerate() {
+            let tuple = field
+                .payload::<PyTuple>()
+                .filter(|tuple| matches!(tuple.len(), 2 | 3))
+                .ok_or_else(pairs_error)?;
+            let name = tuple[0]
+                .clone()
+                .downcast::<PyStr>()
+                .map_err(|_| pairs_error())?;
+            let (proto, info) = type_info(&tuple[1], vm).ok_or_else(|| {
+                vm.new_type_error(format!(
+                    "second item in _fields_ tuple (index {index}) must be a C type"
+                ))
+            })?;
+            finalize(&proto, vm);
+            let field_align = if pack > 0 {
+                info.align.min(pack)
+            } else {
+                info.align
+            };
+
+            let bits = match tuple.get(2) {
+                Some(bits) => {
+                    let kind = match info.kind {
+                        StgKind::Simple { kind, .. } if kind.allows_bitfield() => kind,
+                        _ => {
+                            return Err(vm.new_type_error(format!(
+                                "bit fields not allowed for type {}",
+                                proto.name()
+                            )))
+                        }
+                    };
+                    let bits = bits
+                        .try_index(vm)?
+                        .as_bigint()
+                        .to_u32()
+                        .filter(|&bits| bits >= 1 && bits as usize <= kind.size() * 8)
+                        .ok_or_else(|| {
+                            vm.new_value_error("number of bits invalid for bit field".to_owned())
+                        })?;
+                    Some(bits)
+                }
+                None => None,
+            };
+
+            let (field_offset, field_bits) = if union {
+                size = size.max(info.size);
+                (0, bits.map(|bits| (bits, 0)))
+            } else {
+                match (bits, bitfield) {
+                    (Some(bits), Some((unit_size, used, unit_offset)))
+                        if unit_size == info.size && used + bits <= unit_size as u32 * 8 =>
+                    {
+                        bitfield = Some((unit_size, used + bits, unit_offset));
+                        (unit_offset, Some((bits, used)))
+                    }
+                    (bits, _) => {
+                        offset = round_up(offset, field_align);
+                        let field_offset = offset;
+                        offset += info.size;
+                        bitfield = bits.map(|bits| (info.size, bits, field_offset));
+                        (field_offset, bits.map(|bits| (bits, 0)))
+                    }
+                }
+            };
+            align = align.max(field_align);
+            has_pointer |= info.has_pointer;
+            ffi_fields.push(info.ffi.clone());
+
+            let descr = PyCField {
+                proto,
+                offset: field_offset,
+                size: info.size,
+                bitfield: field_bits,
+            };
+            cls.set_attr(
+                vm.ctx.intern_str(name.as_str()),
+                descr.into_ref(&vm.ctx).into(),
+            );
+            names.push(name.as_str().to_owned());
+        }
+
+        if !union {
+            size = offset;
+        }
+        let info = StgInfo {
+            size: round_up(size, align),
+            align,
+            kind: StgKind::Struct { fields: names },
+            ffi: FfiType::Struct(ffi_fields),
+            has_pointer,
+            finalized: false,
+        };
+
+        if let Some(anonymous) = cls.get_attr(vm.ctx.intern_str("_anonymous_")) {
+            let anonymous: Vec<PyStrRef> = TryFromObject::try_from_object(vm, anonymous)?;
+            for name in anonymous {
+                let field = cls
+                    .get_direct_attr(vm.ctx.intern_str(name.as_str()))
+                    .and_then(|field| field.downcast::<PyCField>().ok())
+                    .ok_or_else(|| {
+                        vm.new_attribute_error(format!(
+                            "'{name}' is specified in _anonymous_ but not in _fields_"
+                        ))
+                    })?;
+                add_anonymous_fields(cls, &field, vm)?;
+            }
+        }
+        Ok(info)
+    }
+
+    /// Make the fields of the structure or union `field` reachable directly from `cls`.
+    fn add_anonymous_fields(
+        cls: &Py<PyType>,
+        field: &PyCField,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let Some(StgKind::Struct { fields }) =
+            stginfo(&field.proto, vm).map(|info| info.info.read().kind.clone())
+        else {
+            return Err(
+                vm.new_type_error("_anonymous_ field must be a structure or union".to_owned())
+            );
+        };
+        for name in fields {
+            let name = vm.ctx.intern_str(name.as_str());
+            let Some(inner) = field
+                .proto
+                .get_attr(name)
+                .and_then(|inner| inner.downcast::<PyCField>().ok())
+            else {
+                continue;
+            };
+            let descr = PyCField {
+                offset: field.offset + inner.offset,
+                ..(*inner).clone()
+            };
+            cls.set_attr(name, descr.into_ref(&vm.ctx).into());
+        }
+        Ok(())
+    }
+
+    impl Constructor for PyCStructType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            struct_type_new(metatype, args, false, vm)
+        }
+    }
+
+    impl AsNumber for PyCStructType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCStructType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCStructType {
+        #[pyslot]
+        fn slot_setattro(
+            obj: &PyObject,
+            name: &Py<PyStr>,
+            value: PySetterValue,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            struct_type_setattro(obj, name, value, false, vm)
+        }
+
+        #[pymethod(magic)]
+        fn setattr(
+            zelf: PyObjectRef,
+            name: PyStrRef,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Self::slot_setattro(&zelf, &name, PySetterValue::Assign(value), vm)
+        }
+
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            instance_from_param(cls, value, vm)
+        }
+    }
+
+    impl Constructor for PyCUnionType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            struct_type_new(metatype, args, true, vm)
+        }
+    }
+
+    impl AsNumber for PyCUnionType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCUnionType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCUnionType {
+        #[pyslot]
+        fn slot_setattro(
+            obj: &PyObject,
+            name: &Py<PyStr>,
+            value: PySetterValue,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            struct_type_setattro(obj, name, value, true, vm)
+        }
+
+        #[pymethod(magic)]
+        fn setattr(
+            zelf: PyObjectRef,
+            name: PyStrRef,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Self::slot_setattro(&zelf, &name, PySetterValue::Assign(value), vm)
+        }
+
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            instance_from_param(cls, value, vm)
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "PyCArrayType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCArrayType;
+
+    impl Constructor for PyCArrayType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let cls = new_class(metatype, args, vm)?;
+            let length = cls
+                .get_attr(vm.ctx.intern_str("_length_"))
+                .ok_or_else(|| {
+                    vm.new_attribute_error("class must define a '_length_' attribute".to_owned())
+                })?
+                .downcast::<PyInt>()
+                .map_err(|_| {
+                    vm.new_type_error("The '_length_' attribute must be an integer".to_owned())
+                })?;
+            if length.as_bigint().is_negative() {
+                return Err(
+                    vm.new_value_error("The '_length_' attribute must not be negative".to_owned())
+                );
+            }
+            let length = length.as_bigint().to_usize().ok_or_else(|| {
+                vm.new_overflow_error("The '_length_' attribute is too large".to_owned())
+            })?;
+            let item = cls.get_attr(vm.ctx.intern_str("_type_")).ok_or_else(|| {
+                vm.new_attribute_error("class must define a '_type_' attribute".to_owned())
+            })?;
+            let (item, info) = type_info(&item, vm)
+                .ok_or_else(|| vm.new_type_error("_type_ must have storage info".to_owned()))?;
+            finalize(&item, vm);
+            let size = info
+                .size
+                .checked_mul(length)
+                .ok_or_else(|| vm.new_overflow_error("array too large".to_owned()))?;
+            let info = StgInfo {
+                size,
+                align: info.align,
+                kind: StgKind::Array { item, length },
+                ffi: FfiType::Array(Box::new(info.ffi), length),
+                has_pointer: info.has_pointer,
+                finalized: false,
+            };
+            set_stginfo(&cls, info, vm);
+            Ok(cls.into())
+        }
+    }
+
+    impl AsNumber for PyCArrayType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCArrayType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCArrayType {
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            instance_from_param(cls, value, vm)
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "PyCPointerType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCPointerType;
+
+    fn pointer_target(cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult<PyTypeRef> {
+        match required_info(cls, vm)?.kind {
+            StgKind::Pointer {
+                target: Some(target),
+            } => Ok(target),
+            _ => Err(vm.new_type_error("Cannot create instance: has no _type_".to_owned())),
+        }
+    }
+
+    fn pointer_target_type(target: &PyObject, vm: &VirtualMachine) -> PyResult<PyTypeRef> {
+        let (target, _) = type_info(target, vm)
+            .ok_or_else(|| vm.new_type_error("_type_ must have storage info".to_owned()))?;
+        finalize(&target, vm);
+        Ok(target)
+    }
+
+    impl Constructor for PyCPointerType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let cls = new_class(metatype, args, vm)?;
+            let target = cls
+                .get_attr(vm.ctx.intern_str("_type_"))
+                .map(|target| pointer_target_type(&target, vm))
+                .transpose()?;
+            set_stginfo(
+                &cls,
+                StgInfo::pointer_sized(StgKind::Pointer { target }),
+                vm,
+            );
+            Ok(cls.into())
+        }
+    }
+
+    impl AsNumber for PyCPointerType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCPointerType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCPointerType {
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            if vm.is_none(&value) {
+                return Ok(value);
+            }
+            if let Ok(target) = pointer_target(&cls, vm) {
+                // an instance of the target type is passed by reference
+                if value.fast_isinstance(&target) {
+                    return Ok(PyCArgObject {
+                        obj: value,
+                        offset: 0,
+                    }
+                    .into_ref(&vm.ctx)
+                    .into());
+                }
+                if value
+                    .payload::<PyCArgObject>()
+                    .map_or(false, |arg| arg.obj.fast_isinstance(&target))
+                {
+                    return Ok(value);
+                }
+                // and so are arrays of and other pointers to it
+                let item =
+                    match stginfo(value.class(), vm).map(|info| info.info.read().kind.clone()) {
+                        Some(StgKind::Array { item, .. }) => Some(item),
+                        Some(StgKind::Pointer { target }) => target,
+                        _ => None,
+                    };
+                if item.map_or(false, |item| item.fast_issubclass(&target)) {
+                    return Ok(value);
+                }
+            }
+            instance_from_param(cls, value, vm)
+        }
+
+        #[pymethod]
+        fn set_type(cls: PyTypeRef, target: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let target = pointer_target_type(&target, vm)?;
+            set_stginfo(
+                &cls,
+                StgInfo::pointer_sized(StgKind::Pointer {
+                    target: Some(target.clone()),
+                }),
+                vm,
+            );
+            cls.set_attr(vm.ctx.intern_str("_type_"), target.into());
+            Ok(())
+        }
+    }
+
+    #[pyclass(no_attr, module = "_ctypes", name = "PyCFuncPtrType", base = "PyType")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCFuncPtrType;
+
+    fn check_argtypes(argtypes: PyObjectRef, vm: &VirtualMachine) -> PyResult<Vec<PyObjectRef>> {
+        let argtypes: Vec<PyObjectRef> = TryFromObject::try_from_object(vm, argtypes)
+            .map_err(|_| vm.new_type_error("_argtypes_ must be a sequence of types".to_owned()))?;
+        for (index, argtype) in argtypes.iter().enumerate() {
+            if vm
+                .get_attribute_opt(argtype.clone(), "from_param")?
+                .is_none()
+            {
+                return Err(vm.new_type_error(format!(
+                    "item {} in _argtypes_ has no from_param method",
+                    index + 1
+                )));
+            }
+        }
+        Ok(argtypes)
+    }
+
+    fn check_restype(restype: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        if vm.is_none(&restype) || type_info(&restype, vm).is_some() || restype.is_callable() {
+            Ok(restype)
+        } else {
+            Err(vm.new_type_error("restype must be a type, a callable, or None".to_owned()))
+        }
+    }
+
+    impl Constructor for PyCFuncPtrType {
+        type Args = FuncArgs;
+
+        fn py_new(metatype: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let cls = new_class(metatype, args, vm)?;
+            let flags = cls
+                .get_attr(vm.ctx.intern_str("_flags_"))
+                .and_then(|flags| flags.try_index(vm).ok())
+                .and_then(|flags| flags.as_bigint().to_u32())
+                .ok_or_else(|| {
+                    vm.new_type_error(
+                        "class must define _flags_ which must be an integer".to_owned(),
+                    )
+                })?;
+            let argtypes = cls
+                .get_attr(vm.ctx.intern_str("_argtypes_"))
+                .map(|argtypes| check_argtypes(argtypes, vm))
+                .transpose()?;
+            let restype = cls
+                .get_attr(vm.ctx.intern_str("_restype_"))
+                .map(|restype| check_restype(restype, vm))
+                .transpose()?;
+            set_stginfo(
+                &cls,
+                StgInfo::pointer_sized(StgKind::Function {
+                    argtypes,
+                    restype,
+                    flags,
+                }),
+                vm,
+            );
+            Ok(cls.into())
+        }
+    }
+
+    impl AsNumber for PyCFuncPtrType {
+        fn as_number() -> &'static PyNumberMethods {
+            &TYPE_AS_NUMBER
+        }
+    }
+
+    impl PyCDataType for PyCFuncPtrType {}
+
+    #[pyclass(flags(BASETYPE), with(Constructor, AsNumber, PyCDataType))]
+    impl PyCFuncPtrType {
+        #[pymethod]
+        fn from_param(cls: PyTypeRef, value: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            if vm.is_none(&value) {
+                return Ok(value);
+            }
+            instance_from_param(cls, value, vm)
+        }
+    }
+
+    // Fields
+
+    #[pyclass(no_attr, module = "_ctypes", name = "CField")]
+    #[derive(Debug, Clone, PyPayload)]
+    pub(super) struct PyCField {
+        proto: PyTypeRef,
+        offset: usize,
+        size: usize,
+        /// The width and shift of a bit field within its storage unit.
+        bitfield: Option<(u32, u32)>,
+    }
+
+    impl PyCField {
+        fn get(&self, owner: &PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let data =
+                cdata(owner).ok_or_else(|| vm.new_type_error("not a ctype instance".to_owned()))?;
+            let ptr = unsafe { data.ptr().add(self.offset) };
+            let info = required_info(&self.proto, vm)?;
+            if let Some((bits, shift)) = self.bitfield {
+                let (kind, _) = simple_kind(&self.proto, vm)?;
+                let storage = simple::read_uint(unsafe { slice::from_raw_parts(ptr, kind.size()) });
+                let value = simple::get_bits(storage, bits, shift, kind.is_signed());
+                return Ok(match kind {
+                    SimpleKind::Bool => vm.ctx.new_bool(value != 0).into(),
+                    _ => vm.ctx.new_int(value as i64).into(),
+                });
+            }
+            if let StgKind::Array { item, length } = &info.kind {
+                if let Some((kind @ (SimpleKind::Char | SimpleKind::WChar), _)) =
+                    fundamental_kind(item, vm)
+                {
+                    return Ok(get_char_array(ptr, kind, *length, vm));
+                }
+            }
+            get_value(&self.proto, &info, ptr, Some(owner), vm)
+        }
+
+        fn set(&self, owner: &PyObject, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let data =
+                cdata(owner).ok_or_else(|| vm.new_type_error("not a ctype instance".to_owned()))?;
+            let ptr = unsafe { data.ptr().add(self.offset) };
+            let info = required_info(&self.proto, vm)?;
+            if let Some((bits, shift)) = self.bitfield {
+                let (kind, _) = simple_kind(&self.proto, vm)?;
+                let value = match kind {
+                    SimpleKind::Bool => value.is_true(vm)? as u64,
+                    _ => int_value(&value, vm)?,
+                };
+                let storage = unsafe { slice::from_raw_parts_mut(ptr, kind.size()) };
+                let updated = simple::set_bits(simple::read_uint(storage), value, bits, shift);
+                simple::write_uint(updated, storage);
+                return Ok(());
+            }
+            if let StgKind::Array { item, length } = &info.kind {
+                if let Some((kind @ (SimpleKind::Char | SimpleKind::WChar), _)) =
+                    fundamental_kind(item, vm)
+                {
+                    if value.payload_is::<PyBytes>() || value.payload_is::<PyStr>() {
+                        return set_char_array(ptr, kind, *length, &value, vm);
+                    }
+                }
+            }
+            set_value(&self.proto, &info, ptr, value, data, vm)
+        }
+    }
+
+    impl GetDescriptor for PyCField {
+        fn descr_get(
+            zelf_obj: PyObjectRef,
+            obj: Option<PyObjectRef>,
+            _cls: Option<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let (zelf, obj) = Self::_unwrap(&zelf_obj, obj, vm)?;
+            if vm.is_none(&obj) {
+                Ok(zelf_obj)
+            } else {
+                zelf.get(&obj, vm)
+            }
+        }
+    }
+
+    impl Representable for PyCField {
+        fn repr_str(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<String> {
+            Ok(match zelf.bitfield {
+                Some((bits, shift)) => format!(
+                    "<Field type={}, ofs={}:{shift}, bits={bits}>",
+                    zelf.proto.name(),
+                    zelf.offset
+                ),
+                None => format!(
+                    "<Field type={}, ofs={}, size={}>",
+                    zelf.proto.name(),
+                    zelf.offset,
+                    zelf.size
+                ),
+            })
+        }
+    }
+
+    #[pyclass(with(GetDescriptor, Representable))]
+    impl PyCField {
+        #[pyslot]
+        fn descr_set(
+            zelf: &PyObject,
+            obj: PyObjectRef,
+            value: PySetterValue,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let zelf = zelf.try_to_ref::<Self>(vm)?;
+            match value {
+                PySetterValue::Assign(value) => zelf.set(&obj, value, vm),
+                PySetterValue::Delete => {
+                    Err(vm.new_type_error("can't delete attribute".to_owned()))
+                }
+            }
+        }
+
+        #[pymethod]
+        fn __set__(
+            zelf: PyObjectRef,
+            obj: PyObjectRef,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Self::descr_set(&zelf, obj, PySetterValue::Assign(value), vm)
+        }
+
+        #[pymethod]
+        fn __delete__(zelf: PyObjectRef, obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            Self::descr_set(&zelf, obj, PySetterValue::Delete, vm)
+        }
+
+        #[pygetset]
+        fn offset(&self) -> usize {
+            self.offset
+        }
+
+        #[pygetset]
+        fn size(&self) -> usize {
+            match self.bitfield {
+                Some((bits, shift)) => ((bits << 16) | shift) as usize,
+                None => self.size,
+            }
+        }
+    }
+
+    // Instance types
+
+    #[pyattr]
+    #[pyclass(name = "_SimpleCData", base = "PyCData", metaclass = "PyCSimpleType")]
+    #[derive(Debug, PyPayload)]
+    struct PyCSimple;
+
+    impl Constructor for PyCSimple {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let info = required_info(&cls, vm)?;
+            finalize(&cls, vm);
+            PyCData::new(info.size, info.align, vm)?
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor))]
+    impl PyCSimple {
+        #[pymethod(magic)]
+        fn init(
+            zelf: PyRef<PyCData>,
+            value: OptionalArg<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            match value {
+                OptionalArg::Present(value) => Self::set_value(zelf, value, vm),
+                OptionalArg::Missing => Ok(()),
+            }
+        }
+
+        #[pygetset]
+        fn value(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult {
+            let (kind, swapped) = simple_kind(zelf.class(), vm)?;
+            simple_get(kind, swapped, zelf.ptr(), vm)
+        }
+
+        #[pygetset(setter)]
+        fn set_value(
+            zelf: PyRef<PyCData>,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let (kind, swapped) = simple_kind(zelf.class(), vm)?;
+            if let Some(keep) = simple_set(kind, swapped, zelf.ptr(), value, vm)? {
+                zelf.keep_alive(zelf.ptr() as usize, keep, vm)?;
+            }
+            Ok(())
+        }
+
+        #[pymethod(magic)]
+        fn bool(zelf: PyRef<PyCData>) -> bool {
+            zelf.to_bytes().iter().any(|&b| b != 0)
+        }
+
+        #[pymethod(magic)]
+        fn repr(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult<String> {
+            let name = zelf.class().name().to_owned();
+            if fundamental_kind(zelf.class(), vm).is_none() {
+                return Ok(format!("<{name} object at {:#x}>", zelf.get_id()));
+            }
+            let value = Self::value(zelf, vm)?;
+            Ok(format!("{name}({})", value.repr(vm)?))
+        }
+
+        #[pymethod(magic)]
+        fn ctypes_from_outparam(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult {
+            if fundamental_kind(zelf.class(), vm).is_some() {
+                Self::value(zelf, vm)
+            } else {
+                Ok(zelf.into())
+            }
+        }
+    }
+
+    fn new_struct_instance(cls: PyTypeRef, vm: &VirtualMachine) -> PyResult {
+        let info = required_info(&cls, vm)?;
+        finalize(&cls, vm);
+        PyCData::new(info.size, info.align, vm)?
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+    }
+
+    fn struct_init(zelf: PyRef<PyCData>, args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let StgKind::Struct { fields } = required_info(zelf.class(), vm)?.kind else {
+            return Ok(());
+        };
+        let FuncArgs { args, kwargs } = args;
+        if args.len() > fields.len() {
+            return Err(vm.new_type_error("too many initializers".to_owned()));
+        }
+        for (name, value) in fields.iter().zip(args) {
+            if kwargs.contains_key(name) {
+                return Err(vm.new_type_error(format!("duplicate values for field '{name}'")));
+            }
+            zelf.as_object()
+                .set_attr(vm.ctx.intern_str(name.as_str()), value, vm)?;
+        }
+        for (name, value) in kwargs {
+            zelf.as_object()
+                .set_attr(vm.ctx.intern_str(name.as_str()), value, vm)?;
+        }
+        Ok(())
+    }
+
+    #[pyattr]
+    #[pyclass(name = "Structure", base = "PyCData", metaclass = "PyCStructType")]
+    #[derive(Debug, PyPayload)]
+    struct PyCStructure;
+
+    impl Constructor for PyCStructure {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            new_struct_instance(cls, vm)
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor))]
+    impl PyCStructure {
+        #[pymethod(magic)]
+        fn init(zelf: PyRef<PyCData>, args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+            struct_init(zelf, args, vm)
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(name = "Union", base = "PyCData", metaclass = "PyCUnionType")]
+    #[derive(Debug, PyPayload)]
+    struct PyCUnion;
+
+    impl Constructor for PyCUnion {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            new_struct_instance(cls, vm)
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor))]
+    impl PyCUnion {
+        #[pymethod(magic)]
+        fn init(zelf: PyRef<PyCData>, args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+            struct_init(zelf, args, vm)
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(name = "Array", base = "PyCData", metaclass = "PyCArrayType")]
+    #[derive(Debug, PyPayload)]
+    struct PyCArray;
+
+    fn array_item(cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult<(PyTypeRef, StgInfo, usize)> {
+        match required_info(cls, vm)?.kind {
+            StgKind::Array { item, length } => {
+                let info = required_info(&item, vm)?;
+                Ok((item, info, length))
+            }
+            _ => Err(vm.new_type_error("abstract class".to_owned())),
+        }
+    }
+
+    fn array_index(needle: &PyObject, length: usize, vm: &VirtualMachine) -> PyResult<usize> {
+        let index: isize = needle.try_index(vm)?.try_to_primitive(vm)?;
+        let index = if index < 0 {
+            index + length as isize
+        } else {
+            index
+        };
+        usize::try_from(index)
+            .ok()
+            .filter(|&index| index < length)
+            .ok_or_else(|| vm.new_index_error("invalid index".to_owned()))
+    }
+
+    impl Constructor for PyCArray {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            new_struct_instance(cls, vm)
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor))]
+    impl PyCArray {
+        #[pymethod(magic)]
+        fn init(zelf: PyRef<PyCData>, args: FuncArgs, vm: &VirtualMachine) -> PyResult<()> {
+            for (index, value) in args.args.into_iter().enumerate() {
+                Self::setitem(zelf.clone(), vm.ctx.new_int(index).into(), value, vm)?;
+            }
+            Ok(())
+        }
+
+        #[pymethod(magic)]
+        fn len(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult<usize> {
+            Ok(array_item(zelf.class(), vm)?.2)
+        }
+
+        #[pymethod(magic)]
+        fn getitem(zelf: PyRef<PyCData>, needle: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let (item, info, length) = array_item(zelf.class(), vm)?;
+            let ptr = zelf.ptr();
+            let owner: PyObjectRef = zelf.into();
+            if let Some(slice) = needle.payload::<PySlice>() {
+                let indices = slice.to_saturated(vm)?.iter(length).map(|i| i as isize);
+                return get_items(&item, &info, ptr, indices, &owner, vm);
+            }
+            let index = array_index(&needle, length, vm)?;
+            get_value(
+                &item,
+                &info,
+                unsafe { ptr.add(index * info.size) },
+                Some(&owner),
+                vm,
+            )
+        }
+
+        #[pymethod(magic)]
+        fn setitem(
+            zelf: PyRef<PyCData>,
+            needle: PyObjectRef,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let (item, info, length) = array_item(zelf.class(), vm)?;
+            let item_ptr = |index: usize| unsafe { zelf.ptr().add(index * info.size) };
+            if let Some(slice) = needle.payload::<PySlice>() {
+                let indices: Vec<usize> = slice.to_saturated(vm)?.iter(length).collect();
+                let values = vm.extract_elements_with(&value, Ok)?;
+                if values.len() != indices.len() {
+                    return Err(
+                        vm.new_value_error("Can only assign sequence of same size".to_owned())
+                    );
+                }
+                for (index, value) in indices.into_iter().zip(values) {
+                    set_value(&item, &info, item_ptr(index), value, &zelf, vm)?;
+                }
+                return Ok(());
+            }
+            let index = array_index(&needle, length, vm)?;
+            set_value(&item, &info, item_ptr(index), value, &zelf, vm)
+        }
+
+        #[pymethod(magic)]
+        fn delitem(
+            _zelf: PyRef<PyCData>,
+            _needle: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            Err(vm.new_type_error("Array does not support item deletion".to_owned()))
+        }
+
+        #[pygetset]
+        fn value(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult {
+            let (item, _, length) = array_item(zelf.class(), vm)?;
+            match fundamental_kind(&item, vm) {
+                Some((kind @ (SimpleKind::Char | SimpleKind::WChar), _)) => {
+                    Ok(get_char_array(zelf.ptr(), kind, length, vm))
+                }
+                _ => Err(vm.new_attribute_error(
+                    "only c_char and c_wchar arrays have a 'value' attribute".to_owned(),
+                )),
+            }
+        }
+
+        #[pygetset(setter)]
+        fn set_value(
+            zelf: PyRef<PyCData>,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let (item, _, length) = array_item(zelf.class(), vm)?;
+            match fundamental_kind(&item, vm) {
+                Some((kind @ (SimpleKind::Char | SimpleKind::WChar), _)) => {
+                    set_char_array(zelf.ptr(), kind, length, &value, vm)
+                }
+                _ => Err(vm.new_attribute_error(
+                    "only c_char and c_wchar arrays have a 'value' attribute".to_owned(),
+                )),
+            }
+        }
+
+        #[pygetset]
+        fn raw(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            let (item, _, _) = array_item(zelf.class(), vm)?;
+            match fundamental_kind(&item, vm) {
+                Some((SimpleKind::Char, _)) => Ok(zelf.to_bytes()),
+                _ => Err(
+                    vm.new_attribute_error("only c_char arrays have a 'raw' attribute".to_owned())
+                ),
+            }
+        }
+
+        #[pygetset(setter)]
+        fn set_raw(zelf: PyRef<PyCData>, value: ArgBytesLike, vm: &VirtualMachine) -> PyResult<()> {
+            let (item, _, length) = array_item(zelf.class(), vm)?;
+            if !matches!(fundamental_kind(&item, vm), Some((SimpleKind::Char, _))) {
+                return Err(
+                    vm.new_attribute_error("only c_char arrays have a 'raw' attribute".to_owned())
+                );
+            }
+            let value = value.borrow_buf();
+            if value.len() > length {
+                return Err(vm.new_value_error("byte string too long".to_owned()));
+            }
+            unsafe { ptr::copy_nonoverlapping(value.as_ptr(), zelf.ptr(), value.len()) };
+            Ok(())
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(name = "_Pointer", base = "PyCData", metaclass = "PyCPointerType")]
+    #[derive(Debug, PyPayload)]
+    struct PyCPointer;
+
+    impl Constructor for PyCPointer {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            pointer_target(&cls, vm)?;
+            new_struct_instance(cls, vm)
+        }
+    }
+
+    /// The address a pointer holds, and the type and layout of what it points to.
+    fn pointee(
+        zelf: &PyRef<PyCData>,
+        vm: &VirtualMachine,
+    ) -> PyResult<(*mut u8, PyTypeRef, StgInfo)> {
+        let target = pointer_target(zelf.class(), vm)?;
+        let info = required_info(&target, vm)?;
+        let address = unsafe { read_usize(zelf.ptr()) } as *mut u8;
+        if address.is_null() {
+            return Err(vm.new_value_error("NULL pointer access".to_owned()));
+        }
+        Ok((address, target, info))
+    }
+
+    fn pointer_slice(slice: &PySlice, vm: &VirtualMachine) -> PyResult<Vec<isize>> {
+        let index = |obj: Option<&PyObject>| -> PyResult<Option<isize>> {
+            match obj {
+                Some(obj) if !vm.is_none(obj) => obj.try_index(vm)?.try_to_primitive(vm).map(Some),
+                _ => Ok(None),
+            }
+        };
+        let step = index(slice.step.as_deref())?.unwrap_or(1);
+        if step == 0 {
+            return Err(vm.new_value_error("slice step cannot be zero".to_owned()));
+        }
+        let start = match index(slice.start.as_deref())? {
+            Some(start) => start,
+            None if step < 0 => {
+                return Err(vm.new_value_error("slice start is required for step < 0".to_owned()))
+            }
+            None => 0,
+        };
+        let stop = index(Some(&slice.stop))?
+            .ok_or_else(|| vm.new_value_error("slice stop is required".to_owned()))?;
+        let mut indices = vec![];
+        let mut index = start;
+        while (step > 0 && index < stop) || (step < 0 && index > stop) {
+            indices.push(index);
+            index += step;
+        }
+        Ok(indices)
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor))]
+    impl PyCPointer {
+        #[pymethod(magic)]
+        fn init(
+            zelf: PyRef<PyCData>,
+            value: OptionalArg<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            match value {
+                OptionalArg::Present(value) if !vm.is_none(&value) => {
+                    Self::set_contents(zelf, value, vm)
+                }
+                _ => Ok(()),
+            }
+        }
+
+        #[pygetset]
+        fn contents(zelf: PyRef<PyCData>, vm: &VirtualMachine) -> PyResult {
+            let (address, target, info) = pointee(&zelf, vm)?;
+            let data =
+                PyCData::with_memory(Memory::borrowed(address, info.size), Some(zelf.into()));
+            new_instance(target, data, vm)
+        }
+
+        #[pygetset(setter)]
+        fn set_contents(
+            zelf: PyRef<PyCData>,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let target = pointer_target(zelf.class(), vm)?;
+            let data = cdata(&value)
+                .filter(|_| value.fast_isinstance(&target))
+                .ok_or_else(|| {
+                    vm.new_type_error(format!(
+                        "expected {} instead of {}",
+                        target.name(),
+                        value.class().name()
+                    ))
+                })?;
+            unsafe { write_usize(zelf.ptr(), data.ptr() as usize) };
+            zelf.keep_alive(zelf.ptr() as usize, value, vm)
+        }
+
+        #[pymethod(magic)]
+        fn getitem(zelf: PyRef<PyCData>, needle: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let (address, target, info) = pointee(&zelf, vm)?;
+            let owner: PyObjectRef = zelf.into();
+            if let Some(slice) = needle.payload::<PySlice>() {
+                let indices = pointer_slice(slice, vm)?;
+                return get_items(&target, &info, address, indices.into_iter(), &owner, vm);
+            }
+            let index: isize = needle.try_index(vm)?.try_to_primitive(vm)?;
+            let ptr = address.wrapping_offset(index * info.size as isize);
+            get_value(&target, &info, ptr, Some(&owner), vm)
+        }
+
+        #[pymethod(magic)]
+        fn setitem(
+            zelf: PyRef<PyCData>,
+            index: isize,
+            value: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let (address, target, info) = pointee(&zelf, vm)?;
+            let ptr = address.wrapping_offset(index * info.size as isize);
+            set_value(&target, &info, ptr, value, &zelf, vm)
+        }
+
+        #[pymethod(magic)]
+        fn delitem(_zelf: PyRef<PyCData>, _index: isize, vm: &VirtualMachine) -> PyResult<()> {
+            Err(vm.new_type_error("Pointer does not support item deletion".to_owned()))
+        }
+
+        #[pymethod(magic)]
+        fn bool(zelf: PyRef<PyCData>) -> bool {
+            unsafe { read_usize(zelf.ptr()) != 0 }
+        }
+    }
+
+    // Function pointers
+
+    struct CallbackData {
+        callable: PyObjectRef,
+        argtypes: Vec<PyTypeRef>,
+        restype: Option<PyTypeRef>,
+        /// Where threads the interpreter doesn't know about get a vm from.
+        #[cfg(feature = "threading")]
+        thread: PyMutex<ThreadedVirtualMachine>,
+    }
+
+    impl fmt::Debug for CallbackData {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("CallbackData")
+                .field("callable", &self.callable)
+                .field("argtypes", &self.argtypes)
+                .field("restype", &self.restype)
+                .finish_non_exhaustive()
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(name = "CFuncPtr", base = "PyCData", metaclass = "PyCFuncPtrType")]
+    #[derive(PyPayload)]
+    pub(super) struct PyCFuncPtr {
+        cdata: PyCData,
+        callback: Option<ffi::Closure<CallbackData>>,
+        argtypes: PyRwLock<Option<Vec<PyObjectRef>>>,
+        /// The result type, where `Some(None)` means `void` and `None` defers to the class.
+        restype: PyRwLock<Option<PyObjectRef>>,
+        errcheck: PyRwLock<Option<PyObjectRef>>,
+    }
+
+    impl fmt::Debug for PyCFuncPtr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("CFuncPtr")
+        }
+    }
+
+    impl PyCFuncPtr {
+        fn new(cdata: PyCData) -> Self {
+            Self {
+                cdata,
+                callback: None,
+                argtypes: PyRwLock::new(None),
+                restype: PyRwLock::new(None),
+                errcheck: PyRwLock::new(None),
+            }
+        }
+    }
+
+    fn make_callback(
+        info: &StgInfo,
+        callable: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<ffi::Closure<CallbackData>> {
+        let StgKind::Function {
+            argtypes, restype, ..
+        } = &info.kind
+        else {
+            return Err(vm.new_type_error("abstract class".to_owned()));
+        };
+        let argtypes = argtypes
+            .iter()
+            .flatten()
+            .map(|argtype| {
+                type_info(argtype, vm).map(|(cls, _)| cls).ok_or_else(|| {
+                    vm.new_type_error("invalid argument type for callback function".to_owned())
+                })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let restype = match restype {
+            Some(restype) if !vm.is_none(restype) => Some(
+                type_info(restype, vm)
+                    .filter(|(_, info)| matches!(info.kind, StgKind::Simple { .. }))
+                    .map(|(cls, _)| cls)
+                    .ok_or_else(|| {
+                        vm.new_type_error("invalid result type for callback function".to_owned())
+                    })?,
+            ),
+            _ => None,
+        };
+        let arg_ffi = argtypes
+            .iter()
+            .map(|argtype| required_info(argtype, vm).map(|info| info.ffi))
+            .collect::<PyResult<Vec<_>>>()?;
+        let result_ffi = match &restype {
+            Some(restype) => required_info(restype, vm)?.ffi,
+            None => FfiType::Void,
+        };
+        let data = CallbackData {
+            callable,
+            argtypes,
+            restype,
+            #[cfg(feature = "threading")]
+            thread: PyMutex::new(vm.new_thread()),
+        };
+        ffi::Closure::new(&arg_ffi, &result_ffi, run_callback, data)
+            .map_err(|e| vm.new_memory_error(e))
+    }
+
+    unsafe extern "C" fn run_callback(
+        _cif: &libffi::low::ffi_cif,
+        result: &mut u8,
+        args: *const *const c_void,
+        data: &CallbackData,
+    ) {
+        let result: *mut u8 = result;
+        let call = |vm: &VirtualMachine| {
+            if let Err(e) = invoke_callback(data, result, args, vm) {
+                vm.run_unraisable(
+                    e,
+                    Some("Exception ignored on calling ctypes callback function".to_owned()),
+                    data.callable.clone(),
+                );
+            }
+        };
+        if thread::with_vm(&data.callable, call).is_none() {
+            // a thread started by the C library, which gets a vm of its own for the call like
+            // PyGILState_Ensure gives it a thread state; without threading there is no other
+            // thread that Python code could run in
+            #[cfg(feature = "threading")]
+            {
+                let thread = data.thread.lock().new_thread();
+                thread.run(call);
+            }
+        }
+    }
+
+    fn invoke_callback(
+        data: &CallbackData,
+        result: *mut u8,
+        args: *const *const c_void,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let py_args = data
+            .argtypes
+            .iter()
+            .enumerate()
+            .map(|(index, cls)| {
+                let ptr = unsafe { *args.add(index) } as *mut u8;
+                match fundamental_kind(cls, vm) {
+                    Some((kind, swapped)) => simple_get(kind, swapped, ptr, vm),
+                    None => {
+                        let info = required_info(cls, vm)?;
+                        let bytes = unsafe { slice::from_raw_parts(ptr, info.size) };
+                        let data = PyCData::from_bytes(bytes, info.align, vm)?;
+                        new_instance(cls.clone(), data, vm)
+                    }
+                }
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let value = data.callable.call(py_args, vm)?;
+        let Some(restype) = &data.restype else {
+            return Ok(());
+        };
+        let (kind, swapped) = simple_kind(restype, vm)?;
+        let mut buf = [0u8; 16];
+        if let Some(keep) = simple_set(kind, swapped, buf.as_mut_ptr(), value, vm)? {
+            // nothing can release what the result points into once C owns it
+            mem::forget(keep);
+        }
+        if kind.is_integral() && kind.size() < ARG_SIZE {
+            let widened = kind.decode_int(&buf) as u64;
+            simple::write_uint(widened, unsafe {
+                slice::from_raw_parts_mut(result, ARG_SIZE)
+            });
+        } else {
+            unsafe { ptr::copy_nonoverlapping(buf.as_ptr(), result, kind.size()) };
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn symbol_address(handle: usize, name: &str, vm: &VirtualMachine) -> PyResult<usize> {
+        let name = CString::new(name).map_err(|e| e.to_pyexception(vm))?;
+        ffi::dlsym(handle, &name).map_err(|e| vm.new_attribute_error(e))
+    }
+
+    #[cfg(windows)]
+    fn symbol_address(handle: usize, name: &str, vm: &VirtualMachine) -> PyResult<usize> {
+        let name = CString::new(name).map_err(|e| e.to_pyexception(vm))?;
+        ffi::get_proc_address(handle, Ok(&name)).map_err(|_| {
+            vm.new_attribute_error(format!("function '{}' not found", name.to_string_lossy()))
+        })
+    }
+
+    /// Find the function `(name, dll)` refers to.
+    fn lookup_function(spec: &PyTuple, vm: &VirtualMachine) -> PyResult<(usize, PyObjectRef)> {
+        let [name, dll] = spec.as_slice() else {
+            return Err(vm.new_type_error(
+                "argument must be callable or integer function address".to_owned(),
+            ));
+        };
+        let handle = dll.get_attr("_handle", vm)?;
+        let handle = address_value(&handle, vm)?;
+        #[cfg(windows)]
+        if let Some(ordinal) = name.payload::<PyInt>() {
+            let ordinal = ordinal.try_to_primitive::<u16>(vm)?;
+            let address = ffi::get_proc_address(handle, Err(ordinal)).map_err(|_| {
+                vm.new_attribute_error(format!("function ordinal {ordinal} not found"))
+            })?;
+            return Ok((address, dll.clone()));
+        }
+        let name = name.payload::<PyStr>().ok_or_else(|| {
+            vm.new_type_error("function name must be string, bytes object or integer".to_owned())
+        })?;
+        Ok((symbol_address(handle, name.as_str(), vm)?, dll.clone()))
+    }
+
+    impl Constructor for PyCFuncPtr {
+        type Args = FuncArgs;
+
+        fn py_new(cls: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let info = required_info(&cls, vm)?;
+            finalize(&cls, vm);
+            let mut func = PyCFuncPtr::new(PyCData::new(info.size, info.align, vm)?);
+            match args.args.as_slice() {
+                [] => {}
+                [arg] if arg.payload_is::<PyInt>() => {
+                    let address = address_value(arg, vm)?;
+                    unsafe { write_usize(func.cdata.ptr(), address) };
+                }
+                [arg] if arg.payload_is::<PyTuple>() => {
+                    let (address, dll) = lookup_function(arg.payload::<PyTuple>().unwrap(), vm)?;
+                    unsafe { write_usize(func.cdata.ptr(), address) };
+                    func.cdata.keep_alive(0, dll, vm)?;
+                }
+                [arg] if arg.is_callable() => {
+                    let callback = make_callback(&info, arg.clone(), vm)?;
+                    unsafe { write_usize(func.cdata.ptr(), callback.code_ptr()) };
+                    func.callback = Some(callback);
+                }
+                _ => {
+                    return Err(vm.new_type_error(
+                        "argument must be callable or integer function address".to_owned(),
+                    ))
+                }
+            }
+            func.into_ref_with_type(vm, cls).map(Into::into)
+        }
+    }
+
+    /// A converted argument, ready to be handed to libffi.
+    struct Argument {
+        ffi: FfiType,
+        value: Vec<u64>,
+        _keep: Option<PyObjectRef>,
+    }
+
+    impl Argument {
+        fn new(ffi: FfiType, bytes: &[u8], keep: Option<PyObjectRef>) -> Self {
+            let mut value = vec![0u64; (bytes.len() + 7) / 8 + 1];
+            unsafe {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), value.as_mut_ptr() as *mut u8, bytes.len())
+            };
+            Self {
+                ffi,
+                value,
+                _keep: keep,
+            }
+        }
+
+        fn pointer(address: usize, keep: Option<PyObjectRef>) -> Self {
+            Self::new(FfiType::Pointer, &address.to_ne_bytes(), keep)
+        }
+    }
+
+    fn argument_error(
+        index: usize,
+        e: PyBaseExceptionRef,
+        vm: &VirtualMachine,
+    ) -> PyBaseExceptionRef {
+        let message = e
+            .as_object()
+            .str(vm)
+            .map_or_else(|_| String::new(), |s| s.as_str().to_owned());
+        vm.new_exception_msg(
+            argument_error_type(vm),
+            format!("argument {index}: {}: {message}", e.class().name()),
+        )
+    }
+
+    /// Convert an argument that has no declared type, or the result of its `from_param()`.
+    fn convert_param(obj: &PyObjectRef, index: usize, vm: &VirtualMachine) -> PyResult<Argument> {
+        if let Some(arg) = obj.payload::<PyCArgObject>() {
+            return Ok(Argument::pointer(arg.address(), Some(obj.clone())));
+        }
+        if let Some(data) = cdata(obj) {
+            let info = stginfo(obj.class(), vm).map(|info| info.info.read().clone());
+            return Ok(match info {
+                Some(info) if !matches!(info.kind, StgKind::Array { .. }) => {
+                    Argument::new(info.ffi, &data.to_bytes()[..info.size], Some(obj.clone()))
+                }
+                // arrays decay to pointers
+                _ => Argument::pointer(data.ptr() as usize, Some(obj.clone())),
+            });
+        }
+        if vm.is_none(obj) {
+            return Ok(Argument::pointer(0, None));
+        }
+        if let Some(int) = obj.payload::<PyInt>() {
+            let value = int
+                .as_bigint()
+                .to_i64()
+                .map(|value| value as u64)
+                .or_else(|| int.as_bigint().to_u64())
+                .ok_or_else(|| vm.new_overflow_error("int too long to convert".to_owned()))?;
+            let mut bytes = [0u8; 8];
+            simple::write_uint(value, &mut bytes[..SimpleKind::Int.size()]);
+            return Ok(Argument::new(
+                FfiType::Simple(SimpleKind::Int),
+                &bytes[..SimpleKind::Int.size()],
+                None,
+            ));
+        }
+        if let Some(bytes) = obj.payload::<PyBytes>() {
+            let holder = c_string(bytes.as_bytes(), vm)?;
+            return Ok(Argument::pointer(
+                holder.ptr() as usize,
+                Some(holder.into()),
+            ));
+        }
+        if let Some(s) = obj.payload::<PyStr>() {
+            let holder = wide_string(s.as_str(), vm)?;
+            return Ok(Argument::pointer(
+                holder.ptr() as usize,
+                Some(holder.into()),
+            ));
+        }
+        if let Some(param) = vm.get_attribute_opt(obj.clone(), "_as_parameter_")? {
+            return convert_param(&param, index, vm);
+        }
+        Err(vm.new_type_error(format!("Don't know how to convert parameter {index}")))
+    }
+
+    fn convert_args(
+        args: &[PyObjectRef],
+        argtypes: Option<&[PyObjectRef]>,
+        flags: u32,
+        vm: &VirtualMachine,
+    ) -> PyResult<Vec<Argument>> {
+        if let Some(argtypes) = argtypes {
+            let required = argtypes.len();
+            let plural = if required == 1 { "" } else { "s" };
+            if args.len() < required {
+                return Err(vm.new_type_error(format!(
+                    "this function takes at least {required} argument{plural} ({} given)",
+                    args.len()
+                )));
+            }
+            if args.len() > required && flags & FUNCFLAG_CDECL == 0 {
+                return Err(vm.new_type_error(format!(
+                    "this function takes {required} argument{plural} ({} given)",
+                    args.len()
+                )));
+            }
+        }
+        args.iter()
+            .enumerate()
+            .map(|(index, arg)| {
+                let converted = match argtypes.and_then(|argtypes| argtypes.get(index)) {
+                    Some(argtype) => vm.call_method(argtype, "from_param", (arg.clone(),)),
+                    None => Ok(arg.clone()),
+                };
+                converted
+                    .and_then(|arg| convert_param(&arg, index + 1, vm))
+                    .map_err(|e| argument_error(index + 1, e, vm))
+            })
+            .collect()
+    }
+
+    /// libffi widens integer results narrower than a register to `ffi_arg`.
+    fn narrow_result(kind: SimpleKind, buf: &[u8]) -> [u8; 16] {
+        let mut out = [0u8; 16];
+        let size = kind.size();
+        if kind.is_integral() && size < ARG_SIZE {
+            simple::write_uint(simple::read_uint(&buf[..ARG_SIZE]), &mut out[..size]);
+        } else {
+            out[..size].copy_from_slice(&buf[..size]);
+        }
+        out
+    }
+
+    fn convert_result(
+        restype: Option<&PyObjectRef>,
+        buf: &[u8],
+        flags: u32,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let int_result = || {
+            simple_get(
+                SimpleKind::Int,
+                false,
+                narrow_result(SimpleKind::Int, buf).as_ptr(),
+                vm,
+            )
+        };
+        let restype = match restype {
+            Some(restype) if vm.is_none(restype) => return Ok(vm.ctx.none()),
+            Some(restype) => restype,
+            None => return int_result(),
+        };
+        let Some((cls, info)) = type_info(restype, vm) else {
+            // any other callable is handed the result as a C int
+            return restype.call((int_result()?,), vm);
+        };
+        let value = match info.kind {
+            StgKind::Simple { kind, swapped } => {
+                let bytes = narrow_result(kind, buf);
+                if kind == SimpleKind::Object && flags & FUNCFLAG_PYTHONAPI != 0 {
+                    // the helpers behind PYFUNCTYPE hand over a new reference
+                    let obj = unsafe { read_usize(bytes.as_ptr()) } as *const PyObject;
+                    if obj.is_null() {
+                        return Err(vm.new_system_error("NULL result without error".to_owned()));
+                    }
+                    unsafe { PyObjectRef::from_raw(obj) }
+                } else if fundamental_kind(&cls, vm).is_some() {
+                    simple_get(kind, swapped, bytes.as_ptr(), vm)?
+                } else {
+                    let data = PyCData::from_bytes(&bytes[..info.size], info.align, vm)?;
+                    new_instance(cls.clone(), data, vm)?
+                }
+            }
+            _ => {
+                let data = PyCData::from_bytes(&buf[..info.size], info.align, vm)?;
+                new_instance(cls.clone(), data, vm)?
+            }
+        };
+        match vm.get_attribute_opt(cls.into(), "_check_retval_")? {
+            Some(check) => check.call((value,), vm),
+            None => Ok(value),
+        }
+    }
+
+    fn call_function(
+        address: usize,
+        args: &mut [Argument],
+        restype: Option<&PyObjectRef>,
+        flags: u32,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let result_info = restype.and_then(|restype| type_info(restype, vm));
+        let result_ffi = match (restype, &result_info) {
+            (Some(restype), _) if vm.is_none(restype) => FfiType::Void,
+            (_, Some((_, info))) => info.ffi.clone(),
+            _ => FfiType::Simple(SimpleKind::Int),
+        };
+        let result_size = result_info.map_or(0, |(_, info)| info.size).max(16);
+        let mut result = vec![0u64; result_size / 8 + 1];
+        let arg_types: Vec<FfiType> = args.iter().map(|arg| arg.ffi.clone()).collect();
+        let mut values: Vec<*mut c_void> = args
+            .iter_mut()
+            .map(|arg| arg.value.as_mut_ptr() as *mut c_void)
+            .collect();
+
+        let use_errno = flags & FUNCFLAG_USE_ERRNO != 0;
+        #[cfg(windows)]
+        let use_last_error = flags & FUNCFLAG_USE_LASTERROR != 0;
+        if use_errno {
+            swap_errno();
+        }
+        #[cfg(windows)]
+        if use_last_error {
+            swap_last_error();
+        }
+        unsafe {
+            ffi::call(
+                address,
+                &arg_types,
+                &mut values,
+                &result_ffi,
+                result.as_mut_ptr() as *mut c_void,
+            )
+        };
+        #[cfg(windows)]
+        if use_last_error {
+            swap_last_error();
+        }
+        if use_errno {
+            swap_errno();
+        }
+
+        if flags & FUNCFLAG_PYTHONAPI != 0 {
+            if let Some(e) = PENDING_ERROR.with(|pending| pending.borrow_mut().take()) {
+                return Err(e);
+            }
+        }
+        let bytes =
+            unsafe { slice::from_raw_parts(result.as_ptr() as *const u8, result.len() * 8) };
+        #[cfg(windows)]
+        if flags & FUNCFLAG_HRESULT != 0 {
+            _check_HRESULT(i32::from_ne_bytes(bytes[..4].try_into().unwrap()), vm)?;
+        }
+        convert_result(restype, bytes, flags, vm)
+    }
+
+    impl Callable for PyCFuncPtr {
+        type Args = FuncArgs;
+
+        fn call(zelf: &Py<Self>, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            let StgKind::Function {
+                argtypes,
+                restype,
+                flags,
+            } = required_info(zelf.class(), vm)?.kind
+            else {
+                return Err(vm.new_type_error("abstract class".to_owned()));
+            };
+            if !args.kwargs.is_empty() {
+                return Err(
+                    vm.new_type_error("this function takes no keyword arguments".to_owned())
+                );
+            }
+            let address = unsafe { read_usize(zelf.cdata.ptr()) };
+            if address == 0 {
+                return Err(vm.new_value_error("NULL function pointer".to_owned()));
+            }
+            let argtypes = zelf.argtypes.read().clone().or(argtypes);
+            let restype = zelf.restype.read().clone().or(restype);
+            let mut arguments = convert_args(&args.args, argtypes.as_deref(), flags, vm)?;
+            let result = call_function(address, &mut arguments, restype.as_ref(), flags, vm)?;
+            let errcheck = zelf.errcheck.read().clone();
+            match errcheck {
+                Some(errcheck) => {
+                    errcheck.call((result, zelf.to_owned(), vm.ctx.new_tuple(args.args)), vm)
+                }
+                None => Ok(result),
+            }
+        }
+    }
+
+    impl AsBuffer for PyCFuncPtr {
+        fn as_buffer(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyBuffer> {
+            Ok(as_buffer(zelf.as_object(), &zelf.cdata))
+        }
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor, Callable, AsBuffer))]
+    impl PyCFuncPtr {
+        #[pygetset]
+        fn argtypes(zelf: &Py<Self>, vm: &VirtualMachine) -> PyObjectRef {
+            let argtypes = zelf.argtypes.read().clone().or_else(|| {
+                match stginfo(zelf.class(), vm)?.info.read().kind.clone() {
+                    StgKind::Function { argtypes, .. } => argtypes,
+                    _ => None,
+                }
+            });
+            argtypes.map_or_else(
+                || vm.ctx.none(),
+                |argtypes| vm.ctx.new_tuple(argtypes).into(),
+            )
+        }
+
+        #[pygetset(setter)]
+        fn set_argtypes(&self, value: PySetterValue, vm: &VirtualMachine) -> PyResult<()> {
+            *self.argtypes.write() = match value {
+                PySetterValue::Assign(value) if !vm.is_none(&value) => {
+                    Some(check_argtypes(value, vm)?)
+                }
+                _ => None,
+            };
+            Ok(())
+        }
+
+        #[pygetset]
+        fn restype(zelf: &Py<Self>, vm: &VirtualMachine) -> PyObjectRef {
+            let restype = zelf.restype.read().clone().or_else(|| {
+                match stginfo(zelf.class(), vm)?.info.read().kind.clone() {
+                    StgKind::Function { restype, .. } => restype,
+                    _ => None,
+                }
+            });
+            restype.unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset(setter)]
+        fn set_restype(&self, value: PySetterValue, vm: &VirtualMachine) -> PyResult<()> {
+            *self.restype.write() = match value {
+                PySetterValue::Assign(value) => Some(check_restype(value, vm)?),
+                PySetterValue::Delete => None,
+            };
+            Ok(())
+        }
+
+        #[pygetset]
+        fn errcheck(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.errcheck
+                .read()
+                .clone()
+                .unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pygetset(setter)]
+        fn set_errcheck(&self, value: PySetterValue, vm: &VirtualMachine) -> PyResult<()> {
+            *self.errcheck.write() = match value {
+                PySetterValue::Assign(value) if !vm.is_none(&value) => {
+                    if !value.is_callable() {
+                        return Err(
+                            vm.new_type_error("the errcheck attribute must be callable".to_owned())
+                        );
+                    }
+                    Some(value)
+                }
+                _ => None,
+            };
+            Ok(())
+        }
+
+        #[pymethod(magic)]
+        fn bool(&self) -> bool {
+            unsafe { read_usize(self.cdata.ptr()) != 0 }
+        }
+
+        #[pymethod(magic)]
+        fn repr(zelf: &Py<Self>) -> String {
+            format!("<{} object at {:#x}>", zelf.class().name(), zelf.get_id())
+        }
+    }
+
+    // byref() results
+
+    #[pyclass(no_attr, module = "_ctypes", name = "CArgObject")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyCArgObject {
+        obj: PyObjectRef,
+        offset: isize,
+    }
+
+    impl PyCArgObject {
+        fn address(&self) -> usize {
+            let base = cdata(&self.obj).map_or(0, |data| data.ptr() as usize);
+            base.wrapping_add_signed(self.offset)
+        }
+    }
+
+    impl Representable for PyCArgObject {
+        fn repr_str(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<String> {
+            Ok(format!("<cparam 'P' ({:#x})>", zelf.address()))
+        }
+    }
+
+    #[pyclass(with(Representable))]
+    impl PyCArgObject {
+        #[pygetset(name = "_obj")]
+        fn obj(&self) -> PyObjectRef {
+            self.obj.clone()
+        }
+    }
+
+    // Module functions
+
+    #[pyfunction]
+    fn sizeof(obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<usize> {
+        if let Some((_, info)) = type_info(&obj, vm) {
+            return Ok(info.size);
+        }
+        if let Some(data) = cdata(&obj) {
+            return Ok(data.size());
+        }
+        Err(vm.new_type_error("this type has no size".to_owned()))
+    }
+
+    #[pyfunction]
+    fn alignment(obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<usize> {
+        type_info(&obj, vm)
+            .or_else(|| type_info(obj.class().as_object(), vm))
+            .map(|(_, info)| info.align)
+            .ok_or_else(|| vm.new_type_error("no alignment info".to_owned()))
+    }
+
+    #[pyfunction]
+    fn byref(
+        obj: PyObjectRef,
+        offset: OptionalArg<isize>,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyCArgObject> {
+        if cdata(&obj).is_none() {
+            return Err(vm.new_type_error(format!(
+                "byref() argument must be a ctypes instance, not '{}'",
+                obj.class().name()
+            )));
+        }
+        Ok(PyCArgObject {
+            obj,
+            offset: offset.unwrap_or(0),
+        })
+    }
+
+    #[pyfunction]
+    fn addressof(obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<usize> {
+        cdata(&obj)
+            .map(|data| data.ptr() as usize)
+            .ok_or_else(|| vm.new_type_error("invalid type".to_owned()))
+    }
+
+    #[pyfunction(name = "POINTER")]
+    fn pointer_type(cls: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        let cache = module_dict("_pointer_type_cache", vm)?;
+        if let Some(pointer) = cache.get_item_opt(&*cls, vm)? {
+            return Ok(pointer);
+        }
+        let metatype = PyCPointerType::static_type().as_object();
+        let bases = (PyCPointer::static_type().to_owned(),);
+        let dict = vm.ctx.new_dict();
+        let (pointer, key) = if let Some(name) = cls.payload::<PyStr>() {
+            // an incomplete pointer type, whose target is filled in by set_type() later
+            let pointer = metatype.call((format!("LP_{name}"), bases, dict), vm)?;
+            let key = vm.ctx.new_int(pointer.get_id()).into();
+            (pointer, key)
+        } else if let Some(target) = cls.payload::<PyType>() {
+            dict.set_item("_type_", cls.clone(), vm)?;
+            let pointer = metatype.call((format!("LP_{}", target.name()), bases, dict), vm)?;
+            (pointer, cls)
+        } else {
+            return Err(vm.new_type_error("must be a ctypes type".to_owned()));
+        };
+        cache.set_item(&*key, pointer.clone(), vm)?;
+        Ok(pointer)
+    }
+
+    #[pyfunction]
+    fn pointer(obj: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        let pointer = pointer_type(obj.class().to_owned().into(), vm)?;
+        pointer.call((obj,), vm)
+    }
+
+    #[pyfunction]
+    fn resize(obj: PyObjectRef, size: isize, vm: &VirtualMachine) -> PyResult<()> {
+        let data =
+            cdata(&obj).ok_or_else(|| vm.new_type_error("expected ctypes instance".to_owned()))?;
+        let info = required_info(obj.class(), vm)?;
+        let size = usize::try_from(size)
+            .ok()
+            .filter(|&size| size >= info.size)
+            .ok_or_else(|| vm.new_value_error(format!("minimum size is {}", info.size)))?;
+        let mut memory = data.memory.write();
+        let Some(layout) = memory.layout else {
+            return Err(vm.new_value_error(
+                "Memory cannot be resized because this object doesn't own it".to_owned(),
+            ));
+        };
+        let resized = Memory::alloc(size, layout.align(), vm)?;
+        unsafe { ptr::copy_nonoverlapping(memory.ptr, resized.ptr, memory.size.min(size)) };
+        *memory = resized;
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn get_errno() -> i32 {
+        CTYPES_ERRNO.with(Cell::get)
+    }
+
+    #[pyfunction]
+    fn set_errno(value: i32) -> i32 {
+        CTYPES_ERRNO.with(|errno| errno.replace(value))
+    }
+
+    #[pyfunction]
+    fn _unpickle(cls: PyTypeRef, state: PyTupleRef, vm: &VirtualMachine) -> PyResult {
+        let obj = vm.call_method(cls.as_object(), "__new__", (cls.clone(),))?;
+        vm.call_method(&obj, "__setstate__", state.as_slice().to_vec())?;
+        Ok(obj)
+    }
+
+    #[cfg(unix)]
+    #[pyfunction]
+    fn dlopen(
+        name: Option<PyObjectRef>,
+        mode: OptionalArg<i32>,
+        vm: &VirtualMachine,
+    ) -> PyResult<usize> {
+        let name = name
+            .map(|name| {
+                let path = crate::vm::ospath::OsPath::try_from_object(vm, name)?;
+                path.into_cstring(vm)
+            })
+            .transpose()?;
+        let mode = mode.unwrap_or(libc::RTLD_NOW | libc::RTLD_LOCAL) | libc::RTLD_NOW;
+        ffi::dlopen(name.as_deref(), mode).map_err(|e| vm.new_os_error(e))
+    }
+
+    #[cfg(unix)]
+    #[pyfunction]
+    fn dlclose(handle: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        ffi::dlclose(address_value(&handle, vm)?).map_err(|e| vm.new_os_error(e))
+    }
+
+    #[cfg(unix)]
+    #[pyfunction]
+    fn dlsym(handle: PyObjectRef, name: PyStrRef, vm: &VirtualMachine) -> PyResult<usize> {
+        let handle = address_value(&handle, vm)?;
+        let name = CString::new(name.as_str()).map_err(|e| e.to_pyexception(vm))?;
+        ffi::dlsym(handle, &name).map_err(|e| vm.new_os_error(e))
+    }
+
+    #[cfg(windows)]
+    #[pyfunction(name = "LoadLibrary")]
+    fn load_library(
+        name: PyStrRef,
+        load_flags: OptionalArg<u32>,
+        vm: &VirtualMachine,
+    ) -> PyResult<usize> {
+        ffi::load_library(name.as_str(), load_flags.unwrap_or(0)).map_err(|e| e.to_pyexception(vm))
+    }
+
+    #[cfg(windows)]
+    #[pyfunction(name = "FreeLibrary")]
+    fn free_library(handle: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        ffi::free_library(address_value(&handle, vm)?).map_err(|e| e.to_pyexception(vm))
+    }
+
+    #[cfg(windows)]
+    #[pyfunction(name = "FormatError")]
+    fn format_error(code: OptionalArg<i32>) -> String {
+        let code =
+            code.unwrap_or_else(|| std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+        std::io::Error::from_raw_os_error(code).to_string()
+    }
+
+    #[cfg(windows)]
+    #[pyfunction]
+    fn get_last_error() -> u32 {
+        CTYPES_LAST_ERROR.with(Cell::get)
+    }
+
+    #[cfg(windows)]
+    #[pyfunction]
+    fn set_last_error(value: u32) -> u32 {
+        CTYPES_LAST_ERROR.with(|error| error.replace(value))
+    }
+
+    #[cfg(windows)]
+    #[pyfunction]
+    #[allow(non_snake_case)]
+    fn _check_HRESULT(value: i32, vm: &VirtualMachine) -> PyResult<i32> {
+        if value < 0 {
+            Err(std::io::Error::from_raw_os_error(value).to_pyexception(vm))
+        } else {
+            Ok(value)
+        }
+    }
+
+    // Helpers that `ctypes` calls through PYFUNCTYPE, which report errors through
+    // PENDING_ERROR.
+
+    fn api_result(f: impl FnOnce(&VirtualMachine) -> PyResult) -> *const PyObject {
+        thread::with_current_vm(|vm| match f(vm) {
+            Ok(obj) => obj.into_raw(),
+            Err(e) => {
+                PENDING_ERROR.with(|pending| *pending.borrow_mut() = Some(e));
+                ptr::null()
+            }
+        })
+    }
+
+    extern "C" fn string_at(ptr: *const u8, size: c_int) -> *const PyObject {
+        api_result(|vm| {
+            if ptr.is_null() {
+                return Err(vm.new_value_error("NULL pointer access".to_owned()));
+            }
+            let bytes = unsafe { simple::c_string_at(ptr, usize::try_from(size).ok()) };
+            Ok(vm.ctx.new_bytes(bytes).into())
+        })
+    }
+
+    extern "C" fn wstring_at(ptr: *const WChar, size: c_int) -> *const PyObject {
+        api_result(|vm| {
+            if ptr.is_null() {
+                return Err(vm.new_value_error("NULL pointer access".to_owned()));
+            }
+            let s = unsafe { simple::wide_string_at(ptr, usize::try_from(size).ok()) };
+            Ok(vm.ctx.new_str(s).into())
+        })
+    }
+
+    extern "C" fn cast(
+        ptr: *mut c_void,
+        src: *const PyObject,
+        ctype: *const PyObject,
+    ) -> *const PyObject {
+        api_result(|vm| {
+            let src = unsafe { &*src }.to_owned();
+            let ctype = unsafe { &*ctype };
+            let is_pointer = type_info(ctype, vm).map_or(false, |(_, info)| match info.kind {
+                StgKind::Pointer { .. } | StgKind::Function { .. } => true,
+                StgKind::Simple { kind, .. } => matches!(
+                    kind,
+                    SimpleKind::CharP | SimpleKind::WCharP | SimpleKind::VoidP | SimpleKind::Object
+                ),
+                _ => false,
+            });
+            if !is_pointer {
+                return Err(vm.new_type_error(format!(
+                    "cast() argument 2 must be a pointer type, not {}",
+                    ctype.downcast_ref::<PyType>().map_or_else(
+                        || ctype.class().name().to_owned(),
+                        |cls| cls.name().to_owned()
+                    )
+                )));
+            }
+            let result = ctype.call((), vm)?;
+            let data =
+                cdata(&result).ok_or_else(|| vm.new_type_error("invalid type".to_owned()))?;
+            if cdata(&src).is_some() {
+                data.keep_alive(src.get_id(), src, vm)?;
+            }
+            unsafe { write_usize(data.ptr(), ptr as usize) };
+            Ok(result)
+        })
+    }
+
+    #[pyattr]
+    fn _memmove_addr(_vm: &VirtualMachine) -> usize {
+        libc::memmove as *const () as usize
+    }
+
+    #[pyattr]
+    fn _memset_addr(_vm: &VirtualMachine) -> usize {
+        libc::memset as *const () as usize
+    }
+
+    #[pyattr]
+    fn _string_at_addr(_vm: &VirtualMachine) -> usize {
+        string_at as *const () as usize
+    }
+
+    #[pyattr]
+    fn _wstring_at_addr(_vm: &VirtualMachine) -> usize {
+        wstring_at as *const () as usize
+    }
+
+    #[pyattr]
+    fn _cast_addr(_vm: &VirtualMachine) -> usize {
+        cast as *const () as usize
+    }
+}
@@ -0,0 +1,236 @@
+//! Dynamic loading and libffi glue for `_ctypes`.
+
+use super::simple::SimpleKind;
+use libffi::{
+    low,
+    middle::{Cif, Type},
+};
+use std::ffi::{c_int, c_void, CStr};
+
+/// How a C type is passed to and returned from foreign functions.
+#[derive(Debug, Clone)]
+pub(super) enum FfiType {
+    Void,
+    Simple(SimpleKind),
+    Pointer,
+    Struct(Vec<FfiType>),
+    /// `length` elements of one type. libffi has no array type, so this becomes a structure
+    /// only when it is passed to libffi.
+    Array(Box<FfiType>, usize),
+}
+
+impl FfiType {
+    pub(super) fn to_libffi(&self) -> Type {
+        match self {
+            Self::Void => Type::void(),
+            Self::Simple(kind) => kind.ffi_type(),
+            Self::Pointer => Type::pointer(),
+            Self::Struct(fields) => Type::structure(fields.iter().map(Self::to_libffi)),
+            Self::Array(item, length) => {
+                Type::structure(std::iter::repeat(item.to_libffi()).take(*length))
+            }
+        }
+    }
+}
+
+/// Call the C function at `code`.
+///
+/// # Safety
+///
+/// `code` must be a function taking `args` and returning `result`, each argument pointer
+/// must point at a value of its type, and `result_buf` must be large enough to hold the
+/// (possibly widened) result.
+pub(super) unsafe fn call(
+    code: usize,
+    args: &[FfiType],
+    arg_values: &mut [*mut c_void],
+    result: &FfiType,
+    result_buf: *mut c_void,
+) {
+    let cif = Cif::new(args.iter().map(FfiType::to_libffi), result.to_libffi());
+    let code: unsafe extern "C" fn() = std::mem::transmute(code as *const c_void);
+    libffi::raw::ffi_call(
+        cif.as_raw_ptr(),
+        Some(code),
+        result_buf,
+        arg_values.as_mut_ptr(),
+    );
+}
+
+/// A C function pointer that forwards its calls, along with `U`, to a Rust callback.
+pub(super) struct Closure<U> {
+    // boxed so that their addresses, which the closure refers to, stay put
+    _cif: Box<Cif>,
+    _userdata: Box<U>,
+    closure: *mut low::ffi_closure,
+    code: low::CodePtr,
+}
+
+// the closure is only written once, when it is prepared
+unsafe impl<U: Send> Send for Closure<U> {}
+unsafe impl<U: Sync> Sync for Closure<U> {}
+
+impl<U> Closure<U> {
+    pub(super) fn new(
+        args: &[FfiType],
+        result: &FfiType,
+        callback: low::Callback<U, u8>,
+        userdata: U,
+    ) -> Result<Self, String> {
+        let cif = Box::new(Cif::new(
+            args.iter().map(FfiType::to_libffi),
+            result.to_libffi(),
+        ));
+        let userdata = Box::new(userdata);
+        let (closure, code) = low::closure_alloc();
+        if closure.is_null() {
+            return Err("cannot allocate a callback closure".to_owned());
+        }
+        let prepared =
+            unsafe { low::prep_closure(closure, cif.as_raw_ptr(), callback, &*userdata, code) };
+        if prepared.is_err() {
+            unsafe { low::closure_free(closure) };
+            return Err("cannot prepare a callback closure".to_owned());
+        }
+        Ok(Self {
+            _cif: cif,
+            _userdata: userdata,
+            closure,
+            code,
+        })
+    }
+
+    pub(super) fn code_ptr(&self) -> usize {
+        self.code.0 as usize
+    }
+}
+
+impl<U> Drop for Closure<U> {
+    fn drop(&mut self) {
+        unsafe { low::closure_free(self.closure) };
+    }
+}
+
+impl<U> std::fmt::Debug for Closure<U> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Closure({:#x})", self.code_ptr())
+    }
+}
+
+extern "C" {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "redox",
+        target_os = "fuchsia",
+        target_os = "dragonfly"
+    ))]
+    #[link_name = "__errno_location"]
+    fn errno_location() -> *mut c_int;
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    #[link_name = "__errno"]
+    fn errno_location() -> *mut c_int;
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+    #[link_name = "__error"]
+    fn errno_location() -> *mut c_int;
+    #[cfg(windows)]
+    #[link_name = "_errno"]
+    fn errno_location() -> *mut c_int;
+}
+
+pub(super) fn errno() -> i32 {
+    unsafe { *errno_location() }
+}
+
+pub(super) fn set_errno(value: i32) {
+    unsafe { *errno_location() = value }
+}
+
+#[cfg(unix)]
+fn dlerror_message(message: *const std::ffi::c_char) -> String {
+    if message.is_null() {
+        "unknown dlopen() error".to_owned()
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(unix)]
+fn dlerror() -> String {
+    dlerror_message(unsafe { libc::dlerror() })
+}
+
+/// Load a shared library, or get a handle to the program itself when `name` is `None`.
+#[cfg(unix)]
+pub(super) fn dlopen(name: Option<&CStr>, mode: i32) -> Result<usize, String> {
+    let name = name.map_or(std::ptr::null(), CStr::as_ptr);
+    let handle = unsafe { libc::dlopen(name, mode) };
+    if handle.is_null() {
+        Err(dlerror())
+    } else {
+        Ok(handle as usize)
+    }
+}
+
+#[cfg(unix)]
+pub(super) fn dlsym(handle: usize, name: &CStr) -> Result<usize, String> {
+    // a symbol may legitimately be NULL, so check dlerror() rather than the result
+    unsafe { libc::dlerror() };
+    let address = unsafe { libc::dlsym(handle as *mut c_void, name.as_ptr()) };
+    if address.is_null() {
+        let message = unsafe { libc::dlerror() };
+        if !message.is_null() {
+            return Err(dlerror_message(message));
+        }
+    }
+    Ok(address as usize)
+}
+
+#[cfg(unix)]
+pub(super) fn dlclose(handle: usize) -> Result<(), String> {
+    if unsafe { libc::dlclose(handle as *mut c_void) } == 0 {
+        Ok(())
+    } else {
+        Err(dlerror())
+    }
+}
+
+#[cfg(windows)]
+pub(super) fn load_library(name: &str, flags: u32) -> Result<usize, std::io::Error> {
+    use windows_sys::Win32::System::LibraryLoader::LoadLibraryExW;
+    let name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let handle = unsafe { LoadLibraryExW(name.as_ptr(), 0, flags) };
+    if handle == 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(handle as usize)
+    }
+}
+
+#[cfg(windows)]
+pub(super) fn free_library(handle: usize) -> Result<(), std::io::Error> {
+    use windows_sys::Win32::System::LibraryLoader::FreeLibrary;
+    if unsafe { FreeLibrary(handle as _) } == 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Look up an exported function by name or, when `name` is `Err`, by ordinal.
+#[cfg(windows)]
+pub(super) fn get_proc_address(
+    handle: usize,
+    name: Result<&CStr, u16>,
+) -> Result<usize, std::io::Error> {
+    use windows_sys::Win32::System::LibraryLoader::GetProcAddress;
+    let name = match name {
+        Ok(name) => name.as_ptr() as *const u8,
+        Err(ordinal) => ordinal as usize as *const u8,
+    };
+    match unsafe { GetProcAddress(handle as _, name) } {
+        Some(address) => Ok(address as usize),
+        None => Err(std::io::Error::last_os_error()),
+    }
+}
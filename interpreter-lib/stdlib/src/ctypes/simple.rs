@@ -0,0 +1,283 @@
+//! The fundamental C types behind `_SimpleCData`, identified by their `_type_` code.
+
+use libffi::middle::Type;
+use std::{
+    ffi::{c_char, c_double, c_float, c_int, c_long, c_longlong, c_short, c_void},
+    mem,
+};
+
+/// Every `_type_` code a `_SimpleCData` subclass may use.
+pub(super) const TYPE_CODES: &str = "cbBhHiIlLdfuzZqQPO?g";
+
+pub(super) type WChar = libc::wchar_t;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SimpleKind {
+    Char,
+    Byte,
+    UByte,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+    Float,
+    Double,
+    /// `long double`, stored as a `double`.
+    LongDouble,
+    Bool,
+    WChar,
+    CharP,
+    WCharP,
+    VoidP,
+    Object,
+}
+
+impl SimpleKind {
+    pub(super) fn from_code(code: char) -> Option<Self> {
+        Some(match code {
+            'c' => Self::Char,
+            'b' => Self::Byte,
+            'B' => Self::UByte,
+            'h' => Self::Short,
+            'H' => Self::UShort,
+            'i' => Self::Int,
+            'I' => Self::UInt,
+            'l' => Self::Long,
+            'L' => Self::ULong,
+            'q' => Self::LongLong,
+            'Q' => Self::ULongLong,
+            'f' => Self::Float,
+            'd' => Self::Double,
+            'g' => Self::LongDouble,
+            '?' => Self::Bool,
+            'u' => Self::WChar,
+            'z' => Self::CharP,
+            'Z' => Self::WCharP,
+            'P' => Self::VoidP,
+            'O' => Self::Object,
+            _ => return None,
+        })
+    }
+
+    pub(super) fn code(self) -> char {
+        match self {
+            Self::Char => 'c',
+            Self::Byte => 'b',
+            Self::UByte => 'B',
+            Self::Short => 'h',
+            Self::UShort => 'H',
+            Self::Int => 'i',
+            Self::UInt => 'I',
+            Self::Long => 'l',
+            Self::ULong => 'L',
+            Self::LongLong => 'q',
+            Self::ULongLong => 'Q',
+            Self::Float => 'f',
+            Self::Double => 'd',
+            Self::LongDouble => 'g',
+            Self::Bool => '?',
+            Self::WChar => 'u',
+            Self::CharP => 'z',
+            Self::WCharP => 'Z',
+            Self::VoidP => 'P',
+            Self::Object => 'O',
+        }
+    }
+
+    pub(super) fn size(self) -> usize {
+        match self {
+            Self::Char | Self::Byte | Self::UByte | Self::Bool => 1,
+            Self::Short | Self::UShort => mem::size_of::<c_short>(),
+            Self::Int | Self::UInt => mem::size_of::<c_int>(),
+            Self::Long | Self::ULong => mem::size_of::<c_long>(),
+            Self::LongLong | Self::ULongLong => mem::size_of::<c_longlong>(),
+            Self::Float => mem::size_of::<c_float>(),
+            Self::Double | Self::LongDouble => mem::size_of::<c_double>(),
+            Self::WChar => mem::size_of::<WChar>(),
+            Self::CharP | Self::WCharP | Self::VoidP | Self::Object => {
+                mem::size_of::<*const c_void>()
+            }
+        }
+    }
+
+    pub(super) fn align(self) -> usize {
+        match self {
+            Self::Char | Self::Byte | Self::UByte | Self::Bool => 1,
+            Self::Short | Self::UShort => mem::align_of::<c_short>(),
+            Self::Int | Self::UInt => mem::align_of::<c_int>(),
+            Self::Long | Self::ULong => mem::align_of::<c_long>(),
+            Self::LongLong | Self::ULongLong => mem::align_of::<c_longlong>(),
+            Self::Float => mem::align_of::<c_float>(),
+            Self::Double | Self::LongDouble => mem::align_of::<c_double>(),
+            Self::WChar => mem::align_of::<WChar>(),
+            Self::CharP | Self::WCharP | Self::VoidP | Self::Object => {
+                mem::align_of::<*const c_void>()
+            }
+        }
+    }
+
+    pub(super) fn ffi_type(self) -> Type {
+        match self {
+            Self::Char => Type::c_schar(),
+            Self::Byte => Type::i8(),
+            Self::UByte | Self::Bool => Type::u8(),
+            Self::Short => Type::c_short(),
+            Self::UShort => Type::c_ushort(),
+            Self::Int => Type::c_int(),
+            Self::UInt => Type::c_uint(),
+            Self::Long => Type::c_long(),
+            Self::ULong => Type::c_ulong(),
+            Self::LongLong => Type::c_longlong(),
+            Self::ULongLong => Type::c_ulonglong(),
+            Self::Float => Type::f32(),
+            Self::Double | Self::LongDouble => Type::f64(),
+            Self::WChar if mem::size_of::<WChar>() == 2 => Type::u16(),
+            Self::WChar => Type::i32(),
+            Self::CharP | Self::WCharP | Self::VoidP | Self::Object => Type::pointer(),
+        }
+    }
+
+    /// Whether values of this kind are integers in C, and so are widened to `ffi_arg`
+    /// when returned through libffi.
+    pub(super) fn is_integral(self) -> bool {
+        !matches!(
+            self,
+            Self::Float
+                | Self::Double
+                | Self::LongDouble
+                | Self::CharP
+                | Self::WCharP
+                | Self::VoidP
+                | Self::Object
+        )
+    }
+
+    /// Whether a structure may declare a bit field of this kind.
+    pub(super) fn allows_bitfield(self) -> bool {
+        self.is_integral() && !matches!(self, Self::Char | Self::WChar)
+    }
+
+    pub(super) fn is_signed(self) -> bool {
+        match self {
+            Self::Byte | Self::Short | Self::Int | Self::Long | Self::LongLong => true,
+            Self::Char => c_char::MIN != 0,
+            Self::WChar => WChar::MIN != 0,
+            _ => false,
+        }
+    }
+
+    /// Decode a native-endian integer of this kind, extending its sign as needed.
+    pub(super) fn decode_int(self, bytes: &[u8]) -> i128 {
+        let raw = read_uint(&bytes[..self.size()]);
+        if self.is_signed() {
+            sign_extend(raw, self.size() as u32 * 8) as i128
+        } else {
+            raw as i128
+        }
+    }
+}
+
+/// Read an unsigned native-endian integer of up to eight bytes.
+pub(super) fn read_uint(bytes: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    if cfg!(target_endian = "little") {
+        buf[..bytes.len()].copy_from_slice(bytes);
+    } else {
+        buf[8 - bytes.len()..].copy_from_slice(bytes);
+    }
+    u64::from_ne_bytes(buf)
+}
+
+/// Write the low bytes of `value` into `out`, native-endian.
+pub(super) fn write_uint(value: u64, out: &mut [u8]) {
+    let buf = value.to_ne_bytes();
+    let len = out.len();
+    if cfg!(target_endian = "little") {
+        out.copy_from_slice(&buf[..len]);
+    } else {
+        out.copy_from_slice(&buf[8 - len..]);
+    }
+}
+
+fn sign_extend(value: u64, bits: u32) -> i64 {
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+fn bit_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Extract a `bits` wide field starting `shift` bits into `storage`.
+pub(super) fn get_bits(storage: u64, bits: u32, shift: u32, signed: bool) -> i128 {
+    let value = (storage >> shift) & bit_mask(bits);
+    if signed {
+        sign_extend(value, bits) as i128
+    } else {
+        value as i128
+    }
+}
+
+/// Replace a `bits` wide field starting `shift` bits into `storage` with `value`.
+pub(super) fn set_bits(storage: u64, value: u64, bits: u32, shift: u32) -> u64 {
+    let mask = bit_mask(bits) << shift;
+    (storage & !mask) | ((value << shift) & mask)
+}
+
+#[cfg(windows)]
+pub(super) fn encode_wide(s: &str) -> Vec<WChar> {
+    s.encode_utf16().collect()
+}
+
+#[cfg(not(windows))]
+pub(super) fn encode_wide(s: &str) -> Vec<WChar> {
+    s.chars().map(|c| c as WChar).collect()
+}
+
+#[cfg(windows)]
+pub(super) fn decode_wide(units: &[WChar]) -> String {
+    String::from_utf16_lossy(units)
+}
+
+#[cfg(not(windows))]
+pub(super) fn decode_wide(units: &[WChar]) -> String {
+    units
+        .iter()
+        .map(|&unit| char::from_u32(unit as u32).unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Copy `size` bytes from `ptr`, or up to its NUL terminator when `size` is `None`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for the bytes read.
+pub(super) unsafe fn c_string_at(ptr: *const u8, size: Option<usize>) -> Vec<u8> {
+    let len = size.unwrap_or_else(|| libc::strlen(ptr as *const c_char));
+    std::slice::from_raw_parts(ptr, len).to_vec()
+}
+
+/// Decode `size` wide characters from `ptr`, or up to its NUL terminator when `size` is
+/// `None`.
+///
+/// # Safety
+///
+/// `ptr` must be valid for the characters read.
+pub(super) unsafe fn wide_string_at(ptr: *const WChar, size: Option<usize>) -> String {
+    let len = size.unwrap_or_else(|| {
+        let mut len = 0;
+        while *ptr.add(len) != 0 {
+            len += 1;
+        }
+        len
+    });
+    decode_wide(std::slice::from_raw_parts(ptr, len))
+}
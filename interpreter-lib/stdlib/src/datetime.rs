@@ -0,0 +1,2833 @@
+// spell-checker:ignore isoformat isocalendar isoweekday fromisoformat fromisocalendar timespec
+// spell-checker:ignore fromordinal toordinal fromtimestamp utcfromtimestamp utcnow fromutc tzname
+// spell-checker:ignore ctime strftime strptime timetuple utctimetuple astimezone getinitargs gmtoff
+
+use crate::vm::{builtins::PyModule, PyRef, VirtualMachine};
+
+pub(crate) mod calendar;
+
+pub(crate) use _datetime::{init_classes, PyDateTime, PyTimeDelta, PyTzInfo};
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyRef<PyModule> {
+    let module = _datetime::make_module(vm);
+    _datetime::init_class_attrs(vm);
+    module
+}
+
+#[pymodule]
+mod _datetime {
+    use super::calendar::{self, IsoError, TimeSpec, US_PER_DAY, US_PER_SECOND};
+    use crate::common::hash::{self, PyHash};
+    use crate::vm::{
+        builtins::{
+            try_f64_to_bigint, PyBaseExceptionRef, PyBytes, PyFloat, PyInt, PyStr, PyStrRef,
+            PyTupleRef, PyType, PyTypeRef,
+        },
+        class::{PyClassDef, PyClassImpl},
+        function::{FuncArgs, KwArgs, OptionalArg, PyComparisonValue},
+        protocol::PyNumberMethods,
+        types::{
+            AsNumber, Comparable, Constructor, DefaultConstructor, Hashable, PyComparisonOp,
+            PyStructSequence, Representable,
+        },
+        AsObject, Context, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, VirtualMachine,
+    };
+    use malachite_bigint::BigInt;
+    use num_integer::Integer;
+    use num_traits::{Signed, ToPrimitive, Zero};
+    use std::cmp::Ordering;
+
+    #[pyattr]
+    const MINYEAR: i32 = calendar::MINYEAR;
+    #[pyattr]
+    const MAXYEAR: i32 = calendar::MAXYEAR;
+
+    /// The ordinal of 1970-01-01.
+    const EPOCH_ORDINAL: i64 = 719_163;
+    const MAX_DELTA_DAYS: i64 = 999_999_999;
+    /// The largest fold in the IANA database is 23 hours, so probing a day back finds any.
+    const MAX_FOLD_SECONDS: i64 = 24 * 3600;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct Date {
+        year: i32,
+        month: u32,
+        day: u32,
+    }
+
+    impl Date {
+        fn new(year: i64, month: i64, day: i64, vm: &VirtualMachine) -> PyResult<Self> {
+            let (year, month, day) =
+                calendar::check_date(year, month, day).map_err(|e| vm.new_value_error(e))?;
+            Ok(Date { year, month, day })
+        }
+
+        fn from_ordinal(ordinal: i64) -> Option<Self> {
+            (1..=calendar::MAX_ORDINAL).contains(&ordinal).then(|| {
+                let (year, month, day) = calendar::ord_to_ymd(ordinal);
+                Date { year, month, day }
+            })
+        }
+
+        fn from_state(state: &[u8]) -> Self {
+            Date {
+                year: (state[0] as i32) << 8 | state[1] as i32,
+                month: (state[2] & 0x7f) as u32,
+                day: state[3] as u32,
+            }
+        }
+
+        fn state(self) -> [u8; 4] {
+            [
+                (self.year >> 8) as u8,
+                self.year as u8,
+                self.month as u8,
+                self.day as u8,
+            ]
+        }
+
+        fn ordinal(self) -> i64 {
+            calendar::ymd_to_ord(self.year, self.month, self.day)
+        }
+
+        fn weekday(self) -> u32 {
+            calendar::weekday(self.year, self.month, self.day)
+        }
+
+        fn yday(self) -> i64 {
+            calendar::days_before_month(self.year, self.month) + self.day as i64
+        }
+
+        fn isoformat(self) -> String {
+            format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+        }
+
+        fn iso_calendar(self, vm: &VirtualMachine) -> PyTupleRef {
+            let (year, week, weekday) = calendar::iso_calendar(self.year, self.month, self.day);
+            IsoCalendarDate {
+                year,
+                week,
+                weekday,
+            }
+            .into_struct_sequence(vm)
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+    struct Time {
+        hour: u32,
+        minute: u32,
+        second: u32,
+        microsecond: u32,
+    }
+
+    impl Time {
+        fn new(
+            hour: i64,
+            minute: i64,
+            second: i64,
+            microsecond: i64,
+            vm: &VirtualMachine,
+        ) -> PyResult<Self> {
+            calendar::check_time(hour, minute, second, microsecond)
+                .map_err(|e| vm.new_value_error(e.to_owned()))?;
+            Ok(Time {
+                hour: hour as u32,
+                minute: minute as u32,
+                second: second as u32,
+                microsecond: microsecond as u32,
+            })
+        }
+
+        fn from_us(us: i64) -> Self {
+            let seconds = us / US_PER_SECOND;
+            Time {
+                hour: (seconds / 3600) as u32,
+                minute: (seconds / 60 % 60) as u32,
+                second: (seconds % 60) as u32,
+                microsecond: (us % US_PER_SECOND) as u32,
+            }
+        }
+
+        fn to_us(self) -> i64 {
+            ((self.hour as i64 * 60 + self.minute as i64) * 60 + self.second as i64) * US_PER_SECOND
+                + self.microsecond as i64
+        }
+
+        fn isoformat(self, spec: TimeSpec) -> String {
+            calendar::format_time(self.hour, self.minute, self.second, self.microsecond, spec)
+        }
+    }
+
+    /// Microseconds since midnight before 0001-01-01, treating the fields as UTC.
+    fn naive_us(date: Date, time: Time) -> i64 {
+        date.ordinal() * US_PER_DAY + time.to_us()
+    }
+
+    fn split_naive_us(us: i64) -> Option<(Date, Time)> {
+        let date = Date::from_ordinal(us.div_euclid(US_PER_DAY))?;
+        Some((date, Time::from_us(us.rem_euclid(US_PER_DAY))))
+    }
+
+    fn unix_seconds_to_naive(seconds: i64, microsecond: u32) -> Option<(Date, Time)> {
+        let us = (EPOCH_ORDINAL * calendar::SECONDS_PER_DAY)
+            .checked_add(seconds)?
+            .checked_mul(US_PER_SECOND)?
+            .checked_add(microsecond as i64)?;
+        split_naive_us(us)
+    }
+
+    fn naive_to_unix_seconds(date: Date, time: Time) -> i64 {
+        (naive_us(date, time) / US_PER_SECOND) - EPOCH_ORDINAL * calendar::SECONDS_PER_DAY
+    }
+
+    fn out_of_range(vm: &VirtualMachine) -> PyBaseExceptionRef {
+        vm.new_overflow_error("date value out of range".to_owned())
+    }
+
+    /// The UTC offset in seconds and the zone abbreviation of local time at a Unix timestamp.
+    #[cfg(unix)]
+    fn local_offset(timestamp: i64, vm: &VirtualMachine) -> PyResult<(i64, Option<String>)> {
+        let t = timestamp as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return Err(vm.new_overflow_error(
+                "timestamp out of range for platform localtime() function".to_owned(),
+            ));
+        }
+        let name = (!tm.tm_zone.is_null()).then(|| {
+            unsafe { std::ffi::CStr::from_ptr(tm.tm_zone) }
+                .to_string_lossy()
+                .into_owned()
+        });
+        Ok((tm.tm_gmtoff as i64, name))
+    }
+
+    #[cfg(not(unix))]
+    fn local_offset(timestamp: i64, vm: &VirtualMachine) -> PyResult<(i64, Option<String>)> {
+        use chrono::{Local, Offset, TimeZone};
+        let offset = Local
+            .timestamp_opt(timestamp, 0)
+            .earliest()
+            .map(|dt| dt.offset().fix().local_minus_utc())
+            .ok_or_else(|| {
+                vm.new_overflow_error(
+                    "timestamp out of range for platform localtime() function".to_owned(),
+                )
+            })?;
+        Ok((offset as i64, None))
+    }
+
+    /// The local wall clock reading, in Unix seconds, at a Unix timestamp.
+    fn local(timestamp: i64, vm: &VirtualMachine) -> PyResult<i64> {
+        Ok(timestamp + local_offset(timestamp, vm)?.0)
+    }
+
+    /// The local date and time at a Unix timestamp, and whether it is the second of two
+    /// identical wall clock readings.
+    fn local_fields(
+        seconds: i64,
+        microsecond: u32,
+        vm: &VirtualMachine,
+    ) -> PyResult<(Date, Time, bool)> {
+        let wall = local(seconds, vm)?;
+        let (date, time) = unix_seconds_to_naive(wall, microsecond)
+            .ok_or_else(|| vm.new_value_error("year is out of range".to_owned()))?;
+        let probe = local(seconds - MAX_FOLD_SECONDS, vm)?;
+        let transition = wall - probe - MAX_FOLD_SECONDS;
+        let fold = transition < 0 && local(seconds + transition, vm)? == wall;
+        Ok((date, time, fold))
+    }
+
+    /// The Unix timestamp of a local wall clock reading, resolving gaps and folds like
+    /// CPython does.
+    fn local_to_timestamp(wall: i64, fold: bool, vm: &VirtualMachine) -> PyResult<i64> {
+        let a = local(wall, vm)? - wall;
+        let u1 = wall - a;
+        let t1 = local(u1, vm)?;
+        let b = if t1 == wall {
+            let u2 = u1
+                + if fold {
+                    MAX_FOLD_SECONDS
+                } else {
+                    -MAX_FOLD_SECONDS
+                };
+            let b = local(u2, vm)? - u2;
+            if a == b {
+                return Ok(u1);
+            }
+            b
+        } else {
+            t1 - u1
+        };
+        let u2 = wall - b;
+        if local(u2, vm)? == wall {
+            return Ok(u2);
+        }
+        if t1 == wall {
+            return Ok(u1);
+        }
+        Ok(if fold { u1.min(u2) } else { u1.max(u2) })
+    }
+
+    /// Split a timestamp into whole seconds and microseconds, rounding half to even.
+    fn split_timestamp(timestamp: &PyObject, vm: &VirtualMachine) -> PyResult<(i64, u32)> {
+        let out_of_range =
+            || vm.new_overflow_error("timestamp out of range for platform time_t".to_owned());
+        if let Some(int) = timestamp.payload_if_subclass::<PyInt>(vm) {
+            let seconds = int.as_bigint().to_i64().ok_or_else(out_of_range)?;
+            return Ok((seconds, 0));
+        }
+        let value = timestamp.try_float(vm)?.to_f64();
+        if value.is_nan() {
+            return Err(vm.new_value_error("Invalid value NaN (not a number)".to_owned()));
+        }
+        let (mut int_part, mut frac_part) = (value.trunc(), value.fract());
+        frac_part = round_half_even(frac_part * 1e6);
+        if frac_part >= 1e6 {
+            frac_part -= 1e6;
+            int_part += 1.0;
+        } else if frac_part < 0.0 {
+            frac_part += 1e6;
+            int_part -= 1.0;
+        }
+        if !(i64::MIN as f64..i64::MAX as f64).contains(&int_part) {
+            return Err(out_of_range());
+        }
+        Ok((int_part as i64, frac_part as u32))
+    }
+
+    fn round_half_even(x: f64) -> f64 {
+        let rounded = x.round();
+        if (rounded - x).abs() == 0.5 {
+            2.0 * (x / 2.0).round()
+        } else {
+            rounded
+        }
+    }
+
+    fn now(vm: &VirtualMachine) -> PyResult<(i64, u32)> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| vm.new_value_error(format!("Time error: {e:?}")))?;
+        Ok((since_epoch.as_secs() as i64, since_epoch.subsec_micros()))
+    }
+
+    fn hash_i64(value: i64) -> PyHash {
+        hash::hash_bigint(&BigInt::from(value))
+    }
+
+    fn int_field(value: &PyObject, vm: &VirtualMachine) -> PyResult<i64> {
+        value.try_index(vm)?.as_bigint().to_i64().ok_or_else(|| {
+            vm.new_overflow_error("Python int too large to convert to C int".to_owned())
+        })
+    }
+
+    fn fold_arg(fold: i64, vm: &VirtualMachine) -> PyResult<bool> {
+        match fold {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(vm.new_value_error("fold must be either 0 or 1".to_owned())),
+        }
+    }
+
+    /// The `tzinfo` argument of a constructor, with None mapped to no timezone.
+    fn tzinfo_arg(
+        tzinfo: Option<PyObjectRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<PyObjectRef>> {
+        match tzinfo {
+            Some(tzinfo) if !vm.is_none(&tzinfo) => {
+                if !tzinfo.fast_isinstance(PyTzInfo::class(&vm.ctx)) {
+                    return Err(vm.new_type_error(format!(
+                        "tzinfo argument must be None or of a tzinfo subclass, not type '{}'",
+                        tzinfo.class().name()
+                    )));
+                }
+                Ok(Some(tzinfo))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn tzinfo_or_none(tzinfo: &Option<PyObjectRef>, vm: &VirtualMachine) -> PyObjectRef {
+        tzinfo.clone().unwrap_or_else(|| vm.ctx.none())
+    }
+
+    /// Call `tzinfo.utcoffset(arg)` or `tzinfo.dst(arg)`, returning the offset in
+    /// microseconds after checking it is a timedelta within a day.
+    fn call_offset(
+        tzinfo: Option<&PyObject>,
+        method: &'static str,
+        arg: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<i64>> {
+        let Some(tzinfo) = tzinfo else {
+            return Ok(None);
+        };
+        let offset = vm.call_method(tzinfo, method, (arg,))?;
+        if vm.is_none(&offset) {
+            return Ok(None);
+        }
+        let delta = offset
+            .payload_if_subclass::<PyTimeDelta>(vm)
+            .ok_or_else(|| {
+                vm.new_type_error(format!(
+                    "tzinfo.{method}() must return None or timedelta, not '{}'",
+                    offset.class().name()
+                ))
+            })?;
+        let us = delta.total_us() as i64;
+        if us.abs() >= US_PER_DAY {
+            return Err(vm.new_value_error(format!(
+                "offset must be a timedelta strictly between -timedelta(hours=24) and \
+                 timedelta(hours=24), not {}.",
+                offset.repr(vm)?
+            )));
+        }
+        Ok(Some(us))
+    }
+
+    fn call_tzname(
+        tzinfo: Option<&PyObject>,
+        arg: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<PyStrRef>> {
+        let Some(tzinfo) = tzinfo else {
+            return Ok(None);
+        };
+        let name = vm.call_method(tzinfo, "tzname", (arg,))?;
+        if vm.is_none(&name) {
+            return Ok(None);
+        }
+        name.downcast::<PyStr>().map(Some).map_err(|name| {
+            vm.new_type_error(format!(
+                "tzinfo.tzname() must return None or a string, not '{}'",
+                name.class().name()
+            ))
+        })
+    }
+
+    fn offset_to_delta(offset: Option<i64>, vm: &VirtualMachine) -> PyObjectRef {
+        match offset {
+            Some(us) => PyTimeDelta::from_us(us as i128)
+                .unwrap()
+                .into_ref(&vm.ctx)
+                .into(),
+            None => vm.ctx.none(),
+        }
+    }
+
+    /// The type name `repr` shows: the qualified one for the native class itself, the
+    /// plain class name for subclasses.
+    fn type_name<T: PyClassDef + PyPayload>(zelf: &PyObject, vm: &VirtualMachine) -> String {
+        if zelf.class().is(T::class(&vm.ctx)) {
+            T::TP_NAME.to_owned()
+        } else {
+            zelf.class().name().to_string()
+        }
+    }
+
+    /// Run `time.strftime` after expanding the directives it cannot know about.
+    fn wrap_strftime(
+        format: &str,
+        timetuple: PyObjectRef,
+        microsecond: u32,
+        tzinfo: Option<&PyObject>,
+        tzinfo_arg: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        let mut expanded = String::with_capacity(format.len());
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('f') => expanded.push_str(&format!("{microsecond:06}")),
+                Some('z') => {
+                    if let Some(offset) = call_offset(tzinfo, "utcoffset", tzinfo_arg.clone(), vm)?
+                    {
+                        expanded.push_str(&calendar::format_offset(offset, ""));
+                    }
+                }
+                Some(':') if chars.peek() == Some(&'z') => {
+                    chars.next();
+                    if let Some(offset) = call_offset(tzinfo, "utcoffset", tzinfo_arg.clone(), vm)?
+                    {
+                        expanded.push_str(&calendar::format_offset(offset, ":"));
+                    }
+                }
+                Some('Z') => {
+                    if let Some(name) = call_tzname(tzinfo, tzinfo_arg.clone(), vm)? {
+                        expanded.push_str(&name.as_str().replace('%', "%%"));
+                    }
+                }
+                Some(other) => {
+                    expanded.push('%');
+                    expanded.push(other);
+                }
+                None => expanded.push('%'),
+            }
+        }
+        let strftime = vm.import("time", 0)?.get_attr("strftime", vm)?;
+        strftime.call((expanded, timetuple), vm)
+    }
+
+    fn struct_time(date: Date, time: Time, dst: i32, vm: &VirtualMachine) -> PyResult {
+        let fields = vm.ctx.new_tuple(vec![
+            vm.new_pyobj(date.year),
+            vm.new_pyobj(date.month),
+            vm.new_pyobj(date.day),
+            vm.new_pyobj(time.hour),
+            vm.new_pyobj(time.minute),
+            vm.new_pyobj(time.second),
+            vm.new_pyobj(date.weekday()),
+            vm.new_pyobj(date.yday()),
+            vm.new_pyobj(dst),
+        ]);
+        let struct_time = vm.import("time", 0)?.get_attr("struct_time", vm)?;
+        struct_time.call((fields,), vm)
+    }
+
+    fn format_with(zelf: &PyObject, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+        if format.as_str().is_empty() {
+            zelf.str(vm).map(Into::into)
+        } else {
+            vm.call_method(zelf, "strftime", (format,))
+        }
+    }
+
+    fn fromisoformat_arg(s: &PyObject, vm: &VirtualMachine) -> PyResult<PyStrRef> {
+        s.to_owned().downcast::<PyStr>().map_err(|s| {
+            vm.new_type_error(format!(
+                "fromisoformat: argument must be str, not {}",
+                s.class().name()
+            ))
+        })
+    }
+
+    fn iso_error(error: IsoError, s: &Py<PyStr>, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        match error {
+            IsoError::Invalid => {
+                let repr = s.as_object().repr(vm).map(|r| r.as_str().to_owned());
+                vm.new_value_error(format!(
+                    "Invalid isoformat string: {}",
+                    repr.unwrap_or_default()
+                ))
+            }
+            IsoError::Range(message) => vm.new_value_error(message),
+        }
+    }
+
+    fn timezone_from_offset(offset: Option<i64>, vm: &VirtualMachine) -> Option<PyObjectRef> {
+        offset.map(|us| match us {
+            0 => utc(vm).into(),
+            us => PyTimeZone {
+                offset: PyTimeDelta::from_us(us as i128).unwrap(),
+                name: None,
+            }
+            .into_ref(&vm.ctx)
+            .into(),
+        })
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "IsoCalendarDate")]
+    #[derive(PyStructSequence)]
+    struct IsoCalendarDate {
+        year: i32,
+        week: u32,
+        weekday: u32,
+    }
+    #[pyclass(with(PyStructSequence))]
+    impl IsoCalendarDate {}
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "timedelta")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, PyPayload)]
+    pub(crate) struct PyTimeDelta {
+        days: i32,
+        seconds: i32,
+        microseconds: i32,
+    }
+
+    #[derive(FromArgs)]
+    pub(crate) struct TimeDeltaArgs {
+        #[pyarg(any, optional)]
+        days: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        seconds: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        microseconds: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        milliseconds: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        minutes: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        hours: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        weeks: OptionalArg<PyObjectRef>,
+    }
+
+    impl Constructor for PyTimeDelta {
+        type Args = TimeDeltaArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            // the same order as CPython, so float rounding agrees
+            let components = [
+                (args.microseconds, "microseconds", 1),
+                (args.milliseconds, "milliseconds", 1000),
+                (args.seconds, "seconds", US_PER_SECOND),
+                (args.minutes, "minutes", 60 * US_PER_SECOND),
+                (args.hours, "hours", 3600 * US_PER_SECOND),
+                (args.days, "days", US_PER_DAY),
+                (args.weeks, "weeks", 7 * US_PER_DAY),
+            ];
+            let mut total = BigInt::zero();
+            let mut leftover = 0.0;
+            for (value, name, factor) in components {
+                let OptionalArg::Present(value) = value else {
+                    continue;
+                };
+                if let Some(int) = value.payload_if_subclass::<PyInt>(vm) {
+                    total += int.as_bigint() * BigInt::from(factor);
+                } else if let Some(float) = value.payload_if_subclass::<PyFloat>(vm) {
+                    let value = float.to_f64();
+                    total += try_f64_to_bigint(value.trunc(), vm)? * BigInt::from(factor);
+                    let fraction = value.fract() * factor as f64;
+                    total += try_f64_to_bigint(fraction.trunc(), vm)?;
+                    leftover += fraction.fract();
+                } else {
+                    return Err(vm.new_type_error(format!(
+                        "unsupported type for timedelta {name} component: {}",
+                        value.class().name()
+                    )));
+                }
+            }
+            if leftover != 0.0 {
+                let mut whole = leftover.round();
+                if (whole - leftover).abs() == 0.5 {
+                    // halfway: round so the total comes out even
+                    let odd = total.is_odd() as i32 as f64;
+                    whole = 2.0 * ((leftover + odd) * 0.5).round() - odd;
+                }
+                total += BigInt::from(whole as i64);
+            }
+            PyTimeDelta::from_bigint_us(&total, vm)?
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        }
+    }
+
+    impl PyTimeDelta {
+        const ZERO: Self = PyTimeDelta {
+            days: 0,
+            seconds: 0,
+            microseconds: 0,
+        };
+
+        pub(crate) fn from_us(us: i128) -> Option<Self> {
+            let days = us.div_euclid(US_PER_DAY as i128);
+            if days.abs() > MAX_DELTA_DAYS as i128 {
+                return None;
+            }
+            let rest = us.rem_euclid(US_PER_DAY as i128) as i64;
+            Some(PyTimeDelta {
+                days: days as i32,
+                seconds: (rest / US_PER_SECOND) as i32,
+                microseconds: (rest % US_PER_SECOND) as i32,
+            })
+        }
+
+        fn checked(us: i128, vm: &VirtualMachine) -> PyResult<Self> {
+            Self::from_us(us).ok_or_else(|| {
+                let days = us.div_euclid(US_PER_DAY as i128);
+                vm.new_overflow_error(format!(
+                    "days={days}; must have magnitude <= {MAX_DELTA_DAYS}"
+                ))
+            })
+        }
+
+        fn from_bigint_us(us: &BigInt, vm: &VirtualMachine) -> PyResult<Self> {
+            let (days, rest) = us.div_mod_floor(&BigInt::from(US_PER_DAY));
+            match days.to_i64() {
+                Some(days) if days.abs() <= MAX_DELTA_DAYS => {
+                    let rest = rest.to_i64().unwrap();
+                    Ok(PyTimeDelta {
+                        days: days as i32,
+                        seconds: (rest / US_PER_SECOND) as i32,
+                        microseconds: (rest % US_PER_SECOND) as i32,
+                    })
+                }
+                _ => Err(vm.new_overflow_error(format!(
+                    "days={days}; must have magnitude <= {MAX_DELTA_DAYS}"
+                ))),
+            }
+        }
+
+        fn total_us(self) -> i128 {
+            self.days as i128 * US_PER_DAY as i128
+                + self.seconds as i128 * US_PER_SECOND as i128
+                + self.microseconds as i128
+        }
+
+        fn total_us_bigint(self) -> BigInt {
+            BigInt::from(self.days as i64) * BigInt::from(US_PER_DAY)
+                + BigInt::from(self.seconds as i64 * US_PER_SECOND + self.microseconds as i64)
+        }
+
+        fn of(obj: &PyObject, vm: &VirtualMachine) -> Option<Self> {
+            obj.payload_if_subclass::<PyTimeDelta>(vm).copied()
+        }
+
+        fn new_object(self, vm: &VirtualMachine) -> PyObjectRef {
+            self.into_ref(&vm.ctx).into()
+        }
+
+        fn binary(
+            a: &PyObject,
+            b: &PyObject,
+            vm: &VirtualMachine,
+            op: impl FnOnce(i128, i128) -> PyResult<i128>,
+        ) -> PyResult {
+            let (Some(a), Some(b)) = (Self::of(a, vm), Self::of(b, vm)) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            let us = op(a.total_us(), b.total_us())?;
+            Ok(Self::checked(us, vm)?.new_object(vm))
+        }
+
+        fn multiply(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let (delta, factor) = match (Self::of(a, vm), Self::of(b, vm)) {
+                (Some(delta), None) => (delta, b),
+                (None, Some(delta)) => (delta, a),
+                _ => return Ok(vm.ctx.not_implemented()),
+            };
+            let us = if let Some(int) = factor.payload_if_subclass::<PyInt>(vm) {
+                delta.total_us_bigint() * int.as_bigint()
+            } else if factor.payload_if_subclass::<PyFloat>(vm).is_some() {
+                let (numerator, denominator) = float_ratio(factor, vm)?;
+                div_round_half_even(&(delta.total_us_bigint() * numerator), &denominator)
+            } else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            Ok(Self::from_bigint_us(&us, vm)?.new_object(vm))
+        }
+
+        fn true_divide(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let Some(delta) = Self::of(a, vm) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            let us = delta.total_us_bigint();
+            if let Some(other) = Self::of(b, vm) {
+                let numerator = vm.ctx.new_bigint(&us);
+                let denominator = vm.ctx.new_bigint(&other.total_us_bigint());
+                return vm._truediv(numerator.as_object(), denominator.as_object());
+            }
+            let (numerator, denominator) = if let Some(int) = b.payload_if_subclass::<PyInt>(vm) {
+                (BigInt::from(1), int.as_bigint().clone())
+            } else if b.payload_if_subclass::<PyFloat>(vm).is_some() {
+                let (numerator, denominator) = float_ratio(b, vm)?;
+                (denominator, numerator)
+            } else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            if denominator.is_zero() {
+                return Err(vm.new_zero_division_error("division by zero".to_owned()));
+            }
+            let us = div_round_half_even(&(us * numerator), &denominator);
+            Ok(Self::from_bigint_us(&us, vm)?.new_object(vm))
+        }
+
+        fn floor_divide(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let Some(delta) = Self::of(a, vm) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            let zero_division =
+                || vm.new_zero_division_error("integer division or modulo by zero".to_owned());
+            if let Some(other) = Self::of(b, vm) {
+                let divisor = other.total_us();
+                if divisor == 0 {
+                    return Err(zero_division());
+                }
+                let quotient = Integer::div_floor(&delta.total_us(), &divisor);
+                return Ok(vm.ctx.new_bigint(&BigInt::from(quotient as i64)).into());
+            }
+            let Some(int) = b.payload_if_subclass::<PyInt>(vm) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            if int.as_bigint().is_zero() {
+                return Err(zero_division());
+            }
+            let us = delta.total_us_bigint().div_floor(int.as_bigint());
+            Ok(Self::from_bigint_us(&us, vm)?.new_object(vm))
+        }
+
+        fn remainder(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            Self::binary(a, b, vm, |a, b| {
+                if b == 0 {
+                    Err(vm.new_zero_division_error("integer division or modulo by zero".to_owned()))
+                } else {
+                    Ok(a.mod_floor(&b))
+                }
+            })
+        }
+
+        fn divmod(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let (Some(a), Some(b)) = (Self::of(a, vm), Self::of(b, vm)) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            let divisor = b.total_us();
+            if divisor == 0 {
+                return Err(
+                    vm.new_zero_division_error("integer division or modulo by zero".to_owned())
+                );
+            }
+            let (quotient, remainder) = a.total_us().div_mod_floor(&divisor);
+            let remainder = Self::checked(remainder, vm)?.new_object(vm);
+            Ok(vm.new_tuple((quotient as i64, remainder)).into())
+        }
+
+        fn format_str(self) -> String {
+            let (hours, minutes, seconds) = (
+                self.seconds / 3600,
+                self.seconds / 60 % 60,
+                self.seconds % 60,
+            );
+            let mut s = String::new();
+            if self.days != 0 {
+                let plural = if self.days.abs() != 1 { "s" } else { "" };
+                s.push_str(&format!("{} day{plural}, ", self.days));
+            }
+            s.push_str(&format!("{hours}:{minutes:02}:{seconds:02}"));
+            if self.microseconds != 0 {
+                s.push_str(&format!(".{:06}", self.microseconds));
+            }
+            s
+        }
+    }
+
+    /// The exact ratio of a float, as `float.as_integer_ratio` gives it.
+    fn float_ratio(value: &PyObject, vm: &VirtualMachine) -> PyResult<(BigInt, BigInt)> {
+        let ratio: PyTupleRef = vm
+            .call_method(value, "as_integer_ratio", ())?
+            .try_into_value(vm)?;
+        let part = |i: usize| -> PyResult<BigInt> {
+            Ok(ratio.as_slice()[i].try_index(vm)?.as_bigint().clone())
+        };
+        Ok((part(0)?, part(1)?))
+    }
+
+    fn div_round_half_even(numerator: &BigInt, denominator: &BigInt) -> BigInt {
+        let (quotient, remainder) = numerator.div_mod_floor(denominator);
+        let twice = (&remainder * BigInt::from(2)).abs();
+        match twice.cmp(&denominator.abs()) {
+            Ordering::Greater => quotient + 1,
+            Ordering::Equal if quotient.is_odd() => quotient + 1,
+            _ => quotient,
+        }
+    }
+
+    #[pyclass(with(Constructor, AsNumber, Comparable, Hashable, Representable))]
+    impl PyTimeDelta {
+        #[pygetset]
+        fn days(&self) -> i32 {
+            self.days
+        }
+
+        #[pygetset]
+        fn seconds(&self) -> i32 {
+            self.seconds
+        }
+
+        #[pygetset]
+        fn microseconds(&self) -> i32 {
+            self.microseconds
+        }
+
+        #[pymethod]
+        fn total_seconds(&self, vm: &VirtualMachine) -> PyResult {
+            let us = vm.ctx.new_bigint(&self.total_us_bigint());
+            let per_second = vm.ctx.new_int(US_PER_SECOND);
+            vm._truediv(us.as_object(), per_second.as_object())
+        }
+
+        #[pymethod(magic)]
+        fn str(&self) -> String {
+            self.format_str()
+        }
+
+        #[pymethod(magic)]
+        fn bool(&self) -> bool {
+            *self != Self::ZERO
+        }
+
+        #[pymethod(magic)]
+        fn add(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::binary(zelf.as_object(), &other, vm, |a, b| Ok(a + b))
+        }
+
+        #[pymethod(magic)]
+        fn radd(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::binary(&other, zelf.as_object(), vm, |a, b| Ok(a + b))
+        }
+
+        #[pymethod(magic)]
+        fn sub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::binary(zelf.as_object(), &other, vm, |a, b| Ok(a - b))
+        }
+
+        #[pymethod(magic)]
+        fn rsub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::binary(&other, zelf.as_object(), vm, |a, b| Ok(a - b))
+        }
+
+        #[pymethod(magic)]
+        fn mul(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::multiply(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rmul(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::multiply(&other, zelf.as_object(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn truediv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::true_divide(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn floordiv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::floor_divide(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(name = "__mod__")]
+        fn mod_(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::remainder(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(name = "__divmod__")]
+        fn divmod_(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::divmod(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn neg(&self, vm: &VirtualMachine) -> PyResult {
+            Ok(Self::checked(-self.total_us(), vm)?.new_object(vm))
+        }
+
+        #[pymethod(magic)]
+        fn pos(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.new_object(vm)
+        }
+
+        #[pymethod(magic)]
+        fn abs(&self, vm: &VirtualMachine) -> PyResult {
+            Ok(Self::checked(self.total_us().abs(), vm)?.new_object(vm))
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            vm.new_tuple((
+                zelf.class().to_owned(),
+                (zelf.days, zelf.seconds, zelf.microseconds),
+            ))
+        }
+    }
+
+    impl AsNumber for PyTimeDelta {
+        fn as_number() -> &'static PyNumberMethods {
+            static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+                add: Some(|a, b, vm| PyTimeDelta::binary(a, b, vm, |a, b| Ok(a + b))),
+                subtract: Some(|a, b, vm| PyTimeDelta::binary(a, b, vm, |a, b| Ok(a - b))),
+                multiply: Some(PyTimeDelta::multiply),
+                remainder: Some(PyTimeDelta::remainder),
+                divmod: Some(PyTimeDelta::divmod),
+                negative: Some(|num, vm| PyTimeDelta::number_downcast(num).neg(vm)),
+                positive: Some(|num, vm| Ok(PyTimeDelta::number_downcast(num).pos(vm))),
+                absolute: Some(|num, vm| PyTimeDelta::number_downcast(num).abs(vm)),
+                boolean: Some(|num, _vm| {
+                    Ok(**PyTimeDelta::number_downcast(num) != PyTimeDelta::ZERO)
+                }),
+                floor_divide: Some(PyTimeDelta::floor_divide),
+                true_divide: Some(PyTimeDelta::true_divide),
+                ..PyNumberMethods::NOT_IMPLEMENTED
+            };
+            &AS_NUMBER
+        }
+    }
+
+    impl Comparable for PyTimeDelta {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            let Some(other) = Self::of(other, vm) else {
+                return Ok(PyComparisonValue::NotImplemented);
+            };
+            Ok(PyComparisonValue::Implemented(
+                op.eval_ord((**zelf).cmp(&other)),
+            ))
+        }
+    }
+
+    impl Hashable for PyTimeDelta {
+        fn hash(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyHash> {
+            Ok(hash::hash_bigint(&zelf.total_us_bigint()))
+        }
+    }
+
+    impl Representable for PyTimeDelta {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let mut fields = Vec::new();
+            if zelf.days != 0 {
+                fields.push(format!("days={}", zelf.days));
+            }
+            if zelf.seconds != 0 {
+                fields.push(format!("seconds={}", zelf.seconds));
+            }
+            if zelf.microseconds != 0 {
+                fields.push(format!("microseconds={}", zelf.microseconds));
+            }
+            if fields.is_empty() {
+                fields.push("0".to_owned());
+            }
+            Ok(format!(
+                "{}({})",
+                type_name::<Self>(zelf.as_object(), vm),
+                fields.join(", ")
+            ))
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "tzinfo")]
+    #[derive(Debug, Default, PyPayload)]
+    pub(crate) struct PyTzInfo;
+
+    impl DefaultConstructor for PyTzInfo {}
+
+    #[pyclass(flags(BASETYPE), with(DefaultConstructor))]
+    impl PyTzInfo {
+        #[pymethod]
+        fn tzname(_zelf: PyObjectRef, _dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Err(vm
+                .new_not_implemented_error("a tzinfo subclass must implement tzname()".to_owned()))
+        }
+
+        #[pymethod]
+        fn utcoffset(_zelf: PyObjectRef, _dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Err(vm.new_not_implemented_error(
+                "a tzinfo subclass must implement utcoffset()".to_owned(),
+            ))
+        }
+
+        #[pymethod]
+        fn dst(_zelf: PyObjectRef, _dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Err(vm.new_not_implemented_error("a tzinfo subclass must implement dst()".to_owned()))
+        }
+
+        #[pymethod]
+        fn fromutc(zelf: PyObjectRef, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let Some(datetime) = dt.payload_if_subclass::<PyDateTime>(vm) else {
+                return Err(vm.new_type_error("fromutc: argument must be a datetime".to_owned()));
+            };
+            if !datetime.tzinfo.as_ref().map_or(false, |tz| tz.is(&zelf)) {
+                return Err(vm.new_value_error("fromutc: dt.tzinfo is not self".to_owned()));
+            }
+            let offset =
+                call_offset(Some(&zelf), "utcoffset", dt.clone(), vm)?.ok_or_else(|| {
+                    vm.new_value_error("fromutc: non-None utcoffset() result required".to_owned())
+                })?;
+            let dst = call_offset(Some(&zelf), "dst", dt.clone(), vm)?.ok_or_else(|| {
+                vm.new_value_error("fromutc: non-None dst() result required".to_owned())
+            })?;
+            let mut dt = dt;
+            let mut dst = dst;
+            let delta = offset - dst;
+            if delta != 0 {
+                dt = PyDateTime::shift_object(&dt, delta, vm)?;
+                dst = call_offset(Some(&zelf), "dst", dt.clone(), vm)?.ok_or_else(|| {
+                    vm.new_value_error(
+                        "fromutc: tz.dst() gave inconsistent results; cannot convert".to_owned(),
+                    )
+                })?;
+            }
+            PyDateTime::shift_object(&dt, dst, vm)
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyTupleRef> {
+            let args = match vm.get_attribute_opt(zelf.clone(), "__getinitargs__")? {
+                Some(getinitargs) => getinitargs.call((), vm)?,
+                None => vm.ctx.empty_tuple.clone().into(),
+            };
+            let state = match vm.get_attribute_opt(zelf.clone(), "__dict__")? {
+                Some(dict) if dict.clone().try_to_bool(vm)? => dict,
+                _ => vm.ctx.none(),
+            };
+            Ok(vm.new_tuple((zelf.class().to_owned(), args, state)))
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "timezone", base = "PyTzInfo")]
+    #[derive(Debug, PyPayload)]
+    struct PyTimeZone {
+        offset: PyTimeDelta,
+        name: Option<PyStrRef>,
+    }
+
+    #[derive(FromArgs)]
+    struct TimeZoneArgs {
+        #[pyarg(any)]
+        offset: PyRef<PyTimeDelta>,
+        #[pyarg(any, optional)]
+        name: OptionalArg<PyStrRef>,
+    }
+
+    impl Constructor for PyTimeZone {
+        type Args = TimeZoneArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let offset = **args.offset;
+            if offset.total_us().abs() >= US_PER_DAY as i128 {
+                return Err(vm.new_value_error(format!(
+                    "offset must be a timedelta strictly between -timedelta(hours=24) and \
+                     timedelta(hours=24), not {}.",
+                    args.offset.as_object().repr(vm)?
+                )));
+            }
+            let name = args.name.into_option();
+            if name.is_none() && offset == PyTimeDelta::ZERO {
+                return Ok(utc(vm).into());
+            }
+            PyTimeZone { offset, name }
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        }
+    }
+
+    impl PyTimeZone {
+        fn check_arg(dt: &PyObject, method: &str, vm: &VirtualMachine) -> PyResult<()> {
+            if vm.is_none(dt) || dt.payload_if_subclass::<PyDateTime>(vm).is_some() {
+                Ok(())
+            } else {
+                Err(vm.new_type_error(format!(
+                    "{method}(dt) argument must be a datetime instance or None, not {}",
+                    dt.class().name()
+                )))
+            }
+        }
+
+        fn name(&self) -> String {
+            match &self.name {
+                Some(name) => name.as_str().to_owned(),
+                None if self.offset == PyTimeDelta::ZERO => "UTC".to_owned(),
+                None => format!(
+                    "UTC{}",
+                    calendar::format_offset(self.offset.total_us() as i64, ":")
+                ),
+            }
+        }
+    }
+
+    #[pyclass(with(Constructor, Comparable, Hashable, Representable))]
+    impl PyTimeZone {
+        #[pymethod]
+        fn utcoffset(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyTimeDelta> {
+            Self::check_arg(&dt, "utcoffset", vm)?;
+            Ok(self.offset)
+        }
+
+        #[pymethod]
+        fn dst(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            Self::check_arg(&dt, "dst", vm)
+        }
+
+        #[pymethod]
+        fn tzname(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult<String> {
+            Self::check_arg(&dt, "tzname", vm)?;
+            Ok(self.name())
+        }
+
+        #[pymethod]
+        fn fromutc(zelf: PyRef<Self>, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let Some(datetime) = dt.payload_if_subclass::<PyDateTime>(vm) else {
+                return Err(vm.new_type_error("fromutc: argument must be a datetime".to_owned()));
+            };
+            if !datetime.tzinfo.as_ref().map_or(false, |tz| tz.is(&zelf)) {
+                return Err(vm.new_value_error("fromutc: dt.tzinfo is not self".to_owned()));
+            }
+            PyDateTime::shift_object(&dt, zelf.offset.total_us() as i64, vm)
+        }
+
+        #[pymethod(magic)]
+        fn getinitargs(&self, vm: &VirtualMachine) -> PyTupleRef {
+            let offset = self.offset.new_object(vm);
+            match &self.name {
+                Some(name) => vm.new_tuple((offset, name.clone())),
+                None => vm.new_tuple((offset,)),
+            }
+        }
+
+        #[pymethod(magic)]
+        fn str(&self) -> String {
+            self.name()
+        }
+    }
+
+    impl Comparable for PyTimeZone {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            _vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            op.eq_only(|| {
+                let Some(other) = other.payload::<PyTimeZone>() else {
+                    return Ok(PyComparisonValue::NotImplemented);
+                };
+                Ok(PyComparisonValue::Implemented(zelf.offset == other.offset))
+            })
+        }
+    }
+
+    impl Hashable for PyTimeZone {
+        fn hash(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyHash> {
+            Ok(hash::hash_bigint(&zelf.offset.total_us_bigint()))
+        }
+    }
+
+    impl Representable for PyTimeZone {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            if zelf.is(&utc(vm)) {
+                return Ok("datetime.timezone.utc".to_owned());
+            }
+            let offset = zelf.offset.new_object(vm).repr(vm)?;
+            Ok(match &zelf.name {
+                Some(name) => format!(
+                    "datetime.timezone({offset}, {})",
+                    name.as_object().repr(vm)?
+                ),
+                None => format!("datetime.timezone({offset})"),
+            })
+        }
+    }
+
+    #[pyattr(name = "UTC", once)]
+    fn utc(vm: &VirtualMachine) -> PyRef<PyTimeZone> {
+        PyTimeZone {
+            offset: PyTimeDelta::ZERO,
+            name: None,
+        }
+        .into_ref(&vm.ctx)
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "date")]
+    #[derive(Debug, PyPayload)]
+    pub(crate) struct PyDate {
+        date: Date,
+    }
+
+    #[derive(FromArgs)]
+    pub(crate) struct DateArgs {
+        #[pyarg(any)]
+        year: PyObjectRef,
+        #[pyarg(any, optional)]
+        month: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        day: OptionalArg<PyObjectRef>,
+    }
+
+    /// Pickles store dates as their packed fields.
+    fn pickle_state(arg: &PyObject, len: usize, valid: fn(&[u8]) -> bool) -> Option<&[u8]> {
+        arg.payload::<PyBytes>()
+            .map(|state| state.as_bytes())
+            .filter(|state| state.len() == len && valid(state))
+    }
+
+    fn valid_month(state: &[u8]) -> bool {
+        (1..=12).contains(&(state[2] & 0x7f))
+    }
+
+    fn missing_argument(name: &str, position: usize, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        vm.new_type_error(format!(
+            "function missing required argument '{name}' (pos {position})"
+        ))
+    }
+
+    impl Constructor for PyDate {
+        type Args = DateArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let date = match (pickle_state(&args.year, 4, valid_month), &args.month) {
+                (Some(state), OptionalArg::Missing) => Date::from_state(state),
+                _ => {
+                    let month = args.month.ok_or_else(|| missing_argument("month", 2, vm))?;
+                    let day = args.day.ok_or_else(|| missing_argument("day", 3, vm))?;
+                    Date::new(
+                        int_field(&args.year, vm)?,
+                        int_field(&month, vm)?,
+                        int_field(&day, vm)?,
+                        vm,
+                    )?
+                }
+            };
+            PyDate { date }.into_ref_with_type(vm, cls).map(Into::into)
+        }
+    }
+
+    /// A date of class `cls`, going through its constructor if it is a subclass.
+    fn new_date(date: Date, cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult {
+        if cls.is(PyDate::class(&vm.ctx)) {
+            Ok(PyDate { date }.into_ref(&vm.ctx).into())
+        } else {
+            cls.as_object().call((date.year, date.month, date.day), vm)
+        }
+    }
+
+    impl PyDate {
+        fn of(obj: &PyObject) -> Option<Date> {
+            obj.payload::<PyDate>().map(|date| date.date)
+        }
+
+        fn add_days(zelf: &PyObject, date: Date, days: i64, vm: &VirtualMachine) -> PyResult {
+            let date = Date::from_ordinal(date.ordinal() + days).ok_or_else(|| out_of_range(vm))?;
+            new_date(date, zelf.class(), vm)
+        }
+
+        fn add_op(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let (date_obj, delta) = match (Self::of(a), PyTimeDelta::of(b, vm)) {
+                (Some(_), Some(delta)) => (a, delta),
+                _ => match (PyTimeDelta::of(a, vm), Self::of(b)) {
+                    (Some(delta), Some(_)) => (b, delta),
+                    _ => return Ok(vm.ctx.not_implemented()),
+                },
+            };
+            Self::add_days(date_obj, Self::of(date_obj).unwrap(), delta.days as i64, vm)
+        }
+
+        fn subtract_op(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let Some(date) = Self::of(a) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            if let Some(other) = Self::of(b) {
+                let days = date.ordinal() - other.ordinal();
+                return Ok(PyTimeDelta::from_us(days as i128 * US_PER_DAY as i128)
+                    .unwrap()
+                    .new_object(vm));
+            }
+            match PyTimeDelta::of(b, vm) {
+                Some(delta) => Self::add_days(a, date, -(delta.days as i64), vm),
+                None => Ok(vm.ctx.not_implemented()),
+            }
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DateReplaceArgs {
+        #[pyarg(any, optional)]
+        year: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        month: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        day: OptionalArg<PyObjectRef>,
+    }
+
+    fn replace_field(
+        value: OptionalArg<PyObjectRef>,
+        current: i64,
+        vm: &VirtualMachine,
+    ) -> PyResult<i64> {
+        match value {
+            OptionalArg::Present(value) => int_field(&value, vm),
+            OptionalArg::Missing => Ok(current),
+        }
+    }
+
+    #[pyclass(
+        flags(BASETYPE),
+        with(Constructor, AsNumber, Comparable, Hashable, Representable)
+    )]
+    impl PyDate {
+        #[pyclassmethod]
+        fn today(cls: PyTypeRef, vm: &VirtualMachine) -> PyResult {
+            let (seconds, microsecond) = now(vm)?;
+            let timestamp = seconds as f64 + microsecond as f64 / 1e6;
+            vm.call_method(cls.as_object(), "fromtimestamp", (timestamp,))
+        }
+
+        #[pyclassmethod]
+        fn fromtimestamp(cls: PyTypeRef, timestamp: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let (seconds, _) = split_timestamp(&timestamp, vm)?;
+            let (date, _, _) = local_fields(seconds, 0, vm)?;
+            new_date(date, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn fromordinal(cls: PyTypeRef, ordinal: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let ordinal = int_field(&ordinal, vm)?;
+            let date = Date::from_ordinal(ordinal)
+                .ok_or_else(|| vm.new_value_error("ordinal must be >= 1".to_owned()))?;
+            new_date(date, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn fromisoformat(cls: PyTypeRef, s: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let s = fromisoformat_arg(&s, vm)?;
+            let (year, month, day) =
+                calendar::parse_iso_date(s.as_str()).map_err(|e| iso_error(e, &s, vm))?;
+            new_date(Date { year, month, day }, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn fromisocalendar(
+            cls: PyTypeRef,
+            year: i64,
+            week: i64,
+            day: i64,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let year = i32::try_from(year)
+                .map_err(|_| vm.new_value_error(format!("Year is out of range: {year}")))?;
+            let week = u32::try_from(week)
+                .map_err(|_| vm.new_value_error(format!("Invalid week: {week}")))?;
+            let day = u32::try_from(day).map_err(|_| {
+                vm.new_value_error(format!("Invalid weekday: {day} (range is [1, 7])"))
+            })?;
+            let (year, month, day) =
+                calendar::iso_to_ymd(year, week, day).map_err(|e| vm.new_value_error(e))?;
+            new_date(Date { year, month, day }, &cls, vm)
+        }
+
+        #[pygetset]
+        fn year(&self) -> i32 {
+            self.date.year
+        }
+
+        #[pygetset]
+        fn month(&self) -> u32 {
+            self.date.month
+        }
+
+        #[pygetset]
+        fn day(&self) -> u32 {
+            self.date.day
+        }
+
+        #[pymethod]
+        fn toordinal(&self) -> i64 {
+            self.date.ordinal()
+        }
+
+        #[pymethod]
+        fn weekday(&self) -> u32 {
+            self.date.weekday()
+        }
+
+        #[pymethod]
+        fn isoweekday(&self) -> u32 {
+            self.date.weekday() + 1
+        }
+
+        #[pymethod]
+        fn isocalendar(&self, vm: &VirtualMachine) -> PyTupleRef {
+            self.date.iso_calendar(vm)
+        }
+
+        #[pymethod]
+        fn isoformat(&self) -> String {
+            self.date.isoformat()
+        }
+
+        #[pymethod(magic)]
+        fn add(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::add_op(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn radd(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::add_op(&other, zelf.as_object(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn sub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::subtract_op(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn str(&self) -> String {
+            self.date.isoformat()
+        }
+
+        #[pymethod]
+        fn ctime(&self) -> String {
+            let d = self.date;
+            calendar::format_ctime(d.year, d.month, d.day, 0, 0, 0)
+        }
+
+        #[pymethod]
+        fn timetuple(&self, vm: &VirtualMachine) -> PyResult {
+            struct_time(self.date, Time::default(), -1, vm)
+        }
+
+        #[pymethod]
+        fn strftime(&self, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            let timetuple = self.timetuple(vm)?;
+            wrap_strftime(format.as_str(), timetuple, 0, None, vm.ctx.none(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn format(zelf: PyRef<Self>, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            format_with(zelf.as_object(), format, vm)
+        }
+
+        #[pymethod]
+        fn replace(zelf: PyRef<Self>, args: DateReplaceArgs, vm: &VirtualMachine) -> PyResult {
+            let d = zelf.date;
+            let date = Date::new(
+                replace_field(args.year, d.year as i64, vm)?,
+                replace_field(args.month, d.month as i64, vm)?,
+                replace_field(args.day, d.day as i64, vm)?,
+                vm,
+            )?;
+            new_date(date, zelf.class(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            let state = vm.ctx.new_bytes(zelf.date.state().to_vec());
+            vm.new_tuple((zelf.class().to_owned(), (state,)))
+        }
+    }
+
+    impl AsNumber for PyDate {
+        fn as_number() -> &'static PyNumberMethods {
+            static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+                add: Some(PyDate::add_op),
+                subtract: Some(PyDate::subtract_op),
+                ..PyNumberMethods::NOT_IMPLEMENTED
+            };
+            &AS_NUMBER
+        }
+    }
+
+    impl Comparable for PyDate {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            _vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            // datetimes are dates too, but comparing only their date part would be wrong
+            let Some(other) = PyDate::of(other) else {
+                return Ok(PyComparisonValue::NotImplemented);
+            };
+            Ok(PyComparisonValue::Implemented(
+                op.eval_ord(zelf.date.cmp(&other)),
+            ))
+        }
+    }
+
+    impl Hashable for PyDate {
+        fn hash(zelf: &Py<Self>, _vm: &VirtualMachine) -> PyResult<PyHash> {
+            Ok(hash_i64(zelf.date.ordinal()))
+        }
+    }
+
+    impl Representable for PyDate {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let d = zelf.date;
+            Ok(format!(
+                "{}({}, {}, {})",
+                type_name::<Self>(zelf.as_object(), vm),
+                d.year,
+                d.month,
+                d.day
+            ))
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "time")]
+    #[derive(Debug, PyPayload)]
+    struct PyTime {
+        time: Time,
+        tzinfo: Option<PyObjectRef>,
+        fold: bool,
+    }
+
+    #[derive(FromArgs)]
+    struct TimeArgs {
+        #[pyarg(any, optional)]
+        hour: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        minute: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        second: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        microsecond: OptionalArg<PyObjectRef>,
+        #[pyarg(any, default)]
+        tzinfo: Option<PyObjectRef>,
+        #[pyarg(named, default = "0")]
+        fold: i64,
+    }
+
+    impl Constructor for PyTime {
+        type Args = TimeArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            if let OptionalArg::Present(hour) = &args.hour {
+                if let Some(state) = pickle_state(hour, 6, |s| s[0] & 0x7f < 24) {
+                    let tzinfo = match args.minute {
+                        OptionalArg::Present(tzinfo) => tzinfo_arg(Some(tzinfo), vm)?,
+                        OptionalArg::Missing => None,
+                    };
+                    let time = Time {
+                        hour: (state[0] & 0x7f) as u32,
+                        minute: state[1] as u32,
+                        second: state[2] as u32,
+                        microsecond: (state[3] as u32) << 16
+                            | (state[4] as u32) << 8
+                            | state[5] as u32,
+                    };
+                    let fold = state[0] & 0x80 != 0;
+                    return PyTime { time, tzinfo, fold }
+                        .into_ref_with_type(vm, cls)
+                        .map(Into::into);
+                }
+            }
+            let field =
+                |value: OptionalArg<PyObjectRef>| -> PyResult<i64> { replace_field(value, 0, vm) };
+            let time = Time::new(
+                field(args.hour)?,
+                field(args.minute)?,
+                field(args.second)?,
+                field(args.microsecond)?,
+                vm,
+            )?;
+            PyTime {
+                time,
+                tzinfo: tzinfo_arg(args.tzinfo, vm)?,
+                fold: fold_arg(args.fold, vm)?,
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    impl PyTime {
+        fn state(&self, protocol: i64) -> Vec<u8> {
+            let t = self.time;
+            let fold = if self.fold && protocol > 3 { 0x80 } else { 0 };
+            vec![
+                t.hour as u8 | fold,
+                t.minute as u8,
+                t.second as u8,
+                (t.microsecond >> 16) as u8,
+                (t.microsecond >> 8) as u8,
+                t.microsecond as u8,
+            ]
+        }
+
+        fn utcoffset_us(&self, vm: &VirtualMachine) -> PyResult<Option<i64>> {
+            call_offset(self.tzinfo.as_deref(), "utcoffset", vm.ctx.none(), vm)
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct TimeReplaceArgs {
+        #[pyarg(any, optional)]
+        hour: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        minute: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        second: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        microsecond: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        tzinfo: OptionalArg<PyObjectRef>,
+        #[pyarg(named, optional)]
+        fold: OptionalArg<i64>,
+    }
+
+    #[derive(FromArgs)]
+    struct IsoFormatArgs {
+        #[pyarg(any, optional)]
+        sep: OptionalArg<PyStrRef>,
+        #[pyarg(any, optional)]
+        timespec: OptionalArg<PyStrRef>,
+    }
+
+    #[derive(FromArgs)]
+    struct TimeSpecArgs {
+        #[pyarg(any, optional)]
+        timespec: OptionalArg<PyStrRef>,
+    }
+
+    fn timespec_arg(timespec: OptionalArg<PyStrRef>, vm: &VirtualMachine) -> PyResult<TimeSpec> {
+        match timespec {
+            OptionalArg::Present(name) => TimeSpec::from_name(name.as_str())
+                .ok_or_else(|| vm.new_value_error("Unknown timespec value".to_owned())),
+            OptionalArg::Missing => Ok(TimeSpec::Auto),
+        }
+    }
+
+    #[pyclass(
+        flags(BASETYPE),
+        with(Constructor, Comparable, Hashable, Representable)
+    )]
+    impl PyTime {
+        #[pyclassmethod]
+        fn fromisoformat(cls: PyTypeRef, s: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let s = fromisoformat_arg(&s, vm)?;
+            let text = s.as_str().strip_prefix('T').unwrap_or(s.as_str());
+            let (hour, minute, second, microsecond, offset) =
+                calendar::parse_iso_time(text).map_err(|e| iso_error(e, &s, vm))?;
+            let tzinfo = timezone_from_offset(offset, vm);
+            let args = (
+                hour,
+                minute,
+                second,
+                microsecond,
+                tzinfo.unwrap_or_else(|| vm.ctx.none()),
+            );
+            if cls.is(PyTime::class(&vm.ctx)) {
+                let time = Time {
+                    hour,
+                    minute,
+                    second,
+                    microsecond,
+                };
+                let tzinfo = tzinfo_arg(Some(args.4), vm)?;
+                Ok(PyTime {
+                    time,
+                    tzinfo,
+                    fold: false,
+                }
+                .into_ref(&vm.ctx)
+                .into())
+            } else {
+                cls.as_object().call(args, vm)
+            }
+        }
+
+        #[pygetset]
+        fn hour(&self) -> u32 {
+            self.time.hour
+        }
+
+        #[pygetset]
+        fn minute(&self) -> u32 {
+            self.time.minute
+        }
+
+        #[pygetset]
+        fn second(&self) -> u32 {
+            self.time.second
+        }
+
+        #[pygetset]
+        fn microsecond(&self) -> u32 {
+            self.time.microsecond
+        }
+
+        #[pygetset]
+        fn tzinfo(&self, vm: &VirtualMachine) -> PyObjectRef {
+            tzinfo_or_none(&self.tzinfo, vm)
+        }
+
+        #[pygetset]
+        fn fold(&self) -> i32 {
+            self.fold as i32
+        }
+
+        #[pymethod]
+        fn utcoffset(&self, vm: &VirtualMachine) -> PyResult {
+            Ok(offset_to_delta(self.utcoffset_us(vm)?, vm))
+        }
+
+        #[pymethod]
+        fn dst(&self, vm: &VirtualMachine) -> PyResult {
+            let dst = call_offset(self.tzinfo.as_deref(), "dst", vm.ctx.none(), vm)?;
+            Ok(offset_to_delta(dst, vm))
+        }
+
+        #[pymethod]
+        fn tzname(&self, vm: &VirtualMachine) -> PyResult<Option<PyStrRef>> {
+            call_tzname(self.tzinfo.as_deref(), vm.ctx.none(), vm)
+        }
+
+        #[pymethod]
+        fn isoformat(&self, args: TimeSpecArgs, vm: &VirtualMachine) -> PyResult<String> {
+            let mut s = self.time.isoformat(timespec_arg(args.timespec, vm)?);
+            if let Some(offset) = self.utcoffset_us(vm)? {
+                s.push_str(&calendar::format_offset(offset, ":"));
+            }
+            Ok(s)
+        }
+
+        #[pymethod(magic)]
+        fn str(&self, vm: &VirtualMachine) -> PyResult<String> {
+            self.isoformat(
+                TimeSpecArgs {
+                    timespec: OptionalArg::Missing,
+                },
+                vm,
+            )
+        }
+
+        #[pymethod]
+        fn strftime(&self, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            let date = Date {
+                year: 1900,
+                month: 1,
+                day: 1,
+            };
+            let timetuple = struct_time(date, self.time, -1, vm)?;
+            wrap_strftime(
+                format.as_str(),
+                timetuple,
+                self.time.microsecond,
+                self.tzinfo.as_deref(),
+                vm.ctx.none(),
+                vm,
+            )
+        }
+
+        #[pymethod(magic)]
+        fn format(zelf: PyRef<Self>, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            format_with(zelf.as_object(), format, vm)
+        }
+
+        #[pymethod]
+        fn replace(zelf: PyRef<Self>, args: TimeReplaceArgs, vm: &VirtualMachine) -> PyResult {
+            let t = zelf.time;
+            let time = Time::new(
+                replace_field(args.hour, t.hour as i64, vm)?,
+                replace_field(args.minute, t.minute as i64, vm)?,
+                replace_field(args.second, t.second as i64, vm)?,
+                replace_field(args.microsecond, t.microsecond as i64, vm)?,
+                vm,
+            )?;
+            let tzinfo = match args.tzinfo {
+                OptionalArg::Present(tzinfo) => tzinfo_arg(Some(tzinfo), vm)?,
+                OptionalArg::Missing => zelf.tzinfo.clone(),
+            };
+            let fold = match args.fold {
+                OptionalArg::Present(fold) => fold_arg(fold, vm)?,
+                OptionalArg::Missing => zelf.fold,
+            };
+            if zelf.class().is(PyTime::class(&vm.ctx)) {
+                return Ok(PyTime { time, tzinfo, fold }.into_ref(&vm.ctx).into());
+            }
+            let args = FuncArgs::new(
+                vec![
+                    vm.new_pyobj(time.hour),
+                    vm.new_pyobj(time.minute),
+                    vm.new_pyobj(time.second),
+                    vm.new_pyobj(time.microsecond),
+                    tzinfo_or_none(&tzinfo, vm),
+                ],
+                KwArgs::from_iter([("fold".to_owned(), vm.new_pyobj(fold as i32))]),
+            );
+            zelf.class().as_object().call(args, vm)
+        }
+
+        #[pymethod(magic)]
+        fn reduce_ex(zelf: PyRef<Self>, protocol: i64, vm: &VirtualMachine) -> PyTupleRef {
+            let state = vm.ctx.new_bytes(zelf.state(protocol));
+            let cls = zelf.class().to_owned();
+            match &zelf.tzinfo {
+                Some(tzinfo) => vm.new_tuple((cls, (state, tzinfo.clone()))),
+                None => vm.new_tuple((cls, (state,))),
+            }
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            Self::reduce_ex(zelf, 2, vm)
+        }
+    }
+
+    impl Comparable for PyTime {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            let Some(other) = other.payload_if_subclass::<PyTime>(vm) else {
+                return Ok(PyComparisonValue::NotImplemented);
+            };
+            let same_tzinfo = match (&zelf.tzinfo, &other.tzinfo) {
+                (Some(a), Some(b)) => a.is(b),
+                (None, None) => true,
+                _ => false,
+            };
+            let ordering = if same_tzinfo {
+                zelf.time.cmp(&other.time)
+            } else {
+                match (zelf.utcoffset_us(vm)?, other.utcoffset_us(vm)?) {
+                    (Some(a), Some(b)) => (zelf.time.to_us() - a).cmp(&(other.time.to_us() - b)),
+                    (None, None) => zelf.time.cmp(&other.time),
+                    _ => {
+                        return match op {
+                            PyComparisonOp::Eq => Ok(PyComparisonValue::Implemented(false)),
+                            PyComparisonOp::Ne => Ok(PyComparisonValue::Implemented(true)),
+                            _ => Err(vm.new_type_error(
+                                "can't compare offset-naive and offset-aware times".to_owned(),
+                            )),
+                        }
+                    }
+                }
+            };
+            Ok(PyComparisonValue::Implemented(op.eval_ord(ordering)))
+        }
+    }
+
+    impl Hashable for PyTime {
+        fn hash(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyHash> {
+            let offset = zelf.utcoffset_us(vm)?.unwrap_or(0);
+            Ok(hash_i64(zelf.time.to_us() - offset))
+        }
+    }
+
+    impl Representable for PyTime {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let t = zelf.time;
+            let mut fields = format!("{}, {}", t.hour, t.minute);
+            if t.second != 0 || t.microsecond != 0 {
+                fields.push_str(&format!(", {}", t.second));
+            }
+            if t.microsecond != 0 {
+                fields.push_str(&format!(", {}", t.microsecond));
+            }
+            if let Some(tzinfo) = &zelf.tzinfo {
+                fields.push_str(&format!(", tzinfo={}", tzinfo.repr(vm)?));
+            }
+            if zelf.fold {
+                fields.push_str(", fold=1");
+            }
+            Ok(format!(
+                "{}({fields})",
+                type_name::<Self>(zelf.as_object(), vm)
+            ))
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(module = "datetime", name = "datetime", base = "PyDate")]
+    #[derive(Debug, PyPayload)]
+    pub(crate) struct PyDateTime {
+        date: Date,
+        time: Time,
+        tzinfo: Option<PyObjectRef>,
+        fold: bool,
+    }
+
+    #[derive(FromArgs)]
+    pub(crate) struct DateTimeArgs {
+        #[pyarg(any)]
+        year: PyObjectRef,
+        #[pyarg(any, optional)]
+        month: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        day: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        hour: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        minute: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        second: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        microsecond: OptionalArg<PyObjectRef>,
+        #[pyarg(any, default)]
+        tzinfo: Option<PyObjectRef>,
+        #[pyarg(named, default = "0")]
+        fold: i64,
+    }
+
+    impl Constructor for PyDateTime {
+        type Args = DateTimeArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            if let Some(state) = pickle_state(&args.year, 10, valid_month) {
+                let tzinfo = match args.month {
+                    OptionalArg::Present(tzinfo) => tzinfo_arg(Some(tzinfo), vm)?,
+                    OptionalArg::Missing => None,
+                };
+                let datetime = PyDateTime {
+                    date: Date::from_state(&state[..4]),
+                    time: Time {
+                        hour: state[4] as u32,
+                        minute: state[5] as u32,
+                        second: state[6] as u32,
+                        microsecond: (state[7] as u32) << 16
+                            | (state[8] as u32) << 8
+                            | state[9] as u32,
+                    },
+                    tzinfo,
+                    fold: state[2] & 0x80 != 0,
+                };
+                return datetime.into_ref_with_type(vm, cls).map(Into::into);
+            }
+            let month = args.month.ok_or_else(|| missing_argument("month", 2, vm))?;
+            let day = args.day.ok_or_else(|| missing_argument("day", 3, vm))?;
+            let date = Date::new(
+                int_field(&args.year, vm)?,
+                int_field(&month, vm)?,
+                int_field(&day, vm)?,
+                vm,
+            )?;
+            let field =
+                |value: OptionalArg<PyObjectRef>| -> PyResult<i64> { replace_field(value, 0, vm) };
+            let time = Time::new(
+                field(args.hour)?,
+                field(args.minute)?,
+                field(args.second)?,
+                field(args.microsecond)?,
+                vm,
+            )?;
+            PyDateTime {
+                date,
+                time,
+                tzinfo: tzinfo_arg(args.tzinfo, vm)?,
+                fold: fold_arg(args.fold, vm)?,
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    /// A datetime of class `cls`, going through its constructor if it is a subclass.
+    fn new_datetime(
+        date: Date,
+        time: Time,
+        tzinfo: Option<PyObjectRef>,
+        fold: bool,
+        cls: &Py<PyType>,
+        vm: &VirtualMachine,
+    ) -> PyResult {
+        if cls.is(PyDateTime::class(&vm.ctx)) {
+            let datetime = PyDateTime {
+                date,
+                time,
+                tzinfo,
+                fold,
+            };
+            return Ok(datetime.into_ref(&vm.ctx).into());
+        }
+        let args = vec![
+            vm.new_pyobj(date.year),
+            vm.new_pyobj(date.month),
+            vm.new_pyobj(date.day),
+            vm.new_pyobj(time.hour),
+            vm.new_pyobj(time.minute),
+            vm.new_pyobj(time.second),
+            vm.new_pyobj(time.microsecond),
+            tzinfo_or_none(&tzinfo, vm),
+        ];
+        let kwargs: KwArgs = if fold {
+            KwArgs::from_iter([("fold".to_owned(), vm.new_pyobj(1))])
+        } else {
+            KwArgs::default()
+        };
+        cls.as_object().call(FuncArgs::new(args, kwargs), vm)
+    }
+
+    impl PyDateTime {
+        fn naive_us(&self) -> i64 {
+            naive_us(self.date, self.time)
+        }
+
+        pub(crate) fn tzinfo(&self) -> Option<&PyObject> {
+            self.tzinfo.as_deref()
+        }
+
+        pub(crate) fn fold(&self) -> bool {
+            self.fold
+        }
+
+        /// The wall clock reading as Unix seconds, ignoring any timezone.
+        pub(crate) fn wall_seconds(&self) -> i64 {
+            naive_to_unix_seconds(self.date, self.time)
+        }
+
+        /// A datetime of the same class and tzinfo as `dt`, moved by `us` microseconds.
+        fn shift_object(dt: &PyObject, us: i64, vm: &VirtualMachine) -> PyResult {
+            let zelf = dt.payload_if_subclass::<PyDateTime>(vm).unwrap();
+            zelf.shifted(dt.class(), us, false, vm)
+        }
+
+        /// A datetime with the same tzinfo, moved by `us` microseconds and with the given fold.
+        pub(crate) fn shifted(
+            &self,
+            cls: &Py<PyType>,
+            us: i64,
+            fold: bool,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let (date, time) = self
+                .naive_us()
+                .checked_add(us)
+                .and_then(split_naive_us)
+                .ok_or_else(|| out_of_range(vm))?;
+            new_datetime(date, time, self.tzinfo.clone(), fold, cls, vm)
+        }
+
+        fn utcoffset_us(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<Option<i64>> {
+            call_offset(zelf.tzinfo(), "utcoffset", zelf.to_owned().into(), vm)
+        }
+
+        fn of(obj: &PyObject) -> Option<PyRef<Self>> {
+            obj.downcast_ref::<PyDateTime>().map(|dt| dt.to_owned())
+        }
+
+        /// The Unix timestamp of this datetime, as whole seconds.
+        fn timestamp_seconds(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<i64> {
+            match Self::utcoffset_us(zelf, vm)? {
+                Some(offset) => Ok((zelf.naive_us() - offset).div_euclid(US_PER_SECOND)
+                    - EPOCH_ORDINAL * calendar::SECONDS_PER_DAY),
+                None => local_to_timestamp(zelf.wall_seconds(), zelf.fold, vm),
+            }
+        }
+
+        fn from_timestamp(
+            cls: &Py<PyType>,
+            (seconds, microsecond): (i64, u32),
+            tz: Option<PyObjectRef>,
+            utc: bool,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let (date, time, fold) = if utc || tz.is_some() {
+                let (date, time) = unix_seconds_to_naive(seconds, microsecond)
+                    .ok_or_else(|| vm.new_value_error("year is out of range".to_owned()))?;
+                (date, time, false)
+            } else {
+                local_fields(seconds, microsecond, vm)?
+            };
+            match tz {
+                Some(tz) => {
+                    let utc = new_datetime(date, time, Some(tz.clone()), false, cls, vm)?;
+                    vm.call_method(&tz, "fromutc", (utc,))
+                }
+                None => new_datetime(date, time, None, fold, cls, vm),
+            }
+        }
+
+        fn add_op(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let (datetime, delta) = match (Self::of(a), PyTimeDelta::of(b, vm)) {
+                (Some(datetime), Some(delta)) => (datetime, delta),
+                _ => match (PyTimeDelta::of(a, vm), Self::of(b)) {
+                    (Some(delta), Some(datetime)) => (datetime, delta),
+                    _ => return Ok(vm.ctx.not_implemented()),
+                },
+            };
+            let us = i64::try_from(delta.total_us()).map_err(|_| out_of_range(vm))?;
+            datetime.shifted(datetime.class(), us, false, vm)
+        }
+
+        fn subtract_op(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+            let Some(zelf) = Self::of(a) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            if let Some(delta) = PyTimeDelta::of(b, vm) {
+                let us = i64::try_from(-delta.total_us()).map_err(|_| out_of_range(vm))?;
+                return zelf.shifted(zelf.class(), us, false, vm);
+            }
+            let Some(other) = Self::of(b) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            let same_tzinfo = match (&zelf.tzinfo, &other.tzinfo) {
+                (Some(a), Some(b)) => a.is(b),
+                _ => false,
+            };
+            let (offset_a, offset_b) = if same_tzinfo {
+                (0, 0)
+            } else {
+                match (
+                    Self::utcoffset_us(&zelf, vm)?,
+                    Self::utcoffset_us(&other, vm)?,
+                ) {
+                    (Some(a), Some(b)) => (a, b),
+                    (None, None) => (0, 0),
+                    _ => {
+                        return Err(vm.new_type_error(
+                            "can't subtract offset-naive and offset-aware datetimes".to_owned(),
+                        ))
+                    }
+                }
+            };
+            let us = (zelf.naive_us() - offset_a) - (other.naive_us() - offset_b);
+            Ok(PyTimeDelta::from_us(us as i128).unwrap().new_object(vm))
+        }
+
+        /// Whether a fold-dependent offset makes two otherwise equal datetimes unequal.
+        fn fold_makes_unequal(
+            zelf: &Py<Self>,
+            other: &Py<Self>,
+            offsets: (Option<i64>, Option<i64>),
+            vm: &VirtualMachine,
+        ) -> PyResult<bool> {
+            for (dt, offset) in [(zelf, offsets.0), (other, offsets.1)] {
+                let flipped = PyDateTime {
+                    date: dt.date,
+                    time: dt.time,
+                    tzinfo: dt.tzinfo.clone(),
+                    fold: !dt.fold,
+                }
+                .into_ref(&vm.ctx);
+                if call_offset(dt.tzinfo(), "utcoffset", flipped.into(), vm)? != offset {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DateTimeReplaceArgs {
+        #[pyarg(any, optional)]
+        year: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        month: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        day: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        hour: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        minute: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        second: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        microsecond: OptionalArg<PyObjectRef>,
+        #[pyarg(any, optional)]
+        tzinfo: OptionalArg<PyObjectRef>,
+        #[pyarg(named, optional)]
+        fold: OptionalArg<i64>,
+    }
+
+    #[derive(FromArgs)]
+    struct TzArgs {
+        #[pyarg(any, default)]
+        tz: Option<PyObjectRef>,
+    }
+
+    #[derive(FromArgs)]
+    struct FromTimestampArgs {
+        #[pyarg(any)]
+        timestamp: PyObjectRef,
+        #[pyarg(any, default)]
+        tz: Option<PyObjectRef>,
+    }
+
+    #[derive(FromArgs)]
+    struct CombineArgs {
+        #[pyarg(any)]
+        date: PyObjectRef,
+        #[pyarg(any)]
+        time: PyRef<PyTime>,
+        #[pyarg(any, optional)]
+        tzinfo: OptionalArg<PyObjectRef>,
+    }
+
+    #[pyclass(
+        flags(BASETYPE),
+        with(Constructor, AsNumber, Comparable, Hashable, Representable)
+    )]
+    impl PyDateTime {
+        #[pyclassmethod]
+        fn now(cls: PyTypeRef, args: TzArgs, vm: &VirtualMachine) -> PyResult {
+            let tz = tzinfo_arg(args.tz, vm)?;
+            Self::from_timestamp(&cls, now(vm)?, tz, false, vm)
+        }
+
+        #[pyclassmethod]
+        fn utcnow(cls: PyTypeRef, vm: &VirtualMachine) -> PyResult {
+            let (seconds, microsecond) = now(vm)?;
+            let (date, time) = unix_seconds_to_naive(seconds, microsecond).unwrap();
+            new_datetime(date, time, None, false, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn fromtimestamp(cls: PyTypeRef, args: FromTimestampArgs, vm: &VirtualMachine) -> PyResult {
+            let tz = tzinfo_arg(args.tz, vm)?;
+            let timestamp = split_timestamp(&args.timestamp, vm)?;
+            Self::from_timestamp(&cls, timestamp, tz, false, vm)
+        }
+
+        #[pyclassmethod]
+        fn utcfromtimestamp(
+            cls: PyTypeRef,
+            timestamp: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let timestamp = split_timestamp(&timestamp, vm)?;
+            Self::from_timestamp(&cls, timestamp, None, true, vm)
+        }
+
+        #[pyclassmethod]
+        fn combine(cls: PyTypeRef, args: CombineArgs, vm: &VirtualMachine) -> PyResult {
+            let date = if let Some(datetime) = args.date.payload_if_subclass::<PyDateTime>(vm) {
+                datetime.date
+            } else if let Some(date) = PyDate::of(&args.date) {
+                date
+            } else {
+                return Err(vm.new_type_error(format!(
+                    "combine() argument 1 must be datetime.date, not {}",
+                    args.date.class().name()
+                )));
+            };
+            let tzinfo = match args.tzinfo {
+                OptionalArg::Present(tzinfo) => tzinfo_arg(Some(tzinfo), vm)?,
+                OptionalArg::Missing => args.time.tzinfo.clone(),
+            };
+            new_datetime(date, args.time.time, tzinfo, args.time.fold, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn fromisoformat(cls: PyTypeRef, s: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let s = fromisoformat_arg(&s, vm)?;
+            let (date_part, time_part) = calendar::split_iso_datetime(s.as_str());
+            let (year, month, day) =
+                calendar::parse_iso_date(date_part).map_err(|e| iso_error(e, &s, vm))?;
+            let (time, offset) = match time_part {
+                Some(time_part) => {
+                    let (hour, minute, second, microsecond, offset) =
+                        calendar::parse_iso_time(time_part).map_err(|e| iso_error(e, &s, vm))?;
+                    let time = Time {
+                        hour,
+                        minute,
+                        second,
+                        microsecond,
+                    };
+                    (time, offset)
+                }
+                None => (Time::default(), None),
+            };
+            let tzinfo = timezone_from_offset(offset, vm);
+            new_datetime(Date { year, month, day }, time, tzinfo, false, &cls, vm)
+        }
+
+        #[pyclassmethod]
+        fn strptime(
+            cls: PyTypeRef,
+            string: PyStrRef,
+            format: PyStrRef,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let strptime = vm.import("_strptime", 0)?;
+            vm.call_method(&strptime, "_strptime_datetime", (cls, string, format))
+        }
+
+        #[pygetset]
+        pub(crate) fn year(&self) -> i32 {
+            self.date.year
+        }
+
+        #[pygetset]
+        fn month(&self) -> u32 {
+            self.date.month
+        }
+
+        #[pygetset]
+        fn day(&self) -> u32 {
+            self.date.day
+        }
+
+        #[pygetset]
+        fn hour(&self) -> u32 {
+            self.time.hour
+        }
+
+        #[pygetset]
+        fn minute(&self) -> u32 {
+            self.time.minute
+        }
+
+        #[pygetset]
+        fn second(&self) -> u32 {
+            self.time.second
+        }
+
+        #[pygetset]
+        fn microsecond(&self) -> u32 {
+            self.time.microsecond
+        }
+
+        #[pygetset(name = "tzinfo")]
+        fn tzinfo_attr(&self, vm: &VirtualMachine) -> PyObjectRef {
+            tzinfo_or_none(&self.tzinfo, vm)
+        }
+
+        #[pygetset(name = "fold")]
+        fn fold_attr(&self) -> i32 {
+            self.fold as i32
+        }
+
+        #[pymethod]
+        fn date(&self, vm: &VirtualMachine) -> PyRef<PyDate> {
+            PyDate { date: self.date }.into_ref(&vm.ctx)
+        }
+
+        #[pymethod]
+        fn time(&self, vm: &VirtualMachine) -> PyRef<PyTime> {
+            PyTime {
+                time: self.time,
+                tzinfo: None,
+                fold: self.fold,
+            }
+            .into_ref(&vm.ctx)
+        }
+
+        #[pymethod]
+        fn timetz(&self, vm: &VirtualMachine) -> PyRef<PyTime> {
+            PyTime {
+                time: self.time,
+                tzinfo: self.tzinfo.clone(),
+                fold: self.fold,
+            }
+            .into_ref(&vm.ctx)
+        }
+
+        #[pymethod]
+        fn toordinal(&self) -> i64 {
+            self.date.ordinal()
+        }
+
+        #[pymethod]
+        fn weekday(&self) -> u32 {
+            self.date.weekday()
+        }
+
+        #[pymethod]
+        fn isoweekday(&self) -> u32 {
+            self.date.weekday() + 1
+        }
+
+        #[pymethod]
+        fn isocalendar(&self, vm: &VirtualMachine) -> PyTupleRef {
+            self.date.iso_calendar(vm)
+        }
+
+        #[pymethod]
+        fn utcoffset(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            Ok(offset_to_delta(Self::utcoffset_us(&zelf, vm)?, vm))
+        }
+
+        #[pymethod]
+        fn dst(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            let dst = call_offset(zelf.tzinfo(), "dst", zelf.clone().into(), vm)?;
+            Ok(offset_to_delta(dst, vm))
+        }
+
+        #[pymethod]
+        fn tzname(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<Option<PyStrRef>> {
+            call_tzname(zelf.tzinfo(), zelf.clone().into(), vm)
+        }
+
+        #[pymethod]
+        fn timetuple(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            let dst = match call_offset(zelf.tzinfo(), "dst", zelf.clone().into(), vm)? {
+                None => -1,
+                Some(0) => 0,
+                Some(_) => 1,
+            };
+            struct_time(zelf.date, zelf.time, dst, vm)
+        }
+
+        #[pymethod]
+        fn utctimetuple(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            let offset = Self::utcoffset_us(&zelf, vm)?.unwrap_or(0);
+            let (date, time) =
+                split_naive_us(zelf.naive_us() - offset).ok_or_else(|| out_of_range(vm))?;
+            struct_time(date, time, 0, vm)
+        }
+
+        #[pymethod]
+        fn timestamp(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<f64> {
+            let seconds = Self::timestamp_seconds(&zelf, vm)?;
+            let microsecond = match Self::utcoffset_us(&zelf, vm)? {
+                Some(offset) => (zelf.naive_us() - offset).rem_euclid(US_PER_SECOND),
+                None => zelf.time.microsecond as i64,
+            };
+            Ok(seconds as f64 + microsecond as f64 / 1e6)
+        }
+
+        #[pymethod]
+        fn astimezone(zelf: PyRef<Self>, args: TzArgs, vm: &VirtualMachine) -> PyResult {
+            let tz = tzinfo_arg(args.tz, vm)?;
+            if let (Some(tz), Some(current)) = (&tz, &zelf.tzinfo) {
+                if tz.is(current) {
+                    return Ok(zelf.into());
+                }
+            }
+            let offset = match Self::utcoffset_us(&zelf, vm)? {
+                Some(offset) => offset,
+                None => {
+                    let timestamp = local_to_timestamp(zelf.wall_seconds(), zelf.fold, vm)?;
+                    (zelf.wall_seconds() - timestamp) * US_PER_SECOND
+                }
+            };
+            let (date, time) =
+                split_naive_us(zelf.naive_us() - offset).ok_or_else(|| out_of_range(vm))?;
+            let tz = match tz {
+                Some(tz) => tz,
+                None => {
+                    // a fixed-offset zone for the local time at that instant
+                    let timestamp = naive_to_unix_seconds(date, time);
+                    let (offset, name) = local_offset(timestamp, vm)?;
+                    let name = name.map(|name| vm.ctx.new_str(name));
+                    PyTimeZone {
+                        offset: PyTimeDelta::from_us(offset as i128 * US_PER_SECOND as i128)
+                            .unwrap(),
+                        name,
+                    }
+                    .into_ref(&vm.ctx)
+                    .into()
+                }
+            };
+            let utc = new_datetime(date, time, Some(tz.clone()), false, zelf.class(), vm)?;
+            vm.call_method(&tz, "fromutc", (utc,))
+        }
+
+        #[pymethod]
+        fn isoformat(
+            zelf: PyRef<Self>,
+            args: IsoFormatArgs,
+            vm: &VirtualMachine,
+        ) -> PyResult<String> {
+            let sep = match args.sep {
+                OptionalArg::Present(sep) => {
+                    let mut chars = sep.as_str().chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => {
+                            return Err(vm.new_type_error(
+                                "isoformat() argument 1 must be a unicode character, not str"
+                                    .to_owned(),
+                            ))
+                        }
+                    }
+                }
+                OptionalArg::Missing => 'T',
+            };
+            let spec = timespec_arg(args.timespec, vm)?;
+            let mut s = format!(
+                "{}{sep}{}",
+                zelf.date.isoformat(),
+                zelf.time.isoformat(spec)
+            );
+            if let Some(offset) = Self::utcoffset_us(&zelf, vm)? {
+                s.push_str(&calendar::format_offset(offset, ":"));
+            }
+            Ok(s)
+        }
+
+        #[pymethod(magic)]
+        fn add(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::add_op(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn radd(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::add_op(&other, zelf.as_object(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn sub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Self::subtract_op(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn str(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let args = IsoFormatArgs {
+                sep: OptionalArg::Present(vm.ctx.new_str(" ")),
+                timespec: OptionalArg::Missing,
+            };
+            Self::isoformat(zelf, args, vm)
+        }
+
+        #[pymethod]
+        fn ctime(&self) -> String {
+            let (d, t) = (self.date, self.time);
+            calendar::format_ctime(d.year, d.month, d.day, t.hour, t.minute, t.second)
+        }
+
+        #[pymethod]
+        fn strftime(zelf: PyRef<Self>, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            let timetuple = Self::timetuple(zelf.clone(), vm)?;
+            wrap_strftime(
+                format.as_str(),
+                timetuple,
+                zelf.time.microsecond,
+                zelf.tzinfo(),
+                zelf.clone().into(),
+                vm,
+            )
+        }
+
+        #[pymethod(magic)]
+        fn format(zelf: PyRef<Self>, format: PyStrRef, vm: &VirtualMachine) -> PyResult {
+            format_with(zelf.as_object(), format, vm)
+        }
+
+        #[pymethod]
+        fn replace(zelf: PyRef<Self>, args: DateTimeReplaceArgs, vm: &VirtualMachine) -> PyResult {
+            let (d, t) = (zelf.date, zelf.time);
+            let date = Date::new(
+                replace_field(args.year, d.year as i64, vm)?,
+                replace_field(args.month, d.month as i64, vm)?,
+                replace_field(args.day, d.day as i64, vm)?,
+                vm,
+            )?;
+            let time = Time::new(
+                replace_field(args.hour, t.hour as i64, vm)?,
+                replace_field(args.minute, t.minute as i64, vm)?,
+                replace_field(args.second, t.second as i64, vm)?,
+                replace_field(args.microsecond, t.microsecond as i64, vm)?,
+                vm,
+            )?;
+            let tzinfo = match args.tzinfo {
+                OptionalArg::Present(tzinfo) => tzinfo_arg(Some(tzinfo), vm)?,
+                OptionalArg::Missing => zelf.tzinfo.clone(),
+            };
+            let fold = match args.fold {
+                OptionalArg::Present(fold) => fold_arg(fold, vm)?,
+                OptionalArg::Missing => zelf.fold,
+            };
+            new_datetime(date, time, tzinfo, fold, zelf.class(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn reduce_ex(zelf: PyRef<Self>, protocol: i64, vm: &VirtualMachine) -> PyTupleRef {
+            let mut state = zelf.date.state().to_vec();
+            if zelf.fold && protocol > 3 {
+                state[2] |= 0x80;
+            }
+            let (t, us) = (zelf.time, zelf.time.microsecond);
+            state.extend([
+                t.hour as u8,
+                t.minute as u8,
+                t.second as u8,
+                (us >> 16) as u8,
+                (us >> 8) as u8,
+                us as u8,
+            ]);
+            let state = vm.ctx.new_bytes(state);
+            let cls = zelf.class().to_owned();
+            match &zelf.tzinfo {
+                Some(tzinfo) => vm.new_tuple((cls, (state, tzinfo.clone()))),
+                None => vm.new_tuple((cls, (state,))),
+            }
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            Self::reduce_ex(zelf, 2, vm)
+        }
+    }
+
+    impl AsNumber for PyDateTime {
+        fn as_number() -> &'static PyNumberMethods {
+            static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+                add: Some(PyDateTime::add_op),
+                subtract: Some(PyDateTime::subtract_op),
+                ..PyNumberMethods::NOT_IMPLEMENTED
+            };
+            &AS_NUMBER
+        }
+    }
+
+    impl Comparable for PyDateTime {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            let Some(other) = PyDateTime::of(other) else {
+                if PyDate::of(other).is_some() {
+                    return match op {
+                        PyComparisonOp::Eq => Ok(PyComparisonValue::Implemented(false)),
+                        PyComparisonOp::Ne => Ok(PyComparisonValue::Implemented(true)),
+                        _ => Err(vm.new_type_error(format!(
+                            "can't compare {} to {}",
+                            zelf.class().name(),
+                            other.class().name()
+                        ))),
+                    };
+                }
+                return Ok(PyComparisonValue::NotImplemented);
+            };
+            let same_tzinfo = match (&zelf.tzinfo, &other.tzinfo) {
+                (Some(a), Some(b)) => a.is(b),
+                (None, None) => true,
+                _ => false,
+            };
+            if same_tzinfo {
+                return Ok(PyComparisonValue::Implemented(
+                    op.eval_ord(zelf.naive_us().cmp(&other.naive_us())),
+                ));
+            }
+            let offsets = (
+                PyDateTime::utcoffset_us(zelf, vm)?,
+                PyDateTime::utcoffset_us(&other, vm)?,
+            );
+            let ordering = match offsets {
+                (Some(a), Some(b)) => (zelf.naive_us() - a).cmp(&(other.naive_us() - b)),
+                (None, None) => zelf.naive_us().cmp(&other.naive_us()),
+                _ => {
+                    return match op {
+                        PyComparisonOp::Eq => Ok(PyComparisonValue::Implemented(false)),
+                        PyComparisonOp::Ne => Ok(PyComparisonValue::Implemented(true)),
+                        _ => Err(vm.new_type_error(
+                            "can't compare offset-naive and offset-aware datetimes".to_owned(),
+                        )),
+                    }
+                }
+            };
+            let ordering = if ordering == Ordering::Equal
+                && matches!(op, PyComparisonOp::Eq | PyComparisonOp::Ne)
+                && PyDateTime::fold_makes_unequal(zelf, &other, offsets, vm)?
+            {
+                Ordering::Less
+            } else {
+                ordering
+            };
+            Ok(PyComparisonValue::Implemented(op.eval_ord(ordering)))
+        }
+    }
+
+    impl Hashable for PyDateTime {
+        fn hash(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyHash> {
+            // hash as if fold were 0, so that the two readings of a fold hash alike
+            let offset = if zelf.fold {
+                let unfolded = PyDateTime {
+                    date: zelf.date,
+                    time: zelf.time,
+                    tzinfo: zelf.tzinfo.clone(),
+                    fold: false,
+                }
+                .into_ref(&vm.ctx);
+                call_offset(zelf.tzinfo(), "utcoffset", unfolded.into(), vm)?
+            } else {
+                PyDateTime::utcoffset_us(zelf, vm)?
+            };
+            Ok(hash_i64(zelf.naive_us() - offset.unwrap_or(0)))
+        }
+    }
+
+    impl Representable for PyDateTime {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let (d, t) = (zelf.date, zelf.time);
+            let mut fields = format!(
+                "{}, {}, {}, {}, {}",
+                d.year, d.month, d.day, t.hour, t.minute
+            );
+            if t.second != 0 || t.microsecond != 0 {
+                fields.push_str(&format!(", {}", t.second));
+            }
+            if t.microsecond != 0 {
+                fields.push_str(&format!(", {}", t.microsecond));
+            }
+            if let Some(tzinfo) = &zelf.tzinfo {
+                fields.push_str(&format!(", tzinfo={}", tzinfo.repr(vm)?));
+            }
+            if zelf.fold {
+                fields.push_str(", fold=1");
+            }
+            Ok(format!(
+                "{}({fields})",
+                type_name::<Self>(zelf.as_object(), vm)
+            ))
+        }
+    }
+
+    /// Create the classes other native modules make instances of or check for.
+    pub(crate) fn init_classes(ctx: &Context) {
+        PyTzInfo::make_class(ctx);
+        PyTimeDelta::make_class(ctx);
+        PyDate::make_class(ctx);
+        PyDateTime::make_class(ctx);
+    }
+
+    /// Set the `min`, `max`, `resolution` and `utc` class attributes, which are instances of
+    /// the classes themselves.
+    pub(super) fn init_class_attrs(vm: &VirtualMachine) {
+        let ctx = &vm.ctx;
+        let set = |cls: &'static Py<PyType>, name: &'static str, value: PyObjectRef| {
+            cls.set_attr(ctx.intern_str(name), value);
+        };
+        let delta = |us: i128| PyTimeDelta::from_us(us).unwrap().new_object(vm);
+        let max_delta_us = (MAX_DELTA_DAYS as i128 + 1) * US_PER_DAY as i128 - 1;
+
+        let timedelta = PyTimeDelta::class(ctx);
+        set(
+            timedelta,
+            "min",
+            delta(-MAX_DELTA_DAYS as i128 * US_PER_DAY as i128),
+        );
+        set(timedelta, "max", delta(max_delta_us));
+        set(timedelta, "resolution", delta(1));
+
+        let min_date = Date::from_ordinal(1).unwrap();
+        let max_date = Date::from_ordinal(calendar::MAX_ORDINAL).unwrap();
+        let date = PyDate::class(ctx);
+        set(date, "min", PyDate { date: min_date }.into_ref(ctx).into());
+        set(date, "max", PyDate { date: max_date }.into_ref(ctx).into());
+        set(date, "resolution", delta(US_PER_DAY as i128));
+
+        let max_time = Time::from_us(US_PER_DAY - 1);
+        let time = |time: Time| -> PyObjectRef {
+            PyTime {
+                time,
+                tzinfo: None,
+                fold: false,
+            }
+            .into_ref(ctx)
+            .into()
+        };
+        let time_class = PyTime::class(ctx);
+        set(time_class, "min", time(Time::default()));
+        set(time_class, "max", time(max_time));
+        set(time_class, "resolution", delta(1));
+
+        let datetime = |date: Date, time: Time| -> PyObjectRef {
+            PyDateTime {
+                date,
+                time,
+                tzinfo: None,
+                fold: false,
+            }
+            .into_ref(ctx)
+            .into()
+        };
+        let datetime_class = PyDateTime::class(ctx);
+        set(datetime_class, "min", datetime(min_date, Time::default()));
+        set(datetime_class, "max", datetime(max_date, max_time));
+        set(datetime_class, "resolution", delta(1));
+
+        let timezone = |minutes: i128| -> PyObjectRef {
+            PyTimeZone {
+                offset: PyTimeDelta::from_us(minutes * 60 * US_PER_SECOND as i128).unwrap(),
+                name: None,
+            }
+            .into_ref(ctx)
+            .into()
+        };
+        let timezone_class = PyTimeZone::class(ctx);
+        set(timezone_class, "utc", utc(vm).into());
+        set(timezone_class, "min", timezone(-(23 * 60 + 59)));
+        set(timezone_class, "max", timezone(23 * 60 + 59));
+    }
+}
@@ -0,0 +1,424 @@
+//! Proleptic Gregorian calendar arithmetic and ISO 8601 parsing, independent of the VM.
+//!
+//! Ordinals count days from 0001-01-01, which is day 1.
+
+pub const MINYEAR: i32 = 1;
+pub const MAXYEAR: i32 = 9999;
+/// The ordinal of 9999-12-31.
+pub const MAX_ORDINAL: i64 = 3_652_059;
+
+pub const US_PER_SECOND: i64 = 1_000_000;
+pub const SECONDS_PER_DAY: i64 = 24 * 3600;
+pub const US_PER_DAY: i64 = SECONDS_PER_DAY * US_PER_SECOND;
+
+const DAYS_IN_MONTH: [u32; 13] = [0, 31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+const DAYS_BEFORE_MONTH: [u32; 13] = [0, 0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+
+/// Number of days in 400, 100 and 4 years.
+const DI400Y: i64 = 146_097;
+const DI100Y: i64 = 36_524;
+const DI4Y: i64 = 1_461;
+
+pub fn is_leap(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    if month == 2 && is_leap(year) {
+        29
+    } else {
+        DAYS_IN_MONTH[month as usize]
+    }
+}
+
+/// Days before January 1st of `year`.
+pub fn days_before_year(year: i32) -> i64 {
+    let y = year as i64 - 1;
+    y * 365 + y / 4 - y / 100 + y / 400
+}
+
+/// Days in `year` before the first of `month`.
+pub fn days_before_month(year: i32, month: u32) -> i64 {
+    let leap = (month > 2 && is_leap(year)) as i64;
+    DAYS_BEFORE_MONTH[month as usize] as i64 + leap
+}
+
+pub fn ymd_to_ord(year: i32, month: u32, day: u32) -> i64 {
+    days_before_year(year) + days_before_month(year, month) + day as i64
+}
+
+pub fn ord_to_ymd(ordinal: i64) -> (i32, u32, u32) {
+    // Shift so that 0001-01-01 is day 0, then peel off 400, 100, 4 and 1 year cycles.
+    let mut n = ordinal - 1;
+    let n400 = n.div_euclid(DI400Y);
+    n = n.rem_euclid(DI400Y);
+    let mut year = n400 * 400 + 1;
+
+    let n100 = n / DI100Y;
+    n %= DI100Y;
+    let n4 = n / DI4Y;
+    n %= DI4Y;
+    let n1 = n / 365;
+    n %= 365;
+
+    year += n100 * 100 + n4 * 4 + n1;
+    if n1 == 4 || n100 == 4 {
+        // the last day of a leap cycle
+        return (year as i32 - 1, 12, 31);
+    }
+
+    let year = year as i32;
+    let leap = n1 == 3 && (n4 != 24 || n100 == 3);
+    debug_assert_eq!(leap, is_leap(year));
+    // estimate the month, then correct by at most one
+    let mut month = ((n + 50) >> 5) as u32;
+    let mut preceding = days_before_month(year, month);
+    if preceding > n {
+        month -= 1;
+        preceding -= days_in_month(year, month) as i64;
+    }
+    (year, month, (n - preceding + 1) as u32)
+}
+
+/// Day of the week, where Monday is 0.
+pub fn weekday(year: i32, month: u32, day: u32) -> u32 {
+    ((ymd_to_ord(year, month, day) + 6) % 7) as u32
+}
+
+/// The ordinal of the Monday of ISO week 1 in `year`.
+pub fn iso_week1_monday(year: i32) -> i64 {
+    let first_day = ymd_to_ord(year, 1, 1);
+    let first_weekday = (first_day + 6) % 7;
+    let mut week1_monday = first_day - first_weekday;
+    if first_weekday > 3 {
+        week1_monday += 7;
+    }
+    week1_monday
+}
+
+/// The ISO year, week and weekday (Monday is 1) of a date.
+pub fn iso_calendar(year: i32, month: u32, day: u32) -> (i32, u32, u32) {
+    let today = ymd_to_ord(year, month, day);
+    let mut year = year;
+    let mut week1_monday = iso_week1_monday(year);
+    let mut week = (today - week1_monday).div_euclid(7);
+    let day = (today - week1_monday).rem_euclid(7);
+    if week < 0 {
+        year -= 1;
+        week1_monday = iso_week1_monday(year);
+        week = (today - week1_monday).div_euclid(7);
+    } else if week >= 52 && today >= iso_week1_monday(year + 1) {
+        year += 1;
+        week = 0;
+    }
+    (year, week as u32 + 1, day as u32 + 1)
+}
+
+/// The date of the given ISO year, week and weekday.
+pub fn iso_to_ymd(year: i32, week: u32, day: u32) -> Result<(i32, u32, u32), String> {
+    if !(MINYEAR..=MAXYEAR).contains(&year) {
+        return Err(format!("Year is out of range: {year}"));
+    }
+    if week == 0 || week > 53 {
+        return Err(format!("Invalid week: {week}"));
+    }
+    if week == 53 {
+        // only years starting on a Thursday, or leap years starting on a Wednesday, have 53 weeks
+        let first_weekday = ymd_to_ord(year, 1, 1) % 7;
+        if !(first_weekday == 4 || (first_weekday == 3 && is_leap(year))) {
+            return Err(format!("Invalid week: {week}"));
+        }
+    }
+    if day == 0 || day > 7 {
+        return Err(format!("Invalid weekday: {day} (range is [1, 7])"));
+    }
+    let ordinal = iso_week1_monday(year) + (week as i64 - 1) * 7 + day as i64 - 1;
+    Ok(ord_to_ymd(ordinal))
+}
+
+pub fn check_date(year: i64, month: i64, day: i64) -> Result<(i32, u32, u32), String> {
+    if !(MINYEAR as i64..=MAXYEAR as i64).contains(&year) {
+        return Err(format!("year {year} is out of range"));
+    }
+    if !(1..=12).contains(&month) {
+        return Err("month must be in 1..12".to_owned());
+    }
+    let (year, month) = (year as i32, month as u32);
+    if day < 1 || day > days_in_month(year, month) as i64 {
+        return Err("day is out of range for month".to_owned());
+    }
+    Ok((year, month, day as u32))
+}
+
+pub fn check_time(
+    hour: i64,
+    minute: i64,
+    second: i64,
+    microsecond: i64,
+) -> Result<(), &'static str> {
+    if !(0..24).contains(&hour) {
+        Err("hour must be in 0..23")
+    } else if !(0..60).contains(&minute) {
+        Err("minute must be in 0..59")
+    } else if !(0..60).contains(&second) {
+        Err("second must be in 0..59")
+    } else if !(0..1_000_000).contains(&microsecond) {
+        Err("microsecond must be in 0..999999")
+    } else {
+        Ok(())
+    }
+}
+
+/// How much of a time `isoformat` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    Auto,
+    Hours,
+    Minutes,
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl TimeSpec {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "auto" => Self::Auto,
+            "hours" => Self::Hours,
+            "minutes" => Self::Minutes,
+            "seconds" => Self::Seconds,
+            "milliseconds" => Self::Milliseconds,
+            "microseconds" => Self::Microseconds,
+            _ => return None,
+        })
+    }
+}
+
+pub fn format_time(
+    hour: u32,
+    minute: u32,
+    second: u32,
+    microsecond: u32,
+    spec: TimeSpec,
+) -> String {
+    match spec {
+        TimeSpec::Auto if microsecond == 0 => format!("{hour:02}:{minute:02}:{second:02}"),
+        TimeSpec::Auto | TimeSpec::Microseconds => {
+            format!("{hour:02}:{minute:02}:{second:02}.{microsecond:06}")
+        }
+        TimeSpec::Hours => format!("{hour:02}"),
+        TimeSpec::Minutes => format!("{hour:02}:{minute:02}"),
+        TimeSpec::Seconds => format!("{hour:02}:{minute:02}:{second:02}"),
+        TimeSpec::Milliseconds => {
+            let millisecond = microsecond / 1000;
+            format!("{hour:02}:{minute:02}:{second:02}.{millisecond:03}")
+        }
+    }
+}
+
+/// Format a UTC offset given in microseconds as `+HH<sep>MM[<sep>SS[.ffffff]]`.
+pub fn format_offset(offset_us: i64, sep: &str) -> String {
+    let sign = if offset_us < 0 { '-' } else { '+' };
+    let offset = offset_us.unsigned_abs();
+    let (seconds, microseconds) = (offset / 1_000_000, offset % 1_000_000);
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let mut s = format!("{sign}{hours:02}{sep}{minutes:02}");
+    if seconds != 0 || microseconds != 0 {
+        s.push_str(&format!("{sep}{seconds:02}"));
+        if microseconds != 0 {
+            s.push_str(&format!(".{microseconds:06}"));
+        }
+    }
+    s
+}
+
+pub fn format_ctime(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> String {
+    const DAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+    const MONTH_NAMES: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    format!(
+        "{} {} {day:2} {hour:02}:{minute:02}:{second:02} {year:04}",
+        DAY_NAMES[weekday(year, month, day) as usize],
+        MONTH_NAMES[month as usize - 1],
+    )
+}
+
+/// Why an ISO 8601 string was rejected.
+#[derive(Debug)]
+pub enum IsoError {
+    /// The string is not in a recognized format.
+    Invalid,
+    /// The fields are well formed but out of range.
+    Range(String),
+}
+
+impl From<String> for IsoError {
+    fn from(message: String) -> Self {
+        IsoError::Range(message)
+    }
+}
+
+fn parse_digits(s: &[u8], n: usize) -> Option<(u32, &[u8])> {
+    if s.len() < n || !s[..n].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = s[..n]
+        .iter()
+        .fold(0, |acc, &digit| acc * 10 + (digit - b'0') as u32);
+    Some((value, &s[n..]))
+}
+
+/// Parse `YYYY-MM-DD`, `YYYYMMDD`, `YYYY-Www[-D]` or `YYYYWww[D]`.
+pub fn parse_iso_date(s: &str) -> Result<(i32, u32, u32), IsoError> {
+    let invalid = || IsoError::Invalid;
+    let bytes = s.as_bytes();
+    let (year, rest) = parse_digits(bytes, 4).ok_or_else(invalid)?;
+    let extended = rest.first() == Some(&b'-');
+    let rest = if extended { &rest[1..] } else { rest };
+
+    if rest.first() == Some(&b'W') {
+        let (week, rest) = parse_digits(&rest[1..], 2).ok_or_else(invalid)?;
+        let day = match rest {
+            [] => 1,
+            [b'-', rest @ ..] if extended => {
+                parse_digits(rest, 1)
+                    .filter(|(_, r)| r.is_empty())
+                    .ok_or_else(invalid)?
+                    .0
+            }
+            rest if !extended => {
+                parse_digits(rest, 1)
+                    .filter(|(_, r)| r.is_empty())
+                    .ok_or_else(invalid)?
+                    .0
+            }
+            _ => return Err(invalid()),
+        };
+        return Ok(iso_to_ymd(year as i32, week, day)?);
+    }
+
+    let (month, rest) = parse_digits(rest, 2).ok_or_else(invalid)?;
+    let rest = match (extended, rest.first()) {
+        (true, Some(b'-')) => &rest[1..],
+        (false, _) => rest,
+        _ => return Err(invalid()),
+    };
+    let (day, rest) = parse_digits(rest, 2).ok_or_else(invalid)?;
+    if !rest.is_empty() {
+        return Err(invalid());
+    }
+    Ok(check_date(year as i64, month as i64, day as i64)?)
+}
+
+/// A parsed ISO 8601 time: hour, minute, second, microsecond and the UTC offset in
+/// microseconds, if one was given.
+pub type IsoTime = (u32, u32, u32, u32, Option<i64>);
+
+/// Parse `HH[:MM[:SS[.fff[fff]]]][tz]`, or the same without separators.
+pub fn parse_iso_time(s: &str) -> Result<IsoTime, IsoError> {
+    let invalid = || IsoError::Invalid;
+    let bytes = s.as_bytes();
+    let tz_start = bytes
+        .iter()
+        .position(|&c| matches!(c, b'+' | b'-' | b'Z'))
+        .unwrap_or(bytes.len());
+    let (time, tz) = bytes.split_at(tz_start);
+
+    let (hour, minute, second, microsecond) = parse_time_fields(time, true).ok_or_else(invalid)?;
+    check_time(
+        hour as i64,
+        minute as i64,
+        second as i64,
+        microsecond as i64,
+    )
+    .map_err(|_| invalid())?;
+
+    let offset = match tz {
+        [] => None,
+        [b'Z'] => Some(0),
+        [sign @ (b'+' | b'-'), rest @ ..] => {
+            let (h, m, s, us) = parse_time_fields(rest, false).ok_or_else(invalid)?;
+            if h >= 24 || m >= 60 || s >= 60 {
+                return Err(invalid());
+            }
+            let offset = (((h as i64 * 60 + m as i64) * 60 + s as i64) * US_PER_SECOND) + us as i64;
+            Some(if *sign == b'-' { -offset } else { offset })
+        }
+        _ => return Err(invalid()),
+    };
+    Ok((hour, minute, second, microsecond, offset))
+}
+
+fn parse_time_fields(s: &[u8], allow_hours_only: bool) -> Option<(u32, u32, u32, u32)> {
+    let (hour, mut rest) = parse_digits(s, 2)?;
+    if rest.is_empty() {
+        return allow_hours_only.then_some((hour, 0, 0, 0));
+    }
+    let extended = rest[0] == b':';
+    let mut fields = [hour, 0, 0];
+    for field in &mut fields[1..] {
+        if rest.is_empty() || matches!(rest[0], b'.' | b',') {
+            break;
+        }
+        if extended {
+            rest = rest.strip_prefix(b":")?;
+        }
+        let (value, next) = parse_digits(rest, 2)?;
+        *field = value;
+        rest = next;
+    }
+    let mut microsecond = 0;
+    if let [b'.' | b',', fraction @ ..] = rest {
+        // CPython accepts any number of digits and truncates past microseconds
+        if fraction.is_empty() || !fraction.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        for i in 0..6 {
+            microsecond = microsecond * 10 + fraction.get(i).map_or(0, |d| (d - b'0') as u32);
+        }
+        rest = &[];
+    }
+    rest.is_empty()
+        .then_some((fields[0], fields[1], fields[2], microsecond))
+}
+
+/// Split a datetime ISO string into its date and time parts.
+pub fn split_iso_datetime(s: &str) -> (&str, Option<&str>) {
+    // the date is 10 characters in extended format and 8 in basic format,
+    // or a week date; the separator may be any single character
+    let bytes = s.as_bytes();
+    let date_len = if bytes.len() >= 8 && bytes.get(4) == Some(&b'-') {
+        if bytes.get(5) == Some(&b'W') {
+            if bytes.get(8) == Some(&b'-') {
+                10
+            } else {
+                8
+            }
+        } else {
+            10
+        }
+    } else if bytes.get(4) == Some(&b'W') {
+        if bytes.len() > 7 && bytes[7].is_ascii_digit() {
+            8
+        } else {
+            7
+        }
+    } else {
+        8
+    };
+    if bytes.len() <= date_len || !s.is_char_boundary(date_len) {
+        return (s, None);
+    }
+    let (date, rest) = s.split_at(date_len);
+    let mut chars = rest.chars();
+    chars.next();
+    (date, Some(chars.as_str()))
+}
@@ -0,0 +1,2664 @@
+// spell-checker:ignore libmpdec localcontext setcontext getcontext Etiny Etop capitals
+
+use crate::vm::{builtins::PyModule, class::PyClassImpl, PyRef, VirtualMachine};
+
+mod number;
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyRef<PyModule> {
+    _decimal::PySignalDict::make_class(&vm.ctx);
+    _decimal::PyContextManager::make_class(&vm.ctx);
+    _decimal::make_module(vm)
+}
+
+#[pymodule]
+mod _decimal {
+    use super::number::{self, Decimal, Rounding};
+    use crate::common::{
+        hash::{self, PyHash},
+        lock::{PyMutex, PyRwLock},
+    };
+    use crate::vm::{
+        atomic_func,
+        builtins::{
+            PyBaseExceptionRef, PyComplex, PyDict, PyDictRef, PyFloat, PyInt, PyIntRef, PyList,
+            PyStr, PyStrRef, PyTuple, PyTupleRef, PyTypeRef,
+        },
+        convert::ToPyObject,
+        function::{ArgIterable, FuncArgs, KwArgs, OptionalArg, OptionalOption, PyComparisonValue},
+        protocol::{PyMappingMethods, PyNumberMethods},
+        types::{
+            AsMapping, AsNumber, Comparable, Constructor, Hashable, Iterable, PyComparisonOp,
+            Representable,
+        },
+        AsObject, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, TryFromObject,
+        VirtualMachine,
+    };
+    use malachite_bigint::BigInt;
+    use num_complex::Complex64;
+    use num_traits::{Signed, ToPrimitive, Zero};
+    use std::cell::RefCell;
+    use unic_ucd_category::GeneralCategory;
+
+    #[pyattr(name = "__version__")]
+    const __VERSION__: &str = "1.70";
+    #[pyattr(name = "__libmpdec_version__")]
+    const __LIBMPDEC_VERSION__: &str = "2.5.1";
+
+    #[pyattr]
+    const MAX_PREC: i64 = number::MAX_PREC;
+    #[pyattr]
+    const MAX_EMAX: i64 = number::MAX_EMAX;
+    #[pyattr]
+    const MIN_EMIN: i64 = number::MIN_EMIN;
+    #[pyattr]
+    const MIN_ETINY: i64 = number::MIN_ETINY;
+    #[pyattr]
+    const HAVE_THREADS: bool = true;
+    #[pyattr]
+    const HAVE_CONTEXTVAR: bool = false;
+
+    #[pyattr]
+    const ROUND_UP: &str = "ROUND_UP";
+    #[pyattr]
+    const ROUND_DOWN: &str = "ROUND_DOWN";
+    #[pyattr]
+    const ROUND_CEILING: &str = "ROUND_CEILING";
+    #[pyattr]
+    const ROUND_FLOOR: &str = "ROUND_FLOOR";
+    #[pyattr]
+    const ROUND_HALF_UP: &str = "ROUND_HALF_UP";
+    #[pyattr]
+    const ROUND_HALF_DOWN: &str = "ROUND_HALF_DOWN";
+    #[pyattr]
+    const ROUND_HALF_EVEN: &str = "ROUND_HALF_EVEN";
+    #[pyattr]
+    const ROUND_05UP: &str = "ROUND_05UP";
+
+    #[pyattr(name = "DecimalException", once)]
+    fn decimal_exception(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "DecimalException",
+            Some(vec![vm.ctx.exceptions.arithmetic_error.to_owned()]),
+        )
+    }
+
+    #[pyattr(name = "Clamped", once)]
+    fn clamped(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("decimal", "Clamped", Some(vec![decimal_exception(vm)]))
+    }
+
+    #[pyattr(name = "InvalidOperation", once)]
+    fn invalid_operation(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "InvalidOperation",
+            Some(vec![decimal_exception(vm)]),
+        )
+    }
+
+    #[pyattr(name = "ConversionSyntax", once)]
+    fn conversion_syntax(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "ConversionSyntax",
+            Some(vec![invalid_operation(vm)]),
+        )
+    }
+
+    #[pyattr(name = "DivisionByZero", once)]
+    fn division_by_zero(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "DivisionByZero",
+            Some(vec![
+                decimal_exception(vm),
+                vm.ctx.exceptions.zero_division_error.to_owned(),
+            ]),
+        )
+    }
+
+    #[pyattr(name = "DivisionImpossible", once)]
+    fn division_impossible(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "DivisionImpossible",
+            Some(vec![invalid_operation(vm)]),
+        )
+    }
+
+    #[pyattr(name = "DivisionUndefined", once)]
+    fn division_undefined(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "DivisionUndefined",
+            Some(vec![
+                invalid_operation(vm),
+                vm.ctx.exceptions.zero_division_error.to_owned(),
+            ]),
+        )
+    }
+
+    #[pyattr(name = "Inexact", once)]
+    fn inexact(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("decimal", "Inexact", Some(vec![decimal_exception(vm)]))
+    }
+
+    #[pyattr(name = "InvalidContext", once)]
+    fn invalid_context(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "InvalidContext",
+            Some(vec![invalid_operation(vm)]),
+        )
+    }
+
+    #[pyattr(name = "Rounded", once)]
+    fn rounded(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("decimal", "Rounded", Some(vec![decimal_exception(vm)]))
+    }
+
+    #[pyattr(name = "Subnormal", once)]
+    fn subnormal(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("decimal", "Subnormal", Some(vec![decimal_exception(vm)]))
+    }
+
+    #[pyattr(name = "Overflow", once)]
+    fn overflow(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("decimal", "Overflow", Some(vec![inexact(vm), rounded(vm)]))
+    }
+
+    #[pyattr(name = "Underflow", once)]
+    fn underflow(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "Underflow",
+            Some(vec![inexact(vm), rounded(vm), subnormal(vm)]),
+        )
+    }
+
+    #[pyattr(name = "FloatOperation", once)]
+    fn float_operation(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "decimal",
+            "FloatOperation",
+            Some(vec![
+                decimal_exception(vm),
+                vm.ctx.exceptions.type_error.to_owned(),
+            ]),
+        )
+    }
+
+    /// The signals a context keeps flags and traps for, in the order they are reported.
+    fn signals(vm: &VirtualMachine) -> [(u32, PyTypeRef); 9] {
+        [
+            (number::IEEE_INVALID, invalid_operation(vm)),
+            (number::FLOAT_OPERATION, float_operation(vm)),
+            (number::DIVISION_BY_ZERO, division_by_zero(vm)),
+            (number::OVERFLOW, overflow(vm)),
+            (number::UNDERFLOW, underflow(vm)),
+            (number::SUBNORMAL, subnormal(vm)),
+            (number::INEXACT, inexact(vm)),
+            (number::ROUNDED, rounded(vm)),
+            (number::CLAMPED, clamped(vm)),
+        ]
+    }
+
+    /// Every condition, including the ones reported as InvalidOperation.
+    fn conditions(vm: &VirtualMachine) -> [(u32, PyTypeRef); 13] {
+        [
+            (number::INVALID_OPERATION, invalid_operation(vm)),
+            (number::CONVERSION_SYNTAX, conversion_syntax(vm)),
+            (number::DIVISION_IMPOSSIBLE, division_impossible(vm)),
+            (number::DIVISION_UNDEFINED, division_undefined(vm)),
+            (number::INVALID_CONTEXT, invalid_context(vm)),
+            (number::FLOAT_OPERATION, float_operation(vm)),
+            (number::DIVISION_BY_ZERO, division_by_zero(vm)),
+            (number::OVERFLOW, overflow(vm)),
+            (number::UNDERFLOW, underflow(vm)),
+            (number::SUBNORMAL, subnormal(vm)),
+            (number::INEXACT, inexact(vm)),
+            (number::ROUNDED, rounded(vm)),
+            (number::CLAMPED, clamped(vm)),
+        ]
+    }
+
+    fn signal_mask(key: &PyObject, vm: &VirtualMachine) -> Option<u32> {
+        signals(vm)
+            .into_iter()
+            .find(|(_, signal)| key.is(signal))
+            .map(|(mask, _)| mask)
+    }
+
+    fn signal_names(bits: u32, vm: &VirtualMachine) -> String {
+        signals(vm)
+            .iter()
+            .filter(|(mask, _)| bits & mask != 0)
+            .map(|(_, signal)| signal.name().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Read a set of signals given either as a mapping to booleans or as a sequence.
+    fn signal_bits(value: &PyObject, vm: &VirtualMachine) -> PyResult<u32> {
+        if let Some(signal_dict) = value.payload::<PySignalDict>() {
+            return Ok(signal_dict.bits());
+        }
+        let mut bits = 0;
+        if let Some(dict) = value.payload_if_subclass::<PyDict>(vm) {
+            for (key, value) in dict {
+                let mask = signal_mask(&key, vm)
+                    .ok_or_else(|| vm.new_key_error("invalid signal dict".to_pyobject(vm)))?;
+                if value.try_to_bool(vm)? {
+                    bits |= mask;
+                }
+            }
+        } else {
+            let iterable = ArgIterable::<PyObjectRef>::try_from_object(vm, value.to_owned())?;
+            for key in iterable.iter(vm)? {
+                let key = key?;
+                bits |= signal_mask(&key, vm).ok_or_else(|| {
+                    vm.new_key_error(vm.ctx.new_str("invalid error flag in list").into())
+                })?;
+            }
+        }
+        Ok(bits)
+    }
+
+    fn new_signal_error(trapped: u32, status: u32, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        let (_, signal) = signals(vm)
+            .into_iter()
+            .find(|(mask, _)| trapped & mask != 0)
+            .unwrap();
+        let raised = conditions(vm)
+            .into_iter()
+            .filter(|(mask, _)| status & mask != 0)
+            .map(|(_, condition)| condition.into())
+            .collect();
+        vm.new_exception(signal, vec![vm.ctx.new_list(raised).into()])
+    }
+
+    fn int_arg(value: &PyObject, what: &str, vm: &VirtualMachine) -> PyResult<i64> {
+        let int = value.payload_if_subclass::<PyInt>(vm).ok_or_else(|| {
+            vm.new_type_error(format!(
+                "{what} must be an integer, not {}",
+                value.class().name()
+            ))
+        })?;
+        int.as_bigint()
+            .to_i64()
+            .ok_or_else(|| vm.new_value_error(format!("{what} is out of range")))
+    }
+
+    fn rounding_arg(value: &PyObject, vm: &VirtualMachine) -> PyResult<Rounding> {
+        value
+            .payload::<PyStr>()
+            .and_then(|name| Rounding::from_name(name.as_str()))
+            .ok_or_else(|| {
+                vm.new_type_error(
+                    "valid values for rounding are:\n  [ROUND_CEILING, ROUND_FLOOR, ROUND_UP, \
+                     ROUND_DOWN,\n   ROUND_HALF_UP, ROUND_HALF_DOWN, ROUND_HALF_EVEN,\n   \
+                     ROUND_05UP]"
+                        .to_owned(),
+                )
+            })
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct ContextState {
+        inner: number::Context,
+        capitals: bool,
+        /// Conditions raised so far, as condition bits.
+        flags: u32,
+        /// Trapped signals, as the union of their condition bits.
+        traps: u32,
+    }
+
+    #[pyattr]
+    #[pyclass(module = "decimal", name = "Context")]
+    #[derive(Debug, PyPayload)]
+    struct PyContext {
+        state: PyRwLock<ContextState>,
+    }
+
+    #[derive(FromArgs)]
+    struct ContextArgs {
+        #[pyarg(any, default)]
+        prec: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        rounding: Option<PyObjectRef>,
+        #[pyarg(any, name = "Emin", default)]
+        emin: Option<PyObjectRef>,
+        #[pyarg(any, name = "Emax", default)]
+        emax: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        capitals: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        clamp: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        flags: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        traps: Option<PyObjectRef>,
+    }
+
+    impl Constructor for PyContext {
+        type Args = ContextArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let template = default_context(vm).state();
+            let context = PyContext::new(ContextState {
+                flags: 0,
+                ..template
+            });
+            let fields = [
+                ("prec", args.prec),
+                ("rounding", args.rounding),
+                ("Emin", args.emin),
+                ("Emax", args.emax),
+                ("capitals", args.capitals),
+                ("clamp", args.clamp),
+                ("flags", args.flags),
+                ("traps", args.traps),
+            ];
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    context.set_field(name, &value, vm)?;
+                }
+            }
+            context.into_ref_with_type(vm, cls).map(Into::into)
+        }
+    }
+
+    impl PyContext {
+        fn new(state: ContextState) -> Self {
+            PyContext {
+                state: PyRwLock::new(state),
+            }
+        }
+
+        fn state(&self) -> ContextState {
+            *self.state.read()
+        }
+
+        fn copy(&self) -> Self {
+            Self::new(self.state())
+        }
+
+        fn set_field(&self, name: &str, value: &PyObject, vm: &VirtualMachine) -> PyResult<()> {
+            let bool_field = |what: &str| -> PyResult<bool> {
+                match int_arg(value, what, vm)? {
+                    0 => Ok(false),
+                    1 => Ok(true),
+                    _ => Err(vm.new_value_error(format!("valid values for {what} are 0 or 1"))),
+                }
+            };
+            // validate before taking the lock; conversions can run Python code
+            match name {
+                "prec" => {
+                    let prec = int_arg(value, "prec", vm)?;
+                    if !(1..=number::MAX_PREC).contains(&prec) {
+                        return Err(
+                            vm.new_value_error("valid range for prec is [1, MAX_PREC]".to_owned())
+                        );
+                    }
+                    self.state.write().inner.prec = prec;
+                }
+                "rounding" => self.state.write().inner.rounding = rounding_arg(value, vm)?,
+                "Emin" => {
+                    let emin = int_arg(value, "Emin", vm)?;
+                    if !(number::MIN_EMIN..=0).contains(&emin) {
+                        return Err(
+                            vm.new_value_error("valid range for Emin is [MIN_EMIN, 0]".to_owned())
+                        );
+                    }
+                    self.state.write().inner.emin = emin;
+                }
+                "Emax" => {
+                    let emax = int_arg(value, "Emax", vm)?;
+                    if !(0..=number::MAX_EMAX).contains(&emax) {
+                        return Err(
+                            vm.new_value_error("valid range for Emax is [0, MAX_EMAX]".to_owned())
+                        );
+                    }
+                    self.state.write().inner.emax = emax;
+                }
+                "capitals" => self.state.write().capitals = bool_field("capitals")?,
+                "clamp" => self.state.write().inner.clamp = bool_field("clamp")?,
+                "flags" => self.state.write().flags = signal_bits(value, vm)?,
+                "traps" => self.state.write().traps = signal_bits(value, vm)?,
+                _ => {
+                    return Err(vm.new_type_error(format!(
+                        "'{name}' is an invalid keyword argument for this function"
+                    )))
+                }
+            }
+            Ok(())
+        }
+
+        /// Record conditions in the flags and raise the first one that is trapped.
+        fn signal(&self, status: u32, vm: &VirtualMachine) -> PyResult<()> {
+            if status == 0 {
+                return Ok(());
+            }
+            let traps = {
+                let mut state = self.state.write();
+                state.flags |= status;
+                state.traps
+            };
+            let trapped = status & traps;
+            if trapped == 0 {
+                Ok(())
+            } else {
+                Err(new_signal_error(trapped, status, vm))
+            }
+        }
+
+        fn run<T>(
+            &self,
+            vm: &VirtualMachine,
+            f: impl FnOnce(&number::Context, &mut u32) -> T,
+        ) -> PyResult<T> {
+            let ctx = self.state.read().inner;
+            let mut status = 0;
+            let result = f(&ctx, &mut status);
+            if status & number::MALLOC_ERROR != 0 {
+                return Err(vm.new_memory_error("".to_owned()));
+            }
+            self.signal(status, vm)?;
+            Ok(result)
+        }
+
+        fn unary(
+            &self,
+            a: PyObjectRef,
+            op: UnaryOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let a = to_decimal(&a, vm)?;
+            self.run(vm, |ctx, status| op(&a, ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        fn binary(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            op: BinaryOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let a = to_decimal(&a, vm)?;
+            let b = to_decimal(&b, vm)?;
+            self.run(vm, |ctx, status| op(&a, &b, ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        /// Convert and round a number to this context.
+        fn create(&self, value: &PyObject, vm: &VirtualMachine) -> PyResult<Decimal> {
+            let value = decimal_from_object(value, self, vm)?;
+            self.run(vm, |ctx, status| value.fix(ctx, status))
+        }
+    }
+
+    #[pyclass(with(Constructor, Representable))]
+    impl PyContext {
+        #[pygetset]
+        fn prec(&self) -> i64 {
+            self.state().inner.prec
+        }
+        #[pygetset(setter)]
+        fn set_prec(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("prec", &value, vm)
+        }
+
+        #[pygetset]
+        fn rounding(&self) -> &'static str {
+            self.state().inner.rounding.name()
+        }
+        #[pygetset(setter)]
+        fn set_rounding(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("rounding", &value, vm)
+        }
+
+        #[pygetset(name = "Emin")]
+        fn emin(&self) -> i64 {
+            self.state().inner.emin
+        }
+        #[pygetset(name = "Emin", setter)]
+        fn set_emin(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("Emin", &value, vm)
+        }
+
+        #[pygetset(name = "Emax")]
+        fn emax(&self) -> i64 {
+            self.state().inner.emax
+        }
+        #[pygetset(name = "Emax", setter)]
+        fn set_emax(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("Emax", &value, vm)
+        }
+
+        #[pygetset]
+        fn capitals(&self) -> i32 {
+            self.state().capitals as i32
+        }
+        #[pygetset(setter)]
+        fn set_capitals(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("capitals", &value, vm)
+        }
+
+        #[pygetset]
+        fn clamp(&self) -> i32 {
+            self.state().inner.clamp as i32
+        }
+        #[pygetset(setter)]
+        fn set_clamp(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("clamp", &value, vm)
+        }
+
+        #[pygetset]
+        fn flags(zelf: PyRef<Self>) -> PySignalDict {
+            PySignalDict {
+                context: zelf,
+                traps: false,
+            }
+        }
+        #[pygetset(setter)]
+        fn set_flags(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("flags", &value, vm)
+        }
+
+        #[pygetset]
+        fn traps(zelf: PyRef<Self>) -> PySignalDict {
+            PySignalDict {
+                context: zelf,
+                traps: true,
+            }
+        }
+        #[pygetset(setter)]
+        fn set_traps(&self, value: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            self.set_field("traps", &value, vm)
+        }
+
+        #[pymethod(name = "Etiny")]
+        fn etiny(&self) -> i64 {
+            self.state().inner.etiny()
+        }
+
+        #[pymethod(name = "Etop")]
+        fn etop(&self) -> i64 {
+            self.state().inner.etop()
+        }
+
+        #[pymethod]
+        fn radix(&self, vm: &VirtualMachine) -> PyDecimalRef {
+            new_decimal(Decimal::from_i64(10), vm)
+        }
+
+        #[pymethod]
+        fn clear_flags(&self) {
+            self.state.write().flags = 0;
+        }
+
+        #[pymethod]
+        fn clear_traps(&self) {
+            self.state.write().traps = 0;
+        }
+
+        #[pymethod(name = "copy")]
+        #[pymethod(name = "__copy__")]
+        fn copy_(&self) -> PyContext {
+            self.copy()
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            let state = zelf.state();
+            let signal_list = |bits: u32| -> PyObjectRef {
+                let signals = signals(vm)
+                    .into_iter()
+                    .filter(|(mask, _)| bits & mask != 0)
+                    .map(|(_, signal)| signal.into())
+                    .collect();
+                vm.ctx.new_list(signals).into()
+            };
+            let args = vec![
+                vm.ctx.new_int(state.inner.prec).into(),
+                vm.ctx.new_str(state.inner.rounding.name()).into(),
+                vm.ctx.new_int(state.inner.emin).into(),
+                vm.ctx.new_int(state.inner.emax).into(),
+                vm.ctx.new_int(state.capitals as i32).into(),
+                vm.ctx.new_int(state.inner.clamp as i32).into(),
+                signal_list(state.flags),
+                signal_list(state.traps),
+            ];
+            vm.new_tuple((zelf.class().to_owned(), vm.ctx.new_tuple(args)))
+        }
+
+        #[pymethod]
+        fn create_decimal(
+            &self,
+            num: OptionalArg<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let value = match num {
+                OptionalArg::Present(num) => self.create(&num, vm)?,
+                OptionalArg::Missing => Decimal::zero(),
+            };
+            Ok(new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn create_decimal_from_float(
+            &self,
+            f: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let value = exact_from_number(&f, vm)?;
+            self.run(vm, |ctx, status| value.fix(ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn _apply(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, |a, ctx, status| a.clone().fix(ctx, status), vm)
+        }
+
+        #[pymethod]
+        fn abs(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::abs_rounded, vm)
+        }
+
+        #[pymethod]
+        fn add(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::add, vm)
+        }
+
+        #[pymethod]
+        fn canonical(&self, a: PyRef<PyDecimal>) -> PyRef<PyDecimal> {
+            a
+        }
+
+        #[pymethod]
+        fn compare(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::compare, vm)
+        }
+
+        #[pymethod]
+        fn compare_signal(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::compare_signal, vm)
+        }
+
+        #[pymethod]
+        fn compare_total(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, |a, b, _, _| a.compare_total(b), vm)
+        }
+
+        #[pymethod]
+        fn compare_total_mag(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, |a, b, _, _| a.compare_total_mag(b), vm)
+        }
+
+        #[pymethod]
+        fn copy_abs(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            Ok(new_decimal(to_decimal(&a, vm)?.abs(), vm))
+        }
+
+        #[pymethod]
+        fn copy_decimal(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            Ok(new_decimal(to_decimal(&a, vm)?, vm))
+        }
+
+        #[pymethod]
+        fn copy_negate(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            Ok(new_decimal(to_decimal(&a, vm)?.negated(), vm))
+        }
+
+        #[pymethod]
+        fn copy_sign(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let (a, b) = (to_decimal(&a, vm)?, to_decimal(&b, vm)?);
+            Ok(new_decimal(a.with_sign_of(&b), vm))
+        }
+
+        #[pymethod]
+        fn divide(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::div, vm)
+        }
+
+        #[pymethod]
+        fn divide_int(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::divide_int, vm)
+        }
+
+        #[pymethod]
+        fn divmod(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyTupleRef> {
+            let (a, b) = (to_decimal(&a, vm)?, to_decimal(&b, vm)?);
+            let (q, r) = self.run(vm, |ctx, status| a.divmod(&b, ctx, status))?;
+            Ok(vm.new_tuple((new_decimal(q, vm), new_decimal(r, vm))))
+        }
+
+        #[pymethod]
+        fn exp(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::exp, vm)
+        }
+
+        #[pymethod]
+        fn fma(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            c: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let (a, b, c) = (
+                to_decimal(&a, vm)?,
+                to_decimal(&b, vm)?,
+                to_decimal(&c, vm)?,
+            );
+            self.run(vm, |ctx, status| a.fma(&b, &c, ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn is_canonical(&self, a: PyRef<PyDecimal>) -> bool {
+            a.is_canonical()
+        }
+
+        #[pymethod]
+        fn is_finite(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_finite())
+        }
+
+        #[pymethod]
+        fn is_infinite(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_infinite())
+        }
+
+        #[pymethod]
+        fn is_nan(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_nan())
+        }
+
+        #[pymethod]
+        fn is_normal(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_normal(&self.state().inner))
+        }
+
+        #[pymethod]
+        fn is_qnan(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_qnan())
+        }
+
+        #[pymethod]
+        fn is_signed(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.negative)
+        }
+
+        #[pymethod]
+        fn is_snan(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_snan())
+        }
+
+        #[pymethod]
+        fn is_subnormal(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_subnormal(&self.state().inner))
+        }
+
+        #[pymethod]
+        fn is_zero(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.is_zero())
+        }
+
+        #[pymethod]
+        fn ln(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::ln, vm)
+        }
+
+        #[pymethod]
+        fn log10(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::log10, vm)
+        }
+
+        #[pymethod]
+        fn logb(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::logb, vm)
+        }
+
+        #[pymethod]
+        fn logical_and(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::logical_and, vm)
+        }
+
+        #[pymethod]
+        fn logical_invert(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::logical_invert, vm)
+        }
+
+        #[pymethod]
+        fn logical_or(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::logical_or, vm)
+        }
+
+        #[pymethod]
+        fn logical_xor(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::logical_xor, vm)
+        }
+
+        #[pymethod]
+        fn max(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::max, vm)
+        }
+
+        #[pymethod]
+        fn max_mag(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::max_mag, vm)
+        }
+
+        #[pymethod]
+        fn min(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::min, vm)
+        }
+
+        #[pymethod]
+        fn min_mag(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::min_mag, vm)
+        }
+
+        #[pymethod]
+        fn minus(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::minus, vm)
+        }
+
+        #[pymethod]
+        fn multiply(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::mul, vm)
+        }
+
+        #[pymethod]
+        fn next_minus(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::next_minus, vm)
+        }
+
+        #[pymethod]
+        fn next_plus(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::next_plus, vm)
+        }
+
+        #[pymethod]
+        fn next_toward(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::next_toward, vm)
+        }
+
+        #[pymethod]
+        fn normalize(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::normalize, vm)
+        }
+
+        #[pymethod]
+        fn number_class(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<&'static str> {
+            Ok(to_decimal(&a, vm)?.number_class(&self.state().inner))
+        }
+
+        #[pymethod]
+        fn plus(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::plus, vm)
+        }
+
+        #[pymethod]
+        fn power(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            modulo: OptionalOption<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let (a, b) = (to_decimal(&a, vm)?, to_decimal(&b, vm)?);
+            let value = match modulo.flatten() {
+                Some(modulo) => {
+                    let modulo = to_decimal(&modulo, vm)?;
+                    self.run(vm, |ctx, status| a.pow_mod(&b, &modulo, ctx, status))?
+                }
+                None => self.run(vm, |ctx, status| a.pow(&b, ctx, status))?,
+            };
+            Ok(new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn quantize(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(
+                a,
+                b,
+                |a, b, ctx, status| a.quantize(b, ctx.rounding, ctx, status),
+                vm,
+            )
+        }
+
+        #[pymethod]
+        fn remainder(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::rem, vm)
+        }
+
+        #[pymethod]
+        fn remainder_near(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::rem_near, vm)
+        }
+
+        #[pymethod]
+        fn rotate(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::rotate, vm)
+        }
+
+        #[pymethod]
+        fn same_quantum(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<bool> {
+            Ok(to_decimal(&a, vm)?.same_quantum(&to_decimal(&b, vm)?))
+        }
+
+        #[pymethod]
+        fn scaleb(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::scaleb, vm)
+        }
+
+        #[pymethod]
+        fn shift(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::shift, vm)
+        }
+
+        #[pymethod]
+        fn sqrt(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(a, Decimal::sqrt, vm)
+        }
+
+        #[pymethod]
+        fn subtract(
+            &self,
+            a: PyObjectRef,
+            b: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.binary(a, b, Decimal::sub, vm)
+        }
+
+        #[pymethod]
+        fn to_eng_string(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<String> {
+            Ok(to_decimal(&a, vm)?.to_eng_string(self.state().capitals))
+        }
+
+        #[pymethod]
+        fn to_sci_string(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<String> {
+            Ok(to_decimal(&a, vm)?.to_sci_string(self.state().capitals))
+        }
+
+        #[pymethod]
+        fn to_integral_exact(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(
+                a,
+                |a, ctx, status| a.to_integral(ctx.rounding, true, ctx, status),
+                vm,
+            )
+        }
+
+        #[pymethod(name = "to_integral")]
+        #[pymethod]
+        fn to_integral_value(&self, a: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(
+                a,
+                |a, ctx, status| a.to_integral(ctx.rounding, false, ctx, status),
+                vm,
+            )
+        }
+    }
+
+    impl Representable for PyContext {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let state = zelf.state();
+            Ok(format!(
+                "Context(prec={}, rounding={}, Emin={}, Emax={}, capitals={}, clamp={}, \
+                 flags=[{}], traps=[{}])",
+                state.inner.prec,
+                state.inner.rounding.name(),
+                state.inner.emin,
+                state.inner.emax,
+                state.capitals as i32,
+                state.inner.clamp as i32,
+                signal_names(state.flags, vm),
+                signal_names(state.traps, vm),
+            ))
+        }
+    }
+
+    /// A live view of the flags or traps of a context, keyed by signal.
+    #[pyclass(no_attr, module = "decimal", name = "SignalDictMixin")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PySignalDict {
+        context: PyRef<PyContext>,
+        traps: bool,
+    }
+
+    impl PySignalDict {
+        fn bits(&self) -> u32 {
+            let state = self.context.state();
+            if self.traps {
+                state.traps
+            } else {
+                state.flags
+            }
+        }
+
+        fn mask(&self, key: &PyObject, vm: &VirtualMachine) -> PyResult<u32> {
+            signal_mask(key, vm).ok_or_else(|| vm.new_key_error(key.to_owned()))
+        }
+
+        fn to_dict(&self, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+            let bits = self.bits();
+            let dict = vm.ctx.new_dict();
+            for (mask, signal) in signals(vm) {
+                dict.set_item(
+                    signal.as_object(),
+                    vm.ctx.new_bool(bits & mask != 0).into(),
+                    vm,
+                )?;
+            }
+            Ok(dict)
+        }
+    }
+
+    #[pyclass(with(AsMapping, Iterable, Comparable, Representable))]
+    impl PySignalDict {
+        #[pymethod]
+        fn copy(&self, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+            self.to_dict(vm)
+        }
+
+        #[pymethod]
+        fn keys(&self, vm: &VirtualMachine) -> PyResult {
+            vm.call_method(self.to_dict(vm)?.as_object(), "keys", ())
+        }
+
+        #[pymethod]
+        fn values(&self, vm: &VirtualMachine) -> PyResult {
+            vm.call_method(self.to_dict(vm)?.as_object(), "values", ())
+        }
+
+        #[pymethod]
+        fn items(&self, vm: &VirtualMachine) -> PyResult {
+            vm.call_method(self.to_dict(vm)?.as_object(), "items", ())
+        }
+
+        #[pymethod(magic)]
+        fn contains(&self, key: PyObjectRef, vm: &VirtualMachine) -> bool {
+            signal_mask(&key, vm).is_some()
+        }
+    }
+
+    impl AsMapping for PySignalDict {
+        fn as_mapping() -> &'static PyMappingMethods {
+            static AS_MAPPING: PyMappingMethods = PyMappingMethods {
+                length: atomic_func!(|_mapping, _vm| Ok(9)),
+                subscript: atomic_func!(|mapping, needle, vm| {
+                    let zelf = PySignalDict::mapping_downcast(mapping);
+                    let mask = zelf.mask(needle, vm)?;
+                    Ok(vm.ctx.new_bool(zelf.bits() & mask != 0).into())
+                }),
+                ass_subscript: atomic_func!(|mapping, needle, value, vm| {
+                    let zelf = PySignalDict::mapping_downcast(mapping);
+                    let mask = zelf.mask(needle, vm)?;
+                    let Some(value) = value else {
+                        return Err(vm.new_value_error("signal keys cannot be deleted".to_owned()));
+                    };
+                    let set = value.try_to_bool(vm)?;
+                    let mut state = zelf.context.state.write();
+                    let bits = if zelf.traps {
+                        &mut state.traps
+                    } else {
+                        &mut state.flags
+                    };
+                    if set {
+                        *bits |= mask;
+                    } else {
+                        *bits &= !mask;
+                    }
+                    Ok(())
+                }),
+            };
+            &AS_MAPPING
+        }
+    }
+
+    impl Iterable for PySignalDict {
+        fn iter(_zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult {
+            let keys: Vec<PyObjectRef> = signals(vm)
+                .into_iter()
+                .map(|(_, signal)| signal.into())
+                .collect();
+            vm.ctx
+                .new_list(keys)
+                .as_object()
+                .get_iter(vm)
+                .map(Into::into)
+        }
+    }
+
+    impl Comparable for PySignalDict {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            op.eq_only(|| {
+                let other = if let Some(other) = other.payload::<PySignalDict>() {
+                    other.to_dict(vm)?
+                } else if let Some(other) = other.downcast_ref::<PyDict>() {
+                    other.to_owned()
+                } else {
+                    return Ok(PyComparisonValue::NotImplemented);
+                };
+                let eq = vm.bool_eq(zelf.to_dict(vm)?.as_object(), other.as_object())?;
+                Ok(eq.into())
+            })
+        }
+    }
+
+    impl Representable for PySignalDict {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let bits = zelf.bits();
+            let items = signals(vm)
+                .iter()
+                .map(|(mask, signal)| {
+                    let value = if bits & mask != 0 { "True" } else { "False" };
+                    format!("<class '{}.{}'>:{value}", "decimal", signal.name())
+                })
+                .collect::<Vec<_>>();
+            Ok(format!("{{{}}}", items.join(", ")))
+        }
+    }
+
+    fn new_template(state: ContextState, vm: &VirtualMachine) -> PyRef<PyContext> {
+        PyContext::new(state).into_ref(&vm.ctx)
+    }
+
+    #[pyattr(name = "DefaultContext", once)]
+    fn default_context(vm: &VirtualMachine) -> PyRef<PyContext> {
+        new_template(
+            ContextState {
+                inner: number::Context::default(),
+                capitals: true,
+                flags: 0,
+                traps: number::IEEE_INVALID | number::DIVISION_BY_ZERO | number::OVERFLOW,
+            },
+            vm,
+        )
+    }
+
+    #[pyattr(name = "BasicContext", once)]
+    fn basic_context(vm: &VirtualMachine) -> PyRef<PyContext> {
+        new_template(
+            ContextState {
+                inner: number::Context {
+                    prec: 9,
+                    rounding: Rounding::HalfUp,
+                    ..number::Context::default()
+                },
+                capitals: true,
+                flags: 0,
+                traps: number::IEEE_INVALID
+                    | number::DIVISION_BY_ZERO
+                    | number::OVERFLOW
+                    | number::UNDERFLOW
+                    | number::CLAMPED,
+            },
+            vm,
+        )
+    }
+
+    #[pyattr(name = "ExtendedContext", once)]
+    fn extended_context(vm: &VirtualMachine) -> PyRef<PyContext> {
+        new_template(
+            ContextState {
+                inner: number::Context {
+                    prec: 9,
+                    ..number::Context::default()
+                },
+                capitals: true,
+                flags: 0,
+                traps: 0,
+            },
+            vm,
+        )
+    }
+
+    thread_local! {
+        static CURRENT_CONTEXT: RefCell<Option<PyRef<PyContext>>> = const { RefCell::new(None) };
+    }
+
+    fn current_context(vm: &VirtualMachine) -> PyRef<PyContext> {
+        CURRENT_CONTEXT.with(|current| {
+            current
+                .borrow_mut()
+                .get_or_insert_with(|| default_context(vm).copy().into_ref(&vm.ctx))
+                .clone()
+        })
+    }
+
+    fn resolve_context(context: Option<PyRef<PyContext>>, vm: &VirtualMachine) -> PyRef<PyContext> {
+        context.unwrap_or_else(|| current_context(vm))
+    }
+
+    #[pyfunction]
+    fn getcontext(vm: &VirtualMachine) -> PyRef<PyContext> {
+        current_context(vm)
+    }
+
+    #[pyfunction]
+    fn setcontext(context: PyRef<PyContext>, vm: &VirtualMachine) {
+        // installing a template installs a fresh copy of it
+        let templates = [default_context(vm), basic_context(vm), extended_context(vm)];
+        let context = if templates.iter().any(|template| template.is(&context)) {
+            let copy = context.copy();
+            copy.clear_flags();
+            copy.into_ref(&vm.ctx)
+        } else {
+            context
+        };
+        CURRENT_CONTEXT.with(|current| *current.borrow_mut() = Some(context));
+    }
+
+    #[pyfunction]
+    fn localcontext(
+        ctx: OptionalOption<PyRef<PyContext>>,
+        mut kwargs: KwArgs,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyContextManager> {
+        let ctx = match kwargs.pop_kwarg("ctx") {
+            Some(ctx) if !vm.is_none(&ctx) => Some(ctx.try_into_value(vm)?),
+            _ => ctx.flatten(),
+        };
+        let local = resolve_context(ctx, vm).copy();
+        for (name, value) in kwargs {
+            local.set_field(&name, &value, vm)?;
+        }
+        Ok(PyContextManager {
+            local: local.into_ref(&vm.ctx),
+            saved: PyMutex::new(None),
+        })
+    }
+
+    #[pyclass(no_attr, module = "decimal", name = "ContextManager")]
+    #[derive(Debug, PyPayload)]
+    pub(super) struct PyContextManager {
+        local: PyRef<PyContext>,
+        saved: PyMutex<Option<PyRef<PyContext>>>,
+    }
+
+    #[pyclass]
+    impl PyContextManager {
+        #[pymethod(magic)]
+        fn enter(&self, vm: &VirtualMachine) -> PyRef<PyContext> {
+            *self.saved.lock() = Some(current_context(vm));
+            setcontext(self.local.clone(), vm);
+            self.local.clone()
+        }
+
+        #[pymethod(magic)]
+        fn exit(&self, _args: FuncArgs, vm: &VirtualMachine) {
+            if let Some(saved) = self.saved.lock().take() {
+                setcontext(saved, vm);
+            }
+        }
+    }
+
+    type PyDecimalRef = PyRef<PyDecimal>;
+    type UnaryOp = fn(&Decimal, &number::Context, &mut u32) -> Decimal;
+    type BinaryOp = fn(&Decimal, &Decimal, &number::Context, &mut u32) -> Decimal;
+
+    #[pyattr]
+    #[pyclass(module = "decimal", name = "Decimal")]
+    #[derive(Debug, PyPayload)]
+    struct PyDecimal {
+        value: Decimal,
+    }
+
+    fn new_decimal(value: Decimal, vm: &VirtualMachine) -> PyDecimalRef {
+        PyDecimal { value }.into_ref(&vm.ctx)
+    }
+
+    /// The operand conversion of the arithmetic operators: only Decimal and int mix.
+    fn convert_op(obj: &PyObject, vm: &VirtualMachine) -> Option<Decimal> {
+        if let Some(decimal) = obj.payload_if_subclass::<PyDecimal>(vm) {
+            Some(decimal.value.clone())
+        } else {
+            obj.payload_if_subclass::<PyInt>(vm)
+                .map(|int| Decimal::from_bigint(int.as_bigint()))
+        }
+    }
+
+    fn to_decimal(obj: &PyObject, vm: &VirtualMachine) -> PyResult<Decimal> {
+        convert_op(obj, vm).ok_or_else(|| {
+            vm.new_type_error(format!(
+                "conversion from {} to Decimal is not supported",
+                obj.class().name()
+            ))
+        })
+    }
+
+    /// The exact value of an int or float.
+    fn exact_from_number(obj: &PyObject, vm: &VirtualMachine) -> PyResult<Decimal> {
+        if let Some(int) = obj.payload_if_subclass::<PyInt>(vm) {
+            Ok(Decimal::from_bigint(int.as_bigint()))
+        } else if let Some(float) = obj.payload_if_subclass::<PyFloat>(vm) {
+            Ok(Decimal::from_f64(float.to_f64()))
+        } else {
+            Err(vm.new_type_error("argument must be int or float".to_owned()))
+        }
+    }
+
+    /// The ASCII form of a numeric string: Unicode digits become ASCII ones, surrounding
+    /// whitespace and underscores are dropped. None if anything else is not ASCII.
+    fn numeric_as_ascii(s: &str) -> Option<String> {
+        s.trim()
+            .chars()
+            .filter(|&c| c != '_')
+            .map(|c| {
+                if c.is_ascii() {
+                    return Some(c);
+                }
+                let is_digit = |c: char| GeneralCategory::of(c) == GeneralCategory::DecimalNumber;
+                if !is_digit(c) {
+                    return None;
+                }
+                // decimal digits come in runs of ten starting at zero
+                let mut zero = c as u32;
+                while char::from_u32(zero - 1).map_or(false, is_digit) {
+                    zero -= 1;
+                }
+                char::from_digit((c as u32 - zero) % 10, 10)
+            })
+            .collect()
+    }
+
+    fn decimal_from_str(s: &str, context: &PyContext, vm: &VirtualMachine) -> PyResult<Decimal> {
+        let parsed = numeric_as_ascii(s).and_then(|s| Decimal::parse(&s));
+        let state = context.state();
+        let max_payload = state.inner.prec - state.inner.clamp as i64;
+        match parsed {
+            Some(value) if !(value.is_nan() && value.digits() > max_payload) => Ok(value),
+            _ => {
+                context.signal(number::CONVERSION_SYNTAX, vm)?;
+                Ok(Decimal::nan(false, BigInt::zero(), false))
+            }
+        }
+    }
+
+    fn decimal_from_tuple(items: &[PyObjectRef], vm: &VirtualMachine) -> PyResult<Decimal> {
+        let [sign, digits, exponent] = items else {
+            return Err(vm.new_value_error("argument must be a sequence of length 3".to_owned()));
+        };
+        let negative = match sign
+            .payload_if_subclass::<PyInt>(vm)
+            .and_then(|sign| sign.as_bigint().to_u8())
+        {
+            Some(0) => false,
+            Some(1) => true,
+            _ => {
+                return Err(
+                    vm.new_value_error("sign must be an integer with the value 0 or 1".to_owned())
+                )
+            }
+        };
+        let digit_error = || vm.new_value_error("coefficient must be a tuple of digits".to_owned());
+        let digit_objects = if let Some(tuple) = digits.payload_if_subclass::<PyTuple>(vm) {
+            tuple.as_slice().to_vec()
+        } else if let Some(list) = digits.payload_if_subclass::<PyList>(vm) {
+            list.borrow_vec().to_vec()
+        } else {
+            return Err(digit_error());
+        };
+        let mut coef = String::with_capacity(digit_objects.len());
+        for digit in &digit_objects {
+            let digit = digit
+                .payload_if_subclass::<PyInt>(vm)
+                .and_then(|digit| digit.as_bigint().to_u32())
+                .and_then(|digit| char::from_digit(digit, 10))
+                .ok_or_else(digit_error)?;
+            coef.push(digit);
+        }
+        let coef = coef.trim_start_matches('0');
+        let coef = if coef.is_empty() {
+            BigInt::zero()
+        } else {
+            coef.parse().unwrap()
+        };
+
+        if let Some(special) = exponent.payload::<PyStr>() {
+            return match special.as_str() {
+                "F" => Ok(Decimal::infinity(negative)),
+                "n" => Ok(Decimal::nan(negative, coef, false)),
+                "N" => Ok(Decimal::nan(negative, coef, true)),
+                _ => Err(vm.new_value_error(
+                    "string argument in the third position must be 'F', 'n' or 'N'".to_owned(),
+                )),
+            };
+        }
+        let exp = exponent
+            .payload_if_subclass::<PyInt>(vm)
+            .ok_or_else(|| vm.new_value_error("exponent must be an integer".to_owned()))?
+            .as_bigint()
+            .to_i64()
+            .filter(|exp| (number::MIN_ETINY..=number::MAX_EMAX).contains(exp))
+            .ok_or_else(|| vm.new_value_error("exponent out of range".to_owned()))?;
+        Ok(Decimal::new(negative, coef, exp))
+    }
+
+    fn decimal_from_object(
+        value: &PyObject,
+        context: &PyContext,
+        vm: &VirtualMachine,
+    ) -> PyResult<Decimal> {
+        if let Some(decimal) = value.payload_if_subclass::<PyDecimal>(vm) {
+            Ok(decimal.value.clone())
+        } else if let Some(int) = value.payload_if_subclass::<PyInt>(vm) {
+            Ok(Decimal::from_bigint(int.as_bigint()))
+        } else if let Some(s) = value.payload_if_subclass::<PyStr>(vm) {
+            decimal_from_str(s.as_str(), context, vm)
+        } else if let Some(float) = value.payload_if_subclass::<PyFloat>(vm) {
+            context.signal(number::FLOAT_OPERATION, vm)?;
+            Ok(Decimal::from_f64(float.to_f64()))
+        } else if let Some(tuple) = value.payload_if_subclass::<PyTuple>(vm) {
+            decimal_from_tuple(tuple.as_slice(), vm)
+        } else if let Some(list) = value.payload_if_subclass::<PyList>(vm) {
+            let items = list.borrow_vec().to_vec();
+            decimal_from_tuple(&items, vm)
+        } else {
+            Err(vm.new_type_error(format!(
+                "conversion from {} to Decimal is not supported",
+                value.class().name()
+            )))
+        }
+    }
+
+    fn binary_op(a: &PyObject, b: &PyObject, op: BinaryOp, vm: &VirtualMachine) -> PyResult {
+        let (Some(a), Some(b)) = (convert_op(a, vm), convert_op(b, vm)) else {
+            return Ok(vm.ctx.not_implemented());
+        };
+        let value = current_context(vm).run(vm, |ctx, status| op(&a, &b, ctx, status))?;
+        Ok(new_decimal(value, vm).into())
+    }
+
+    fn divmod_op(a: &PyObject, b: &PyObject, vm: &VirtualMachine) -> PyResult {
+        let (Some(a), Some(b)) = (convert_op(a, vm), convert_op(b, vm)) else {
+            return Ok(vm.ctx.not_implemented());
+        };
+        let (q, r) = current_context(vm).run(vm, |ctx, status| a.divmod(&b, ctx, status))?;
+        Ok(vm
+            .new_tuple((new_decimal(q, vm), new_decimal(r, vm)))
+            .into())
+    }
+
+    fn power_op(a: &PyObject, b: &PyObject, c: &PyObject, vm: &VirtualMachine) -> PyResult {
+        let (Some(a), Some(b)) = (convert_op(a, vm), convert_op(b, vm)) else {
+            return Ok(vm.ctx.not_implemented());
+        };
+        let context = current_context(vm);
+        let value = if vm.is_none(c) {
+            context.run(vm, |ctx, status| a.pow(&b, ctx, status))?
+        } else {
+            let Some(c) = convert_op(c, vm) else {
+                return Ok(vm.ctx.not_implemented());
+            };
+            context.run(vm, |ctx, status| a.pow_mod(&b, &c, ctx, status))?
+        };
+        Ok(new_decimal(value, vm).into())
+    }
+
+    #[derive(FromArgs)]
+    struct DecimalArgs {
+        #[pyarg(any, optional)]
+        value: OptionalArg<PyObjectRef>,
+        #[pyarg(any, default)]
+        context: Option<PyRef<PyContext>>,
+    }
+
+    impl Constructor for PyDecimal {
+        type Args = DecimalArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let value = match args.value {
+                OptionalArg::Present(value) => {
+                    let context = resolve_context(args.context, vm);
+                    decimal_from_object(&value, &context, vm)?
+                }
+                OptionalArg::Missing => Decimal::zero(),
+            };
+            PyDecimal { value }
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct ContextArg {
+        #[pyarg(any, default)]
+        context: Option<PyRef<PyContext>>,
+    }
+
+    #[derive(FromArgs)]
+    struct BinaryArgs {
+        #[pyarg(any)]
+        other: PyObjectRef,
+        #[pyarg(any, default)]
+        context: Option<PyRef<PyContext>>,
+    }
+
+    #[derive(FromArgs)]
+    struct RoundingArgs {
+        #[pyarg(any, default)]
+        rounding: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        context: Option<PyRef<PyContext>>,
+    }
+
+    #[derive(FromArgs)]
+    struct QuantizeArgs {
+        #[pyarg(any)]
+        exp: PyObjectRef,
+        #[pyarg(any, default)]
+        rounding: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        context: Option<PyRef<PyContext>>,
+    }
+
+    impl PyDecimal {
+        fn unary(
+            &self,
+            args: ContextArg,
+            op: UnaryOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            resolve_context(args.context, vm)
+                .run(vm, |ctx, status| op(&self.value, ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        fn binary(
+            &self,
+            args: BinaryArgs,
+            op: BinaryOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let other = to_decimal(&args.other, vm)?;
+            resolve_context(args.context, vm)
+                .run(vm, |ctx, status| op(&self.value, &other, ctx, status))
+                .map(|value| new_decimal(value, vm))
+        }
+
+        fn is_canonical(&self) -> bool {
+            true
+        }
+
+        fn to_int(&self, rounding: Rounding, vm: &VirtualMachine) -> PyResult<PyIntRef> {
+            if self.value.is_nan() {
+                return Err(vm.new_value_error("cannot convert NaN to integer".to_owned()));
+            }
+            if self.value.is_infinite() {
+                return Err(vm.new_overflow_error("cannot convert Infinity to integer".to_owned()));
+            }
+            let int = self
+                .value
+                .rescale(0, rounding)
+                .and_then(|value| value.to_bigint())
+                .ok_or_else(|| vm.new_memory_error("".to_owned()))?;
+            Ok(vm.ctx.new_int(int))
+        }
+
+        fn to_float(&self, vm: &VirtualMachine) -> PyResult<f64> {
+            if self.value.is_snan() {
+                return Err(vm.new_value_error("cannot convert signaling NaN to float".to_owned()));
+            }
+            Ok(self.value.to_f64())
+        }
+
+        /// The operands of a rich comparison, with the other side scaled for rationals.
+        fn comparison_operands(
+            &self,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<Option<(Decimal, Decimal)>> {
+            if let Some(other) = convert_op(other, vm) {
+                return Ok(Some((self.value.clone(), other)));
+            }
+            let equality = matches!(op, PyComparisonOp::Eq | PyComparisonOp::Ne);
+            let float = if let Some(float) = other.payload_if_subclass::<PyFloat>(vm) {
+                Some(float.to_f64())
+            } else if let Some(complex) = other.payload_if_subclass::<PyComplex>(vm) {
+                let complex = complex.to_complex();
+                if !equality || complex.im != 0.0 {
+                    return Ok(None);
+                }
+                Some(complex.re)
+            } else {
+                None
+            };
+            if let Some(float) = float {
+                let context = current_context(vm);
+                if equality {
+                    context.state.write().flags |= number::FLOAT_OPERATION;
+                } else {
+                    context.signal(number::FLOAT_OPERATION, vm)?;
+                }
+                return Ok(Some((self.value.clone(), Decimal::from_f64(float))));
+            }
+
+            let rational = vm.import("numbers", 0)?.get_attr("Rational", vm)?;
+            if !other.is_instance(&rational, vm)? {
+                return Ok(None);
+            }
+            let numerator: PyIntRef = other.get_attr("numerator", vm)?.try_into_value(vm)?;
+            let denominator: PyIntRef = other.get_attr("denominator", vm)?.try_into_value(vm)?;
+            let lhs = if self.value.is_finite() {
+                Decimal::new(
+                    self.value.negative,
+                    &self.value.coef * denominator.as_bigint().abs(),
+                    self.value.exp,
+                )
+            } else {
+                self.value.clone()
+            };
+            Ok(Some((lhs, Decimal::from_bigint(numerator.as_bigint()))))
+        }
+
+        fn format_spec(&self, spec: &str, vm: &VirtualMachine) -> PyResult<String> {
+            let invalid = || vm.new_value_error(format!("Invalid format specifier: {spec}"));
+            let spec = FormatSpec::parse(spec).ok_or_else(invalid)?;
+            let state = current_context(vm).state();
+            spec.format(&self.value, &state.inner, state.capitals)
+                .ok_or_else(|| vm.new_memory_error("".to_owned()))
+        }
+    }
+
+    #[pyclass(
+        flags(BASETYPE),
+        with(Constructor, AsNumber, Comparable, Hashable, Representable)
+    )]
+    impl PyDecimal {
+        #[pyclassmethod]
+        fn from_float(cls: PyTypeRef, f: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let value = new_decimal(exact_from_number(&f, vm)?, vm);
+            if cls.is(PyDecimal::class(&vm.ctx)) {
+                Ok(value.into())
+            } else {
+                cls.as_object().call((value,), vm)
+            }
+        }
+
+        #[pymethod(magic)]
+        fn add(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::add, vm)
+        }
+
+        #[pymethod(magic)]
+        fn radd(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::add, vm)
+        }
+
+        #[pymethod(magic)]
+        fn sub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::sub, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rsub(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::sub, vm)
+        }
+
+        #[pymethod(magic)]
+        fn mul(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::mul, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rmul(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::mul, vm)
+        }
+
+        #[pymethod(magic)]
+        fn truediv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::div, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rtruediv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::div, vm)
+        }
+
+        #[pymethod(magic)]
+        fn floordiv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::divide_int, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rfloordiv(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::divide_int, vm)
+        }
+
+        #[pymethod(name = "__mod__")]
+        fn mod_(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(zelf.as_object(), &other, Decimal::rem, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rmod(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            binary_op(&other, zelf.as_object(), Decimal::rem, vm)
+        }
+
+        #[pymethod(magic)]
+        fn divmod(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            divmod_op(zelf.as_object(), &other, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rdivmod(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            divmod_op(&other, zelf.as_object(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn pow(
+            zelf: PyRef<Self>,
+            other: PyObjectRef,
+            modulo: OptionalArg<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let modulo = modulo.unwrap_or_none(vm);
+            power_op(zelf.as_object(), &other, &modulo, vm)
+        }
+
+        #[pymethod(magic)]
+        fn rpow(zelf: PyRef<Self>, other: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            power_op(&other, zelf.as_object(), vm.ctx.none.as_object(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn neg(&self, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(ContextArg { context: None }, Decimal::minus, vm)
+        }
+
+        #[pymethod(magic)]
+        fn pos(&self, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(ContextArg { context: None }, Decimal::plus, vm)
+        }
+
+        #[pymethod(magic)]
+        fn abs(&self, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(ContextArg { context: None }, Decimal::abs_rounded, vm)
+        }
+
+        #[pymethod(magic)]
+        fn bool(&self) -> bool {
+            !self.value.is_zero()
+        }
+
+        #[pymethod(magic)]
+        fn int(&self, vm: &VirtualMachine) -> PyResult<PyIntRef> {
+            self.to_int(Rounding::Down, vm)
+        }
+
+        #[pymethod(magic)]
+        fn trunc(&self, vm: &VirtualMachine) -> PyResult<PyIntRef> {
+            self.to_int(Rounding::Down, vm)
+        }
+
+        #[pymethod(magic)]
+        fn floor(&self, vm: &VirtualMachine) -> PyResult<PyIntRef> {
+            self.to_int(Rounding::Floor, vm)
+        }
+
+        #[pymethod(magic)]
+        fn ceil(&self, vm: &VirtualMachine) -> PyResult<PyIntRef> {
+            self.to_int(Rounding::Ceiling, vm)
+        }
+
+        #[pymethod(magic)]
+        fn float(&self, vm: &VirtualMachine) -> PyResult<f64> {
+            self.to_float(vm)
+        }
+
+        #[pymethod(magic)]
+        fn complex(&self, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+            let real = self.to_float(vm)?;
+            Ok(vm.ctx.new_complex(Complex64::new(real, 0.0)).into())
+        }
+
+        #[pymethod(magic)]
+        fn round(&self, ndigits: OptionalOption<PyObjectRef>, vm: &VirtualMachine) -> PyResult {
+            let Some(ndigits) = ndigits.flatten() else {
+                return self.to_int(Rounding::HalfEven, vm).map(Into::into);
+            };
+            let ndigits = int_arg(&ndigits, "ndigits", vm)?;
+            let exp = Decimal::new(false, BigInt::from(1u32), -ndigits);
+            current_context(vm)
+                .run(vm, |ctx, status| {
+                    self.value.quantize(&exp, ctx.rounding, ctx, status)
+                })
+                .map(|value| new_decimal(value, vm).into())
+        }
+
+        #[pymethod(magic)]
+        fn str(&self, vm: &VirtualMachine) -> String {
+            self.value
+                .to_sci_string(current_context(vm).state().capitals)
+        }
+
+        #[pymethod(magic)]
+        fn format(&self, spec: PyStrRef, vm: &VirtualMachine) -> PyResult<String> {
+            self.format_spec(spec.as_str(), vm)
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyTupleRef {
+            let s = zelf.value.to_sci_string(true);
+            vm.new_tuple((zelf.class().to_owned(), (s,)))
+        }
+
+        #[pymethod(magic)]
+        fn copy(zelf: PyRef<Self>) -> PyRef<Self> {
+            zelf
+        }
+
+        #[pymethod(magic)]
+        fn deepcopy(zelf: PyRef<Self>, _memo: PyObjectRef) -> PyRef<Self> {
+            zelf
+        }
+
+        #[pygetset]
+        fn real(zelf: PyRef<Self>) -> PyRef<Self> {
+            zelf
+        }
+
+        #[pygetset]
+        fn imag(&self, vm: &VirtualMachine) -> PyDecimalRef {
+            new_decimal(Decimal::zero(), vm)
+        }
+
+        #[pymethod]
+        fn conjugate(zelf: PyRef<Self>) -> PyRef<Self> {
+            zelf
+        }
+
+        #[pymethod]
+        fn adjusted(&self) -> i64 {
+            self.value.adjusted()
+        }
+
+        #[pymethod]
+        fn as_tuple(&self, vm: &VirtualMachine) -> PyResult {
+            let value = &self.value;
+            let digits: Vec<PyObjectRef> = if value.is_infinite() {
+                vec![vm.ctx.new_int(0).into()]
+            } else if value.is_nan() && value.coef.is_zero() {
+                vec![]
+            } else {
+                value
+                    .coef
+                    .to_string()
+                    .bytes()
+                    .map(|digit| vm.ctx.new_int(digit - b'0').into())
+                    .collect()
+            };
+            let exponent: PyObjectRef = match value.kind {
+                number::Kind::Finite => vm.ctx.new_int(value.exp).into(),
+                number::Kind::Infinite => vm.ctx.new_str("F").into(),
+                number::Kind::QNaN => vm.ctx.new_str("n").into(),
+                number::Kind::SNaN => vm.ctx.new_str("N").into(),
+            };
+            let args = (value.negative as i32, vm.ctx.new_tuple(digits), exponent);
+            decimal_tuple(vm).call(args, vm)
+        }
+
+        #[pymethod]
+        fn as_integer_ratio(&self, vm: &VirtualMachine) -> PyResult<PyTupleRef> {
+            if self.value.is_nan() {
+                return Err(vm.new_value_error("cannot convert NaN to integer ratio".to_owned()));
+            }
+            if self.value.is_infinite() {
+                return Err(
+                    vm.new_overflow_error("cannot convert Infinity to integer ratio".to_owned())
+                );
+            }
+            let (n, d) = self
+                .value
+                .as_integer_ratio()
+                .ok_or_else(|| vm.new_memory_error("".to_owned()))?;
+            Ok(vm.new_tuple((n, d)))
+        }
+
+        #[pymethod]
+        fn canonical(zelf: PyRef<Self>) -> PyRef<Self> {
+            zelf
+        }
+
+        #[pymethod(name = "is_canonical")]
+        fn is_canonical_(&self) -> bool {
+            self.is_canonical()
+        }
+
+        #[pymethod]
+        fn compare(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::compare, vm)
+        }
+
+        #[pymethod]
+        fn compare_signal(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::compare_signal, vm)
+        }
+
+        #[pymethod]
+        fn compare_total(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            let other = to_decimal(&args.other, vm)?;
+            Ok(new_decimal(self.value.compare_total(&other), vm))
+        }
+
+        #[pymethod]
+        fn compare_total_mag(
+            &self,
+            args: BinaryArgs,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let other = to_decimal(&args.other, vm)?;
+            Ok(new_decimal(self.value.compare_total_mag(&other), vm))
+        }
+
+        #[pymethod]
+        fn copy_abs(&self, vm: &VirtualMachine) -> PyDecimalRef {
+            new_decimal(self.value.abs(), vm)
+        }
+
+        #[pymethod]
+        fn copy_negate(&self, vm: &VirtualMachine) -> PyDecimalRef {
+            new_decimal(self.value.negated(), vm)
+        }
+
+        #[pymethod]
+        fn copy_sign(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            let other = to_decimal(&args.other, vm)?;
+            Ok(new_decimal(self.value.with_sign_of(&other), vm))
+        }
+
+        #[pymethod]
+        fn exp(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::exp, vm)
+        }
+
+        #[pymethod]
+        fn fma(
+            &self,
+            other: PyObjectRef,
+            third: PyObjectRef,
+            args: ContextArg,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let (other, third) = (to_decimal(&other, vm)?, to_decimal(&third, vm)?);
+            resolve_context(args.context, vm)
+                .run(vm, |ctx, status| {
+                    self.value.fma(&other, &third, ctx, status)
+                })
+                .map(|value| new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn is_finite(&self) -> bool {
+            self.value.is_finite()
+        }
+
+        #[pymethod]
+        fn is_infinite(&self) -> bool {
+            self.value.is_infinite()
+        }
+
+        #[pymethod]
+        fn is_nan(&self) -> bool {
+            self.value.is_nan()
+        }
+
+        #[pymethod]
+        fn is_qnan(&self) -> bool {
+            self.value.is_qnan()
+        }
+
+        #[pymethod]
+        fn is_snan(&self) -> bool {
+            self.value.is_snan()
+        }
+
+        #[pymethod]
+        fn is_signed(&self) -> bool {
+            self.value.negative
+        }
+
+        #[pymethod]
+        fn is_zero(&self) -> bool {
+            self.value.is_zero()
+        }
+
+        #[pymethod]
+        fn is_normal(&self, args: ContextArg, vm: &VirtualMachine) -> bool {
+            let context = resolve_context(args.context, vm);
+            self.value.is_normal(&context.state().inner)
+        }
+
+        #[pymethod]
+        fn is_subnormal(&self, args: ContextArg, vm: &VirtualMachine) -> bool {
+            let context = resolve_context(args.context, vm);
+            self.value.is_subnormal(&context.state().inner)
+        }
+
+        #[pymethod]
+        fn ln(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::ln, vm)
+        }
+
+        #[pymethod]
+        fn log10(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::log10, vm)
+        }
+
+        #[pymethod]
+        fn logb(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::logb, vm)
+        }
+
+        #[pymethod]
+        fn logical_and(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::logical_and, vm)
+        }
+
+        #[pymethod]
+        fn logical_invert(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::logical_invert, vm)
+        }
+
+        #[pymethod]
+        fn logical_or(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::logical_or, vm)
+        }
+
+        #[pymethod]
+        fn logical_xor(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::logical_xor, vm)
+        }
+
+        #[pymethod]
+        fn max(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::max, vm)
+        }
+
+        #[pymethod]
+        fn max_mag(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::max_mag, vm)
+        }
+
+        #[pymethod]
+        fn min(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::min, vm)
+        }
+
+        #[pymethod]
+        fn min_mag(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::min_mag, vm)
+        }
+
+        #[pymethod]
+        fn next_minus(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::next_minus, vm)
+        }
+
+        #[pymethod]
+        fn next_plus(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::next_plus, vm)
+        }
+
+        #[pymethod]
+        fn next_toward(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::next_toward, vm)
+        }
+
+        #[pymethod]
+        fn normalize(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::normalize, vm)
+        }
+
+        #[pymethod]
+        fn number_class(&self, args: ContextArg, vm: &VirtualMachine) -> &'static str {
+            let context = resolve_context(args.context, vm);
+            self.value.number_class(&context.state().inner)
+        }
+
+        #[pymethod]
+        fn quantize(&self, args: QuantizeArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            let exp = to_decimal(&args.exp, vm)?;
+            let rounding = args
+                .rounding
+                .map(|rounding| rounding_arg(&rounding, vm))
+                .transpose()?;
+            resolve_context(args.context, vm)
+                .run(vm, |ctx, status| {
+                    let rounding = rounding.unwrap_or(ctx.rounding);
+                    self.value.quantize(&exp, rounding, ctx, status)
+                })
+                .map(|value| new_decimal(value, vm))
+        }
+
+        #[pymethod]
+        fn radix(&self, vm: &VirtualMachine) -> PyDecimalRef {
+            new_decimal(Decimal::from_i64(10), vm)
+        }
+
+        #[pymethod]
+        fn remainder_near(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::rem_near, vm)
+        }
+
+        #[pymethod]
+        fn rotate(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::rotate, vm)
+        }
+
+        #[pymethod]
+        fn same_quantum(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<bool> {
+            let other = to_decimal(&args.other, vm)?;
+            Ok(self.value.same_quantum(&other))
+        }
+
+        #[pymethod]
+        fn scaleb(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::scaleb, vm)
+        }
+
+        #[pymethod]
+        fn shift(&self, args: BinaryArgs, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.binary(args, Decimal::shift, vm)
+        }
+
+        #[pymethod]
+        fn sqrt(&self, args: ContextArg, vm: &VirtualMachine) -> PyResult<PyDecimalRef> {
+            self.unary(args, Decimal::sqrt, vm)
+        }
+
+        #[pymethod]
+        fn to_eng_string(&self, args: ContextArg, vm: &VirtualMachine) -> String {
+            let context = resolve_context(args.context, vm);
+            self.value.to_eng_string(context.state().capitals)
+        }
+
+        #[pymethod]
+        fn to_integral_exact(
+            &self,
+            args: RoundingArgs,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.to_integral_impl(args, true, vm)
+        }
+
+        #[pymethod(name = "to_integral")]
+        #[pymethod]
+        fn to_integral_value(
+            &self,
+            args: RoundingArgs,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            self.to_integral_impl(args, false, vm)
+        }
+
+        fn to_integral_impl(
+            &self,
+            args: RoundingArgs,
+            exact: bool,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyDecimalRef> {
+            let rounding = args
+                .rounding
+                .map(|rounding| rounding_arg(&rounding, vm))
+                .transpose()?;
+            resolve_context(args.context, vm)
+                .run(vm, |ctx, status| {
+                    let rounding = rounding.unwrap_or(ctx.rounding);
+                    self.value.to_integral(rounding, exact, ctx, status)
+                })
+                .map(|value| new_decimal(value, vm))
+        }
+    }
+
+    impl AsNumber for PyDecimal {
+        fn as_number() -> &'static PyNumberMethods {
+            static AS_NUMBER: PyNumberMethods = PyNumberMethods {
+                add: Some(|a, b, vm| binary_op(a, b, Decimal::add, vm)),
+                subtract: Some(|a, b, vm| binary_op(a, b, Decimal::sub, vm)),
+                multiply: Some(|a, b, vm| binary_op(a, b, Decimal::mul, vm)),
+                remainder: Some(|a, b, vm| binary_op(a, b, Decimal::rem, vm)),
+                divmod: Some(divmod_op),
+                power: Some(power_op),
+                negative: Some(|num, vm| PyDecimal::number_downcast(num).neg(vm).map(Into::into)),
+                positive: Some(|num, vm| PyDecimal::number_downcast(num).pos(vm).map(Into::into)),
+                absolute: Some(|num, vm| PyDecimal::number_downcast(num).abs(vm).map(Into::into)),
+                boolean: Some(|num, _vm| Ok(PyDecimal::number_downcast(num).bool())),
+                int: Some(|num, vm| PyDecimal::number_downcast(num).int(vm).map(Into::into)),
+                float: Some(|num, vm| {
+                    let value = PyDecimal::number_downcast(num).to_float(vm)?;
+                    Ok(vm.ctx.new_float(value).into())
+                }),
+                floor_divide: Some(|a, b, vm| binary_op(a, b, Decimal::divide_int, vm)),
+                true_divide: Some(|a, b, vm| binary_op(a, b, Decimal::div, vm)),
+                ..PyNumberMethods::NOT_IMPLEMENTED
+            };
+            &AS_NUMBER
+        }
+    }
+
+    impl Comparable for PyDecimal {
+        fn cmp(
+            zelf: &Py<Self>,
+            other: &PyObject,
+            op: PyComparisonOp,
+            vm: &VirtualMachine,
+        ) -> PyResult<PyComparisonValue> {
+            let Some((a, b)) = zelf.comparison_operands(other, op, vm)? else {
+                return Ok(PyComparisonValue::NotImplemented);
+            };
+            if a.is_nan() || b.is_nan() {
+                let equality = matches!(op, PyComparisonOp::Eq | PyComparisonOp::Ne);
+                // ordering NaNs is invalid; only signaling ones make equality invalid
+                if !equality || a.is_snan() || b.is_snan() {
+                    current_context(vm).signal(number::INVALID_OPERATION, vm)?;
+                }
+                return Ok(PyComparisonValue::Implemented(op == PyComparisonOp::Ne));
+            }
+            Ok(PyComparisonValue::Implemented(op.eval_ord(a.cmp_value(&b))))
+        }
+    }
+
+    impl Hashable for PyDecimal {
+        fn hash(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyHash> {
+            let value = &zelf.value;
+            if value.is_snan() {
+                Err(vm.new_type_error("Cannot hash a signaling NaN value.".to_owned()))
+            } else if value.is_nan() {
+                Ok(hash::hash_object_id(zelf.get_id()))
+            } else {
+                Ok(value.hash())
+            }
+        }
+    }
+
+    impl Representable for PyDecimal {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let capitals = current_context(vm).state().capitals;
+            Ok(format!("Decimal('{}')", zelf.value.to_sci_string(capitals)))
+        }
+    }
+
+    #[pyattr(name = "DecimalTuple", once)]
+    fn decimal_tuple(vm: &VirtualMachine) -> PyObjectRef {
+        let make = || -> PyResult {
+            let namedtuple = vm.import("collections", 0)?.get_attr("namedtuple", vm)?;
+            let typ = namedtuple.call(("DecimalTuple", "sign digits exponent"), vm)?;
+            typ.set_attr("__module__", vm.ctx.new_str("decimal"), vm)?;
+            Ok(typ)
+        };
+        make().unwrap_or_else(|_| vm.ctx.types.tuple_type.to_owned().into())
+    }
+
+    /// A parsed `[[fill]align][sign][z][#][0][width][,|_][.precision][type]` specifier.
+    struct FormatSpec {
+        fill: char,
+        align: char,
+        sign: char,
+        no_neg_0: bool,
+        alt: bool,
+        zeropad: bool,
+        width: usize,
+        thousands_sep: &'static str,
+        precision: Option<i64>,
+        ty: Option<char>,
+    }
+
+    impl FormatSpec {
+        fn parse(spec: &str) -> Option<Self> {
+            let chars: Vec<char> = spec.chars().collect();
+            let mut pos = 0;
+            let is_align = |c: char| matches!(c, '<' | '>' | '=' | '^');
+            let (mut fill, mut align) = (None, None);
+            if chars.len() >= 2 && is_align(chars[1]) {
+                fill = Some(chars[0]);
+                align = Some(chars[1]);
+                pos = 2;
+            } else if chars.first().copied().map_or(false, is_align) {
+                align = Some(chars[0]);
+                pos = 1;
+            }
+            let mut take = |pred: &dyn Fn(char) -> bool| -> Option<char> {
+                let c = *chars.get(pos).filter(|&&c| pred(c))?;
+                pos += 1;
+                Some(c)
+            };
+            let sign = take(&|c| matches!(c, '+' | '-' | ' ')).unwrap_or('-');
+            let no_neg_0 = take(&|c| c == 'z').is_some();
+            let alt = take(&|c| c == '#').is_some();
+            let zeropad = take(&|c| c == '0').is_some();
+            let mut width = String::new();
+            while let Some(digit) = take(&|c| c.is_ascii_digit()) {
+                width.push(digit);
+            }
+            let thousands_sep = match take(&|c| c == ',' || c == '_') {
+                Some(',') => ",",
+                Some(_) => "_",
+                None => "",
+            };
+            let precision = if take(&|c| c == '.').is_some() {
+                let mut digits = String::new();
+                while let Some(digit) = take(&|c| c.is_ascii_digit()) {
+                    digits.push(digit);
+                }
+                Some(digits.parse().ok()?)
+            } else {
+                None
+            };
+            let ty = take(&|c| "eEfFgGn%".contains(c));
+            if pos != chars.len() {
+                return None;
+            }
+
+            if zeropad {
+                // the 0 flag picks its own fill and alignment
+                if fill.is_some() || align.is_some() {
+                    return None;
+                }
+                fill = Some('0');
+                align = Some('=');
+            }
+            if ty == Some('n') && !thousands_sep.is_empty() {
+                return None;
+            }
+            let precision = match (ty, precision) {
+                (Some('g' | 'G' | 'n'), Some(0)) => Some(1),
+                (_, precision) => precision,
+            };
+            Some(FormatSpec {
+                fill: fill.unwrap_or(' '),
+                align: align.unwrap_or('>'),
+                sign,
+                no_neg_0,
+                alt,
+                zeropad,
+                width: if width.is_empty() {
+                    0
+                } else {
+                    width.parse().ok()?
+                },
+                thousands_sep,
+                precision,
+                ty,
+            })
+        }
+
+        fn sign_str(&self, negative: bool) -> &'static str {
+            match (negative, self.sign) {
+                (true, _) => "-",
+                (false, '+') => "+",
+                (false, ' ') => " ",
+                _ => "",
+            }
+        }
+
+        fn align(&self, sign: &str, body: &str) -> String {
+            let len = sign.chars().count() + body.chars().count();
+            let padding: String = std::iter::repeat(self.fill)
+                .take(self.width.saturating_sub(len))
+                .collect();
+            match self.align {
+                '<' => format!("{sign}{body}{padding}"),
+                '=' => format!("{sign}{padding}{body}"),
+                '^' => {
+                    let half = padding.chars().count() / 2;
+                    let (left, right) = padding.split_at(
+                        padding
+                            .char_indices()
+                            .nth(half)
+                            .map_or(padding.len(), |(i, _)| i),
+                    );
+                    format!("{left}{sign}{body}{right}")
+                }
+                _ => format!("{padding}{sign}{body}"),
+            }
+        }
+
+        fn insert_thousands_sep(&self, digits: &str, min_width: i64) -> String {
+            let sep = self.thousands_sep;
+            let mut min_width = min_width;
+            if sep.is_empty() {
+                let width = (digits.len() as i64).max(min_width).max(1) as usize;
+                return format!("{}{digits}", "0".repeat(width - digits.len()));
+            }
+            let mut digits = digits;
+            let mut groups = Vec::new();
+            loop {
+                let l = 3.min((digits.len() as i64).max(min_width).max(1)) as usize;
+                let take = l.min(digits.len());
+                let (rest, group) = digits.split_at(digits.len() - take);
+                groups.push(format!("{}{group}", "0".repeat(l - take)));
+                digits = rest;
+                min_width -= l as i64;
+                if digits.is_empty() && min_width <= 0 {
+                    break;
+                }
+                min_width -= sep.len() as i64;
+            }
+            groups.reverse();
+            groups.join(sep)
+        }
+
+        /// The formatted value; None if rounding it needs a coefficient too large to compute.
+        fn format(&self, value: &Decimal, ctx: &number::Context, capitals: bool) -> Option<String> {
+            if value.is_special() {
+                let mut body = value.abs().to_sci_string(true);
+                if self.ty == Some('%') {
+                    body.push('%');
+                }
+                return Some(self.align(self.sign_str(value.negative), &body));
+            }
+
+            let ty = self.ty.unwrap_or(if capitals { 'G' } else { 'g' });
+            let mut value = value.clone();
+            if ty == '%' {
+                value.exp += 2;
+            }
+            let rounding = ctx.rounding;
+            if let Some(precision) = self.precision {
+                match ty {
+                    'e' | 'E' => value = value.round_to_places(precision + 1, rounding)?,
+                    'f' | 'F' | '%' => value = value.rescale(-precision, rounding)?,
+                    _ if value.digits() > precision => {
+                        value = value.round_to_places(precision, rounding)?
+                    }
+                    _ => {}
+                }
+            }
+            if value.coef.is_zero() && value.exp > 0 && matches!(ty, 'f' | 'F' | '%') {
+                value = value.rescale(0, rounding)?;
+            }
+            let negative = value.negative && !(value.coef.is_zero() && self.no_neg_0);
+
+            let digits = value.coef.to_string();
+            let leftdigits = value.exp + digits.len() as i64;
+            let dotplace = match ty {
+                'e' | 'E' => match self.precision {
+                    Some(precision) if value.coef.is_zero() => 1 - precision,
+                    _ => 1,
+                },
+                'f' | 'F' | '%' => leftdigits,
+                _ if value.exp <= 0 && leftdigits > -6 => leftdigits,
+                _ => 1,
+            };
+            let (intpart, mut fracpart) = if dotplace < 0 {
+                (
+                    "0".to_owned(),
+                    format!("{}{digits}", "0".repeat(-dotplace as usize)),
+                )
+            } else if dotplace as usize > digits.len() {
+                let zeros = "0".repeat(dotplace as usize - digits.len());
+                (format!("{digits}{zeros}"), String::new())
+            } else {
+                let (int, frac) = digits.split_at(dotplace as usize);
+                let int = if int.is_empty() { "0" } else { int };
+                (int.to_owned(), frac.to_owned())
+            };
+            let exp = leftdigits - dotplace;
+
+            let sign = self.sign_str(negative);
+            if !fracpart.is_empty() || self.alt {
+                fracpart.insert(0, '.');
+            }
+            if exp != 0 || matches!(ty, 'e' | 'E') {
+                let echar = if matches!(ty, 'E' | 'G') { 'E' } else { 'e' };
+                fracpart.push_str(&format!("{echar}{exp:+}"));
+            }
+            if ty == '%' {
+                fracpart.push('%');
+            }
+            let min_width = if self.zeropad {
+                self.width as i64 - fracpart.len() as i64 - sign.len() as i64
+            } else {
+                0
+            };
+            let intpart = self.insert_thousands_sep(&intpart, min_width);
+            Some(self.align(sign, &format!("{intpart}{fracpart}")))
+        }
+    }
+}
@@ -0,0 +1,2394 @@
+//! Arbitrary precision decimal arithmetic, as described by the General Decimal Arithmetic
+//! Specification. The algorithms are the ones `_pydecimal` uses, carried over to big integers.
+//!
+//! Operations never fail: exceptional conditions are recorded as bits in a status word and a
+//! quiet NaN, infinity or rounded value is returned, exactly as the specification describes for
+//! untrapped conditions. Turning those bits into Python exceptions is left to the caller.
+
+use crate::common::hash::{self, PyHash};
+use malachite_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+pub const CLAMPED: u32 = 1 << 0;
+pub const CONVERSION_SYNTAX: u32 = 1 << 1;
+pub const DIVISION_BY_ZERO: u32 = 1 << 2;
+pub const DIVISION_IMPOSSIBLE: u32 = 1 << 3;
+pub const DIVISION_UNDEFINED: u32 = 1 << 4;
+pub const FLOAT_OPERATION: u32 = 1 << 5;
+pub const INEXACT: u32 = 1 << 6;
+pub const INVALID_CONTEXT: u32 = 1 << 7;
+pub const INVALID_OPERATION: u32 = 1 << 8;
+pub const OVERFLOW: u32 = 1 << 9;
+pub const ROUNDED: u32 = 1 << 10;
+pub const SUBNORMAL: u32 = 1 << 11;
+pub const UNDERFLOW: u32 = 1 << 12;
+/// Not a condition of the specification: the result would need a coefficient too large to
+/// compute. Like `MPD_Malloc_error` in libmpdec, it is reported as a MemoryError.
+pub const MALLOC_ERROR: u32 = 1 << 13;
+
+/// The conditions that are all reported through the InvalidOperation signal.
+pub const IEEE_INVALID: u32 = CONVERSION_SYNTAX
+    | DIVISION_IMPOSSIBLE
+    | DIVISION_UNDEFINED
+    | INVALID_CONTEXT
+    | INVALID_OPERATION;
+
+pub const MAX_PREC: i64 = 999_999_999_999_999_999;
+pub const MAX_EMAX: i64 = 999_999_999_999_999_999;
+pub const MIN_EMIN: i64 = -999_999_999_999_999_999;
+pub const MIN_ETINY: i64 = MIN_EMIN - (MAX_PREC - 1);
+
+/// Exponents read from strings are saturated to this, which is far outside any context.
+const EXP_LIMIT: i64 = 4 * MAX_EMAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Up,
+    Down,
+    Ceiling,
+    Floor,
+    HalfUp,
+    HalfDown,
+    HalfEven,
+    ZeroFiveUp,
+}
+
+impl Rounding {
+    pub const ALL: [Rounding; 8] = [
+        Rounding::Up,
+        Rounding::Down,
+        Rounding::Ceiling,
+        Rounding::Floor,
+        Rounding::HalfUp,
+        Rounding::HalfDown,
+        Rounding::HalfEven,
+        Rounding::ZeroFiveUp,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rounding::Up => "ROUND_UP",
+            Rounding::Down => "ROUND_DOWN",
+            Rounding::Ceiling => "ROUND_CEILING",
+            Rounding::Floor => "ROUND_FLOOR",
+            Rounding::HalfUp => "ROUND_HALF_UP",
+            Rounding::HalfDown => "ROUND_HALF_DOWN",
+            Rounding::HalfEven => "ROUND_HALF_EVEN",
+            Rounding::ZeroFiveUp => "ROUND_05UP",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|rounding| rounding.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub prec: i64,
+    pub rounding: Rounding,
+    pub emin: i64,
+    pub emax: i64,
+    pub clamp: bool,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            prec: 28,
+            rounding: Rounding::HalfEven,
+            emin: -999_999,
+            emax: 999_999,
+            clamp: false,
+        }
+    }
+}
+
+impl Context {
+    /// The smallest exponent a subnormal result can have.
+    pub fn etiny(&self) -> i64 {
+        self.emin - self.prec + 1
+    }
+
+    /// The largest exponent a result can have when clamping.
+    pub fn etop(&self) -> i64 {
+        self.emax - self.prec + 1
+    }
+
+    fn with_rounding(&self, rounding: Rounding) -> Context {
+        Context { rounding, ..*self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Finite,
+    Infinite,
+    QNaN,
+    SNaN,
+}
+
+/// `(-1)**negative * coef * 10**exp`, or a special value. The coefficient of a NaN is its
+/// diagnostic payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decimal {
+    pub negative: bool,
+    pub coef: BigInt,
+    pub exp: i64,
+    pub kind: Kind,
+}
+
+/// `10**n`, or None if it has too many digits to compute.
+fn checked_pow10(n: i64) -> Option<BigInt> {
+    debug_assert!(n >= 0);
+    u32::try_from(n).ok().map(|n| BigInt::from(10u32).pow(n))
+}
+
+/// `10**n` where `n` is bounded by the digits of a coefficient or by the precision.
+fn pow10(n: i64) -> BigInt {
+    checked_pow10(n).expect("power of ten too large to compute")
+}
+
+/// Number of decimal digits in a nonnegative integer; zero has one digit.
+fn num_digits(n: &BigInt) -> i64 {
+    if let Some(n) = n.to_u64() {
+        return n.checked_ilog10().map_or(1, |log| log as i64 + 1);
+    }
+    // 2**(bits-1) <= n < 2**bits, so the estimate is exact or one short
+    let estimate = ((n.bits() - 1) as f64 * std::f64::consts::LOG10_2) as i64 + 1;
+    if *n >= pow10(estimate) {
+        estimate + 1
+    } else {
+        estimate
+    }
+}
+
+/// Closest integer to a / b, ties to even.
+fn div_nearest(a: &BigInt, b: &BigInt) -> BigInt {
+    let (q, r) = a.div_mod_floor(b);
+    let twice = r * 2u32 + if q.is_odd() { 1u32 } else { 0u32 };
+    if twice > *b {
+        q + 1u32
+    } else {
+        q
+    }
+}
+
+fn rshift_nearest(x: &BigInt, shift: i64) -> BigInt {
+    div_nearest(x, &(BigInt::one() << shift as usize))
+}
+
+/// Closest integer to the square root of n, starting Newton's method from a >= sqrt(n).
+fn sqrt_nearest(n: &BigInt, a: BigInt) -> BigInt {
+    let mut a = a;
+    let mut b = BigInt::zero();
+    while a != b {
+        let next = (&a + (n + &a - 1u32) / &a) >> 1usize;
+        b = std::mem::replace(&mut a, next);
+    }
+    a
+}
+
+/// `ceil(10 * digits(m) / (3 * l))`, the number of series terms needed by `ilog` and `iexp`.
+fn series_terms(m: &BigInt, l: i64) -> i64 {
+    -Integer::div_floor(&(-10 * num_digits(m)), &(3 * l))
+}
+
+/// Integer approximation to `m * log(x / m)`, with absolute error at most 1.
+fn ilog(x: &BigInt, m: &BigInt) -> BigInt {
+    const L: i64 = 8;
+    let mut y = x - m;
+    let mut r = 0i64;
+    loop {
+        let ay = y.abs();
+        let big = if r <= L {
+            (&ay << (L - r) as usize) >= *m
+        } else {
+            (&ay >> (r - L) as usize) >= *m
+        };
+        if !big {
+            break;
+        }
+        let root = sqrt_nearest(&(m * (m + rshift_nearest(&y, r))), m.clone());
+        y = div_nearest(&(m * &y * 2u32), &(m + root));
+        r += 1;
+    }
+    let t = series_terms(m, L);
+    let yshift = rshift_nearest(&y, r);
+    let mut w = div_nearest(m, &BigInt::from(t));
+    for k in (1..t).rev() {
+        w = div_nearest(m, &BigInt::from(k)) - div_nearest(&(&yshift * &w), m);
+    }
+    div_nearest(&(w * y), m)
+}
+
+/// Integer approximation to `m * exp(x / m)` for 0 <= x <= 2.4 * m.
+fn iexp(x: &BigInt, m: &BigInt) -> BigInt {
+    const L: i64 = 8;
+    let r = ((x << L as usize) / m).bits();
+    let t = series_terms(m, L);
+    let mut y = div_nearest(x, &BigInt::from(t));
+    let mshift = m << r as usize;
+    for i in (1..t).rev() {
+        y = div_nearest(&(x * (&mshift + &y)), &(&mshift * i as u64));
+    }
+    for k in (0..r).rev() {
+        let mshift = m << (k + 2) as usize;
+        y = div_nearest(&(&y * (&y + &mshift)), &mshift);
+    }
+    m + y
+}
+
+thread_local! {
+    static LOG10_DIGITS: RefCell<String> =
+        RefCell::new("23025850929940456840179914546843642076011014886".to_owned());
+}
+
+/// `floor(log(10) * 10**p)`, computing and caching more digits as needed.
+fn log10_digits(p: i64) -> BigInt {
+    LOG10_DIGITS.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if p as usize >= cache.len() {
+            let mut extra = 3;
+            let digits = loop {
+                let m = pow10(p + extra + 2);
+                let digits = div_nearest(&ilog(&(&m * 10u32), &m), &BigInt::from(100u32));
+                let digits = digits.to_string();
+                if !digits.ends_with(&"0".repeat(extra as usize)) {
+                    break digits;
+                }
+                extra += 3;
+            };
+            let digits = digits.trim_end_matches('0');
+            *cache = digits[..digits.len() - 1].to_owned();
+        }
+        cache[..=p as usize].parse().unwrap()
+    })
+}
+
+/// Integer approximation to `10**p * log10(c * 10**e)`, with error less than 1.
+fn dlog10(c: &BigInt, e: i64, p: i64) -> BigInt {
+    let p = p + 2;
+    let l = num_digits(c);
+    let f = e + l - i64::from(e + l >= 1);
+    let (log_d, log_tenpower) = if p > 0 {
+        let m = pow10(p);
+        let k = e + p - f;
+        let c = if k >= 0 {
+            c * pow10(k)
+        } else {
+            div_nearest(c, &pow10(-k))
+        };
+        let log_d = ilog(&c, &m);
+        let log_10 = log10_digits(p);
+        (div_nearest(&(log_d * &m), &log_10), BigInt::from(f) * m)
+    } else {
+        (BigInt::zero(), div_nearest(&BigInt::from(f), &pow10(-p)))
+    };
+    div_nearest(&(log_tenpower + log_d), &BigInt::from(100u32))
+}
+
+/// Integer approximation to `10**p * log(c * 10**e)`, with error less than 1.
+fn dlog(c: &BigInt, e: i64, p: i64) -> BigInt {
+    let p = p + 2;
+    let l = num_digits(c);
+    let f = e + l - i64::from(e + l >= 1);
+    let log_d = if p > 0 {
+        let k = e + p - f;
+        let c = if k >= 0 {
+            c * pow10(k)
+        } else {
+            div_nearest(c, &pow10(-k))
+        };
+        ilog(&c, &pow10(p))
+    } else {
+        BigInt::zero()
+    };
+    let f_log_ten = if f != 0 {
+        let extra = num_digits(&BigInt::from(f.unsigned_abs())) - 1;
+        if p + extra >= 0 {
+            div_nearest(&(BigInt::from(f) * log10_digits(p + extra)), &pow10(extra))
+        } else {
+            BigInt::zero()
+        }
+    } else {
+        BigInt::zero()
+    };
+    div_nearest(&(f_log_ten + log_d), &BigInt::from(100u32))
+}
+
+/// `(d, f)` with `10**(p-1) <= d <= 10**p` and `d * 10**f` within one of `exp(c * 10**e)`.
+fn dexp(c: &BigInt, e: i64, p: i64) -> (BigInt, i64) {
+    let p = p + 2;
+    let extra = (e + num_digits(&c.abs()) - 1).max(0);
+    let q = p + extra;
+    let shift = e + q;
+    let cshift = if shift >= 0 {
+        c * pow10(shift)
+    } else {
+        c.div_floor(&pow10(-shift))
+    };
+    let (quot, rem) = cshift.div_mod_floor(&log10_digits(q));
+    let rem = div_nearest(&rem, &pow10(extra));
+    let coeff = div_nearest(&iexp(&rem, &pow10(p)), &BigInt::from(1000u32));
+    (coeff, quot.to_i64().unwrap_or(i64::MAX / 2) - p + 3)
+}
+
+/// `(c, e)` with `c * 10**e` approximating `x**y` to `p` digits, where x = xc * 10**xe and
+/// y = yc * 10**ye.
+fn dpower(xc: &BigInt, xe: i64, yc: &BigInt, ye: i64, p: i64) -> (BigInt, i64) {
+    let b = num_digits(&yc.abs()) + ye;
+    let lxc = dlog(xc, xe, p + b + 1);
+    let shift = ye - b;
+    let pc = if shift >= 0 {
+        lxc * yc * pow10(shift)
+    } else {
+        div_nearest(&(lxc * yc), &pow10(-shift))
+    };
+    if pc.is_zero() {
+        // x**y is 1 to within the precision; decide which side of 1 it is on
+        if (num_digits(xc) + xe >= 1) == yc.is_positive() {
+            (pow10(p - 1) + 1u32, 1 - p)
+        } else {
+            (pow10(p) - 1u32, -p)
+        }
+    } else {
+        let (coeff, exp) = dexp(&pc, -(p + 1), p + 1);
+        (div_nearest(&coeff, &BigInt::from(10u32)), exp + 1)
+    }
+}
+
+/// A lower bound for `100 * log10(c)`.
+fn log10_lb(c: &BigInt) -> i64 {
+    let s = c.to_string();
+    let correction = match s.as_bytes()[0] {
+        b'1' => 100,
+        b'2' => 70,
+        b'3' => 53,
+        b'4' => 40,
+        b'5' => 31,
+        b'6' => 23,
+        b'7' => 16,
+        b'8' => 10,
+        _ => 5,
+    };
+    100 * s.len() as i64 - correction
+}
+
+/// `n * 10**e` if that is an integer.
+fn decimal_lshift_exact(n: &BigInt, e: &BigInt) -> Option<BigInt> {
+    if n.is_zero() {
+        return Some(BigInt::zero());
+    }
+    let e = e.to_i64()?;
+    if e >= 0 {
+        Some(n * pow10(e))
+    } else {
+        let divisor = pow10(-e);
+        let (q, r) = n.div_rem(&divisor);
+        r.is_zero().then_some(q)
+    }
+}
+
+/// Whether a correctly rounded result to `p` digits can be read off `coeff`, which carries some
+/// extra digits and an error of at most one unit in the last place.
+fn ziv_done(coeff: &BigInt, p: i64) -> bool {
+    let shift = num_digits(&coeff.abs()) - p - 1;
+    shift >= 0 && !(coeff % (pow10(shift) * 5u32)).is_zero()
+}
+
+/// Whether `a` sorts before `b` as Python compares their decimal strings.
+fn str_lt(a: &BigInt, b: &BigInt) -> bool {
+    a.to_string() < b.to_string()
+}
+
+/// Drop `drop` > 0 trailing digits of `coef`, rounding as `rounding` says; also report whether
+/// anything nonzero was discarded.
+fn round_coef(coef: &BigInt, drop: i64, negative: bool, rounding: Rounding) -> (BigInt, bool) {
+    let divisor = pow10(drop);
+    let (q, r) = coef.div_rem(&divisor);
+    if r.is_zero() {
+        return (q, false);
+    }
+    let increment = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Ceiling => !negative,
+        Rounding::Floor => negative,
+        Rounding::HalfUp => r * 2u32 >= divisor,
+        Rounding::HalfDown => r * 2u32 > divisor,
+        Rounding::HalfEven => match (r * 2u32).cmp(&divisor) {
+            Ordering::Greater => true,
+            Ordering::Equal => q.is_odd(),
+            Ordering::Less => false,
+        },
+        Rounding::ZeroFiveUp => matches!((&q % 10u32).to_u32(), Some(0 | 5)),
+    };
+    if increment {
+        (q + 1u32, true)
+    } else {
+        (q, true)
+    }
+}
+
+impl Decimal {
+    pub fn new(negative: bool, coef: BigInt, exp: i64) -> Self {
+        Decimal {
+            negative,
+            coef,
+            exp,
+            kind: Kind::Finite,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::new(false, BigInt::zero(), 0)
+    }
+
+    pub fn infinity(negative: bool) -> Self {
+        Decimal {
+            negative,
+            coef: BigInt::zero(),
+            exp: 0,
+            kind: Kind::Infinite,
+        }
+    }
+
+    pub fn nan(negative: bool, payload: BigInt, signaling: bool) -> Self {
+        Decimal {
+            negative,
+            coef: payload,
+            exp: 0,
+            kind: if signaling { Kind::SNaN } else { Kind::QNaN },
+        }
+    }
+
+    fn quiet_nan() -> Self {
+        Self::nan(false, BigInt::zero(), false)
+    }
+
+    pub fn from_bigint(value: &BigInt) -> Self {
+        Self::new(value.is_negative(), value.abs(), 0)
+    }
+
+    pub fn from_i64(value: i64) -> Self {
+        Self::from_bigint(&BigInt::from(value))
+    }
+
+    /// The exact value of a float.
+    pub fn from_f64(value: f64) -> Self {
+        let negative = value.is_sign_negative();
+        if value.is_nan() {
+            return Self::nan(negative, BigInt::zero(), false);
+        }
+        if value.is_infinite() {
+            return Self::infinity(negative);
+        }
+        let bits = value.abs().to_bits();
+        let biased = ((bits >> 52) & 0x7ff) as i64;
+        let fraction = bits & ((1 << 52) - 1);
+        let (mut mantissa, mut exp2) = if biased == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), biased - 1075)
+        };
+        if mantissa == 0 {
+            return Self::new(negative, BigInt::zero(), 0);
+        }
+        while mantissa & 1 == 0 && exp2 < 0 {
+            mantissa >>= 1;
+            exp2 += 1;
+        }
+        if exp2 >= 0 {
+            Self::new(negative, BigInt::from(mantissa) << exp2 as usize, 0)
+        } else {
+            // m * 2**-k == m * 5**k * 10**-k
+            let coef = BigInt::from(mantissa) * BigInt::from(5u32).pow(-exp2 as u32);
+            Self::new(negative, coef, exp2)
+        }
+    }
+
+    /// Parse the ASCII form of a number, without surrounding whitespace.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (negative, rest) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let lower = rest.to_ascii_lowercase();
+        if lower == "inf" || lower == "infinity" {
+            return Some(Self::infinity(negative));
+        }
+        let nan = lower
+            .strip_prefix("nan")
+            .map(|payload| (payload, false))
+            .or_else(|| lower.strip_prefix("snan").map(|payload| (payload, true)));
+        if let Some((payload, signaling)) = nan {
+            if !payload.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let payload = if payload.is_empty() {
+                BigInt::zero()
+            } else {
+                payload.parse().ok()?
+            };
+            return Some(Self::nan(negative, payload, signaling));
+        }
+
+        let (mantissa, exponent) = match lower.find('e') {
+            Some(pos) => (&lower[..pos], Some(&lower[pos + 1..])),
+            None => (&lower[..], None),
+        };
+        let (int_part, frac_part) = match mantissa.find('.') {
+            Some(pos) => (&mantissa[..pos], &mantissa[pos + 1..]),
+            None => (mantissa, ""),
+        };
+        let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if int_part.is_empty() && frac_part.is_empty()
+            || !all_digits(int_part)
+            || !all_digits(frac_part)
+        {
+            return None;
+        }
+        let mut exp = match exponent {
+            Some(exponent) => {
+                let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                if digits.is_empty() || !all_digits(digits) {
+                    return None;
+                }
+                let value: BigInt = exponent.parse().ok()?;
+                value
+                    .to_i64()
+                    .unwrap_or(if value.is_negative() {
+                        -EXP_LIMIT
+                    } else {
+                        EXP_LIMIT
+                    })
+                    .clamp(-EXP_LIMIT, EXP_LIMIT)
+            }
+            None => 0,
+        };
+        exp -= frac_part.len() as i64;
+        let coef: BigInt = format!("{int_part}{frac_part}").parse().ok()?;
+        Some(Self::new(negative, coef, exp))
+    }
+
+    pub fn is_nan(&self) -> bool {
+        matches!(self.kind, Kind::QNaN | Kind::SNaN)
+    }
+
+    pub fn is_snan(&self) -> bool {
+        self.kind == Kind::SNaN
+    }
+
+    pub fn is_qnan(&self) -> bool {
+        self.kind == Kind::QNaN
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.kind == Kind::Infinite
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.kind == Kind::Finite
+    }
+
+    pub fn is_special(&self) -> bool {
+        self.kind != Kind::Finite
+    }
+
+    /// A finite zero.
+    pub fn is_zero(&self) -> bool {
+        self.is_finite() && self.coef.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        if !self.is_finite() {
+            return false;
+        }
+        if self.exp >= 0 || self.coef.is_zero() {
+            return true;
+        }
+        // below one, so the fractional digits aren't all in the coefficient
+        if self.adjusted() < 0 {
+            return false;
+        }
+        (&self.coef % pow10(-self.exp)).is_zero()
+    }
+
+    /// Whether an integral value is even.
+    fn is_even(&self) -> bool {
+        if self.coef.is_zero() || self.exp > 0 {
+            return true;
+        }
+        (&self.coef / pow10(-self.exp)).is_even()
+    }
+
+    pub fn is_normal(&self, ctx: &Context) -> bool {
+        self.is_finite() && !self.coef.is_zero() && ctx.emin <= self.adjusted()
+    }
+
+    pub fn is_subnormal(&self, ctx: &Context) -> bool {
+        self.is_finite() && !self.coef.is_zero() && self.adjusted() < ctx.emin
+    }
+
+    /// Number of digits in the coefficient.
+    pub fn digits(&self) -> i64 {
+        num_digits(&self.coef)
+    }
+
+    /// The exponent of the most significant digit, 0 for special values.
+    pub fn adjusted(&self) -> i64 {
+        if self.is_finite() {
+            self.exp + self.digits() - 1
+        } else {
+            0
+        }
+    }
+
+    pub fn negated(&self) -> Self {
+        Decimal {
+            negative: !self.negative,
+            ..self.clone()
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Decimal {
+            negative: false,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_sign_of(&self, other: &Decimal) -> Self {
+        Decimal {
+            negative: other.negative,
+            ..self.clone()
+        }
+    }
+
+    /// The value truncated towards zero; None for infinities and NaNs, and for integers too
+    /// large to compute.
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        if !self.is_finite() {
+            return None;
+        }
+        let magnitude = if self.exp >= 0 {
+            &self.coef * checked_pow10(self.exp)?
+        } else if self.adjusted() < 0 {
+            BigInt::zero()
+        } else {
+            &self.coef / pow10(-self.exp)
+        };
+        Some(if self.negative { -magnitude } else { magnitude })
+    }
+
+    /// The closest float, correctly rounded.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = match self.kind {
+            Kind::QNaN | Kind::SNaN => f64::NAN,
+            Kind::Infinite => f64::INFINITY,
+            Kind::Finite => format!("{}e{}", self.coef, self.exp).parse().unwrap(),
+        };
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// `(numerator, denominator)` in lowest terms; the value must be finite. None if a term is
+    /// too large to compute.
+    pub fn as_integer_ratio(&self) -> Option<(BigInt, BigInt)> {
+        if self.coef.is_zero() {
+            return Some((BigInt::zero(), BigInt::one()));
+        }
+        let (mut n, mut d) = if self.exp >= 0 {
+            (&self.coef * checked_pow10(self.exp)?, BigInt::one())
+        } else {
+            (self.coef.clone(), checked_pow10(-self.exp)?)
+        };
+        let g = n.gcd(&d);
+        if !g.is_zero() && !g.is_one() {
+            n /= &g;
+            d /= &g;
+        }
+        Some((if self.negative { -n } else { n }, d))
+    }
+
+    /// The numeric hash, agreeing with `int`, `float` and `Fraction`. NaNs hash by identity
+    /// and must be handled by the caller.
+    pub fn hash(&self) -> PyHash {
+        if self.is_infinite() {
+            return if self.negative { -hash::INF } else { hash::INF };
+        }
+        let modulus = hash::MODULUS as u128;
+        let modpow = |mut base: u128, mut exp: u64| {
+            let mut result = 1u128;
+            base %= modulus;
+            while exp > 0 {
+                if exp & 1 == 1 {
+                    result = result * base % modulus;
+                }
+                base = base * base % modulus;
+                exp >>= 1;
+            }
+            result
+        };
+        let exp_hash = if self.exp >= 0 {
+            modpow(10, self.exp as u64)
+        } else {
+            // 10**-1 is 10**(P-2) modulo P
+            modpow(modpow(10, hash::MODULUS - 2), self.exp.unsigned_abs())
+        };
+        let coef = (&self.coef % hash::MODULUS).to_u64().unwrap() as u128;
+        let value = (coef * exp_hash % modulus) as PyHash;
+        hash::fix_sentinel(if self.negative { -value } else { value })
+    }
+
+    pub fn to_sci_string(&self, capitals: bool) -> String {
+        self.format(false, capitals)
+    }
+
+    pub fn to_eng_string(&self, capitals: bool) -> String {
+        self.format(true, capitals)
+    }
+
+    fn format(&self, eng: bool, capitals: bool) -> String {
+        let sign = if self.negative { "-" } else { "" };
+        let payload = |coef: &BigInt| {
+            if coef.is_zero() {
+                String::new()
+            } else {
+                coef.to_string()
+            }
+        };
+        match self.kind {
+            Kind::Infinite => return format!("{sign}Infinity"),
+            Kind::QNaN => return format!("{sign}NaN{}", payload(&self.coef)),
+            Kind::SNaN => return format!("{sign}sNaN{}", payload(&self.coef)),
+            Kind::Finite => {}
+        }
+        let digits = self.coef.to_string();
+        let leftdigits = self.exp + digits.len() as i64;
+        let dotplace = if self.exp <= 0 && leftdigits > -6 {
+            leftdigits
+        } else if !eng {
+            1
+        } else if self.coef.is_zero() {
+            (leftdigits + 1).rem_euclid(3) - 1
+        } else {
+            (leftdigits - 1).rem_euclid(3) + 1
+        };
+        let (intpart, fracpart) = if dotplace <= 0 {
+            (
+                "0".to_owned(),
+                format!(".{}{digits}", "0".repeat(-dotplace as usize)),
+            )
+        } else if dotplace >= digits.len() as i64 {
+            let zeros = "0".repeat((dotplace - digits.len() as i64) as usize);
+            (format!("{digits}{zeros}"), String::new())
+        } else {
+            let (int, frac) = digits.split_at(dotplace as usize);
+            (int.to_owned(), format!(".{frac}"))
+        };
+        let exp = if leftdigits == dotplace {
+            String::new()
+        } else {
+            let e = if capitals { 'E' } else { 'e' };
+            format!("{e}{:+}", leftdigits - dotplace)
+        };
+        format!("{sign}{intpart}{fracpart}{exp}")
+    }
+
+    /// Round to the context, signalling what that involved.
+    pub fn fix(self, ctx: &Context, status: &mut u32) -> Decimal {
+        match self.kind {
+            Kind::QNaN | Kind::SNaN => return self.fix_nan(ctx),
+            Kind::Infinite => return self,
+            Kind::Finite => {}
+        }
+        let etiny = ctx.etiny();
+        let etop = ctx.etop();
+        if self.coef.is_zero() {
+            let exp_max = if ctx.clamp { etop } else { ctx.emax };
+            let new_exp = self.exp.max(etiny).min(exp_max);
+            if new_exp != self.exp {
+                *status |= CLAMPED;
+                return Decimal::new(self.negative, self.coef, new_exp);
+            }
+            return self;
+        }
+
+        let ndigits = self.digits();
+        let mut exp_min = ndigits + self.exp - ctx.prec;
+        if exp_min > etop {
+            *status |= OVERFLOW | INEXACT | ROUNDED;
+            return Self::overflow(self.negative, ctx);
+        }
+        let subnormal = exp_min < etiny;
+        if subnormal {
+            exp_min = etiny;
+        }
+        if self.exp < exp_min {
+            let (coef, inexact) = self.round_coef_to(exp_min, ctx.rounding);
+            let mut coef = coef;
+            if num_digits(&coef) > ctx.prec {
+                coef /= 10u32;
+                exp_min += 1;
+            }
+            let ans = if exp_min > etop {
+                *status |= OVERFLOW;
+                Self::overflow(self.negative, ctx)
+            } else {
+                Decimal::new(self.negative, coef, exp_min)
+            };
+            if inexact && subnormal {
+                *status |= UNDERFLOW;
+            }
+            if subnormal {
+                *status |= SUBNORMAL;
+            }
+            if inexact {
+                *status |= INEXACT;
+            }
+            *status |= ROUNDED;
+            if ans.is_zero() {
+                *status |= CLAMPED;
+            }
+            return ans;
+        }
+
+        if subnormal {
+            *status |= SUBNORMAL;
+        }
+        if ctx.clamp && self.exp > etop {
+            *status |= CLAMPED;
+            let coef = self.coef * pow10(self.exp - etop);
+            return Decimal::new(self.negative, coef, etop);
+        }
+        self
+    }
+
+    /// The result of an overflow: infinity or the largest finite number, by rounding mode.
+    fn overflow(negative: bool, ctx: &Context) -> Decimal {
+        let infinite = match ctx.rounding {
+            Rounding::HalfUp | Rounding::HalfEven | Rounding::HalfDown | Rounding::Up => true,
+            Rounding::Ceiling => !negative,
+            Rounding::Floor => negative,
+            Rounding::Down | Rounding::ZeroFiveUp => false,
+        };
+        if infinite {
+            Self::infinity(negative)
+        } else {
+            Decimal::new(negative, pow10(ctx.prec) - 1u32, ctx.etop())
+        }
+    }
+
+    /// Truncate a NaN payload that does not fit in the context.
+    fn fix_nan(self, ctx: &Context) -> Decimal {
+        let max_len = ctx.prec - i64::from(ctx.clamp);
+        if !self.coef.is_zero() && num_digits(&self.coef) > max_len {
+            let coef = if max_len > 0 {
+                &self.coef % pow10(max_len)
+            } else {
+                BigInt::zero()
+            };
+            return Decimal { coef, ..self };
+        }
+        self
+    }
+
+    /// The coefficient rounded so that the exponent becomes `exp` > self.exp.
+    fn round_coef_to(&self, exp: i64, rounding: Rounding) -> (BigInt, bool) {
+        let drop = exp - self.exp;
+        if drop > self.digits() {
+            // everything goes; a sticky digit below the rounding position is all that matters
+            round_coef(&BigInt::one(), 1, self.negative, rounding)
+        } else {
+            round_coef(&self.coef, drop, self.negative, rounding)
+        }
+    }
+
+    /// Change the exponent to `exp`, rounding if digits are lost; no context limits apply. None
+    /// if the coefficient would be too large to compute.
+    pub fn rescale(&self, exp: i64, rounding: Rounding) -> Option<Decimal> {
+        if !self.is_finite() {
+            return Some(self.clone());
+        }
+        if self.coef.is_zero() {
+            return Some(Decimal::new(self.negative, BigInt::zero(), exp));
+        }
+        if self.exp >= exp {
+            let coef = &self.coef * checked_pow10(self.exp - exp)?;
+            return Some(Decimal::new(self.negative, coef, exp));
+        }
+        let (coef, _) = self.round_coef_to(exp, rounding);
+        Some(Decimal::new(self.negative, coef, exp))
+    }
+
+    /// Round to `places` significant digits.
+    pub fn round_to_places(&self, places: i64, rounding: Rounding) -> Option<Decimal> {
+        if !self.is_finite() || self.coef.is_zero() {
+            return Some(self.clone());
+        }
+        let ans = self.rescale(self.adjusted() + 1 - places, rounding)?;
+        // rounding 99.97 to three places gives 100.0, which needs one more rescale
+        if ans.adjusted() != self.adjusted() {
+            ans.rescale(ans.adjusted() + 1 - places, rounding)
+        } else {
+            Some(ans)
+        }
+    }
+
+    fn invalid(status: &mut u32, condition: u32) -> Decimal {
+        *status |= condition;
+        Self::quiet_nan()
+    }
+
+    /// The result of an operation whose result would be too large to compute.
+    fn out_of_memory(status: &mut u32) -> Decimal {
+        *status |= MALLOC_ERROR;
+        Self::quiet_nan()
+    }
+
+    /// Rescale as part of an operation, see [`Self::out_of_memory`].
+    fn rescale_or_fail(&self, exp: i64, rounding: Rounding, status: &mut u32) -> Decimal {
+        self.rescale(exp, rounding)
+            .unwrap_or_else(|| Self::out_of_memory(status))
+    }
+
+    /// The quiet NaN result of an operation on NaNs, if any operand is one.
+    fn check_nans(
+        &self,
+        other: Option<&Decimal>,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Option<Decimal> {
+        let operands = std::iter::once(self).chain(other);
+        if let Some(snan) = operands.clone().find(|x| x.is_snan()) {
+            *status |= INVALID_OPERATION;
+            let quiet = Decimal {
+                kind: Kind::QNaN,
+                ..snan.clone()
+            };
+            return Some(quiet.fix_nan(ctx));
+        }
+        operands
+            .clone()
+            .find(|x| x.is_qnan())
+            .map(|nan| nan.clone().fix_nan(ctx))
+    }
+
+    /// Comparison ignoring NaNs, which must already be ruled out.
+    pub fn cmp_value(&self, other: &Decimal) -> Ordering {
+        let inf_rank = |x: &Decimal| match (x.kind, x.negative) {
+            (Kind::Infinite, false) => 1,
+            (Kind::Infinite, true) => -1,
+            _ => 0,
+        };
+        if self.is_special() || other.is_special() {
+            return inf_rank(self).cmp(&inf_rank(other));
+        }
+        match (self.coef.is_zero(), other.coef.is_zero()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => {
+                return if other.negative {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                }
+            }
+            (false, true) => {
+                return if self.negative {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            }
+            (false, false) => {}
+        }
+        if self.negative != other.negative {
+            return if self.negative {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+        }
+        let magnitude = match self.adjusted().cmp(&other.adjusted()) {
+            Ordering::Equal => {
+                // the exponents differ by no more than the number of digits
+                let exp = self.exp.min(other.exp);
+                let a = &self.coef * pow10(self.exp - exp);
+                let b = &other.coef * pow10(other.exp - exp);
+                a.cmp(&b)
+            }
+            ordering => ordering,
+        };
+        if self.negative {
+            magnitude.reverse()
+        } else {
+            magnitude
+        }
+    }
+
+    fn ordering_result(ordering: Ordering) -> Decimal {
+        Self::from_i64(ordering as i64)
+    }
+
+    pub fn add(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        self.add_numbers(other, ctx, status)
+    }
+
+    pub fn sub(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        self.add_numbers(&other.negated(), ctx, status)
+    }
+
+    fn add_numbers(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if self.is_infinite() {
+            if other.is_infinite() && self.negative != other.negative {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            return self.clone();
+        }
+        if other.is_infinite() {
+            return other.clone();
+        }
+
+        let exp = self.exp.min(other.exp);
+        let negative_zero = ctx.rounding == Rounding::Floor && self.negative != other.negative;
+        match (self.coef.is_zero(), other.coef.is_zero()) {
+            (true, true) => {
+                let negative = (self.negative && other.negative) || negative_zero;
+                return Decimal::new(negative, BigInt::zero(), exp).fix(ctx, status);
+            }
+            (true, false) => {
+                let exp = exp.max(other.exp - ctx.prec - 1);
+                return other
+                    .rescale_or_fail(exp, ctx.rounding, status)
+                    .fix(ctx, status);
+            }
+            (false, true) => {
+                let exp = exp.max(self.exp - ctx.prec - 1);
+                return self
+                    .rescale_or_fail(exp, ctx.rounding, status)
+                    .fix(ctx, status);
+            }
+            (false, false) => {}
+        }
+
+        // An operand far below the precision of the other only matters as a sticky digit.
+        let (big, small) = if self.exp < other.exp {
+            (other, self)
+        } else {
+            (self, other)
+        };
+        let floor = big.exp + (-1i64).min(big.digits() - ctx.prec - 2);
+        let small = if small.digits() + small.exp - 1 < floor {
+            Decimal::new(small.negative, BigInt::one(), floor)
+        } else {
+            small.clone()
+        };
+        let exp = small.exp;
+        let signed = |x: &Decimal, coef: BigInt| if x.negative { -coef } else { coef };
+        let sum =
+            signed(big, &big.coef * pow10(big.exp - exp)) + signed(&small, small.coef.clone());
+        if sum.is_zero() {
+            return Decimal::new(negative_zero, sum, exp).fix(ctx, status);
+        }
+        Decimal::new(sum.is_negative(), sum.abs(), exp).fix(ctx, status)
+    }
+
+    pub fn mul(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        let negative = self.negative != other.negative;
+        if self.is_infinite() || other.is_infinite() {
+            if self.is_zero() || other.is_zero() {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            return Self::infinity(negative);
+        }
+        let exp = self.exp + other.exp;
+        Decimal::new(negative, &self.coef * &other.coef, exp).fix(ctx, status)
+    }
+
+    pub fn div(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        let negative = self.negative != other.negative;
+        if self.is_infinite() {
+            if other.is_infinite() {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            return Self::infinity(negative);
+        }
+        if other.is_infinite() {
+            *status |= CLAMPED;
+            return Decimal::new(negative, BigInt::zero(), ctx.etiny());
+        }
+        if other.coef.is_zero() {
+            if self.coef.is_zero() {
+                return Self::invalid(status, DIVISION_UNDEFINED);
+            }
+            *status |= DIVISION_BY_ZERO;
+            return Self::infinity(negative);
+        }
+
+        let (coef, exp) = if self.coef.is_zero() {
+            (BigInt::zero(), self.exp - other.exp)
+        } else {
+            let shift = other.digits() - self.digits() + ctx.prec + 1;
+            let mut exp = self.exp - other.exp - shift;
+            let (mut coef, rem) = if shift >= 0 {
+                (&self.coef * pow10(shift)).div_rem(&other.coef)
+            } else {
+                self.coef.div_rem(&(&other.coef * pow10(-shift)))
+            };
+            if !rem.is_zero() {
+                // make the quotient sticky so that rounding sees it is inexact
+                if (&coef % 5u32).is_zero() {
+                    coef += 1u32;
+                }
+            } else {
+                let ideal_exp = self.exp - other.exp;
+                while exp < ideal_exp && (&coef % 10u32).is_zero() {
+                    coef /= 10u32;
+                    exp += 1;
+                }
+            }
+            (coef, exp)
+        };
+        Decimal::new(negative, coef, exp).fix(ctx, status)
+    }
+
+    /// Integer quotient and remainder, both unrounded; the operands must be finite or the
+    /// divisor infinite, and the divisor nonzero.
+    fn divide_parts(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> (Decimal, Decimal) {
+        let negative = self.negative != other.negative;
+        let ideal_exp = if other.is_infinite() {
+            self.exp
+        } else {
+            self.exp.min(other.exp)
+        };
+        let expdiff = self.adjusted() - other.adjusted();
+        if self.coef.is_zero() || other.is_infinite() || expdiff <= -2 {
+            return (
+                Decimal::new(negative, BigInt::zero(), 0),
+                self.rescale_or_fail(ideal_exp, ctx.rounding, status),
+            );
+        }
+        if expdiff <= ctx.prec {
+            let exp = self.exp.min(other.exp);
+            let a = &self.coef * pow10(self.exp - exp);
+            let b = &other.coef * pow10(other.exp - exp);
+            let (q, r) = a.div_rem(&b);
+            if q < pow10(ctx.prec) {
+                return (
+                    Decimal::new(negative, q, 0),
+                    Decimal::new(self.negative, r, ideal_exp),
+                );
+            }
+        }
+        let nan = Self::invalid(status, DIVISION_IMPOSSIBLE);
+        (nan.clone(), nan)
+    }
+
+    pub fn divmod(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> (Decimal, Decimal) {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return (nan.clone(), nan);
+        }
+        let negative = self.negative != other.negative;
+        if self.is_infinite() {
+            if other.is_infinite() {
+                let nan = Self::invalid(status, INVALID_OPERATION);
+                return (nan.clone(), nan);
+            }
+            return (
+                Self::infinity(negative),
+                Self::invalid(status, INVALID_OPERATION),
+            );
+        }
+        if other.is_zero() {
+            if self.coef.is_zero() {
+                let nan = Self::invalid(status, DIVISION_UNDEFINED);
+                return (nan.clone(), nan);
+            }
+            *status |= DIVISION_BY_ZERO;
+            return (
+                Self::infinity(negative),
+                Self::invalid(status, INVALID_OPERATION),
+            );
+        }
+        let (quotient, remainder) = self.divide_parts(other, ctx, status);
+        (quotient, remainder.fix(ctx, status))
+    }
+
+    pub fn divide_int(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        let negative = self.negative != other.negative;
+        if self.is_infinite() {
+            if other.is_infinite() {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            return Self::infinity(negative);
+        }
+        if other.is_zero() {
+            if self.coef.is_zero() {
+                return Self::invalid(status, DIVISION_UNDEFINED);
+            }
+            *status |= DIVISION_BY_ZERO;
+            return Self::infinity(negative);
+        }
+        self.divide_parts(other, ctx, status).0
+    }
+
+    pub fn rem(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        if self.is_infinite() {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if other.is_zero() {
+            return if self.coef.is_zero() {
+                Self::invalid(status, DIVISION_UNDEFINED)
+            } else {
+                Self::invalid(status, INVALID_OPERATION)
+            };
+        }
+        self.divide_parts(other, ctx, status).1.fix(ctx, status)
+    }
+
+    pub fn rem_near(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        if self.is_infinite() {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if other.is_zero() {
+            return if self.coef.is_zero() {
+                Self::invalid(status, DIVISION_UNDEFINED)
+            } else {
+                Self::invalid(status, INVALID_OPERATION)
+            };
+        }
+        if other.is_infinite() {
+            return self.clone().fix(ctx, status);
+        }
+        let ideal_exp = self.exp.min(other.exp);
+        if self.coef.is_zero() {
+            return Decimal::new(self.negative, BigInt::zero(), ideal_exp).fix(ctx, status);
+        }
+        let expdiff = self.adjusted() - other.adjusted();
+        if expdiff > ctx.prec {
+            return Self::invalid(status, DIVISION_IMPOSSIBLE);
+        }
+        if expdiff <= -2 {
+            return self
+                .rescale_or_fail(ideal_exp, ctx.rounding, status)
+                .fix(ctx, status);
+        }
+        let a = &self.coef * pow10(self.exp - ideal_exp);
+        let b = &other.coef * pow10(other.exp - ideal_exp);
+        let (mut q, mut r) = a.div_rem(&b);
+        // pick the remainder closest to zero, ties going to an even quotient
+        if &r * 2u32 + if q.is_odd() { 1u32 } else { 0u32 } > b {
+            r -= &b;
+            q += 1u32;
+        }
+        if q >= pow10(ctx.prec) {
+            return Self::invalid(status, DIVISION_IMPOSSIBLE);
+        }
+        let negative = self.negative != r.is_negative();
+        Decimal::new(negative, r.abs(), ideal_exp).fix(ctx, status)
+    }
+
+    /// `self * other + third` with a single rounding.
+    pub fn fma(
+        &self,
+        other: &Decimal,
+        third: &Decimal,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Decimal {
+        let product = if self.is_special() || other.is_special() {
+            if let Some(snan) = [self, other].into_iter().find(|x| x.is_snan()) {
+                return snan.check_nans(None, ctx, status).unwrap();
+            }
+            if let Some(nan) = [self, other].into_iter().find(|x| x.is_qnan()) {
+                nan.clone()
+            } else if self.is_zero() || other.is_zero() {
+                return Self::invalid(status, INVALID_OPERATION);
+            } else {
+                Self::infinity(self.negative != other.negative)
+            }
+        } else {
+            Decimal::new(
+                self.negative != other.negative,
+                &self.coef * &other.coef,
+                self.exp + other.exp,
+            )
+        };
+        product.add(third, ctx, status)
+    }
+
+    pub fn plus(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        let ans = if self.is_zero() && ctx.rounding != Rounding::Floor {
+            self.abs()
+        } else {
+            self.clone()
+        };
+        ans.fix(ctx, status)
+    }
+
+    pub fn minus(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        let ans = if self.is_zero() && ctx.rounding != Rounding::Floor {
+            self.abs()
+        } else {
+            self.negated()
+        };
+        ans.fix(ctx, status)
+    }
+
+    pub fn abs_rounded(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if self.negative {
+            self.minus(ctx, status)
+        } else {
+            self.plus(ctx, status)
+        }
+    }
+
+    pub fn compare(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        Self::ordering_result(self.cmp_value(other))
+    }
+
+    pub fn compare_signal(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if self.is_nan() || other.is_nan() {
+            let nan = [self, other]
+                .into_iter()
+                .find(|x| x.is_snan())
+                .or_else(|| [self, other].into_iter().find(|x| x.is_qnan()))
+                .unwrap();
+            *status |= INVALID_OPERATION;
+            let quiet = Decimal {
+                kind: Kind::QNaN,
+                ..nan.clone()
+            };
+            return quiet.fix_nan(ctx);
+        }
+        self.compare(other, ctx, status)
+    }
+
+    /// The total ordering of the specification, which also orders NaNs and exponents.
+    pub fn cmp_total(&self, other: &Decimal) -> Ordering {
+        if self.negative != other.negative {
+            return if self.negative {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+        }
+        let flip = |ordering: Ordering| {
+            if self.negative {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        };
+        let nan_rank = |x: &Decimal| match x.kind {
+            Kind::QNaN => 2,
+            Kind::SNaN => 1,
+            _ => 0,
+        };
+        let (self_nan, other_nan) = (nan_rank(self), nan_rank(other));
+        if self_nan != 0 || other_nan != 0 {
+            let ordering = if self_nan == other_nan {
+                self.coef.cmp(&other.coef)
+            } else {
+                self_nan.cmp(&other_nan)
+            };
+            return flip(ordering);
+        }
+        match self.cmp_value(other) {
+            Ordering::Equal if self.is_finite() && other.is_finite() => {
+                flip(self.exp.cmp(&other.exp))
+            }
+            ordering => ordering,
+        }
+    }
+
+    pub fn compare_total(&self, other: &Decimal) -> Decimal {
+        Self::ordering_result(self.cmp_total(other))
+    }
+
+    pub fn compare_total_mag(&self, other: &Decimal) -> Decimal {
+        Self::ordering_result(self.abs().cmp_total(&other.abs()))
+    }
+
+    /// The shared part of max/min: a quiet NaN loses to a number.
+    fn minmax_nans(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Option<Decimal> {
+        if !self.is_nan() && !other.is_nan() {
+            return None;
+        }
+        if other.is_qnan() && !self.is_nan() {
+            return Some(self.clone().fix(ctx, status));
+        }
+        if self.is_qnan() && !other.is_nan() {
+            return Some(other.clone().fix(ctx, status));
+        }
+        self.check_nans(Some(other), ctx, status)
+    }
+
+    fn minmax(
+        &self,
+        other: &Decimal,
+        by_magnitude: bool,
+        want_max: bool,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Decimal {
+        if let Some(ans) = self.minmax_nans(other, ctx, status) {
+            return ans;
+        }
+        let mut ordering = if by_magnitude {
+            self.abs().cmp_value(&other.abs())
+        } else {
+            self.cmp_value(other)
+        };
+        if ordering == Ordering::Equal {
+            ordering = self.cmp_total(other);
+        }
+        let take_other = (ordering == Ordering::Less) == want_max;
+        let ans = if take_other { other } else { self };
+        ans.clone().fix(ctx, status)
+    }
+
+    pub fn max(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.minmax(other, false, true, ctx, status)
+    }
+
+    pub fn min(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.minmax(other, false, false, ctx, status)
+    }
+
+    pub fn max_mag(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.minmax(other, true, true, ctx, status)
+    }
+
+    pub fn min_mag(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.minmax(other, true, false, ctx, status)
+    }
+
+    pub fn quantize(
+        &self,
+        exp: &Decimal,
+        rounding: Rounding,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Decimal {
+        if self.is_special() || exp.is_special() {
+            if let Some(nan) = self.check_nans(Some(exp), ctx, status) {
+                return nan;
+            }
+            if exp.is_infinite() && self.is_infinite() {
+                return self.clone();
+            }
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if !(ctx.etiny() <= exp.exp && exp.exp <= ctx.emax) {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if self.coef.is_zero() {
+            return Decimal::new(self.negative, BigInt::zero(), exp.exp).fix(ctx, status);
+        }
+        let self_adjusted = self.adjusted();
+        if self_adjusted > ctx.emax || self_adjusted - exp.exp + 1 > ctx.prec {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        let ans = self.rescale_or_fail(exp.exp, rounding, status);
+        if ans.is_nan() {
+            return ans;
+        }
+        if ans.adjusted() > ctx.emax || ans.digits() > ctx.prec {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if !ans.coef.is_zero() && ans.adjusted() < ctx.emin {
+            *status |= SUBNORMAL;
+        }
+        if ans.exp > self.exp {
+            if ans.cmp_value(self) != Ordering::Equal {
+                *status |= INEXACT;
+            }
+            *status |= ROUNDED;
+        }
+        ans.fix(ctx, status)
+    }
+
+    pub fn same_quantum(&self, other: &Decimal) -> bool {
+        if self.is_special() || other.is_special() {
+            return (self.is_nan() && other.is_nan())
+                || (self.is_infinite() && other.is_infinite());
+        }
+        self.exp == other.exp
+    }
+
+    /// Round to an integer; `exact` signals Inexact and Rounded the way to_integral_exact does.
+    pub fn to_integral(
+        &self,
+        rounding: Rounding,
+        exact: bool,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Decimal {
+        if self.is_special() {
+            if let Some(nan) = self.check_nans(None, ctx, status) {
+                return nan;
+            }
+            return self.clone();
+        }
+        if self.exp >= 0 {
+            return self.clone();
+        }
+        if self.coef.is_zero() {
+            return Decimal::new(self.negative, BigInt::zero(), 0);
+        }
+        let ans = self.rescale_or_fail(0, rounding, status);
+        if exact {
+            if ans.cmp_value(self) != Ordering::Equal {
+                *status |= INEXACT;
+            }
+            *status |= ROUNDED;
+        }
+        ans
+    }
+
+    pub fn normalize(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        let dup = self.clone().fix(ctx, status);
+        if dup.is_infinite() {
+            return dup;
+        }
+        if dup.coef.is_zero() {
+            return Decimal::new(dup.negative, BigInt::zero(), 0);
+        }
+        let exp_max = if ctx.clamp { ctx.etop() } else { ctx.emax };
+        let mut coef = dup.coef;
+        let mut exp = dup.exp;
+        while exp < exp_max && (&coef % 10u32).is_zero() {
+            coef /= 10u32;
+            exp += 1;
+        }
+        Decimal::new(dup.negative, coef, exp)
+    }
+
+    pub fn sqrt(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if self.is_special() {
+            if let Some(nan) = self.check_nans(None, ctx, status) {
+                return nan;
+            }
+            if self.is_infinite() && !self.negative {
+                return self.clone();
+            }
+        }
+        if self.is_zero() {
+            return Decimal::new(self.negative, BigInt::zero(), self.exp.div_euclid(2))
+                .fix(ctx, status);
+        }
+        if self.negative {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+
+        let prec = ctx.prec + 1;
+        let mut e = self.exp.div_euclid(2);
+        let (c, l) = if self.exp.rem_euclid(2) == 1 {
+            (&self.coef * 10u32, (self.digits() >> 1) + 1)
+        } else {
+            (self.coef.clone(), (self.digits() + 1) >> 1)
+        };
+        let shift = prec - l;
+        let (c, mut exact) = if shift >= 0 {
+            (c * pow10(2 * shift), true)
+        } else {
+            let (q, r) = c.div_rem(&pow10(-2 * shift));
+            (q, r.is_zero())
+        };
+        e -= shift;
+        let mut n = pow10(prec);
+        loop {
+            let q = &c / &n;
+            if n <= q {
+                break;
+            }
+            n = (n + q) >> 1usize;
+        }
+        exact = exact && &n * &n == c;
+        if exact {
+            if shift >= 0 {
+                n /= pow10(shift);
+            } else {
+                n *= pow10(-shift);
+            }
+            e += shift;
+        } else if (&n % 5u32).is_zero() {
+            n += 1u32;
+        }
+        let ctx = ctx.with_rounding(Rounding::HalfEven);
+        Decimal::new(false, n, e).fix(&ctx, status)
+    }
+
+    pub fn exp(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        if self.is_infinite() {
+            return if self.negative {
+                Self::zero()
+            } else {
+                self.clone()
+            };
+        }
+        if self.coef.is_zero() {
+            return Self::from_i64(1);
+        }
+        let p = ctx.prec;
+        let adj = self.adjusted();
+        let digits_of = |n: i64| num_digits(&BigInt::from(n.unsigned_abs()));
+        let ans = if !self.negative && adj > digits_of((ctx.emax + 1) * 3) {
+            // certain overflow
+            Decimal::new(false, BigInt::one(), ctx.emax + 1)
+        } else if self.negative && adj > digits_of((-ctx.etiny() + 1) * 3) {
+            // certain underflow
+            Decimal::new(false, BigInt::one(), ctx.etiny() - 1)
+        } else if !self.negative && adj < -p {
+            // 1 < exp(x) < 1 + 10**-p
+            Decimal::new(false, pow10(p) + 1u32, -p)
+        } else if self.negative && adj < -p - 1 {
+            // 1 - 10**-p < exp(x) < 1
+            Decimal::new(false, pow10(p + 1) - 1u32, -p - 1)
+        } else {
+            let c = if self.negative {
+                -self.coef.clone()
+            } else {
+                self.coef.clone()
+            };
+            let mut extra = 3;
+            let (coeff, exp) = loop {
+                let (coeff, exp) = dexp(&c, self.exp, p + extra);
+                if ziv_done(&coeff, p) {
+                    break (coeff, exp);
+                }
+                extra += 3;
+            };
+            Decimal::new(false, coeff, exp)
+        };
+        let ctx = ctx.with_rounding(Rounding::HalfEven);
+        ans.fix(&ctx, status)
+    }
+
+    /// An estimate of the number of digits before the point in `ln(self)`, never too big.
+    fn ln_exp_bound(&self) -> i64 {
+        let adj = self.adjusted();
+        let digits_of = |n: i64| num_digits(&BigInt::from(n));
+        if adj >= 1 {
+            return digits_of(adj * 23 / 10) - 1;
+        }
+        if adj <= -2 {
+            return digits_of((-1 - adj) * 23 / 10) - 1;
+        }
+        let (c, e) = (&self.coef, self.exp);
+        if adj == 0 {
+            // 1 <= self < 10
+            let num = c - pow10(-e);
+            return num_digits(&num.abs()) + i64::from(num.is_negative())
+                - num_digits(c)
+                - i64::from(str_lt(&num, c));
+        }
+        // 0.1 <= self < 1
+        e + num_digits(&(pow10(-e) - c)) - 1
+    }
+
+    pub fn ln(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        if self.is_zero() {
+            return Self::infinity(true);
+        }
+        if self.is_infinite() && !self.negative {
+            return self.clone();
+        }
+        if self.negative {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if self.cmp_value(&Self::from_i64(1)) == Ordering::Equal {
+            return Self::zero();
+        }
+        let p = ctx.prec;
+        let mut places = p - self.ln_exp_bound() + 2;
+        let coeff = loop {
+            let coeff = dlog(&self.coef, self.exp, places);
+            if ziv_done(&coeff, p) {
+                break coeff;
+            }
+            places += 3;
+        };
+        let ctx = ctx.with_rounding(Rounding::HalfEven);
+        Decimal::new(coeff.is_negative(), coeff.abs(), -places).fix(&ctx, status)
+    }
+
+    /// An estimate of the number of digits before the point in `log10(self)`, never too big.
+    fn log10_exp_bound(&self) -> i64 {
+        let adj = self.adjusted();
+        let digits_of = |n: i64| num_digits(&BigInt::from(n));
+        if adj >= 1 {
+            return digits_of(adj) - 1;
+        }
+        if adj <= -2 {
+            return digits_of(-1 - adj) - 1;
+        }
+        let (c, e) = (&self.coef, self.exp);
+        if adj == 0 {
+            let num = c - pow10(-e);
+            let den = c * 231u32;
+            return num_digits(&num.abs()) + i64::from(num.is_negative())
+                - num_digits(&den)
+                - i64::from(str_lt(&num, &den))
+                + 2;
+        }
+        let num = pow10(-e) - c;
+        num_digits(&num) + e - i64::from(num.to_string().as_str() < "231") - 1
+    }
+
+    pub fn log10(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        if self.is_zero() {
+            return Self::infinity(true);
+        }
+        if self.is_infinite() && !self.negative {
+            return self.clone();
+        }
+        if self.negative {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        let ans = if self.coef == pow10(self.digits() - 1) {
+            // an exact power of ten
+            Self::from_i64(self.adjusted())
+        } else {
+            let p = ctx.prec;
+            let mut places = p - self.log10_exp_bound() + 2;
+            let coeff = loop {
+                let coeff = dlog10(&self.coef, self.exp, places);
+                if ziv_done(&coeff, p) {
+                    break coeff;
+                }
+                places += 3;
+            };
+            Decimal::new(coeff.is_negative(), coeff.abs(), -places)
+        };
+        let ctx = ctx.with_rounding(Rounding::HalfEven);
+        ans.fix(&ctx, status)
+    }
+
+    pub fn logb(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        if self.is_infinite() {
+            return Self::infinity(false);
+        }
+        if self.coef.is_zero() {
+            *status |= DIVISION_BY_ZERO;
+            return Self::infinity(true);
+        }
+        Self::from_i64(self.adjusted()).fix(ctx, status)
+    }
+
+    pub fn scaleb(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        if !other.is_finite() || other.exp != 0 {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        let limit = 2 * (ctx.emax + ctx.prec);
+        let Some(scale) = other.to_bigint().and_then(|n| n.to_i64()) else {
+            return Self::invalid(status, INVALID_OPERATION);
+        };
+        if !(-limit..=limit).contains(&scale) {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        if self.is_infinite() {
+            return self.clone();
+        }
+        Decimal::new(self.negative, self.coef.clone(), self.exp + scale).fix(ctx, status)
+    }
+
+    pub fn next_plus(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        self.next(true, ctx, status)
+    }
+
+    pub fn next_minus(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        self.next(false, ctx, status)
+    }
+
+    fn next(&self, up: bool, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(None, ctx, status) {
+            return nan;
+        }
+        if self.is_infinite() {
+            return if self.negative != up {
+                self.clone()
+            } else {
+                Decimal::new(self.negative, pow10(ctx.prec) - 1u32, ctx.etop())
+            };
+        }
+        let rounding = if up {
+            Rounding::Ceiling
+        } else {
+            Rounding::Floor
+        };
+        let quiet_ctx = ctx.with_rounding(rounding);
+        let mut ignored = 0;
+        let rounded = self.clone().fix(&quiet_ctx, &mut ignored);
+        if rounded.cmp_value(self) != Ordering::Equal {
+            return rounded;
+        }
+        let tiny = Decimal::new(!up, BigInt::one(), ctx.etiny() - 1);
+        self.add_numbers(&tiny, &quiet_ctx, &mut ignored)
+    }
+
+    pub fn next_toward(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        let ans = match self.cmp_value(other) {
+            Ordering::Equal => return self.with_sign_of(other),
+            Ordering::Less => self.next_plus(ctx, status),
+            Ordering::Greater => self.next_minus(ctx, status),
+        };
+        if ans.is_infinite() {
+            *status |= OVERFLOW | INEXACT | ROUNDED;
+        } else if ans.adjusted() < ctx.emin {
+            *status |= UNDERFLOW | SUBNORMAL | INEXACT | ROUNDED;
+            if ans.coef.is_zero() {
+                *status |= CLAMPED;
+            }
+        }
+        ans
+    }
+
+    pub fn number_class(&self, ctx: &Context) -> &'static str {
+        match self.kind {
+            Kind::SNaN => return "sNaN",
+            Kind::QNaN => return "NaN",
+            Kind::Infinite if self.negative => return "-Infinity",
+            Kind::Infinite => return "+Infinity",
+            Kind::Finite => {}
+        }
+        match (self.coef.is_zero(), self.is_subnormal(ctx), self.negative) {
+            (true, _, true) => "-Zero",
+            (true, _, false) => "+Zero",
+            (false, true, true) => "-Subnormal",
+            (false, true, false) => "+Subnormal",
+            (false, false, true) => "-Normal",
+            (false, false, false) => "+Normal",
+        }
+    }
+
+    /// The coefficient digits of a logical operand, padded or cut to the precision.
+    fn logical_digits(&self, ctx: &Context) -> Option<Vec<u8>> {
+        if !self.is_finite() || self.negative || self.exp != 0 {
+            return None;
+        }
+        let digits = self.coef.to_string().into_bytes();
+        if digits.iter().any(|&d| d != b'0' && d != b'1') {
+            return None;
+        }
+        Some(Self::fit_digits(digits, ctx.prec))
+    }
+
+    fn fit_digits(mut digits: Vec<u8>, prec: i64) -> Vec<u8> {
+        let prec = prec as usize;
+        if digits.len() < prec {
+            let mut padded = vec![b'0'; prec - digits.len()];
+            padded.append(&mut digits);
+            padded
+        } else {
+            digits.split_off(digits.len() - prec)
+        }
+    }
+
+    fn from_digits(negative: bool, digits: &[u8], exp: i64) -> Decimal {
+        let digits = std::str::from_utf8(digits).unwrap().trim_start_matches('0');
+        let coef = if digits.is_empty() {
+            BigInt::zero()
+        } else {
+            digits.parse().unwrap()
+        };
+        Decimal::new(negative, coef, exp)
+    }
+
+    fn logical(
+        &self,
+        other: &Decimal,
+        ctx: &Context,
+        status: &mut u32,
+        op: fn(bool, bool) -> bool,
+    ) -> Decimal {
+        let (Some(a), Some(b)) = (self.logical_digits(ctx), other.logical_digits(ctx)) else {
+            return Self::invalid(status, INVALID_OPERATION);
+        };
+        let result: Vec<u8> = a
+            .iter()
+            .zip(&b)
+            .map(|(&x, &y)| if op(x == b'1', y == b'1') { b'1' } else { b'0' })
+            .collect();
+        Self::from_digits(false, &result, 0)
+    }
+
+    pub fn logical_and(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.logical(other, ctx, status, |a, b| a && b)
+    }
+
+    pub fn logical_or(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.logical(other, ctx, status, |a, b| a || b)
+    }
+
+    pub fn logical_xor(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        self.logical(other, ctx, status, |a, b| a != b)
+    }
+
+    pub fn logical_invert(&self, ctx: &Context, status: &mut u32) -> Decimal {
+        let ones: BigInt = "1".repeat(ctx.prec as usize).parse().unwrap();
+        self.logical_xor(&Decimal::new(false, ones, 0), ctx, status)
+    }
+
+    /// The checks shared by rotate and shift; yields the digit count to move by.
+    fn shift_amount(
+        &self,
+        other: &Decimal,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Result<i64, Decimal> {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return Err(nan);
+        }
+        let amount = (other.is_finite() && other.exp == 0)
+            .then(|| other.to_bigint())
+            .flatten()
+            .and_then(|n| n.to_i64())
+            .filter(|n| (-ctx.prec..=ctx.prec).contains(n));
+        match amount {
+            Some(amount) => Ok(amount),
+            None => Err(Self::invalid(status, INVALID_OPERATION)),
+        }
+    }
+
+    pub fn rotate(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        let amount = match self.shift_amount(other, ctx, status) {
+            Ok(amount) => amount,
+            Err(ans) => return ans,
+        };
+        if self.is_infinite() {
+            return self.clone();
+        }
+        let mut digits = Self::fit_digits(self.coef.to_string().into_bytes(), ctx.prec);
+        if amount >= 0 {
+            digits.rotate_left(amount as usize);
+        } else {
+            digits.rotate_right(-amount as usize);
+        }
+        Self::from_digits(self.negative, &digits, self.exp)
+    }
+
+    pub fn shift(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        let amount = match self.shift_amount(other, ctx, status) {
+            Ok(amount) => amount,
+            Err(ans) => return ans,
+        };
+        if self.is_infinite() {
+            return self.clone();
+        }
+        let mut digits = Self::fit_digits(self.coef.to_string().into_bytes(), ctx.prec);
+        if amount < 0 {
+            digits.truncate(digits.len() - (-amount) as usize);
+        } else {
+            digits.extend(std::iter::repeat(b'0').take(amount as usize));
+            digits = Self::fit_digits(digits, ctx.prec);
+        }
+        Self::from_digits(self.negative, &digits, self.exp)
+    }
+
+    pub fn pow(&self, other: &Decimal, ctx: &Context, status: &mut u32) -> Decimal {
+        if let Some(nan) = self.check_nans(Some(other), ctx, status) {
+            return nan;
+        }
+        if other.is_zero() {
+            if self.is_zero() {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            return Self::from_i64(1);
+        }
+
+        // the result is negative iff self is negative and other is an odd integer
+        let mut result_negative = false;
+        let base = if self.negative {
+            if other.is_integer() {
+                result_negative = !other.is_even();
+            } else if !self.is_zero() {
+                return Self::invalid(status, INVALID_OPERATION);
+            }
+            self.negated()
+        } else {
+            self.clone()
+        };
+
+        if base.is_zero() {
+            return if other.negative {
+                Self::infinity(result_negative)
+            } else {
+                Decimal::new(result_negative, BigInt::zero(), 0)
+            };
+        }
+        if base.is_infinite() {
+            return if other.negative {
+                Decimal::new(result_negative, BigInt::zero(), 0)
+            } else {
+                Self::infinity(result_negative)
+            };
+        }
+
+        // 1**other is 1, with an exponent depending on whether other is an integer
+        if base.cmp_value(&Self::from_i64(1)) == Ordering::Equal {
+            let mut exp;
+            if other.is_integer() {
+                let multiplier = if other.negative {
+                    0
+                } else if other.cmp_value(&Self::from_i64(ctx.prec)) == Ordering::Greater {
+                    ctx.prec
+                } else {
+                    other.to_bigint().and_then(|n| n.to_i64()).unwrap()
+                };
+                exp = base.exp.saturating_mul(multiplier);
+                if exp < 1 - ctx.prec {
+                    exp = 1 - ctx.prec;
+                    *status |= ROUNDED;
+                }
+            } else {
+                *status |= INEXACT | ROUNDED;
+                exp = 1 - ctx.prec;
+            }
+            return Decimal::new(result_negative, pow10(-exp), exp);
+        }
+
+        let self_adj = base.adjusted();
+        if other.is_infinite() {
+            return if other.negative != (self_adj < 0) {
+                Decimal::new(result_negative, BigInt::zero(), 0)
+            } else {
+                Self::infinity(result_negative)
+            };
+        }
+
+        // catch certain overflow and underflow before doing any real work
+        let mut ans = None;
+        let digits_of = |n: i64| num_digits(&BigInt::from(n.unsigned_abs()));
+        let bound = base.log10_exp_bound() + other.adjusted();
+        if (self_adj >= 0) != other.negative {
+            if bound >= digits_of(ctx.emax) {
+                ans = Some(Decimal::new(result_negative, BigInt::one(), ctx.emax + 1));
+            }
+        } else if bound >= digits_of(-ctx.etiny()) {
+            ans = Some(Decimal::new(
+                result_negative,
+                BigInt::one(),
+                ctx.etiny() - 1,
+            ));
+        }
+
+        let mut exact = false;
+        if ans.is_none() {
+            ans = base.pow_exact(other, ctx.prec + 1).map(|exact_ans| {
+                exact = true;
+                Decimal {
+                    negative: result_negative,
+                    ..exact_ans
+                }
+            });
+        }
+
+        let ans = ans.unwrap_or_else(|| {
+            let p = ctx.prec;
+            let yc = if other.negative {
+                -other.coef.clone()
+            } else {
+                other.coef.clone()
+            };
+            let mut extra = 3;
+            let (coeff, exp) = loop {
+                let (coeff, exp) = dpower(&base.coef, base.exp, &yc, other.exp, p + extra);
+                if ziv_done(&coeff, p) {
+                    break (coeff, exp);
+                }
+                extra += 3;
+            };
+            Decimal::new(result_negative, coeff, exp)
+        });
+
+        if exact && !other.is_integer() {
+            // An exact result for a non-integral power still counts as inexact; pad so that
+            // Rounded is raised too.
+            let mut ans = ans;
+            if ans.digits() <= ctx.prec {
+                let expdiff = ctx.prec + 1 - ans.digits();
+                ans = Decimal::new(ans.negative, ans.coef * pow10(expdiff), ans.exp - expdiff);
+            }
+            let mut fix_status = 0;
+            let ans = ans.fix(ctx, &mut fix_status);
+            fix_status |= INEXACT;
+            if fix_status & SUBNORMAL != 0 {
+                fix_status |= UNDERFLOW;
+            }
+            *status |= fix_status;
+            ans
+        } else {
+            ans.fix(ctx, status)
+        }
+    }
+
+    /// `self**other` if it is exactly representable with `p` digits; self is positive and
+    /// finite, other finite and nonzero.
+    fn pow_exact(&self, other: &Decimal, p: i64) -> Option<Decimal> {
+        let strip = |coef: &BigInt, exp: i64| {
+            let (mut c, mut e) = (coef.clone(), BigInt::from(exp));
+            while (&c % 10u32).is_zero() {
+                c /= 10u32;
+                e += 1u32;
+            }
+            (c, e)
+        };
+        let (mut xc, mut xe) = strip(&self.coef, self.exp);
+        let (yc, mut ye) = strip(&other.coef, other.exp);
+        let ye_small = ye.to_i64()?;
+
+        // x is a power of ten, so the result is 10**(xe*y), with xe*y required to be an integer
+        if xc.is_one() {
+            xe *= &yc;
+            while !xe.is_zero() && (&xe % 10u32).is_zero() {
+                xe /= 10u32;
+                ye += 1u32;
+            }
+            if ye.is_negative() {
+                return None;
+            }
+            let mut exponent = xe * pow10(ye.to_i64()?);
+            if other.negative {
+                exponent = -exponent;
+            }
+            let exponent = exponent.to_i64()?;
+            let zeros = if other.is_integer() && !other.negative {
+                let ideal = self.exp.checked_mul(other.to_bigint()?.to_i64()?)?;
+                (exponent - ideal).min(p - 1)
+            } else {
+                0
+            };
+            return Some(Decimal::new(false, pow10(zeros), exponent - zeros));
+        }
+
+        // for negative y, x has to be a power of 2 or of 5 (times a power of ten)
+        if other.negative {
+            let last_digit = (&xc % 10u32).to_u32().unwrap();
+            let e;
+            if last_digit % 2 == 0 {
+                let bits = xc.bits();
+                if xc != BigInt::one() << (bits - 1) as usize {
+                    return None;
+                }
+                // x = 2**e * 10**xe; x**y = 5**(-e*y) * 10**(e*y + xe*y)
+                let emax = p * 93 / 65;
+                if ye_small >= num_digits(&BigInt::from(emax)) {
+                    return None;
+                }
+                let scaled = decimal_lshift_exact(&(BigInt::from(bits - 1) * &yc), &ye)?;
+                xe = decimal_lshift_exact(&(&xe * &yc), &ye)?;
+                e = scaled.to_i64()?;
+                if e > emax {
+                    return None;
+                }
+                xc = BigInt::from(5u32).pow(e as u32);
+            } else if last_digit == 5 {
+                // e >= log_5(xc) if xc is a power of 5
+                let mut guess = (xc.bits() * 28 / 65) as i64;
+                let (q, r) = BigInt::from(5u32).pow(guess as u32).div_rem(&xc);
+                if !r.is_zero() {
+                    return None;
+                }
+                let mut q = q;
+                while (&q % 5u32).is_zero() {
+                    q /= 5u32;
+                    guess -= 1;
+                }
+                let emax = p * 10 / 3;
+                if ye_small >= num_digits(&BigInt::from(emax)) {
+                    return None;
+                }
+                let scaled = decimal_lshift_exact(&(BigInt::from(guess) * &yc), &ye)?;
+                xe = decimal_lshift_exact(&(&xe * &yc), &ye)?;
+                e = scaled.to_i64()?;
+                if e > emax {
+                    return None;
+                }
+                xc = BigInt::from(2u32).pow(e as u32);
+            } else {
+                return None;
+            }
+            if xc >= pow10(p) {
+                return None;
+            }
+            let exp = (-e - xe).to_i64()?;
+            return Some(Decimal::new(false, xc, exp));
+        }
+
+        // y is positive; write it as m/n
+        let (m, n) = if ye_small >= 0 {
+            (&yc * pow10(ye_small), BigInt::one())
+        } else {
+            if !xe.is_zero() && num_digits(&(&yc * &xe).abs()) <= -ye_small {
+                return None;
+            }
+            let xc_bits = xc.bits();
+            if num_digits(&(&yc * xc_bits)) <= -ye_small {
+                return None;
+            }
+            let (mut m, mut n) = (yc.clone(), pow10(-ye_small));
+            while m.is_even() && n.is_even() {
+                m /= 2u32;
+                n /= 2u32;
+            }
+            while (&m % 5u32).is_zero() && (&n % 5u32).is_zero() {
+                m /= 5u32;
+                n /= 5u32;
+            }
+            (m, n)
+        };
+
+        // the nth root of xc * 10**xe
+        if n > BigInt::one() {
+            let n = n.to_u64()?;
+            if !xc.is_one() && xc.bits() <= n {
+                return None;
+            }
+            let (q, r) = xe.div_mod_floor(&BigInt::from(n));
+            if !r.is_zero() {
+                return None;
+            }
+            xe = q;
+            // Newton's method from above
+            let mut a = BigInt::one() << xc.bits().div_ceil(n) as usize;
+            let (q, r) = loop {
+                let (q, r) = xc.div_rem(&Pow::pow(&a, (n - 1) as u32));
+                if a <= q {
+                    break (q, r);
+                }
+                a = (&a * (n - 1) + q) / n;
+            };
+            if !(a == q && r.is_zero()) {
+                return None;
+            }
+            xc = a;
+        }
+
+        // and then its mth power
+        if !xc.is_one() && m > BigInt::from(p * 100 / log10_lb(&xc)) {
+            return None;
+        }
+        let m_small = m.to_u32()?;
+        xc = xc.pow(m_small);
+        xe *= &m;
+        if xc > pow10(p) {
+            return None;
+        }
+        let xe = xe.to_i64()?;
+        // pad towards the ideal exponent for integer powers
+        let zeros = if other.is_integer() && !other.negative {
+            let ideal = self.exp.checked_mul(other.to_bigint()?.to_i64()?)?;
+            (xe - ideal).min(p - num_digits(&xc))
+        } else {
+            0
+        };
+        Some(Decimal::new(false, xc * pow10(zeros), xe - zeros))
+    }
+
+    /// Three-argument pow; every operand must be an integer.
+    pub fn pow_mod(
+        &self,
+        other: &Decimal,
+        modulo: &Decimal,
+        ctx: &Context,
+        status: &mut u32,
+    ) -> Decimal {
+        let operands = [self, other, modulo];
+        if let Some(snan) = operands.into_iter().find(|x| x.is_snan()) {
+            return snan.check_nans(None, ctx, status).unwrap();
+        }
+        if let Some(nan) = operands.into_iter().find(|x| x.is_qnan()) {
+            return nan.clone().fix_nan(ctx);
+        }
+        if !operands.into_iter().all(|x| x.is_integer())
+            || other.negative
+            || modulo.is_zero()
+            || modulo.adjusted() >= ctx.prec
+            || (other.is_zero() && self.is_zero())
+        {
+            return Self::invalid(status, INVALID_OPERATION);
+        }
+        let negative = if other.is_even() {
+            false
+        } else {
+            self.negative
+        };
+        let (Some(modulus), Some(exponent)) = (modulo.to_bigint(), other.to_bigint()) else {
+            return Self::out_of_memory(status);
+        };
+        let modulus = modulus.abs();
+        // the base is reduced before it is written out, its exponent may be huge
+        let base = if self.exp >= 0 {
+            let scale = BigInt::from(10u32).modpow(&BigInt::from(self.exp), &modulus);
+            &self.coef % &modulus * scale % &modulus
+        } else {
+            self.to_bigint().unwrap().abs()
+        };
+        let result = base.modpow(&exponent, &modulus);
+        Decimal::new(negative, result, 0)
+    }
+}
@@ -2,62 +2,633 @@ pub(crate) use decl::make_module;
 
 #[pymodule(name = "faulthandler")]
 mod decl {
-    use crate::vm::{frame::Frame, function::OptionalArg, stdlib::sys::PyStderr, VirtualMachine};
+    use crate::common::{lock::PyMutex, static_cell};
+    use crate::vm::{
+        convert::ToPyException, frame::Frame, function::ArgIntoFloat, stdlib::sys, vm::thread,
+        AsObject, PyObjectRef, PyResult, VirtualMachine,
+    };
+    use parking_lot::{Condvar, Mutex};
+    use std::{
+        cell::UnsafeCell,
+        fmt::{self, Write},
+        io,
+        sync::{
+            atomic::{AtomicBool, AtomicI32, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
 
-    fn dump_frame(frame: &Frame, vm: &VirtualMachine) {
-        let stderr = PyStderr(vm);
+    const MAX_FRAME_DEPTH: usize = 100;
+    const MAX_NTHREADS: usize = 100;
+
+    /// Writes straight to a file descriptor, without allocating or taking any lock, so that it
+    /// can be used from a signal handler.
+    struct FdWriter(i32);
+
+    impl fmt::Write for FdWriter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let mut buf = s.as_bytes();
+            while !buf.is_empty() {
+                let n = unsafe { libc::write(self.0, buf.as_ptr() as *const _, buf.len() as _) };
+                if n < 0 {
+                    if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(fmt::Error);
+                }
+                buf = &buf[n as usize..];
+            }
+            Ok(())
+        }
+    }
+
+    fn dump_frame(out: &mut FdWriter, frame: &Frame) -> fmt::Result {
         writeln!(
-            stderr,
+            out,
             "  File \"{}\", line {} in {}",
             frame.code.source_path,
-            frame.current_location().row.to_usize(),
+            frame.f_lineno(),
             frame.code.obj_name
         )
     }
 
-    #[pyfunction]
-    fn dump_traceback(
-        _file: OptionalArg<i64>,
-        _all_threads: OptionalArg<bool>,
+    /// Dump the stack of `vm`, most recent call first.
+    ///
+    /// # Safety
+    ///
+    /// `vm` may belong to another thread, see [`thread::for_each_vm`].
+    unsafe fn dump_stack(out: &mut FdWriter, vm: &VirtualMachine) -> fmt::Result {
+        // the stack can't be read while its thread is pushing or popping a frame
+        let frames = match vm.frames.try_borrow_unguarded() {
+            Ok(frames) => frames,
+            Err(_) => return writeln!(out, "  <frame stack is being modified>"),
+        };
+        if frames.is_empty() {
+            return writeln!(out, "  <no Python frame>");
+        }
+        for (depth, frame) in frames.iter().rev().enumerate() {
+            if depth >= MAX_FRAME_DEPTH {
+                return writeln!(out, "  ...");
+            }
+            dump_frame(out, frame)?;
+        }
+        Ok(())
+    }
+
+    /// Dump the stack of the `index`th thread of [`write_traceback`].
+    ///
+    /// # Safety
+    ///
+    /// See [`dump_stack`].
+    unsafe fn dump_thread(
+        out: &mut FdWriter,
+        index: usize,
+        ident: u64,
         vm: &VirtualMachine,
-    ) {
-        let stderr = PyStderr(vm);
-        writeln!(stderr, "Stack (most recent call first):");
+        is_current: bool,
+    ) -> fmt::Result {
+        if index > MAX_NTHREADS {
+            return Ok(());
+        }
+        if index == MAX_NTHREADS {
+            return writeln!(out, "...");
+        }
+        if index > 0 {
+            writeln!(out)?;
+        }
+        let thread = if is_current {
+            "Current thread"
+        } else {
+            "Thread"
+        };
+        writeln!(out, "{thread} {ident:#018x} (most recent call first):")?;
+        dump_stack(out, vm)
+    }
 
-        for frame in vm.frames.borrow().iter() {
-            dump_frame(frame, vm);
+    /// Dump the stack of every thread running Python code, or only that of `current` when
+    /// `all_threads` is false. `current` is the vm of the calling thread, if it has one.
+    ///
+    /// The other threads keep running while their stacks are read, so this is only for when the
+    /// process crashed or hung, from a signal handler or the watchdog. When `blocking` is false,
+    /// giving up on the other threads rather than waiting for a thread that is starting or
+    /// exiting makes this safe to call from a signal handler.
+    fn write_traceback(
+        out: &mut FdWriter,
+        current: Option<&VirtualMachine>,
+        all_threads: bool,
+        blocking: bool,
+    ) -> fmt::Result {
+        if all_threads {
+            let mut nthreads = 0;
+            let mut result = Ok(());
+            let dumped = unsafe {
+                thread::for_each_vm(blocking, |ident, vm| {
+                    if result.is_ok() {
+                        let is_current = current.map_or(false, |current| std::ptr::eq(current, vm));
+                        result = dump_thread(out, nthreads, ident, vm, is_current);
+                    }
+                    nthreads += 1;
+                })
+            };
+            if dumped {
+                return result;
+            }
+            writeln!(out, "<Cannot get the list of threads>")?;
+        }
+        match current {
+            Some(vm) => {
+                writeln!(out, "Stack (most recent call first):")?;
+                unsafe { dump_stack(out, vm) }
+            }
+            None => writeln!(out, "<Cannot get the current thread>"),
         }
     }
 
+    /// Dump tracebacks from a signal handler.
+    fn dump_traceback_from_handler(out: &mut FdWriter, all_threads: bool) {
+        let dumped =
+            thread::try_with_current_vm(|vm| write_traceback(out, Some(vm), all_threads, false));
+        if dumped.is_none() {
+            let _ = write_traceback(out, None, all_threads, false);
+        }
+    }
+
+    /// Get the file descriptor to write to from a `file` argument, which is either a file
+    /// descriptor or an object with a `fileno()` method, `sys.stderr` by default. The file
+    /// object, if any, is returned as well so that it can be kept alive.
+    fn get_fileno(
+        file: Option<PyObjectRef>,
+        vm: &VirtualMachine,
+    ) -> PyResult<(i32, Option<PyObjectRef>)> {
+        let file = match file {
+            Some(file) if !vm.is_none(&file) => file,
+            _ => {
+                let stderr = sys::get_stderr(vm)?;
+                if vm.is_none(&stderr) {
+                    return Err(vm.new_runtime_error("sys.stderr is None".to_owned()));
+                }
+                stderr
+            }
+        };
+        let invalid_fd = || vm.new_value_error("file is not a valid file descriptor".to_owned());
+        if file.fast_isinstance(vm.ctx.types.int_type) {
+            let fd: i32 = file.try_into_value(vm)?;
+            return if fd < 0 {
+                Err(invalid_fd())
+            } else {
+                Ok((fd, None))
+            };
+        }
+        let fd: i32 = vm.call_method(&file, "fileno", ())?.try_into_value(vm)?;
+        if fd < 0 {
+            return Err(invalid_fd());
+        }
+        // anything buffered by Python would come out after the traceback otherwise
+        let _ = vm.call_method(&file, "flush", ());
+        Ok((fd, Some(file)))
+    }
+
+    /// The file objects the handlers write to, kept alive so that their fds stay open.
+    #[derive(Default)]
+    struct Files {
+        fatal_error: Option<PyObjectRef>,
+        watchdog: Option<PyObjectRef>,
+        #[cfg(unix)]
+        user_signals: std::collections::HashMap<i32, PyObjectRef>,
+    }
+
+    static_cell! {
+        static FILES: PyMutex<Files>;
+    }
+
+    fn files() -> &'static PyMutex<Files> {
+        FILES.get_or_init(Default::default)
+    }
+
+    /// State shared with the signal handlers, which can't take locks.
+    struct HandlerCell<T>(UnsafeCell<T>);
+
+    unsafe impl<T> Sync for HandlerCell<T> {}
+
+    #[cfg(unix)]
+    type PreviousHandler = libc::sigaction;
+    #[cfg(windows)]
+    type PreviousHandler = libc::sighandler_t;
+
+    /// Install `handler` for `signum`, returning the handler that was there before.
+    #[cfg(unix)]
+    unsafe fn install_handler(
+        signum: i32,
+        handler: extern "C" fn(i32),
+        flags: i32,
+    ) -> io::Result<PreviousHandler> {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        action.sa_flags = flags;
+        let mut previous = std::mem::zeroed();
+        if libc::sigaction(signum, &action, &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+
+    #[cfg(windows)]
+    unsafe fn install_handler(
+        signum: i32,
+        handler: extern "C" fn(i32),
+        _flags: i32,
+    ) -> io::Result<PreviousHandler> {
+        let previous = libc::signal(signum, handler as libc::sighandler_t);
+        if previous == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+
+    unsafe fn restore_handler(signum: i32, previous: &PreviousHandler) {
+        #[cfg(unix)]
+        libc::sigaction(signum, previous, std::ptr::null_mut());
+        #[cfg(windows)]
+        libc::signal(signum, *previous);
+    }
+
+    #[cfg(unix)]
+    const FATAL_SIGNALS: [(i32, &str); 5] = [
+        (libc::SIGBUS, "Bus error"),
+        (libc::SIGILL, "Illegal instruction"),
+        (libc::SIGFPE, "Floating point exception"),
+        (libc::SIGABRT, "Aborted"),
+        (libc::SIGSEGV, "Segmentation fault"),
+    ];
+    #[cfg(windows)]
+    const FATAL_SIGNALS: [(i32, &str); 4] = [
+        (libc::SIGILL, "Illegal instruction"),
+        (libc::SIGFPE, "Floating point exception"),
+        (libc::SIGABRT, "Aborted"),
+        (libc::SIGSEGV, "Segmentation fault"),
+    ];
+
+    // the flags of the fatal error handlers: SA_NODEFER lets the handler raise the signal
+    // again once the previous handler is back, and SA_ONSTACK runs it on the alternate stack
+    // that the Rust runtime sets up for each thread, so that a stack overflow can be reported
+    #[cfg(unix)]
+    const FATAL_FLAGS: i32 = libc::SA_NODEFER | libc::SA_ONSTACK;
+    #[cfg(windows)]
+    const FATAL_FLAGS: i32 = 0;
+
+    static FATAL_ENABLED: AtomicBool = AtomicBool::new(false);
+    static FATAL_FD: AtomicI32 = AtomicI32::new(2);
+    static FATAL_ALL_THREADS: AtomicBool = AtomicBool::new(true);
+    static FATAL_PREVIOUS: HandlerCell<[Option<PreviousHandler>; FATAL_SIGNALS.len()]> =
+        HandlerCell(UnsafeCell::new([None; FATAL_SIGNALS.len()]));
+
+    /// Restore the handlers that were installed before `enable()`.
+    fn disable_fatal_handlers() {
+        let previous = unsafe { &mut *FATAL_PREVIOUS.0.get() };
+        for ((signum, _), previous) in FATAL_SIGNALS.iter().zip(previous) {
+            if let Some(previous) = previous.take() {
+                unsafe { restore_handler(*signum, &previous) };
+            }
+        }
+    }
+
+    extern "C" fn fatal_error_handler(signum: i32) {
+        if !FATAL_ENABLED.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let name = FATAL_SIGNALS
+            .iter()
+            .find(|(fatal, _)| *fatal == signum)
+            .map_or("Unknown signal", |(_, name)| name);
+        let mut out = FdWriter(FATAL_FD.load(Ordering::Relaxed));
+        let _ = writeln!(out, "Fatal Python error: {name}\n");
+        dump_traceback_from_handler(&mut out, FATAL_ALL_THREADS.load(Ordering::Relaxed));
+
+        // let the previous handler, usually the default one, deal with the signal: when it
+        // comes from a fault, returning runs the faulting instruction again
+        disable_fatal_handlers();
+        unsafe { libc::raise(signum) };
+    }
+
     #[derive(FromArgs)]
-    #[allow(unused)]
     struct EnableArgs {
         #[pyarg(any, default)]
-        file: Option<i64>,
+        file: Option<PyObjectRef>,
         #[pyarg(any, default = "true")]
         all_threads: bool,
     }
 
     #[pyfunction]
-    fn enable(_args: EnableArgs) {
-        // TODO
+    fn enable(args: EnableArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let (fd, file) = get_fileno(args.file, vm)?;
+        let mut files = files().lock();
+        FATAL_FD.store(fd, Ordering::Relaxed);
+        FATAL_ALL_THREADS.store(args.all_threads, Ordering::Relaxed);
+        files.fatal_error = file;
+
+        if !FATAL_ENABLED.load(Ordering::SeqCst) {
+            let previous = unsafe { &mut *FATAL_PREVIOUS.0.get() };
+            for ((signum, _), previous) in FATAL_SIGNALS.iter().zip(previous.iter_mut()) {
+                match unsafe { install_handler(*signum, fatal_error_handler, FATAL_FLAGS) } {
+                    Ok(handler) => *previous = Some(handler),
+                    Err(err) => {
+                        disable_fatal_handlers();
+                        files.fatal_error = None;
+                        return Err(err.to_pyexception(vm));
+                    }
+                }
+            }
+            FATAL_ENABLED.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn disable() -> bool {
+        let mut files = files().lock();
+        if !FATAL_ENABLED.swap(false, Ordering::SeqCst) {
+            return false;
+        }
+        disable_fatal_handlers();
+        files.fatal_error = None;
+        true
+    }
+
+    #[pyfunction]
+    fn is_enabled() -> bool {
+        FATAL_ENABLED.load(Ordering::SeqCst)
     }
 
     #[derive(FromArgs)]
-    #[allow(unused)]
+    struct DumpTracebackArgs {
+        #[pyarg(any, default)]
+        file: Option<PyObjectRef>,
+        #[pyarg(any, default = "true")]
+        all_threads: bool,
+    }
+
+    #[pyfunction]
+    fn dump_traceback(args: DumpTracebackArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let (fd, _file) = get_fileno(args.file, vm)?;
+        let mut out = FdWriter(fd);
+        // the stacks of other threads can't be read safely while they keep running, so only the
+        // calling thread is dumped
+        let result = if args.all_threads {
+            writeln!(
+                out,
+                "Current thread {:#018x} (most recent call first):",
+                thread::get_ident()
+            )
+        } else {
+            writeln!(out, "Stack (most recent call first):")
+        };
+        // SAFETY: vm is the calling thread's own
+        result
+            .and_then(|()| unsafe { dump_stack(&mut out, vm) })
+            .map_err(|_| io::Error::last_os_error().to_pyexception(vm))
+    }
+
+    struct Watchdog {
+        cancelled: Mutex<bool>,
+        cancel: Condvar,
+    }
+
+    static WATCHDOG: Mutex<Option<(Arc<Watchdog>, std::thread::JoinHandle<()>)>> =
+        parking_lot::const_mutex(None);
+
+    /// Format a timeout like CPython does, as `H:MM:SS` with optional microseconds.
+    fn format_timeout(timeout: Duration) -> String {
+        let secs = timeout.as_secs();
+        let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+        let micros = timeout.subsec_micros();
+        if micros != 0 {
+            format!("Timeout ({hours}:{mins:02}:{secs:02}.{micros:06})!")
+        } else {
+            format!("Timeout ({hours}:{mins:02}:{secs:02})!")
+        }
+    }
+
+    fn run_watchdog(watchdog: Arc<Watchdog>, timeout: Duration, repeat: bool, exit: bool, fd: i32) {
+        let header = format_timeout(timeout);
+        loop {
+            let deadline = Instant::now() + timeout;
+            let mut cancelled = watchdog.cancelled.lock();
+            while !*cancelled && Instant::now() < deadline {
+                watchdog.cancel.wait_until(&mut cancelled, deadline);
+            }
+            if *cancelled {
+                return;
+            }
+            drop(cancelled);
+
+            let mut out = FdWriter(fd);
+            let _ = writeln!(out, "{header}");
+            let _ = write_traceback(&mut out, None, true, true);
+            if exit {
+                unsafe { libc::_exit(1) };
+            }
+            if !repeat {
+                return;
+            }
+        }
+    }
+
+    fn cancel_watchdog() {
+        let watchdog = WATCHDOG.lock().take();
+        if let Some((watchdog, handle)) = watchdog {
+            *watchdog.cancelled.lock() = true;
+            watchdog.cancel.notify_all();
+            let _ = handle.join();
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DumpTracebackLaterArgs {
+        #[pyarg(positional)]
+        timeout: ArgIntoFloat,
+        #[pyarg(any, default = "false")]
+        repeat: bool,
+        #[pyarg(any, default)]
+        file: Option<PyObjectRef>,
+        #[pyarg(any, default = "false")]
+        exit: bool,
+    }
+
+    #[pyfunction]
+    fn dump_traceback_later(args: DumpTracebackLaterArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let timeout: f64 = args.timeout.into();
+        if timeout.is_nan() || timeout <= 0.0 {
+            return Err(vm.new_value_error("timeout must be greater than 0".to_owned()));
+        }
+        let timeout = Duration::try_from_secs_f64(timeout)
+            .map_err(|_| vm.new_overflow_error("timeout value is too large".to_owned()))?;
+        let (fd, file) = get_fileno(args.file, vm)?;
+
+        cancel_watchdog();
+        let watchdog = Arc::new(Watchdog {
+            cancelled: Mutex::new(false),
+            cancel: Condvar::new(),
+        });
+        let handle = {
+            let watchdog = watchdog.clone();
+            std::thread::Builder::new()
+                .name("faulthandler watchdog".to_owned())
+                .spawn(move || run_watchdog(watchdog, timeout, args.repeat, args.exit, fd))
+                .map_err(|err| err.to_pyexception(vm))?
+        };
+        *WATCHDOG.lock() = Some((watchdog, handle));
+        files().lock().watchdog = file;
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn cancel_dump_traceback_later() {
+        cancel_watchdog();
+        files().lock().watchdog = None;
+    }
+
+    #[cfg(unix)]
+    struct UserSignal {
+        enabled: AtomicBool,
+        fd: AtomicI32,
+        all_threads: AtomicBool,
+        chain: AtomicBool,
+        previous: HandlerCell<Option<PreviousHandler>>,
+    }
+
+    #[cfg(unix)]
+    #[allow(clippy::declare_interior_mutable_const)]
+    const USER_SIGNAL_INIT: UserSignal = UserSignal {
+        enabled: AtomicBool::new(false),
+        fd: AtomicI32::new(2),
+        all_threads: AtomicBool::new(true),
+        chain: AtomicBool::new(false),
+        previous: HandlerCell(UnsafeCell::new(None)),
+    };
+
+    #[cfg(unix)]
+    static USER_SIGNALS: [UserSignal; crate::vm::signal::NSIG] =
+        [USER_SIGNAL_INIT; crate::vm::signal::NSIG];
+
+    #[cfg(unix)]
+    fn user_signal_flags(chain: bool) -> i32 {
+        // with chain, the previous handler is called by raising the signal from the handler
+        let flags = libc::SA_RESTART | libc::SA_ONSTACK;
+        if chain {
+            flags | libc::SA_NODEFER
+        } else {
+            flags
+        }
+    }
+
+    #[cfg(unix)]
+    extern "C" fn user_signal_handler(signum: i32) {
+        let user = &USER_SIGNALS[signum as usize];
+        if !user.enabled.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut out = FdWriter(user.fd.load(Ordering::Relaxed));
+        dump_traceback_from_handler(&mut out, user.all_threads.load(Ordering::Relaxed));
+
+        if user.chain.load(Ordering::Relaxed) {
+            unsafe {
+                if let Some(previous) = &*user.previous.0.get() {
+                    restore_handler(signum, previous);
+                }
+                libc::raise(signum);
+                // the previous handler is the same one as before
+                let _ = install_handler(signum, user_signal_handler, user_signal_flags(true));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn check_signum(signum: i32, vm: &VirtualMachine) -> PyResult<()> {
+        if FATAL_SIGNALS.iter().any(|(fatal, _)| *fatal == signum) {
+            return Err(vm.new_runtime_error(format!(
+                "signal {signum} cannot be registered, use enable() instead"
+            )));
+        }
+        if signum < 1 || signum as usize >= crate::vm::signal::NSIG {
+            return Err(vm.new_value_error("signal number out of range".to_owned()));
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[derive(FromArgs)]
     struct RegisterArgs {
         #[pyarg(positional)]
-        signum: i64,
+        signum: i32,
         #[pyarg(any, default)]
-        file: Option<i64>,
+        file: Option<PyObjectRef>,
         #[pyarg(any, default = "true")]
         all_threads: bool,
         #[pyarg(any, default = "false")]
         chain: bool,
     }
 
+    #[cfg(unix)]
+    #[pyfunction]
+    fn register(args: RegisterArgs, vm: &VirtualMachine) -> PyResult<()> {
+        let signum = args.signum;
+        check_signum(signum, vm)?;
+        let (fd, file) = get_fileno(args.file, vm)?;
+        let mut files = files().lock();
+        let user = &USER_SIGNALS[signum as usize];
+        if !user.enabled.load(Ordering::SeqCst) {
+            let previous = unsafe {
+                install_handler(signum, user_signal_handler, user_signal_flags(args.chain))
+            }
+            .map_err(|err| err.to_pyexception(vm))?;
+            unsafe { *user.previous.0.get() = Some(previous) };
+        }
+        user.fd.store(fd, Ordering::Relaxed);
+        user.all_threads.store(args.all_threads, Ordering::Relaxed);
+        user.chain.store(args.chain, Ordering::Relaxed);
+        user.enabled.store(true, Ordering::SeqCst);
+        match file {
+            Some(file) => files.user_signals.insert(signum, file),
+            None => files.user_signals.remove(&signum),
+        };
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[pyfunction]
+    fn unregister(signum: i32, vm: &VirtualMachine) -> PyResult<bool> {
+        check_signum(signum, vm)?;
+        let mut files = files().lock();
+        let user = &USER_SIGNALS[signum as usize];
+        if !user.enabled.swap(false, Ordering::SeqCst) {
+            return Ok(false);
+        }
+        if let Some(previous) = unsafe { (*user.previous.0.get()).take() } {
+            unsafe { restore_handler(signum, &previous) };
+        }
+        files.user_signals.remove(&signum);
+        Ok(true)
+    }
+
+    // helpers for the tests of faulthandler, which need a way to crash the process
+
+    #[pyfunction]
+    fn _sigsegv() {
+        unsafe { libc::raise(libc::SIGSEGV) };
+        // the Rust runtime's own handler, which looks for stack overflows, only puts the default
+        // action back and returns when the signal doesn't come from a fault
+        unsafe { libc::raise(libc::SIGSEGV) };
+    }
+
+    #[pyfunction]
+    fn _sigabrt() {
+        unsafe { libc::abort() };
+    }
+
     #[pyfunction]
-    fn register(_args: RegisterArgs) {
-        // TODO
+    fn _sigfpe() {
+        unsafe { libc::raise(libc::SIGFPE) };
     }
 }
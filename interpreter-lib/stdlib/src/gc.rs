@@ -2,75 +2,182 @@ pub(crate) use gc::make_module;
 
 #[pymodule]
 mod gc {
-    use crate::vm::{function::FuncArgs, PyResult, VirtualMachine};
+    use crate::vm::{
+        builtins::PyListRef,
+        function::{OptionalArg, OptionalOption, PosArgs},
+        object::{gc as collector, Traverse},
+        AsObject, PyObjectRef, PyResult, VirtualMachine,
+    };
+
+    #[pyattr]
+    use collector::{DEBUG_COLLECTABLE, DEBUG_LEAK, DEBUG_SAVEALL, DEBUG_STATS, DEBUG_UNCOLLECTABLE};
+
+    #[pyattr]
+    fn garbage(vm: &VirtualMachine) -> PyListRef {
+        vm.state.gc_garbage.clone()
+    }
+
+    #[pyattr]
+    fn callbacks(vm: &VirtualMachine) -> PyListRef {
+        vm.state.gc_callbacks.clone()
+    }
+
+    #[derive(FromArgs)]
+    struct CollectArgs {
+        #[pyarg(any, default = "collector::NUM_GENERATIONS as i32 - 1")]
+        generation: i32,
+    }
 
     #[pyfunction]
-    fn collect(_args: FuncArgs, _vm: &VirtualMachine) -> i32 {
-        0
+    fn collect(args: CollectArgs, vm: &VirtualMachine) -> PyResult<usize> {
+        let generation = usize::try_from(args.generation)
+            .ok()
+            .filter(|&gen| gen < collector::NUM_GENERATIONS)
+            .ok_or_else(|| vm.new_value_error("invalid generation".to_owned()))?;
+        Ok(collector::collect(vm, generation))
     }
 
     #[pyfunction]
-    fn isenabled(_args: FuncArgs, _vm: &VirtualMachine) -> bool {
-        false
+    fn isenabled() -> bool {
+        collector::isenabled()
     }
 
     #[pyfunction]
-    fn enable(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn enable() {
+        collector::enable()
     }
 
     #[pyfunction]
-    fn disable(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn disable() {
+        collector::disable()
     }
 
     #[pyfunction]
-    fn get_count(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_count() -> (usize, usize, usize) {
+        let [gen0, gen1, gen2] = collector::get_count();
+        (gen0, gen1, gen2)
     }
 
     #[pyfunction]
-    fn get_debug(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_debug() -> u32 {
+        collector::get_debug()
+    }
+
+    #[pyfunction]
+    fn set_debug(flags: u32) {
+        collector::set_debug(flags)
+    }
+
+    #[pyfunction]
+    fn get_threshold() -> (u32, u32, u32) {
+        let [threshold0, threshold1, threshold2] = collector::get_threshold();
+        (threshold0, threshold1, threshold2)
+    }
+
+    #[pyfunction]
+    fn set_threshold(
+        threshold0: u32,
+        threshold1: OptionalArg<u32>,
+        threshold2: OptionalArg<u32>,
+    ) {
+        let thresholds: Vec<u32> = std::iter::once(threshold0)
+            .chain(threshold1.into_option())
+            .chain(threshold2.into_option())
+            .collect();
+        collector::set_threshold(&thresholds)
+    }
+
+    #[pyfunction]
+    fn get_stats(vm: &VirtualMachine) -> PyResult<PyListRef> {
+        let stats = collector::get_stats()
+            .iter()
+            .map(|stats| {
+                let dict = vm.ctx.new_dict();
+                dict.set_item("collections", vm.ctx.new_int(stats.collections).into(), vm)?;
+                dict.set_item("collected", vm.ctx.new_int(stats.collected).into(), vm)?;
+                dict.set_item(
+                    "uncollectable",
+                    vm.ctx.new_int(stats.uncollectable).into(),
+                    vm,
+                )?;
+                Ok(dict.into())
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(vm.ctx.new_list(stats))
+    }
+
+    #[derive(FromArgs)]
+    struct GetObjectsArgs {
+        #[pyarg(any, optional)]
+        generation: OptionalOption<i32>,
     }
 
     #[pyfunction]
-    fn get_objects(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_objects(args: GetObjectsArgs, vm: &VirtualMachine) -> PyResult<Vec<PyObjectRef>> {
+        let generation = args
+            .generation
+            .flatten()
+            .map(|gen| {
+                usize::try_from(gen)
+                    .ok()
+                    .filter(|&gen| gen < collector::NUM_GENERATIONS)
+                    .ok_or_else(|| {
+                        vm.new_value_error(format!(
+                            "generation parameter must be less than the number of available generations ({})",
+                            collector::NUM_GENERATIONS
+                        ))
+                    })
+            })
+            .transpose()?;
+        Ok(collector::get_objects(generation))
     }
 
     #[pyfunction]
-    fn get_refererts(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_referents(objs: PosArgs) -> Vec<PyObjectRef> {
+        let mut referents = Vec::new();
+        for obj in objs.iter() {
+            obj.as_object()
+                .traverse(&mut |child| referents.push(child.to_owned()));
+        }
+        referents
     }
 
     #[pyfunction]
-    fn get_referrers(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_referrers(objs: PosArgs) -> Vec<PyObjectRef> {
+        collector::get_objects(None)
+            .into_iter()
+            .filter(|candidate| {
+                let mut refers = false;
+                candidate.as_object().traverse(&mut |child| {
+                    refers |= objs.iter().any(|obj| obj.is(child));
+                });
+                refers
+            })
+            .collect()
     }
 
     #[pyfunction]
-    fn get_stats(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn is_tracked(obj: PyObjectRef) -> bool {
+        collector::is_tracked(&obj)
     }
 
     #[pyfunction]
-    fn get_threshold(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn is_finalized(obj: PyObjectRef) -> bool {
+        collector::is_finalized(&obj)
     }
 
     #[pyfunction]
-    fn is_tracked(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn freeze() {
+        collector::freeze()
     }
 
     #[pyfunction]
-    fn set_debug(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn unfreeze() {
+        collector::unfreeze()
     }
 
     #[pyfunction]
-    fn set_threshold(_args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-        Err(vm.new_not_implemented_error("".to_owned()))
+    fn get_freeze_count() -> usize {
+        collector::get_freeze_count()
     }
 }
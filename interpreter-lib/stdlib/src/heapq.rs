@@ -0,0 +1,181 @@
+pub(crate) use _heapq::make_module;
+
+#[pymodule]
+mod _heapq {
+    use crate::vm::{
+        builtins::{PyList, PyListRef},
+        types::PyComparisonOp,
+        PyObjectRef, PyResult, TryFromObject, VirtualMachine,
+    };
+
+    /// A list used as a heap. Comparisons run arbitrary Python code, so the list is never
+    /// borrowed across one and is checked for changes in size afterwards.
+    struct Heap {
+        list: PyListRef,
+        /// Whether the largest item is at the top, for `heapq.merge(reverse=True)`.
+        max: bool,
+    }
+
+    impl TryFromObject for Heap {
+        fn try_from_object(vm: &VirtualMachine, obj: PyObjectRef) -> PyResult<Self> {
+            let list = obj
+                .downcast::<PyList>()
+                .map_err(|_| vm.new_type_error("heap argument must be a list".to_owned()))?;
+            Ok(Heap { list, max: false })
+        }
+    }
+
+    impl Heap {
+        fn max(self) -> Self {
+            Heap { max: true, ..self }
+        }
+
+        fn len(&self) -> usize {
+            self.list.borrow_vec().len()
+        }
+
+        fn get(&self, i: usize) -> PyObjectRef {
+            self.list.borrow_vec()[i].clone()
+        }
+
+        /// Whether the item at `a` belongs above the one at `b`.
+        fn before(&self, a: usize, b: usize, vm: &VirtualMachine) -> PyResult<bool> {
+            let size = self.len();
+            let (a, b) = (self.get(a), self.get(b));
+            let result = if self.max {
+                b.rich_compare_bool(&a, PyComparisonOp::Lt, vm)?
+            } else {
+                a.rich_compare_bool(&b, PyComparisonOp::Lt, vm)?
+            };
+            if self.len() != size {
+                return Err(vm.new_runtime_error("list changed size during iteration".to_owned()));
+            }
+            Ok(result)
+        }
+
+        fn swap(&self, a: usize, b: usize) {
+            self.list.borrow_vec_mut().swap(a, b);
+        }
+
+        /// Move the item at `pos` up towards `start` until its parent belongs above it.
+        fn sift_down(&self, start: usize, mut pos: usize, vm: &VirtualMachine) -> PyResult<()> {
+            while pos > start {
+                let parent = (pos - 1) >> 1;
+                if !self.before(pos, parent, vm)? {
+                    break;
+                }
+                self.swap(parent, pos);
+                pos = parent;
+            }
+            Ok(())
+        }
+
+        /// Move the smaller child up until a leaf is reached at `pos`, then sift the item that
+        /// was there into place.
+        fn sift_up(&self, mut pos: usize, vm: &VirtualMachine) -> PyResult<()> {
+            let end = self.len();
+            let start = pos;
+            while pos < end >> 1 {
+                let mut child = 2 * pos + 1;
+                if child + 1 < end && !self.before(child, child + 1, vm)? {
+                    child += 1;
+                }
+                self.swap(child, pos);
+                pos = child;
+            }
+            self.sift_down(start, pos, vm)
+        }
+
+        fn push(&self, item: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let pos = {
+                let mut vec = self.list.borrow_vec_mut();
+                vec.push(item);
+                vec.len() - 1
+            };
+            self.sift_down(0, pos, vm)
+        }
+
+        fn pop(&self, vm: &VirtualMachine) -> PyResult {
+            let last = self
+                .list
+                .borrow_vec_mut()
+                .pop()
+                .ok_or_else(|| vm.new_index_error("index out of range".to_owned()))?;
+            let top = {
+                let mut vec = self.list.borrow_vec_mut();
+                match vec.first_mut() {
+                    Some(top) => std::mem::replace(top, last),
+                    None => return Ok(last),
+                }
+            };
+            self.sift_up(0, vm)?;
+            Ok(top)
+        }
+
+        fn replace(&self, item: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let top = {
+                let mut vec = self.list.borrow_vec_mut();
+                let top = vec
+                    .first_mut()
+                    .ok_or_else(|| vm.new_index_error("index out of range".to_owned()))?;
+                std::mem::replace(top, item)
+            };
+            self.sift_up(0, vm)?;
+            Ok(top)
+        }
+
+        fn heapify(&self, vm: &VirtualMachine) -> PyResult<()> {
+            for pos in (0..self.len() / 2).rev() {
+                self.sift_up(pos, vm)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[pyfunction]
+    fn heappush(heap: Heap, item: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+        heap.push(item, vm)
+    }
+
+    #[pyfunction]
+    fn heappop(heap: Heap, vm: &VirtualMachine) -> PyResult {
+        heap.pop(vm)
+    }
+
+    #[pyfunction]
+    fn heapreplace(heap: Heap, item: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        heap.replace(item, vm)
+    }
+
+    #[pyfunction]
+    fn heappushpop(heap: Heap, item: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        let Some(top) = heap.list.borrow_vec().first().cloned() else {
+            return Ok(item);
+        };
+        if !top.rich_compare_bool(&item, PyComparisonOp::Lt, vm)? {
+            return Ok(item);
+        }
+        // the comparison may have emptied the list
+        heap.replace(item, vm)
+    }
+
+    #[pyfunction]
+    fn heapify(heap: Heap, vm: &VirtualMachine) -> PyResult<()> {
+        heap.heapify(vm)
+    }
+
+    #[pyfunction]
+    fn _heappop_max(heap: Heap, vm: &VirtualMachine) -> PyResult {
+        heap.max().pop(vm)
+    }
+
+    #[pyfunction]
+    fn _heapreplace_max(heap: Heap, item: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+        heap.max().replace(item, vm)
+    }
+
+    #[pyfunction]
+    fn _heapify_max(heap: Heap, vm: &VirtualMachine) -> PyResult<()> {
+        heap.max().heapify(vm)
+    }
+}
@@ -6,13 +6,17 @@
 extern crate rustpython_derive;
 
 pub mod array;
+mod asyncio;
 mod binascii;
 mod bisect;
 mod cmath;
 mod contextvars;
 mod csv;
+mod datetime;
+mod decimal;
 mod dis;
 mod gc;
+mod heapq;
 
 mod blake2;
 mod hashlib;
@@ -25,24 +29,32 @@ mod sha512;
 mod json;
 #[cfg(not(any(target_os = "ios", target_os = "android", target_arch = "wasm32")))]
 mod locale;
+mod lsprof;
 mod math;
 #[cfg(unix)]
 mod mmap;
+mod pickle;
 mod pyexpat;
 mod pystruct;
 mod random;
 mod statistics;
+mod tracemalloc;
 // TODO: maybe make this an extension module, if we ever get those
 // mod re;
 #[cfg(feature = "bz2")]
 mod bz2;
+#[cfg(feature = "lzma")]
+mod lzma;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod socket;
 #[cfg(all(unix, not(target_os = "redox")))]
 mod syslog;
 mod unicodedata;
 mod zlib;
+mod zoneinfo;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod ctypes;
 #[cfg(not(target_arch = "wasm32"))]
 mod faulthandler;
 #[cfg(any(unix, target_os = "wasi"))]
@@ -101,13 +113,17 @@ pub fn get_module_inits() -> impl Iterator<Item = (Cow<'static, str>, StdlibInit
         #[cfg(all())]
         {
             "array" => array::make_module,
+            "_asyncio" => asyncio::make_module,
             "binascii" => binascii::make_module,
             "_bisect" => bisect::make_module,
             "cmath" => cmath::make_module,
             "_contextvars" => contextvars::make_module,
             "_csv" => csv::make_module,
+            "_datetime" => datetime::make_module,
+            "_decimal" => decimal::make_module,
             "_dis" => dis::make_module,
             "gc" => gc::make_module,
+            "_heapq" => heapq::make_module,
             "_hashlib" => hashlib::make_module,
             "_sha1" => sha1::make_module,
             "_sha3" => sha3::make_module,
@@ -116,13 +132,17 @@ pub fn get_module_inits() -> impl Iterator<Item = (Cow<'static, str>, StdlibInit
             "_md5" => md5::make_module,
             "_blake2" => blake2::make_module,
             "_json" => json::make_module,
+            "_lsprof" => lsprof::make_module,
             "math" => math::make_module,
+            "_pickle" => pickle::make_module,
             "pyexpat" => pyexpat::make_module,
             "_random" => random::make_module,
             "_statistics" => statistics::make_module,
             "_struct" => pystruct::make_module,
+            "_tracemalloc" => tracemalloc::make_module,
             "unicodedata" => unicodedata::make_module,
             "zlib" => zlib::make_module,
+            "_zoneinfo" => zoneinfo::make_module,
             "_statistics" => statistics::make_module,
             // crate::vm::sysmodule::sysconfigdata_name() => sysconfigdata::make_module,
         }
@@ -132,6 +152,7 @@ pub fn get_module_inits() -> impl Iterator<Item = (Cow<'static, str>, StdlibInit
         }
         #[cfg(not(target_arch = "wasm32"))]
         {
+            "_ctypes" => ctypes::make_module,
             "_multiprocessing" => multiprocessing::make_module,
             "select" => select::make_module,
             "_socket" => socket::make_module,
@@ -149,6 +170,10 @@ pub fn get_module_inits() -> impl Iterator<Item = (Cow<'static, str>, StdlibInit
         {
             "_bz2" => bz2::make_module,
         }
+        #[cfg(feature = "lzma")]
+        {
+            "_lzma" => lzma::make_module,
+        }
         // Unix-only
         #[cfg(unix)]
         {
@@ -0,0 +1,393 @@
+pub(crate) use _lsprof::make_module;
+
+#[pymodule]
+mod _lsprof {
+    use crate::common::lock::PyMutex;
+    use crate::vm::{
+        builtins::{PyModule, PyStrRef, PyTypeRef},
+        frame::Frame,
+        function::OptionalArg,
+        types::{Callable, Constructor, PyStructSequence},
+        AsObject, Py, PyObject, PyObjectRef, PyPayload, PyResult, VirtualMachine,
+    };
+    use std::collections::{BTreeMap, HashMap};
+    use std::time::Instant;
+
+    #[pyattr]
+    #[pyclass(module = "_lsprof", name = "profiler_entry", traverse)]
+    #[derive(PyStructSequence)]
+    struct StatsEntry {
+        code: PyObjectRef,
+        #[pytraverse(skip)]
+        callcount: usize,
+        #[pytraverse(skip)]
+        reccallcount: usize,
+        #[pytraverse(skip)]
+        totaltime: f64,
+        #[pytraverse(skip)]
+        inlinetime: f64,
+        calls: PyObjectRef,
+    }
+    #[pyclass(with(PyStructSequence))]
+    impl StatsEntry {}
+
+    #[pyattr]
+    #[pyclass(module = "_lsprof", name = "profiler_subentry", traverse)]
+    #[derive(PyStructSequence)]
+    struct StatsSubEntry {
+        code: PyObjectRef,
+        #[pytraverse(skip)]
+        callcount: usize,
+        #[pytraverse(skip)]
+        reccallcount: usize,
+        #[pytraverse(skip)]
+        totaltime: f64,
+        #[pytraverse(skip)]
+        inlinetime: f64,
+    }
+    #[pyclass(with(PyStructSequence))]
+    impl StatsSubEntry {}
+
+    /// Call statistics shared by entries and subentries.
+    #[derive(Debug, Default)]
+    struct CallStats {
+        callcount: usize,
+        reccallcount: usize,
+        totaltime: f64,
+        inlinetime: f64,
+        recursion_level: usize,
+    }
+
+    impl CallStats {
+        fn enter(&mut self) {
+            self.recursion_level += 1;
+        }
+
+        fn leave(&mut self, total: f64, inline: f64) {
+            self.recursion_level -= 1;
+            if self.recursion_level == 0 {
+                self.totaltime += total;
+            } else {
+                self.reccallcount += 1;
+            }
+            self.inlinetime += inline;
+            self.callcount += 1;
+        }
+    }
+
+    #[derive(Debug)]
+    struct ProfilerEntry {
+        /// The object the entry is keyed on; kept alive so that its id is not reused.
+        _key: PyObjectRef,
+        /// What `getstats()` reports: the code object, or a label for builtins.
+        code: PyObjectRef,
+        stats: CallStats,
+        /// Callees of this entry, by their index in `ProfilerState::entries`.
+        calls: BTreeMap<usize, CallStats>,
+    }
+
+    #[derive(Debug)]
+    struct ProfilerContext {
+        entry: usize,
+        start: f64,
+        /// Time spent in calls made from this context.
+        subcalls: f64,
+    }
+
+    #[derive(Debug)]
+    struct ProfilerState {
+        subcalls: bool,
+        builtins: bool,
+        entries: Vec<ProfilerEntry>,
+        index: HashMap<usize, usize>,
+        stack: Vec<ProfilerContext>,
+    }
+
+    impl ProfilerState {
+        /// The entry for `key`, created with `code` if there's none yet; `None` when there's
+        /// neither an entry nor a code.
+        fn entry_for(
+            &mut self,
+            key: &PyObject,
+            code: impl FnOnce() -> Option<PyObjectRef>,
+        ) -> Option<usize> {
+            if let Some(&index) = self.index.get(&key.get_id()) {
+                return Some(index);
+            }
+            let index = self.entries.len();
+            self.entries.push(ProfilerEntry {
+                _key: key.to_owned(),
+                code: code()?,
+                stats: CallStats::default(),
+                calls: BTreeMap::new(),
+            });
+            self.index.insert(key.get_id(), index);
+            Some(index)
+        }
+
+        fn enter_call(&mut self, entry: usize, now: f64) {
+            if self.subcalls {
+                if let Some(caller) = self.stack.last() {
+                    let caller = caller.entry;
+                    self.entries[caller].calls.entry(entry).or_default().enter();
+                }
+            }
+            self.entries[entry].stats.enter();
+            self.stack.push(ProfilerContext {
+                entry,
+                start: now,
+                subcalls: 0.0,
+            });
+        }
+
+        fn leave_call(&mut self, key: &PyObject, now: f64) {
+            let Some(&entry) = self.index.get(&key.get_id()) else {
+                return;
+            };
+            if self.stack.last().map(|ctx| ctx.entry) == Some(entry) {
+                self.pop_context(now);
+            }
+        }
+
+        fn pop_context(&mut self, now: f64) {
+            let Some(ctx) = self.stack.pop() else {
+                return;
+            };
+            let total = now - ctx.start;
+            let inline = total - ctx.subcalls;
+            self.entries[ctx.entry].stats.leave(total, inline);
+            if let Some(caller) = self.stack.last_mut() {
+                caller.subcalls += total;
+                if self.subcalls {
+                    if let Some(sub) = self.entries[caller.entry].calls.get_mut(&ctx.entry) {
+                        sub.leave(total, inline);
+                    }
+                }
+            }
+        }
+    }
+
+    #[pyattr]
+    #[pyclass(module = "_lsprof", name = "Profiler")]
+    #[derive(Debug, PyPayload)]
+    struct Profiler {
+        timer: Option<PyObjectRef>,
+        timeunit: f64,
+        epoch: Instant,
+        state: PyMutex<ProfilerState>,
+    }
+
+    #[derive(FromArgs)]
+    struct ProfilerArgs {
+        #[pyarg(any, optional)]
+        timer: OptionalArg<PyObjectRef>,
+        #[pyarg(any, default = "0.0")]
+        timeunit: f64,
+        #[pyarg(any, default = "true")]
+        subcalls: bool,
+        #[pyarg(any, default = "true")]
+        builtins: bool,
+    }
+
+    #[derive(FromArgs)]
+    struct EnableArgs {
+        #[pyarg(any, default = "true")]
+        subcalls: bool,
+        #[pyarg(any, default = "true")]
+        builtins: bool,
+    }
+
+    impl Constructor for Profiler {
+        type Args = ProfilerArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let timer = args.timer.into_option().filter(|timer| !vm.is_none(timer));
+            Profiler {
+                timer,
+                timeunit: args.timeunit,
+                epoch: Instant::now(),
+                state: PyMutex::new(ProfilerState {
+                    subcalls: args.subcalls,
+                    builtins: args.builtins,
+                    entries: Vec::new(),
+                    index: HashMap::new(),
+                    stack: Vec::new(),
+                }),
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    impl Callable for Profiler {
+        type Args = (PyObjectRef, PyStrRef, PyObjectRef);
+
+        fn call(zelf: &Py<Self>, (frame, event, arg): Self::Args, vm: &VirtualMachine) -> PyResult {
+            let now = zelf.now(vm)?;
+            // the label of a builtin is looked up with Python attribute accesses, which may run
+            // arbitrary code and so must not happen while the state is locked
+            let label = if event.as_str() == "c_call" {
+                let needs_label = {
+                    let state = zelf.state.lock();
+                    state.builtins && !state.index.contains_key(&arg.get_id())
+                };
+                if needs_label {
+                    Some(builtin_label(&arg, vm)?)
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+            let mut state = zelf.state.lock();
+            match event.as_str() {
+                "call" => {
+                    let code = frame
+                        .payload::<Frame>()
+                        .ok_or_else(|| vm.new_type_error("expected a frame".to_owned()))?
+                        .code
+                        .clone();
+                    if let Some(entry) =
+                        state.entry_for(code.as_object(), || Some(code.clone().into()))
+                    {
+                        state.enter_call(entry, now);
+                    }
+                }
+                "return" => {
+                    if let Some(frame) = frame.payload::<Frame>() {
+                        state.leave_call(frame.code.as_object(), now);
+                    }
+                }
+                "c_call" if state.builtins => {
+                    // builtins were only just enabled if there's no label, so skip this call
+                    if let Some(entry) = state.entry_for(&arg, || label) {
+                        state.enter_call(entry, now);
+                    }
+                }
+                "c_return" | "c_exception" if state.builtins => {
+                    state.leave_call(&arg, now);
+                }
+                _ => {}
+            }
+            Ok(vm.ctx.none())
+        }
+    }
+
+    #[pyclass(with(Constructor, Callable), flags(BASETYPE))]
+    impl Profiler {
+        fn now(&self, vm: &VirtualMachine) -> PyResult<f64> {
+            let Some(timer) = &self.timer else {
+                return Ok(self.epoch.elapsed().as_secs_f64());
+            };
+            let value = timer.call((), vm)?;
+            if self.timeunit > 0.0 {
+                Ok(value.try_into_value::<i64>(vm)? as f64 * self.timeunit)
+            } else {
+                Ok(value.try_float(vm)?.to_f64())
+            }
+        }
+
+        #[pymethod]
+        fn enable(zelf: &Py<Self>, args: EnableArgs, vm: &VirtualMachine) {
+            {
+                let mut state = zelf.state.lock();
+                state.subcalls = args.subcalls;
+                state.builtins = args.builtins;
+            }
+            vm.profile_func.replace(zelf.to_owned().into());
+            vm.update_use_tracing();
+        }
+
+        #[pymethod]
+        fn disable(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<()> {
+            if vm.profile_func.borrow().is(zelf) {
+                vm.profile_func.replace(vm.ctx.none());
+                vm.update_use_tracing();
+            }
+            // account for the calls that are still running
+            let now = zelf.now(vm)?;
+            let mut state = zelf.state.lock();
+            while !state.stack.is_empty() {
+                state.pop_context(now);
+            }
+            Ok(())
+        }
+
+        #[pymethod]
+        fn clear(&self) {
+            let mut state = self.state.lock();
+            state.entries.clear();
+            state.index.clear();
+            state.stack.clear();
+        }
+
+        #[pymethod]
+        fn getstats(&self, vm: &VirtualMachine) -> PyResult {
+            let state = self.state.lock();
+            let stats = state
+                .entries
+                .iter()
+                .map(|entry| {
+                    let calls = if entry.calls.is_empty() {
+                        vm.ctx.none()
+                    } else {
+                        let calls = entry
+                            .calls
+                            .iter()
+                            .map(|(&callee, stats)| {
+                                StatsSubEntry {
+                                    code: state.entries[callee].code.clone(),
+                                    callcount: stats.callcount,
+                                    reccallcount: stats.reccallcount,
+                                    totaltime: stats.totaltime,
+                                    inlinetime: stats.inlinetime,
+                                }
+                                .into_struct_sequence(vm)
+                                .into()
+                            })
+                            .collect();
+                        vm.ctx.new_list(calls).into()
+                    };
+                    StatsEntry {
+                        code: entry.code.clone(),
+                        callcount: entry.stats.callcount,
+                        reccallcount: entry.stats.reccallcount,
+                        totaltime: entry.stats.totaltime,
+                        inlinetime: entry.stats.inlinetime,
+                        calls,
+                    }
+                    .into_struct_sequence(vm)
+                    .into()
+                })
+                .collect();
+            Ok(vm.ctx.new_list(stats).into())
+        }
+    }
+
+    /// The label cProfile shows for a builtin: `<built-in method module.name>` for functions
+    /// and `<method 'name' of 'type' objects>` for methods.
+    fn builtin_label(func: &PyObject, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        let name = func.get_attr("__name__", vm)?.str(vm)?;
+        let owner = func
+            .get_attr("__self__", vm)
+            .ok()
+            .filter(|owner| !vm.is_none(owner) && !owner.payload_is::<PyModule>());
+        let class_name = match owner {
+            Some(owner) => Some(owner.class().name().to_owned()),
+            None => match func.get_attr("__objclass__", vm) {
+                Ok(objclass) => Some(objclass.get_attr("__name__", vm)?.str(vm)?.to_string()),
+                Err(_) => None,
+            },
+        };
+        let label = match class_name {
+            Some(class_name) => format!("<method '{name}' of '{class_name}' objects>"),
+            None => match func.get_attr("__module__", vm) {
+                Ok(module) if !vm.is_none(&module) => {
+                    format!("<built-in method {}.{name}>", module.str(vm)?)
+                }
+                _ => format!("<built-in method {name}>"),
+            },
+        };
+        Ok(vm.ctx.new_str(label).into())
+    }
+}
@@ -0,0 +1,767 @@
+// spell-checker:ignore armthumb memlimit powerpc sparc
+
+pub(crate) use _lzma::make_module;
+
+#[pymodule]
+mod _lzma {
+    use crate::common::lock::PyMutex;
+    use crate::vm::{
+        builtins::{PyBaseExceptionRef, PyBytesRef, PyDict, PyDictRef, PyTypeRef},
+        convert::ToPyObject,
+        function::{ArgBytesLike, ArgIterable},
+        object::{Py, PyObjectRef, PyPayload, PyResult},
+        types::Constructor,
+        TryFromObject, VirtualMachine,
+    };
+    use lzma_sys::*;
+    use std::{ffi::c_void, fmt, mem, ptr};
+
+    #[pyattr]
+    const FORMAT_AUTO: i32 = 0;
+    #[pyattr]
+    const FORMAT_XZ: i32 = 1;
+    #[pyattr]
+    const FORMAT_ALONE: i32 = 2;
+    #[pyattr]
+    const FORMAT_RAW: i32 = 3;
+
+    #[pyattr]
+    const CHECK_NONE: i32 = LZMA_CHECK_NONE as i32;
+    #[pyattr]
+    const CHECK_CRC32: i32 = LZMA_CHECK_CRC32 as i32;
+    #[pyattr]
+    const CHECK_CRC64: i32 = LZMA_CHECK_CRC64 as i32;
+    #[pyattr]
+    const CHECK_SHA256: i32 = LZMA_CHECK_SHA256 as i32;
+    #[pyattr]
+    const CHECK_ID_MAX: i32 = 15;
+    #[pyattr]
+    const CHECK_UNKNOWN: i32 = CHECK_ID_MAX + 1;
+
+    // not exported by lzma-sys
+    const LZMA_FILTER_DELTA: lzma_vli = 0x03;
+    const LZMA_DELTA_TYPE_BYTE: u32 = 0;
+    const LZMA_FILTERS_MAX: usize = 4;
+
+    #[pyattr]
+    const FILTER_LZMA1: u64 = LZMA_FILTER_LZMA1;
+    #[pyattr]
+    const FILTER_LZMA2: u64 = LZMA_FILTER_LZMA2;
+    #[pyattr]
+    const FILTER_DELTA: u64 = LZMA_FILTER_DELTA;
+    #[pyattr]
+    const FILTER_X86: u64 = LZMA_FILTER_X86;
+    #[pyattr]
+    const FILTER_POWERPC: u64 = LZMA_FILTER_POWERPC;
+    #[pyattr]
+    const FILTER_IA64: u64 = LZMA_FILTER_IA64;
+    #[pyattr]
+    const FILTER_ARM: u64 = LZMA_FILTER_ARM;
+    #[pyattr]
+    const FILTER_ARMTHUMB: u64 = LZMA_FILTER_ARMTHUMB;
+    #[pyattr]
+    const FILTER_SPARC: u64 = LZMA_FILTER_SPARC;
+
+    #[pyattr]
+    const MF_HC3: lzma_match_finder = LZMA_MF_HC3;
+    #[pyattr]
+    const MF_HC4: lzma_match_finder = LZMA_MF_HC4;
+    #[pyattr]
+    const MF_BT2: lzma_match_finder = LZMA_MF_BT2;
+    #[pyattr]
+    const MF_BT3: lzma_match_finder = LZMA_MF_BT3;
+    #[pyattr]
+    const MF_BT4: lzma_match_finder = LZMA_MF_BT4;
+
+    #[pyattr]
+    const MODE_FAST: lzma_mode = LZMA_MODE_FAST;
+    #[pyattr]
+    const MODE_NORMAL: lzma_mode = LZMA_MODE_NORMAL;
+
+    #[pyattr]
+    const PRESET_DEFAULT: u32 = LZMA_PRESET_DEFAULT;
+    #[pyattr]
+    const PRESET_EXTREME: u32 = LZMA_PRESET_EXTREME;
+
+    const BUFSIZ: usize = 8192;
+
+    #[pyattr(name = "LZMAError", once)]
+    fn error(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "_lzma",
+            "LZMAError",
+            Some(vec![vm.ctx.exceptions.exception_type.to_owned()]),
+        )
+    }
+
+    fn new_lzma_error(message: impl Into<String>, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        vm.new_exception_msg(vm.class("_lzma", "LZMAError"), message.into())
+    }
+
+    /// Turn a liblzma return code into an error, letting the non-error codes through.
+    fn catch_lzma_error(ret: lzma_ret, vm: &VirtualMachine) -> PyResult<lzma_ret> {
+        let message = match ret {
+            LZMA_OK | LZMA_GET_CHECK | LZMA_NO_CHECK | LZMA_STREAM_END => return Ok(ret),
+            LZMA_UNSUPPORTED_CHECK => "Unsupported integrity check",
+            LZMA_MEM_ERROR => return Err(vm.new_memory_error(String::new())),
+            LZMA_MEMLIMIT_ERROR => "Memory usage limit exceeded",
+            LZMA_FORMAT_ERROR => "Input format not supported by decoder",
+            LZMA_OPTIONS_ERROR => "Invalid or unsupported options",
+            LZMA_DATA_ERROR => "Corrupt input data",
+            LZMA_BUF_ERROR => "Insufficient buffer space",
+            LZMA_PROG_ERROR => "Internal error",
+            _ => {
+                return Err(new_lzma_error(
+                    format!("Unrecognized error from liblzma: {ret}"),
+                    vm,
+                ))
+            }
+        };
+        Err(new_lzma_error(message, vm))
+    }
+
+    /// An initialized `lzma_stream`, ended when dropped.
+    struct Stream(Box<lzma_stream>);
+
+    // liblzma streams may move between threads; access is serialized by the owner's mutex
+    unsafe impl Send for Stream {}
+
+    impl Stream {
+        fn new(
+            init: impl FnOnce(*mut lzma_stream) -> lzma_ret,
+            vm: &VirtualMachine,
+        ) -> PyResult<Self> {
+            // LZMA_STREAM_INIT is all zeroes
+            let mut stream = Stream(Box::new(unsafe { mem::zeroed() }));
+            catch_lzma_error(init(&mut *stream.0), vm)?;
+            Ok(stream)
+        }
+
+        /// Run the coder over `input`, appending to `output`. Stops when the input is used up
+        /// and the coder has room to spare, at the end of the stream, or once `output` holds
+        /// `max_length` bytes. Returns the last return code and how much input was consumed.
+        fn code(
+            &mut self,
+            input: &[u8],
+            output: &mut Vec<u8>,
+            action: lzma_action,
+            max_length: Option<usize>,
+            vm: &VirtualMachine,
+        ) -> PyResult<(lzma_ret, usize)> {
+            let stream = &mut *self.0;
+            stream.next_in = input.as_ptr();
+            stream.avail_in = input.len();
+            loop {
+                if output.len() == output.capacity() {
+                    let additional = match max_length {
+                        Some(max_length) => BUFSIZ.min(max_length - output.len()),
+                        None => BUFSIZ.max(output.len()),
+                    };
+                    output.reserve_exact(additional);
+                }
+                let spare = output.capacity() - output.len();
+                stream.next_out = unsafe { output.as_mut_ptr().add(output.len()) };
+                stream.avail_out = spare;
+                let mut ret = unsafe { lzma_code(stream, action) };
+                let written = spare - stream.avail_out;
+                unsafe { output.set_len(output.len() + written) };
+                if ret == LZMA_BUF_ERROR && stream.avail_in == 0 && stream.avail_out > 0 {
+                    // the decoder is waiting for more input
+                    ret = LZMA_OK;
+                }
+                let ret = catch_lzma_error(ret, vm);
+                let consumed = input.len() - stream.avail_in;
+                let ret = match ret {
+                    Ok(ret) => ret,
+                    Err(e) => {
+                        stream.next_in = ptr::null();
+                        stream.avail_in = 0;
+                        return Err(e);
+                    }
+                };
+                let done = ret == LZMA_STREAM_END
+                    || ret == LZMA_GET_CHECK
+                    || ret == LZMA_NO_CHECK
+                    || (stream.avail_out == 0 && Some(output.len()) == max_length)
+                    || (stream.avail_out > 0 && stream.avail_in == 0 && action == LZMA_RUN);
+                if done {
+                    stream.next_in = ptr::null();
+                    stream.avail_in = 0;
+                    return Ok((ret, consumed));
+                }
+            }
+        }
+
+        fn check(&self) -> i32 {
+            unsafe { lzma_get_check(&*self.0) as i32 }
+        }
+    }
+
+    impl Drop for Stream {
+        fn drop(&mut self) {
+            unsafe { lzma_end(&mut *self.0) };
+        }
+    }
+
+    /// A filter chain parsed from Python filter specifiers, owning the options the chain
+    /// points to.
+    struct FilterChain {
+        filters: Vec<lzma_filter>,
+        _options: Vec<FilterOptions>,
+    }
+
+    enum FilterOptions {
+        Lzma(Box<lzma_options_lzma>),
+        Delta(Box<OptionsDelta>),
+        Bcj(Box<lzma_options_bcj>),
+    }
+
+    /// `lzma_options_delta`, which lzma-sys does not bind.
+    #[repr(C)]
+    struct OptionsDelta {
+        type_: u32,
+        dist: u32,
+        reserved_int: [u32; 8],
+        reserved_ptr: [*mut c_void; 2],
+    }
+
+    impl FilterOptions {
+        fn as_mut_ptr(&mut self) -> *mut c_void {
+            match self {
+                FilterOptions::Lzma(o) => &mut **o as *mut lzma_options_lzma as *mut c_void,
+                FilterOptions::Delta(o) => &mut **o as *mut OptionsDelta as *mut c_void,
+                FilterOptions::Bcj(o) => &mut **o as *mut lzma_options_bcj as *mut c_void,
+            }
+        }
+    }
+
+    impl FilterChain {
+        fn as_ptr(&self) -> *const lzma_filter {
+            self.filters.as_ptr()
+        }
+    }
+
+    fn spec_field(spec: &Py<PyDict>, name: &str, vm: &VirtualMachine) -> PyResult<Option<u64>> {
+        spec.get_item_opt(name, vm)?
+            .map(|value| value.try_index(vm)?.try_to_primitive::<u64>(vm))
+            .transpose()
+    }
+
+    fn parse_filter_spec(
+        spec: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<(lzma_vli, FilterOptions)> {
+        let spec = spec.downcast::<PyDict>().map_err(|_| {
+            vm.new_type_error("Filter specifier must be a dict or dict-like object".to_owned())
+        })?;
+        let id = spec_field(&spec, "id", vm)?.ok_or_else(|| {
+            vm.new_value_error("Filter specifier must have an \"id\" entry".to_owned())
+        })?;
+        let allowed: &[&str] = match id {
+            LZMA_FILTER_LZMA1 | LZMA_FILTER_LZMA2 => &[
+                "id",
+                "preset",
+                "dict_size",
+                "lc",
+                "lp",
+                "pb",
+                "mode",
+                "nice_len",
+                "mf",
+                "depth",
+            ],
+            LZMA_FILTER_DELTA => &["id", "dist"],
+            LZMA_FILTER_X86 | LZMA_FILTER_POWERPC | LZMA_FILTER_IA64 | LZMA_FILTER_ARM
+            | LZMA_FILTER_ARMTHUMB | LZMA_FILTER_SPARC => &["id", "start_offset"],
+            _ => return Err(vm.new_value_error(format!("Invalid filter ID: {id}"))),
+        };
+        for (key, _) in &spec {
+            let key = key.str(vm)?;
+            if !allowed.contains(&key.as_str()) {
+                return Err(vm.new_type_error(format!(
+                    "'{}' is an invalid keyword argument for this filter",
+                    key.as_str()
+                )));
+            }
+        }
+        let field = |name: &str| -> PyResult<Option<u32>> {
+            spec_field(&spec, name, vm)?
+                .map(|value| {
+                    u32::try_from(value).map_err(|_| {
+                        vm.new_overflow_error(format!("Value too large for {name} option"))
+                    })
+                })
+                .transpose()
+        };
+        let options = match id {
+            LZMA_FILTER_LZMA1 | LZMA_FILTER_LZMA2 => {
+                let preset = field("preset")?.unwrap_or(LZMA_PRESET_DEFAULT);
+                let mut options: Box<lzma_options_lzma> = Box::new(unsafe { mem::zeroed() });
+                if unsafe { lzma_lzma_preset(&mut *options, preset) } != 0 {
+                    return Err(new_lzma_error(
+                        format!("Invalid compression preset: {preset}"),
+                        vm,
+                    ));
+                }
+                let fields: [(&str, &mut u32); 6] = [
+                    ("dict_size", &mut options.dict_size),
+                    ("lc", &mut options.lc),
+                    ("lp", &mut options.lp),
+                    ("pb", &mut options.pb),
+                    ("nice_len", &mut options.nice_len),
+                    ("depth", &mut options.depth),
+                ];
+                for (name, slot) in fields {
+                    if let Some(value) = field(name)? {
+                        *slot = value;
+                    }
+                }
+                // enums in liblzma, whose representation varies by platform
+                if let Some(mode) = field("mode")? {
+                    options.mode = mode as lzma_mode;
+                }
+                if let Some(mf) = field("mf")? {
+                    options.mf = mf as lzma_match_finder;
+                }
+                FilterOptions::Lzma(options)
+            }
+            LZMA_FILTER_DELTA => FilterOptions::Delta(Box::new(OptionsDelta {
+                type_: LZMA_DELTA_TYPE_BYTE,
+                dist: field("dist")?.unwrap_or(1),
+                reserved_int: [0; 8],
+                reserved_ptr: [ptr::null_mut(); 2],
+            })),
+            _ => FilterOptions::Bcj(Box::new(lzma_options_bcj {
+                start_offset: field("start_offset")?.unwrap_or(0),
+            })),
+        };
+        Ok((id, options))
+    }
+
+    fn parse_filter_chain(filters: PyObjectRef, vm: &VirtualMachine) -> PyResult<FilterChain> {
+        let specs: Vec<PyObjectRef> = ArgIterable::try_from_object(vm, filters)?
+            .iter(vm)?
+            .collect::<PyResult<_>>()?;
+        if specs.len() > LZMA_FILTERS_MAX {
+            return Err(vm.new_value_error(format!(
+                "Too many filters - liblzma supports a maximum of {LZMA_FILTERS_MAX}"
+            )));
+        }
+        let mut filters = Vec::with_capacity(specs.len() + 1);
+        let mut options = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let (id, mut option) = parse_filter_spec(spec, vm)?;
+            filters.push(lzma_filter {
+                id,
+                options: option.as_mut_ptr(),
+            });
+            options.push(option);
+        }
+        filters.push(lzma_filter {
+            id: LZMA_VLI_UNKNOWN,
+            options: ptr::null_mut(),
+        });
+        Ok(FilterChain {
+            filters,
+            _options: options,
+        })
+    }
+
+    #[pyfunction]
+    fn is_check_supported(check_id: i32) -> bool {
+        unsafe { lzma_check_is_supported(check_id as lzma_check) != 0 }
+    }
+
+    #[pyfunction]
+    fn _encode_filter_properties(filter: PyObjectRef, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+        let (id, mut options) = parse_filter_spec(filter, vm)?;
+        let filter = lzma_filter {
+            id,
+            options: options.as_mut_ptr(),
+        };
+        let mut size = 0;
+        catch_lzma_error(unsafe { lzma_properties_size(&mut size, &filter) }, vm)?;
+        let mut props = vec![0; size as usize];
+        catch_lzma_error(
+            unsafe { lzma_properties_encode(&filter, props.as_mut_ptr()) },
+            vm,
+        )?;
+        Ok(props)
+    }
+
+    #[pyfunction]
+    fn _decode_filter_properties(
+        filter_id: u64,
+        encoded_props: ArgBytesLike,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyDictRef> {
+        let mut filter = lzma_filter {
+            id: filter_id,
+            options: ptr::null_mut(),
+        };
+        let ret = encoded_props.with_ref(|props| unsafe {
+            lzma_properties_decode(&mut filter, ptr::null(), props.as_ptr(), props.len())
+        });
+        catch_lzma_error(ret, vm)?;
+
+        let dict = vm.ctx.new_dict();
+        let set = |key: &str, value: PyObjectRef| dict.set_item(key, value, vm);
+        set("id", filter_id.to_pyobject(vm))?;
+        if !filter.options.is_null() {
+            match filter_id {
+                LZMA_FILTER_LZMA1 | LZMA_FILTER_LZMA2 => {
+                    let options = unsafe { &*(filter.options as *const lzma_options_lzma) };
+                    if filter_id == LZMA_FILTER_LZMA1 {
+                        set("lc", options.lc.to_pyobject(vm))?;
+                        set("lp", options.lp.to_pyobject(vm))?;
+                    }
+                    set("pb", options.pb.to_pyobject(vm))?;
+                    set("dict_size", options.dict_size.to_pyobject(vm))?;
+                }
+                LZMA_FILTER_DELTA => {
+                    let options = unsafe { &*(filter.options as *const OptionsDelta) };
+                    set("dist", options.dist.to_pyobject(vm))?;
+                }
+                _ => {
+                    let options = unsafe { &*(filter.options as *const lzma_options_bcj) };
+                    set("start_offset", options.start_offset.to_pyobject(vm))?;
+                }
+            }
+            // allocated by liblzma with the default allocator
+            unsafe { libc::free(filter.options) };
+        }
+        Ok(dict)
+    }
+
+    struct DecompressorState {
+        stream: Stream,
+        check: i32,
+        eof: bool,
+        needs_input: bool,
+        /// Input the decoder has not consumed yet because `max_length` stopped it.
+        input_buffer: Vec<u8>,
+        unused_data: Option<PyBytesRef>,
+    }
+
+    #[pyattr]
+    #[pyclass(name = "LZMADecompressor")]
+    #[derive(PyPayload)]
+    struct LZMADecompressor {
+        state: PyMutex<DecompressorState>,
+    }
+
+    impl fmt::Debug for LZMADecompressor {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "_lzma.LZMADecompressor")
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DecompressorArgs {
+        #[pyarg(any, default = "FORMAT_AUTO")]
+        format: i32,
+        #[pyarg(any, default)]
+        memlimit: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        filters: Option<PyObjectRef>,
+    }
+
+    impl Constructor for LZMADecompressor {
+        type Args = DecompressorArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let memlimit = args.memlimit.filter(|m| !vm.is_none(m));
+            let filters = args.filters.filter(|f| !vm.is_none(f));
+            if memlimit.is_some() && args.format == FORMAT_RAW {
+                return Err(
+                    vm.new_value_error("Cannot specify memory limit with FORMAT_RAW".to_owned())
+                );
+            }
+            let memlimit = match memlimit {
+                Some(memlimit) => memlimit.try_index(vm)?.try_to_primitive::<u64>(vm)?,
+                None => u64::MAX,
+            };
+            if args.format == FORMAT_RAW && filters.is_none() {
+                return Err(vm.new_value_error("Must specify filters for FORMAT_RAW".to_owned()));
+            }
+            if args.format != FORMAT_RAW && filters.is_some() {
+                return Err(
+                    vm.new_value_error("Cannot specify filters except with FORMAT_RAW".to_owned())
+                );
+            }
+            let flags = LZMA_TELL_ANY_CHECK | LZMA_TELL_NO_CHECK;
+            let (stream, check) = match args.format {
+                FORMAT_AUTO => (
+                    Stream::new(|s| unsafe { lzma_auto_decoder(s, memlimit, flags) }, vm)?,
+                    CHECK_UNKNOWN,
+                ),
+                FORMAT_XZ => (
+                    Stream::new(|s| unsafe { lzma_stream_decoder(s, memlimit, flags) }, vm)?,
+                    CHECK_UNKNOWN,
+                ),
+                FORMAT_ALONE => (
+                    Stream::new(|s| unsafe { lzma_alone_decoder(s, memlimit) }, vm)?,
+                    CHECK_NONE,
+                ),
+                FORMAT_RAW => {
+                    let chain = parse_filter_chain(filters.unwrap(), vm)?;
+                    let stream =
+                        Stream::new(|s| unsafe { lzma_raw_decoder(s, chain.as_ptr()) }, vm)?;
+                    (stream, CHECK_NONE)
+                }
+                format => {
+                    return Err(vm.new_value_error(format!("Invalid container format: {format}")))
+                }
+            };
+            Self {
+                state: PyMutex::new(DecompressorState {
+                    stream,
+                    check,
+                    eof: false,
+                    needs_input: true,
+                    input_buffer: Vec::new(),
+                    unused_data: None,
+                }),
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DecompressArgs {
+        #[pyarg(any)]
+        data: ArgBytesLike,
+        #[pyarg(any, default = "-1")]
+        max_length: isize,
+    }
+
+    #[pyclass(with(Constructor))]
+    impl LZMADecompressor {
+        #[pymethod]
+        fn decompress(&self, args: DecompressArgs, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            let max_length = usize::try_from(args.max_length).ok();
+            let mut state = self.state.lock();
+            let DecompressorState {
+                stream,
+                check,
+                eof,
+                needs_input,
+                input_buffer,
+                unused_data,
+            } = &mut *state;
+
+            if *eof {
+                return Err(vm.new_exception_msg(
+                    vm.ctx.exceptions.eof_error.to_owned(),
+                    "Already at end of stream".to_owned(),
+                ));
+            }
+
+            args.data
+                .with_ref(|data| input_buffer.extend_from_slice(data));
+            let mut output = Vec::new();
+            let result = loop {
+                let (ret, consumed) =
+                    match stream.code(input_buffer, &mut output, LZMA_RUN, max_length, vm) {
+                        Ok(result) => result,
+                        Err(e) => {
+                            input_buffer.clear();
+                            return Err(e);
+                        }
+                    };
+                input_buffer.drain(..consumed);
+                match ret {
+                    LZMA_GET_CHECK | LZMA_NO_CHECK => {
+                        // the header told us the check; keep decoding
+                        *check = stream.check();
+                        continue;
+                    }
+                    LZMA_STREAM_END => *eof = true,
+                    _ => {}
+                }
+                break output;
+            };
+
+            if *eof {
+                *needs_input = false;
+                if !input_buffer.is_empty() {
+                    *unused_data = Some(vm.ctx.new_bytes(mem::take(input_buffer)));
+                }
+            } else if input_buffer.is_empty() {
+                // with a full output buffer the decoder may still hold some output back
+                *needs_input = Some(result.len()) != max_length;
+            } else {
+                *needs_input = false;
+            }
+            Ok(result)
+        }
+
+        #[pygetset]
+        fn check(&self) -> i32 {
+            self.state.lock().check
+        }
+
+        #[pygetset]
+        fn eof(&self) -> bool {
+            self.state.lock().eof
+        }
+
+        #[pygetset]
+        fn unused_data(&self, vm: &VirtualMachine) -> PyBytesRef {
+            self.state
+                .lock()
+                .unused_data
+                .clone()
+                .unwrap_or_else(|| vm.ctx.empty_bytes.clone())
+        }
+
+        #[pygetset]
+        fn needs_input(&self) -> bool {
+            self.state.lock().needs_input
+        }
+    }
+
+    struct CompressorState {
+        stream: Stream,
+        flushed: bool,
+    }
+
+    #[pyattr]
+    #[pyclass(name = "LZMACompressor")]
+    #[derive(PyPayload)]
+    struct LZMACompressor {
+        state: PyMutex<CompressorState>,
+    }
+
+    impl fmt::Debug for LZMACompressor {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "_lzma.LZMACompressor")
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct CompressorArgs {
+        #[pyarg(any, default = "FORMAT_XZ")]
+        format: i32,
+        #[pyarg(any, default = "-1")]
+        check: i32,
+        #[pyarg(any, default)]
+        preset: Option<PyObjectRef>,
+        #[pyarg(any, default)]
+        filters: Option<PyObjectRef>,
+    }
+
+    impl Constructor for LZMACompressor {
+        type Args = CompressorArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let preset = args.preset.filter(|p| !vm.is_none(p));
+            let filters = args.filters.filter(|f| !vm.is_none(f));
+            let mut check = args.check;
+            if args.format != FORMAT_XZ && check != -1 && check != CHECK_NONE {
+                return Err(vm.new_value_error(
+                    "Integrity checks are only supported by FORMAT_XZ".to_owned(),
+                ));
+            }
+            if preset.is_some() && filters.is_some() {
+                return Err(
+                    vm.new_value_error("Cannot specify both preset and filter chain".to_owned())
+                );
+            }
+            let preset = match preset {
+                Some(preset) => preset.try_index(vm)?.try_to_primitive::<u32>(vm)?,
+                None => LZMA_PRESET_DEFAULT,
+            };
+            let chain = filters
+                .map(|filters| parse_filter_chain(filters, vm))
+                .transpose()?;
+            let stream = match args.format {
+                FORMAT_XZ => {
+                    if check == -1 {
+                        check = CHECK_CRC64;
+                    }
+                    let check = check as lzma_check;
+                    match &chain {
+                        Some(chain) => Stream::new(
+                            |s| unsafe { lzma_stream_encoder(s, chain.as_ptr(), check) },
+                            vm,
+                        )?,
+                        None => {
+                            Stream::new(|s| unsafe { lzma_easy_encoder(s, preset, check) }, vm)?
+                        }
+                    }
+                }
+                FORMAT_ALONE => {
+                    let options = match &chain {
+                        Some(chain) => {
+                            let filter = &chain.filters[0];
+                            if chain.filters.len() != 2 || filter.id != LZMA_FILTER_LZMA1 {
+                                return Err(vm.new_value_error(
+                                    "Invalid filter chain for FORMAT_ALONE - \
+                                     must be a single LZMA1 filter"
+                                        .to_owned(),
+                                ));
+                            }
+                            unsafe { *(filter.options as *const lzma_options_lzma) }
+                        }
+                        None => {
+                            let mut options: lzma_options_lzma = unsafe { mem::zeroed() };
+                            if unsafe { lzma_lzma_preset(&mut options, preset) } != 0 {
+                                return Err(new_lzma_error(
+                                    format!("Invalid compression preset: {preset}"),
+                                    vm,
+                                ));
+                            }
+                            options
+                        }
+                    };
+                    Stream::new(|s| unsafe { lzma_alone_encoder(s, &options) }, vm)?
+                }
+                FORMAT_RAW => {
+                    let chain = chain.ok_or_else(|| {
+                        vm.new_value_error("Must specify filters for FORMAT_RAW".to_owned())
+                    })?;
+                    Stream::new(|s| unsafe { lzma_raw_encoder(s, chain.as_ptr()) }, vm)?
+                }
+                format => {
+                    return Err(vm.new_value_error(format!("Invalid container format: {format}")))
+                }
+            };
+            Self {
+                state: PyMutex::new(CompressorState {
+                    stream,
+                    flushed: false,
+                }),
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    #[pyclass(with(Constructor))]
+    impl LZMACompressor {
+        #[pymethod]
+        fn compress(&self, data: ArgBytesLike, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            let mut state = self.state.lock();
+            if state.flushed {
+                return Err(vm.new_value_error("Compressor has been flushed".to_owned()));
+            }
+            let mut output = Vec::new();
+            data.with_ref(|data| state.stream.code(data, &mut output, LZMA_RUN, None, vm))?;
+            Ok(output)
+        }
+
+        #[pymethod]
+        fn flush(&self, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            let mut state = self.state.lock();
+            if state.flushed {
+                return Err(vm.new_value_error("Repeated call to flush()".to_owned()));
+            }
+            state.flushed = true;
+            let mut output = Vec::new();
+            state.stream.code(&[], &mut output, LZMA_FINISH, None, vm)?;
+            Ok(output)
+        }
+    }
+}
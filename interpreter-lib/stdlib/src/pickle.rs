@@ -0,0 +1,2530 @@
+pub(crate) use _pickle::make_module;
+
+#[pymodule]
+mod _pickle {
+    use crate::common::lock::PyMutex;
+    use crate::vm::{
+        builtins::{
+            PyBaseExceptionRef, PyByteArray, PyBytes, PyDict, PyDictRef, PyFloat, PyFrozenSet,
+            PyInt, PyList, PyMemoryView, PySet, PyStr, PyStrRef, PyTuple, PyTupleRef, PyType,
+            PyTypeRef,
+        },
+        convert::ToPyObject,
+        function::{ArgBytesLike, FuncArgs, KwArgs, OptionalArg, OptionalOption},
+        protocol::{PyBuffer, PyIter, PyIterReturn},
+        types::{AsBuffer, Constructor, DefaultConstructor, Initializer},
+        AsObject, Py, PyObjectRef, PyPayload, PyRef, PyResult, TryFromBorrowedObject,
+        TryFromObject, VirtualMachine,
+    };
+    use malachite_bigint::BigInt;
+    use num_traits::{ToPrimitive, Zero};
+    use std::collections::HashMap;
+
+    #[pyattr]
+    const HIGHEST_PROTOCOL: u8 = 5;
+    #[pyattr]
+    const DEFAULT_PROTOCOL: u8 = 4;
+
+    mod opcode {
+        pub const MARK: u8 = b'(';
+        pub const STOP: u8 = b'.';
+        pub const POP: u8 = b'0';
+        pub const POP_MARK: u8 = b'1';
+        pub const DUP: u8 = b'2';
+        pub const FLOAT: u8 = b'F';
+        pub const INT: u8 = b'I';
+        pub const BININT: u8 = b'J';
+        pub const BININT1: u8 = b'K';
+        pub const LONG: u8 = b'L';
+        pub const BININT2: u8 = b'M';
+        pub const NONE: u8 = b'N';
+        pub const PERSID: u8 = b'P';
+        pub const BINPERSID: u8 = b'Q';
+        pub const REDUCE: u8 = b'R';
+        pub const STRING: u8 = b'S';
+        pub const BINSTRING: u8 = b'T';
+        pub const SHORT_BINSTRING: u8 = b'U';
+        pub const UNICODE: u8 = b'V';
+        pub const BINUNICODE: u8 = b'X';
+        pub const APPEND: u8 = b'a';
+        pub const BUILD: u8 = b'b';
+        pub const GLOBAL: u8 = b'c';
+        pub const DICT: u8 = b'd';
+        pub const EMPTY_DICT: u8 = b'}';
+        pub const APPENDS: u8 = b'e';
+        pub const GET: u8 = b'g';
+        pub const BINGET: u8 = b'h';
+        pub const INST: u8 = b'i';
+        pub const LONG_BINGET: u8 = b'j';
+        pub const LIST: u8 = b'l';
+        pub const EMPTY_LIST: u8 = b']';
+        pub const OBJ: u8 = b'o';
+        pub const PUT: u8 = b'p';
+        pub const BINPUT: u8 = b'q';
+        pub const LONG_BINPUT: u8 = b'r';
+        pub const SETITEM: u8 = b's';
+        pub const TUPLE: u8 = b't';
+        pub const EMPTY_TUPLE: u8 = b')';
+        pub const SETITEMS: u8 = b'u';
+        pub const BINFLOAT: u8 = b'G';
+        // protocol 2
+        pub const PROTO: u8 = 0x80;
+        pub const NEWOBJ: u8 = 0x81;
+        pub const EXT1: u8 = 0x82;
+        pub const EXT2: u8 = 0x83;
+        pub const EXT4: u8 = 0x84;
+        pub const TUPLE1: u8 = 0x85;
+        pub const TUPLE2: u8 = 0x86;
+        pub const TUPLE3: u8 = 0x87;
+        pub const NEWTRUE: u8 = 0x88;
+        pub const NEWFALSE: u8 = 0x89;
+        pub const LONG1: u8 = 0x8a;
+        pub const LONG4: u8 = 0x8b;
+        // protocol 3
+        pub const BINBYTES: u8 = b'B';
+        pub const SHORT_BINBYTES: u8 = b'C';
+        // protocol 4
+        pub const SHORT_BINUNICODE: u8 = 0x8c;
+        pub const BINUNICODE8: u8 = 0x8d;
+        pub const BINBYTES8: u8 = 0x8e;
+        pub const EMPTY_SET: u8 = 0x8f;
+        pub const ADDITEMS: u8 = 0x90;
+        pub const FROZENSET: u8 = 0x91;
+        pub const NEWOBJ_EX: u8 = 0x92;
+        pub const STACK_GLOBAL: u8 = 0x93;
+        pub const MEMOIZE: u8 = 0x94;
+        pub const FRAME: u8 = 0x95;
+        // protocol 5
+        pub const BYTEARRAY8: u8 = 0x96;
+        pub const NEXT_BUFFER: u8 = 0x97;
+        pub const READONLY_BUFFER: u8 = 0x98;
+    }
+
+    /// How many items `APPENDS`, `SETITEMS` and `ADDITEMS` take at most.
+    const BATCH_SIZE: usize = 1000;
+    const FRAME_SIZE_MIN: usize = 4;
+    const FRAME_SIZE_TARGET: usize = 64 * 1024;
+    const FRAME_HEADER_SIZE: usize = 9;
+
+    #[pyattr(name = "PickleError", once)]
+    fn pickle_error(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx.new_exception_type(
+            "_pickle",
+            "PickleError",
+            Some(vec![vm.ctx.exceptions.exception_type.to_owned()]),
+        )
+    }
+
+    #[pyattr(name = "PicklingError", once)]
+    fn pickling_error(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("_pickle", "PicklingError", Some(vec![pickle_error(vm)]))
+    }
+
+    #[pyattr(name = "UnpicklingError", once)]
+    fn unpickling_error(vm: &VirtualMachine) -> PyTypeRef {
+        vm.ctx
+            .new_exception_type("_pickle", "UnpicklingError", Some(vec![pickle_error(vm)]))
+    }
+
+    fn new_pickling_error(msg: String, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        vm.new_exception_msg(pickling_error(vm), msg)
+    }
+
+    fn new_unpickling_error(msg: String, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        vm.new_exception_msg(unpickling_error(vm), msg)
+    }
+
+    /// Convert the items of a 2-tuple.
+    fn unpack_pair<A: TryFromObject, B: TryFromObject>(
+        obj: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<(A, B)> {
+        let tuple = PyTupleRef::try_from_object(vm, obj)?;
+        match tuple.as_slice() {
+            [a, b] => Ok((
+                A::try_from_object(vm, a.clone())?,
+                B::try_from_object(vm, b.clone())?,
+            )),
+            _ => Err(vm.new_type_error(format!(
+                "expected a tuple of length 2, not {}",
+                tuple.len()
+            ))),
+        }
+    }
+
+    /// Resolve a dotted `name` inside `obj`, returning the attribute and its parent.
+    fn get_dotted_attr(
+        obj: PyObjectRef,
+        name: &str,
+        vm: &VirtualMachine,
+    ) -> PyResult<(PyObjectRef, PyObjectRef)> {
+        let mut parent = obj.clone();
+        let mut obj = obj;
+        for subpath in name.split('.') {
+            if subpath == "<locals>" {
+                return Err(vm.new_attribute_error(format!(
+                    "Can't get local attribute {name:?} on {}",
+                    obj.repr(vm)?
+                )));
+            }
+            parent = obj;
+            obj = parent.get_attr(&vm.ctx.new_str(subpath), vm)?;
+        }
+        Ok((obj, parent))
+    }
+
+    /// Import `name` and return the module itself rather than its top-level package.
+    fn import_module(name: &PyStrRef, vm: &VirtualMachine) -> PyResult {
+        vm.import(name, 0)?;
+        vm.sys_module
+            .get_attr("modules", vm)?
+            .get_item(name.as_object(), vm)
+    }
+
+    /// `pickle.whichmodule`: the name of the module `obj` can be imported from as `name`.
+    fn which_module(obj: &PyObjectRef, name: &str, vm: &VirtualMachine) -> PyResult {
+        if let Some(module_name) = vm.get_attribute_opt(obj.clone(), "__module__")? {
+            if !vm.is_none(&module_name) {
+                return Ok(module_name);
+            }
+        }
+        let modules = vm.sys_module.get_attr("modules", vm)?;
+        let modules = vm.call_method(&modules, "copy", ())?;
+        let modules = PyDictRef::try_from_object(vm, modules)?;
+        for (module_name, module) in &modules {
+            let skip = vm.is_none(&module)
+                || module_name.downcast_ref::<PyStr>().map_or(true, |name| {
+                    matches!(name.as_str(), "__main__" | "__mp_main__")
+                });
+            if skip {
+                continue;
+            }
+            if let Ok((found, _)) = get_dotted_attr(module, name, vm) {
+                if found.is(obj) {
+                    return Ok(module_name);
+                }
+            }
+        }
+        Ok(vm.ctx.new_str("__main__").into())
+    }
+
+    fn kwargs_from_dict(kwargs: PyObjectRef, vm: &VirtualMachine) -> PyResult<KwArgs> {
+        let kwargs = PyDictRef::try_from_object(vm, kwargs)?;
+        kwargs
+            .into_iter()
+            .map(|(key, value)| {
+                let key = PyStrRef::try_from_object(vm, key)?;
+                Ok((key.as_str().to_owned(), value))
+            })
+            .collect()
+    }
+
+    /// Two's complement little-endian encoding of `value` as `LONG1`/`LONG4` use it.
+    fn encode_long(value: &BigInt) -> Vec<u8> {
+        if value.is_zero() {
+            Vec::new()
+        } else {
+            value.to_signed_bytes_le()
+        }
+    }
+
+    fn parse_int(line: &[u8], vm: &VirtualMachine) -> PyResult<BigInt> {
+        std::str::from_utf8(line)
+            .ok()
+            .and_then(|s| s.trim().parse::<BigInt>().ok())
+            .ok_or_else(|| {
+                vm.new_value_error(format!(
+                    "invalid literal for int() with base 10: {:?}",
+                    String::from_utf8_lossy(line)
+                ))
+            })
+    }
+
+    /// The argument of a protocol 0 `UNICODE` opcode.
+    fn raw_unicode_escape(s: &str) -> Vec<u8> {
+        let mut out = Vec::with_capacity(s.len());
+        for c in s.chars() {
+            let code = c as u32;
+            match c {
+                '\\' | '\0' | '\n' | '\r' | '\x1a' => {
+                    out.extend_from_slice(format!("\\u{code:04x}").as_bytes())
+                }
+                _ if code < 0x100 => out.push(code as u8),
+                _ if code < 0x10000 => out.extend_from_slice(format!("\\u{code:04x}").as_bytes()),
+                _ => out.extend_from_slice(format!("\\U{code:08x}").as_bytes()),
+            }
+        }
+        out
+    }
+
+    /// Decode the argument of a protocol 0 `UNICODE` opcode, or None if it has a bad escape or
+    /// a surrogate.
+    fn raw_unicode_unescape(line: &[u8]) -> Option<String> {
+        let mut out = String::with_capacity(line.len());
+        let mut i = 0;
+        while i < line.len() {
+            let width = match line[i..] {
+                [b'\\', b'u', ..] => 4,
+                [b'\\', b'U', ..] => 8,
+                _ => {
+                    out.push(line[i] as char);
+                    i += 1;
+                    continue;
+                }
+            };
+            let digits = line.get(i + 2..i + 2 + width)?;
+            let digits = std::str::from_utf8(digits).ok()?;
+            let code = u32::from_str_radix(digits, 16).ok()?;
+            out.push(char::from_u32(code)?);
+            i += 2 + width;
+        }
+        Some(out)
+    }
+
+    type Memo = HashMap<usize, (u32, PyObjectRef)>;
+
+    #[pyattr]
+    #[pyclass(name = "PickleBuffer")]
+    #[derive(Debug, PyPayload)]
+    struct PyPickleBuffer {
+        view: PyMutex<Option<PyRef<PyMemoryView>>>,
+    }
+
+    impl PyPickleBuffer {
+        fn view(&self, vm: &VirtualMachine) -> PyResult<PyRef<PyMemoryView>> {
+            self.view.lock().clone().ok_or_else(|| {
+                vm.new_value_error("operation forbidden on released PickleBuffer object".to_owned())
+            })
+        }
+    }
+
+    impl Constructor for PyPickleBuffer {
+        type Args = PyObjectRef;
+
+        fn py_new(cls: PyTypeRef, obj: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let view = PyMemoryView::from_object(&obj, vm)?.into_ref(&vm.ctx);
+            PyPickleBuffer {
+                view: PyMutex::new(Some(view)),
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+    }
+
+    impl AsBuffer for PyPickleBuffer {
+        fn as_buffer(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyBuffer> {
+            PyBuffer::try_from_borrowed_object(vm, zelf.view(vm)?.as_object())
+        }
+    }
+
+    #[pyclass(with(Constructor, AsBuffer))]
+    impl PyPickleBuffer {
+        /// A flat, byte-formatted memoryview of the underlying buffer.
+        #[pymethod]
+        fn raw(&self, vm: &VirtualMachine) -> PyResult {
+            let view = self.view(vm)?;
+            let buffer = PyBuffer::try_from_borrowed_object(vm, view.as_object())?;
+            if !buffer.desc.is_contiguous() {
+                return Err(vm.new_buffer_error(
+                    "cannot extract raw buffer from non-contiguous buffer".to_owned(),
+                ));
+            }
+            vm.call_method(view.as_object(), "cast", ("B",))
+        }
+
+        #[pymethod]
+        fn release(&self) {
+            if let Some(view) = self.view.lock().take() {
+                view.release();
+            }
+        }
+    }
+
+    /// The pickle being written, with protocol 4 framing.
+    #[derive(Debug)]
+    struct Output {
+        buf: Vec<u8>,
+        framing: bool,
+        /// Where the header of the frame being written was reserved.
+        frame_start: Option<usize>,
+        file_write: Option<PyObjectRef>,
+    }
+
+    impl Output {
+        fn write(&mut self, data: &[u8]) {
+            if self.framing && self.frame_start.is_none() {
+                self.frame_start = Some(self.buf.len());
+                self.buf.extend_from_slice(&[0; FRAME_HEADER_SIZE]);
+            }
+            self.buf.extend_from_slice(data);
+        }
+
+        fn commit_frame(&mut self, force: bool, vm: &VirtualMachine) -> PyResult<()> {
+            let Some(start) = self.frame_start else {
+                return Ok(());
+            };
+            let size = self.buf.len() - start - FRAME_HEADER_SIZE;
+            if size < FRAME_SIZE_TARGET && !force {
+                return Ok(());
+            }
+            if size >= FRAME_SIZE_MIN {
+                self.buf[start] = opcode::FRAME;
+                self.buf[start + 1..start + FRAME_HEADER_SIZE]
+                    .copy_from_slice(&(size as u64).to_le_bytes());
+            } else {
+                self.buf.drain(start..start + FRAME_HEADER_SIZE);
+            }
+            self.frame_start = None;
+            self.flush(vm)
+        }
+
+        /// Write a big payload outside of any frame, so that the unpickler can read it
+        /// straight into place.
+        fn write_large(
+            &mut self,
+            header: &[u8],
+            payload: &[u8],
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            self.commit_frame(true, vm)?;
+            self.buf.extend_from_slice(header);
+            self.buf.extend_from_slice(payload);
+            self.flush(vm)
+        }
+
+        fn flush(&mut self, vm: &VirtualMachine) -> PyResult<()> {
+            if let Some(write) = &self.file_write {
+                if !self.buf.is_empty() {
+                    let data = vm.ctx.new_bytes(std::mem::take(&mut self.buf));
+                    write.call((data,), vm)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct PicklerConfig {
+        file_write: PyObjectRef,
+        protocol: u8,
+        fix_imports: bool,
+        buffer_callback: Option<PyObjectRef>,
+    }
+
+    #[pyattr]
+    #[pyclass(name = "Pickler")]
+    #[derive(Debug, Default, PyPayload)]
+    struct PyPickler {
+        config: PyMutex<Option<PicklerConfig>>,
+        memo: PyMutex<Memo>,
+        fast: PyMutex<bool>,
+    }
+
+    #[derive(FromArgs)]
+    struct PicklerArgs {
+        #[pyarg(any)]
+        file: PyObjectRef,
+        #[pyarg(any, optional)]
+        protocol: OptionalOption<i32>,
+        #[pyarg(any, default = "true")]
+        fix_imports: bool,
+        #[pyarg(any, optional)]
+        buffer_callback: OptionalOption<PyObjectRef>,
+    }
+
+    fn check_protocol(protocol: OptionalOption<i32>, vm: &VirtualMachine) -> PyResult<u8> {
+        match protocol.flatten() {
+            None => Ok(DEFAULT_PROTOCOL),
+            Some(protocol) if protocol < 0 => Ok(HIGHEST_PROTOCOL),
+            Some(protocol) if protocol <= HIGHEST_PROTOCOL as i32 => Ok(protocol as u8),
+            Some(_) => {
+                Err(vm.new_value_error(format!("pickle protocol must be <= {HIGHEST_PROTOCOL}")))
+            }
+        }
+    }
+
+    impl PicklerConfig {
+        fn new(args: PicklerArgs, vm: &VirtualMachine) -> PyResult<Self> {
+            let protocol = check_protocol(args.protocol, vm)?;
+            let buffer_callback = args
+                .buffer_callback
+                .flatten()
+                .filter(|callback| !vm.is_none(callback));
+            if buffer_callback.is_some() && protocol < 5 {
+                return Err(vm.new_value_error("buffer_callback needs protocol >= 5".to_owned()));
+            }
+            let file_write = vm.get_attribute_opt(args.file, "write")?.ok_or_else(|| {
+                vm.new_type_error("file must have a 'write' attribute".to_owned())
+            })?;
+            Ok(PicklerConfig {
+                file_write,
+                protocol,
+                fix_imports: args.fix_imports,
+                buffer_callback,
+            })
+        }
+    }
+
+    impl DefaultConstructor for PyPickler {}
+
+    impl Initializer for PyPickler {
+        type Args = PicklerArgs;
+
+        fn init(zelf: PyRef<Self>, args: Self::Args, vm: &VirtualMachine) -> PyResult<()> {
+            let config = PicklerConfig::new(args, vm)?;
+            *zelf.config.lock() = Some(config);
+            zelf.memo.lock().clear();
+            Ok(())
+        }
+    }
+
+    #[pyclass(with(DefaultConstructor, Initializer), flags(BASETYPE, HAS_DICT))]
+    impl PyPickler {
+        #[pymethod]
+        fn dump(zelf: &Py<Self>, obj: PyObjectRef, vm: &VirtualMachine) -> PyResult<()> {
+            let (file_write, protocol, fix_imports, buffer_callback) = {
+                let config = zelf.config.lock();
+                let Some(config) = &*config else {
+                    return Err(new_pickling_error(
+                        format!(
+                            "Pickler.__init__() was not called by {}.__init__()",
+                            zelf.class().name()
+                        ),
+                        vm,
+                    ));
+                };
+                (
+                    config.file_write.clone(),
+                    config.protocol,
+                    config.fix_imports,
+                    config.buffer_callback.clone(),
+                )
+            };
+            let memo = std::mem::take(&mut *zelf.memo.lock());
+            let mut pickler = Pickler {
+                vm,
+                protocol,
+                fix_imports,
+                fast: *zelf.fast.lock(),
+                buffer_callback,
+                persistent_id: vm.get_attribute_opt(zelf.to_owned().into(), "persistent_id")?,
+                reducer_override: vm
+                    .get_attribute_opt(zelf.to_owned().into(), "reducer_override")?,
+                dispatch_table: vm.get_attribute_opt(zelf.to_owned().into(), "dispatch_table")?,
+                memo,
+                output: Output {
+                    buf: Vec::new(),
+                    framing: false,
+                    frame_start: None,
+                    file_write: Some(file_write),
+                },
+            };
+            let result = pickler.dump(obj);
+            *zelf.memo.lock() = pickler.memo;
+            result
+        }
+
+        #[pymethod]
+        fn clear_memo(&self) {
+            self.memo.lock().clear();
+        }
+
+        #[pygetset]
+        fn memo(&self, vm: &VirtualMachine) -> PyDictRef {
+            let dict = vm.ctx.new_dict();
+            for (&id, (index, obj)) in self.memo.lock().iter() {
+                let entry = (*index, obj.clone()).to_pyobject(vm);
+                dict.set_item(&*id.to_pyobject(vm), entry, vm).unwrap();
+            }
+            dict
+        }
+
+        #[pygetset(setter)]
+        fn set_memo(&self, memo: PyDictRef, vm: &VirtualMachine) -> PyResult<()> {
+            let mut new_memo = Memo::new();
+            for (id, entry) in &memo {
+                let id = usize::try_from_object(vm, id)?;
+                let (index, obj) = unpack_pair::<u32, PyObjectRef>(entry, vm)?;
+                new_memo.insert(id, (index, obj));
+            }
+            *self.memo.lock() = new_memo;
+            Ok(())
+        }
+
+        #[pygetset]
+        fn bin(&self) -> bool {
+            self.config
+                .lock()
+                .as_ref()
+                .map_or(false, |config| config.protocol >= 1)
+        }
+
+        #[pygetset]
+        fn fast(&self) -> bool {
+            *self.fast.lock()
+        }
+
+        #[pygetset(setter)]
+        fn set_fast(&self, value: bool) {
+            *self.fast.lock() = value;
+        }
+    }
+
+    /// The state of a single `dump()`.
+    struct Pickler<'vm> {
+        vm: &'vm VirtualMachine,
+        protocol: u8,
+        fix_imports: bool,
+        /// Don't memoize, which saves time and space but can't handle recursive objects.
+        fast: bool,
+        buffer_callback: Option<PyObjectRef>,
+        persistent_id: Option<PyObjectRef>,
+        reducer_override: Option<PyObjectRef>,
+        dispatch_table: Option<PyObjectRef>,
+        memo: Memo,
+        output: Output,
+    }
+
+    impl Pickler<'_> {
+        fn dump(&mut self, obj: PyObjectRef) -> PyResult<()> {
+            if self.protocol >= 2 {
+                self.write(&[opcode::PROTO, self.protocol]);
+            }
+            if self.protocol >= 4 {
+                self.output.framing = true;
+            }
+            self.save(&obj, true)?;
+            self.write(&[opcode::STOP]);
+            self.output.commit_frame(true, self.vm)?;
+            self.output.framing = false;
+            self.output.flush(self.vm)
+        }
+
+        fn bin(&self) -> bool {
+            self.protocol >= 1
+        }
+
+        fn write(&mut self, data: &[u8]) {
+            self.output.write(data)
+        }
+
+        fn write_line(&mut self, op: u8, arg: &[u8]) {
+            self.write(&[op]);
+            self.write(arg);
+            self.write(b"\n");
+        }
+
+        fn memoize(&mut self, obj: &PyObjectRef) {
+            if self.fast {
+                return;
+            }
+            let index = self.memo.len() as u32;
+            if self.protocol >= 4 {
+                self.write(&[opcode::MEMOIZE]);
+            } else if self.bin() {
+                match u8::try_from(index) {
+                    Ok(index) => self.write(&[opcode::BINPUT, index]),
+                    Err(_) => {
+                        self.write(&[opcode::LONG_BINPUT]);
+                        self.write(&index.to_le_bytes());
+                    }
+                }
+            } else {
+                self.write_line(opcode::PUT, index.to_string().as_bytes());
+            }
+            self.memo.insert(obj.get_id(), (index, obj.clone()));
+        }
+
+        fn memo_get(&self, obj: &PyObjectRef) -> Option<u32> {
+            self.memo.get(&obj.get_id()).map(|&(index, _)| index)
+        }
+
+        fn write_get(&mut self, index: u32) {
+            if self.bin() {
+                match u8::try_from(index) {
+                    Ok(index) => self.write(&[opcode::BINGET, index]),
+                    Err(_) => {
+                        self.write(&[opcode::LONG_BINGET]);
+                        self.write(&index.to_le_bytes());
+                    }
+                }
+            } else {
+                self.write_line(opcode::GET, index.to_string().as_bytes());
+            }
+        }
+
+        fn save(&mut self, obj: &PyObjectRef, save_persistent_id: bool) -> PyResult<()> {
+            let vm = self.vm;
+            self.output.commit_frame(false, vm)?;
+
+            if save_persistent_id {
+                if let Some(persistent_id) = &self.persistent_id {
+                    let pid = persistent_id.call((obj.clone(),), vm)?;
+                    if !vm.is_none(&pid) {
+                        return self.save_pers(&pid);
+                    }
+                }
+            }
+
+            // atoms aren't memoized
+            if vm.is_none(obj) {
+                self.write(&[opcode::NONE]);
+                return Ok(());
+            }
+            if obj.class().is(vm.ctx.types.bool_type) {
+                let value = obj.is(&vm.ctx.true_value);
+                if self.protocol >= 2 {
+                    self.write(&[if value {
+                        opcode::NEWTRUE
+                    } else {
+                        opcode::NEWFALSE
+                    }]);
+                } else {
+                    self.write(if value { b"I01\n" } else { b"I00\n" });
+                }
+                return Ok(());
+            }
+            if let Some(int) = obj.payload_if_exact::<PyInt>(vm) {
+                self.save_int(int.as_bigint());
+                return Ok(());
+            }
+            if let Some(float) = obj.payload_if_exact::<PyFloat>(vm) {
+                if self.bin() {
+                    self.write(&[opcode::BINFLOAT]);
+                    self.write(&float.to_f64().to_be_bytes());
+                } else {
+                    let repr = obj.repr(vm)?;
+                    self.write_line(opcode::FLOAT, repr.as_str().as_bytes());
+                }
+                return Ok(());
+            }
+
+            if let Some(index) = self.memo_get(obj) {
+                self.write_get(index);
+                return Ok(());
+            }
+
+            if let Some(bytes) = obj.payload_if_exact::<PyBytes>(vm) {
+                return self.save_bytes(obj, bytes.as_bytes());
+            }
+            if let Some(s) = obj.payload_if_exact::<PyStr>(vm) {
+                self.save_str(s.as_str())?;
+                self.memoize(obj);
+                return Ok(());
+            }
+
+            let reduced = match &self.reducer_override {
+                Some(reducer_override) => {
+                    let rv = reducer_override.call((obj.clone(),), vm)?;
+                    (!rv.is(&vm.ctx.not_implemented)).then_some(rv)
+                }
+                None => None,
+            };
+            let rv = match reduced {
+                Some(rv) => rv,
+                None => {
+                    if self.save_builtin(obj)? {
+                        return Ok(());
+                    }
+                    match self.reduce(obj)? {
+                        Some(rv) => rv,
+                        None => return Ok(()),
+                    }
+                }
+            };
+
+            if let Some(name) = rv.downcast_ref::<PyStr>() {
+                return self.save_global(obj, Some(name.as_str()));
+            }
+            let Some(rv) = rv.downcast_ref::<PyTuple>() else {
+                return Err(new_pickling_error(
+                    "__reduce__ must return a string or tuple".to_owned(),
+                    vm,
+                ));
+            };
+            let rv = rv.as_slice();
+            if !(2..=6).contains(&rv.len()) {
+                return Err(new_pickling_error(
+                    "tuple returned by __reduce__ must contain 2 through 6 elements".to_owned(),
+                    vm,
+                ));
+            }
+            let opt = |i: usize| rv.get(i).filter(|x| !vm.is_none(x)).cloned();
+            self.save_reduce(&rv[0], &rv[1], opt(2), opt(3), opt(4), opt(5), Some(obj))
+        }
+
+        /// Save the containers and other builtins pickle has opcodes for; `false` when `obj`
+        /// isn't one of them.
+        fn save_builtin(&mut self, obj: &PyObjectRef) -> PyResult<bool> {
+            let vm = self.vm;
+            if let Some(dict) = obj.downcast_ref_if_exact::<PyDict>(vm) {
+                if self.bin() {
+                    self.write(&[opcode::EMPTY_DICT]);
+                } else {
+                    self.write(&[opcode::MARK, opcode::DICT]);
+                }
+                self.memoize(obj);
+                let items: Vec<_> = dict.into_iter().collect();
+                vm.with_recursion("while pickling an object", || {
+                    self.batch_setitems(items.into_iter().map(Ok))
+                })?;
+            } else if let Some(list) = obj.downcast_ref_if_exact::<PyList>(vm) {
+                if self.bin() {
+                    self.write(&[opcode::EMPTY_LIST]);
+                } else {
+                    self.write(&[opcode::MARK, opcode::LIST]);
+                }
+                self.memoize(obj);
+                let items = list.borrow_vec().to_vec();
+                vm.with_recursion("while pickling an object", || {
+                    self.batch_appends(items.into_iter().map(Ok))
+                })?;
+            } else if let Some(tuple) = obj.downcast_ref_if_exact::<PyTuple>(vm) {
+                vm.with_recursion("while pickling an object", || {
+                    self.save_tuple(obj, tuple.as_slice())
+                })?;
+            } else if let Some(set) = obj.payload_if_exact::<PySet>(vm) {
+                self.save_set(obj, set.elements())?;
+            } else if let Some(set) = obj.payload_if_exact::<PyFrozenSet>(vm) {
+                self.save_frozenset(obj, set.elements())?;
+            } else if let Some(bytearray) = obj.payload_if_exact::<PyByteArray>(vm) {
+                let data = bytearray.borrow_buf().to_vec();
+                self.save_bytearray(obj, data)?;
+            } else if obj.payload_is::<PyPickleBuffer>() {
+                self.save_picklebuffer(obj)?;
+            } else if obj.class().is(vm.ctx.types.type_type) {
+                self.save_type(obj)?;
+            } else if obj.class().is(vm.ctx.types.function_type) {
+                self.save_global(obj, None)?;
+            } else {
+                return Ok(false);
+            }
+            Ok(true)
+        }
+
+        /// Get the reduction of `obj` from the dispatch table or its `__reduce_ex__`; `None`
+        /// when it was saved as a global instead.
+        fn reduce(&mut self, obj: &PyObjectRef) -> PyResult<Option<PyObjectRef>> {
+            let vm = self.vm;
+            let cls: PyObjectRef = obj.class().to_owned().into();
+            let dispatch_table = match &self.dispatch_table {
+                Some(table) => table.clone(),
+                None => vm.import("copyreg", 0)?.get_attr("dispatch_table", vm)?,
+            };
+            let reducer = match dispatch_table.get_item(cls.as_object(), vm) {
+                Ok(reducer) => Some(reducer),
+                Err(e) if e.fast_isinstance(vm.ctx.exceptions.key_error) => None,
+                Err(e) => return Err(e),
+            };
+            if let Some(reducer) = reducer {
+                return reducer.call((obj.clone(),), vm).map(Some);
+            }
+            if obj.class().fast_issubclass(vm.ctx.types.type_type) {
+                self.save_global(obj, None)?;
+                return Ok(None);
+            }
+            match vm.get_attribute_opt(obj.clone(), "__reduce_ex__")? {
+                Some(reduce_ex) => reduce_ex.call((self.protocol,), vm).map(Some),
+                None => vm.call_method(obj, "__reduce__", ()).map(Some),
+            }
+        }
+
+        fn save_pers(&mut self, pid: &PyObjectRef) -> PyResult<()> {
+            if self.bin() {
+                self.save(pid, false)?;
+                self.write(&[opcode::BINPERSID]);
+            } else {
+                let pid = pid.str(self.vm)?;
+                if !pid.as_str().is_ascii() {
+                    return Err(new_pickling_error(
+                        "persistent IDs in protocol 0 must be ASCII strings".to_owned(),
+                        self.vm,
+                    ));
+                }
+                self.write_line(opcode::PERSID, pid.as_str().as_bytes());
+            }
+            Ok(())
+        }
+
+        fn save_int(&mut self, value: &BigInt) {
+            if self.bin() {
+                if let Some(value) = value.to_i32() {
+                    match value {
+                        0..=0xff => self.write(&[opcode::BININT1, value as u8]),
+                        0x100..=0xffff => {
+                            self.write(&[opcode::BININT2]);
+                            self.write(&(value as u16).to_le_bytes());
+                        }
+                        _ => {
+                            self.write(&[opcode::BININT]);
+                            self.write(&value.to_le_bytes());
+                        }
+                    }
+                    return;
+                }
+            }
+            if self.protocol >= 2 {
+                let encoded = encode_long(value);
+                match u8::try_from(encoded.len()) {
+                    Ok(len) => self.write(&[opcode::LONG1, len]),
+                    Err(_) => {
+                        self.write(&[opcode::LONG4]);
+                        self.write(&(encoded.len() as i32).to_le_bytes());
+                    }
+                }
+                self.write(&encoded);
+            } else if value.to_i32().is_some() {
+                self.write_line(opcode::INT, value.to_string().as_bytes());
+            } else {
+                self.write_line(opcode::LONG, format!("{value}L").as_bytes());
+            }
+        }
+
+        fn save_bytes(&mut self, obj: &PyObjectRef, data: &[u8]) -> PyResult<()> {
+            let vm = self.vm;
+            if self.protocol < 3 {
+                // bytes didn't exist yet: go through codecs.encode(latin-1 str)
+                let bytes_type: PyObjectRef = vm.ctx.types.bytes_type.to_owned().into();
+                if data.is_empty() {
+                    let args = vm.ctx.empty_tuple.clone().into();
+                    return self.save_reduce(&bytes_type, &args, None, None, None, None, Some(obj));
+                }
+                let encode = vm.import("codecs", 0)?.get_attr("encode", vm)?;
+                let latin1: String = data.iter().map(|&b| b as char).collect();
+                let args = (latin1, "latin1").to_pyobject(vm);
+                return self.save_reduce(&encode, &args, None, None, None, None, Some(obj));
+            }
+            self.save_bytes_data(data)?;
+            self.memoize(obj);
+            Ok(())
+        }
+
+        fn save_bytes_data(&mut self, data: &[u8]) -> PyResult<()> {
+            let len = data.len();
+            let mut header = Vec::with_capacity(9);
+            if let Ok(len) = u8::try_from(len) {
+                header.extend_from_slice(&[opcode::SHORT_BINBYTES, len]);
+            } else if let Ok(len) = u32::try_from(len) {
+                header.push(opcode::BINBYTES);
+                header.extend_from_slice(&len.to_le_bytes());
+            } else if self.protocol >= 4 {
+                header.push(opcode::BINBYTES8);
+                header.extend_from_slice(&(len as u64).to_le_bytes());
+            } else {
+                return Err(self
+                    .vm
+                    .new_overflow_error("serializing a bytes object larger than 4 GiB requires pickle protocol 4 or higher".to_owned()));
+            }
+            self.write_with_header(&header, data)
+        }
+
+        fn write_with_header(&mut self, header: &[u8], payload: &[u8]) -> PyResult<()> {
+            if payload.len() >= FRAME_SIZE_TARGET && self.output.framing {
+                self.output.write_large(header, payload, self.vm)
+            } else {
+                self.write(header);
+                self.write(payload);
+                Ok(())
+            }
+        }
+
+        fn save_bytearray(&mut self, obj: &PyObjectRef, data: Vec<u8>) -> PyResult<()> {
+            let vm = self.vm;
+            if self.protocol < 5 {
+                let bytearray_type: PyObjectRef = vm.ctx.types.bytearray_type.to_owned().into();
+                let args = if data.is_empty() {
+                    vm.ctx.empty_tuple.clone().into()
+                } else {
+                    (vm.ctx.new_bytes(data),).to_pyobject(vm)
+                };
+                return self.save_reduce(&bytearray_type, &args, None, None, None, None, Some(obj));
+            }
+            self.save_bytearray_data(&data)?;
+            self.memoize(obj);
+            Ok(())
+        }
+
+        fn save_bytearray_data(&mut self, data: &[u8]) -> PyResult<()> {
+            let mut header = vec![opcode::BYTEARRAY8];
+            header.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            self.write_with_header(&header, data)
+        }
+
+        fn save_picklebuffer(&mut self, obj: &PyObjectRef) -> PyResult<()> {
+            let vm = self.vm;
+            if self.protocol < 5 {
+                return Err(new_pickling_error(
+                    "PickleBuffer can only pickled with protocol >= 5".to_owned(),
+                    vm,
+                ));
+            }
+            let buffer = PyBuffer::try_from_borrowed_object(vm, obj)?;
+            if !buffer.desc.is_contiguous() {
+                return Err(new_pickling_error(
+                    "PickleBuffer can not be pickled when pointing to a non-contiguous buffer"
+                        .to_owned(),
+                    vm,
+                ));
+            }
+            let in_band = match &self.buffer_callback {
+                Some(callback) => callback.call((obj.clone(),), vm)?.try_to_bool(vm)?,
+                None => true,
+            };
+            if in_band {
+                let data = buffer.contiguous_or_collect(|data| data.to_vec());
+                if buffer.desc.readonly {
+                    self.save_bytes_data(&data)?;
+                } else {
+                    self.save_bytearray_data(&data)?;
+                }
+                self.memoize(obj);
+                Ok(())
+            } else {
+                self.write(&[opcode::NEXT_BUFFER]);
+                if buffer.desc.readonly {
+                    self.write(&[opcode::READONLY_BUFFER]);
+                }
+                Ok(())
+            }
+        }
+
+        fn save_str(&mut self, s: &str) -> PyResult<()> {
+            if !self.bin() {
+                self.write_line(opcode::UNICODE, &raw_unicode_escape(s));
+                return Ok(());
+            }
+            let len = s.len();
+            let mut header = Vec::with_capacity(9);
+            match u8::try_from(len) {
+                Ok(len) if self.protocol >= 4 => {
+                    header.extend_from_slice(&[opcode::SHORT_BINUNICODE, len])
+                }
+                _ if len <= u32::MAX as usize => {
+                    header.push(opcode::BINUNICODE);
+                    header.extend_from_slice(&(len as u32).to_le_bytes());
+                }
+                _ if self.protocol >= 4 => {
+                    header.push(opcode::BINUNICODE8);
+                    header.extend_from_slice(&(len as u64).to_le_bytes());
+                }
+                _ => return Err(self.vm.new_overflow_error(
+                    "serializing a string larger than 4 GiB requires pickle protocol 4 or higher"
+                        .to_owned(),
+                )),
+            }
+            self.write_with_header(&header, s.as_bytes())
+        }
+
+        fn save_tuple(&mut self, obj: &PyObjectRef, items: &[PyObjectRef]) -> PyResult<()> {
+            if items.is_empty() {
+                if self.bin() {
+                    self.write(&[opcode::EMPTY_TUPLE]);
+                } else {
+                    self.write(&[opcode::MARK, opcode::TUPLE]);
+                }
+                return Ok(());
+            }
+            let n = items.len();
+            if n <= 3 && self.protocol >= 2 {
+                for item in items {
+                    self.save(item, true)?;
+                }
+                // a recursive tuple got memoized while saving its items: use that one
+                if let Some(index) = self.memo_get(obj) {
+                    self.write(&vec![opcode::POP; n]);
+                    self.write_get(index);
+                } else {
+                    self.write(&[[opcode::TUPLE1, opcode::TUPLE2, opcode::TUPLE3][n - 1]]);
+                    self.memoize(obj);
+                }
+                return Ok(());
+            }
+            self.write(&[opcode::MARK]);
+            for item in items {
+                self.save(item, true)?;
+            }
+            if let Some(index) = self.memo_get(obj) {
+                if self.bin() {
+                    self.write(&[opcode::POP_MARK]);
+                } else {
+                    self.write(&vec![opcode::POP; n + 1]);
+                }
+                self.write_get(index);
+            } else {
+                self.write(&[opcode::TUPLE]);
+                self.memoize(obj);
+            }
+            Ok(())
+        }
+
+        fn save_set(&mut self, obj: &PyObjectRef, items: Vec<PyObjectRef>) -> PyResult<()> {
+            let vm = self.vm;
+            if self.protocol < 4 {
+                let set_type: PyObjectRef = vm.ctx.types.set_type.to_owned().into();
+                let args = (vm.ctx.new_list(items),).to_pyobject(vm);
+                return self.save_reduce(&set_type, &args, None, None, None, None, Some(obj));
+            }
+            self.write(&[opcode::EMPTY_SET]);
+            self.memoize(obj);
+            for batch in items.chunks(BATCH_SIZE) {
+                self.write(&[opcode::MARK]);
+                for item in batch {
+                    self.save(item, true)?;
+                }
+                self.write(&[opcode::ADDITEMS]);
+            }
+            Ok(())
+        }
+
+        fn save_frozenset(&mut self, obj: &PyObjectRef, items: Vec<PyObjectRef>) -> PyResult<()> {
+            let vm = self.vm;
+            if self.protocol < 4 {
+                let frozenset_type: PyObjectRef = vm.ctx.types.frozenset_type.to_owned().into();
+                let args = (vm.ctx.new_list(items),).to_pyobject(vm);
+                return self.save_reduce(&frozenset_type, &args, None, None, None, None, Some(obj));
+            }
+            self.write(&[opcode::MARK]);
+            for item in &items {
+                self.save(item, true)?;
+            }
+            if let Some(index) = self.memo_get(obj) {
+                self.write(&[opcode::POP_MARK]);
+                self.write_get(index);
+            } else {
+                self.write(&[opcode::FROZENSET]);
+                self.memoize(obj);
+            }
+            Ok(())
+        }
+
+        fn batch_appends(
+            &mut self,
+            items: impl Iterator<Item = PyResult<PyObjectRef>>,
+        ) -> PyResult<()> {
+            if !self.bin() {
+                for item in items {
+                    self.save(&item?, true)?;
+                    self.write(&[opcode::APPEND]);
+                }
+                return Ok(());
+            }
+            let mut items = items.peekable();
+            while items.peek().is_some() {
+                let batch = items
+                    .by_ref()
+                    .take(BATCH_SIZE)
+                    .collect::<PyResult<Vec<_>>>()?;
+                if let [item] = &batch[..] {
+                    self.save(item, true)?;
+                    self.write(&[opcode::APPEND]);
+                } else {
+                    self.write(&[opcode::MARK]);
+                    for item in &batch {
+                        self.save(item, true)?;
+                    }
+                    self.write(&[opcode::APPENDS]);
+                }
+            }
+            Ok(())
+        }
+
+        fn batch_setitems(
+            &mut self,
+            items: impl Iterator<Item = PyResult<(PyObjectRef, PyObjectRef)>>,
+        ) -> PyResult<()> {
+            if !self.bin() {
+                for item in items {
+                    let (key, value) = item?;
+                    self.save(&key, true)?;
+                    self.save(&value, true)?;
+                    self.write(&[opcode::SETITEM]);
+                }
+                return Ok(());
+            }
+            let mut items = items.peekable();
+            while items.peek().is_some() {
+                let batch = items
+                    .by_ref()
+                    .take(BATCH_SIZE)
+                    .collect::<PyResult<Vec<_>>>()?;
+                if let [(key, value)] = &batch[..] {
+                    self.save(key, true)?;
+                    self.save(value, true)?;
+                    self.write(&[opcode::SETITEM]);
+                } else {
+                    self.write(&[opcode::MARK]);
+                    for (key, value) in &batch {
+                        self.save(key, true)?;
+                        self.save(value, true)?;
+                    }
+                    self.write(&[opcode::SETITEMS]);
+                }
+            }
+            Ok(())
+        }
+
+        fn save_type(&mut self, obj: &PyObjectRef) -> PyResult<()> {
+            let vm = self.vm;
+            // the types of the singletons aren't reachable by name
+            let singleton = if obj.is(vm.ctx.types.none_type) {
+                Some(vm.ctx.none())
+            } else if obj.is(vm.ctx.types.not_implemented_type) {
+                Some(vm.ctx.not_implemented())
+            } else if obj.is(vm.ctx.types.ellipsis_type) {
+                Some(vm.ctx.ellipsis())
+            } else {
+                None
+            };
+            match singleton {
+                Some(singleton) => {
+                    let type_type: PyObjectRef = vm.ctx.types.type_type.to_owned().into();
+                    let args = (singleton,).to_pyobject(vm);
+                    self.save_reduce(&type_type, &args, None, None, None, None, Some(obj))
+                }
+                None => self.save_global(obj, None),
+            }
+        }
+
+        fn save_global(&mut self, obj: &PyObjectRef, name: Option<&str>) -> PyResult<()> {
+            let vm = self.vm;
+            let name: PyStrRef = match name {
+                Some(name) => vm.ctx.new_str(name),
+                None => match vm.get_attribute_opt(obj.clone(), "__qualname__")? {
+                    Some(name) => name.try_into_value(vm)?,
+                    None => obj.get_attr("__name__", vm)?.try_into_value(vm)?,
+                },
+            };
+            let module_name = which_module(obj, name.as_str(), vm)?;
+            let module_name: PyStrRef = module_name.try_into_value(vm)?;
+            let not_found = || {
+                new_pickling_error(
+                    format!(
+                        "Can't pickle {}: it's not found as {}.{}",
+                        obj.repr(vm)
+                            .map_or_else(|_| "?".to_owned(), |r| r.to_string()),
+                        module_name,
+                        name
+                    ),
+                    vm,
+                )
+            };
+            let (module, obj2, parent) = import_module(&module_name, vm)
+                .and_then(|module| {
+                    get_dotted_attr(module.clone(), name.as_str(), vm)
+                        .map(|(obj2, parent)| (module, obj2, parent))
+                })
+                .map_err(|e| {
+                    if e.fast_isinstance(vm.ctx.exceptions.import_error)
+                        || e.fast_isinstance(vm.ctx.exceptions.key_error)
+                        || e.fast_isinstance(vm.ctx.exceptions.attribute_error)
+                    {
+                        not_found()
+                    } else {
+                        e
+                    }
+                })?;
+            if !obj2.is(obj) {
+                return Err(new_pickling_error(
+                    format!(
+                        "Can't pickle {}: it's not the same object as {}.{}",
+                        obj.repr(vm)?,
+                        module_name,
+                        name
+                    ),
+                    vm,
+                ));
+            }
+
+            if self.protocol >= 2 {
+                let copyreg = vm.import("copyreg", 0)?;
+                let registry = copyreg.get_attr("_extension_registry", vm)?;
+                let key = (module_name.clone(), name.clone()).to_pyobject(vm);
+                let code = vm.call_method(&registry, "get", (key,))?;
+                if !vm.is_none(&code) {
+                    let code = i32::try_from_object(vm, code)?;
+                    if let Ok(code) = u8::try_from(code) {
+                        self.write(&[opcode::EXT1, code]);
+                    } else if let Ok(code) = u16::try_from(code) {
+                        self.write(&[opcode::EXT2]);
+                        self.write(&code.to_le_bytes());
+                    } else {
+                        self.write(&[opcode::EXT4]);
+                        self.write(&code.to_le_bytes());
+                    }
+                    return Ok(());
+                }
+            }
+
+            let lastname = name.as_str().rsplit('.').next().unwrap().to_owned();
+            let name = if parent.is(&module) {
+                vm.ctx.new_str(lastname.as_str())
+            } else {
+                name
+            };
+            if self.protocol >= 4 {
+                self.save(&module_name.clone().into(), true)?;
+                self.save(&name.clone().into(), true)?;
+                self.write(&[opcode::STACK_GLOBAL]);
+            } else if !parent.is(&module) {
+                let getattr = vm.builtins.get_attr("getattr", vm)?;
+                let args = (parent, lastname).to_pyobject(vm);
+                self.save_reduce(&getattr, &args, None, None, None, None, None)?;
+            } else if self.protocol >= 3 {
+                self.write_line(opcode::GLOBAL, module_name.as_str().as_bytes());
+                self.write(name.as_str().as_bytes());
+                self.write(b"\n");
+            } else {
+                let (module_name, name) = self.reverse_compat_name(module_name, name)?;
+                if !module_name.as_str().is_ascii() || !name.as_str().is_ascii() {
+                    return Err(new_pickling_error(
+                        format!(
+                            "can't pickle global identifier '{module_name}.{name}' using pickle protocol {}",
+                            self.protocol
+                        ),
+                        vm,
+                    ));
+                }
+                self.write_line(opcode::GLOBAL, module_name.as_str().as_bytes());
+                self.write(name.as_str().as_bytes());
+                self.write(b"\n");
+            }
+            self.memoize(obj);
+            Ok(())
+        }
+
+        /// Map Python 3 names back to their Python 2 spelling for protocols 0 to 2.
+        fn reverse_compat_name(
+            &self,
+            module_name: PyStrRef,
+            name: PyStrRef,
+        ) -> PyResult<(PyStrRef, PyStrRef)> {
+            let vm = self.vm;
+            if !self.fix_imports {
+                return Ok((module_name, name));
+            }
+            let compat = vm.import("_compat_pickle", 0)?;
+            let name_mapping = compat.get_attr("REVERSE_NAME_MAPPING", vm)?;
+            let key = (module_name.clone(), name.clone()).to_pyobject(vm);
+            if let Ok(mapped) = name_mapping.get_item(key.as_object(), vm) {
+                return unpack_pair(mapped, vm);
+            }
+            let import_mapping = compat.get_attr("REVERSE_IMPORT_MAPPING", vm)?;
+            match import_mapping.get_item(module_name.as_object(), vm) {
+                Ok(mapped) => Ok((mapped.try_into_value(vm)?, name)),
+                Err(_) => Ok((module_name, name)),
+            }
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn save_reduce(
+            &mut self,
+            func: &PyObjectRef,
+            args: &PyObjectRef,
+            state: Option<PyObjectRef>,
+            listitems: Option<PyObjectRef>,
+            dictitems: Option<PyObjectRef>,
+            state_setter: Option<PyObjectRef>,
+            obj: Option<&PyObjectRef>,
+        ) -> PyResult<()> {
+            let vm = self.vm;
+            let Some(args) = args.downcast_ref::<PyTuple>() else {
+                return Err(new_pickling_error(
+                    "second item of the tuple returned by __reduce__ must be a tuple".to_owned(),
+                    vm,
+                ));
+            };
+            if !func.is_callable() {
+                return Err(new_pickling_error(
+                    "first item of the tuple returned by __reduce__ must be callable".to_owned(),
+                    vm,
+                ));
+            }
+            let func_name = vm
+                .get_attribute_opt(func.clone(), "__name__")?
+                .and_then(|name| name.downcast::<PyStr>().ok());
+            let func_name = func_name.as_ref().map(|name| name.as_str());
+
+            if self.protocol >= 2 && func_name == Some("__newobj_ex__") {
+                let [cls, new_args, kwargs] = args.as_slice() else {
+                    return Err(new_pickling_error(
+                        format!(
+                            "length of the NEWOBJ_EX argument tuple must be exactly 3, not {}",
+                            args.len()
+                        ),
+                        vm,
+                    ));
+                };
+                self.check_newobj_class(cls, obj, "__newobj_ex__")?;
+                if self.protocol >= 4 {
+                    self.save(cls, true)?;
+                    self.save(new_args, true)?;
+                    self.save(kwargs, true)?;
+                    self.write(&[opcode::NEWOBJ_EX]);
+                } else {
+                    // functools.partial(cls.__new__, cls, *args, **kwargs)
+                    let partial = vm.import("functools", 0)?.get_attr("partial", vm)?;
+                    let mut partial_args = vec![cls.get_attr("__new__", vm)?, cls.clone()];
+                    partial_args.extend(
+                        PyTupleRef::try_from_object(vm, new_args.clone())?
+                            .iter()
+                            .cloned(),
+                    );
+                    let kwargs = kwargs_from_dict(kwargs.clone(), vm)?;
+                    let func = partial.call(FuncArgs::new(partial_args, kwargs), vm)?;
+                    self.save(&func, true)?;
+                    self.save(&vm.ctx.empty_tuple.clone().into(), true)?;
+                    self.write(&[opcode::REDUCE]);
+                }
+            } else if self.protocol >= 2 && func_name == Some("__newobj__") {
+                let Some((cls, new_args)) = args.as_slice().split_first() else {
+                    return Err(new_pickling_error(
+                        "__newobj__ arglist is empty".to_owned(),
+                        vm,
+                    ));
+                };
+                self.check_newobj_class(cls, obj, "__newobj__")?;
+                self.save(cls, true)?;
+                self.save(&vm.ctx.new_tuple(new_args.to_vec()).into(), true)?;
+                self.write(&[opcode::NEWOBJ]);
+            } else {
+                self.save(func, true)?;
+                self.save(&args.to_owned().into(), true)?;
+                self.write(&[opcode::REDUCE]);
+            }
+
+            if let Some(obj) = obj {
+                // a recursive object already got memoized: fetch it back from the memo
+                if let Some(index) = self.memo_get(obj) {
+                    self.write(&[opcode::POP]);
+                    self.write_get(index);
+                } else {
+                    self.memoize(obj);
+                }
+            }
+
+            if let Some(listitems) = listitems {
+                let iter = listitems.get_iter(vm)?;
+                self.batch_appends(iter.iter_without_hint::<PyObjectRef>(vm)?)?;
+            }
+            if let Some(dictitems) = dictitems {
+                let iter = dictitems.get_iter(vm)?;
+                let items = iter.iter_without_hint::<PyObjectRef>(vm)?.map(|item| {
+                    let item = item?;
+                    unpack_pair::<PyObjectRef, PyObjectRef>(item.clone(), vm).map_err(|_| {
+                        new_pickling_error(
+                            "dict items iterator must return 2-tuples".to_owned(),
+                            vm,
+                        )
+                    })
+                });
+                self.batch_setitems(items)?;
+            }
+            if let Some(state) = state {
+                match state_setter {
+                    None => {
+                        self.save(&state, true)?;
+                        self.write(&[opcode::BUILD]);
+                    }
+                    Some(state_setter) => {
+                        // call state_setter(obj, state) and throw the result away
+                        self.save(&state_setter, true)?;
+                        self.save(obj.unwrap_or(&vm.ctx.none()), true)?;
+                        self.save(&state, true)?;
+                        self.write(&[opcode::TUPLE2, opcode::REDUCE, opcode::POP]);
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn check_newobj_class(
+            &self,
+            cls: &PyObjectRef,
+            obj: Option<&PyObjectRef>,
+            func_name: &str,
+        ) -> PyResult<()> {
+            let vm = self.vm;
+            if cls.downcast_ref::<PyType>().is_none() {
+                return Err(new_pickling_error(
+                    format!("first item from {func_name} args must be a class"),
+                    vm,
+                ));
+            }
+            if let Some(obj) = obj {
+                if !obj.class().is(cls) {
+                    return Err(new_pickling_error(
+                        format!("first item from {func_name} args has the wrong class"),
+                        vm,
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Where an unpickler gets its data from.
+    #[derive(Debug)]
+    enum Input {
+        Bytes {
+            data: Vec<u8>,
+            pos: usize,
+        },
+        File {
+            read: PyObjectRef,
+            readline: PyObjectRef,
+        },
+    }
+
+    #[derive(Debug)]
+    struct Reader {
+        input: Input,
+        /// The rest of the frame being read, if any.
+        frame: Option<(Vec<u8>, usize)>,
+    }
+
+    impl Reader {
+        fn eof(vm: &VirtualMachine) -> PyBaseExceptionRef {
+            vm.new_exception_msg(
+                vm.ctx.exceptions.eof_error.to_owned(),
+                "Ran out of input".to_owned(),
+            )
+        }
+
+        fn read(&mut self, n: usize, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            if let Some((frame, pos)) = &mut self.frame {
+                if *pos < frame.len() || n == 0 {
+                    let Some(data) = frame.get(*pos..*pos + n) else {
+                        return Err(new_unpickling_error(
+                            "pickle exhausted before end of frame".to_owned(),
+                            vm,
+                        ));
+                    };
+                    *pos += n;
+                    return Ok(data.to_vec());
+                }
+                self.frame = None;
+            }
+            match &mut self.input {
+                Input::Bytes { data, pos } => {
+                    let Some(chunk) = data.get(*pos..*pos + n) else {
+                        return Err(Self::eof(vm));
+                    };
+                    *pos += n;
+                    Ok(chunk.to_vec())
+                }
+                Input::File { read, .. } => {
+                    let data = read.call((n,), vm)?;
+                    let data = ArgBytesLike::try_from_object(vm, data)?;
+                    let data = data.borrow_buf().to_vec();
+                    if data.len() != n {
+                        return Err(Self::eof(vm));
+                    }
+                    Ok(data)
+                }
+            }
+        }
+
+        /// Read a line without its `\n`.
+        fn readline(&mut self, vm: &VirtualMachine) -> PyResult<Vec<u8>> {
+            if let Some((frame, pos)) = &mut self.frame {
+                if *pos < frame.len() {
+                    let rest = &frame[*pos..];
+                    let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                        return Err(new_unpickling_error(
+                            "pickle exhausted before end of frame".to_owned(),
+                            vm,
+                        ));
+                    };
+                    let line = rest[..end].to_vec();
+                    *pos += end + 1;
+                    return Ok(line);
+                }
+                self.frame = None;
+            }
+            let mut line = match &mut self.input {
+                Input::Bytes { data, pos } => {
+                    let rest = &data[*pos..];
+                    let end = rest
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .map_or(rest.len(), |end| end + 1);
+                    *pos += end;
+                    rest[..end].to_vec()
+                }
+                Input::File { readline, .. } => {
+                    let line = readline.call((), vm)?;
+                    ArgBytesLike::try_from_object(vm, line)?
+                        .borrow_buf()
+                        .to_vec()
+                }
+            };
+            if line.pop() != Some(b'\n') {
+                return Err(Self::eof(vm));
+            }
+            Ok(line)
+        }
+
+        fn load_frame(&mut self, size: usize, vm: &VirtualMachine) -> PyResult<()> {
+            if matches!(&self.frame, Some((frame, pos)) if *pos < frame.len()) {
+                return Err(new_unpickling_error(
+                    "beginning of a new frame before end of current frame".to_owned(),
+                    vm,
+                ));
+            }
+            self.frame = None;
+            let frame = self.read(size, vm)?;
+            self.frame = Some((frame, 0));
+            Ok(())
+        }
+    }
+
+    #[derive(Debug)]
+    struct UnpicklerConfig {
+        read: PyObjectRef,
+        readline: PyObjectRef,
+        fix_imports: bool,
+        encoding: String,
+        errors: String,
+        buffers: Option<PyObjectRef>,
+    }
+
+    #[pyattr]
+    #[pyclass(name = "Unpickler")]
+    #[derive(Debug, Default, PyPayload)]
+    struct PyUnpickler {
+        config: PyMutex<Option<UnpicklerConfig>>,
+        memo: PyMutex<HashMap<usize, PyObjectRef>>,
+        /// The protocol of the pickle being loaded, for `find_class()`.
+        proto: PyMutex<u8>,
+    }
+
+    #[derive(FromArgs)]
+    struct LoadOptions {
+        #[pyarg(named, default = "true")]
+        fix_imports: bool,
+        #[pyarg(named, optional)]
+        encoding: OptionalArg<PyStrRef>,
+        #[pyarg(named, optional)]
+        errors: OptionalArg<PyStrRef>,
+        #[pyarg(named, optional)]
+        buffers: OptionalOption<PyObjectRef>,
+    }
+
+    impl LoadOptions {
+        fn into_config(
+            self,
+            read: PyObjectRef,
+            readline: PyObjectRef,
+            vm: &VirtualMachine,
+        ) -> PyResult<UnpicklerConfig> {
+            let buffers = match self.buffers.flatten() {
+                Some(buffers) => Some(buffers.get_iter(vm)?.into()),
+                None => None,
+            };
+            Ok(UnpicklerConfig {
+                read,
+                readline,
+                fix_imports: self.fix_imports,
+                encoding: self
+                    .encoding
+                    .map_or_else(|| "ASCII".to_owned(), |s| s.as_str().to_owned()),
+                errors: self
+                    .errors
+                    .map_or_else(|| "strict".to_owned(), |s| s.as_str().to_owned()),
+                buffers,
+            })
+        }
+    }
+
+    impl DefaultConstructor for PyUnpickler {}
+
+    impl Initializer for PyUnpickler {
+        type Args = (PyObjectRef, LoadOptions);
+
+        fn init(
+            zelf: PyRef<Self>,
+            (file, options): Self::Args,
+            vm: &VirtualMachine,
+        ) -> PyResult<()> {
+            let read = file.get_attr("read", vm)?;
+            let readline = file.get_attr("readline", vm)?;
+            let config = options.into_config(read, readline, vm)?;
+            *zelf.config.lock() = Some(config);
+            zelf.memo.lock().clear();
+            Ok(())
+        }
+    }
+
+    #[pyclass(with(DefaultConstructor, Initializer), flags(BASETYPE, HAS_DICT))]
+    impl PyUnpickler {
+        #[pymethod]
+        fn load(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult {
+            let config = zelf.config.lock().as_ref().map(|config| {
+                (
+                    Input::File {
+                        read: config.read.clone(),
+                        readline: config.readline.clone(),
+                    },
+                    config.buffers.clone(),
+                )
+            });
+            let Some((input, buffers)) = config else {
+                return Err(new_unpickling_error(
+                    format!(
+                        "Unpickler.__init__() was not called by {}.__init__()",
+                        zelf.class().name()
+                    ),
+                    vm,
+                ));
+            };
+            Self::load_from(zelf, input, buffers, vm)
+        }
+
+        fn load_from(
+            zelf: &Py<Self>,
+            input: Input,
+            buffers: Option<PyObjectRef>,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let (encoding, errors) = {
+                let config = zelf.config.lock();
+                let config = config.as_ref().unwrap();
+                (config.encoding.clone(), config.errors.clone())
+            };
+            let memo = std::mem::take(&mut *zelf.memo.lock());
+            let obj: PyObjectRef = zelf.to_owned().into();
+            let mut unpickler = Unpickler {
+                vm,
+                zelf,
+                reader: Reader { input, frame: None },
+                stack: Vec::new(),
+                metastack: Vec::new(),
+                memo,
+                proto: 0,
+                encoding,
+                errors,
+                buffers: buffers.map(PyIter::new),
+                persistent_load: vm.get_attribute_opt(obj, "persistent_load")?,
+            };
+            let result = unpickler.load();
+            *zelf.memo.lock() = unpickler.memo;
+            result
+        }
+
+        #[pymethod]
+        fn find_class(
+            zelf: &Py<Self>,
+            module_name: PyStrRef,
+            name: PyStrRef,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            let proto = *zelf.proto.lock();
+            let fix_imports = zelf
+                .config
+                .lock()
+                .as_ref()
+                .map_or(true, |config| config.fix_imports);
+            let (module_name, name) = if proto < 3 && fix_imports {
+                compat_name(module_name, name, vm)?
+            } else {
+                (module_name, name)
+            };
+            let module = import_module(&module_name, vm)?;
+            if proto >= 4 {
+                get_dotted_attr(module, name.as_str(), vm).map(|(obj, _)| obj)
+            } else {
+                module.get_attr(&name, vm)
+            }
+        }
+
+        #[pygetset]
+        fn memo(&self, vm: &VirtualMachine) -> PyDictRef {
+            let dict = vm.ctx.new_dict();
+            for (&index, obj) in self.memo.lock().iter() {
+                dict.set_item(&*index.to_pyobject(vm), obj.clone(), vm)
+                    .unwrap();
+            }
+            dict
+        }
+
+        #[pygetset(setter)]
+        fn set_memo(&self, memo: PyDictRef, vm: &VirtualMachine) -> PyResult<()> {
+            let mut new_memo = HashMap::new();
+            for (index, obj) in &memo {
+                new_memo.insert(usize::try_from_object(vm, index)?, obj);
+            }
+            *self.memo.lock() = new_memo;
+            Ok(())
+        }
+    }
+
+    /// Map Python 2 names to their Python 3 spelling.
+    fn compat_name(
+        module_name: PyStrRef,
+        name: PyStrRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<(PyStrRef, PyStrRef)> {
+        let compat = vm.import("_compat_pickle", 0)?;
+        let name_mapping = compat.get_attr("NAME_MAPPING", vm)?;
+        let key = (module_name.clone(), name.clone()).to_pyobject(vm);
+        if let Ok(mapped) = name_mapping.get_item(key.as_object(), vm) {
+            return unpack_pair(mapped, vm);
+        }
+        let import_mapping = compat.get_attr("IMPORT_MAPPING", vm)?;
+        match import_mapping.get_item(module_name.as_object(), vm) {
+            Ok(mapped) => Ok((mapped.try_into_value(vm)?, name)),
+            Err(_) => Ok((module_name, name)),
+        }
+    }
+
+    /// The state of a single `load()`.
+    struct Unpickler<'a> {
+        vm: &'a VirtualMachine,
+        zelf: &'a Py<PyUnpickler>,
+        reader: Reader,
+        stack: Vec<PyObjectRef>,
+        metastack: Vec<Vec<PyObjectRef>>,
+        memo: HashMap<usize, PyObjectRef>,
+        proto: u8,
+        encoding: String,
+        errors: String,
+        buffers: Option<PyIter>,
+        persistent_load: Option<PyObjectRef>,
+    }
+
+    impl Unpickler<'_> {
+        fn load(&mut self) -> PyResult {
+            loop {
+                let op = self.read(1)?[0];
+                if op == opcode::STOP {
+                    return self.pop();
+                }
+                self.dispatch(op)?;
+            }
+        }
+
+        fn read(&mut self, n: usize) -> PyResult<Vec<u8>> {
+            self.reader.read(n, self.vm)
+        }
+
+        fn read_u8(&mut self) -> PyResult<u8> {
+            Ok(self.read(1)?[0])
+        }
+
+        fn read_u16(&mut self) -> PyResult<u16> {
+            Ok(u16::from_le_bytes(self.read(2)?.try_into().unwrap()))
+        }
+
+        fn read_u32(&mut self) -> PyResult<u32> {
+            Ok(u32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+        }
+
+        fn read_i32(&mut self) -> PyResult<i32> {
+            Ok(i32::from_le_bytes(self.read(4)?.try_into().unwrap()))
+        }
+
+        fn read_u64(&mut self) -> PyResult<u64> {
+            Ok(u64::from_le_bytes(self.read(8)?.try_into().unwrap()))
+        }
+
+        fn read_size(&mut self, size: u64) -> PyResult<usize> {
+            usize::try_from(size)
+                .ok()
+                .filter(|&size| size <= isize::MAX as usize)
+                .ok_or_else(|| {
+                    self.vm
+                        .new_overflow_error(format!("size {size} exceeds sys.maxsize"))
+                })
+        }
+
+        fn readline(&mut self) -> PyResult<Vec<u8>> {
+            self.reader.readline(self.vm)
+        }
+
+        fn readline_str(&mut self, what: &str) -> PyResult<String> {
+            String::from_utf8(self.readline()?).map_err(|_| {
+                new_unpickling_error(
+                    format!("{what} in protocol 0 must be ASCII strings"),
+                    self.vm,
+                )
+            })
+        }
+
+        fn push(&mut self, obj: impl Into<PyObjectRef>) {
+            self.stack.push(obj.into());
+        }
+
+        fn stack_underflow(&self) -> PyBaseExceptionRef {
+            new_unpickling_error("unpickling stack underflow".to_owned(), self.vm)
+        }
+
+        fn pop(&mut self) -> PyResult {
+            self.stack.pop().ok_or_else(|| self.stack_underflow())
+        }
+
+        fn top(&self) -> PyResult {
+            self.stack
+                .last()
+                .cloned()
+                .ok_or_else(|| self.stack_underflow())
+        }
+
+        fn pop_mark(&mut self) -> PyResult<Vec<PyObjectRef>> {
+            let outer = self
+                .metastack
+                .pop()
+                .ok_or_else(|| new_unpickling_error("could not find MARK".to_owned(), self.vm))?;
+            Ok(std::mem::replace(&mut self.stack, outer))
+        }
+
+        fn memo_get(&self, index: usize) -> PyResult {
+            self.memo.get(&index).cloned().ok_or_else(|| {
+                new_unpickling_error(format!("Memo value not found at index {index}"), self.vm)
+            })
+        }
+
+        fn memo_put(&mut self, index: usize) -> PyResult<()> {
+            let top = self.top()?;
+            self.memo.insert(index, top);
+            Ok(())
+        }
+
+        fn decode_string(&self, data: Vec<u8>) -> PyResult {
+            let vm = self.vm;
+            let bytes = vm.ctx.new_bytes(data);
+            if self.encoding == "bytes" {
+                Ok(bytes.into())
+            } else {
+                vm.call_method(
+                    bytes.as_object(),
+                    "decode",
+                    (self.encoding.as_str(), self.errors.as_str()),
+                )
+            }
+        }
+
+        fn decode_utf8(&self, data: Vec<u8>) -> PyResult {
+            let vm = self.vm;
+            match String::from_utf8(data) {
+                Ok(s) => Ok(vm.ctx.new_str(s).into()),
+                // let the codec report the error, or deal with surrogates
+                Err(e) => vm.call_method(
+                    vm.ctx.new_bytes(e.into_bytes()).as_object(),
+                    "decode",
+                    ("utf-8", "surrogatepass"),
+                ),
+            }
+        }
+
+        fn find_class(&mut self, module_name: PyObjectRef, name: PyObjectRef) -> PyResult {
+            *self.zelf.proto.lock() = self.proto;
+            self.vm
+                .call_method(self.zelf.as_object(), "find_class", (module_name, name))
+        }
+
+        fn persistent_load(&mut self, pid: PyObjectRef) -> PyResult<()> {
+            let Some(persistent_load) = &self.persistent_load else {
+                return Err(new_unpickling_error(
+                    "A load persistent id instruction was encountered, but no persistent_load function was specified.".to_owned(),
+                    self.vm,
+                ));
+            };
+            let obj = persistent_load.call((pid,), self.vm)?;
+            self.push(obj);
+            Ok(())
+        }
+
+        fn get_extension(&mut self, code: i32) -> PyResult<()> {
+            let vm = self.vm;
+            let copyreg = vm.import("copyreg", 0)?;
+            let cache = copyreg.get_attr("_extension_cache", vm)?;
+            let code_obj = code.to_pyobject(vm);
+            if let Ok(obj) = cache.get_item(code_obj.as_object(), vm) {
+                self.push(obj);
+                return Ok(());
+            }
+            let inverted = copyreg.get_attr("_inverted_registry", vm)?;
+            let key = vm.call_method(&inverted, "get", (code_obj.clone(),))?;
+            if vm.is_none(&key) {
+                return Err(vm.new_value_error(format!("unregistered extension code {code}")));
+            }
+            let (module_name, name) = unpack_pair::<PyObjectRef, PyObjectRef>(key, vm)?;
+            let obj = self.find_class(module_name, name)?;
+            cache.set_item(code_obj.as_object(), obj.clone(), vm)?;
+            self.push(obj);
+            Ok(())
+        }
+
+        /// Create `cls(*args)` for the protocol 0 and 1 `INST` and `OBJ` opcodes.
+        fn instantiate(&mut self, cls: PyObjectRef, args: Vec<PyObjectRef>) -> PyResult<()> {
+            let vm = self.vm;
+            let obj = if args.is_empty()
+                && cls.downcast_ref::<PyType>().is_some()
+                && vm
+                    .get_attribute_opt(cls.clone(), "__getinitargs__")?
+                    .is_none()
+            {
+                vm.call_method(&cls, "__new__", (cls.clone(),))?
+            } else {
+                cls.call(args, vm)?
+            };
+            self.push(obj);
+            Ok(())
+        }
+
+        fn build(&mut self) -> PyResult<()> {
+            let vm = self.vm;
+            let state = self.pop()?;
+            let inst = self.top()?;
+            if let Some(setstate) = vm.get_attribute_opt(inst.clone(), "__setstate__")? {
+                setstate.call((state,), vm)?;
+                return Ok(());
+            }
+            let (state, slotstate) = match state.downcast_ref::<PyTuple>() {
+                Some(tuple) if tuple.len() == 2 => (
+                    tuple.as_slice()[0].clone(),
+                    Some(tuple.as_slice()[1].clone()),
+                ),
+                _ => (state, None),
+            };
+            if !vm.is_none(&state) && state.clone().try_to_bool(vm)? {
+                let inst_dict = inst.get_attr("__dict__", vm)?;
+                let items = vm.call_method(&state, "items", ())?;
+                for item in items.get_iter(vm)?.iter_without_hint::<PyObjectRef>(vm)? {
+                    let (key, value) = unpack_pair::<PyObjectRef, PyObjectRef>(item?, vm)?;
+                    inst_dict.set_item(key.as_object(), value, vm)?;
+                }
+            }
+            if let Some(slotstate) = slotstate.filter(|slotstate| !vm.is_none(slotstate)) {
+                let items = vm.call_method(&slotstate, "items", ())?;
+                for item in items.get_iter(vm)?.iter_without_hint::<PyObjectRef>(vm)? {
+                    let (key, value) = unpack_pair::<PyStrRef, PyObjectRef>(item?, vm)?;
+                    inst.set_attr(&key, value, vm)?;
+                }
+            }
+            Ok(())
+        }
+
+        fn dispatch(&mut self, op: u8) -> PyResult<()> {
+            let vm = self.vm;
+            match op {
+                opcode::PROTO => {
+                    let proto = self.read_u8()?;
+                    if proto > HIGHEST_PROTOCOL {
+                        return Err(
+                            vm.new_value_error(format!("unsupported pickle protocol: {proto}"))
+                        );
+                    }
+                    self.proto = proto;
+                }
+                opcode::FRAME => {
+                    let size = self.read_u64()?;
+                    let size = self.read_size(size)?;
+                    self.reader.load_frame(size, vm)?;
+                }
+                opcode::PERSID => {
+                    let pid = self.readline_str("persistent IDs")?;
+                    self.persistent_load(vm.ctx.new_str(pid).into())?;
+                }
+                opcode::BINPERSID => {
+                    let pid = self.pop()?;
+                    self.persistent_load(pid)?;
+                }
+                opcode::NONE => self.push(vm.ctx.none()),
+                opcode::NEWFALSE => self.push(vm.ctx.new_bool(false)),
+                opcode::NEWTRUE => self.push(vm.ctx.new_bool(true)),
+                opcode::INT => {
+                    let line = self.readline()?;
+                    let obj: PyObjectRef = match &line[..] {
+                        b"00" => vm.ctx.new_bool(false).into(),
+                        b"01" => vm.ctx.new_bool(true).into(),
+                        _ => vm.ctx.new_bigint(&parse_int(&line, vm)?).into(),
+                    };
+                    self.push(obj);
+                }
+                opcode::BININT => {
+                    let value = self.read_i32()?;
+                    self.push(vm.ctx.new_int(value));
+                }
+                opcode::BININT1 => {
+                    let value = self.read_u8()?;
+                    self.push(vm.ctx.new_int(value));
+                }
+                opcode::BININT2 => {
+                    let value = self.read_u16()?;
+                    self.push(vm.ctx.new_int(value));
+                }
+                opcode::LONG => {
+                    let mut line = self.readline()?;
+                    if line.last() == Some(&b'L') {
+                        line.pop();
+                    }
+                    let value = parse_int(&line, vm)?;
+                    self.push(vm.ctx.new_bigint(&value));
+                }
+                opcode::LONG1 => {
+                    let n = self.read_u8()? as usize;
+                    let data = self.read(n)?;
+                    self.push(vm.ctx.new_bigint(&BigInt::from_signed_bytes_le(&data)));
+                }
+                opcode::LONG4 => {
+                    let n = self.read_i32()?;
+                    let Ok(n) = usize::try_from(n) else {
+                        return Err(new_unpickling_error(
+                            "LONG pickle has negative byte count".to_owned(),
+                            vm,
+                        ));
+                    };
+                    let data = self.read(n)?;
+                    self.push(vm.ctx.new_bigint(&BigInt::from_signed_bytes_le(&data)));
+                }
+                opcode::FLOAT => {
+                    let line = self.readline()?;
+                    let value = std::str::from_utf8(&line)
+                        .ok()
+                        .and_then(|s| s.trim().parse::<f64>().ok())
+                        .ok_or_else(|| {
+                            vm.new_value_error("could not convert string to float".to_owned())
+                        })?;
+                    self.push(vm.ctx.new_float(value));
+                }
+                opcode::BINFLOAT => {
+                    let data = self.read(8)?;
+                    let value = f64::from_be_bytes(data.try_into().unwrap());
+                    self.push(vm.ctx.new_float(value));
+                }
+                opcode::STRING => {
+                    let line = self.readline()?;
+                    let quoted = line.len() >= 2
+                        && line[0] == line[line.len() - 1]
+                        && matches!(line[0], b'"' | b'\'');
+                    if !quoted {
+                        return Err(new_unpickling_error(
+                            "the STRING opcode argument must be quoted".to_owned(),
+                            vm,
+                        ));
+                    }
+                    let escaped = vm.ctx.new_bytes(line[1..line.len() - 1].to_vec());
+                    let decoded = vm
+                        .import("codecs", 0)?
+                        .get_attr("escape_decode", vm)?
+                        .call((escaped,), vm)?;
+                    let (decoded, _) = unpack_pair::<ArgBytesLike, PyObjectRef>(decoded, vm)?;
+                    let decoded = decoded.borrow_buf().to_vec();
+                    let obj = self.decode_string(decoded)?;
+                    self.push(obj);
+                }
+                opcode::BINSTRING => {
+                    let Ok(n) = usize::try_from(self.read_i32()?) else {
+                        return Err(new_unpickling_error(
+                            "BINSTRING pickle has negative byte count".to_owned(),
+                            vm,
+                        ));
+                    };
+                    let data = self.read(n)?;
+                    let obj = self.decode_string(data)?;
+                    self.push(obj);
+                }
+                opcode::SHORT_BINSTRING => {
+                    let n = self.read_u8()? as usize;
+                    let data = self.read(n)?;
+                    let obj = self.decode_string(data)?;
+                    self.push(obj);
+                }
+                opcode::BINBYTES => {
+                    let n = self.read_u32()? as usize;
+                    let data = self.read(n)?;
+                    self.push(vm.ctx.new_bytes(data));
+                }
+                opcode::SHORT_BINBYTES => {
+                    let n = self.read_u8()? as usize;
+                    let data = self.read(n)?;
+                    self.push(vm.ctx.new_bytes(data));
+                }
+                opcode::BINBYTES8 => {
+                    let n = self.read_u64()?;
+                    let n = self.read_size(n)?;
+                    let data = self.read(n)?;
+                    self.push(vm.ctx.new_bytes(data));
+                }
+                opcode::BYTEARRAY8 => {
+                    let n = self.read_u64()?;
+                    let n = self.read_size(n)?;
+                    let data = self.read(n)?;
+                    self.push(PyByteArray::from(data).into_ref(&vm.ctx));
+                }
+                opcode::NEXT_BUFFER => {
+                    let Some(buffers) = &self.buffers else {
+                        return Err(new_unpickling_error(
+                            "pickle stream refers to out-of-band data but no *buffers* argument was given".to_owned(),
+                            vm,
+                        ));
+                    };
+                    match buffers.next(vm)? {
+                        PyIterReturn::Return(buffer) => self.push(buffer),
+                        PyIterReturn::StopIteration(_) => {
+                            return Err(new_unpickling_error(
+                                "not enough out-of-band buffers".to_owned(),
+                                vm,
+                            ))
+                        }
+                    }
+                }
+                opcode::READONLY_BUFFER => {
+                    let top = self.top()?;
+                    let readonly = PyBuffer::try_from_borrowed_object(vm, &top)?.desc.readonly;
+                    if !readonly {
+                        let view = PyMemoryView::from_object(&top, vm)?.into_ref(&vm.ctx);
+                        let view = vm.call_method(view.as_object(), "toreadonly", ())?;
+                        *self.stack.last_mut().unwrap() = view;
+                    }
+                }
+                opcode::UNICODE => {
+                    let line = self.readline()?;
+                    let obj = match raw_unicode_unescape(&line) {
+                        Some(s) => vm.ctx.new_str(s).into(),
+                        // let the codec report the error, or deal with surrogates
+                        None => vm.call_method(
+                            vm.ctx.new_bytes(line).as_object(),
+                            "decode",
+                            ("raw-unicode-escape",),
+                        )?,
+                    };
+                    self.push(obj);
+                }
+                opcode::BINUNICODE => {
+                    let n = self.read_u32()? as usize;
+                    let data = self.read(n)?;
+                    let obj = self.decode_utf8(data)?;
+                    self.push(obj);
+                }
+                opcode::SHORT_BINUNICODE => {
+                    let n = self.read_u8()? as usize;
+                    let data = self.read(n)?;
+                    let obj = self.decode_utf8(data)?;
+                    self.push(obj);
+                }
+                opcode::BINUNICODE8 => {
+                    let n = self.read_u64()?;
+                    let n = self.read_size(n)?;
+                    let data = self.read(n)?;
+                    let obj = self.decode_utf8(data)?;
+                    self.push(obj);
+                }
+                opcode::TUPLE => {
+                    let items = self.pop_mark()?;
+                    self.push(vm.ctx.new_tuple(items));
+                }
+                opcode::EMPTY_TUPLE => self.push(vm.ctx.empty_tuple.clone()),
+                opcode::TUPLE1 | opcode::TUPLE2 | opcode::TUPLE3 => {
+                    let n = (op - opcode::TUPLE1 + 1) as usize;
+                    if self.stack.len() < n {
+                        return Err(self.stack_underflow());
+                    }
+                    let items = self.stack.split_off(self.stack.len() - n);
+                    self.push(vm.ctx.new_tuple(items));
+                }
+                opcode::EMPTY_LIST => self.push(vm.ctx.new_list(Vec::new())),
+                opcode::EMPTY_DICT => self.push(vm.ctx.new_dict()),
+                opcode::EMPTY_SET => self.push(PySet::new_ref(&vm.ctx)),
+                opcode::FROZENSET => {
+                    let items = self.pop_mark()?;
+                    let set = PyFrozenSet::from_iter(vm, items)?;
+                    self.push(set.into_ref(&vm.ctx));
+                }
+                opcode::LIST => {
+                    let items = self.pop_mark()?;
+                    self.push(vm.ctx.new_list(items));
+                }
+                opcode::DICT => {
+                    let items = self.pop_mark()?;
+                    let dict = vm.ctx.new_dict();
+                    for pair in items.chunks_exact(2) {
+                        dict.set_item(&*pair[0], pair[1].clone(), vm)?;
+                    }
+                    self.push(dict);
+                }
+                opcode::INST => {
+                    let module_name = self.readline_str("module names")?;
+                    let name = self.readline_str("class names")?;
+                    let cls = self.find_class(
+                        vm.ctx.new_str(module_name).into(),
+                        vm.ctx.new_str(name).into(),
+                    )?;
+                    let args = self.pop_mark()?;
+                    self.instantiate(cls, args)?;
+                }
+                opcode::OBJ => {
+                    let mut args = self.pop_mark()?;
+                    if args.is_empty() {
+                        return Err(self.stack_underflow());
+                    }
+                    let cls = args.remove(0);
+                    self.instantiate(cls, args)?;
+                }
+                opcode::NEWOBJ => {
+                    let args = self.pop()?;
+                    let cls = self.pop()?;
+                    let args = PyTupleRef::try_from_object(vm, args)?;
+                    let mut new_args = vec![cls.clone()];
+                    new_args.extend(args.iter().cloned());
+                    let obj = vm.call_method(&cls, "__new__", new_args)?;
+                    self.push(obj);
+                }
+                opcode::NEWOBJ_EX => {
+                    let kwargs = self.pop()?;
+                    let args = self.pop()?;
+                    let cls = self.pop()?;
+                    let args = PyTupleRef::try_from_object(vm, args)?;
+                    let kwargs = kwargs_from_dict(kwargs, vm)?;
+                    let mut new_args = vec![cls.clone()];
+                    new_args.extend(args.iter().cloned());
+                    let new = cls.get_attr("__new__", vm)?;
+                    let obj = new.call(FuncArgs::new(new_args, kwargs), vm)?;
+                    self.push(obj);
+                }
+                opcode::GLOBAL => {
+                    let module_name = self.readline()?;
+                    let module_name = self.decode_utf8(module_name)?;
+                    let name = self.readline()?;
+                    let name = self.decode_utf8(name)?;
+                    let obj = self.find_class(module_name, name)?;
+                    self.push(obj);
+                }
+                opcode::STACK_GLOBAL => {
+                    let name = self.pop()?;
+                    let module_name = self.pop()?;
+                    if !name.class().is(vm.ctx.types.str_type)
+                        || !module_name.class().is(vm.ctx.types.str_type)
+                    {
+                        return Err(new_unpickling_error(
+                            "STACK_GLOBAL requires str".to_owned(),
+                            vm,
+                        ));
+                    }
+                    let obj = self.find_class(module_name, name)?;
+                    self.push(obj);
+                }
+                opcode::EXT1 => {
+                    let code = self.read_u8()?;
+                    self.get_extension(code.into())?;
+                }
+                opcode::EXT2 => {
+                    let code = self.read_u16()?;
+                    self.get_extension(code.into())?;
+                }
+                opcode::EXT4 => {
+                    let code = self.read_i32()?;
+                    self.get_extension(code)?;
+                }
+                opcode::REDUCE => {
+                    let args = self.pop()?;
+                    let func = self.pop()?;
+                    let args = PyTupleRef::try_from_object(vm, args)?;
+                    let obj = func.call(args.as_slice().to_vec(), vm)?;
+                    self.push(obj);
+                }
+                opcode::POP => {
+                    if self.stack.pop().is_none() {
+                        self.pop_mark()?;
+                    }
+                }
+                opcode::POP_MARK => {
+                    self.pop_mark()?;
+                }
+                opcode::DUP => {
+                    let top = self.top()?;
+                    self.push(top);
+                }
+                opcode::GET => {
+                    let line = self.readline()?;
+                    let index = parse_int(&line, vm)?.to_usize().unwrap_or(usize::MAX);
+                    let obj = self.memo_get(index)?;
+                    self.push(obj);
+                }
+                opcode::BINGET => {
+                    let index = self.read_u8()?;
+                    let obj = self.memo_get(index.into())?;
+                    self.push(obj);
+                }
+                opcode::LONG_BINGET => {
+                    let index = self.read_u32()?;
+                    let obj = self.memo_get(index as usize)?;
+                    self.push(obj);
+                }
+                opcode::PUT => {
+                    let line = self.readline()?;
+                    let index = parse_int(&line, vm)?
+                        .to_usize()
+                        .ok_or_else(|| vm.new_value_error("negative PUT argument".to_owned()))?;
+                    self.memo_put(index)?;
+                }
+                opcode::BINPUT => {
+                    let index = self.read_u8()?;
+                    self.memo_put(index.into())?;
+                }
+                opcode::LONG_BINPUT => {
+                    let index = self.read_u32()?;
+                    self.memo_put(index as usize)?;
+                }
+                opcode::MEMOIZE => {
+                    let index = self.memo.len();
+                    self.memo_put(index)?;
+                }
+                opcode::APPEND => {
+                    let value = self.pop()?;
+                    let list = self.top()?;
+                    match list.downcast_ref::<PyList>() {
+                        Some(list) => list.borrow_vec_mut().push(value),
+                        None => {
+                            vm.call_method(&list, "append", (value,))?;
+                        }
+                    }
+                }
+                opcode::APPENDS => {
+                    let items = self.pop_mark()?;
+                    let list = self.top()?;
+                    if let Some(list) = list.downcast_ref_if_exact::<PyList>(vm) {
+                        list.borrow_vec_mut().extend(items);
+                    } else if let Some(extend) = vm.get_attribute_opt(list.clone(), "extend")? {
+                        extend.call((vm.ctx.new_list(items),), vm)?;
+                    } else {
+                        let append = list.get_attr("append", vm)?;
+                        for item in items {
+                            append.call((item,), vm)?;
+                        }
+                    }
+                }
+                opcode::SETITEM => {
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    let dict = self.top()?;
+                    dict.set_item(key.as_object(), value, vm)?;
+                }
+                opcode::SETITEMS => {
+                    let items = self.pop_mark()?;
+                    let dict = self.top()?;
+                    for pair in items.chunks_exact(2) {
+                        dict.set_item(pair[0].as_object(), pair[1].clone(), vm)?;
+                    }
+                }
+                opcode::ADDITEMS => {
+                    let items = self.pop_mark()?;
+                    let set = self.top()?;
+                    if let Some(set) = set.payload::<PySet>() {
+                        for item in items {
+                            set.add(item, vm)?;
+                        }
+                    } else {
+                        let add = set.get_attr("add", vm)?;
+                        for item in items {
+                            add.call((item,), vm)?;
+                        }
+                    }
+                }
+                opcode::BUILD => self.build()?,
+                opcode::MARK => {
+                    let outer = std::mem::take(&mut self.stack);
+                    self.metastack.push(outer);
+                }
+                _ => {
+                    let key = if op.is_ascii_graphic() {
+                        (op as char).to_string()
+                    } else {
+                        format!("\\x{op:02x}")
+                    };
+                    return Err(new_unpickling_error(
+                        format!("invalid load key, '{key}'."),
+                        vm,
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(FromArgs)]
+    struct DumpOptions {
+        #[pyarg(any, optional)]
+        protocol: OptionalOption<i32>,
+        #[pyarg(named, default = "true")]
+        fix_imports: bool,
+        #[pyarg(named, optional)]
+        buffer_callback: OptionalOption<PyObjectRef>,
+    }
+
+    #[pyfunction]
+    fn dump(
+        obj: PyObjectRef,
+        file: PyObjectRef,
+        options: DumpOptions,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let pickler = PyPickler::default().into_ref(&vm.ctx);
+        let args = PicklerArgs {
+            file,
+            protocol: options.protocol,
+            fix_imports: options.fix_imports,
+            buffer_callback: options.buffer_callback,
+        };
+        *pickler.config.lock() = Some(PicklerConfig::new(args, vm)?);
+        PyPickler::dump(&pickler, obj, vm)
+    }
+
+    #[pyfunction]
+    fn dumps(
+        obj: PyObjectRef,
+        options: DumpOptions,
+        vm: &VirtualMachine,
+    ) -> PyResult<PyRef<PyBytes>> {
+        let protocol = check_protocol(options.protocol, vm)?;
+        let buffer_callback = options
+            .buffer_callback
+            .flatten()
+            .filter(|callback| !vm.is_none(callback));
+        if buffer_callback.is_some() && protocol < 5 {
+            return Err(vm.new_value_error("buffer_callback needs protocol >= 5".to_owned()));
+        }
+        let mut pickler = Pickler {
+            vm,
+            protocol,
+            fix_imports: options.fix_imports,
+            fast: false,
+            buffer_callback,
+            persistent_id: None,
+            reducer_override: None,
+            dispatch_table: None,
+            memo: Memo::new(),
+            output: Output {
+                buf: Vec::new(),
+                framing: false,
+                frame_start: None,
+                file_write: None,
+            },
+        };
+        pickler.dump(obj)?;
+        Ok(vm.ctx.new_bytes(pickler.output.buf))
+    }
+
+    #[pyfunction]
+    fn load(file: PyObjectRef, options: LoadOptions, vm: &VirtualMachine) -> PyResult {
+        let unpickler = PyUnpickler::default().into_ref(&vm.ctx);
+        PyUnpickler::init(unpickler.clone(), (file, options), vm)?;
+        PyUnpickler::load(&unpickler, vm)
+    }
+
+    #[pyfunction]
+    fn loads(data: ArgBytesLike, options: LoadOptions, vm: &VirtualMachine) -> PyResult {
+        let unpickler = PyUnpickler::default().into_ref(&vm.ctx);
+        let none = vm.ctx.none();
+        let config = options.into_config(none.clone(), none, vm)?;
+        let buffers = config.buffers.clone();
+        *unpickler.config.lock() = Some(config);
+        let input = Input::Bytes {
+            data: data.borrow_buf().to_vec(),
+            pos: 0,
+        };
+        PyUnpickler::load_from(&unpickler, input, buffers, vm)
+    }
+}
@@ -132,7 +132,7 @@ pub fn select(
         Some(tv) => tv as *mut timeval,
         None => std::ptr::null_mut(),
     };
-    let ret = unsafe {
+    let ret = crate::vm::object::gc::allow_threads(|| unsafe {
         platform::select(
             nfds,
             readfds.0.as_mut_ptr(),
@@ -140,7 +140,7 @@ pub fn select(
             errfds.0.as_mut_ptr(),
             timeout,
         )
-    };
+    });
     if platform::check_err(ret) {
         Err(io::Error::last_os_error())
     } else {
@@ -264,6 +264,25 @@ mod decl {
     #[pyattr]
     use libc::{POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT, POLLPRI};
 
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[pyattr(name = "epoll", once)]
+    fn epoll_type(vm: &VirtualMachine) -> PyTypeRef {
+        use crate::vm::class::PyClassImpl;
+        epoll::PyEpoll::make_class(&vm.ctx).to_owned()
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[pyattr]
+    use libc::{
+        EPOLLERR, EPOLLEXCLUSIVE, EPOLLHUP, EPOLLIN, EPOLLMSG, EPOLLONESHOT, EPOLLOUT, EPOLLPRI,
+        EPOLLRDBAND, EPOLLRDHUP, EPOLLRDNORM, EPOLLWRBAND, EPOLLWRNORM, EPOLL_CLOEXEC,
+    };
+
+    // libc declares it as a negative c_int
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[pyattr]
+    const EPOLLET: u32 = libc::EPOLLET as u32;
+
     #[cfg(unix)]
     pub(super) mod poll {
         use super::*;
@@ -367,7 +386,9 @@ mod decl {
                     .then(|| time::Instant::now() + time::Duration::from_millis(timeout_ms as u64));
                 let mut poll_timeout = timeout_ms;
                 loop {
-                    let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, poll_timeout) };
+                    let res = crate::vm::object::gc::allow_threads(|| unsafe {
+                        libc::poll(fds.as_mut_ptr(), fds.len() as _, poll_timeout)
+                    });
                     let res = if res < 0 {
                         Err(io::Error::last_os_error())
                     } else {
@@ -396,4 +417,227 @@ mod decl {
             }
         }
     }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(super) mod epoll {
+        use super::*;
+        use crate::vm::{
+            common::lock::PyRwLock,
+            convert::ToPyObject,
+            function::{FuncArgs, OptionalArg},
+            stdlib::io::Fildes,
+            types::Constructor,
+            Py, PyPayload,
+        };
+        use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+        use std::time;
+
+        #[pyclass(module = "select", name = "epoll")]
+        #[derive(Debug, PyPayload)]
+        pub struct PyEpoll {
+            epoll_fd: PyRwLock<Option<OwnedFd>>,
+        }
+
+        #[derive(FromArgs)]
+        pub struct EpollNewArgs {
+            #[pyarg(any, default = "-1")]
+            sizehint: i32,
+            #[pyarg(any, default = "0")]
+            flags: i32,
+        }
+
+        #[derive(FromArgs)]
+        pub struct EpollPollArgs {
+            #[pyarg(any, optional)]
+            timeout: OptionalOption<Either<f64, isize>>,
+            #[pyarg(any, default = "-1")]
+            maxevents: i32,
+        }
+
+        const DEFAULT_EVENTS: u32 = (libc::EPOLLIN | libc::EPOLLPRI | libc::EPOLLOUT) as u32;
+
+        impl Constructor for PyEpoll {
+            type Args = EpollNewArgs;
+
+            fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+                if args.sizehint == 0 || args.sizehint < -1 {
+                    return Err(vm.new_value_error("negative sizehint".to_owned()));
+                }
+                // the flags are accepted for compatibility; the fd is always close-on-exec
+                if args.flags != 0 && args.flags != libc::EPOLL_CLOEXEC {
+                    return Err(vm.new_os_error("invalid flags".to_owned()));
+                }
+                let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+                if fd < 0 {
+                    return Err(io::Error::last_os_error().to_pyexception(vm));
+                }
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                Self::from_fd(fd)
+                    .into_ref_with_type(vm, cls)
+                    .map(Into::into)
+            }
+        }
+
+        impl PyEpoll {
+            fn from_fd(fd: OwnedFd) -> Self {
+                PyEpoll {
+                    epoll_fd: PyRwLock::new(Some(fd)),
+                }
+            }
+
+            fn raw_fd(&self, vm: &VirtualMachine) -> PyResult<RawFd> {
+                self.epoll_fd
+                    .read()
+                    .as_ref()
+                    .map(|fd| fd.as_raw_fd())
+                    .ok_or_else(|| {
+                        vm.new_value_error("I/O operation on closed epoll object".to_owned())
+                    })
+            }
+
+            fn ctl(&self, op: i32, fd: RawFd, events: u32, vm: &VirtualMachine) -> PyResult<()> {
+                let epfd = self.raw_fd(vm)?;
+                let mut event = libc::epoll_event {
+                    events,
+                    u64: fd as u64,
+                };
+                let res = unsafe { libc::epoll_ctl(epfd, op, fd, &mut event) };
+                if res < 0 {
+                    Err(io::Error::last_os_error().to_pyexception(vm))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        #[pyclass(with(Constructor))]
+        impl PyEpoll {
+            #[pymethod]
+            fn close(&self) -> io::Result<()> {
+                let Some(fd) = self.epoll_fd.write().take() else {
+                    return Ok(());
+                };
+                if unsafe { libc::close(fd.into_raw_fd()) } < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }
+
+            #[pygetset]
+            fn closed(&self) -> bool {
+                self.epoll_fd.read().is_none()
+            }
+
+            #[pymethod]
+            fn fileno(&self, vm: &VirtualMachine) -> PyResult<RawFd> {
+                self.raw_fd(vm)
+            }
+
+            #[pyclassmethod]
+            fn fromfd(cls: PyTypeRef, Fildes(fd): Fildes, vm: &VirtualMachine) -> PyResult {
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                Self::from_fd(fd)
+                    .into_ref_with_type(vm, cls)
+                    .map(Into::into)
+            }
+
+            #[pymethod]
+            fn register(
+                &self,
+                Fildes(fd): Fildes,
+                eventmask: OptionalArg<u32>,
+                vm: &VirtualMachine,
+            ) -> PyResult<()> {
+                let events = eventmask.unwrap_or(DEFAULT_EVENTS);
+                self.ctl(libc::EPOLL_CTL_ADD, fd, events, vm)
+            }
+
+            #[pymethod]
+            fn modify(
+                &self,
+                Fildes(fd): Fildes,
+                eventmask: u32,
+                vm: &VirtualMachine,
+            ) -> PyResult<()> {
+                self.ctl(libc::EPOLL_CTL_MOD, fd, eventmask, vm)
+            }
+
+            #[pymethod]
+            fn unregister(&self, Fildes(fd): Fildes, vm: &VirtualMachine) -> PyResult<()> {
+                self.ctl(libc::EPOLL_CTL_DEL, fd, 0, vm)
+            }
+
+            #[pymethod]
+            fn poll(&self, args: EpollPollArgs, vm: &VirtualMachine) -> PyResult<Vec<PyObjectRef>> {
+                let timeout = args.timeout.flatten().map(|e| match e {
+                    Either::A(f) => f,
+                    Either::B(i) => i as f64,
+                });
+                let timeout_ms = match timeout {
+                    Some(timeout) if timeout >= 0.0 => {
+                        let ms = (timeout * 1000.0).ceil();
+                        if ms > i32::MAX as f64 {
+                            return Err(vm.new_overflow_error("timeout is too large".to_owned()));
+                        }
+                        ms as i32
+                    }
+                    _ => -1,
+                };
+                let maxevents = match args.maxevents {
+                    -1 => libc::FD_SETSIZE as i32 - 1,
+                    n if n <= 0 => {
+                        return Err(vm
+                            .new_value_error(format!("maxevents must be greater than 0, got {n}")))
+                    }
+                    n => n,
+                };
+
+                let epfd = self.raw_fd(vm)?;
+                let mut events = Vec::<libc::epoll_event>::with_capacity(maxevents as usize);
+                let deadline = (timeout_ms >= 0)
+                    .then(|| time::Instant::now() + time::Duration::from_millis(timeout_ms as u64));
+                let mut wait_timeout = timeout_ms;
+                loop {
+                    let res = crate::vm::object::gc::allow_threads(|| unsafe {
+                        libc::epoll_wait(epfd, events.as_mut_ptr(), maxevents, wait_timeout)
+                    });
+                    if res >= 0 {
+                        unsafe { events.set_len(res as usize) };
+                        break;
+                    }
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err.to_pyexception(vm));
+                    }
+                    vm.check_signals()?;
+                    if let Some(d) = deadline {
+                        match d.checked_duration_since(time::Instant::now()) {
+                            Some(remaining) => wait_timeout = remaining.as_millis() as i32,
+                            // we've timed out
+                            None => break,
+                        }
+                    }
+                }
+                Ok(events
+                    .iter()
+                    .map(|event| {
+                        let (fd, events) = (event.u64 as RawFd, event.events);
+                        (fd, events).to_pyobject(vm)
+                    })
+                    .collect())
+            }
+
+            #[pymethod(magic)]
+            fn enter(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<PyRef<Self>> {
+                zelf.raw_fd(vm)?;
+                Ok(zelf)
+            }
+
+            #[pymethod(magic)]
+            fn exit(zelf: &Py<Self>, _args: FuncArgs) -> io::Result<()> {
+                zelf.close()
+            }
+        }
+    }
 }
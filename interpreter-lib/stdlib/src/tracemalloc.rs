@@ -0,0 +1,287 @@
+pub(crate) use _tracemalloc::make_module;
+
+#[pymodule]
+mod _tracemalloc {
+    use crate::vm::{
+        builtins::{PyListRef, PyStrInterned, PyTupleRef},
+        convert::ToPyObject,
+        function::OptionalArg,
+        object::{set_alloc_hook, AllocHook},
+        vm::thread::try_with_current_vm,
+        AsObject, PyObject, PyObjectRef, PyResult, VirtualMachine,
+    };
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::{
+        cell::Cell,
+        collections::{HashMap, HashSet},
+        hash::{Hash, Hasher},
+        mem,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    const MAX_NFRAME: usize = u16::MAX as usize;
+
+    /// The filename of a code object. Interned strings are never deallocated, so it is fine to
+    /// keep one around after the interpreter that created it is gone.
+    #[derive(Debug, Clone, Copy)]
+    struct Filename(&'static PyStrInterned);
+
+    unsafe impl Send for Filename {}
+    unsafe impl Sync for Filename {}
+
+    impl PartialEq for Filename {
+        fn eq(&self, other: &Self) -> bool {
+            std::ptr::eq(self.0, other.0)
+        }
+    }
+
+    impl Eq for Filename {}
+
+    impl Hash for Filename {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            std::ptr::hash(self.0, state)
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Frame {
+        /// `None` when the object was allocated outside of any Python frame.
+        filename: Option<Filename>,
+        lineno: u32,
+    }
+
+    /// Where an object was allocated, most recent frame first.
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    struct Traceback {
+        frames: Box<[Frame]>,
+        /// The depth of the stack, which may be more than the number of frames kept.
+        total_nframe: usize,
+    }
+
+    impl Traceback {
+        fn capture(limit: usize) -> Self {
+            let traceback = try_with_current_vm(|vm| {
+                // the frame stack is borrowed mutably while a frame is pushed or popped
+                let frames = vm.frames.try_borrow().ok()?;
+                Some(Traceback {
+                    frames: frames
+                        .iter()
+                        .rev()
+                        .take(limit)
+                        .map(|frame| Frame {
+                            filename: Some(Filename(frame.code.source_path)),
+                            lineno: frame.f_lineno() as u32,
+                        })
+                        .collect(),
+                    total_nframe: frames.len(),
+                })
+            })
+            .flatten()
+            .filter(|traceback| !traceback.frames.is_empty());
+            traceback.unwrap_or_else(|| Traceback {
+                frames: Box::new([Frame {
+                    filename: None,
+                    lineno: 0,
+                }]),
+                total_nframe: 1,
+            })
+        }
+
+        fn to_tuple(&self, vm: &VirtualMachine) -> PyTupleRef {
+            let frames = self
+                .frames
+                .iter()
+                .map(|frame| {
+                    let filename: PyObjectRef = match frame.filename {
+                        Some(filename) => filename.0.to_owned().into(),
+                        None => vm.ctx.new_str("<unknown>").into(),
+                    };
+                    vm.new_tuple((filename, frame.lineno)).into()
+                })
+                .collect();
+            vm.ctx.new_tuple(frames)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Trace {
+        size: usize,
+        traceback: Arc<Traceback>,
+    }
+
+    #[derive(Debug, Default)]
+    struct TracemallocState {
+        /// Traces keyed by the address of the object.
+        traces: HashMap<usize, Trace>,
+        /// Identical tracebacks are shared between traces.
+        tracebacks: HashSet<Arc<Traceback>>,
+        traced_memory: usize,
+        peak_traced_memory: usize,
+    }
+
+    impl TracemallocState {
+        fn clear(&mut self) {
+            self.traces.clear();
+            self.tracebacks.clear();
+            self.traced_memory = 0;
+            self.peak_traced_memory = 0;
+        }
+    }
+
+    // allocating or releasing an object while the lock is held would deadlock in the hooks, so
+    // copy what is needed out of the state before building any Python objects
+    static STATE: Lazy<Mutex<TracemallocState>> = Lazy::new(Default::default);
+    static TRACING: AtomicBool = AtomicBool::new(false);
+    static MAX_NFRAME_SETTING: AtomicUsize = AtomicUsize::new(1);
+
+    static HOOK: AllocHook = AllocHook {
+        alloc: trace_alloc,
+        dealloc: trace_dealloc,
+    };
+
+    thread_local! {
+        /// Set while the current thread is inside `trace_alloc`.
+        static REENTRANT: Cell<bool> = const { Cell::new(false) };
+    }
+
+    fn trace_alloc(obj: &PyObject, size: usize) {
+        if REENTRANT.with(|reentrant| reentrant.replace(true)) {
+            return;
+        }
+        let traceback = Traceback::capture(MAX_NFRAME_SETTING.load(Ordering::Relaxed));
+
+        let mut state = STATE.lock();
+        let traceback = match state.tracebacks.get(&traceback) {
+            Some(traceback) => traceback.clone(),
+            None => {
+                let traceback = Arc::new(traceback);
+                state.tracebacks.insert(traceback.clone());
+                traceback
+            }
+        };
+        let addr = obj as *const PyObject as usize;
+        if let Some(old) = state.traces.insert(addr, Trace { size, traceback }) {
+            // the dealloc of whatever lived at this address was missed
+            state.traced_memory -= old.size;
+        }
+        state.traced_memory += size;
+        state.peak_traced_memory = state.peak_traced_memory.max(state.traced_memory);
+        drop(state);
+
+        REENTRANT.with(|reentrant| reentrant.set(false));
+    }
+
+    fn trace_dealloc(obj: &PyObject) {
+        let addr = obj as *const PyObject as usize;
+        let mut state = STATE.lock();
+        if let Some(trace) = state.traces.remove(&addr) {
+            state.traced_memory -= trace.size;
+        }
+    }
+
+    #[pyfunction]
+    fn is_tracing() -> bool {
+        TRACING.load(Ordering::Relaxed)
+    }
+
+    #[pyfunction]
+    fn clear_traces() {
+        STATE.lock().clear();
+    }
+
+    #[pyfunction]
+    fn start(nframe: OptionalArg<isize>, vm: &VirtualMachine) -> PyResult<()> {
+        let nframe = nframe.unwrap_or(1);
+        let nframe = usize::try_from(nframe)
+            .ok()
+            .filter(|nframe| (1..=MAX_NFRAME).contains(nframe))
+            .ok_or_else(|| {
+                vm.new_value_error(format!(
+                    "the number of frames must be in range [1; {MAX_NFRAME}]"
+                ))
+            })?;
+        MAX_NFRAME_SETTING.store(nframe, Ordering::Relaxed);
+        if !TRACING.swap(true, Ordering::Relaxed) {
+            set_alloc_hook(Some(&HOOK));
+        }
+        Ok(())
+    }
+
+    #[pyfunction]
+    fn stop() {
+        if TRACING.swap(false, Ordering::Relaxed) {
+            set_alloc_hook(None);
+        }
+        STATE.lock().clear();
+    }
+
+    #[pyfunction]
+    fn get_traceback_limit() -> usize {
+        MAX_NFRAME_SETTING.load(Ordering::Relaxed)
+    }
+
+    #[pyfunction]
+    fn get_traced_memory() -> (usize, usize) {
+        if !is_tracing() {
+            return (0, 0);
+        }
+        let state = STATE.lock();
+        (state.traced_memory, state.peak_traced_memory)
+    }
+
+    #[pyfunction]
+    fn reset_peak() {
+        let mut state = STATE.lock();
+        state.peak_traced_memory = state.traced_memory;
+    }
+
+    /// An estimate of the memory used by tracemalloc itself to store the traces.
+    #[pyfunction]
+    fn get_tracemalloc_memory() -> usize {
+        let state = STATE.lock();
+        let traces = state.traces.capacity() * mem::size_of::<(usize, Trace)>();
+        let tracebacks = state
+            .tracebacks
+            .iter()
+            .map(|traceback| {
+                mem::size_of::<Traceback>() + traceback.frames.len() * mem::size_of::<Frame>()
+            })
+            .sum::<usize>();
+        mem::size_of::<TracemallocState>() + traces + tracebacks
+    }
+
+    #[pyfunction]
+    fn _get_object_traceback(obj: PyObjectRef, vm: &VirtualMachine) -> Option<PyTupleRef> {
+        let addr = obj.as_object() as *const PyObject as usize;
+        let traceback = STATE.lock().traces.get(&addr)?.traceback.clone();
+        Some(traceback.to_tuple(vm))
+    }
+
+    /// Return a list of `(domain, size, traceback, total_nframe)` tuples for every traced
+    /// object, where `traceback` is a tuple of `(filename, lineno)` tuples.
+    #[pyfunction]
+    fn _get_traces(vm: &VirtualMachine) -> PyListRef {
+        let traces: Vec<_> = STATE
+            .lock()
+            .traces
+            .values()
+            .map(|trace| (trace.size, trace.traceback.clone()))
+            .collect();
+        let mut tuples = HashMap::new();
+        let traces = traces
+            .into_iter()
+            .map(|(size, traceback)| {
+                let frames = tuples
+                    .entry(Arc::as_ptr(&traceback))
+                    .or_insert_with(|| traceback.to_tuple(vm))
+                    .clone();
+                (0, size, frames, traceback.total_nframe).to_pyobject(vm)
+            })
+            .collect();
+        vm.ctx.new_list(traces)
+    }
+}
@@ -0,0 +1,309 @@
+// spell-checker:ignore tzif tzpath tzdata tzname fromutc utcoffset
+
+use crate::vm::{builtins::PyModule, PyRef, VirtualMachine};
+
+mod tzif;
+
+pub(crate) fn make_module(vm: &VirtualMachine) -> PyRef<PyModule> {
+    crate::datetime::init_classes(&vm.ctx);
+    let module = _zoneinfo::make_module(vm);
+    _zoneinfo::init_cache(vm);
+    module
+}
+
+#[pymodule]
+mod _zoneinfo {
+    use super::tzif::{TtInfo, Zone};
+    use crate::datetime::{PyDateTime, PyTimeDelta, PyTzInfo};
+    use crate::vm::{
+        builtins::{
+            PyBaseExceptionRef, PyBytes, PyDict, PyDictRef, PyStrRef, PyTupleRef, PyType, PyTypeRef,
+        },
+        function::FuncArgs,
+        types::{Constructor, Representable},
+        AsObject, Py, PyObject, PyObjectRef, PyPayload, PyRef, PyResult, VirtualMachine,
+    };
+
+    #[derive(Debug)]
+    enum Source {
+        /// Loaded by key, through the cache or not.
+        Key { from_cache: bool },
+        /// Loaded from a file object, which is kept for `repr`.
+        File { repr: String },
+    }
+
+    #[pyattr]
+    #[pyclass(module = "zoneinfo", name = "ZoneInfo", base = "PyTzInfo")]
+    #[derive(Debug, PyPayload)]
+    struct PyZoneInfo {
+        key: Option<PyObjectRef>,
+        zone: Zone,
+        source: Source,
+    }
+
+    #[derive(FromArgs)]
+    struct KeyArgs {
+        #[pyarg(any)]
+        key: PyObjectRef,
+    }
+
+    impl Constructor for PyZoneInfo {
+        type Args = KeyArgs;
+
+        fn py_new(cls: PyTypeRef, args: Self::Args, vm: &VirtualMachine) -> PyResult {
+            let cache = class_cache(&cls, vm)?;
+            if let Some(zone) = cache.get_item_opt(&*args.key, vm)? {
+                return Ok(zone);
+            }
+            let zone: PyObjectRef = PyZoneInfo::load(&args.key, true, vm)?
+                .into_ref_with_type(vm, cls)?
+                .into();
+            // another thread may have got there first, and identity matters for tzinfo
+            if let Some(cached) = cache.get_item_opt(&*args.key, vm)? {
+                return Ok(cached);
+            }
+            cache.set_item(&*args.key, zone.clone(), vm)?;
+            Ok(zone)
+        }
+    }
+
+    /// The key cache of `cls`, which `__init_subclass__` gives every subclass its own copy of.
+    fn class_cache(cls: &Py<PyType>, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+        cls.as_object()
+            .get_attr("_weak_cache", vm)?
+            .downcast::<PyDict>()
+            .map_err(|_| vm.new_type_error("ZoneInfo._weak_cache must be a dict".to_owned()))
+    }
+
+    pub(super) fn init_cache(vm: &VirtualMachine) {
+        PyZoneInfo::class(&vm.ctx)
+            .set_attr(vm.ctx.intern_str("_weak_cache"), vm.ctx.new_dict().into());
+    }
+
+    fn not_found(key: &PyObject, vm: &VirtualMachine) -> PyResult<PyBaseExceptionRef> {
+        let common = vm.import("zoneinfo._common", 0)?;
+        let error = common.get_attr("ZoneInfoNotFoundError", vm)?;
+        let message = format!("No time zone found with key {}", key.str(vm)?);
+        let exc = error.call((message,), vm)?;
+        exc.downcast()
+            .map_err(|_| vm.new_type_error("ZoneInfoNotFoundError is not an exception".to_owned()))
+    }
+
+    fn parse_zone(data: &[u8], vm: &VirtualMachine) -> PyResult<Zone> {
+        Zone::parse(data).map_err(|e| vm.new_value_error(e.to_owned()))
+    }
+
+    impl PyZoneInfo {
+        /// Find the TZif data for `key` on the time zone search path, falling back on the
+        /// `tzdata` package.
+        fn load(key: &PyObjectRef, from_cache: bool, vm: &VirtualMachine) -> PyResult<Self> {
+            let tzpath = vm.import("zoneinfo._tzpath", 0)?;
+            let path = vm.call_method(&tzpath, "find_tzfile", (key.clone(),))?;
+            let data = if vm.is_none(&path) {
+                let common = vm.import("zoneinfo._common", 0)?;
+                let file = match vm.call_method(&common, "load_tzdata", (key.clone(),)) {
+                    Ok(file) => file,
+                    Err(e) if e.fast_isinstance(vm.ctx.exceptions.lookup_error) => {
+                        return Err(not_found(key, vm)?)
+                    }
+                    Err(e) => return Err(e),
+                };
+                let data = vm.call_method(&file, "read", ());
+                vm.call_method(&file, "close", ())?;
+                data?
+                    .downcast::<PyBytes>()
+                    .map_err(|_| {
+                        vm.new_type_error(
+                            "tzdata resource must be opened in binary mode".to_owned(),
+                        )
+                    })?
+                    .as_bytes()
+                    .to_vec()
+            } else {
+                let path = path.str(vm)?;
+                std::fs::read(path.as_str())
+                    .map_err(|_| not_found(key, vm).unwrap_or_else(|e| e))?
+            };
+            Ok(PyZoneInfo {
+                key: Some(key.clone()),
+                zone: parse_zone(&data, vm)?,
+                source: Source::Key { from_cache },
+            })
+        }
+
+        /// The type in effect at `dt`, which is None or a datetime.
+        fn find(&self, dt: &PyObject, vm: &VirtualMachine) -> PyResult<Option<TtInfo>> {
+            if vm.is_none(dt) {
+                return Ok(self.zone.find_fixed().cloned());
+            }
+            let dt = dt.payload_if_subclass::<PyDateTime>(vm).ok_or_else(|| {
+                vm.new_type_error(format!(
+                    "utcoffset() argument must be a datetime instance or None, not {}",
+                    dt.class().name()
+                ))
+            })?;
+            Ok(Some(self.zone.find_local(
+                dt.wall_seconds(),
+                dt.year(),
+                dt.fold(),
+            )))
+        }
+    }
+
+    fn seconds_to_delta(seconds: i64, vm: &VirtualMachine) -> PyObjectRef {
+        PyTimeDelta::from_us(seconds as i128 * 1_000_000)
+            .unwrap()
+            .into_ref(&vm.ctx)
+            .into()
+    }
+
+    #[derive(FromArgs)]
+    struct FromFileArgs {
+        #[pyarg(any)]
+        file_obj: PyObjectRef,
+        #[pyarg(any, default)]
+        key: Option<PyObjectRef>,
+    }
+
+    #[derive(FromArgs)]
+    struct ClearCacheArgs {
+        #[pyarg(named, default)]
+        only_keys: Option<PyObjectRef>,
+    }
+
+    #[pyclass(flags(BASETYPE), with(Constructor, Representable))]
+    impl PyZoneInfo {
+        #[pyclassmethod]
+        fn no_cache(cls: PyTypeRef, key: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            PyZoneInfo::load(&key, false, vm)?
+                .into_ref_with_type(vm, cls)
+                .map(Into::into)
+        }
+
+        #[pyclassmethod]
+        fn from_file(cls: PyTypeRef, args: FromFileArgs, vm: &VirtualMachine) -> PyResult {
+            let data = vm.call_method(&args.file_obj, "read", ())?;
+            let data = data.downcast::<PyBytes>().map_err(|data| {
+                vm.new_type_error(format!(
+                    "file_obj.read() must return bytes, not {}",
+                    data.class().name()
+                ))
+            })?;
+            let repr = args.file_obj.repr(vm)?.as_str().to_owned();
+            PyZoneInfo {
+                key: args.key.filter(|key| !vm.is_none(key)),
+                zone: parse_zone(data.as_bytes(), vm)?,
+                source: Source::File { repr },
+            }
+            .into_ref_with_type(vm, cls)
+            .map(Into::into)
+        }
+
+        #[pyclassmethod]
+        fn clear_cache(cls: PyTypeRef, args: ClearCacheArgs, vm: &VirtualMachine) -> PyResult<()> {
+            let cache = class_cache(&cls, vm)?;
+            match args.only_keys {
+                Some(keys) if !vm.is_none(&keys) => {
+                    for key in keys.get_iter(vm)?.iter::<PyObjectRef>(vm)? {
+                        let key = key?;
+                        if cache.get_item_opt(&*key, vm)?.is_some() {
+                            cache.del_item(&*key, vm)?;
+                        }
+                    }
+                }
+                _ => cache.clear(),
+            }
+            Ok(())
+        }
+
+        #[pyclassmethod(magic)]
+        fn init_subclass(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) {
+            cls.set_attr(vm.ctx.intern_str("_weak_cache"), vm.ctx.new_dict().into());
+        }
+
+        #[pyclassmethod]
+        fn _unpickle(
+            cls: PyTypeRef,
+            key: PyObjectRef,
+            from_cache: bool,
+            vm: &VirtualMachine,
+        ) -> PyResult {
+            if from_cache {
+                cls.as_object().call((key,), vm)
+            } else {
+                Self::no_cache(cls, key, vm)
+            }
+        }
+
+        #[pygetset]
+        fn key(&self, vm: &VirtualMachine) -> PyObjectRef {
+            self.key.clone().unwrap_or_else(|| vm.ctx.none())
+        }
+
+        #[pymethod]
+        fn utcoffset(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Ok(match self.find(&dt, vm)? {
+                Some(tti) => seconds_to_delta(tti.utcoff, vm),
+                None => vm.ctx.none(),
+            })
+        }
+
+        #[pymethod]
+        fn dst(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Ok(match self.find(&dt, vm)? {
+                Some(tti) => seconds_to_delta(tti.dstoff, vm),
+                None => vm.ctx.none(),
+            })
+        }
+
+        #[pymethod]
+        fn tzname(&self, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            Ok(match self.find(&dt, vm)? {
+                Some(tti) => vm.ctx.new_str(tti.abbr).into(),
+                None => vm.ctx.none(),
+            })
+        }
+
+        #[pymethod]
+        fn fromutc(zelf: PyRef<Self>, dt: PyObjectRef, vm: &VirtualMachine) -> PyResult {
+            let datetime = dt.payload_if_subclass::<PyDateTime>(vm).ok_or_else(|| {
+                vm.new_type_error("fromutc: argument must be a datetime".to_owned())
+            })?;
+            if !datetime.tzinfo().map_or(false, |tz| tz.is(&zelf)) {
+                return Err(vm.new_value_error("fromutc: dt.tzinfo is not self".to_owned()));
+            }
+            let (tti, fold) = zelf.zone.find_utc(datetime.wall_seconds(), datetime.year());
+            datetime.shifted(dt.class(), tti.utcoff * 1_000_000, fold, vm)
+        }
+
+        #[pymethod(magic)]
+        fn str(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<PyStrRef> {
+            match &zelf.key {
+                Some(key) => key.str(vm),
+                None => zelf.as_object().repr(vm),
+            }
+        }
+
+        #[pymethod(magic)]
+        fn reduce(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<PyTupleRef> {
+            let Source::Key { from_cache } = zelf.source else {
+                return Err(vm.new_type_error(
+                    "Cannot pickle a ZoneInfo file created from a file stream.".to_owned(),
+                ));
+            };
+            let unpickle = zelf.class().as_object().get_attr("_unpickle", vm)?;
+            Ok(vm.new_tuple((unpickle, (zelf.key(vm), from_cache))))
+        }
+    }
+
+    impl Representable for PyZoneInfo {
+        fn repr_str(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<String> {
+            let name = zelf.class().name();
+            Ok(match (&zelf.key, &zelf.source) {
+                (Some(key), _) => format!("{name}(key={})", key.repr(vm)?),
+                (None, Source::File { repr }) => format!("{name}.from_file({repr})"),
+                (None, Source::Key { .. }) => format!("{name}()"),
+            })
+        }
+    }
+}
@@ -0,0 +1,556 @@
+//! Reading TZif files (RFC 8536) and the POSIX TZ rules in their footers, independent of the
+//! VM. All times are in seconds: transitions since the Unix epoch, offsets east of UTC.
+
+// spell-checker:ignore isdst isstdcnt isutcnt leapcnt timecnt typecnt charcnt
+
+/// One local time type: the offset from UTC, the part of it that is daylight saving, and the
+/// abbreviation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TtInfo {
+    pub utcoff: i64,
+    pub dstoff: i64,
+    pub abbr: String,
+}
+
+/// What applies after the last transition: either a fixed type or a DST rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum After {
+    Fixed(TtInfo),
+    Rule(TzRule),
+}
+
+#[derive(Debug, Clone)]
+pub struct Zone {
+    /// Transition times in UTC.
+    trans_utc: Vec<i64>,
+    /// Transition times in local time, for fold 0 and fold 1.
+    trans_local: [Vec<i64>; 2],
+    /// The type that starts at each transition.
+    trans_tti: Vec<TtInfo>,
+    /// The type before the first transition.
+    tti_before: Option<TtInfo>,
+    after: After,
+    fixed_offset: bool,
+}
+
+pub type ParseError = &'static str;
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], ParseError> {
+        if self.data.len() < n {
+            return Err("Invalid TZif file: unexpected end of file");
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn time(&mut self, size: usize) -> Result<i64, ParseError> {
+        let bytes = self.take(size)?;
+        Ok(if size == 4 {
+            i32::from_be_bytes(bytes.try_into().unwrap()) as i64
+        } else {
+            i64::from_be_bytes(bytes.try_into().unwrap())
+        })
+    }
+}
+
+struct Header {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl Header {
+    fn read(reader: &mut Reader) -> Result<Self, ParseError> {
+        if reader.take(4)? != b"TZif" {
+            return Err("Invalid TZif file: magic not found");
+        }
+        let version = match reader.take(1)?[0] {
+            0 => 1,
+            c @ b'2'..=b'9' => c - b'0',
+            _ => return Err("Invalid TZif file: unknown version"),
+        };
+        reader.take(15)?;
+        let mut count = || reader.u32().map(|n| n as usize);
+        Ok(Header {
+            version,
+            isutcnt: count()?,
+            isstdcnt: count()?,
+            leapcnt: count()?,
+            timecnt: count()?,
+            typecnt: count()?,
+            charcnt: count()?,
+        })
+    }
+
+    fn data_len(&self, time_size: usize) -> usize {
+        self.timecnt * (time_size + 1)
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+impl Zone {
+    pub fn parse(data: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader { data };
+        let mut header = Header::read(&mut reader)?;
+        let mut time_size = 4;
+        if header.version >= 2 {
+            // the version 1 block is only there for old readers
+            reader.take(header.data_len(4))?;
+            header = Header::read(&mut reader)?;
+            time_size = 8;
+        }
+        if header.typecnt == 0 {
+            return Err("Invalid TZif file: no local time types");
+        }
+
+        let trans_utc = (0..header.timecnt)
+            .map(|_| reader.time(time_size))
+            .collect::<Result<Vec<_>, _>>()?;
+        let trans_idx = reader.take(header.timecnt)?.to_vec();
+        if trans_idx.iter().any(|&i| i as usize >= header.typecnt) {
+            return Err("Invalid TZif file: transition type out of range");
+        }
+        let mut types = Vec::with_capacity(header.typecnt);
+        for _ in 0..header.typecnt {
+            let utcoff = reader.time(4)?;
+            let isdst = reader.take(1)?[0] != 0;
+            let abbr_index = reader.take(1)?[0] as usize;
+            types.push((utcoff, isdst, abbr_index));
+        }
+        let chars = reader.take(header.charcnt)?;
+        reader.take(header.leapcnt * (time_size + 4) + header.isstdcnt + header.isutcnt)?;
+
+        let footer = if header.version >= 2 {
+            let rest = reader.data;
+            let rest = rest.strip_prefix(b"\n").unwrap_or(rest);
+            let end = rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+            Some(std::str::from_utf8(&rest[..end]).map_err(|_| "Invalid TZ string")?)
+        } else {
+            None
+        };
+
+        let utcoffs: Vec<i64> = types.iter().map(|t| t.0).collect();
+        let isdsts: Vec<bool> = types.iter().map(|t| t.1).collect();
+        let dstoffs = utcoff_to_dstoff(&trans_idx, &utcoffs, &isdsts);
+        let ttinfos = types
+            .iter()
+            .zip(dstoffs)
+            .map(|(&(utcoff, _, abbr_index), dstoff)| {
+                let abbr = chars.get(abbr_index..).unwrap_or_default();
+                let end = abbr.iter().position(|&c| c == 0).unwrap_or(abbr.len());
+                TtInfo {
+                    utcoff,
+                    dstoff,
+                    abbr: String::from_utf8_lossy(&abbr[..end]).into_owned(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let trans_local = ts_to_local(&trans_idx, &trans_utc, &utcoffs);
+        let trans_tti: Vec<TtInfo> = trans_idx
+            .iter()
+            .map(|&i| ttinfos[i as usize].clone())
+            .collect();
+        let tti_before = isdsts
+            .iter()
+            .position(|&isdst| !isdst)
+            .map(|i| ttinfos[i].clone())
+            .or_else(|| trans_tti.first().cloned());
+
+        let after = match footer {
+            Some(footer) if !footer.is_empty() => TzRule::parse(footer)?,
+            _ => After::Fixed(trans_tti.last().or_else(|| ttinfos.last()).unwrap().clone()),
+        };
+        let fixed_offset = match &after {
+            After::Fixed(after) => ttinfos.len() == 1 && ttinfos[0] == *after,
+            After::Rule(_) => false,
+        };
+
+        Ok(Zone {
+            trans_utc,
+            trans_local,
+            trans_tti,
+            tti_before,
+            after,
+            fixed_offset,
+        })
+    }
+
+    /// The type in effect for `dt` when it carries no date, which is only known for zones
+    /// with a single fixed offset.
+    pub fn find_fixed(&self) -> Option<&TtInfo> {
+        match &self.after {
+            After::Fixed(tti) if self.fixed_offset => Some(tti),
+            _ => None,
+        }
+    }
+
+    /// The type in effect at a local wall time, given in seconds since the epoch.
+    pub fn find_local(&self, timestamp: i64, year: i32, fold: bool) -> TtInfo {
+        let local = &self.trans_local[fold as usize];
+        match local.first() {
+            Some(&first) if timestamp < first => self.tti_before.clone().unwrap(),
+            Some(_) if timestamp <= *local.last().unwrap() => {
+                let idx = local.partition_point(|&t| t <= timestamp);
+                self.trans_tti[idx - 1].clone()
+            }
+            _ => match &self.after {
+                After::Fixed(tti) => tti.clone(),
+                After::Rule(rule) => rule.find_local(timestamp, year, fold).clone(),
+            },
+        }
+    }
+
+    /// The type in effect at a UTC time, and whether the local time is the second of two
+    /// identical wall clock readings.
+    pub fn find_utc(&self, timestamp: i64, year: i32) -> (TtInfo, bool) {
+        let utc = &self.trans_utc;
+        match utc.first() {
+            Some(&first) if timestamp < first => (self.tti_before.clone().unwrap(), false),
+            Some(_) if timestamp <= *utc.last().unwrap() => {
+                let idx = utc.partition_point(|&t| t <= timestamp);
+                let tti = &self.trans_tti[idx - 1];
+                let prev = match idx {
+                    1 => self.tti_before.as_ref().unwrap(),
+                    _ => &self.trans_tti[idx - 2],
+                };
+                let fold = prev.utcoff - tti.utcoff > timestamp - utc[idx - 1];
+                (tti.clone(), fold)
+            }
+            _ => match &self.after {
+                After::Fixed(tti) => (tti.clone(), false),
+                After::Rule(rule) => {
+                    let (tti, fold) = rule.find_utc(timestamp, year);
+                    (tti.clone(), fold)
+                }
+            },
+        }
+    }
+}
+
+/// Infer the DST part of each type's offset from the offsets of neighbouring standard types,
+/// since TZif only records whether a type is DST.
+fn utcoff_to_dstoff(trans_idx: &[u8], utcoffs: &[i64], isdsts: &[bool]) -> Vec<i64> {
+    let typecnt = isdsts.len();
+    let mut dstoffs = vec![0; typecnt];
+    let dst_count = isdsts.iter().filter(|&&isdst| isdst).count();
+    let mut dst_found = 0;
+    for i in 1..trans_idx.len() {
+        if dst_found == dst_count {
+            return dstoffs;
+        }
+        let idx = trans_idx[i] as usize;
+        if !isdsts[idx] || dstoffs[idx] != 0 {
+            continue;
+        }
+        let utcoff = utcoffs[idx];
+        let mut dstoff = 0;
+        let prev = trans_idx[i - 1] as usize;
+        if !isdsts[prev] {
+            dstoff = utcoff - utcoffs[prev];
+        }
+        if dstoff == 0 {
+            if let Some(&next) = trans_idx.get(i + 1) {
+                if !isdsts[next as usize] {
+                    dstoff = utcoff - utcoffs[next as usize];
+                }
+            }
+        }
+        if dstoff != 0 {
+            dst_found += 1;
+            dstoffs[idx] = dstoff;
+        }
+    }
+    // a DST type without a usable neighbour; an hour is a far better guess than nothing
+    for idx in 0..typecnt {
+        if isdsts[idx] && dstoffs[idx] == 0 {
+            dstoffs[idx] = 3600;
+        }
+    }
+    dstoffs
+}
+
+/// Convert the UTC transition times to local time. For fold 0 each transition happens at the
+/// wall time of the larger of the offsets around it, for fold 1 at that of the smaller.
+fn ts_to_local(trans_idx: &[u8], trans_utc: &[i64], utcoffs: &[i64]) -> [Vec<i64>; 2] {
+    let mut local = [trans_utc.to_vec(), trans_utc.to_vec()];
+    for i in 0..trans_idx.len() {
+        let (before, after) = if i == 0 {
+            (utcoffs[0], utcoffs[trans_idx[0] as usize])
+        } else {
+            (
+                utcoffs[trans_idx[i - 1] as usize],
+                utcoffs[trans_idx[i] as usize],
+            )
+        };
+        local[0][i] += before.max(after);
+        local[1][i] += before.min(after);
+    }
+    local
+}
+
+/// A day of the year on which a DST rule switches, with the local time of the switch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleDate {
+    /// `Jn`: day 1 to 365, never counting February 29.
+    Julian(u32, i64),
+    /// `n`: day 0 to 365, counting February 29.
+    Ordinal(u32, i64),
+    /// `Mm.w.d`: weekday `d` (Sunday is 0) of week `w` (5 is the last) of month `m`.
+    Month(u32, u32, u32, i64),
+}
+
+impl RuleDate {
+    /// The switch in the given year, as local seconds since the epoch.
+    fn to_epoch(self, year: i32) -> i64 {
+        use crate::datetime::calendar;
+        let jan1 = calendar::ymd_to_ord(year, 1, 1) - EPOCH_ORDINAL;
+        let (days, time) = match self {
+            RuleDate::Julian(n, time) => {
+                let leap_day = (n >= 60 && calendar::is_leap(year)) as i64;
+                (jan1 + n as i64 - 1 + leap_day, time)
+            }
+            RuleDate::Ordinal(n, time) => (jan1 + n as i64, time),
+            RuleDate::Month(m, w, d, time) => {
+                let first = calendar::ymd_to_ord(year, m, 1);
+                // calendar weekdays start on Monday, POSIX ones on Sunday
+                let first_weekday = (calendar::weekday(year, m, 1) + 1) % 7;
+                let mut day = (d + 7 - first_weekday) % 7 + (w - 1) * 7;
+                while day >= calendar::days_in_month(year, m) {
+                    day -= 7;
+                }
+                (first + day as i64 - EPOCH_ORDINAL, time)
+            }
+        };
+        days * 86400 + time
+    }
+}
+
+const EPOCH_ORDINAL: i64 = 719_163;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TzRule {
+    std: TtInfo,
+    dst: TtInfo,
+    /// The DST offset minus the standard one.
+    dst_diff: i64,
+    start: RuleDate,
+    end: RuleDate,
+}
+
+impl TzRule {
+    /// Parse a POSIX TZ string such as `EST5EDT,M3.2.0,M11.1.0`.
+    pub fn parse(s: &str) -> Result<After, ParseError> {
+        const INVALID: ParseError = "Invalid TZ string";
+        let mut parser = Parser { s };
+        let std_abbr = parser.abbr().ok_or(INVALID)?;
+        let std_offset = -parser.offset(24).ok_or(INVALID)?;
+        let std = TtInfo {
+            utcoff: std_offset,
+            dstoff: 0,
+            abbr: std_abbr,
+        };
+        if parser.s.is_empty() {
+            return Ok(After::Fixed(std));
+        }
+        let dst_abbr = parser.abbr().ok_or(INVALID)?;
+        let dst_offset = if parser.s.starts_with(',') {
+            std_offset + 3600
+        } else {
+            -parser.offset(24).ok_or(INVALID)?
+        };
+        if !parser.eat(',') {
+            return Err(INVALID);
+        }
+        let start = parser.rule_date().ok_or(INVALID)?;
+        if !parser.eat(',') {
+            return Err(INVALID);
+        }
+        let end = parser.rule_date().ok_or(INVALID)?;
+        if !parser.s.is_empty() {
+            return Err(INVALID);
+        }
+        let dst_diff = dst_offset - std_offset;
+        Ok(After::Rule(TzRule {
+            std,
+            dst: TtInfo {
+                utcoff: dst_offset,
+                dstoff: dst_diff,
+                abbr: dst_abbr,
+            },
+            dst_diff,
+            start,
+            end,
+        }))
+    }
+
+    fn transitions(&self, year: i32) -> (i64, i64) {
+        (self.start.to_epoch(year), self.end.to_epoch(year))
+    }
+
+    fn find_local(&self, timestamp: i64, year: i32, fold: bool) -> &TtInfo {
+        let (mut start, mut end) = self.transitions(year);
+        // the period with the smaller offset starts at the end of the gap and ends at the end
+        // of the fold for fold 0, and runs from the start of the gap to the start of the fold
+        // for fold 1
+        if fold == (self.dst_diff >= 0) {
+            end -= self.dst_diff;
+        } else {
+            start += self.dst_diff;
+        }
+        let is_dst = if start < end {
+            (start..end).contains(&timestamp)
+        } else {
+            !(end..start).contains(&timestamp)
+        };
+        if is_dst {
+            &self.dst
+        } else {
+            &self.std
+        }
+    }
+
+    fn find_utc(&self, timestamp: i64, year: i32) -> (&TtInfo, bool) {
+        let (start, end) = self.transitions(year);
+        let start = start - self.std.utcoff;
+        let end = end - self.dst.utcoff;
+        let is_dst = if start < end {
+            (start..end).contains(&timestamp)
+        } else {
+            !(end..start).contains(&timestamp)
+        };
+        // the repeated hour follows the end of positive DST or precedes the start of
+        // negative DST
+        let ambiguous = if self.dst_diff > 0 {
+            end..end + self.dst_diff
+        } else {
+            start..start - self.dst_diff
+        };
+        let tti = if is_dst { &self.dst } else { &self.std };
+        (tti, ambiguous.contains(&timestamp))
+    }
+}
+
+struct Parser<'a> {
+    s: &'a str,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, c: char) -> bool {
+        match self.s.strip_prefix(c) {
+            Some(rest) => {
+                self.s = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn abbr(&mut self) -> Option<String> {
+        let (abbr, rest) = if let Some(quoted) = self.s.strip_prefix('<') {
+            let end = quoted.find('>')?;
+            let abbr = &quoted[..end];
+            if !abbr
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-')
+            {
+                return None;
+            }
+            (abbr, &quoted[end + 1..])
+        } else {
+            let end = self
+                .s
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(self.s.len());
+            self.s.split_at(end)
+        };
+        if abbr.len() < 3 {
+            return None;
+        }
+        self.s = rest;
+        Some(abbr.to_owned())
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let end = self
+            .s
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.s.len());
+        if end == 0 || end > 3 {
+            return None;
+        }
+        let (digits, rest) = self.s.split_at(end);
+        self.s = rest;
+        digits.parse().ok()
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, with hours up to `max_hours`.
+    fn offset(&mut self, max_hours: i64) -> Option<i64> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        let hours = self.number()?;
+        if hours > max_hours {
+            return None;
+        }
+        let mut seconds = hours * 3600;
+        for scale in [60, 1] {
+            if !self.eat(':') {
+                break;
+            }
+            let n = self.number().filter(|&n| n < 60)?;
+            seconds += n * scale;
+        }
+        Some(sign * seconds)
+    }
+
+    fn rule_date(&mut self) -> Option<RuleDate> {
+        let date = if self.eat('M') {
+            let m = self.number().filter(|m| (1..=12).contains(m))?;
+            self.eat('.').then_some(())?;
+            let w = self.number().filter(|w| (1..=5).contains(w))?;
+            self.eat('.').then_some(())?;
+            let d = self.number().filter(|d| (0..=6).contains(d))?;
+            RuleDate::Month(m as u32, w as u32, d as u32, 0)
+        } else if self.eat('J') {
+            let n = self.number().filter(|n| (1..=365).contains(n))?;
+            RuleDate::Julian(n as u32, 0)
+        } else {
+            let n = self.number().filter(|n| (0..=365).contains(n))?;
+            RuleDate::Ordinal(n as u32, 0)
+        };
+        // the time of day may run past midnight in either direction, up to a week
+        let time = if self.eat('/') {
+            self.offset(167)?
+        } else {
+            2 * 3600
+        };
+        Some(match date {
+            RuleDate::Month(m, w, d, _) => RuleDate::Month(m, w, d, time),
+            RuleDate::Julian(n, _) => RuleDate::Julian(n, time),
+            RuleDate::Ordinal(n, _) => RuleDate::Ordinal(n, time),
+        })
+    }
+}
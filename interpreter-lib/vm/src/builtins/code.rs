@@ -10,12 +10,15 @@ use crate::{
     convert::ToPyObject,
     frozen,
     function::{FuncArgs, OptionalArg},
+    protocol::PyIter,
     source_code::OneIndexed,
     types::Representable,
     AsObject, Context, Py, PyObject, PyObjectRef, PyPayload, PyResult, VirtualMachine,
 };
 use malachite_bigint::BigInt;
 use num_traits::Zero;
+#[cfg(feature = "jit")]
+use rustpython_common::atomic::{self, PyAtomic, Radium};
 use std::{borrow::Borrow, fmt, ops::Deref};
 
 #[derive(FromArgs)]
@@ -192,6 +195,10 @@ impl<B: AsRef<[u8]>> IntoCodeObject for frozen::FrozenCodeObject<B> {
 #[pyclass(module = false, name = "code")]
 pub struct PyCode {
     pub code: CodeObject,
+    /// How many times the code was entered plus how many times its loops went around; used to
+    /// decide when a function is hot enough to be jitted.
+    #[cfg(feature = "jit")]
+    hotness: PyAtomic<u32>,
 }
 
 impl Deref for PyCode {
@@ -203,7 +210,24 @@ impl Deref for PyCode {
 
 impl PyCode {
     pub fn new(code: CodeObject) -> PyCode {
-        PyCode { code }
+        PyCode {
+            code,
+            #[cfg(feature = "jit")]
+            hotness: Radium::new(0),
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn hotness(&self) -> u32 {
+        self.hotness.load(atomic::Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn record_hotness(&self) {
+        // a lost update here and there doesn't matter, this is only a heuristic
+        let hotness = self.hotness.load(atomic::Ordering::Relaxed);
+        self.hotness
+            .store(hotness.saturating_add(1), atomic::Ordering::Relaxed);
     }
 }
 
@@ -333,6 +357,26 @@ impl PyCode {
         vm.ctx.new_tuple(names)
     }
 
+    #[pymethod]
+    fn co_positions(&self, vm: &VirtualMachine) -> PyResult<PyIter> {
+        // (start line, end line, start column, end column) for each instruction
+        let positions = self
+            .code
+            .locations
+            .iter()
+            .map(|(start, end)| {
+                vm.new_tuple((
+                    start.row.get(),
+                    end.row.get(),
+                    start.column.to_zero_indexed(),
+                    end.column.to_zero_indexed(),
+                ))
+                .into()
+            })
+            .collect();
+        vm.ctx.new_tuple(positions).as_object().get_iter(vm)
+    }
+
     #[pymethod]
     pub fn replace(&self, args: ReplaceArgs, vm: &VirtualMachine) -> PyResult<PyCode> {
         let posonlyarg_count = match args.co_posonlyargcount {
@@ -391,33 +435,33 @@ impl PyCode {
             OptionalArg::Missing => self.code.varnames.iter().map(|s| s.to_object()).collect(),
         };
 
-        Ok(PyCode {
-            code: CodeObject {
-                flags: CodeFlags::from_bits_truncate(flags),
-                posonlyarg_count,
-                arg_count,
-                kwonlyarg_count,
-                source_path: source_path.as_object().as_interned_str(vm).unwrap(),
-                first_line_number,
-                obj_name: obj_name.as_object().as_interned_str(vm).unwrap(),
-
-                max_stackdepth: self.code.max_stackdepth,
-                instructions: self.code.instructions.clone(),
-                locations: self.code.locations.clone(),
-                constants: constants.into_iter().map(Literal).collect(),
-                names: names
-                    .into_iter()
-                    .map(|o| o.as_interned_str(vm).unwrap())
-                    .collect(),
-                varnames: varnames
-                    .into_iter()
-                    .map(|o| o.as_interned_str(vm).unwrap())
-                    .collect(),
-                cellvars: self.code.cellvars.clone(),
-                freevars: self.code.freevars.clone(),
-                cell2arg: self.code.cell2arg.clone(),
-            },
-        })
+        Ok(PyCode::new(CodeObject {
+            flags: CodeFlags::from_bits_truncate(flags),
+            posonlyarg_count,
+            arg_count,
+            kwonlyarg_count,
+            source_path: source_path.as_object().as_interned_str(vm).unwrap(),
+            first_line_number,
+            obj_name: obj_name.as_object().as_interned_str(vm).unwrap(),
+
+            max_stackdepth: self.code.max_stackdepth,
+            instructions: self.code.instructions.clone(),
+            locations: self.code.locations.clone(),
+            constants: constants.into_iter().map(Literal).collect(),
+            names: names
+                .into_iter()
+                .map(|o| o.as_interned_str(vm).unwrap())
+                .collect(),
+            varnames: varnames
+                .into_iter()
+                .map(|o| o.as_interned_str(vm).unwrap())
+                .collect(),
+            cellvars: self.code.cellvars.clone(),
+            freevars: self.code.freevars.clone(),
+            cell2arg: self.code.cell2arg.clone(),
+            exception_handlers: self.code.exception_handlers.clone(),
+            exception_table: self.code.exception_table.clone(),
+        }))
     }
 }
 
@@ -4,13 +4,15 @@
 
 use super::{PyCode, PyDictRef, PyIntRef, PyStrRef};
 use crate::{
+    bytecode::{self, CodeObject, HandlerKind, Instruction},
     class::PyClassImpl,
     frame::{Frame, FrameRef},
     function::PySetterValue,
+    protocol::TraceEvent,
     types::{Constructor, Representable, Unconstructible},
     AsObject, Context, Py, PyObjectRef, PyRef, PyResult, VirtualMachine,
 };
-use num_traits::Zero;
+use num_traits::{ToPrimitive, Zero};
 
 pub fn init(context: &Context) {
     Frame::extend_class(context, context.types.frame_type);
@@ -60,7 +62,78 @@ impl Frame {
 
     #[pygetset]
     pub fn f_lineno(&self) -> usize {
-        self.current_location().row.to_usize()
+        if self.lasti() == 0 {
+            // not started yet, e.g. during the `call` trace event
+            self.code
+                .first_line_number
+                .map_or(0, |line| line.to_usize())
+        } else {
+            self.current_location().row.to_usize()
+        }
+    }
+
+    #[pygetset(setter)]
+    fn set_f_lineno(&self, value: PySetterValue, vm: &VirtualMachine) -> PyResult<()> {
+        let PySetterValue::Assign(value) = value else {
+            return Err(vm.new_attribute_error("cannot delete attribute".to_owned()));
+        };
+        let lineno: PyIntRef = value
+            .downcast()
+            .map_err(|_| vm.new_value_error("lineno must be an integer".to_owned()))?;
+
+        match *self.current_trace_event.lock() {
+            Some(TraceEvent::Line) => {}
+            Some(TraceEvent::Call) => {
+                return Err(vm.new_value_error(
+                    "can't jump from the 'call' trace event of a new frame".to_owned(),
+                ))
+            }
+            Some(_) => {
+                return Err(vm.new_value_error("can only jump from a 'line' trace event".to_owned()))
+            }
+            None => {
+                return Err(
+                    vm.new_value_error("f_lineno can only be set by a trace function".to_owned())
+                )
+            }
+        }
+
+        let code = &self.code.code;
+        let first_line = code.first_line_number.map_or(0, |line| line.to_usize());
+        let lineno = lineno.as_bigint();
+        let target_line = match lineno.to_usize() {
+            Some(lineno) if lineno >= first_line => lineno,
+            _ => {
+                return Err(vm
+                    .new_value_error(format!("line {lineno} comes before the current code block")))
+            }
+        };
+        // jump to the first instruction of the first line at or after the requested one
+        let target = code
+            .locations
+            .iter()
+            .map(|(loc, _)| loc.row.to_usize())
+            .enumerate()
+            .filter(|&(_, line)| line >= target_line)
+            .min_by_key(|&(idx, line)| (line, idx))
+            .map(|(idx, _)| idx)
+            .ok_or_else(|| {
+                vm.new_value_error(format!("line {lineno} comes after the current code block"))
+            })?;
+
+        let current = self.lasti() as usize - 1;
+        let states = jump_states(code);
+        let handler = |idx: usize| code.handler_at(idx as u32);
+        match (&states[current], &states[target]) {
+            (Some(from), Some(to)) if from == to && handler(current) == handler(target) => {}
+            (Some(from), Some(to)) if to.nesting(code, target) < from.nesting(code, current) => {
+                return Err(vm.new_value_error("can't jump out of a block".to_owned()))
+            }
+            _ => return Err(vm.new_value_error("can't jump into the middle of a block".to_owned())),
+        }
+
+        *self.jump_target.lock() = Some(target as u32);
+        Ok(())
     }
 
     #[pygetset]
@@ -107,6 +180,184 @@ impl Frame {
             }
         }
     }
+
+    #[pymember(type = "bool")]
+    fn f_trace_opcodes(vm: &VirtualMachine, zelf: PyObjectRef) -> PyResult {
+        let zelf: FrameRef = zelf.downcast().unwrap_or_else(|_| unreachable!());
+
+        let boxed = zelf.trace_opcodes.lock();
+        Ok(vm.ctx.new_bool(*boxed).into())
+    }
+
+    #[pymember(type = "bool", setter)]
+    fn set_f_trace_opcodes(
+        vm: &VirtualMachine,
+        zelf: PyObjectRef,
+        value: PySetterValue,
+    ) -> PyResult<()> {
+        match value {
+            PySetterValue::Assign(value) => {
+                let zelf: FrameRef = zelf.downcast().unwrap_or_else(|_| unreachable!());
+
+                let value: PyIntRef = value.downcast().map_err(|_| {
+                    vm.new_type_error("attribute value type must be bool".to_owned())
+                })?;
+
+                let mut trace_opcodes = zelf.trace_opcodes.lock();
+                *trace_opcodes = !value.as_bigint().is_zero();
+
+                Ok(())
+            }
+            PySetterValue::Delete => {
+                Err(vm.new_type_error("can't delete numeric/char attribute".to_owned()))
+            }
+        }
+    }
+}
+
+/// The value stack depth and the running handlers before an instruction, as far as they can be
+/// told statically. A jump by assigning `f_lineno` is only allowed between instructions with the
+/// same state and the same handlers of the exception table around them, otherwise the frame
+/// would end up with the wrong values or blocks on its stacks.
+#[derive(Clone, PartialEq, Eq)]
+struct JumpState {
+    depth: i32,
+    /// The handler code that runs, as the offset of the instruction that entered it (or of the
+    /// handler itself when it was entered by unwinding), and the handler around it.
+    running: Vec<(u32, Option<u32>)>,
+}
+
+impl JumpState {
+    /// How many blocks the instruction at `idx` is in, running or not.
+    fn nesting<C: bytecode::Constant>(&self, code: &CodeObject<C>, idx: usize) -> usize {
+        let handlers = std::iter::successors(code.handler_at(idx as u32), |&h| {
+            code.exception_handlers[h as usize].parent
+        });
+        handlers.count() + self.running.len()
+    }
+}
+
+/// Compute the [`JumpState`] of every instruction of `code`, `None` for unreachable instructions
+/// and ones that are reached with different states.
+fn jump_states<C: bytecode::Constant>(code: &CodeObject<C>) -> Vec<Option<JumpState>> {
+    let len = code.instructions.len();
+    let mut states: Vec<Option<JumpState>> = vec![None; len];
+    let mut conflicts = vec![false; len];
+    let mut pending = Vec::new();
+
+    // record the state an instruction is reached with, and walk it if it's new
+    let mut visit = |states: &mut [Option<JumpState>],
+                     pending: &mut Vec<(usize, JumpState)>,
+                     idx: usize,
+                     state: JumpState| {
+        if idx >= len {
+            return;
+        }
+        match &states[idx] {
+            None => {
+                states[idx] = Some(state.clone());
+                pending.push((idx, state));
+            }
+            Some(existing) if *existing != state => conflicts[idx] = true,
+            Some(_) => {}
+        }
+    };
+    visit(
+        &mut states,
+        &mut pending,
+        0,
+        JumpState {
+            depth: 0,
+            running: Vec::new(),
+        },
+    );
+
+    while let Some((start, mut state)) = pending.pop() {
+        let mut arg_state = bytecode::OpArgState::default();
+        let mut idx = start;
+        while idx < len {
+            if idx != start {
+                if states[idx].is_some() {
+                    visit(&mut states, &mut pending, idx, state);
+                    break;
+                }
+                states[idx] = Some(state.clone());
+            }
+            let (instr, arg) = arg_state.get(code.instructions[idx]);
+
+            // an exception enters the innermost except or finally handler around the instruction,
+            // leaving the handlers that run inside of the ones on the way there
+            let mut handler = code.handler_at(idx as u32);
+            while let Some(index) = handler {
+                let h = &code.exception_handlers[index as usize];
+                if h.kind == HandlerKind::Loop {
+                    handler = h.parent;
+                    continue;
+                }
+                let mut running = state.running.clone();
+                let mut outer = code.handler_at(idx as u32);
+                while outer != h.parent {
+                    while running.last().map_or(false, |&(_, parent)| parent == outer) {
+                        running.pop();
+                    }
+                    outer = outer.and_then(|o| code.exception_handlers[o as usize].parent);
+                }
+                running.push((h.target.0, h.parent));
+                let depth = h.depth as i32 + (h.kind == HandlerKind::Except) as i32;
+                visit(
+                    &mut states,
+                    &mut pending,
+                    h.target.0 as usize,
+                    JumpState { depth, running },
+                );
+                break;
+            }
+
+            if let Some(target) = instr.label_arg() {
+                // Break and Continue unwind the block stack, there's no point in following them
+                if !matches!(
+                    instr,
+                    Instruction::Break { .. } | Instruction::Continue { .. }
+                ) {
+                    let target_state = JumpState {
+                        depth: state.depth + instr.stack_effect(arg, true),
+                        running: state.running.clone(),
+                    };
+                    visit(
+                        &mut states,
+                        &mut pending,
+                        target.get(arg).0 as usize,
+                        target_state,
+                    );
+                }
+            }
+
+            state.depth += instr.stack_effect(arg, false);
+            match instr {
+                Instruction::EnterFinally => state
+                    .running
+                    .push((idx as u32, code.handler_at(idx as u32))),
+                Instruction::EndFinally
+                | Instruction::WithCleanupFinish
+                | Instruction::PopException
+                | Instruction::EndAsyncFor => {
+                    state.running.pop();
+                }
+                _ => {}
+            }
+            if instr.unconditional_branch() {
+                break;
+            }
+            idx += 1;
+        }
+    }
+
+    for (state, conflict) in states.iter_mut().zip(conflicts) {
+        if conflict {
+            *state = None;
+        }
+    }
+    states
 }
 
 #[pyclass]
@@ -126,3 +377,45 @@ impl Py<Frame> {
             .cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    #[test]
+    fn test_settrace_line_events_and_jump() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let source = r#"
+events = []
+
+def tracer(frame, event, arg):
+    if frame.f_code.co_name == "f":
+        line = frame.f_lineno - frame.f_code.co_firstlineno
+        events.append((event, line))
+        if event == "line" and line == 2:
+            frame.f_lineno += 1
+    return tracer
+
+def f():
+    x = 1
+    x = 2
+    return x
+
+sys.settrace(tracer)
+result = f()
+sys.settrace(None)
+assert result == 1, result
+assert events == [("call", 0), ("line", 1), ("line", 2), ("return", 3)], events
+"#;
+            let scope = vm.new_scope_with_builtins();
+            scope
+                .globals
+                .set_item("sys", vm.sys_module.clone().into(), vm)
+                .unwrap();
+            if let Err(e) = vm.run_code_string(scope, source, "<unittest>".to_owned()) {
+                vm.print_exception(e);
+                panic!();
+            }
+        })
+    }
+}
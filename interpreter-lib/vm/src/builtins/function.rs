@@ -6,6 +6,8 @@ use super::{
     PyTupleRef, PyType, PyTypeRef,
 };
 #[cfg(feature = "jit")]
+use crate::common::atomic::{self, PyAtomic, Radium};
+#[cfg(feature = "jit")]
 use crate::common::lock::OnceCell;
 use crate::common::lock::PyMutex;
 use crate::convert::ToPyObject;
@@ -24,7 +26,7 @@ use crate::{
 };
 use itertools::Itertools;
 #[cfg(feature = "jit")]
-use rustpython_jit::CompiledCode;
+use jitfunc::JittedFunction;
 
 #[pyclass(module = false, name = "function", traverse = "manual")]
 #[derive(Debug)]
@@ -37,7 +39,10 @@ pub struct PyFunction {
     qualname: PyMutex<PyStrRef>,
     type_params: PyMutex<PyTupleRef>,
     #[cfg(feature = "jit")]
-    jitted_code: OnceCell<CompiledCode>,
+    jitted_code: OnceCell<JittedFunction>,
+    /// Set when jitting the function automatically failed, so that it isn't attempted again.
+    #[cfg(feature = "jit")]
+    jit_failed: PyAtomic<bool>,
 }
 
 unsafe impl Traverse for PyFunction {
@@ -69,6 +74,55 @@ impl PyFunction {
             type_params: PyMutex::new(type_params),
             #[cfg(feature = "jit")]
             jitted_code: OnceCell::new(),
+            #[cfg(feature = "jit")]
+            jit_failed: Radium::new(false),
+        }
+    }
+
+    /// The jitted code to run instead of interpreting the function, if there's any.
+    #[cfg(feature = "jit")]
+    fn jitted_code_for_call(
+        &self,
+        func_args: &FuncArgs,
+        vm: &VirtualMachine,
+    ) -> Option<&JittedFunction> {
+        if vm.use_tracing.get() {
+            // jitted code has no frame for the trace function to look at
+            return None;
+        }
+        self.jitted_code
+            .get()
+            .or_else(|| self.jit_if_hot(func_args, vm))
+    }
+
+    /// Jit the function once its code got hot, specialized on the types of the arguments it is
+    /// being called with. Calls with other argument types keep being interpreted.
+    #[cfg(feature = "jit")]
+    fn jit_if_hot(&self, func_args: &FuncArgs, vm: &VirtualMachine) -> Option<&JittedFunction> {
+        let threshold = vm.state.settings.jit_threshold;
+        if threshold == 0
+            || self.code.hotness() < threshold
+            || self.jit_failed.load(atomic::Ordering::Relaxed)
+        {
+            return None;
+        }
+        let arg_types = jitfunc::arg_types_from_values(self, func_args, vm)?;
+        match jitfunc::compile_with_arg_types(self, &arg_types, vm) {
+            Ok(jitted) => {
+                info!(
+                    "jit: function `{}` got hot and was jitted",
+                    self.code.obj_name
+                );
+                Some(self.jitted_code.get_or_init(|| jitted))
+            }
+            Err(err) => {
+                info!(
+                    "jit: function `{}` got hot but can't be jitted: {}",
+                    self.code.obj_name, err
+                );
+                self.jit_failed.store(true, atomic::Ordering::Relaxed);
+                None
+            }
         }
     }
 
@@ -293,10 +347,10 @@ impl PyFunction {
         vm: &VirtualMachine,
     ) -> PyResult {
         #[cfg(feature = "jit")]
-        if let Some(jitted_code) = self.jitted_code.get() {
-            match jitfunc::get_jit_args(self, &func_args, jitted_code, vm) {
-                Ok(args) => {
-                    return Ok(args.invoke().to_pyobject(vm));
+        if let Some(jitted) = self.jitted_code_for_call(&func_args, vm) {
+            match jitfunc::invoke(self, &func_args, jitted, vm) {
+                Ok(ret) => {
+                    return Ok(ret);
                 }
                 Err(err) => info!(
                     "jit: function `{}` is falling back to being interpreted because of the \
@@ -459,11 +513,7 @@ impl PyFunction {
     #[pymethod(magic)]
     fn jit(zelf: PyRef<Self>, vm: &VirtualMachine) -> PyResult<()> {
         zelf.jitted_code
-            .get_or_try_init(|| {
-                let arg_types = jitfunc::get_jit_arg_types(&zelf, vm)?;
-                rustpython_jit::compile(&zelf.code.code, &arg_types)
-                    .map_err(|err| jitfunc::new_jit_error(err.to_string(), vm))
-            })
+            .get_or_try_init(|| jitfunc::compile(&zelf, vm))
             .map(drop)
     }
 }
@@ -723,3 +773,72 @@ pub fn init(context: &Context) {
     PyBoundMethod::extend_class(context, context.types.bound_method_type);
     PyCell::extend_class(context, context.types.cell_type);
 }
+
+#[cfg(all(test, feature = "jit"))]
+mod tests {
+    use super::PyFunction;
+    use crate::{AsObject, Interpreter, Settings};
+
+    /// Call a simple function `calls` times and report after each call whether it was jitted.
+    fn jitted_after_calls(jit_threshold: u32, calls: usize) -> Vec<bool> {
+        let mut settings = Settings::default();
+        settings.jit_threshold = jit_threshold;
+        Interpreter::without_stdlib(settings).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            vm.run_code_string(
+                scope.clone(),
+                "def add(a, b):\n    return a + b\n",
+                "<unittest>".to_owned(),
+            )
+            .map_err(|e| vm.print_exception(e))
+            .unwrap();
+            let add = scope.globals.get_item("add", vm).unwrap();
+            let add = add.downcast_ref::<PyFunction>().unwrap();
+            (0..calls)
+                .map(|_| {
+                    add.as_object().call((1, 2), vm).unwrap();
+                    add.jitted_code.get().is_some()
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_jit_when_hot() {
+        // the call after the threshold is reached runs the jitted code
+        assert_eq!(jitted_after_calls(3, 5), [false, false, false, true, true]);
+    }
+
+    #[test]
+    fn test_jit_disabled() {
+        assert!(!jitted_after_calls(0, 100).contains(&true));
+    }
+
+    #[test]
+    fn test_callee_globals_are_guarded() {
+        Interpreter::without_stdlib(Settings::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            let source = "\
+K = 2
+def scale(x: int):
+    return x * K
+def f(x: int):
+    return scale(x) + 1
+scale.__jit__()
+f.__jit__()
+before = f(3)
+K = 10
+after = f(3)
+";
+            vm.run_code_string(scope.clone(), source, "<unittest>".to_owned())
+                .map_err(|e| vm.print_exception(e))
+                .unwrap();
+            let get = |name| {
+                let value = scope.globals.get_item(name, vm).unwrap();
+                value.try_into_value::<i64>(vm).unwrap()
+            };
+            // rebinding a global of the callee sends the caller back to the interpreter
+            assert_eq!((get("before"), get("after")), (7, 31));
+        })
+    }
+}
@@ -6,7 +6,10 @@ use crate::{
     AsObject, Py, PyObject, PyObjectRef, PyResult, TryFromObject, VirtualMachine,
 };
 use num_traits::ToPrimitive;
-use rustpython_jit::{AbiValue, Args, CompiledCode, JitArgumentError, JitType};
+use rustpython_jit::{
+    AbiValue, Args, CompiledCode, JitArgumentError, JitCompileError, JitGlobal, JitType,
+};
+use std::{cell::RefCell, sync::Arc};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ArgsError {
@@ -22,6 +25,8 @@ pub enum ArgsError {
     IntOverflow,
     #[error("type can't be used in a jit function")]
     NonJitType,
+    #[error("global `{0}` has changed since the function was jitted")]
+    GlobalChanged(String),
     #[error("{0}")]
     JitError(#[from] JitArgumentError),
 }
@@ -106,6 +111,147 @@ pub fn get_jit_arg_types(func: &Py<PyFunction>, vm: &VirtualMachine) -> PyResult
     }
 }
 
+/// The machine code of a function, along with the globals it was compiled against.
+#[derive(Debug)]
+pub(crate) struct JittedFunction {
+    code: Arc<CompiledCode>,
+    guards: Vec<GlobalGuard>,
+}
+
+/// A global name the machine code depends on. The guards of a jitted function include those of
+/// the jitted functions it calls, which may live in other modules.
+#[derive(Debug)]
+struct GlobalGuard {
+    globals: PyDictRef,
+    name: String,
+    /// `None` means the function itself, which isn't stored to avoid a reference cycle.
+    expected: Option<PyObjectRef>,
+}
+
+fn lookup_global(globals: &PyDictRef, name: &str, vm: &VirtualMachine) -> Option<PyObjectRef> {
+    match globals.get_item_opt(name, vm) {
+        Ok(Some(obj)) => Some(obj),
+        Ok(None) => vm.builtins.dict().get_item_opt(name, vm).ok().flatten(),
+        Err(_) => None,
+    }
+}
+
+fn is_same_function(obj: &PyObject, func: &PyFunction) -> bool {
+    obj.payload::<PyFunction>()
+        .map_or(false, |other| std::ptr::eq(other, func))
+}
+
+pub(crate) fn compile(func: &Py<PyFunction>, vm: &VirtualMachine) -> PyResult<JittedFunction> {
+    let arg_types = get_jit_arg_types(func, vm)?;
+    compile_with_arg_types(func, &arg_types, vm).map_err(|err| new_jit_error(err.to_string(), vm))
+}
+
+pub(crate) fn compile_with_arg_types(
+    func: &PyFunction,
+    arg_types: &[JitType],
+    vm: &VirtualMachine,
+) -> Result<JittedFunction, JitCompileError> {
+    let guards = RefCell::new(Vec::new());
+    let resolve = |name: &str| {
+        let obj = lookup_global(&func.globals, name, vm)?;
+        let (global, expected) = if is_same_function(&obj, func) {
+            (JitGlobal::Recursive, None)
+        } else if obj.is(vm.ctx.types.range_type) {
+            (JitGlobal::Range, Some(obj))
+        } else if let Some(callee) = obj
+            .payload::<PyFunction>()
+            .and_then(|other| other.jitted_code.get())
+        {
+            // the callee's code is called directly, without checking its guards
+            guards
+                .borrow_mut()
+                .extend(callee.guards.iter().map(|guard| GlobalGuard {
+                    globals: guard.globals.clone(),
+                    name: guard.name.clone(),
+                    expected: Some(guard.expected.clone().unwrap_or_else(|| obj.clone())),
+                }));
+            (JitGlobal::Function(callee.code.clone()), Some(obj))
+        } else {
+            (
+                JitGlobal::Constant(get_jit_value(vm, &obj).ok()?),
+                Some(obj),
+            )
+        };
+        guards.borrow_mut().push(GlobalGuard {
+            globals: func.globals.clone(),
+            name: name.to_owned(),
+            expected,
+        });
+        Some(global)
+    };
+    let code = rustpython_jit::compile_with_globals(&func.code.code, arg_types, &resolve)?;
+    Ok(JittedFunction {
+        code: Arc::new(code),
+        guards: guards.into_inner(),
+    })
+}
+
+/// The types of the arguments of a call, if they can all be passed to jitted code. Only plain
+/// positional calls are considered.
+pub(crate) fn arg_types_from_values(
+    func: &PyFunction,
+    func_args: &FuncArgs,
+    vm: &VirtualMachine,
+) -> Option<Vec<JitType>> {
+    let code = &func.code;
+    if code
+        .flags
+        .intersects(CodeFlags::HAS_VARARGS | CodeFlags::HAS_VARKEYWORDS)
+        || code.kwonlyarg_count != 0
+        || !func_args.kwargs.is_empty()
+        || func_args.args.len() != code.arg_count as usize
+    {
+        return None;
+    }
+    func_args
+        .args
+        .iter()
+        .map(|arg| match get_jit_value(vm, arg).ok()? {
+            AbiValue::Int(_) => Some(JitType::Int),
+            AbiValue::Float(_) => Some(JitType::Float),
+            AbiValue::Bool(_) => Some(JitType::Bool),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Run the jitted code, unless the globals it depends on were rebound or the arguments don't fit
+/// it. Bailing out of the jitted code is also reported as an error, so that the caller falls back
+/// to interpreting the function.
+#[cfg(feature = "jit")]
+pub(crate) fn invoke(
+    func: &PyFunction,
+    func_args: &FuncArgs,
+    jitted: &JittedFunction,
+    vm: &VirtualMachine,
+) -> Result<PyObjectRef, ArgsError> {
+    for guard in &jitted.guards {
+        let current = lookup_global(&guard.globals, &guard.name, vm);
+        let unchanged = match (&guard.expected, &current) {
+            (Some(expected), Some(current)) => expected.is(current),
+            (None, Some(current)) => is_same_function(current, func),
+            (_, None) => false,
+        };
+        if !unchanged {
+            return Err(ArgsError::GlobalChanged(guard.name.clone()));
+        }
+    }
+    let args = get_jit_args(func, func_args, &jitted.code, vm)?;
+    // the interpreter raises RecursionError if jitted calls nest too deep
+    let depth_left = vm
+        .recursion_limit
+        .get()
+        .saturating_sub(vm.current_recursion_depth());
+    Ok(args
+        .invoke_with_recursion_limit(depth_left)?
+        .to_pyobject(vm))
+}
+
 fn get_jit_value(vm: &VirtualMachine, obj: &PyObject) -> Result<AbiValue, ArgsError> {
     // This does exact type checks as subclasses of int/float can't be passed to jitted functions
     let cls = obj.class();
@@ -439,6 +439,16 @@ impl PyMemoryView {
         }
     }
 
+    /// The viewed bytes in C order, or `None` if the view has been released.
+    pub(crate) fn collect_bytes(&self) -> Option<Vec<u8>> {
+        if self.released.load() {
+            return None;
+        }
+        let mut bytes = vec![];
+        self.append_to(&mut bytes);
+        Some(bytes)
+    }
+
     fn contiguous_or_collect<R, F: FnOnce(&[u8]) -> R>(&self, f: F) -> R {
         let borrowed;
         let mut collected;
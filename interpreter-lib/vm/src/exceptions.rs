@@ -9,6 +9,7 @@ use crate::{
     convert::{ToPyException, ToPyObject},
     function::{ArgIterable, FuncArgs, IntoFuncArgs},
     py_io::{self, Write},
+    source_code::SourceLocation,
     stdlib::sys,
     suggestion::offer_suggestions,
     types::{Callable, Constructor, Initializer, Representable},
@@ -226,6 +227,7 @@ fn print_source_line<W: Write>(
     output: &mut W,
     filename: &str,
     lineno: usize,
+    positions: Option<(SourceLocation, SourceLocation)>,
 ) -> Result<(), W::Error> {
     // TODO: use io.open() method instead, when available, according to https://github.com/python/cpython/blob/main/Python/traceback.c#L393
     // TODO: support different encodings
@@ -240,6 +242,9 @@ fn print_source_line<W: Write>(
             if let Ok(line) = line {
                 // Indented with 4 spaces
                 writeln!(output, "    {}", line.trim_start())?;
+                if let Some(carets) = positions.and_then(|pos| caret_line(&line, lineno, pos)) {
+                    writeln!(output, "    {carets}")?;
+                }
             }
             return Ok(());
         }
@@ -259,11 +264,108 @@ fn write_traceback_entry<W: Write>(
         r##"  File "{}", line {}, in {}"##,
         filename, tb_entry.lineno, tb_entry.frame.code.obj_name
     )?;
-    print_source_line(output, filename, tb_entry.lineno.to_usize())?;
+    let positions = (tb_entry.lasti as usize)
+        .checked_sub(1)
+        .and_then(|idx| tb_entry.frame.code.locations.get(idx))
+        .copied();
+    print_source_line(output, filename, tb_entry.lineno.to_usize(), positions)?;
 
     Ok(())
 }
 
+/// The line drawn under the (indentation-stripped) source line to point at the failing
+/// expression, e.g. `~~~^^^~~~` for a binary operation. `None` when the expression starts on
+/// another line or covers the whole line.
+fn caret_line(
+    line: &str,
+    lineno: usize,
+    (start, end): (SourceLocation, SourceLocation),
+) -> Option<String> {
+    if start.row.to_usize() != lineno {
+        return None;
+    }
+    let line = line.trim_end();
+    let line_len = line.chars().count();
+    let indent = line_len - line.trim_start().chars().count();
+    let start_col = start.column.to_zero_indexed() as usize;
+    let end_col = if end.row == start.row {
+        (end.column.to_zero_indexed() as usize).min(line_len)
+    } else {
+        line_len
+    };
+    if start_col < indent || end_col <= start_col || (start_col == indent && end_col == line_len) {
+        return None;
+    }
+
+    let segment: String = line
+        .chars()
+        .skip(start_col)
+        .take(end_col - start_col)
+        .collect();
+    let anchors = if end.row == start.row {
+        caret_anchors(&segment)
+    } else {
+        None
+    };
+    let mut carets = " ".repeat(start_col - indent);
+    carets.extend((0..end_col - start_col).map(|i| match anchors {
+        Some((from, to)) if !(from..to).contains(&i) => '~',
+        _ => '^',
+    }));
+    Some(carets)
+}
+
+/// The part of `segment` that gets `^` rather than `~`: the operator of a binary operation,
+/// the brackets of a subscript or the arguments of a call.
+#[cfg(feature = "rustpython-parser")]
+fn caret_anchors(segment: &str) -> Option<(usize, usize)> {
+    use rustpython_ast::{
+        fold::Fold,
+        located::{self as located_ast, Located},
+    };
+    use rustpython_parser::Parse;
+
+    let expr = rustpython_ast::Expr::parse(segment, "<traceback>").ok()?;
+    let expr = crate::source_code::LinearLocator::new(segment)
+        .fold_expr(expr)
+        .ok()?;
+    let column = |location: SourceLocation| location.column.to_zero_indexed() as usize;
+    match expr {
+        located_ast::Expr::BinOp(located_ast::ExprBinOp { left, right, .. }) => {
+            let left_end = column(left.end_location()?);
+            let right_start = column(right.location());
+            // the operator is what's left between the operands apart from spaces and parentheses
+            let between: Vec<char> = segment
+                .chars()
+                .skip(left_end)
+                .take(right_start.checked_sub(left_end)?)
+                .collect();
+            let offset = between
+                .iter()
+                .position(|c| !c.is_whitespace() && !matches!(c, '(' | ')'))?;
+            let len = between[offset..]
+                .iter()
+                .take_while(|c| "+-*/%@&|^<>".contains(**c))
+                .count()
+                .clamp(1, 2);
+            Some((left_end + offset, left_end + offset + len))
+        }
+        located_ast::Expr::Subscript(located_ast::ExprSubscript { value, slice, .. }) => Some((
+            column(value.end_location()?),
+            column(slice.end_location()?) + 1,
+        )),
+        located_ast::Expr::Call(located_ast::ExprCall { func, .. }) => {
+            Some((column(func.end_location()?), segment.chars().count()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "rustpython-parser"))]
+fn caret_anchors(_segment: &str) -> Option<(usize, usize)> {
+    None
+}
+
 #[derive(Clone)]
 pub enum ExceptionCtor {
     Class(PyTypeRef),
@@ -1553,3 +1655,39 @@ pub(super) mod types {
     #[derive(Debug)]
     pub struct PyEncodingWarning {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_code::OneIndexed;
+
+    fn loc(row: u32, column: u32) -> SourceLocation {
+        SourceLocation {
+            row: OneIndexed::new(row).unwrap(),
+            column: OneIndexed::from_zero_indexed(column),
+        }
+    }
+
+    #[test]
+    fn test_caret_line() {
+        let line = "    x = a + b[1]";
+        // nothing to point at when the whole line failed
+        assert_eq!(caret_line(line, 1, (loc(1, 4), loc(1, 16))), None);
+        assert_eq!(caret_line(line, 2, (loc(1, 8), loc(1, 16))), None);
+        assert_eq!(
+            caret_line(line, 1, (loc(1, 8), loc(3, 2))).as_deref(),
+            Some("    ^^^^^^^^")
+        );
+        #[cfg(feature = "rustpython-parser")]
+        {
+            assert_eq!(
+                caret_line(line, 1, (loc(1, 8), loc(1, 16))).as_deref(),
+                Some("    ~~^~~~~~")
+            );
+            assert_eq!(
+                caret_line(line, 1, (loc(1, 12), loc(1, 16))).as_deref(),
+                Some("        ~^^^")
+            );
+        }
+    }
+}
@@ -12,7 +12,8 @@ use crate::{
     coroutine::Coro,
     exceptions::ExceptionCtor,
     function::{ArgMapping, Either, FuncArgs},
-    protocol::{PyIter, PyIterReturn},
+    object::gc,
+    protocol::{PyIter, PyIterReturn, TraceEvent},
     scope::Scope,
     source_code::SourceLocation,
     stdlib::builtins,
@@ -25,24 +26,21 @@ use itertools::Itertools;
 use std::sync::atomic;
 use std::{fmt, iter::zip};
 
+/// A handler that is running. The blocks that are only being protected are not kept at
+/// runtime, they are looked up in the exception table of the code when unwinding.
 #[derive(Clone, Debug)]
 struct Block {
     /// The type of block.
     typ: BlockType,
     /// The level of the value stack when the block was entered.
     level: usize,
+    /// The innermost handler of the exception table around the handler code, which is where
+    /// unwinding continues once this block is left.
+    parent: Option<u32>,
 }
 
 #[derive(Clone, Debug)]
 enum BlockType {
-    Loop,
-    TryExcept {
-        handler: bytecode::Label,
-    },
-    Finally {
-        handler: bytecode::Label,
-    },
-
     /// Active finally sequence
     FinallyHandler {
         reason: Option<UnwindReason>,
@@ -112,6 +110,11 @@ pub struct Frame {
 
     // member
     pub trace_lines: PyMutex<bool>,
+    pub trace_opcodes: PyMutex<bool>,
+    /// The event the trace function of this frame is currently handling, if any
+    pub(crate) current_trace_event: PyMutex<Option<TraceEvent>>,
+    /// Instruction to continue at, set by assigning `f_lineno` from a trace function
+    pub(crate) jump_target: PyMutex<Option<u32>>,
     pub temporary_refs: PyMutex<Vec<PyObjectRef>>,
 }
 
@@ -161,12 +164,15 @@ impl Frame {
             state: PyMutex::new(state),
             trace: PyMutex::new(vm.ctx.none()),
             trace_lines: PyMutex::new(true),
+            trace_opcodes: PyMutex::new(false),
+            current_trace_event: PyMutex::new(None),
+            jump_target: PyMutex::new(None),
             temporary_refs: PyMutex::new(vec![]),
         }
     }
 
     pub fn current_location(&self) -> SourceLocation {
-        self.code.locations[self.lasti() as usize - 1]
+        self.code.locations[self.lasti() as usize - 1].0
     }
 
     pub fn lasti(&self) -> u32 {
@@ -237,9 +243,75 @@ impl Py<Frame> {
         f(exec)
     }
 
+    /// Like `with_exec`, but reports the frame being entered and left to the profile and trace
+    /// functions.
+    fn with_exec_traced(
+        &self,
+        vm: &VirtualMachine,
+        f: impl FnOnce(ExecutingFrame) -> PyResult<ExecutionResult>,
+    ) -> PyResult<ExecutionResult> {
+        if !vm.use_tracing.get() {
+            return self.with_exec(f);
+        }
+        vm.profile_event(TraceEvent::Call, vm.ctx.none.as_object())?;
+        self.trace_event(TraceEvent::Call, vm.ctx.none(), vm)?;
+        let result = self.with_exec(f);
+        let retval = match &result {
+            Ok(ExecutionResult::Return(value) | ExecutionResult::Yield(value)) => value.clone(),
+            Err(_) => vm.ctx.none(),
+        };
+        vm.profile_event(TraceEvent::Return, &retval)?;
+        self.trace_event(TraceEvent::Return, retval, vm)?;
+        result
+    }
+
+    /// Call the trace function for `event`: the global one for `call` events, and the one local
+    /// to this frame otherwise. Whatever the trace function returns, unless it is None, becomes
+    /// the new local trace function.
+    pub(crate) fn trace_event(
+        &self,
+        event: TraceEvent,
+        arg: PyObjectRef,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        if !vm.use_tracing.get() {
+            return Ok(());
+        }
+        let callback = match event {
+            TraceEvent::Call => vm.trace_func.borrow().clone(),
+            _ => self.trace.lock().clone(),
+        };
+        if vm.is_none(&callback) {
+            return Ok(());
+        }
+
+        let prev_event = self.current_trace_event.lock().replace(event);
+        // temporarily disable tracing, during the call to the
+        // tracing function itself.
+        vm.use_tracing.set(false);
+        let result = callback.call((self.to_owned(), event.to_string(), arg), vm);
+        *self.current_trace_event.lock() = prev_event;
+        match result {
+            Ok(local_trace) => {
+                if !vm.is_none(&local_trace) {
+                    *self.trace.lock() = local_trace;
+                }
+                vm.update_use_tracing();
+                Ok(())
+            }
+            Err(err) => {
+                vm.trace_func.replace(vm.ctx.none());
+                *self.trace.lock() = vm.ctx.none();
+                self.jump_target.lock().take();
+                vm.update_use_tracing();
+                Err(err)
+            }
+        }
+    }
+
     // #[cfg_attr(feature = "flame-it", flame("Frame"))]
     pub fn run(&self, vm: &VirtualMachine) -> PyResult<ExecutionResult> {
-        self.with_exec(|mut exec| exec.run(vm))
+        self.with_exec_traced(vm, |mut exec| exec.run(vm))
     }
 
     pub(crate) fn resume(
@@ -247,7 +319,7 @@ impl Py<Frame> {
         value: Option<PyObjectRef>,
         vm: &VirtualMachine,
     ) -> PyResult<ExecutionResult> {
-        self.with_exec(|mut exec| {
+        self.with_exec_traced(vm, |mut exec| {
             if let Some(value) = value {
                 exec.push_value(value)
             }
@@ -262,7 +334,7 @@ impl Py<Frame> {
         exc_val: PyObjectRef,
         exc_tb: PyObjectRef,
     ) -> PyResult<ExecutionResult> {
-        self.with_exec(|mut exec| exec.gen_throw(vm, exc_type, exc_val, exc_tb))
+        self.with_exec_traced(vm, |mut exec| exec.gen_throw(vm, exc_type, exc_val, exc_tb))
     }
 
     pub fn yield_from_target(&self) -> Option<PyObjectRef> {
@@ -346,22 +418,54 @@ impl ExecutingFrame<'_> {
 
     fn run(&mut self, vm: &VirtualMachine) -> PyResult<ExecutionResult> {
         flame_guard!(format!("Frame::run({})", self.code.obj_name));
+        #[cfg(feature = "jit")]
+        if self.lasti() == 0 {
+            self.code.record_hotness();
+        }
+        gc::safepoint(vm);
         // Execute until return or exception:
         let instrs = &self.code.instructions;
         let mut arg_state = bytecode::OpArgState::default();
+        // the last instruction that was seen while tracing, to tell when a new line starts
+        let mut prev_traced = None;
+        let mut do_extend_arg = false;
         loop {
-            let idx = self.lasti() as usize;
+            let mut idx = self.lasti() as usize;
             // eprintln!(
             //     "location: {:?} {}",
             //     self.code.locations[idx], self.code.source_path
             // );
             self.update_lasti(|i| *i += 1);
-            let bytecode::CodeUnit { op, arg } = instrs[idx];
-            let arg = arg_state.extend(arg);
-            let mut do_extend_arg = false;
-            let result = self.execute_instruction(op, arg, &mut do_extend_arg, vm);
+            let traced = if vm.use_tracing.get() && !do_extend_arg {
+                self.trace_instruction(idx, &mut prev_traced, vm)
+            } else {
+                Ok(None)
+            };
+            let result = match traced {
+                Ok(jump_target) => {
+                    if let Some(target) = jump_target {
+                        // the trace function assigned to f_lineno
+                        idx = target as usize;
+                        self.update_lasti(|i| *i = target + 1);
+                        arg_state.reset();
+                    }
+                    let bytecode::CodeUnit { op, arg } = instrs[idx];
+                    let arg = arg_state.extend(arg);
+                    do_extend_arg = false;
+                    self.execute_instruction(op, arg, &mut do_extend_arg, vm)
+                }
+                Err(exception) => {
+                    do_extend_arg = false;
+                    Err(exception)
+                }
+            };
             match result {
-                Ok(None) => {}
+                Ok(None) => {
+                    // a loop went around
+                    if (self.lasti() as usize) <= idx {
+                        gc::safepoint(vm);
+                    }
+                }
                 Ok(Some(value)) => {
                     break Ok(value);
                 }
@@ -378,7 +482,7 @@ impl ExecutingFrame<'_> {
                         // 2. Add new entry with current execution position (filename, lineno, code_object) to traceback.
                         // 3. Unwind block stack till appropriate handler is found.
 
-                        let loc = frame.code.locations[idx];
+                        let (loc, _) = frame.code.locations[idx];
                         let next = exception.traceback();
                         let new_traceback =
                             PyTraceback::new(next, frame.object.to_owned(), frame.lasti(), loc.row);
@@ -387,6 +491,21 @@ impl ExecutingFrame<'_> {
 
                         vm.contextualize_exception(&exception);
 
+                        // an exception raised by the trace function replaces the original one
+                        let exception = if vm.use_tracing.get() {
+                            let (exc_type, exc_val, exc_tb) = vm.split_exception(exception.clone());
+                            let exc_info = vm.new_tuple((exc_type, exc_val, exc_tb)).into();
+                            match frame
+                                .object
+                                .trace_event(TraceEvent::Exception, exc_info, vm)
+                            {
+                                Ok(()) => exception,
+                                Err(trace_exception) => trace_exception,
+                            }
+                        } else {
+                            exception
+                        };
+
                         frame.unwind_blocks(vm, UnwindReason::Raising { exception })
                     }
 
@@ -405,6 +524,46 @@ impl ExecutingFrame<'_> {
         }
     }
 
+    /// Send the `line` and `opcode` events for the instruction at `idx`, returning where to
+    /// continue instead if the trace function assigned to `f_lineno`.
+    #[cold]
+    fn trace_instruction(
+        &mut self,
+        idx: usize,
+        prev_traced: &mut Option<usize>,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<u32>> {
+        let frame = self.object;
+        if vm.is_none(&frame.trace.lock()) {
+            *prev_traced = None;
+            return Ok(None);
+        }
+        let locations = &self.code.locations;
+        let line = locations[idx].0.row;
+        // a line event is due when the line changes, or when jumping backwards to the start of
+        // a line, like at the top of a loop
+        let new_line = match *prev_traced {
+            None => true,
+            Some(prev) => {
+                locations[prev].0.row != line
+                    || (idx <= prev && (idx == 0 || locations[idx - 1].0.row != line))
+            }
+        };
+        *prev_traced = Some(idx);
+
+        if new_line && *frame.trace_lines.lock() {
+            frame.trace_event(TraceEvent::Line, vm.ctx.none(), vm)?;
+            if let Some(target) = frame.jump_target.lock().take() {
+                *prev_traced = Some(target as usize);
+                return Ok(Some(target));
+            }
+        }
+        if *frame.trace_opcodes.lock() {
+            frame.trace_event(TraceEvent::Opcode, vm.ctx.none(), vm)?;
+        }
+        Ok(None)
+    }
+
     fn yield_from_target(&self) -> Option<&PyObject> {
         if let Some(bytecode::CodeUnit {
             op: bytecode::Instruction::YieldFrom,
@@ -817,27 +976,22 @@ impl ExecutingFrame<'_> {
             }
             bytecode::Instruction::YieldFrom => self.execute_yield_from(vm),
             bytecode::Instruction::SetupAnnotation => self.setup_annotations(vm),
-            bytecode::Instruction::SetupLoop => {
-                self.push_block(BlockType::Loop);
-                Ok(None)
-            }
-            bytecode::Instruction::SetupExcept { handler } => {
-                self.push_block(BlockType::TryExcept {
-                    handler: handler.get(arg),
-                });
-                Ok(None)
-            }
-            bytecode::Instruction::SetupFinally { handler } => {
-                self.push_block(BlockType::Finally {
-                    handler: handler.get(arg),
-                });
-                Ok(None)
+            bytecode::Instruction::SetupLoop
+            | bytecode::Instruction::SetupExcept { .. }
+            | bytecode::Instruction::SetupFinally { .. }
+            | bytecode::Instruction::SetupAsyncWith { .. }
+            | bytecode::Instruction::PopBlock => {
+                self.fatal("block setup pseudo-instruction in finalized code")
             }
             bytecode::Instruction::EnterFinally => {
-                self.push_block(BlockType::FinallyHandler {
-                    reason: None,
-                    prev_exc: vm.current_exception(),
-                });
+                let parent = self.code.handler_at(self.lasti() - 1);
+                self.push_block(
+                    BlockType::FinallyHandler {
+                        reason: None,
+                        prev_exc: vm.current_exception(),
+                    },
+                    parent,
+                );
                 Ok(None)
             }
             bytecode::Instruction::EndFinally => {
@@ -858,7 +1012,7 @@ impl ExecutingFrame<'_> {
                     );
                 }
             }
-            bytecode::Instruction::SetupWith { end } => {
+            bytecode::Instruction::SetupWith { .. } => {
                 let context_manager = self.pop_value();
                 let error_string = || -> String {
                     format!(
@@ -879,9 +1033,6 @@ impl ExecutingFrame<'_> {
                         })
                     })?;
                 self.push_value(exit);
-                self.push_block(BlockType::Finally {
-                    handler: end.get(arg),
-                });
                 self.push_value(enter_res);
                 Ok(None)
             }
@@ -910,14 +1061,6 @@ impl ExecutingFrame<'_> {
 
                 Ok(None)
             }
-            bytecode::Instruction::SetupAsyncWith { end } => {
-                let enter_res = self.pop_value();
-                self.push_block(BlockType::Finally {
-                    handler: end.get(arg),
-                });
-                self.push_value(enter_res);
-                Ok(None)
-            }
             bytecode::Instruction::WithCleanupStart => {
                 let block = self.current_block().unwrap();
                 let reason = match block.typ {
@@ -960,10 +1103,6 @@ impl ExecutingFrame<'_> {
                     Ok(None)
                 }
             }
-            bytecode::Instruction::PopBlock => {
-                self.pop_block();
-                Ok(None)
-            }
             bytecode::Instruction::GetIter => {
                 let iterated_obj = self.pop_value();
                 let iter_obj = iterated_obj.get_iter(vm)?;
@@ -1245,13 +1384,32 @@ impl ExecutingFrame<'_> {
     /// Optionally returns an exception.
     #[cfg_attr(feature = "flame-it", flame("Frame"))]
     fn unwind_blocks(&mut self, vm: &VirtualMachine, reason: UnwindReason) -> FrameResult {
-        // First unwind all existing blocks on the block stack:
-        while let Some(block) = self.current_block() {
-            // eprintln!("unwinding block: {:.60?} {:.60?}", block.typ, reason);
-            match block.typ {
-                BlockType::Loop => match reason {
+        // Walk out through the handlers around the current instruction, leaving the running
+        // handlers inside of each of them on the way:
+        let mut handler = self
+            .lasti()
+            .checked_sub(1)
+            .and_then(|offset| self.code.handler_at(offset));
+        loop {
+            while self
+                .state
+                .blocks
+                .last()
+                .map_or(false, |block| handler.is_none() || block.parent == handler)
+            {
+                match self.pop_block().typ {
+                    BlockType::FinallyHandler { prev_exc, .. }
+                    | BlockType::ExceptHandler { prev_exc } => vm.set_exception(prev_exc),
+                }
+            }
+            let Some(index) = handler else {
+                break;
+            };
+            let h = self.code.exception_handlers[index as usize];
+            match h.kind {
+                bytecode::HandlerKind::Loop => match reason {
                     UnwindReason::Break { target } => {
-                        self.pop_block();
+                        self.state.stack.truncate(h.depth as usize);
                         self.jump(target);
                         return Ok(None);
                     }
@@ -1259,42 +1417,42 @@ impl ExecutingFrame<'_> {
                         self.jump(target);
                         return Ok(None);
                     }
-                    _ => {
-                        self.pop_block();
-                    }
+                    _ => {}
                 },
-                BlockType::Finally { handler } => {
-                    self.pop_block();
+                bytecode::HandlerKind::Finally => {
+                    self.state.stack.truncate(h.depth as usize);
                     let prev_exc = vm.current_exception();
                     if let UnwindReason::Raising { exception } = &reason {
                         vm.set_exception(Some(exception.clone()));
                     }
-                    self.push_block(BlockType::FinallyHandler {
-                        reason: Some(reason),
-                        prev_exc,
-                    });
-                    self.jump(handler);
+                    self.push_block(
+                        BlockType::FinallyHandler {
+                            reason: Some(reason),
+                            prev_exc,
+                        },
+                        h.parent,
+                    );
+                    self.jump(h.target);
                     return Ok(None);
                 }
-                BlockType::TryExcept { handler } => {
-                    self.pop_block();
+                bytecode::HandlerKind::Except => {
                     if let UnwindReason::Raising { exception } = reason {
-                        self.push_block(BlockType::ExceptHandler {
-                            prev_exc: vm.current_exception(),
-                        });
+                        self.state.stack.truncate(h.depth as usize);
+                        self.push_block(
+                            BlockType::ExceptHandler {
+                                prev_exc: vm.current_exception(),
+                            },
+                            h.parent,
+                        );
                         vm.contextualize_exception(&exception);
                         vm.set_exception(Some(exception.clone()));
                         self.push_value(exception.into());
-                        self.jump(handler);
+                        self.jump(h.target);
                         return Ok(None);
                     }
                 }
-                BlockType::FinallyHandler { prev_exc, .. }
-                | BlockType::ExceptHandler { prev_exc } => {
-                    self.pop_block();
-                    vm.set_exception(prev_exc);
-                }
             }
+            handler = h.parent;
         }
 
         // We do not have any more blocks to unwind. Inspect the reason we are here:
@@ -1577,6 +1735,11 @@ impl ExecutingFrame<'_> {
     fn jump(&mut self, label: bytecode::Label) {
         let target_pc = label.0;
         vm_trace!("jump from {:?} to {:?}", self.lasti(), target_pc);
+        // a backwards jump means a loop went around
+        #[cfg(feature = "jit")]
+        if target_pc < self.lasti() {
+            self.code.record_hotness();
+        }
         self.update_lasti(|i| *i = target_pc);
     }
 
@@ -1944,11 +2107,12 @@ impl ExecutingFrame<'_> {
         Ok(None)
     }
 
-    fn push_block(&mut self, typ: BlockType) {
+    fn push_block(&mut self, typ: BlockType, parent: Option<u32>) {
         // eprintln!("block pushed: {:.60?} {}", typ, self.state.stack.len());
         self.state.blocks.push(Block {
             typ,
             level: self.state.stack.len(),
+            parent,
         });
     }
 
@@ -2072,3 +2236,68 @@ impl fmt::Debug for Frame {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    #[test]
+    fn test_unwind_through_exception_table() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let source = r#"
+log = []
+
+def f():
+    for i in range(4):
+        try:
+            try:
+                if i == 0:
+                    continue
+                if i == 1:
+                    raise ValueError(i)
+                if i == 3:
+                    break
+            except ValueError as e:
+                log.append(("except", e.args[0]))
+                try:
+                    raise KeyError(i)
+                except KeyError:
+                    log.append(("nested", i))
+                continue
+            finally:
+                log.append(("finally", i))
+            log.append(("body", i))
+        finally:
+            log.append(("outer", i))
+    try:
+        return "returned"
+    finally:
+        log.append("return")
+
+class Manager:
+    def __enter__(self):
+        return self
+    def __exit__(self, typ, value, tb):
+        log.append(("exit", typ))
+        return True
+
+assert f() == "returned"
+with Manager():
+    raise TypeError
+assert log == [
+    ("finally", 0), ("outer", 0),
+    ("except", 1), ("nested", 1), ("finally", 1), ("outer", 1),
+    ("finally", 2), ("body", 2), ("outer", 2),
+    ("finally", 3), ("outer", 3),
+    "return",
+    ("exit", TypeError),
+], log
+"#;
+            let scope = vm.new_scope_with_builtins();
+            if let Err(e) = vm.run_code_string(scope, source, "<unittest>".to_owned()) {
+                vm.print_exception(e);
+                panic!();
+            }
+        })
+    }
+}
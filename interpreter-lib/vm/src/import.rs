@@ -3,12 +3,16 @@
  */
 use crate::{
     builtins::{list, traceback::PyTraceback, PyBaseExceptionRef, PyCode},
+    common::hash::keyed_hash,
     scope::Scope,
     version::get_git_revision,
     vm::{thread, VirtualMachine},
     AsObject, PyObjectRef, PyPayload, PyRef, PyResult, TryFromObject,
 };
-use rand::Rng;
+use rustpython_compiler_core::marshal;
+
+/// `sys.implementation.cache_tag`, which names the files in `__pycache__`.
+pub const CACHE_TAG: &str = "rustpython-01";
 
 pub(crate) fn init_importlib_base(vm: &mut VirtualMachine) -> PyResult<PyObjectRef> {
     flame_guard!("init importlib");
@@ -45,15 +49,15 @@ pub(crate) fn init_importlib_package(vm: &VirtualMachine, importlib: PyObjectRef
 
         let install_external = importlib.get_attr("_install_external_importers", vm)?;
         install_external.call((), vm)?;
-        // Set pyc magic number to commit hash. Should be changed when bytecode will be more stable.
+        // set the pyc magic number, which changes whenever the bytecode may have changed
         let importlib_external = vm.import("_frozen_importlib_external", 0)?;
-        let mut magic = get_git_revision().into_bytes();
-        magic.truncate(4);
-        if magic.len() != 4 {
-            magic = rand::thread_rng().gen::<[u8; 4]>().to_vec();
-        }
-        let magic: PyObjectRef = vm.ctx.new_bytes(magic).into();
+        let magic: PyObjectRef = vm.ctx.new_bytes(pyc_magic_number().to_vec()).into();
         importlib_external.set_attr("MAGIC_NUMBER", magic, vm)?;
+        importlib_external.set_attr(
+            "_RAW_MAGIC_NUMBER",
+            vm.ctx.new_int(u32::from_le_bytes(pyc_magic_number())),
+            vm,
+        )?;
         let zipimport_res = (|| -> PyResult<()> {
             let zipimport = vm.import("zipimport", 0)?;
             let zipimporter = zipimport.get_attr("zipimporter", vm)?;
@@ -119,6 +123,20 @@ pub fn import_file(
     import_codeobj(vm, module_name, code, true)
 }
 
+/// The magic number that starts `.pyc` files. It changes with the interpreter revision, since
+/// there is no promise that the bytecode stays the same between any two of them.
+pub fn pyc_magic_number() -> [u8; 4] {
+    let revision = get_git_revision();
+    let revision = if revision.is_empty() {
+        env!("CARGO_PKG_VERSION")
+    } else {
+        &revision
+    };
+    let hash = keyed_hash(marshal::FORMAT_VERSION.into(), revision.as_bytes()).to_le_bytes();
+    // end with \r\n like CPython's, so that a file mangled by text-mode transfers is rejected
+    [hash[0], hash[1], b'\r', b'\n']
+}
+
 #[cfg(feature = "rustpython-compiler")]
 pub fn import_source(vm: &VirtualMachine, module_name: &str, content: &str) -> PyResult {
     let code = vm
@@ -27,6 +27,7 @@ use crate::{
     },
     vm::VirtualMachine,
 };
+use crossbeam_utils::atomic::AtomicCell;
 use itertools::Itertools;
 use std::{
     any::TypeId,
@@ -103,6 +104,8 @@ pub(super) struct PyInner<T> {
     // TODO: move typeid into vtable once TypeId::of is const
     pub(super) typeid: TypeId,
     pub(super) vtable: &'static PyObjVTable,
+    /// `GC_TRACKED` and `GC_FINALIZED`, see [`super::gc`].
+    pub(super) gc_bits: PyAtomic<u8>,
 
     pub(super) typ: PyAtomicRef<PyType>, // __class__ member
     pub(super) dict: Option<InstanceDict>,
@@ -442,6 +445,7 @@ impl<T: PyObjectPayload> PyInner<T> {
             ref_count: RefCount::new(),
             typeid: TypeId::of::<T>(),
             vtable: PyObjVTable::of::<T>(),
+            gc_bits: Radium::new(0),
             typ: PyAtomicRef::from(typ),
             dict: dict.map(InstanceDict::new),
             weak_list: WeakRefList::new(),
@@ -454,6 +458,25 @@ impl<T: PyObjectPayload> PyInner<T> {
     }
 }
 
+/// Callbacks run whenever an object is allocated or is about to be deallocated, used by
+/// `_tracemalloc` to record where objects were created.
+///
+/// The callbacks must not allocate or release Python objects themselves.
+#[derive(Debug)]
+pub struct AllocHook {
+    /// Called with the new object and the size of its allocation.
+    pub alloc: fn(&PyObject, usize),
+    pub dealloc: fn(&PyObject),
+}
+
+static ALLOC_HOOK: AtomicCell<Option<&'static AllocHook>> = AtomicCell::new(None);
+
+/// Install an allocation hook, or remove the current one with `None`, returning the hook that
+/// was installed before.
+pub fn set_alloc_hook(hook: Option<&'static AllocHook>) -> Option<&'static AllocHook> {
+    ALLOC_HOOK.swap(hook)
+}
+
 /// The `PyObjectRef` is one of the most used types. It is a reference to a
 /// python object. A single python object can have multiple references, and
 /// this reference counting is accounted for by this type. Use the `.clone()`
@@ -799,7 +822,11 @@ impl PyObject {
         // CPython-compatible drop implementation
         let del = self.class().mro_find_map(|cls| cls.slots.del.load());
         if let Some(slot_del) = del {
-            call_slot_del(self, slot_del)?;
+            // like CPython, __del__ of an object that can be in a cycle runs only once, even if
+            // it resurrects the object
+            if !self.0.is_gc() || super::gc::mark_finalized(self) {
+                call_slot_del(self, slot_del)?;
+            }
         }
         if let Some(wrl) = self.weak_ref_list() {
             wrl.clear();
@@ -811,10 +838,18 @@ impl PyObject {
     /// Can only be called when ref_count has dropped to zero. `ptr` must be valid
     #[inline(never)]
     unsafe fn drop_slow(ptr: NonNull<PyObject>) {
+        // untrack before running __del__, so a collection started from it never sees this object
+        let tracked = ptr.as_ref().0.is_gc() && super::gc::untrack(ptr.as_ref());
         if let Err(()) = ptr.as_ref().drop_slow_inner() {
             // abort drop for whatever reason
+            if tracked {
+                super::gc::track(ptr.as_ref());
+            }
             return;
         }
+        if let Some(hook) = ALLOC_HOOK.load() {
+            (hook.dealloc)(ptr.as_ref());
+        }
         let drop_dealloc = ptr.as_ref().0.vtable.drop_dealloc;
         // call drop only when there are no references in scope - stacked borrows stuff
         drop_dealloc(ptr.as_ptr())
@@ -837,6 +872,23 @@ impl PyObject {
     pub(crate) fn set_slot(&self, offset: usize, value: Option<PyObjectRef>) {
         *self.0.slots[offset].write() = value;
     }
+
+    /// Take a new reference, unless the object is already being deallocated.
+    pub(super) fn try_to_owned(&self) -> Option<PyObjectRef> {
+        self.0.ref_count.safe_inc().then(|| PyObjectRef {
+            ptr: NonNull::from(self),
+        })
+    }
+
+    /// Empty every `__slots__` member, for the cycle collector.
+    pub(super) fn take_slots(&self) -> impl Iterator<Item = PyObjectRef> + '_ {
+        self.0.slots.iter().filter_map(|slot| slot.write().take())
+    }
+
+    /// The flags the cycle collector keeps for this object.
+    pub(super) fn gc_bits(&self) -> &PyAtomic<u8> {
+        &self.0.gc_bits
+    }
 }
 
 impl Borrow<PyObject> for PyObjectRef {
@@ -1014,9 +1066,18 @@ impl<T: PyObjectPayload> PyRef<T> {
     #[inline(always)]
     pub fn new_ref(payload: T, typ: crate::builtins::PyTypeRef, dict: Option<PyDictRef>) -> Self {
         let inner = Box::into_raw(PyInner::new(payload, typ, dict));
-        Self {
+        let zelf = Self {
             ptr: unsafe { NonNull::new_unchecked(inner.cast::<Py<T>>()) },
+        };
+        if zelf.0.is_gc() {
+            super::gc::track(zelf.as_object());
         }
+        if let Some(hook) = ALLOC_HOOK.load() {
+            let size = std::mem::size_of::<PyInner<T>>()
+                + zelf.0.slots.len() * std::mem::size_of::<PyRwLock<Option<PyObjectRef>>>();
+            (hook.alloc)(zelf.as_object(), size);
+        }
+        zelf
     }
 
     pub fn leak(pyref: Self) -> &'static Py<T> {
@@ -1167,6 +1228,7 @@ pub(crate) fn init_type_hierarchy() -> (PyTypeRef, PyTypeRef, PyTypeRef) {
                 ref_count: RefCount::new(),
                 typeid: TypeId::of::<PyType>(),
                 vtable: PyObjVTable::of::<PyType>(),
+                gc_bits: Radium::new(0),
                 dict: None,
                 weak_list: WeakRefList::new(),
                 payload: type_payload,
@@ -1179,6 +1241,7 @@ pub(crate) fn init_type_hierarchy() -> (PyTypeRef, PyTypeRef, PyTypeRef) {
                 ref_count: RefCount::new(),
                 typeid: TypeId::of::<PyType>(),
                 vtable: PyObjVTable::of::<PyType>(),
+                gc_bits: Radium::new(0),
                 dict: None,
                 weak_list: WeakRefList::new(),
                 payload: object_payload,
@@ -1253,4 +1316,30 @@ mod tests {
         let obj = ctx.new_bytes(b"dfghjkl".to_vec());
         drop(obj);
     }
+
+    #[test]
+    fn test_alloc_hook() {
+        use std::cell::RefCell;
+        std::thread_local! {
+            static EVENTS: RefCell<Vec<(usize, bool)>> = RefCell::default();
+        }
+        fn alloc(obj: &PyObject, size: usize) {
+            assert!(size >= std::mem::size_of::<PyInner<Erased>>());
+            EVENTS.with(|events| events.borrow_mut().push((obj as *const _ as usize, true)));
+        }
+        fn dealloc(obj: &PyObject) {
+            EVENTS.with(|events| events.borrow_mut().push((obj as *const _ as usize, false)));
+        }
+        static HOOK: AllocHook = AllocHook { alloc, dealloc };
+
+        let ctx = crate::Context::genesis();
+        set_alloc_hook(Some(&HOOK));
+        let obj = ctx.new_bytes(b"dfghjkl".to_vec());
+        let addr = obj.as_object() as *const PyObject as usize;
+        drop(obj);
+        set_alloc_hook(None);
+
+        let events = EVENTS.with(|events| events.take());
+        assert_eq!(events, [(addr, true), (addr, false)]);
+    }
 }
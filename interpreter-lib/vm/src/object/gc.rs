@@ -0,0 +1,996 @@
+//! Generational cycle collector.
+//!
+//! Reference counting frees an object as soon as its last reference goes away, but it can never
+//! free a group of objects that refer to each other. Every object that can hold references (its
+//! payload is [`Traverse`], or it has an instance dict or `__slots__`) is tracked here when it is
+//! allocated, and [`collect`] looks for tracked objects that are only kept alive by other tracked
+//! objects, the same way CPython's `gcmodule.c` does:
+//!
+//! 1. copy the reference count of every object in the generation into `gc_refs`;
+//! 2. traverse every object and decrement `gc_refs` of each child in the generation, which leaves
+//!    only the references coming from outside of it;
+//! 3. everything reachable from an object with `gc_refs > 0` is alive, the rest is garbage;
+//! 4. the garbage is kept alive while its containers are cleared, which breaks the cycles and lets
+//!    reference counting free it.
+//!
+//! A `Traverse` impl that misses a child only makes the collector more conservative, so objects
+//! with incomplete traversal are leaked rather than freed too early.
+//!
+//! Reference counts are only a sound basis for step 1 while nobody else is changing them, so with
+//! the `threading` feature the collecting thread first stops the world: every other thread that
+//! runs Python code parks at its next safepoint (a call or a backward jump, see [`safepoint`]) and
+//! stays there until the unreachable objects have been found. Newly allocated objects go into a
+//! per-thread young set so that tracking them never touches a lock shared between threads.
+
+use super::{core::PyInner, Traverse};
+use crate::common::atomic::Radium;
+use crate::{
+    builtins::{function::PyCell, PyDict, PyList, PyType},
+    common::lock::PyMutex,
+    PyObject, PyObjectRef, VirtualMachine,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "threading")]
+use std::{sync::Arc, time::Instant};
+
+pub const NUM_GENERATIONS: usize = 3;
+
+pub const DEBUG_STATS: u32 = 1;
+pub const DEBUG_COLLECTABLE: u32 = 2;
+pub const DEBUG_UNCOLLECTABLE: u32 = 4;
+pub const DEBUG_SAVEALL: u32 = 32;
+pub const DEBUG_LEAK: u32 = DEBUG_COLLECTABLE | DEBUG_UNCOLLECTABLE | DEBUG_SAVEALL;
+
+/// The object is in one of the generations, so dropping it has to take it out again.
+const GC_TRACKED: u8 = 1;
+/// `__del__` has run on the object.
+const GC_FINALIZED: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct GcPtr(NonNull<PyObject>);
+
+// SAFETY: a GcPtr is only dereferenced while the object is tracked, and an object is untracked
+// before it is deallocated.
+unsafe impl Send for GcPtr {}
+
+impl From<&PyObject> for GcPtr {
+    #[inline]
+    fn from(obj: &PyObject) -> Self {
+        Self(NonNull::from(obj))
+    }
+}
+
+impl GcPtr {
+    /// # Safety
+    /// The object must still be tracked.
+    #[inline]
+    unsafe fn as_object<'a>(self) -> &'a PyObject {
+        self.0.as_ref()
+    }
+
+    /// Take a new strong reference, unless the object is already being deallocated.
+    fn upgrade(self) -> Option<PyObjectRef> {
+        unsafe { self.as_object() }.try_to_owned()
+    }
+}
+
+/// Per-generation statistics, as reported by `gc.get_stats()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcStats {
+    pub collections: usize,
+    pub collected: usize,
+    pub uncollectable: usize,
+}
+
+struct GcGenerations {
+    /// With threading, generation 0 only holds the young objects of threads that have exited;
+    /// the others are in the `Shard` of the thread that allocated them.
+    generations: [HashSet<GcPtr>; NUM_GENERATIONS],
+    /// Objects moved out of the collector's reach by `gc.freeze()`.
+    permanent: HashSet<GcPtr>,
+    /// How many times the next younger generation was collected since this one was; the count of
+    /// generation 0 is [`GcState::young_count`].
+    counts: [usize; NUM_GENERATIONS],
+    thresholds: [u32; NUM_GENERATIONS],
+    stats: [GcStats; NUM_GENERATIONS],
+}
+
+struct GcState {
+    inner: PyMutex<GcGenerations>,
+    /// The number of objects tracked since generation 0 was last collected.
+    young_count: AtomicUsize,
+    /// A copy of `thresholds[0]` that allocation can check without taking `inner`.
+    young_threshold: AtomicUsize,
+    enabled: AtomicBool,
+    /// Set when generation 0 goes over its threshold; the frame loop runs the collection at its
+    /// next safepoint.
+    pending: AtomicBool,
+    collecting: AtomicBool,
+    debug: AtomicU32,
+    #[cfg(feature = "threading")]
+    shards: parking_lot::Mutex<Vec<Arc<Shard>>>,
+    #[cfg(feature = "threading")]
+    world: World,
+}
+
+impl GcState {
+    fn new() -> Self {
+        const THRESHOLDS: [u32; NUM_GENERATIONS] = [700, 10, 10];
+        Self {
+            inner: PyMutex::new(GcGenerations {
+                generations: Default::default(),
+                permanent: HashSet::new(),
+                counts: [0; NUM_GENERATIONS],
+                thresholds: THRESHOLDS,
+                stats: Default::default(),
+            }),
+            young_count: AtomicUsize::new(0),
+            young_threshold: AtomicUsize::new(THRESHOLDS[0] as usize),
+            enabled: AtomicBool::new(true),
+            pending: AtomicBool::new(false),
+            collecting: AtomicBool::new(false),
+            debug: AtomicU32::new(0),
+            #[cfg(feature = "threading")]
+            shards: parking_lot::Mutex::new(Vec::new()),
+            #[cfg(feature = "threading")]
+            world: World::new(),
+        }
+    }
+
+    fn track(&self, obj: GcPtr) {
+        if !self.insert_young(obj) {
+            return;
+        }
+        let count = self.young_count.fetch_add(1, Ordering::Relaxed) + 1;
+        let threshold = self.young_threshold.load(Ordering::Relaxed);
+        if threshold != 0 && count > threshold && self.enabled.load(Ordering::Relaxed) {
+            self.pending.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "threading")]
+    fn insert_young(&self, obj: GcPtr) -> bool {
+        SHARD
+            .try_with(|shard| shard.0.young.lock().insert(obj))
+            // the thread is exiting and its shard is already gone
+            .unwrap_or_else(|_| self.inner.lock().generations[0].insert(obj))
+    }
+
+    #[cfg(not(feature = "threading"))]
+    fn insert_young(&self, obj: GcPtr) -> bool {
+        self.inner.lock().generations[0].insert(obj)
+    }
+
+    fn forget_young(&self) {
+        let _ = self
+            .young_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    fn untrack(&self, obj: GcPtr) -> bool {
+        #[cfg(feature = "threading")]
+        if SHARD.try_with(|shard| shard.0.young.lock().remove(&obj)) == Ok(true) {
+            self.forget_young();
+            return true;
+        }
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        if inner.generations[0].remove(&obj) {
+            self.forget_young();
+            return true;
+        }
+        if inner.generations[1..]
+            .iter_mut()
+            .chain(std::iter::once(&mut inner.permanent))
+            .any(|gen| gen.remove(&obj))
+        {
+            return true;
+        }
+        // an object that is dropped by another thread than the one that allocated it
+        #[cfg(feature = "threading")]
+        if self
+            .shards
+            .lock()
+            .iter()
+            .any(|shard| shard.young.lock().remove(&obj))
+        {
+            self.forget_young();
+            return true;
+        }
+        false
+    }
+
+    /// Move the young objects of every thread into generation 0.
+    #[cfg(feature = "threading")]
+    fn gather_young(&self, inner: &mut GcGenerations) {
+        for shard in self.shards.lock().iter() {
+            let young = std::mem::take(&mut *shard.young.lock());
+            inner.generations[0].extend(young);
+        }
+    }
+
+    #[cfg(not(feature = "threading"))]
+    fn gather_young(&self, _inner: &mut GcGenerations) {}
+
+    fn counts(&self, inner: &GcGenerations) -> [usize; NUM_GENERATIONS] {
+        let mut counts = inner.counts;
+        counts[0] = self.young_count.load(Ordering::Relaxed);
+        counts
+    }
+
+    /// The oldest generation whose count went over its threshold.
+    fn generation_to_collect(&self) -> Option<usize> {
+        let inner = self.inner.lock();
+        let counts = self.counts(&inner);
+        (0..NUM_GENERATIONS).rev().find(|&gen| {
+            let threshold = inner.thresholds[gen] as usize;
+            threshold != 0 && counts[gen] > threshold
+        })
+    }
+
+    /// Find the objects of `generation` and all younger generations that are only referenced from
+    /// inside those generations. Survivors are moved to the next generation.
+    ///
+    /// No other thread may run Python code while this runs, see `World::stop`.
+    fn find_unreachable(&self, generation: usize) -> Vec<PyObjectRef> {
+        let mut inner = self.inner.lock();
+        self.gather_young(&mut inner);
+        for gen in 0..generation {
+            let young = std::mem::take(&mut inner.generations[gen]);
+            inner.generations[generation].extend(young);
+        }
+        self.young_count.store(0, Ordering::Relaxed);
+        for count in &mut inner.counts[..=generation] {
+            *count = 0;
+        }
+        if let Some(count) = inner.counts.get_mut(generation + 1) {
+            *count += 1;
+        }
+
+        let young = &inner.generations[generation];
+        // leaked (interned) objects report a huge count, so they always stay reachable
+        let mut gc_refs: HashMap<GcPtr, usize> = young
+            .iter()
+            .map(|&ptr| (ptr, unsafe { ptr.as_object() }.strong_count()))
+            .collect();
+        for &ptr in young {
+            unsafe { ptr.as_object() }.traverse(&mut |child| {
+                if let Some(refs) = gc_refs.get_mut(&GcPtr::from(child)) {
+                    *refs = refs.saturating_sub(1);
+                }
+            });
+        }
+
+        let mut reachable = HashSet::with_capacity(gc_refs.len());
+        let mut stack: Vec<GcPtr> = gc_refs
+            .iter()
+            .filter(|(_, &refs)| refs > 0)
+            .map(|(&ptr, _)| ptr)
+            .collect();
+        while let Some(ptr) = stack.pop() {
+            if !reachable.insert(ptr) {
+                continue;
+            }
+            unsafe { ptr.as_object() }.traverse(&mut |child| {
+                let child = GcPtr::from(child);
+                if gc_refs.contains_key(&child) && !reachable.contains(&child) {
+                    stack.push(child);
+                }
+            });
+        }
+
+        let unreachable = gc_refs
+            .into_keys()
+            .filter(|ptr| !reachable.contains(ptr))
+            .filter_map(GcPtr::upgrade)
+            .collect();
+
+        if generation + 1 < NUM_GENERATIONS {
+            let survivors = std::mem::take(&mut inner.generations[generation]);
+            inner.generations[generation + 1].extend(survivors);
+        }
+        unreachable
+    }
+}
+
+/// How long an automatic collection waits for the other threads before giving up.
+const AUTOMATIC_PATIENCE: Duration = Duration::from_millis(5);
+/// How long `gc.collect()` waits for the other threads before giving up.
+const EXPLICIT_PATIENCE: Duration = Duration::from_secs(1);
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "threading")] {
+        static GC_STATE: once_cell::sync::Lazy<GcState> = once_cell::sync::Lazy::new(GcState::new);
+
+        #[inline]
+        fn with_state<R>(f: impl FnOnce(&GcState) -> R) -> Option<R> {
+            Some(f(&GC_STATE))
+        }
+
+        /// The objects a thread allocated since the last collection.
+        #[derive(Default)]
+        struct Shard {
+            young: parking_lot::Mutex<HashSet<GcPtr>>,
+        }
+
+        /// Registers the thread's shard with the collector, and hands its objects over to
+        /// generation 0 when the thread exits.
+        struct ShardHandle(Arc<Shard>);
+
+        impl ShardHandle {
+            fn new() -> Self {
+                let shard = Arc::new(Shard::default());
+                GC_STATE.shards.lock().push(shard.clone());
+                Self(shard)
+            }
+        }
+
+        impl Drop for ShardHandle {
+            fn drop(&mut self) {
+                let mut inner = GC_STATE.inner.lock();
+                let young = std::mem::take(&mut *self.0.young.lock());
+                inner.generations[0].extend(young);
+                GC_STATE
+                    .shards
+                    .lock()
+                    .retain(|shard| !Arc::ptr_eq(shard, &self.0));
+            }
+        }
+
+        std::thread_local! {
+            static SHARD: ShardHandle = ShardHandle::new();
+        }
+
+        /// Lets the collector wait for every thread that runs Python code to reach a safepoint.
+        struct World {
+            /// The number of threads inside [`enter_vm`](crate::vm::thread::enter_vm).
+            attached: AtomicUsize,
+            stop_requested: AtomicBool,
+            /// The number of attached threads that are waiting at a safepoint.
+            parked: parking_lot::Mutex<usize>,
+            changed: parking_lot::Condvar,
+        }
+
+        impl World {
+            fn new() -> Self {
+                Self {
+                    attached: AtomicUsize::new(0),
+                    stop_requested: AtomicBool::new(false),
+                    parked: parking_lot::Mutex::new(0),
+                    changed: parking_lot::Condvar::new(),
+                }
+            }
+
+            fn attach(&self) {
+                self.attached.fetch_add(1, Ordering::SeqCst);
+                if self.stop_requested.load(Ordering::SeqCst) {
+                    self.park();
+                }
+            }
+
+            fn detach(&self) {
+                let _parked = self.parked.lock();
+                self.attached.fetch_sub(1, Ordering::SeqCst);
+                self.changed.notify_all();
+            }
+
+            fn park(&self) {
+                let mut parked = self.parked.lock();
+                *parked += 1;
+                self.changed.notify_all();
+                while self.stop_requested.load(Ordering::SeqCst) {
+                    self.changed.wait(&mut parked);
+                }
+                *parked -= 1;
+            }
+
+            /// Wait until every other attached thread is parked. If that takes longer than
+            /// `patience`, e.g. because one of them is blocked on a lock held by a parked thread,
+            /// the others are let go again and `false` is returned.
+            fn stop(&self, patience: Duration) -> bool {
+                let deadline = Instant::now() + patience;
+                let mut parked = self.parked.lock();
+                self.stop_requested.store(true, Ordering::SeqCst);
+                // the collecting thread is attached itself
+                while *parked + 1 < self.attached.load(Ordering::SeqCst) {
+                    if self.changed.wait_until(&mut parked, deadline).timed_out()
+                        && *parked + 1 < self.attached.load(Ordering::SeqCst)
+                    {
+                        self.stop_requested.store(false, Ordering::SeqCst);
+                        self.changed.notify_all();
+                        return false;
+                    }
+                }
+                true
+            }
+
+            fn resume(&self) {
+                let _parked = self.parked.lock();
+                self.stop_requested.store(false, Ordering::SeqCst);
+                self.changed.notify_all();
+            }
+        }
+
+        std::thread_local! {
+            static ATTACHED: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+        }
+
+        /// Called by [`enter_vm`](crate::vm::thread::enter_vm) when a thread starts running
+        /// Python code.
+        pub(crate) fn attach_thread() {
+            ATTACHED.with(|attached| attached.set(true));
+            GC_STATE.world.attach();
+        }
+
+        /// Called by [`enter_vm`](crate::vm::thread::enter_vm) when a thread stops running
+        /// Python code.
+        pub(crate) fn detach_thread() {
+            GC_STATE.world.detach();
+            ATTACHED.with(|attached| attached.set(false));
+        }
+
+        /// Run `f` without holding up collections that other threads start meanwhile. Meant for
+        /// blocking calls like sleeping or waiting for a lock; `f` must not touch any Python
+        /// object.
+        pub fn allow_threads<R>(f: impl FnOnce() -> R) -> R {
+            struct Reattach;
+            impl Drop for Reattach {
+                fn drop(&mut self) {
+                    attach_thread();
+                }
+            }
+            if !ATTACHED.with(|attached| attached.get()) {
+                return f();
+            }
+            detach_thread();
+            let _reattach = Reattach;
+            f()
+        }
+
+        impl GcState {
+            fn stop_requested(&self) -> bool {
+                self.world.stop_requested.load(Ordering::Relaxed)
+            }
+
+            fn stop_the_world(&self, patience: Duration) -> bool {
+                self.world.stop(patience)
+            }
+
+            fn resume_the_world(&self) {
+                self.world.resume();
+            }
+        }
+    } else {
+        std::thread_local! {
+            static GC_STATE: GcState = GcState::new();
+        }
+
+        /// Returns `None` when objects are dropped during thread teardown, after the state is gone.
+        #[inline]
+        fn with_state<R>(f: impl FnOnce(&GcState) -> R) -> Option<R> {
+            GC_STATE.try_with(f).ok()
+        }
+
+        /// Run `f` without holding up collections that other threads start meanwhile. Meant for
+        /// blocking calls like sleeping or waiting for a lock; `f` must not touch any Python
+        /// object.
+        #[inline(always)]
+        pub fn allow_threads<R>(f: impl FnOnce() -> R) -> R {
+            f()
+        }
+
+        // only one thread can see the state, so there is nothing to stop
+        impl GcState {
+            fn stop_requested(&self) -> bool {
+                false
+            }
+
+            fn stop_the_world(&self, _patience: Duration) -> bool {
+                true
+            }
+
+            fn resume_the_world(&self) {}
+        }
+    }
+}
+
+impl<T> PyInner<T> {
+    /// Whether objects of this layout can take part in a reference cycle.
+    pub(super) fn is_gc(&self) -> bool {
+        // the vtable only has a trace function if the payload is `Traverse`
+        self.vtable.trace.is_some() || self.dict.is_some() || !self.slots.is_empty()
+    }
+}
+
+pub(super) fn track(obj: &PyObject) {
+    Radium::fetch_or(obj.gc_bits(), GC_TRACKED, Ordering::Relaxed);
+    with_state(|state| state.track(GcPtr::from(obj)));
+}
+
+/// Returns whether the object was tracked.
+pub(super) fn untrack(obj: &PyObject) -> bool {
+    if Radium::fetch_and(obj.gc_bits(), !GC_TRACKED, Ordering::Relaxed) & GC_TRACKED == 0 {
+        return false;
+    }
+    with_state(|state| state.untrack(GcPtr::from(obj))).unwrap_or(false)
+}
+
+pub fn is_tracked(obj: &PyObject) -> bool {
+    Radium::load(obj.gc_bits(), Ordering::Relaxed) & GC_TRACKED != 0
+}
+
+/// Record that `__del__` is about to run on `obj`. Returns `false` if it already ran.
+pub(super) fn mark_finalized(obj: &PyObject) -> bool {
+    Radium::fetch_or(obj.gc_bits(), GC_FINALIZED, Ordering::Relaxed) & GC_FINALIZED == 0
+}
+
+pub fn is_finalized(obj: &PyObject) -> bool {
+    Radium::load(obj.gc_bits(), Ordering::Relaxed) & GC_FINALIZED != 0
+}
+
+pub fn isenabled() -> bool {
+    with_state(|state| state.enabled.load(Ordering::Relaxed)).unwrap_or(false)
+}
+
+pub fn enable() {
+    with_state(|state| state.enabled.store(true, Ordering::Relaxed));
+}
+
+pub fn disable() {
+    with_state(|state| {
+        state.enabled.store(false, Ordering::Relaxed);
+        state.pending.store(false, Ordering::Relaxed);
+    });
+}
+
+pub fn get_debug() -> u32 {
+    with_state(|state| state.debug.load(Ordering::Relaxed)).unwrap_or(0)
+}
+
+pub fn set_debug(flags: u32) {
+    with_state(|state| state.debug.store(flags, Ordering::Relaxed));
+}
+
+pub fn get_count() -> [usize; NUM_GENERATIONS] {
+    with_state(|state| state.counts(&state.inner.lock())).unwrap_or_default()
+}
+
+pub fn get_threshold() -> [u32; NUM_GENERATIONS] {
+    with_state(|state| state.inner.lock().thresholds).unwrap_or_default()
+}
+
+/// Only the thresholds that are given are changed.
+pub fn set_threshold(thresholds: &[u32]) {
+    with_state(|state| {
+        let mut inner = state.inner.lock();
+        for (old, &new) in inner.thresholds.iter_mut().zip(thresholds) {
+            *old = new;
+        }
+        state
+            .young_threshold
+            .store(inner.thresholds[0] as usize, Ordering::Relaxed);
+    });
+}
+
+pub fn get_stats() -> [GcStats; NUM_GENERATIONS] {
+    with_state(|state| state.inner.lock().stats).unwrap_or_default()
+}
+
+/// All tracked objects, or only those in `generation`.
+pub fn get_objects(generation: Option<usize>) -> Vec<PyObjectRef> {
+    with_state(|state| {
+        let mut inner = state.inner.lock();
+        if generation.map_or(true, |gen| gen == 0) {
+            state.gather_young(&mut inner);
+        }
+        let gens = match generation {
+            Some(gen) => &inner.generations[gen..=gen],
+            None => &inner.generations[..],
+        };
+        gens.iter()
+            .flatten()
+            .filter_map(|ptr| ptr.upgrade())
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Move every tracked object to the permanent generation, which is never collected.
+pub fn freeze() {
+    with_state(|state| {
+        let mut inner = state.inner.lock();
+        state.gather_young(&mut inner);
+        let GcGenerations {
+            generations,
+            permanent,
+            counts,
+            ..
+        } = &mut *inner;
+        for gen in generations.iter_mut() {
+            permanent.extend(gen.drain());
+        }
+        *counts = [0; NUM_GENERATIONS];
+        state.young_count.store(0, Ordering::Relaxed);
+    });
+}
+
+/// Move the permanent generation back into the oldest one.
+pub fn unfreeze() {
+    with_state(|state| {
+        let mut inner = state.inner.lock();
+        let frozen = std::mem::take(&mut inner.permanent);
+        inner.generations[NUM_GENERATIONS - 1].extend(frozen);
+    });
+}
+
+pub fn get_freeze_count() -> usize {
+    with_state(|state| state.inner.lock().permanent.len()).unwrap_or(0)
+}
+
+/// Break the references held by an unreachable object. Removed references are pushed to `out`
+/// so that they are dropped after every object in the cycle has been cleared.
+fn clear_object(obj: &PyObject, out: &mut Vec<PyObjectRef>) {
+    out.extend(obj.take_slots());
+    if let Some(list) = obj.payload::<PyList>() {
+        out.append(&mut list.borrow_vec_mut());
+    } else if let Some(dict) = obj.payload::<PyDict>() {
+        dict.clear();
+    } else if let Some(cell) = obj.payload::<PyCell>() {
+        out.extend(cell.get());
+        cell.set(None);
+    } else if let Some(typ) = obj.payload::<PyType>() {
+        if typ.heaptype_ext.is_some() {
+            out.extend(typ.attributes.write().drain(..).map(|(_, v)| v));
+        }
+    }
+}
+
+fn invoke_callbacks(
+    vm: &VirtualMachine,
+    phase: &'static str,
+    generation: usize,
+    collected: usize,
+    uncollectable: usize,
+) {
+    let callbacks = vm.state.gc_callbacks.borrow_vec().to_vec();
+    if callbacks.is_empty() {
+        return;
+    }
+    let info = vm.ctx.new_dict();
+    for (key, value) in [
+        ("generation", generation),
+        ("collected", collected),
+        ("uncollectable", uncollectable),
+    ] {
+        info.set_item(key, vm.ctx.new_int(value).into(), vm)
+            .unwrap();
+    }
+    let phase = vm.ctx.new_str(phase);
+    for callback in callbacks {
+        if let Err(e) = callback.call((phase.clone(), info.clone()), vm) {
+            vm.run_unraisable(
+                e,
+                Some("Exception ignored in garbage collector callback".to_owned()),
+                callback,
+            );
+        }
+    }
+}
+
+/// Collect `generation` and every younger generation. Returns the number of unreachable objects
+/// that were found.
+pub fn collect(vm: &VirtualMachine, generation: usize) -> usize {
+    collect_with_patience(vm, generation, EXPLICIT_PATIENCE)
+}
+
+fn collect_with_patience(vm: &VirtualMachine, generation: usize, patience: Duration) -> usize {
+    let Some(state) = with_state(|state| state as *const GcState) else {
+        return 0;
+    };
+    // SAFETY: the state lives as long as the thread (or the process, with threading)
+    let state = unsafe { &*state };
+    if state.collecting.swap(true, Ordering::Acquire) {
+        return 0;
+    }
+    state.pending.store(false, Ordering::Relaxed);
+    invoke_callbacks(vm, "start", generation, 0, 0);
+
+    let debug = state.debug.load(Ordering::Relaxed);
+    if debug & DEBUG_STATS != 0 {
+        let counts = state.counts(&state.inner.lock());
+        writeln!(
+            crate::stdlib::sys::PyStderr(vm),
+            "gc: collecting generation {generation}...\ngc: objects in each generation: {} {} {}",
+            counts[0],
+            counts[1],
+            counts[2],
+        );
+    }
+
+    if !state.stop_the_world(patience) {
+        // try again once generation 0 has filled up another time
+        state.young_count.store(0, Ordering::Relaxed);
+        state.collecting.store(false, Ordering::Release);
+        invoke_callbacks(vm, "stop", generation, 0, 0);
+        return 0;
+    }
+    let garbage = state.find_unreachable(generation);
+    // clearing the garbage can run finalizers and weakref callbacks, which may need the other
+    // threads to make progress
+    state.resume_the_world();
+    let collected = garbage.len();
+    if debug & DEBUG_COLLECTABLE != 0 {
+        for obj in &garbage {
+            writeln!(
+                crate::stdlib::sys::PyStderr(vm),
+                "gc: collectable <{} {:p}>",
+                obj.class().name(),
+                obj.as_raw(),
+            );
+        }
+    }
+    if debug & DEBUG_SAVEALL != 0 {
+        vm.state.gc_garbage.borrow_vec_mut().extend(garbage);
+    } else {
+        let mut children = Vec::new();
+        for obj in &garbage {
+            clear_object(obj, &mut children);
+        }
+        drop(children);
+        drop(garbage);
+    }
+    if debug & DEBUG_STATS != 0 {
+        writeln!(
+            crate::stdlib::sys::PyStderr(vm),
+            "gc: done, {collected} unreachable, 0 uncollectable.",
+        );
+    }
+
+    {
+        let mut inner = state.inner.lock();
+        let stats = &mut inner.stats[generation];
+        stats.collections += 1;
+        stats.collected += collected;
+    }
+    state.collecting.store(false, Ordering::Release);
+    invoke_callbacks(vm, "stop", generation, collected, 0);
+    collected
+}
+
+/// Called by the frame loop when a frame starts and when it jumps backwards, so that every loop
+/// and every recursion passes one regularly. Parks the thread while another thread is collecting,
+/// and runs an automatic collection once generation 0 has gone over its threshold.
+#[inline(always)]
+pub(crate) fn safepoint(vm: &VirtualMachine) {
+    if with_state(|state| state.pending.load(Ordering::Relaxed) || state.stop_requested())
+        == Some(true)
+    {
+        safepoint_slow(vm);
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn safepoint_slow(vm: &VirtualMachine) {
+    #[cfg(feature = "threading")]
+    if GC_STATE.stop_requested() {
+        GC_STATE.world.park();
+    }
+    if !isenabled() || vm.state.finalizing.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(generation) = with_state(GcState::generation_to_collect).flatten() {
+        collect_with_patience(vm, generation, AUTOMATIC_PATIENCE);
+    } else {
+        with_state(|state| state.pending.store(false, Ordering::Relaxed));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AsObject, Interpreter};
+
+    #[test]
+    fn test_collect_list_cycle() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let list = vm.ctx.new_list(vec![]);
+            list.borrow_vec_mut().push(list.clone().into());
+            assert!(is_tracked(list.as_object()));
+            let weak = list.as_object().downgrade(None, vm).unwrap();
+            drop(list);
+            assert!(weak.upgrade().is_some());
+            assert!(collect(vm, NUM_GENERATIONS - 1) >= 1);
+            assert!(weak.upgrade().is_none());
+        })
+    }
+
+    /// Puts back the settings of the collector, which is shared by every test, when a test
+    /// that changes them ends, even by panicking.
+    struct RestoreSettings {
+        enabled: bool,
+        thresholds: [u32; NUM_GENERATIONS],
+    }
+
+    impl RestoreSettings {
+        fn new() -> Self {
+            Self {
+                enabled: isenabled(),
+                thresholds: get_threshold(),
+            }
+        }
+    }
+
+    impl Drop for RestoreSettings {
+        fn drop(&mut self) {
+            set_threshold(&self.thresholds);
+            if self.enabled {
+                enable()
+            } else {
+                disable()
+            }
+        }
+    }
+
+    fn run(vm: &VirtualMachine, scope: &crate::scope::Scope, source: &str) {
+        vm.run_code_string(scope.clone(), source, "<unittest>".to_owned())
+            .map_err(|e| vm.print_exception(e))
+            .expect("source should run");
+    }
+
+    #[test]
+    fn test_collect_del_cycle() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            run(
+                vm,
+                &scope,
+                r#"
+import _weakref
+finalized = []
+class A:
+    def __del__(self):
+        finalized.append(True)
+a = A()
+a.self = a
+r = _weakref.ref(a)
+del a
+assert r() is not None
+"#,
+            );
+            assert!(collect(vm, NUM_GENERATIONS - 1) >= 1);
+            run(
+                vm,
+                &scope,
+                "assert finalized == [True]\nassert r() is None\n",
+            );
+        })
+    }
+
+    #[test]
+    fn test_collect_dict_and_attribute_cycle() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            run(
+                vm,
+                &scope,
+                r#"
+import _weakref
+class A:
+    pass
+a = A()
+b = A()
+a.other = b
+b.other = a
+d = {}
+d['self'] = d
+d['obj'] = c = A()
+refs = [_weakref.ref(a), _weakref.ref(b), _weakref.ref(c)]
+del a, b, c, d
+"#,
+            );
+            assert!(collect(vm, NUM_GENERATIONS - 1) >= 3);
+            run(vm, &scope, "for r in refs:\n    assert r() is None\n");
+        })
+    }
+
+    #[test]
+    fn test_reachable_cycle_survives() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            run(
+                vm,
+                &scope,
+                r#"
+class A:
+    pass
+a = A()
+a.self = a
+lst = [a]
+lst.append(lst)
+"#,
+            );
+            collect(vm, NUM_GENERATIONS - 1);
+            run(
+                vm,
+                &scope,
+                "assert a.self is a\nassert lst[0] is a and lst[1] is lst\n",
+            );
+        })
+    }
+
+    #[test]
+    fn test_disable() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let _restore = RestoreSettings::new();
+            let scope = vm.new_scope_with_builtins();
+            disable();
+            set_threshold(&[1]);
+            run(
+                vm,
+                &scope,
+                r#"
+import _weakref
+class A:
+    pass
+a = A()
+a.self = a
+r = _weakref.ref(a)
+del a
+for _ in range(100):
+    x = []
+assert r() is not None
+"#,
+            );
+            enable();
+            run(
+                vm,
+                &scope,
+                "for _ in range(100):\n    x = []\nassert r() is None\n",
+            );
+        })
+    }
+
+    #[test]
+    fn test_del_runs_once() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            run(
+                vm,
+                &scope,
+                r#"
+calls = []
+class A:
+    def __del__(self):
+        calls.append(self)
+a = A()
+del a
+assert len(calls) == 1
+"#,
+            );
+            let calls = scope.globals.get_item("calls", vm).unwrap();
+            let resurrected = vm.call_method(&calls, "pop", ()).unwrap();
+            assert!(is_finalized(&resurrected));
+            assert!(is_tracked(&resurrected));
+            drop(resurrected);
+            run(vm, &scope, "assert calls == []\n");
+        })
+    }
+
+    #[test]
+    fn test_untracked_objects() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let int = vm.ctx.new_int(12345);
+            assert!(!is_tracked(int.as_object()));
+            assert!(!is_finalized(int.as_object()));
+            let list = vm.ctx.new_list(vec![int.into()]);
+            assert!(is_tracked(list.as_object()));
+            assert!(!is_finalized(list.as_object()));
+        })
+    }
+}
@@ -1,5 +1,6 @@
 mod core;
 mod ext;
+pub mod gc;
 mod payload;
 mod traverse;
 mod traverse_object;
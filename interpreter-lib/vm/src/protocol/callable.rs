@@ -1,4 +1,8 @@
 use crate::{
+    builtins::{
+        builtin_func::{PyNativeFunction, PyNativeMethod},
+        descriptor::PyMethodDescriptor,
+    },
     function::{FuncArgs, IntoFuncArgs},
     types::GenericMethod,
     {AsObject, PyObject, PyResult, VirtualMachine},
@@ -47,17 +51,40 @@ impl<'a> PyCallable<'a> {
 
     pub fn invoke(&self, args: impl IntoFuncArgs, vm: &VirtualMachine) -> PyResult {
         let args = args.into_args(vm);
-        vm.trace_event(TraceEvent::Call)?;
+        if !(vm.use_tracing.get() && self.is_builtin()) {
+            return (self.call)(self.obj, args, vm);
+        }
+        // Python functions report themselves from their frames; only native callables
+        // produce c_call / c_return / c_exception here.
+        vm.profile_event(TraceEvent::CCall, self.obj)?;
         let result = (self.call)(self.obj, args, vm);
-        vm.trace_event(TraceEvent::Return)?;
+        let event = if result.is_ok() {
+            TraceEvent::CReturn
+        } else {
+            TraceEvent::CException
+        };
+        vm.profile_event(event, self.obj)?;
         result
     }
+
+    fn is_builtin(&self) -> bool {
+        self.obj.payload_is::<PyNativeFunction>()
+            || self.obj.payload_is::<PyNativeMethod>()
+            || self.obj.payload_is::<PyMethodDescriptor>()
+    }
 }
 
 /// Trace events for sys.settrace and sys.setprofile.
-enum TraceEvent {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TraceEvent {
     Call,
     Return,
+    Line,
+    Exception,
+    Opcode,
+    CCall,
+    CReturn,
+    CException,
 }
 
 impl std::fmt::Display for TraceEvent {
@@ -66,24 +93,30 @@ impl std::fmt::Display for TraceEvent {
         match self {
             Call => write!(f, "call"),
             Return => write!(f, "return"),
+            Line => write!(f, "line"),
+            Exception => write!(f, "exception"),
+            Opcode => write!(f, "opcode"),
+            CCall => write!(f, "c_call"),
+            CReturn => write!(f, "c_return"),
+            CException => write!(f, "c_exception"),
         }
     }
 }
 
 impl VirtualMachine {
-    /// Call registered trace function.
+    /// Call registered profile function with the current frame, `event` and `arg`. Trace
+    /// functions are called by the frames themselves, see `Frame::trace_event`.
     #[inline]
-    fn trace_event(&self, event: TraceEvent) -> PyResult<()> {
+    pub(crate) fn profile_event(&self, event: TraceEvent, arg: &PyObject) -> PyResult<()> {
         if self.use_tracing.get() {
-            self._trace_event_inner(event)
+            self._profile_event_inner(event, arg)
         } else {
             Ok(())
         }
     }
-    fn _trace_event_inner(&self, event: TraceEvent) -> PyResult<()> {
-        let trace_func = self.trace_func.borrow().to_owned();
+    fn _profile_event_inner(&self, event: TraceEvent, arg: &PyObject) -> PyResult<()> {
         let profile_func = self.profile_func.borrow().to_owned();
-        if self.is_none(&trace_func) && self.is_none(&profile_func) {
+        if self.is_none(&profile_func) {
             return Ok(());
         }
 
@@ -94,27 +127,23 @@ impl VirtualMachine {
 
         let frame = frame_ref.unwrap().as_object().to_owned();
         let event = self.ctx.new_str(event.to_string()).into();
-        let args = vec![frame, event, self.ctx.none()];
+        let args = vec![frame, event, arg.to_owned()];
 
         // temporarily disable tracing, during the call to the
-        // tracing function itself.
-        if !self.is_none(&trace_func) {
-            self.use_tracing.set(false);
-            let res = trace_func.call(args.clone(), self);
-            self.use_tracing.set(true);
-            if res.is_err() {
-                *self.trace_func.borrow_mut() = self.ctx.none();
-            }
+        // profiling function itself.
+        self.use_tracing.set(false);
+        let res = profile_func.call(args, self);
+        if res.is_err() {
+            *self.profile_func.borrow_mut() = self.ctx.none();
         }
+        self.update_use_tracing();
+        res.map(drop)
+    }
 
-        if !self.is_none(&profile_func) {
-            self.use_tracing.set(false);
-            let res = profile_func.call(args, self);
-            self.use_tracing.set(true);
-            if res.is_err() {
-                *self.profile_func.borrow_mut() = self.ctx.none();
-            }
-        }
-        Ok(())
+    /// Tracing is on when either a trace or a profile function is set.
+    pub fn update_use_tracing(&self) {
+        let trace_is_none = self.is_none(&self.trace_func.borrow());
+        let profile_is_none = self.is_none(&self.profile_func.borrow());
+        self.use_tracing.set(!(trace_is_none && profile_is_none));
     }
 }
@@ -8,7 +8,7 @@ use std::{
     },
 };
 
-pub(crate) const NSIG: usize = 64;
+pub const NSIG: usize = 64;
 static ANY_TRIGGERED: AtomicBool = AtomicBool::new(false);
 // hack to get around const array repeat expressions, rust issue #79270
 #[allow(clippy::declare_interior_mutable_const)]
@@ -797,6 +797,25 @@ impl NodeStmtContinue {
         );
     }
 }
+#[pyclass(module = "_ast", name = "Invalid", base = "NodeStmt")]
+struct NodeStmtInvalid;
+#[pyclass(flags(HAS_DICT, BASETYPE))]
+impl NodeStmtInvalid {
+    #[extend_class]
+    fn extend_class_with_fields(ctx: &Context, class: &'static Py<PyType>) {
+        class.set_attr(identifier!(ctx, _fields), ctx.new_tuple(vec![]).into());
+        class.set_attr(
+            identifier!(ctx, _attributes),
+            ctx.new_list(vec![
+                ctx.new_str(ascii!("lineno")).into(),
+                ctx.new_str(ascii!("col_offset")).into(),
+                ctx.new_str(ascii!("end_lineno")).into(),
+                ctx.new_str(ascii!("end_col_offset")).into(),
+            ])
+            .into(),
+        );
+    }
+}
 #[pyclass(module = "_ast", name = "expr", base = "NodeAst")]
 struct NodeExpr;
 #[pyclass(flags(HAS_DICT, BASETYPE))]
@@ -1497,6 +1516,25 @@ impl NodeExprSlice {
         );
     }
 }
+#[pyclass(module = "_ast", name = "Invalid", base = "NodeExpr")]
+struct NodeExprInvalid;
+#[pyclass(flags(HAS_DICT, BASETYPE))]
+impl NodeExprInvalid {
+    #[extend_class]
+    fn extend_class_with_fields(ctx: &Context, class: &'static Py<PyType>) {
+        class.set_attr(identifier!(ctx, _fields), ctx.new_tuple(vec![]).into());
+        class.set_attr(
+            identifier!(ctx, _attributes),
+            ctx.new_list(vec![
+                ctx.new_str(ascii!("lineno")).into(),
+                ctx.new_str(ascii!("col_offset")).into(),
+                ctx.new_str(ascii!("end_lineno")).into(),
+                ctx.new_str(ascii!("end_col_offset")).into(),
+            ])
+            .into(),
+        );
+    }
+}
 #[pyclass(module = "_ast", name = "expr_context", base = "NodeAst")]
 struct NodeExprContext;
 #[pyclass(flags(HAS_DICT, BASETYPE))]
@@ -2492,6 +2530,7 @@ impl Node for ast::located::Stmt {
             ast::located::Stmt::Pass(cons) => cons.ast_to_object(vm),
             ast::located::Stmt::Break(cons) => cons.ast_to_object(vm),
             ast::located::Stmt::Continue(cons) => cons.ast_to_object(vm),
+            ast::located::Stmt::Invalid(cons) => cons.ast_to_object(vm),
         }
     }
     fn ast_from_object(_vm: &VirtualMachine, _object: PyObjectRef) -> PyResult<Self> {
@@ -2566,6 +2605,8 @@ impl Node for ast::located::Stmt {
             ast::located::Stmt::Break(ast::located::StmtBreak::ast_from_object(_vm, _object)?)
         } else if _cls.is(NodeStmtContinue::static_type()) {
             ast::located::Stmt::Continue(ast::located::StmtContinue::ast_from_object(_vm, _object)?)
+        } else if _cls.is(NodeStmtInvalid::static_type()) {
+            ast::located::Stmt::Invalid(ast::located::StmtInvalid::ast_from_object(_vm, _object)?)
         } else {
             return Err(_vm.new_type_error(format!(
                 "expected some sort of stmt, but got {}",
@@ -3515,6 +3556,23 @@ impl Node for ast::located::StmtContinue {
         })
     }
 }
+// constructor
+impl Node for ast::located::StmtInvalid {
+    fn ast_to_object(self, _vm: &VirtualMachine) -> PyObjectRef {
+        let ast::located::StmtInvalid { range: _range } = self;
+        let node = NodeAst
+            .into_ref_with_type(_vm, NodeStmtInvalid::static_type().to_owned())
+            .unwrap();
+        let dict = node.as_object().dict().unwrap();
+        node_add_location(&dict, _range, _vm);
+        node.into()
+    }
+    fn ast_from_object(_vm: &VirtualMachine, _object: PyObjectRef) -> PyResult<Self> {
+        Ok(ast::located::StmtInvalid {
+            range: range_from_object(_vm, _object, "Invalid")?,
+        })
+    }
+}
 // sum
 impl Node for ast::located::Expr {
     fn ast_to_object(self, vm: &VirtualMachine) -> PyObjectRef {
@@ -3546,6 +3604,7 @@ impl Node for ast::located::Expr {
             ast::located::Expr::List(cons) => cons.ast_to_object(vm),
             ast::located::Expr::Tuple(cons) => cons.ast_to_object(vm),
             ast::located::Expr::Slice(cons) => cons.ast_to_object(vm),
+            ast::located::Expr::Invalid(cons) => cons.ast_to_object(vm),
         }
     }
     fn ast_from_object(_vm: &VirtualMachine, _object: PyObjectRef) -> PyResult<Self> {
@@ -3618,6 +3677,8 @@ impl Node for ast::located::Expr {
             ast::located::Expr::Tuple(ast::located::ExprTuple::ast_from_object(_vm, _object)?)
         } else if _cls.is(NodeExprSlice::static_type()) {
             ast::located::Expr::Slice(ast::located::ExprSlice::ast_from_object(_vm, _object)?)
+        } else if _cls.is(NodeExprInvalid::static_type()) {
+            ast::located::Expr::Invalid(ast::located::ExprInvalid::ast_from_object(_vm, _object)?)
         } else {
             return Err(_vm.new_type_error(format!(
                 "expected some sort of expr, but got {}",
@@ -4401,6 +4462,23 @@ impl Node for ast::located::ExprSlice {
         })
     }
 }
+// constructor
+impl Node for ast::located::ExprInvalid {
+    fn ast_to_object(self, _vm: &VirtualMachine) -> PyObjectRef {
+        let ast::located::ExprInvalid { range: _range } = self;
+        let node = NodeAst
+            .into_ref_with_type(_vm, NodeExprInvalid::static_type().to_owned())
+            .unwrap();
+        let dict = node.as_object().dict().unwrap();
+        node_add_location(&dict, _range, _vm);
+        node.into()
+    }
+    fn ast_from_object(_vm: &VirtualMachine, _object: PyObjectRef) -> PyResult<Self> {
+        Ok(ast::located::ExprInvalid {
+            range: range_from_object(_vm, _object, "Invalid")?,
+        })
+    }
+}
 // sum
 impl Node for ast::located::ExprContext {
     fn ast_to_object(self, vm: &VirtualMachine) -> PyObjectRef {
@@ -5403,6 +5481,7 @@ pub fn extend_module_nodes(vm: &VirtualMachine, module: &Py<PyModule>) {
         "Pass" => NodeStmtPass::make_class(&vm.ctx),
         "Break" => NodeStmtBreak::make_class(&vm.ctx),
         "Continue" => NodeStmtContinue::make_class(&vm.ctx),
+        "Invalid" => NodeStmtInvalid::make_class(&vm.ctx),
         "expr" => NodeExpr::make_class(&vm.ctx),
         "BoolOp" => NodeExprBoolOp::make_class(&vm.ctx),
         "NamedExpr" => NodeExprNamedExpr::make_class(&vm.ctx),
@@ -5431,6 +5510,7 @@ pub fn extend_module_nodes(vm: &VirtualMachine, module: &Py<PyModule>) {
         "List" => NodeExprList::make_class(&vm.ctx),
         "Tuple" => NodeExprTuple::make_class(&vm.ctx),
         "Slice" => NodeExprSlice::make_class(&vm.ctx),
+        "Invalid" => NodeExprInvalid::make_class(&vm.ctx),
         "expr_context" => NodeExprContext::make_class(&vm.ctx),
         "Load" => NodeExprContextLoad::make_class(&vm.ctx),
         "Store" => NodeExprContextStore::make_class(&vm.ctx),
@@ -6,8 +6,9 @@ mod decl {
     use crate::class::StaticType;
     use crate::{
         builtins::{
-            PyBool, PyByteArray, PyBytes, PyCode, PyComplex, PyDict, PyEllipsis, PyFloat,
-            PyFrozenSet, PyInt, PyList, PyNone, PySet, PyStopIteration, PyStr, PyTuple,
+            PyBaseExceptionRef, PyBool, PyByteArray, PyBytes, PyCode, PyComplex, PyDict,
+            PyEllipsis, PyFloat, PyFrozenSet, PyInt, PyList, PyMemoryView, PyNone, PySet,
+            PyStopIteration, PyStr, PyTuple,
         },
         convert::ToPyObject,
         function::{ArgBytesLike, OptionalArg},
@@ -79,6 +80,12 @@ mod decl {
                 ref bytes @ PyByteArray => {
                     f(Bytes(&bytes.borrow_buf()))
                 }
+                ref view @ PyMemoryView => {
+                    let Some(bytes) = view.collect_bytes() else {
+                        return Err(DumpError);
+                    };
+                    f(Bytes(&bytes))
+                }
                 ref co @ PyCode => {
                     f(Code(co))
                 }
@@ -99,11 +106,7 @@ mod decl {
         value
             .with_dump(|val| marshal::serialize_value(&mut buf, val))
             .unwrap_or_else(Err)
-            .map_err(|DumpError| {
-                vm.new_not_implemented_error(
-                    "TODO: not implemented yet or marshal unsupported type".to_owned(),
-                )
-            })?;
+            .map_err(|DumpError| vm.new_value_error("unmarshallable object".to_owned()))?;
         Ok(PyBytes::from(buf))
     }
 
@@ -204,7 +207,30 @@ mod decl {
         let buf = pybuffer.as_contiguous().ok_or_else(|| {
             vm.new_buffer_error("Buffer provided to marshal.loads() is not contiguous".to_owned())
         })?;
-        marshal::deserialize_value(&mut &buf[..], PyMarshalBag(vm)).map_err(|e| match e {
+        marshal::deserialize_value(&mut &buf[..], PyMarshalBag(vm))
+            .map_err(|e| new_marshal_error(e, vm))
+    }
+
+    #[pyfunction]
+    fn load(f: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
+        let read_res = vm.call_method(&f, "read", ())?;
+        let bytes = ArgBytesLike::try_from_object(vm, read_res)?;
+        let (value, unread) = bytes.with_ref(|buf| {
+            let mut rdr = buf;
+            marshal::deserialize_value(&mut rdr, PyMarshalBag(vm))
+                .map(|value| (value, rdr.len()))
+                .map_err(|e| new_marshal_error(e, vm))
+        })?;
+        // leave the file right after the value, so that values dumped one after another can
+        // be loaded one after another
+        if unread > 0 {
+            vm.call_method(&f, "seek", (-(unread as isize), 1))?;
+        }
+        Ok(value)
+    }
+
+    fn new_marshal_error(err: marshal::MarshalError, vm: &VirtualMachine) -> PyBaseExceptionRef {
+        match err {
             marshal::MarshalError::Eof => vm.new_exception_msg(
                 vm.ctx.exceptions.eof_error.to_owned(),
                 "marshal data too short".to_owned(),
@@ -221,13 +247,6 @@ mod decl {
             marshal::MarshalError::BadType => {
                 vm.new_value_error("bad marshal data (unknown type code)".to_owned())
             }
-        })
-    }
-
-    #[pyfunction]
-    fn load(f: PyObjectRef, vm: &VirtualMachine) -> PyResult<PyObjectRef> {
-        let read_res = vm.call_method(&f, "read", ())?;
-        let bytes = ArgBytesLike::try_from_object(vm, read_res)?;
-        loads(PyBuffer::from(bytes), vm)
+        }
     }
 }
@@ -243,8 +243,7 @@ pub(super) mod _os {
     fn read(fd: i32, n: usize, vm: &VirtualMachine) -> PyResult<PyBytesRef> {
         let mut buffer = vec![0u8; n];
         let mut file = Fd(fd);
-        let n = file
-            .read(&mut buffer)
+        let n = crate::object::gc::allow_threads(|| file.read(&mut buffer))
             .map_err(|err| err.into_pyexception(vm))?;
         buffer.truncate(n);
 
@@ -255,7 +254,7 @@ pub(super) mod _os {
     fn write(fd: i32, data: ArgBytesLike, vm: &VirtualMachine) -> PyResult {
         let mut file = Fd(fd);
         let written = data
-            .with_ref(|b| file.write(b))
+            .with_ref(|b| crate::object::gc::allow_threads(|| file.write(b)))
             .map_err(|err| err.into_pyexception(vm))?;
 
         Ok(vm.ctx.new_int(written).into())
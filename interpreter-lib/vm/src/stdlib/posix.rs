@@ -23,12 +23,12 @@ pub mod module {
     use crate::{
         builtins::{PyDictRef, PyInt, PyListRef, PyStrRef, PyTupleRef, PyTypeRef},
         convert::{IntoPyException, ToPyObject, TryFromObject},
-        function::{Either, KwArgs, OptionalArg},
+        function::{ArgBytesLike, ArgMemoryBuffer, Either, KwArgs, OptionalArg},
         ospath::{IOErrorBuilder, OsPath, OsPathOrFd},
         stdlib::os::{
             errno_err, DirFd, FollowSymlinks, SupportFunc, TargetIsDirectory, _os, fs_metadata,
         },
-        types::{Constructor, Representable},
+        types::{Constructor, PyStructSequence, Representable},
         utils::ToCString,
         AsObject, Py, PyObjectRef, PyPayload, PyResult, VirtualMachine,
     };
@@ -1607,7 +1607,8 @@ pub mod module {
     #[pyfunction]
     fn waitpid(pid: libc::pid_t, opt: i32, vm: &VirtualMachine) -> PyResult<(libc::pid_t, i32)> {
         let mut status = 0;
-        let pid = unsafe { libc::waitpid(pid, &mut status, opt) };
+        let pid =
+            crate::object::gc::allow_threads(|| unsafe { libc::waitpid(pid, &mut status, opt) });
         let pid = nix::Error::result(pid).map_err(|err| err.into_pyexception(vm))?;
         Ok((pid, status))
     }
@@ -1729,6 +1730,16 @@ pub mod module {
             SupportFunc::new("umask", Some(false), Some(false), Some(false)),
             SupportFunc::new("execv", None, None, None),
             SupportFunc::new("pathconf", Some(true), None, None),
+            #[cfg(not(target_os = "redox"))]
+            SupportFunc::new("statvfs", Some(true), None, None),
+            #[cfg(target_os = "linux")]
+            SupportFunc::new("getxattr", Some(true), None, Some(true)),
+            #[cfg(target_os = "linux")]
+            SupportFunc::new("setxattr", Some(true), None, Some(true)),
+            #[cfg(target_os = "linux")]
+            SupportFunc::new("listxattr", Some(true), None, Some(true)),
+            #[cfg(target_os = "linux")]
+            SupportFunc::new("removexattr", Some(true), None, Some(true)),
         ]
     }
 
@@ -2166,4 +2177,823 @@ pub mod module {
         }
         Ok(buf)
     }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyattr]
+    #[pyclass(module = "os", name = "statvfs_result")]
+    #[derive(Debug, PyStructSequence)]
+    struct StatvfsResult {
+        pub f_bsize: u64,
+        pub f_frsize: u64,
+        pub f_blocks: u64,
+        pub f_bfree: u64,
+        pub f_bavail: u64,
+        pub f_files: u64,
+        pub f_ffree: u64,
+        pub f_favail: u64,
+        pub f_flag: u64,
+        pub f_namemax: u64,
+        pub f_fsid: u64,
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyclass(with(PyStructSequence))]
+    impl StatvfsResult {
+        #[allow(clippy::unnecessary_cast)]
+        fn from_statvfs(st: &libc::statvfs) -> Self {
+            Self {
+                f_bsize: st.f_bsize as u64,
+                f_frsize: st.f_frsize as u64,
+                f_blocks: st.f_blocks as u64,
+                f_bfree: st.f_bfree as u64,
+                f_bavail: st.f_bavail as u64,
+                f_files: st.f_files as u64,
+                f_ffree: st.f_ffree as u64,
+                f_favail: st.f_favail as u64,
+                f_flag: st.f_flag as u64,
+                f_namemax: st.f_namemax as u64,
+                f_fsid: st.f_fsid as u64,
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn statvfs(path: OsPathOrFd, vm: &VirtualMachine) -> PyResult<StatvfsResult> {
+        let mut st = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        let ret = match &path {
+            OsPathOrFd::Path(p) => {
+                let p = p.clone().into_cstring(vm)?;
+                unsafe { libc::statvfs(p.as_ptr(), st.as_mut_ptr()) }
+            }
+            OsPathOrFd::Fd(fd) => unsafe { libc::fstatvfs(*fd, st.as_mut_ptr()) },
+        };
+        if ret == -1 {
+            return Err(IOErrorBuilder::with_filename(
+                &io::Error::last_os_error(),
+                path,
+                vm,
+            ));
+        }
+        Ok(StatvfsResult::from_statvfs(unsafe { st.assume_init_ref() }))
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn fstatvfs(fd: i32, vm: &VirtualMachine) -> PyResult<StatvfsResult> {
+        statvfs(OsPathOrFd::Fd(fd), vm)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{XATTR_CREATE, XATTR_REPLACE};
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    const XATTR_SIZE_MAX: usize = 1 << 16;
+
+    /// The file an extended attribute call applies to.
+    #[cfg(target_os = "linux")]
+    enum XattrTarget {
+        Path(CString),
+        Link(CString),
+        Fd(RawFd),
+    }
+
+    #[cfg(target_os = "linux")]
+    impl XattrTarget {
+        fn new(
+            func: &str,
+            path: &OsPathOrFd,
+            follow_symlinks: FollowSymlinks,
+            vm: &VirtualMachine,
+        ) -> PyResult<Self> {
+            match path {
+                OsPathOrFd::Path(path) => {
+                    let path = path.clone().into_cstring(vm)?;
+                    Ok(if follow_symlinks.0 {
+                        Self::Path(path)
+                    } else {
+                        Self::Link(path)
+                    })
+                }
+                OsPathOrFd::Fd(_) if !follow_symlinks.0 => Err(vm.new_value_error(format!(
+                    "{func}: cannot use fd and follow_symlinks together"
+                ))),
+                OsPathOrFd::Fd(fd) => Ok(Self::Fd(*fd)),
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn xattr_error(
+        path: Option<OsPathOrFd>,
+        vm: &VirtualMachine,
+    ) -> crate::builtins::PyBaseExceptionRef {
+        let err = io::Error::last_os_error();
+        match path {
+            Some(path) => IOErrorBuilder::with_filename(&err, path, vm),
+            None => err.into_pyexception(vm),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn getxattr(
+        path: OsPathOrFd,
+        attribute: OsPath,
+        follow_symlinks: FollowSymlinks,
+        vm: &VirtualMachine,
+    ) -> PyResult<Vec<u8>> {
+        let target = XattrTarget::new("getxattr", &path, follow_symlinks, vm)?;
+        let attribute = attribute.into_cstring(vm)?;
+        // try a small buffer first, and the largest possible value if that is too small
+        for size in [128, XATTR_SIZE_MAX] {
+            let mut buf = vec![0u8; size];
+            let value = buf.as_mut_ptr() as *mut libc::c_void;
+            let name = attribute.as_ptr();
+            let ret = unsafe {
+                match &target {
+                    XattrTarget::Path(p) => libc::getxattr(p.as_ptr(), name, value, size),
+                    XattrTarget::Link(p) => libc::lgetxattr(p.as_ptr(), name, value, size),
+                    XattrTarget::Fd(fd) => libc::fgetxattr(*fd, name, value, size),
+                }
+            };
+            if ret >= 0 {
+                buf.truncate(ret as usize);
+                return Ok(buf);
+            }
+            if io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE)
+                || size == XATTR_SIZE_MAX
+            {
+                break;
+            }
+        }
+        Err(xattr_error(Some(path), vm))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn setxattr(
+        path: OsPathOrFd,
+        attribute: OsPath,
+        value: ArgBytesLike,
+        flags: OptionalArg<i32>,
+        follow_symlinks: FollowSymlinks,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let target = XattrTarget::new("setxattr", &path, follow_symlinks, vm)?;
+        let attribute = attribute.into_cstring(vm)?;
+        let flags = flags.unwrap_or(0);
+        let ret = value.with_ref(|value| {
+            let name = attribute.as_ptr();
+            let size = value.len();
+            let value = value.as_ptr() as *const libc::c_void;
+            unsafe {
+                match &target {
+                    XattrTarget::Path(p) => libc::setxattr(p.as_ptr(), name, value, size, flags),
+                    XattrTarget::Link(p) => libc::lsetxattr(p.as_ptr(), name, value, size, flags),
+                    XattrTarget::Fd(fd) => libc::fsetxattr(*fd, name, value, size, flags),
+                }
+            }
+        });
+        if ret == -1 {
+            Err(xattr_error(Some(path), vm))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn removexattr(
+        path: OsPathOrFd,
+        attribute: OsPath,
+        follow_symlinks: FollowSymlinks,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let target = XattrTarget::new("removexattr", &path, follow_symlinks, vm)?;
+        let attribute = attribute.into_cstring(vm)?;
+        let name = attribute.as_ptr();
+        let ret = unsafe {
+            match &target {
+                XattrTarget::Path(p) => libc::removexattr(p.as_ptr(), name),
+                XattrTarget::Link(p) => libc::lremovexattr(p.as_ptr(), name),
+                XattrTarget::Fd(fd) => libc::fremovexattr(*fd, name),
+            }
+        };
+        if ret == -1 {
+            Err(xattr_error(Some(path), vm))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn listxattr(
+        path: crate::function::OptionalOption<OsPathOrFd>,
+        follow_symlinks: FollowSymlinks,
+        vm: &VirtualMachine,
+    ) -> PyResult<Vec<PyObjectRef>> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = path.flatten();
+        let target = match &path {
+            Some(path) => XattrTarget::new("listxattr", path, follow_symlinks, vm)?,
+            None if follow_symlinks.0 => XattrTarget::Path(CString::new(".").unwrap()),
+            None => XattrTarget::Link(CString::new(".").unwrap()),
+        };
+        for size in [256, XATTR_SIZE_MAX] {
+            let mut buf = vec![0u8; size];
+            let list = buf.as_mut_ptr() as *mut libc::c_char;
+            let ret = unsafe {
+                match &target {
+                    XattrTarget::Path(p) => libc::listxattr(p.as_ptr(), list, size),
+                    XattrTarget::Link(p) => libc::llistxattr(p.as_ptr(), list, size),
+                    XattrTarget::Fd(fd) => libc::flistxattr(*fd, list, size),
+                }
+            };
+            if ret >= 0 {
+                buf.truncate(ret as usize);
+                return buf
+                    .split(|&c| c == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| OsPath::new_str(std::ffi::OsStr::from_bytes(name)).filename(vm))
+                    .collect();
+            }
+            if io::Error::last_os_error().raw_os_error() != Some(libc::ERANGE)
+                || size == XATTR_SIZE_MAX
+            {
+                break;
+            }
+        }
+        Err(xattr_error(path, vm))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{MFD_ALLOW_SEALING, MFD_CLOEXEC, MFD_HUGETLB};
+
+    #[cfg(target_os = "linux")]
+    #[derive(FromArgs)]
+    struct MemfdCreateArgs {
+        #[pyarg(any)]
+        name: OsPath,
+        #[pyarg(any, default = "libc::MFD_CLOEXEC")]
+        flags: libc::c_uint,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn memfd_create(args: MemfdCreateArgs, vm: &VirtualMachine) -> PyResult<RawFd> {
+        let name = args.name.into_cstring(vm)?;
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), args.flags) };
+        if fd == -1 {
+            Err(errno_err(vm))
+        } else {
+            Ok(fd)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{EFD_CLOEXEC, EFD_NONBLOCK, EFD_SEMAPHORE};
+
+    #[cfg(target_os = "linux")]
+    #[derive(FromArgs)]
+    struct EventfdArgs {
+        #[pyarg(any)]
+        initval: libc::c_uint,
+        #[pyarg(any, default = "libc::EFD_CLOEXEC")]
+        flags: i32,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn eventfd(args: EventfdArgs, vm: &VirtualMachine) -> PyResult<RawFd> {
+        let fd = unsafe { libc::eventfd(args.initval, args.flags) };
+        if fd == -1 {
+            Err(errno_err(vm))
+        } else {
+            Ok(fd)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn eventfd_read(fd: RawFd, vm: &VirtualMachine) -> PyResult<u64> {
+        let mut value = 0u64;
+        let ret = unsafe {
+            libc::read(
+                fd,
+                &mut value as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if ret == -1 {
+            Err(errno_err(vm))
+        } else {
+            Ok(value)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn eventfd_write(fd: RawFd, value: u64, vm: &VirtualMachine) -> PyResult<()> {
+        let ret = unsafe {
+            libc::write(
+                fd,
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        if ret == -1 {
+            Err(errno_err(vm))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    const PIDFD_NONBLOCK: i32 = libc::O_NONBLOCK;
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn pidfd_open(
+        pid: libc::pid_t,
+        flags: OptionalArg<u32>,
+        vm: &VirtualMachine,
+    ) -> PyResult<RawFd> {
+        // glibc only gained a wrapper in 2.36, so make the system call directly
+        let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, flags.unwrap_or(0)) };
+        if fd == -1 {
+            Err(errno_err(vm))
+        } else {
+            Ok(fd as RawFd)
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{
+        CLD_CONTINUED, CLD_DUMPED, CLD_EXITED, CLD_KILLED, CLD_STOPPED, CLD_TRAPPED, P_ALL, P_PGID,
+        P_PID, P_PIDFD, WCONTINUED, WEXITED, WNOWAIT, WSTOPPED,
+    };
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    #[pyclass(module = "posix", name = "waitid_result")]
+    #[derive(Debug, PyStructSequence)]
+    struct WaitidResult {
+        pub si_pid: libc::pid_t,
+        pub si_uid: libc::uid_t,
+        pub si_signo: i32,
+        pub si_status: i32,
+        pub si_code: i32,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyclass(with(PyStructSequence))]
+    impl WaitidResult {}
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn waitid(
+        idtype: libc::idtype_t,
+        id: libc::id_t,
+        options: i32,
+        vm: &VirtualMachine,
+    ) -> PyResult<Option<WaitidResult>> {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let ret = unsafe { libc::waitid(idtype, id, &mut info, options) };
+        if ret == -1 {
+            return Err(errno_err(vm));
+        }
+        let si_pid = unsafe { info.si_pid() };
+        // with WNOHANG and no child in a waitable state, nothing is filled in
+        if si_pid == 0 {
+            return Ok(None);
+        }
+        Ok(Some(WaitidResult {
+            si_pid,
+            si_uid: unsafe { info.si_uid() },
+            si_signo: info.si_signo,
+            si_status: unsafe { info.si_status() },
+            si_code: info.si_code,
+        }))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn sched_getaffinity(
+        pid: libc::pid_t,
+        vm: &VirtualMachine,
+    ) -> PyResult<crate::PyRef<crate::builtins::PySet>> {
+        use crate::builtins::PySet;
+        use nix::sched::CpuSet;
+        let cpus = nix::sched::sched_getaffinity(Pid::from_raw(pid))
+            .map_err(|err| err.into_pyexception(vm))?;
+        let set = PySet::new_ref(&vm.ctx);
+        for cpu in 0..CpuSet::count() {
+            if cpus.is_set(cpu).unwrap_or(false) {
+                set.add(vm.ctx.new_int(cpu).into(), vm)?;
+            }
+        }
+        Ok(set)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn sched_setaffinity(
+        pid: libc::pid_t,
+        mask: crate::function::ArgIterable,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        use nix::sched::CpuSet;
+        use num_traits::Signed;
+        let mut cpus = CpuSet::new();
+        for cpu in mask.iter(vm)? {
+            let cpu = cpu?.try_index(vm)?;
+            if cpu.as_bigint().is_negative() {
+                return Err(vm.new_value_error("negative CPU number".to_owned()));
+            }
+            let invalid = || vm.new_overflow_error("invalid CPU number".to_owned());
+            let cpu = cpu.try_to_primitive::<usize>(vm).map_err(|_| invalid())?;
+            cpus.set(cpu).map_err(|_| invalid())?;
+        }
+        nix::sched::sched_setaffinity(Pid::from_raw(pid), &cpus)
+            .map_err(|err| err.into_pyexception(vm))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{
+        POSIX_FADV_DONTNEED, POSIX_FADV_NOREUSE, POSIX_FADV_NORMAL, POSIX_FADV_RANDOM,
+        POSIX_FADV_SEQUENTIAL, POSIX_FADV_WILLNEED,
+    };
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn posix_fadvise(
+        fd: RawFd,
+        offset: crate::common::crt_fd::Offset,
+        len: crate::common::crt_fd::Offset,
+        advice: i32,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        // these report failure through their result rather than errno
+        let ret = unsafe { libc::posix_fadvise(fd, offset, len, advice) };
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret).into_pyexception(vm))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn posix_fallocate(
+        fd: RawFd,
+        offset: crate::common::crt_fd::Offset,
+        len: crate::common::crt_fd::Offset,
+        vm: &VirtualMachine,
+    ) -> PyResult<()> {
+        let ret = unsafe { libc::posix_fallocate(fd, offset, len) };
+        if ret != 0 {
+            Err(io::Error::from_raw_os_error(ret).into_pyexception(vm))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyattr]
+    use libc::{SPLICE_F_MORE, SPLICE_F_MOVE, SPLICE_F_NONBLOCK};
+
+    #[cfg(target_os = "linux")]
+    #[derive(FromArgs)]
+    struct SpliceArgs {
+        #[pyarg(positional)]
+        src: RawFd,
+        #[pyarg(positional)]
+        dst: RawFd,
+        #[pyarg(positional)]
+        count: i64,
+        #[pyarg(any, default)]
+        offset_src: Option<libc::loff_t>,
+        #[pyarg(any, default)]
+        offset_dst: Option<libc::loff_t>,
+        #[pyarg(any, default)]
+        flags: libc::c_uint,
+    }
+
+    #[cfg(target_os = "linux")]
+    #[pyfunction]
+    fn splice(args: SpliceArgs, vm: &VirtualMachine) -> PyResult<usize> {
+        let SpliceArgs {
+            src,
+            dst,
+            count,
+            mut offset_src,
+            mut offset_dst,
+            flags,
+        } = args;
+        let count: usize = count
+            .try_into()
+            .map_err(|_| vm.new_value_error("count should >= 0".to_owned()))?;
+        let p_offset_src = offset_src
+            .as_mut()
+            .map_or_else(std::ptr::null_mut, |x| x as *mut _);
+        let p_offset_dst = offset_dst
+            .as_mut()
+            .map_or_else(std::ptr::null_mut, |x| x as *mut _);
+        let ret = unsafe { libc::splice(src, p_offset_src, dst, p_offset_dst, count, flags) };
+        usize::try_from(ret).map_err(|_| errno_err(vm))
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn pread(
+        fd: RawFd,
+        n: usize,
+        offset: crate::common::crt_fd::Offset,
+        vm: &VirtualMachine,
+    ) -> PyResult<Vec<u8>> {
+        let mut buf = vec![0u8; n];
+        let ret = unsafe { libc::pread(fd, buf.as_mut_ptr() as *mut libc::c_void, n, offset) };
+        let n = usize::try_from(ret).map_err(|_| errno_err(vm))?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn pwrite(
+        fd: RawFd,
+        data: ArgBytesLike,
+        offset: crate::common::crt_fd::Offset,
+        vm: &VirtualMachine,
+    ) -> PyResult<usize> {
+        let ret = data.with_ref(|data| unsafe {
+            libc::pwrite(fd, data.as_ptr() as *const libc::c_void, data.len(), offset)
+        });
+        usize::try_from(ret).map_err(|_| errno_err(vm))
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    fn iovec_count(len: usize, vm: &VirtualMachine) -> PyResult<libc::c_int> {
+        libc::c_int::try_from(len)
+            .map_err(|_| vm.new_os_error("too many buffers for a single call".to_owned()))
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn readv(fd: RawFd, buffers: Vec<ArgMemoryBuffer>, vm: &VirtualMachine) -> PyResult<usize> {
+        // The buffers may share memory, e.g. when the same bytearray is passed twice, so they
+        // can't all be borrowed at once. The data is read into temporaries instead and copied
+        // out one buffer at a time.
+        let mut temps: Vec<Vec<u8>> = buffers.iter().map(|buf| vec![0; buf.len()]).collect();
+        let iovecs: Vec<libc::iovec> = temps
+            .iter_mut()
+            .map(|temp| libc::iovec {
+                iov_base: temp.as_mut_ptr() as *mut libc::c_void,
+                iov_len: temp.len(),
+            })
+            .collect();
+        let count = iovec_count(iovecs.len(), vm)?;
+        let ret = unsafe { libc::readv(fd, iovecs.as_ptr(), count) };
+        let read = usize::try_from(ret).map_err(|_| errno_err(vm))?;
+        let mut remaining = read;
+        for (buf, temp) in buffers.iter().zip(&temps) {
+            if remaining == 0 {
+                break;
+            }
+            let mut buf = buf.borrow_buf_mut();
+            let n = remaining.min(temp.len()).min(buf.len());
+            buf[..n].copy_from_slice(&temp[..n]);
+            remaining -= temp.len().min(remaining);
+        }
+        Ok(read)
+    }
+
+    #[cfg(not(target_os = "redox"))]
+    #[pyfunction]
+    fn writev(fd: RawFd, buffers: Vec<ArgBytesLike>, vm: &VirtualMachine) -> PyResult<usize> {
+        let borrowed: Vec<_> = buffers.iter().map(|buf| buf.borrow_buf()).collect();
+        let iovecs: Vec<libc::iovec> = borrowed
+            .iter()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let count = iovec_count(iovecs.len(), vm)?;
+        let ret = unsafe { libc::writev(fd, iovecs.as_ptr(), count) };
+        usize::try_from(ret).map_err(|_| errno_err(vm))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Interpreter;
+
+    #[test]
+    fn test_readv_writev() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let source = r#"
+import posix
+r, w = posix.pipe()
+try:
+    assert posix.writev(w, [b'abc', bytearray(b'de'), memoryview(b'fgh')]) == 8
+    first = bytearray(2)
+    second = bytearray(10)
+    assert posix.readv(r, [first, second]) == 8
+    assert first == b'ab'
+    assert second == b'cdefgh\0\0\0\0'
+
+    # the same buffer twice gets both parts of the data in turn
+    assert posix.writev(w, [b'abc', b'def']) == 6
+    buf = bytearray(3)
+    assert posix.readv(r, [buf, buf]) == 6
+    assert buf == b'def'
+finally:
+    posix.close(r)
+    posix.close(w)
+"#;
+            vm.run_code_string(
+                vm.new_scope_with_builtins(),
+                source,
+                "<unittest>".to_owned(),
+            )
+            .map_err(|e| vm.print_exception(e))
+            .unwrap();
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_apis() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let source = r#"
+import posix, errno
+
+def expect(error, call, *args, **kwargs):
+    try:
+        call(*args, **kwargs)
+    except error as e:
+        return e
+    raise AssertionError(f'{call.__name__}{args} did not raise {error!r}')
+
+# statvfs, by path and by file descriptor
+st = posix.statvfs('/')
+assert st.f_bsize > 0 and st.f_frsize > 0 and st.f_namemax > 0, st
+assert st.f_bavail <= st.f_bfree <= st.f_blocks, st
+assert st[0] == st.f_bsize and st[9] == st.f_namemax
+fd = posix.open('/', posix.O_RDONLY)
+try:
+    assert posix.fstatvfs(fd).f_blocks == st.f_blocks
+    assert posix.statvfs(fd).f_namemax == st.f_namemax
+finally:
+    posix.close(fd)
+e = expect(FileNotFoundError, posix.statvfs, '/no/such/directory')
+assert e.filename == '/no/such/directory'
+
+# memfd_create gives an anonymous file that behaves like a regular one
+fd = posix.memfd_create('smoke test')
+try:
+    assert not posix.get_inheritable(fd)
+    assert posix.readlink(f'/proc/self/fd/{fd}').startswith('/memfd:smoke test')
+    assert posix.write(fd, b'hello memfd') == 11
+    assert posix.fstat(fd).st_size == 11
+    posix.lseek(fd, 0, 0)
+    assert posix.read(fd, 100) == b'hello memfd'
+    posix.ftruncate(fd, 5)
+    assert posix.pread(fd, 100, 0) == b'hello'
+finally:
+    posix.close(fd)
+fd = posix.memfd_create('inheritable', 0)
+assert posix.get_inheritable(fd)
+posix.close(fd)
+expect(OSError, posix.memfd_create, 'bad flags', 0xFFFF)
+
+# eventfd counts writes up, and reads them back all at once or one by one
+fd = posix.eventfd(3)
+try:
+    assert not posix.get_inheritable(fd)
+    assert posix.eventfd_read(fd) == 3
+    posix.eventfd_write(fd, 4)
+    posix.eventfd_write(fd, 5)
+    assert posix.eventfd_read(fd) == 9
+finally:
+    posix.close(fd)
+fd = posix.eventfd(2, posix.EFD_SEMAPHORE | posix.EFD_NONBLOCK)
+try:
+    assert posix.eventfd_read(fd) == 1 and posix.eventfd_read(fd) == 1
+    expect(BlockingIOError, posix.eventfd_read, fd)
+finally:
+    posix.close(fd)
+
+# pidfd_open and waitid on a child that exits with a known status
+pid = posix.fork()
+if pid == 0:
+    posix._exit(7)
+pidfd = posix.pidfd_open(pid)
+try:
+    assert pidfd >= 0 and not posix.get_inheritable(pidfd)
+    result = posix.waitid(posix.P_PIDFD, pidfd, posix.WEXITED | posix.WNOWAIT)
+    assert result.si_pid == pid and result.si_code == posix.CLD_EXITED and result.si_status == 7, result
+    assert result.si_signo == 17 and result.si_uid == posix.getuid(), result
+    # WNOWAIT left the child waitable
+    result = posix.waitid(posix.P_PID, pid, posix.WEXITED)
+    assert result.si_pid == pid and result.si_status == 7, result
+finally:
+    posix.close(pidfd)
+expect(ChildProcessError, posix.waitid, posix.P_PID, pid, posix.WEXITED)
+expect(ProcessLookupError, posix.pidfd_open, pid)
+pid = posix.fork()
+if pid == 0:
+    posix.kill(posix.getpid(), 9)
+r, w = posix.pipe()
+blocker = posix.fork()
+if blocker == 0:
+    posix.read(r, 1)
+    posix._exit(0)
+try:
+    assert posix.waitid(posix.P_PID, blocker, posix.WEXITED | posix.WNOHANG) is None
+finally:
+    posix.write(w, b'x')
+    posix.close(r)
+    posix.close(w)
+result = posix.waitid(posix.P_PID, pid, posix.WEXITED)
+assert result.si_code == posix.CLD_KILLED and result.si_status == 9, result
+assert posix.waitid(posix.P_PID, blocker, posix.WEXITED).si_code == posix.CLD_EXITED
+
+# the affinity of the current process can be read and written back
+cpus = posix.sched_getaffinity(0)
+assert isinstance(cpus, set) and cpus and all(isinstance(cpu, int) and cpu >= 0 for cpu in cpus), cpus
+posix.sched_setaffinity(0, cpus)
+posix.sched_setaffinity(0, list(cpus))
+assert posix.sched_getaffinity(0) == cpus
+expect(ValueError, posix.sched_setaffinity, 0, [-1])
+expect(OverflowError, posix.sched_setaffinity, 0, [1 << 100])
+expect(OSError, posix.sched_setaffinity, 0, [])
+
+# extended attributes, where the file system has them
+path = f'/tmp/posix-xattr-{posix.getpid()}'
+fd = posix.open(path, posix.O_CREAT | posix.O_RDWR, 0o600)
+try:
+    try:
+        posix.setxattr(path, 'user.smoke', b'value')
+    except OSError as e:
+        if e.errno not in (errno.ENOTSUP, errno.EPERM):
+            raise
+    else:
+        assert posix.getxattr(path, 'user.smoke') == b'value'
+        assert posix.getxattr(fd, b'user.smoke') == b'value'
+        assert 'user.smoke' in posix.listxattr(path) and 'user.smoke' in posix.listxattr(fd)
+        posix.setxattr(fd, 'user.big', b'x' * 1000)
+        assert posix.getxattr(path, 'user.big') == b'x' * 1000
+        expect(FileExistsError, posix.setxattr, path, 'user.smoke', b'other', posix.XATTR_CREATE)
+        expect(OSError, posix.setxattr, path, 'user.missing', b'other', posix.XATTR_REPLACE)
+        posix.setxattr(path, 'user.smoke', b'replaced', posix.XATTR_REPLACE)
+        assert posix.getxattr(path, 'user.smoke', follow_symlinks=False) == b'replaced'
+        posix.removexattr(path, 'user.smoke')
+        posix.removexattr(fd, 'user.big')
+        assert not any(name.startswith('user.') for name in posix.listxattr(path))
+        e = expect(OSError, posix.getxattr, path, 'user.smoke')
+        assert e.errno == errno.ENODATA and e.filename == path, e
+    expect(ValueError, posix.getxattr, fd, 'user.smoke', follow_symlinks=False)
+    expect(FileNotFoundError, posix.listxattr, path + '-missing')
+finally:
+    posix.close(fd)
+    posix.unlink(path)
+
+# vectored I/O into and out of a memfd
+fd = posix.memfd_create('vectored')
+try:
+    assert posix.writev(fd, [b'one', b'', bytearray(b'two'), memoryview(b'three')]) == 11
+    posix.lseek(fd, 0, 0)
+    data = bytearray(8)
+    halves = [memoryview(data)[:4], memoryview(data)[4:]]
+    extra = bytearray(10)
+    assert posix.readv(fd, halves + [extra]) == 11
+    assert data == b'onetwoth' and extra[:3] == b'ree'
+    assert posix.readv(fd, [bytearray(4)]) == 0
+    expect((TypeError, BufferError), posix.readv, fd, [b'immutable'])
+finally:
+    posix.close(fd)
+expect(OSError, posix.writev, fd, [b'closed'])
+expect(OSError, posix.readv, fd, [bytearray(1)])
+"#;
+            vm.run_code_string(
+                vm.new_scope_with_builtins(),
+                source,
+                "<unittest>".to_owned(),
+            )
+            .map_err(|e| vm.print_exception(e))
+            .unwrap();
+        })
+    }
 }
@@ -1,6 +1,6 @@
 use crate::{builtins::PyModule, convert::ToPyObject, Py, PyResult, VirtualMachine};
 
-pub(crate) use sys::{UnraisableHookArgs, __module_def, DOC, MAXSIZE, MULTIARCH};
+pub(crate) use sys::{__module_def, UnraisableHookArgs, DOC, MAXSIZE, MULTIARCH};
 
 #[pymodule]
 mod sys {
@@ -220,7 +220,7 @@ mod sys {
         let ctx = &vm.ctx;
         py_namespace!(vm, {
             "name" => ctx.new_str(ascii!("rustpython")),
-            "cache_tag" => ctx.new_str(ascii!("rustpython-01")),
+            "cache_tag" => ctx.new_str(crate::import::CACHE_TAG),
             "_multiarch" => ctx.new_str(MULTIARCH.to_owned()),
             "version" => version_info(vm),
             "hexversion" => ctx.new_int(version::VERSION_HEX),
@@ -643,7 +643,7 @@ mod sys {
     #[pyfunction]
     fn setprofile(profilefunc: PyObjectRef, vm: &VirtualMachine) {
         vm.profile_func.replace(profilefunc);
-        update_use_tracing(vm);
+        vm.update_use_tracing();
     }
 
     #[pyfunction]
@@ -671,7 +671,7 @@ mod sys {
     #[pyfunction]
     fn settrace(tracefunc: PyObjectRef, vm: &VirtualMachine) {
         vm.trace_func.replace(tracefunc);
-        update_use_tracing(vm);
+        vm.update_use_tracing();
     }
 
     #[cfg(feature = "threading")]
@@ -685,13 +685,6 @@ mod sys {
         VersionInfo::VERSION.into_struct_sequence(vm)
     }
 
-    fn update_use_tracing(vm: &VirtualMachine) {
-        let trace_is_none = vm.is_none(&vm.trace_func.borrow());
-        let profile_is_none = vm.is_none(&vm.profile_func.borrow());
-        let tracing = !(trace_is_none && profile_is_none);
-        vm.use_tracing.set(tracing);
-    }
-
     /// sys.flags
     ///
     /// Flags provided through command line arguments or environment vars.
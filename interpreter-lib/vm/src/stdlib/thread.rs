@@ -2,22 +2,48 @@
 #[cfg_attr(target_arch = "wasm32", allow(unused_imports))]
 pub(crate) use _thread::{make_module, RawRMutex};
 
+use crate::VirtualMachine;
+
+/// Wait for all non-daemon threads started through `threading` to finish, like CPython's
+/// `wait_for_thread_shutdown()`. Nothing is done unless `threading` has been imported.
+pub(crate) fn wait_for_thread_shutdown(vm: &VirtualMachine) {
+    let threading = match vm
+        .sys_module
+        .get_attr("modules", vm)
+        .and_then(|modules| modules.get_item("threading", vm))
+    {
+        Ok(threading) => threading,
+        Err(_) => return,
+    };
+    if let Err(e) = vm.call_method(&threading, "_shutdown", ()) {
+        vm.run_unraisable(
+            e,
+            Some("Exception ignored on threading shutdown".to_owned()),
+            threading,
+        );
+    }
+}
+
 #[pymodule]
 pub(crate) mod _thread {
     use crate::{
         builtins::{PyDictRef, PyStr, PyTupleRef, PyTypeRef},
+        common::lock::PyMutex,
         convert::ToPyException,
         function::{ArgCallable, Either, FuncArgs, KwArgs, OptionalArg, PySetterValue},
-        types::{Constructor, GetAttr, Representable, SetAttr},
-        AsObject, Py, PyPayload, PyRef, PyResult, VirtualMachine,
+        py_io::{PyWriter, Write},
+        stdlib::sys,
+        types::{Constructor, GetAttr, PyStructSequence, Representable, SetAttr},
+        AsObject, Py, PyObjectRef, PyPayload, PyRef, PyResult, PyWeakRef, VirtualMachine,
     };
     use crossbeam_utils::atomic::AtomicCell;
     use parking_lot::{
         lock_api::{RawMutex as RawMutexT, RawMutexTimed, RawReentrantMutex},
         RawMutex, RawThreadId,
     };
-    use std::{cell::RefCell, fmt, thread, time::Duration};
-    use thread_local::ThreadLocal;
+    use std::{
+        cell::RefCell, collections::HashMap, fmt, sync::atomic::Ordering, thread, time::Duration,
+    };
 
     // PYTHREAD_NAME: show current thread name
     pub const PYTHREAD_NAME: Option<&str> = {
@@ -67,7 +93,7 @@ pub(crate) mod _thread {
             };
             match args.blocking {
                 true if timeout == -1.0 => {
-                    mu.lock();
+                    crate::object::gc::allow_threads(|| mu.lock());
                     Ok(true)
                 }
                 true if timeout < 0.0 => {
@@ -83,7 +109,10 @@ pub(crate) mod _thread {
                         ));
                     }
 
-                    Ok(mu.try_lock_for(Duration::from_secs_f64(timeout)))
+                    let timeout = Duration::from_secs_f64(timeout);
+                    Ok(crate::object::gc::allow_threads(|| {
+                        mu.try_lock_for(timeout)
+                    }))
                 }
                 false if timeout != -1.0 => Err(vm
                     .new_value_error("can't specify a timeout for a non-blocking call".to_owned())),
@@ -217,8 +246,9 @@ pub(crate) mod _thread {
         #[pymethod]
         #[pymethod(name = "release_lock")]
         fn release(&self, vm: &VirtualMachine) -> PyResult<()> {
-            if !self.mu.is_locked() {
-                return Err(vm.new_runtime_error("release unlocked lock".to_owned()));
+            // unlike a lock, an RLock can only be released by the thread that holds it
+            if !self.mu.is_owned_by_current_thread() {
+                return Err(vm.new_runtime_error("cannot release un-acquired lock".to_owned()));
             }
             unsafe { self.mu.unlock() };
             Ok(())
@@ -259,30 +289,7 @@ pub(crate) mod _thread {
 
     #[pyfunction]
     fn get_ident() -> u64 {
-        thread_to_id(&thread::current())
-    }
-
-    fn thread_to_id(t: &thread::Thread) -> u64 {
-        use std::hash::{Hash, Hasher};
-        struct U64Hash {
-            v: Option<u64>,
-        }
-        impl Hasher for U64Hash {
-            fn write(&mut self, _: &[u8]) {
-                unreachable!()
-            }
-            fn write_u64(&mut self, i: u64) {
-                self.v = Some(i);
-            }
-            fn finish(&self) -> u64 {
-                self.v.expect("should have written a u64")
-            }
-        }
-        // TODO: use id.as_u64() once it's stable, until then, ThreadId is just a wrapper
-        // around NonZeroU64, so this should work (?)
-        let mut h = U64Hash { v: None };
-        t.id().hash(&mut h);
-        h.finish()
+        crate::vm::thread::get_ident()
     }
 
     #[pyfunction]
@@ -297,6 +304,11 @@ pub(crate) mod _thread {
         kwargs: OptionalArg<PyDictRef>,
         vm: &VirtualMachine,
     ) -> PyResult<u64> {
+        if vm.state.finalizing.load(Ordering::Acquire) {
+            return Err(
+                vm.new_runtime_error("can't create new thread at interpreter shutdown".to_owned())
+            );
+        }
         let args = FuncArgs::new(
             args.to_vec(),
             kwargs
@@ -310,19 +322,50 @@ pub(crate) mod _thread {
         if stacksize != 0 {
             thread_builder = thread_builder.stack_size(stacksize);
         }
+        // count the thread before it starts, so that it can never be uncounted before it is
+        // counted
+        vm.state.thread_count.fetch_add(1);
         thread_builder
             .spawn(
                 vm.new_thread()
                     .make_spawn_func(move |vm| run_thread(func, args, vm)),
             )
-            .map(|handle| {
-                vm.state.thread_count.fetch_add(1);
-                thread_to_id(handle.thread())
+            .map(|handle| crate::vm::thread::thread_to_id(handle.thread()))
+            .map_err(|err| {
+                vm.state.thread_count.fetch_sub(1);
+                err.to_pyexception(vm)
             })
-            .map_err(|err| err.to_pyexception(vm))
+    }
+
+    /// Tears down the state of a thread started by `start_new_thread` when it exits, even if
+    /// it panicked.
+    struct ThreadStateGuard<'vm> {
+        vm: &'vm VirtualMachine,
+    }
+
+    impl Drop for ThreadStateGuard<'_> {
+        fn drop(&mut self) {
+            let vm = self.vm;
+            vm.state.thread_count.fetch_sub(1);
+            // clearing the thread's `_local` dicts may run `__del__`s, which should be done
+            // by the time anybody joining on the thread wakes up
+            let ident = get_ident();
+            for local in LOCALS.with(|locals| locals.take()) {
+                if let Some(local) = local.upgrade() {
+                    let ldict = local.data.lock().remove(&ident);
+                    drop(ldict);
+                }
+            }
+            for lock in SENTINELS.with(|sents| sents.take()) {
+                if lock.mu.is_locked() {
+                    unsafe { lock.mu.unlock() };
+                }
+            }
+        }
     }
 
     fn run_thread(func: ArgCallable, args: FuncArgs, vm: &VirtualMachine) {
+        let _state = ThreadStateGuard { vm };
         match func.invoke(args, vm) {
             Ok(_obj) => {}
             Err(e) if e.fast_isinstance(vm.ctx.exceptions.system_exit) => {}
@@ -334,14 +377,6 @@ pub(crate) mod _thread {
                 );
             }
         }
-        SENTINELS.with(|sents| {
-            for lock in sents.replace(Default::default()) {
-                if lock.mu.is_locked() {
-                    unsafe { lock.mu.unlock() };
-                }
-            }
-        });
-        vm.state.thread_count.fetch_sub(1);
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -355,8 +390,15 @@ pub(crate) mod _thread {
         Err(vm.new_exception_empty(vm.ctx.exceptions.system_exit.to_owned()))
     }
 
-    thread_local!(static SENTINELS: RefCell<Vec<PyRef<Lock>>> = RefCell::default());
+    thread_local! {
+        /// Locks handed out by `_set_sentinel`, released once the thread exits.
+        static SENTINELS: RefCell<Vec<PyRef<Lock>>> = RefCell::default();
+        /// Every `_local` that holds a dict for this thread.
+        static LOCALS: RefCell<Vec<PyWeakRef<Local>>> = RefCell::default();
+    }
 
+    /// Return a lock which is released when the current thread exits. `threading` acquires it
+    /// right away and uses it to implement `Thread.join()`.
     #[pyfunction]
     fn _set_sentinel(vm: &VirtualMachine) -> PyRef<Lock> {
         let lock = Lock { mu: RawMutex::INIT }.into_ref(&vm.ctx);
@@ -364,11 +406,18 @@ pub(crate) mod _thread {
         lock
     }
 
+    // the smallest stack size accepted by `stack_size()`, like CPython's THREAD_STACK_MIN
+    const THREAD_STACK_MIN: usize = 0x8000;
+
     #[pyfunction]
-    fn stack_size(size: OptionalArg<usize>, vm: &VirtualMachine) -> usize {
+    fn stack_size(size: OptionalArg<isize>, vm: &VirtualMachine) -> PyResult<usize> {
         let size = size.unwrap_or(0);
-        // TODO: do validation on this to make sure it's not too small
-        vm.state.stacksize.swap(size)
+        let size = usize::try_from(size)
+            .map_err(|_| vm.new_value_error("size must be 0 or a positive value".to_owned()))?;
+        if size != 0 && size < THREAD_STACK_MIN {
+            return Err(vm.new_value_error(format!("size not valid: {size} bytes")));
+        }
+        Ok(vm.state.stacksize.swap(size))
     }
 
     #[pyfunction]
@@ -376,32 +425,73 @@ pub(crate) mod _thread {
         vm.state.thread_count.load()
     }
 
+    #[pyfunction]
+    fn daemon_threads_allowed() -> bool {
+        // there are no subinterpreters that could forbid them
+        true
+    }
+
     #[pyattr]
     #[pyclass(module = "thread", name = "_local")]
     #[derive(Debug, PyPayload)]
     struct Local {
-        data: ThreadLocal<PyDictRef>,
+        // keyed by `get_ident()`, which unlike OS thread ids is never reused
+        data: PyMutex<HashMap<u64, PyDictRef>>,
+        args: FuncArgs,
     }
 
     #[pyclass(with(GetAttr, SetAttr), flags(BASETYPE))]
     impl Local {
-        fn ldict(&self, vm: &VirtualMachine) -> PyDictRef {
-            self.data.get_or(|| vm.ctx.new_dict()).clone()
+        /// Get the dict of the current thread, creating it and running `__init__` again with
+        /// the constructor arguments if this is the first time the thread touches `zelf`.
+        fn ldict(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+            let ident = get_ident();
+            if let Some(ldict) = zelf.data.lock().get(&ident) {
+                return Ok(ldict.clone());
+            }
+            let ldict = Self::new_ldict(zelf, vm)?;
+            if let Err(e) = vm.call_method(zelf.as_object(), "__init__", zelf.args.clone()) {
+                zelf.data.lock().remove(&ident);
+                return Err(e);
+            }
+            Ok(ldict)
+        }
+
+        fn new_ldict(zelf: &Py<Self>, vm: &VirtualMachine) -> PyResult<PyDictRef> {
+            let ldict = vm.ctx.new_dict();
+            zelf.data.lock().insert(get_ident(), ldict.clone());
+            let local = zelf.downgrade(None, vm)?;
+            LOCALS.with(|locals| locals.borrow_mut().push(local));
+            Ok(ldict)
         }
 
         #[pyslot]
-        fn slot_new(cls: PyTypeRef, _args: FuncArgs, vm: &VirtualMachine) -> PyResult {
-            Local {
-                data: ThreadLocal::new(),
+        fn slot_new(cls: PyTypeRef, args: FuncArgs, vm: &VirtualMachine) -> PyResult {
+            if !args.args.is_empty() || !args.kwargs.is_empty() {
+                let object_init = vm.ctx.types.object_type.get_attr(identifier!(vm, __init__));
+                let init = cls.get_attr(identifier!(vm, __init__));
+                if let (Some(init), Some(object_init)) = (init, object_init) {
+                    if init.is(&object_init) {
+                        return Err(vm.new_type_error(
+                            "Initialization arguments are not supported".to_owned(),
+                        ));
+                    }
+                }
             }
-            .into_ref_with_type(vm, cls)
-            .map(Into::into)
+            let zelf = Local {
+                data: PyMutex::default(),
+                args,
+            }
+            .into_ref_with_type(vm, cls)?;
+            // the creating thread's `__init__` is run by the type call itself
+            Self::new_ldict(&zelf, vm)?;
+            Ok(zelf.into())
         }
     }
 
     impl GetAttr for Local {
         fn getattro(zelf: &Py<Self>, attr: &Py<PyStr>, vm: &VirtualMachine) -> PyResult {
-            let ldict = zelf.ldict(vm);
+            let ldict = Local::ldict(zelf, vm)?;
             if attr.as_str() == "__dict__" {
                 Ok(ldict.into())
             } else {
@@ -431,7 +521,7 @@ pub(crate) mod _thread {
                     zelf.class().name()
                 )))
             } else {
-                let dict = zelf.ldict(vm);
+                let dict = Local::ldict(zelf, vm)?;
                 if let PySetterValue::Assign(value) = value {
                     dict.set_item(attr, value, vm)?;
                 } else {
@@ -441,4 +531,427 @@ pub(crate) mod _thread {
             }
         }
     }
+
+    #[pyattr]
+    #[pyclass(module = "thread", name = "_ExceptHookArgs")]
+    #[derive(Debug, PyStructSequence, TryIntoPyStructSequence)]
+    struct ExceptHookArgs {
+        exc_type: PyObjectRef,
+        exc_value: PyObjectRef,
+        exc_traceback: PyObjectRef,
+        thread: PyObjectRef,
+    }
+
+    #[pyclass(with(PyStructSequence))]
+    impl ExceptHookArgs {}
+
+    /// Handle an uncaught exception raised by `threading.Thread.run()`.
+    #[pyfunction]
+    fn _excepthook(args: ExceptHookArgs, vm: &VirtualMachine) -> PyResult<()> {
+        if args.exc_type.is(vm.ctx.exceptions.system_exit) {
+            // silently ignore SystemExit
+            return Ok(());
+        }
+
+        let file = sys::get_stderr(vm)
+            .ok()
+            .filter(|stderr| !vm.is_none(stderr))
+            .or_else(|| {
+                // sys.stderr is None when run with pythonw.exe: fall back to the stderr the
+                // thread saw when it was created
+                (!vm.is_none(&args.thread))
+                    .then(|| args.thread.get_attr("_stderr", vm).ok())
+                    .flatten()
+                    .filter(|stderr| !vm.is_none(stderr))
+            });
+        let file = match file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let name = if vm.is_none(&args.thread) {
+            None
+        } else {
+            args.thread
+                .get_attr("name", vm)
+                .ok()
+                .filter(|name| !vm.is_none(name))
+        };
+        let name = match name {
+            Some(name) => name.str(vm)?.as_str().to_owned(),
+            None => get_ident().to_string(),
+        };
+
+        let mut output = PyWriter(file.clone(), vm);
+        writeln!(output, "Exception in thread {name}:")?;
+        let exc = vm.normalize_exception(args.exc_type, args.exc_value, args.exc_traceback)?;
+        vm.write_exception(&mut output, &exc)?;
+        vm.call_method(&file, "flush", ())?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "rustpython-compiler"))]
+mod tests {
+    use crate::{object::gc, scope::Scope, Interpreter, VirtualMachine};
+
+    // `start()` runs `func` in a new thread and returns its sentinel lock, which `join()`
+    // waits on like `threading.Thread.join()` does
+    const PRELUDE: &str = r#"
+import _thread
+
+def start(func, *args):
+    started = _thread.allocate_lock()
+    started.acquire()
+    sentinel = []
+    def bootstrap():
+        lock = _thread._set_sentinel()
+        lock.acquire()
+        sentinel.append(lock)
+        started.release()
+        func(*args)
+    _thread.start_new_thread(bootstrap, ())
+    started.acquire()
+    return sentinel[0]
+
+def join(sentinel):
+    sentinel.acquire()
+    sentinel.release()
+"#;
+
+    fn exec(vm: &VirtualMachine, scope: Scope, source: &str) {
+        if let Err(exc) = vm.run_code_string(scope, source, "<unittest>".to_owned()) {
+            let mut msg = String::new();
+            vm.write_exception(&mut msg, &exc).unwrap();
+            panic!("{msg}");
+        }
+    }
+
+    fn run(source: &str) {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            exec(vm, scope, &format!("{PRELUDE}\n{source}"));
+        })
+    }
+
+    #[test]
+    fn test_join_many_threads() {
+        run(r#"
+lock = _thread.allocate_lock()
+counter = [0]
+def worker():
+    for _ in range(1000):
+        with lock:
+            counter[0] += 1
+sentinels = [start(worker) for _ in range(50)]
+for sentinel in sentinels:
+    join(sentinel)
+assert counter[0] == 50 * 1000, counter
+assert _thread._count() == 0, _thread._count()
+"#);
+    }
+
+    #[test]
+    fn test_local_is_per_thread_and_cleared_on_exit() {
+        run(r#"
+import _weakref
+class Value:
+    pass
+local = _thread._local()
+local.x = 'main'
+inherited = []
+seen = []
+refs = []
+def worker(i):
+    inherited.append(hasattr(local, 'x'))
+    local.x = i
+    value = Value()
+    refs.append(_weakref.ref(value))
+    local.value = value
+    del value
+    seen.append(local.x)
+for sentinel in [start(worker, i) for i in range(20)]:
+    join(sentinel)
+assert inherited == [False] * 20, inherited
+assert sorted(seen) == list(range(20)), seen
+assert local.x == 'main'
+assert not hasattr(local, 'value')
+assert all(ref() is None for ref in refs)
+"#);
+    }
+
+    #[test]
+    fn test_local_init_runs_in_each_thread() {
+        run(r#"
+try:
+    _thread._local(1)
+except TypeError:
+    pass
+else:
+    raise AssertionError('_local accepted arguments without __init__')
+
+inits = []
+class MyLocal(_thread._local):
+    def __init__(self, value):
+        inits.append(_thread.get_ident())
+        self.value = value
+local = MyLocal(42)
+values = []
+join(start(lambda: values.append(local.value)))
+assert values == [42], values
+assert len(inits) == 2 and inits[0] != inits[1], inits
+"#);
+    }
+
+    #[test]
+    fn test_stack_size() {
+        run(r#"
+assert _thread.stack_size() == 0
+for size in (-1, 4096):
+    try:
+        _thread.stack_size(size)
+    except ValueError:
+        pass
+    else:
+        raise AssertionError(f'stack_size({size}) was accepted')
+assert _thread.stack_size(1 << 20) == 0
+def recurse(n):
+    return n and recurse(n - 1)
+join(start(recurse, 100))
+assert _thread.stack_size(0) == 1 << 20
+"#);
+    }
+
+    #[test]
+    fn test_excepthook() {
+        run(r#"
+import sys
+class Capture:
+    def __init__(self):
+        self.data = []
+    def write(self, s):
+        self.data.append(s)
+    def flush(self):
+        pass
+class FakeThread:
+    name = 'worker-1'
+sys.stderr = Capture()
+try:
+    raise ValueError('boom')
+except ValueError as e:
+    exc = e
+args = _thread._ExceptHookArgs((type(exc), exc, exc.__traceback__, FakeThread()))
+assert args.thread.name == 'worker-1'
+assert _thread._excepthook(args) is None
+out = ''.join(sys.stderr.data)
+assert out.startswith('Exception in thread worker-1:\n'), out
+assert 'ValueError: boom' in out, out
+sys.stderr.data.clear()
+_thread._excepthook(_thread._ExceptHookArgs((SystemExit, SystemExit(), None, None)))
+assert sys.stderr.data == []
+"#);
+    }
+
+    #[test]
+    fn test_lock_contention() {
+        run(r#"
+import time
+lock = _thread.allocate_lock()
+counter = [0]
+def worker(i):
+    for n in range(300):
+        # every way of taking the lock, all at once
+        if (i + n) % 3 == 0:
+            while not lock.acquire(False):
+                pass
+        elif (i + n) % 3 == 1:
+            while not lock.acquire(timeout=0.001):
+                pass
+        else:
+            lock.acquire()
+        counter[0] += 1
+        lock.release()
+for sentinel in [start(worker, i) for i in range(16)]:
+    join(sentinel)
+assert counter[0] == 16 * 300, counter
+assert not lock.locked()
+
+# two threads handing the turn to each other through a pair of locks
+ping, pong = _thread.allocate_lock(), _thread.allocate_lock()
+pong.acquire()
+turns = []
+def player(name, mine, theirs):
+    for _ in range(500):
+        mine.acquire()
+        turns.append(name)
+        theirs.release()
+a = start(player, 'a', ping, pong)
+b = start(player, 'b', pong, ping)
+join(a)
+join(b)
+assert turns == ['a', 'b'] * 500, turns[:10]
+
+# a timed acquire gives up once the time is over, a lock can be released by any thread
+lock.acquire()
+waited = []
+def wait_for_lock():
+    begin = time.monotonic()
+    waited.append((lock.acquire(timeout=0.05), time.monotonic() - begin))
+    lock.release()
+join(start(wait_for_lock))
+assert not waited[0][0] and waited[0][1] >= 0.04, waited
+assert not lock.locked()
+try:
+    lock.release()
+except RuntimeError:
+    pass
+else:
+    raise AssertionError('released an unlocked lock')
+"#);
+    }
+
+    #[test]
+    fn test_rlock_contention() {
+        run(r#"
+rlock = _thread.RLock()
+counter = [0]
+def worker():
+    for _ in range(200):
+        with rlock:
+            with rlock:
+                assert rlock._is_owned()
+                counter[0] += 1
+            assert rlock._is_owned()
+        assert not rlock._is_owned()
+for sentinel in [start(worker) for _ in range(16)]:
+    join(sentinel)
+assert counter[0] == 16 * 200, counter
+
+# only the owner can release it, and it stays held until released as often as acquired
+rlock.acquire()
+rlock.acquire()
+seen = []
+def intruder():
+    try:
+        rlock.release()
+    except RuntimeError:
+        seen.append('refused')
+    seen.append(rlock.acquire(False))
+    seen.append(rlock._is_owned())
+join(start(intruder))
+assert seen == ['refused', False, False], seen
+rlock.release()
+join(start(intruder))
+assert seen[3:] == ['refused', False, False], seen
+rlock.release()
+join(start(lambda: seen.append(rlock.acquire(False)) or rlock.release()))
+assert seen[6:] == [True], seen
+try:
+    rlock.release()
+except RuntimeError:
+    pass
+else:
+    raise AssertionError('released an RLock that was not held')
+"#);
+    }
+
+    #[test]
+    fn test_start_new_thread_with_concurrent_gc() {
+        Interpreter::without_stdlib(Default::default()).enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            let collect = vm.new_function("collect", |vm: &VirtualMachine| {
+                gc::collect(vm, gc::NUM_GENERATIONS - 1)
+            });
+            scope
+                .globals
+                .set_item("collect", collect.into(), vm)
+                .unwrap();
+            let source = r#"
+import _weakref
+class Node:
+    def __init__(self, parent):
+        self.parent = parent
+        self.children = []
+finalized = []
+class Finalized:
+    def __del__(self):
+        finalized.append(True)
+refs = []
+def make_garbage():
+    for i in range(200):
+        root = Node(None)
+        root.children.append(Node(root))
+        root.marker = Finalized()
+        root.marker.cycle = root
+        d = {'root': root}
+        d['self'] = d
+        if i % 20 == 0:
+            refs.append(_weakref.ref(root))
+    # what is still reachable comes out of every collection intact
+    kept = Node(None)
+    kept.children.append(Node(kept))
+    for _ in range(100):
+        assert kept.children[0].parent is kept and kept.children[0].children == []
+done = []
+collected = []
+def collector():
+    while not done:
+        collected.append(collect())
+# threads are started, run and exit while collections are going on
+gc_thread = start(collector)
+for _ in range(3):
+    for sentinel in [start(make_garbage) for _ in range(6)]:
+        join(sentinel)
+done.append(True)
+join(gc_thread)
+collect()
+assert len(refs) == 3 * 6 * 10 and all(ref() is None for ref in refs)
+assert len(finalized) == 3 * 6 * 200, len(finalized)
+assert sum(collected) > 0, collected
+assert _thread._count() == 0
+"#;
+            exec(vm, scope, &format!("{PRELUDE}\n{source}"));
+        })
+    }
+
+    #[test]
+    fn test_shutdown_with_live_threads() {
+        let interp = Interpreter::without_stdlib(Default::default());
+        let (thread, scope) = interp.enter(|vm| {
+            let scope = vm.new_scope_with_builtins();
+            let source = r#"
+import sys, time
+refused = []
+def wait_for_shutdown(i):
+    while not sys.is_finalizing():
+        time.sleep(0.001)
+    try:
+        _thread.start_new_thread(print, ())
+    except RuntimeError:
+        refused.append(i)
+blocker = _thread.allocate_lock()
+blocker.acquire()
+def blocked():
+    with blocker:
+        pass
+sentinels = [start(wait_for_shutdown, i) for i in range(4)]
+sentinels.append(start(blocked))
+assert _thread._count() == 5
+"#;
+            exec(vm, scope.clone(), &format!("{PRELUDE}\n{source}"));
+            (vm.new_thread(), scope)
+        });
+        // the threads are neither waited for nor stopped by finalization
+        assert_eq!(interp.finalize(None), 0);
+        thread.run(|vm| {
+            let source = r#"
+blocker.release()
+for sentinel in sentinels:
+    join(sentinel)
+assert sorted(refused) == [0, 1, 2, 3], refused
+assert _thread._count() == 0
+"#;
+            exec(vm, scope, source);
+        })
+    }
 }
@@ -58,7 +58,7 @@ mod time {
     #[cfg(not(unix))]
     #[pyfunction]
     fn sleep(dur: Duration) {
-        std::thread::sleep(dur);
+        crate::object::gc::allow_threads(|| std::thread::sleep(dur));
     }
 
     #[cfg(not(target_os = "wasi"))]
@@ -557,7 +557,9 @@ mod platform {
         // this is basically std::thread::sleep, but that catches interrupts and we don't want to;
 
         let ts = TimeSpec::from(dur);
-        let res = unsafe { libc::nanosleep(ts.as_ref(), std::ptr::null_mut()) };
+        let res = crate::object::gc::allow_threads(|| unsafe {
+            libc::nanosleep(ts.as_ref(), std::ptr::null_mut())
+        });
         let interrupted = res == -1 && nix::errno::errno() == libc::EINTR;
 
         if interrupted {
@@ -107,9 +107,10 @@ impl Interpreter {
 
     /// Finalize vm and turns an exception to exit code.
     ///
-    /// Finalization steps including 4 steps:
+    /// Finalization steps including 5 steps:
     /// 1. Flush stdout and stderr.
     /// 1. Handle exit exception and turn it to exit code.
+    /// 1. Wait for non-daemon threads started by `threading` to finish.
     /// 1. Run atexit exit functions.
     /// 1. Mark vm as finalized.
     ///
@@ -125,6 +126,9 @@ impl Interpreter {
                 0
             };
 
+            #[cfg(feature = "threading")]
+            crate::stdlib::thread::wait_for_thread_shutdown(vm);
+
             atexit::_run_exitfuncs(vm);
 
             vm.state.finalizing.store(true, Ordering::Release);
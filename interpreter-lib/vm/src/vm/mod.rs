@@ -19,8 +19,8 @@ use crate::{
         code::PyCode,
         pystr::AsPyStr,
         tuple::{PyTuple, PyTupleTyped},
-        PyBaseExceptionRef, PyDictRef, PyInt, PyList, PyModule, PyStr, PyStrInterned, PyStrRef,
-        PyTypeRef,
+        PyBaseExceptionRef, PyDictRef, PyInt, PyList, PyListRef, PyModule, PyStr, PyStrInterned,
+        PyStrRef, PyTypeRef,
     },
     codecs::CodecsRegistry,
     common::{hash::HashSecret, lock::PyMutex, rc::PyRc},
@@ -101,6 +101,8 @@ pub struct PyGlobalState {
     pub after_forkers_child: PyMutex<Vec<PyObjectRef>>,
     pub after_forkers_parent: PyMutex<Vec<PyObjectRef>>,
     pub int_max_str_digits: AtomicCell<usize>,
+    pub gc_callbacks: PyListRef,
+    pub gc_garbage: PyListRef,
 }
 
 pub fn process_hash_secret_seed() -> u32 {
@@ -151,6 +153,9 @@ impl VirtualMachine {
 
         let warnings = WarningsState::init_state(&ctx);
 
+        let gc_callbacks = ctx.new_list(vec![]);
+        let gc_garbage = ctx.new_list(vec![]);
+
         let int_max_str_digits = AtomicCell::new(match settings.int_max_str_digits {
             -1 => 4300,
             other => other,
@@ -186,6 +191,8 @@ impl VirtualMachine {
                 after_forkers_child: PyMutex::default(),
                 after_forkers_parent: PyMutex::default(),
                 int_max_str_digits,
+                gc_callbacks,
+                gc_garbage,
             }),
             initialized: false,
             recursion_depth: Cell::new(0),
@@ -49,6 +49,9 @@ pub struct Settings {
     /// -X int_max_str_digits
     pub int_max_str_digits: i64,
 
+    /// -X jit=N, how hot a function has to get before it is jitted automatically; 0 disables it
+    pub jit_threshold: u32,
+
     /// -I
     pub isolated: bool,
 
@@ -125,6 +128,7 @@ impl Default for Settings {
             allow_external_library: cfg!(feature = "importlib"),
             utf8_mode: 1,
             int_max_str_digits: -1,
+            jit_threshold: 0,
             #[cfg(feature = "flame-it")]
             profile_output: None,
             #[cfg(feature = "flame-it")]
@@ -11,6 +11,69 @@ thread_local! {
     static VM_CURRENT: RefCell<*const VirtualMachine> = null::<VirtualMachine>().into();
 }
 
+/// A vm that some thread is running, see [`for_each_vm`].
+struct ActiveVm {
+    ident: u64,
+    vm: NonNull<VirtualMachine>,
+}
+
+// SAFETY: the vm is only dereferenced by for_each_vm, whose caller promises to be careful
+unsafe impl Send for ActiveVm {}
+
+static ACTIVE_VMS: parking_lot::Mutex<Vec<ActiveVm>> = parking_lot::const_mutex(Vec::new());
+
+/// The identifier of a thread, as returned by `_thread.get_ident()`.
+pub fn thread_to_id(t: &std::thread::Thread) -> u64 {
+    use std::hash::{Hash, Hasher};
+    struct U64Hash {
+        v: Option<u64>,
+    }
+    impl Hasher for U64Hash {
+        fn write(&mut self, _: &[u8]) {
+            unreachable!()
+        }
+        fn write_u64(&mut self, i: u64) {
+            self.v = Some(i);
+        }
+        fn finish(&self) -> u64 {
+            self.v.expect("should have written a u64")
+        }
+    }
+    // TODO: use id.as_u64() once it's stable, until then, ThreadId is just a wrapper
+    // around NonZeroU64, so this should work (?)
+    let mut h = U64Hash { v: None };
+    t.id().hash(&mut h);
+    h.finish()
+}
+
+/// The identifier of the current thread, as returned by `_thread.get_ident()`.
+pub fn get_ident() -> u64 {
+    thread_to_id(&std::thread::current())
+}
+
+/// Call `f` with the thread identifier of every vm that a thread is currently running, and the
+/// vm itself. When `blocking` is false and another thread is entering or leaving a vm, nothing
+/// is called and `false` is returned, which makes this usable from a signal handler.
+///
+/// # Safety
+///
+/// The other threads keep running while `f` looks at their vms, so `f` may only read from
+/// them in ways that can race with their owners, e.g. to dump a traceback after a crash.
+pub unsafe fn for_each_vm(blocking: bool, mut f: impl FnMut(u64, &VirtualMachine)) -> bool {
+    let vms = if blocking {
+        ACTIVE_VMS.lock()
+    } else {
+        match ACTIVE_VMS.try_lock() {
+            Some(vms) => vms,
+            None => return false,
+        }
+    };
+    for active in vms.iter() {
+        f(active.ident, active.vm.as_ref());
+    }
+    true
+}
+
 pub fn with_current_vm<R>(f: impl FnOnce(&VirtualMachine) -> R) -> R {
     VM_CURRENT.with(|x| unsafe {
         f(x.clone()
@@ -20,12 +83,45 @@ pub fn with_current_vm<R>(f: impl FnOnce(&VirtualMachine) -> R) -> R {
     })
 }
 
+/// Like [`with_current_vm`], but returns `None` instead of panicking when the current thread
+/// isn't running any vm.
+pub fn try_with_current_vm<R>(f: impl FnOnce(&VirtualMachine) -> R) -> Option<R> {
+    VM_CURRENT
+        .try_with(|x| unsafe { x.borrow().as_ref().map(f) })
+        .ok()
+        .flatten()
+}
+
 pub fn enter_vm<R>(vm: &VirtualMachine, f: impl FnOnce() -> R) -> R {
     VM_STACK.with(|vms| {
+        #[cfg(feature = "threading")]
+        let outermost = vms.borrow().is_empty();
         vms.borrow_mut().push(vm.into());
+        #[cfg(feature = "threading")]
+        if outermost {
+            crate::object::gc::attach_thread();
+        }
+        let ident = get_ident();
+        ACTIVE_VMS.lock().push(ActiveVm {
+            ident,
+            vm: vm.into(),
+        });
         let prev = VM_CURRENT.with(|current| current.replace(vm));
         let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
         vms.borrow_mut().pop();
+        #[cfg(feature = "threading")]
+        if outermost {
+            crate::object::gc::detach_thread();
+        }
+        {
+            let mut active = ACTIVE_VMS.lock();
+            if let Some(i) = active
+                .iter()
+                .rposition(|x| x.ident == ident && x.vm == NonNull::from(vm))
+            {
+                active.remove(i);
+            }
+        }
         VM_CURRENT.with(|current| current.replace(prev));
         ret.unwrap_or_else(|e| std::panic::resume_unwind(e))
     })
@@ -96,6 +192,11 @@ impl ThreadedVirtualMachine {
         let vm = &self.vm;
         enter_vm(vm, || f(vm))
     }
+
+    /// Another handle on the same interpreter, for yet another thread.
+    pub fn new_thread(&self) -> ThreadedVirtualMachine {
+        self.vm.new_thread()
+    }
 }
 
 impl VirtualMachine {
@@ -164,9 +164,11 @@ class Check(VisitorBase):
 
     def visitConstructor(self, cons, name):
         key = str(cons.name)
-        conflict = self.cons.get(key)
+        # Unlike the C structures, the generated Rust variants are namespaced by their sum
+        # type, so only a constructor defined twice in the same type is an error.
+        conflict = self.cons.get((name, key))
         if conflict is None:
-            self.cons[key] = name
+            self.cons[(name, key)] = name
         else:
             print('Redefinition of constructor {}'.format(key))
             print('Defined in {} and {}'.format(conflict, name))
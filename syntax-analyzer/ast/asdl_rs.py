@@ -122,6 +122,12 @@ def maybe_custom(type):
     return CUSTOM_REPLACEMENTS.get(type.name, type)
 
 
+def constructor_key(type_name, cons_name):
+    # constructors live in the namespace of their sum type, so the same name (e.g. `Invalid`)
+    # may be used by more than one of them
+    return f"{type_name}.{cons_name}"
+
+
 def rust_field_name(name):
     name = rust_type_name(name)
     return re.sub(r"(?<!^)(?=[A-Z])", "_", name).lower()
@@ -222,6 +228,18 @@ class TypeInfo:
     def is_custom_replaced(self):
         return self.type.name in CUSTOM_REPLACEMENTS
 
+    @property
+    def key(self):
+        if self.enum_name is None:
+            return self.name
+        return constructor_key(self.enum_name, self.name)
+
+    @property
+    def custom_key(self):
+        if self.is_custom_replaced:
+            return CUSTOM_REPLACEMENTS[self.type.name].name
+        return self.key
+
     @property
     def custom(self):
         if self.type.name in CUSTOM_REPLACEMENTS:
@@ -346,7 +364,7 @@ class FindUserDataTypesVisitor(asdl.VisitorBase):
             return
 
         for t in sum.types:
-            self.add_children(t.name, t.fields)
+            self.add_children(constructor_key(type.name, t.name), t.fields)
 
         if len(sum.types) > 1:
             info.boxed = True
@@ -359,7 +377,7 @@ class FindUserDataTypesVisitor(asdl.VisitorBase):
             self.add_children(type.name, variant.fields)
 
     def visitConstructor(self, cons, type, simple):
-        info = self.type_info[cons.name] = TypeInfo(cons)
+        info = self.type_info[constructor_key(type.name, cons.name)] = TypeInfo(cons)
         info.enum_name = type.name
         info.is_simple = simple
 
@@ -564,7 +582,8 @@ class StructVisitor(EmitVisitor):
         for f in t.fields:
             self.visit(f, sum_type_info, "pub ", depth + 1, t.name)
 
-        assert sum_type_info.has_attributes == self.type_info[t.name].no_cfg(
+        cons_info = self.type_info[constructor_key(sum_type_info.name, t.name)]
+        assert sum_type_info.has_attributes == cons_info.no_cfg(
             self.type_info
         )
 
@@ -970,11 +989,12 @@ class VisitorModuleVisitor(StructVisitor):
         )
 
     def visit_sum_type(self, name, type_, depth):
-        self.emit_visitor(type_.name, depth, has_node=type_.fields)
+        key = constructor_key(name, type_.name)
+        self.emit_visitor(key, depth, has_node=type_.fields)
         if not type_.fields:
             return
 
-        self.emit_generic_visitor_signature(type_.name, depth, has_node=True)
+        self.emit_generic_visitor_signature(key, depth, has_node=True)
         for field in type_.fields:
             if field.type in CUSTOM_REPLACEMENTS:
                 type_name = CUSTOM_REPLACEMENTS[field.type].name
@@ -1045,14 +1065,14 @@ class RangedDefVisitor(EmitVisitor):
 
         if info.is_simple:
             for ty in sum.types:
-                variant_info = self.type_info[ty.name]
+                variant_info = self.type_info[constructor_key(name, ty.name)]
                 self.emit_type_alias(variant_info)
             return
 
         sum_match_arms = ""
 
         for ty in sum.types:
-            variant_info = self.type_info[ty.name]
+            variant_info = self.type_info[constructor_key(name, ty.name)]
             sum_match_arms += (
                 f"        Self::{variant_info.rust_name}(node) => node.range(),"
             )
@@ -1121,14 +1141,14 @@ class LocatedDefVisitor(EmitVisitor):
 
         if info.is_simple:
             for ty in sum.types:
-                variant_info = self.type_info[ty.name]
+                variant_info = self.type_info[constructor_key(name, ty.name)]
                 self.emit_type_alias(variant_info)
             return
 
         sum_match_arms = ""
 
         for ty in sum.types:
-            variant_info = self.type_info[ty.name]
+            variant_info = self.type_info[constructor_key(name, ty.name)]
             sum_match_arms += (
                 f"        Self::{variant_info.rust_name}(node) => node.range(),"
             )
@@ -1387,7 +1407,7 @@ class Pyo3StructVisitor(EmitVisitor):
 
     def emit_class(self, info, simple, base="super::Ast"):
         inner_name = info.full_type_name
-        rust_name = self.type_info[info.custom.name].full_type_name
+        rust_name = self.type_info[info.custom_key].full_type_name
         if simple:
             generics = ""
         else:
@@ -1517,7 +1537,7 @@ class Pyo3StructVisitor(EmitVisitor):
 
     def emit_wrapper(self, info):
         inner_name = info.full_type_name
-        rust_name = self.type_info[info.custom.name].full_type_name
+        rust_name = self.type_info[info.custom_key].full_type_name
         self.emit(
             f"""
             impl ToPyWrapper for ast::{inner_name}{self.generics} {{
@@ -1568,7 +1588,7 @@ class Pyo3StructVisitor(EmitVisitor):
             )
 
         for cons in sum.types:
-            self.visit(cons, rust_name, simple, depth + 1)
+            self.visit(cons, type, simple, depth + 1)
 
     def visitProduct(self, product, type, depth=0):
         info = self.type_info[type.name]
@@ -1577,7 +1597,8 @@ class Pyo3StructVisitor(EmitVisitor):
         if self.borrow:
             self.emit_getter(product, rust_name)
 
-    def visitConstructor(self, cons, parent, simple, depth):
+    def visitConstructor(self, cons, type, simple, depth):
+        parent = rust_type_name(type.name)
         if simple:
             self.emit(
                 f"""
@@ -1596,7 +1617,7 @@ class Pyo3StructVisitor(EmitVisitor):
                 depth,
             )
         else:
-            info = self.type_info[cons.name]
+            info = self.type_info[constructor_key(type.name, cons.name)]
             self.emit_class(
                 info,
                 simple=False,
@@ -1631,12 +1652,12 @@ class Pyo3PymoduleVisitor(EmitVisitor):
             self.visit(cons, name, simple, depth)
 
     def visitConstructor(self, cons, parent, simple, depth):
-        info = self.type_info[cons.name]
+        info = self.type_info[constructor_key(parent, cons.name)]
         self.emit_fields(info, simple)
 
     def emit_fields(self, info, simple):
         inner_name = info.full_type_name
-        rust_name = self.type_info[info.custom.name].full_type_name
+        rust_name = self.type_info[info.custom_key].full_type_name
         self.emit(f"super::init_type::<{rust_name}, ast::{inner_name}>(py, m)?;", 1)
 
 
@@ -1660,17 +1681,18 @@ class StdlibClassDefVisitor(EmitVisitor):
         self.emit("#[pyclass(flags(HAS_DICT, BASETYPE))]", depth)
         self.emit(f"impl {struct_name} {{}}", depth)
         for cons in sum.types:
-            self.visit(cons, sum.attributes, struct_name, depth)
+            self.visit(cons, name, sum.attributes, struct_name, depth)
 
-    def visitConstructor(self, cons, attrs, base, depth):
-        self.gen_class_def(cons.name, cons.fields, attrs, depth, base)
+    def visitConstructor(self, cons, type_name, attrs, base, depth):
+        key = constructor_key(type_name, cons.name)
+        self.gen_class_def(cons.name, cons.fields, attrs, depth, base, key)
 
     def visitProduct(self, product, name, depth):
         self.gen_class_def(name, product.fields, product.attributes, depth)
 
-    def gen_class_def(self, name, fields, attrs, depth, base=None):
+    def gen_class_def(self, name, fields, attrs, depth, base=None, key=None):
 
-        info = self.type_info[self.type_info[name].custom.name]
+        info = self.type_info[self.type_info[key or name].custom_key]
         if base is None:
             base = "NodeAst"
             struct_name = "Node" + info.full_type_name
@@ -2060,7 +2082,7 @@ def write_pyo3_wrapper(mod, type_info, namespace, f):
                 continue
 
             rust_name = info.full_type_name
-            inner_name = type_info[info.custom.name].full_type_name
+            inner_name = type_info[info.custom_key].full_type_name
             f.write(
                 f"""
                 impl ToPyWrapper for ast::{inner_name} {{
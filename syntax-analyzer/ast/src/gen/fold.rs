@@ -231,6 +231,12 @@ pub trait Fold<U> {
     ) -> Result<StmtContinue<Self::TargetU>, Self::Error> {
         fold_stmt_continue(self, node)
     }
+    fn fold_stmt_invalid(
+        &mut self,
+        node: StmtInvalid<U>,
+    ) -> Result<StmtInvalid<Self::TargetU>, Self::Error> {
+        fold_stmt_invalid(self, node)
+    }
     fn fold_expr(&mut self, node: Expr<U>) -> Result<Expr<Self::TargetU>, Self::Error> {
         fold_expr(self, node)
     }
@@ -393,6 +399,12 @@ pub trait Fold<U> {
     ) -> Result<ExprSlice<Self::TargetU>, Self::Error> {
         fold_expr_slice(self, node)
     }
+    fn fold_expr_invalid(
+        &mut self,
+        node: ExprInvalid<U>,
+    ) -> Result<ExprInvalid<Self::TargetU>, Self::Error> {
+        fold_expr_invalid(self, node)
+    }
     fn fold_expr_context(&mut self, node: ExprContext) -> Result<ExprContext, Self::Error> {
         fold_expr_context(self, node)
     }
@@ -705,6 +717,7 @@ pub fn fold_stmt<U, F: Fold<U> + ?Sized>(
         Stmt::Pass(cons) => Stmt::Pass(Foldable::fold(cons, folder)?),
         Stmt::Break(cons) => Stmt::Break(Foldable::fold(cons, folder)?),
         Stmt::Continue(cons) => Stmt::Continue(Foldable::fold(cons, folder)?),
+        Stmt::Invalid(cons) => Stmt::Invalid(Foldable::fold(cons, folder)?),
     };
     Ok(folded)
 }
@@ -1522,6 +1535,25 @@ pub fn fold_stmt_continue<U, F: Fold<U> + ?Sized>(
     let range = folder.map_user(range, context)?;
     Ok(StmtContinue { range })
 }
+impl<T, U> Foldable<T, U> for StmtInvalid<T> {
+    type Mapped = StmtInvalid<U>;
+    fn fold<F: Fold<T, TargetU = U> + ?Sized>(
+        self,
+        folder: &mut F,
+    ) -> Result<Self::Mapped, F::Error> {
+        folder.fold_stmt_invalid(self)
+    }
+}
+pub fn fold_stmt_invalid<U, F: Fold<U> + ?Sized>(
+    #[allow(unused)] folder: &mut F,
+    node: StmtInvalid<U>,
+) -> Result<StmtInvalid<F::TargetU>, F::Error> {
+    let StmtInvalid { range } = node;
+    let context = folder.will_map_user(&range);
+
+    let range = folder.map_user(range, context)?;
+    Ok(StmtInvalid { range })
+}
 impl<T, U> Foldable<T, U> for Expr<T> {
     type Mapped = Expr<U>;
     fn fold<F: Fold<T, TargetU = U> + ?Sized>(
@@ -1563,6 +1595,7 @@ pub fn fold_expr<U, F: Fold<U> + ?Sized>(
         Expr::List(cons) => Expr::List(Foldable::fold(cons, folder)?),
         Expr::Tuple(cons) => Expr::Tuple(Foldable::fold(cons, folder)?),
         Expr::Slice(cons) => Expr::Slice(Foldable::fold(cons, folder)?),
+        Expr::Invalid(cons) => Expr::Invalid(Foldable::fold(cons, folder)?),
     };
     Ok(folded)
 }
@@ -2267,6 +2300,25 @@ pub fn fold_expr_slice<U, F: Fold<U> + ?Sized>(
         range,
     })
 }
+impl<T, U> Foldable<T, U> for ExprInvalid<T> {
+    type Mapped = ExprInvalid<U>;
+    fn fold<F: Fold<T, TargetU = U> + ?Sized>(
+        self,
+        folder: &mut F,
+    ) -> Result<Self::Mapped, F::Error> {
+        folder.fold_expr_invalid(self)
+    }
+}
+pub fn fold_expr_invalid<U, F: Fold<U> + ?Sized>(
+    #[allow(unused)] folder: &mut F,
+    node: ExprInvalid<U>,
+) -> Result<ExprInvalid<F::TargetU>, F::Error> {
+    let ExprInvalid { range } = node;
+    let context = folder.will_map_user(&range);
+
+    let range = folder.map_user(range, context)?;
+    Ok(ExprInvalid { range })
+}
 impl<T, U> Foldable<T, U> for ExprContext {
     type Mapped = ExprContext;
     fn fold<F: Fold<T, TargetU = U> + ?Sized>(
@@ -306,6 +306,8 @@ pub enum Stmt<R = TextRange> {
     Break(StmtBreak<R>),
     #[is(name = "continue_stmt")]
     Continue(StmtContinue<R>),
+    #[is(name = "invalid_stmt")]
+    Invalid(StmtInvalid<R>),
 }
 
 /// See also [FunctionDef](https://docs.python.org/3/library/ast.html#ast.FunctionDef)
@@ -999,6 +1001,27 @@ impl<R> From<StmtContinue<R>> for Ast<R> {
     }
 }
 
+/// See also [Invalid](https://docs.python.org/3/library/ast.html#ast.Invalid)
+#[derive(Clone, Debug, PartialEq)]
+pub struct StmtInvalid<R = TextRange> {
+    pub range: R,
+}
+
+impl<R> Node for StmtInvalid<R> {
+    const NAME: &'static str = "Invalid";
+    const FIELD_NAMES: &'static [&'static str] = &[];
+}
+impl<R> From<StmtInvalid<R>> for Stmt<R> {
+    fn from(payload: StmtInvalid<R>) -> Self {
+        Stmt::Invalid(payload)
+    }
+}
+impl<R> From<StmtInvalid<R>> for Ast<R> {
+    fn from(payload: StmtInvalid<R>) -> Self {
+        Stmt::from(payload).into()
+    }
+}
+
 impl<R> Node for Stmt<R> {
     const NAME: &'static str = "stmt";
     const FIELD_NAMES: &'static [&'static str] = &[];
@@ -1061,6 +1084,8 @@ pub enum Expr<R = TextRange> {
     Tuple(ExprTuple<R>),
     #[is(name = "slice_expr")]
     Slice(ExprSlice<R>),
+    #[is(name = "invalid_expr")]
+    Invalid(ExprInvalid<R>),
 }
 
 /// See also [BoolOp](https://docs.python.org/3/library/ast.html#ast.BoolOp)
@@ -1688,6 +1713,27 @@ impl<R> From<ExprSlice<R>> for Ast<R> {
     }
 }
 
+/// See also [Invalid](https://docs.python.org/3/library/ast.html#ast.Invalid)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExprInvalid<R = TextRange> {
+    pub range: R,
+}
+
+impl<R> Node for ExprInvalid<R> {
+    const NAME: &'static str = "Invalid";
+    const FIELD_NAMES: &'static [&'static str] = &[];
+}
+impl<R> From<ExprInvalid<R>> for Expr<R> {
+    fn from(payload: ExprInvalid<R>) -> Self {
+        Expr::Invalid(payload)
+    }
+}
+impl<R> From<ExprInvalid<R>> for Ast<R> {
+    fn from(payload: ExprInvalid<R>) -> Self {
+        Expr::from(payload).into()
+    }
+}
+
 impl<R> Node for Expr<R> {
     const NAME: &'static str = "expr";
     const FIELD_NAMES: &'static [&'static str] = &[];
@@ -479,6 +479,20 @@ impl LocatedMut for StmtContinue {
     }
 }
 
+pub type StmtInvalid = crate::generic::StmtInvalid<SourceRange>;
+
+impl Located for StmtInvalid {
+    fn range(&self) -> SourceRange {
+        self.range
+    }
+}
+
+impl LocatedMut for StmtInvalid {
+    fn range_mut(&mut self) -> &mut SourceRange {
+        &mut self.range
+    }
+}
+
 impl Located for Stmt {
     fn range(&self) -> SourceRange {
         match self {
@@ -510,6 +524,7 @@ impl Located for Stmt {
             Self::Pass(node) => node.range(),
             Self::Break(node) => node.range(),
             Self::Continue(node) => node.range(),
+            Self::Invalid(node) => node.range(),
         }
     }
 }
@@ -545,6 +560,7 @@ impl LocatedMut for Stmt {
             Self::Pass(node) => node.range_mut(),
             Self::Break(node) => node.range_mut(),
             Self::Continue(node) => node.range_mut(),
+            Self::Invalid(node) => node.range_mut(),
         }
     }
 }
@@ -929,6 +945,20 @@ impl LocatedMut for ExprSlice {
     }
 }
 
+pub type ExprInvalid = crate::generic::ExprInvalid<SourceRange>;
+
+impl Located for ExprInvalid {
+    fn range(&self) -> SourceRange {
+        self.range
+    }
+}
+
+impl LocatedMut for ExprInvalid {
+    fn range_mut(&mut self) -> &mut SourceRange {
+        &mut self.range
+    }
+}
+
 impl Located for Expr {
     fn range(&self) -> SourceRange {
         match self {
@@ -959,6 +989,7 @@ impl Located for Expr {
             Self::List(node) => node.range(),
             Self::Tuple(node) => node.range(),
             Self::Slice(node) => node.range(),
+            Self::Invalid(node) => node.range(),
         }
     }
 }
@@ -993,6 +1024,7 @@ impl LocatedMut for Expr {
             Self::List(node) => node.range_mut(),
             Self::Tuple(node) => node.range_mut(),
             Self::Slice(node) => node.range_mut(),
+            Self::Invalid(node) => node.range_mut(),
         }
     }
 }
@@ -176,6 +176,11 @@ impl Ranged for crate::generic::StmtContinue<TextRange> {
         self.range
     }
 }
+impl Ranged for crate::generic::StmtInvalid<TextRange> {
+    fn range(&self) -> TextRange {
+        self.range
+    }
+}
 impl Ranged for crate::Stmt {
     fn range(&self) -> TextRange {
         match self {
@@ -207,6 +212,7 @@ impl Ranged for crate::Stmt {
             Self::Pass(node) => node.range(),
             Self::Break(node) => node.range(),
             Self::Continue(node) => node.range(),
+            Self::Invalid(node) => node.range(),
         }
     }
 }
@@ -346,6 +352,11 @@ impl Ranged for crate::generic::ExprSlice<TextRange> {
         self.range
     }
 }
+impl Ranged for crate::generic::ExprInvalid<TextRange> {
+    fn range(&self) -> TextRange {
+        self.range
+    }
+}
 impl Ranged for crate::Expr {
     fn range(&self) -> TextRange {
         match self {
@@ -376,6 +387,7 @@ impl Ranged for crate::Expr {
             Self::List(node) => node.range(),
             Self::Tuple(node) => node.range(),
             Self::Slice(node) => node.range(),
+            Self::Invalid(node) => node.range(),
         }
     }
 }
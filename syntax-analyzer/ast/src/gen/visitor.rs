@@ -35,6 +35,7 @@ pub trait Visitor<R = crate::text_size::TextRange> {
             Stmt::Pass(data) => self.visit_stmt_pass(data),
             Stmt::Break(data) => self.visit_stmt_break(data),
             Stmt::Continue(data) => self.visit_stmt_continue(data),
+            Stmt::Invalid(data) => self.visit_stmt_invalid(data),
         }
     }
     fn visit_stmt_function_def(&mut self, node: StmtFunctionDef<R>) {
@@ -367,6 +368,7 @@ pub trait Visitor<R = crate::text_size::TextRange> {
     fn visit_stmt_pass(&mut self, node: StmtPass<R>) {}
     fn visit_stmt_break(&mut self, node: StmtBreak<R>) {}
     fn visit_stmt_continue(&mut self, node: StmtContinue<R>) {}
+    fn visit_stmt_invalid(&mut self, node: StmtInvalid<R>) {}
     fn visit_expr(&mut self, node: Expr<R>) {
         self.generic_visit_expr(node)
     }
@@ -399,6 +401,7 @@ pub trait Visitor<R = crate::text_size::TextRange> {
             Expr::List(data) => self.visit_expr_list(data),
             Expr::Tuple(data) => self.visit_expr_tuple(data),
             Expr::Slice(data) => self.visit_expr_slice(data),
+            Expr::Invalid(data) => self.visit_expr_invalid(data),
         }
     }
     fn visit_expr_bool_op(&mut self, node: ExprBoolOp<R>) {
@@ -687,6 +690,7 @@ pub trait Visitor<R = crate::text_size::TextRange> {
             self.visit_expr(*value);
         }
     }
+    fn visit_expr_invalid(&mut self, node: ExprInvalid<R>) {}
     fn visit_expr_context(&mut self, node: ExprContext) {
         self.generic_visit_expr_context(node)
     }
@@ -50,6 +50,7 @@ impl<R> Expr<R> {
             Expr::Lambda { .. } => "lambda",
             Expr::IfExp { .. } => "conditional expression",
             Expr::NamedExpr { .. } => "named expression",
+            Expr::Invalid { .. } => "invalid expression",
         }
     }
 }
@@ -155,8 +155,12 @@ impl<'a> Unparser<'a> {
                 range: _range,
             }) => {
                 group_if!(precedence::TEST, {
-                    let pos = args.args.len() + args.posonlyargs.len();
-                    self.p(if pos > 0 { "lambda " } else { "lambda" })?;
+                    let has_args = !args.posonlyargs.is_empty()
+                        || !args.args.is_empty()
+                        || args.vararg.is_some()
+                        || !args.kwonlyargs.is_empty()
+                        || args.kwarg.is_some();
+                    self.p(if has_args { "lambda " } else { "lambda" })?;
                     self.unparse_arguments(args)?;
                     write!(self, ": {}", **body)?;
                 })
@@ -435,6 +439,7 @@ impl<'a> Unparser<'a> {
                     self.unparse_expr(step, precedence::TEST)?;
                 }
             }
+            Expr::Invalid(_) => self.p("<invalid>")?,
         }
         Ok(())
     }
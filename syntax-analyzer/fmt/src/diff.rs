@@ -0,0 +1,181 @@
+//! Unified diffs of the changes made by the formatter, for `--diff`.
+
+/// One line of an edit script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Edit {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// The lines of context around each change.
+const CONTEXT: usize = 3;
+
+/// A unified diff from `old` to `new`, or an empty string if they are the same.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<_> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<_> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    if edits.iter().all(|edit| matches!(edit, Edit::Keep(..))) {
+        return String::new();
+    }
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    let changes: Vec<usize> = (0..edits.len())
+        .filter(|&index| !matches!(edits[index], Edit::Keep(..)))
+        .collect();
+
+    // Group the changes that are close enough to share their context
+    let mut start = 0;
+    while start < changes.len() {
+        let mut end = start;
+        while end + 1 < changes.len() && changes[end + 1] - changes[end] <= 2 * CONTEXT + 1 {
+            end += 1;
+        }
+        let first = changes[start].saturating_sub(CONTEXT);
+        let last = (changes[end] + CONTEXT + 1).min(edits.len());
+        hunk(&mut out, &edits[first..last], &old_lines, &new_lines);
+        start = end + 1;
+    }
+    out
+}
+
+fn hunk(out: &mut String, edits: &[Edit], old: &[&str], new: &[&str]) {
+    // The position of the hunk is the first line it touches on each side
+    let (mut old_start, mut new_start) = (old.len(), new.len());
+    let (mut old_count, mut new_count) = (0, 0);
+    for edit in edits {
+        match *edit {
+            Edit::Keep(o, n) => {
+                old_start = old_start.min(o);
+                new_start = new_start.min(n);
+                old_count += 1;
+                new_count += 1;
+            }
+            Edit::Delete(o) => {
+                old_start = old_start.min(o);
+                old_count += 1;
+            }
+            Edit::Insert(n) => {
+                new_start = new_start.min(n);
+                new_count += 1;
+            }
+        }
+    }
+    let range = |start: usize, count: usize| {
+        // An empty range is given as the line before it
+        let start = if count == 0 { start } else { start + 1 };
+        if count == 1 {
+            start.to_string()
+        } else {
+            format!("{start},{count}")
+        }
+    };
+    out.push_str(&format!(
+        "@@ -{} +{} @@\n",
+        range(old_start, old_count),
+        range(new_start, new_count)
+    ));
+    for edit in edits {
+        let (marker, line) = match *edit {
+            Edit::Keep(o, _) => (' ', old[o]),
+            Edit::Delete(o) => ('-', old[o]),
+            Edit::Insert(n) => ('+', new[n]),
+        };
+        out.push(marker);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// The shortest edit script from `old` to `new`, with Myers' algorithm.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk the trace back from the end
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let index = |k: isize| (k + offset) as usize;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                edits.push(Edit::Insert(previous_y as usize));
+            } else {
+                edits.push(Edit::Delete(previous_x as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_changes() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old: String = (1..=14).map(|line| format!("{line}\n")).collect();
+        let new = old.replace("\n5\n", "\nfive\n") + "15\n";
+        assert_eq!(
+            unified_diff(&old, &new, "a.py", "a.py"),
+            "--- a.py\n+++ a.py\n@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n@@ -12,3 +12,4 @@\n 12\n 13\n 14\n+15\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_newline() {
+        assert_eq!(
+            unified_diff("x", "x\n", "old", "new"),
+            "--- old\n+++ new\n@@ -1 +1 @@\n-x\n\\ No newline at end of file\n+x\n"
+        );
+    }
+}
@@ -0,0 +1,659 @@
+//! Fit expressions into the line width.
+//!
+//! An expression is first turned into a [`Doc`], which knows where the line may be broken:
+//! inside brackets and, within brackets, before operators. [`Layout`] then picks the breaks
+//! the way black does:
+//!
+//! 1. If the whole line fits, it is printed as is.
+//! 2. Otherwise the last pair of brackets whose opening line fits is split, with its contents
+//!    on their own lines.
+//! 3. Contents that still don't fit are split at the commas, one item per line and with a
+//!    trailing comma, or before the operators.
+//!
+//! Comments in brackets are part of their [`Group`]. One on the line of a bracket or an item
+//! ends up at the end of the line that bracket or item is printed on, and counts towards its
+//! width; one on a line of its own keeps the group split.
+
+/// Delimits a comment in rendered text, which [`place_comments`] moves to the end of its line.
+const COMMENT: char = '\0';
+/// Marks a comment that needs a line of its own, but was rendered in the middle of one.
+const OWN_LINE: char = '\u{1}';
+
+/// A piece of code that may be split over several lines.
+#[derive(Clone, Debug)]
+pub(crate) enum Doc {
+    Text(String),
+    Concat(Vec<Doc>),
+    Group(Box<Group>),
+    /// Operands joined by operators, as `(operator, operand)` pairs. The operator of the first
+    /// operand is empty, as are the operators between implicitly concatenated strings.
+    Chain(Vec<(&'static str, Doc)>),
+}
+
+/// How the items of a [`Group`] are separated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Separator {
+    Comma,
+    /// Used for the clauses of comprehensions.
+    Space,
+}
+
+/// Items between brackets.
+#[derive(Clone, Debug)]
+pub(crate) struct Group {
+    pub open: &'static str,
+    pub close: &'static str,
+    pub items: Vec<Doc>,
+    pub separator: Separator,
+    /// The brackets are only written when the group is split, e.g. around the value of an
+    /// assignment.
+    pub optional: bool,
+    /// A tuple of one element, which needs its trailing comma on one line too.
+    pub one_tuple: bool,
+    /// The items may be moved to a line of their own without splitting them further. Literal
+    /// collections are split one item per line instead.
+    pub hug: bool,
+    /// The source ends the items with a comma, which keeps them one per line.
+    pub magic_trailing_comma: bool,
+    pub comments: Comments,
+}
+
+/// The comments between the brackets of a [`Group`], normalized.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Comments {
+    /// On the line of the opening bracket.
+    pub open: Vec<String>,
+    /// On lines of their own before each item. Empty, or one entry per item.
+    pub leading: Vec<Vec<String>>,
+    /// On the line of each item, after it and its comma. Empty, or one entry per item.
+    pub trailing: Vec<Vec<String>>,
+    /// On lines of their own after the last item.
+    pub dangling: Vec<String>,
+}
+
+impl Comments {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.open.is_empty()
+            && self.dangling.is_empty()
+            && self.leading.iter().all(Vec::is_empty)
+            && self.trailing.iter().all(Vec::is_empty)
+    }
+
+    /// Returns true if a comment is on a line of its own.
+    fn has_lines(&self) -> bool {
+        !self.dangling.is_empty() || self.leading.iter().any(|lines| !lines.is_empty())
+    }
+
+    fn leading(&self, index: usize) -> &[String] {
+        self.leading.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    fn trailing(&self, index: usize) -> &[String] {
+        self.trailing.get(index).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// `comments` as they are rendered at the end of a line.
+fn markers(comments: &[String]) -> String {
+    comments
+        .iter()
+        .map(|comment| format!("{COMMENT}  {comment}{COMMENT}"))
+        .collect()
+}
+
+impl Group {
+    pub(crate) fn new(open: &'static str, close: &'static str, items: Vec<Doc>) -> Self {
+        Group {
+            open,
+            close,
+            items,
+            separator: Separator::Comma,
+            optional: false,
+            one_tuple: false,
+            hug: true,
+            magic_trailing_comma: false,
+            comments: Comments::default(),
+        }
+    }
+
+    fn brackets(&self) -> (&'static str, &'static str) {
+        if self.optional {
+            ("(", ")")
+        } else {
+            (self.open, self.close)
+        }
+    }
+
+    fn separator(&self) -> &'static str {
+        match self.separator {
+            Separator::Comma => ", ",
+            Separator::Space => " ",
+        }
+    }
+
+    fn flat_items(&self) -> String {
+        let mut flat = String::new();
+        if self.comments.has_lines() {
+            flat.push(OWN_LINE);
+            flat.push('\n');
+        }
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                flat.push_str(self.separator());
+            }
+            flat.push_str(&item.flat());
+            flat.push_str(&markers(self.comments.trailing(index)));
+        }
+        if self.one_tuple {
+            flat.push(',');
+        }
+        flat
+    }
+}
+
+impl From<Group> for Doc {
+    fn from(group: Group) -> Self {
+        Doc::Group(Box::new(group))
+    }
+}
+
+impl Doc {
+    pub(crate) fn text(text: impl Into<String>) -> Self {
+        Doc::Text(text.into())
+    }
+
+    /// A comment at the end of the line the doc before it ends on.
+    pub(crate) fn comment(comment: String) -> Self {
+        Doc::Text(markers(&[comment]))
+    }
+
+    /// Concatenate `docs`, merging nested concatenations.
+    pub(crate) fn concat(docs: impl IntoIterator<Item = Doc>) -> Self {
+        let mut parts = Vec::new();
+        for doc in docs {
+            match doc {
+                Doc::Concat(inner) => parts.extend(inner),
+                Doc::Text(text) if text.is_empty() => {}
+                doc => parts.push(doc),
+            }
+        }
+        if parts.len() == 1 {
+            parts.pop().unwrap()
+        } else {
+            Doc::Concat(parts)
+        }
+    }
+
+    /// Wrap `doc` in parentheses that are only written when it has to be split.
+    pub(crate) fn optional_parentheses(doc: Doc) -> Self {
+        match doc {
+            Doc::Group(group) if group.optional => Doc::Group(group),
+            doc => Group {
+                optional: true,
+                ..Group::new("(", ")", vec![doc])
+            }
+            .into(),
+        }
+    }
+
+    /// The doc on a single line.
+    pub(crate) fn flat(&self) -> String {
+        match self {
+            Doc::Text(text) => text.clone(),
+            Doc::Concat(parts) => parts.iter().map(Doc::flat).collect(),
+            Doc::Group(group) => {
+                let open = markers(&group.comments.open);
+                let items = group.flat_items();
+                if group.optional {
+                    open + &items
+                } else {
+                    format!("{}{open}{items}{}", group.open, group.close)
+                }
+            }
+            Doc::Chain(operands) => {
+                let mut flat = String::new();
+                for (index, (operator, operand)) in operands.iter().enumerate() {
+                    if index > 0 {
+                        flat.push(' ');
+                    }
+                    if !operator.is_empty() {
+                        flat.push_str(operator);
+                        flat.push(' ');
+                    }
+                    flat.push_str(&operand.flat());
+                }
+                flat
+            }
+        }
+    }
+
+    /// Returns true for implicitly concatenated strings.
+    pub(crate) fn is_concatenation(&self) -> bool {
+        match self {
+            Doc::Chain(operands) => {
+                operands.len() > 1 && operands.iter().all(|(operator, _)| operator.is_empty())
+            }
+            _ => false,
+        }
+    }
+
+    /// Apply `f` to the last of implicitly concatenated strings, which is what a trailer like
+    /// `.format(x)` or a following operator binds to on its line, or to the whole doc.
+    pub(crate) fn map_last_string(self, f: impl FnOnce(Doc) -> Doc) -> Doc {
+        match self {
+            Doc::Chain(mut operands) if self.is_concatenation() => {
+                let (operator, last) = operands.pop().unwrap();
+                operands.push((operator, f(last)));
+                Doc::Chain(operands)
+            }
+            doc => f(doc),
+        }
+    }
+
+    /// Returns true if a group in the doc has a magic trailing comma or a comment on a line of
+    /// its own, so it can't be printed on one line.
+    fn must_split(&self) -> bool {
+        match self {
+            Doc::Text(_) => false,
+            Doc::Concat(parts) => parts.iter().any(Doc::must_split),
+            Doc::Group(group) => {
+                group.magic_trailing_comma
+                    || group.comments.has_lines()
+                    || group.items.iter().any(Doc::must_split)
+            }
+            Doc::Chain(operands) => operands.iter().any(|(_, operand)| operand.must_split()),
+        }
+    }
+
+    /// Returns true if the doc can be split at all.
+    fn is_splittable(&self) -> bool {
+        match self {
+            Doc::Text(_) => false,
+            Doc::Concat(parts) => parts.iter().any(Doc::is_splittable),
+            Doc::Group(group) => !group.items.is_empty() || group.comments.has_lines(),
+            Doc::Chain(operands) => operands
+                .last()
+                .is_some_and(|(_, operand)| operand.is_splittable()),
+        }
+    }
+
+    /// Returns true if the doc ends with brackets that can be split, like a call. Trailers like
+    /// `.pop()` after them are skipped.
+    fn ends_with_brackets(&self) -> bool {
+        match self {
+            Doc::Concat(parts) => parts
+                .iter()
+                .rev()
+                .find(|part| !part.is_trailer())
+                .is_some_and(Doc::ends_with_brackets),
+            Doc::Group(group) => !group.optional && self.is_splittable(),
+            Doc::Text(_) | Doc::Chain(_) => false,
+        }
+    }
+
+    /// Returns true for an attribute access, empty brackets or a comment.
+    fn is_trailer(&self) -> bool {
+        match self {
+            Doc::Text(text) => text.trim_start().starts_with(['.', COMMENT]),
+            Doc::Group(group) => group.items.is_empty(),
+            Doc::Concat(_) | Doc::Chain(_) => false,
+        }
+    }
+
+    /// Returns true if optional parentheses around the doc are better left out, splitting the
+    /// last brackets of the doc instead. Like black, this is the case if it has no operators or
+    /// a single one, and ends with brackets. A call chain like `a(b).c(d).e()` keeps them, as
+    /// does an operand before the operator that can't be on one line.
+    fn can_omit_parentheses(&self) -> bool {
+        match self {
+            Doc::Chain(operands) => {
+                operands.len() == 2
+                    && operands[1].1.ends_with_brackets()
+                    && !operands[0].1.must_split()
+            }
+            Doc::Concat(parts) => {
+                let calls = parts
+                    .windows(2)
+                    .filter(|pair| matches!(pair[0], Doc::Group(_)) && pair[1].is_trailer())
+                    .count();
+                calls <= 1 && self.ends_with_brackets()
+            }
+            doc => doc.ends_with_brackets(),
+        }
+    }
+}
+
+/// The width of the last line of `text`, including its comments.
+fn width(text: &str) -> usize {
+    text.rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|&c| c != COMMENT)
+        .count()
+}
+
+/// Returns true if `text` contains the characters used to mark comments, so the comments
+/// rendered with it couldn't be told apart.
+pub(crate) fn has_markers(text: &str) -> bool {
+    text.contains([COMMENT, OWN_LINE])
+}
+
+/// Move the comments in rendered `text` to the ends of their lines, after the code. Returns
+/// `None` if a comment that needs a line of its own was rendered in the middle of one.
+pub(crate) fn place_comments(text: &str) -> Option<String> {
+    if text.contains(OWN_LINE) {
+        return None;
+    }
+    let lines: Vec<_> = text
+        .split('\n')
+        .map(|line| {
+            if !line.contains(COMMENT) {
+                return line.to_owned();
+            }
+            let (mut code, mut comments) = (String::new(), String::new());
+            for (index, part) in line.split(COMMENT).enumerate() {
+                if index % 2 == 0 {
+                    code.push_str(part);
+                } else {
+                    comments.push_str(part);
+                }
+            }
+            code.truncate(code.trim_end().len());
+            code + &comments
+        })
+        .collect();
+    Some(lines.join("\n"))
+}
+
+/// Splits docs into lines of at most `line_length` characters where possible.
+pub(crate) struct Layout {
+    pub line_length: usize,
+}
+
+impl Layout {
+    /// Returns true if `text` fits after `used` characters, with `suffix` characters following
+    /// it. Like in black, multi-line strings never fit.
+    fn fits(&self, text: &str, used: usize, suffix: usize) -> bool {
+        !text.contains('\n') && used + width(text) + suffix <= self.line_length
+    }
+
+    /// Returns true if the first line of the rendered `text` fits after `used` characters.
+    fn first_line_fits(&self, text: &str, used: usize) -> bool {
+        let first = text.split('\n').next().unwrap_or_default();
+        used + width(first) <= self.line_length
+    }
+
+    /// Render `doc` at the given indentation. `used` characters of the current line are taken
+    /// already and `suffix` characters will follow the doc on its last line.
+    pub(crate) fn render(&self, doc: &Doc, indent: usize, used: usize, suffix: usize) -> String {
+        if !doc.must_split() {
+            let flat = doc.flat();
+            if self.fits(&flat, used, suffix) {
+                return flat;
+            }
+        }
+        match doc {
+            Doc::Text(text) => text.clone(),
+            Doc::Concat(parts) => self.right_hand_split(parts, indent, used, suffix),
+            Doc::Group(group) => self.split_group(group, indent, used, suffix),
+            Doc::Chain(operands) => {
+                // Outside of brackets, only the last operand can be split
+                let (last, init) = operands.split_last().unwrap();
+                let mut head = Doc::Chain(init.to_vec()).flat();
+                head.push(' ');
+                if !last.0.is_empty() {
+                    head.push_str(last.0);
+                    head.push(' ');
+                }
+                let tail = self.render(&last.1, indent, used + width(&head), suffix);
+                head + &tail
+            }
+        }
+    }
+
+    /// Render `doc` inside brackets, where operators are split before brackets are.
+    fn render_in_brackets(&self, doc: &Doc, indent: usize, used: usize, suffix: usize) -> String {
+        let Doc::Chain(operands) = doc else {
+            return self.render(doc, indent, used, suffix);
+        };
+        let flat = doc.flat();
+        if !doc.must_split() && self.fits(&flat, used, suffix) {
+            return flat;
+        }
+        // Split before each operator, and the operands at their own operators
+        let mut lines = Vec::with_capacity(operands.len());
+        for (index, (operator, operand)) in operands.iter().enumerate() {
+            let prefix = if operator.is_empty() {
+                String::new()
+            } else {
+                format!("{operator} ")
+            };
+            let used = if index == 0 { used } else { indent } + width(&prefix);
+            let suffix = if index + 1 == operands.len() {
+                suffix
+            } else {
+                0
+            };
+            let rendered = self.render_in_brackets(operand, indent, used, suffix);
+            lines.push(prefix + &rendered);
+        }
+        lines.join(&format!("\n{}", " ".repeat(indent)))
+    }
+
+    /// Split the last brackets of `parts` whose opening line fits.
+    fn right_hand_split(&self, parts: &[Doc], indent: usize, used: usize, suffix: usize) -> String {
+        let candidates: Vec<usize> = (0..parts.len())
+            .filter(|&index| parts[index].is_splittable())
+            .collect();
+        let flat = |parts: &[Doc]| parts.iter().map(Doc::flat).collect::<String>();
+
+        let chosen = if let Some(&magic) = candidates
+            .iter()
+            .rev()
+            .find(|&&index| parts[index].must_split())
+        {
+            Some(magic)
+        } else {
+            candidates
+                .iter()
+                .rev()
+                .find(|&&index| {
+                    let head = flat(&parts[..index]);
+                    let tail = flat(&parts[index + 1..]);
+                    self.fits(&head, used, 1) && self.fits(&tail, indent, 1 + suffix)
+                })
+                .or(candidates.first())
+                .copied()
+        };
+
+        let Some(index) = chosen else {
+            return flat(parts);
+        };
+        let head = flat(&parts[..index]);
+        let tail = flat(&parts[index + 1..]);
+        let middle = self.render(
+            &parts[index],
+            indent,
+            used + width(&head),
+            width(&tail) + suffix,
+        );
+        head + &middle + &tail
+    }
+
+    /// Render a line split at its first brackets, like the parameters of a function
+    /// definition. Whatever follows them is split from the right again.
+    pub(crate) fn left_hand_split(&self, parts: &[Doc], indent: usize) -> String {
+        let doc = Doc::Concat(parts.to_vec());
+        let flat = doc.flat();
+        if !doc.must_split() && self.fits(&flat, indent, 0) {
+            return flat;
+        }
+        let Some(index) = parts.iter().position(Doc::is_splittable) else {
+            return flat;
+        };
+        let head: String = parts[..index].iter().map(Doc::flat).collect();
+        let used = indent + width(&head);
+        let middle = match &parts[index] {
+            Doc::Group(group) => self.split_group(group, indent, used, 0),
+            part => self.render(part, indent, used, 0),
+        };
+        let last_line = middle.rsplit('\n').next().unwrap_or_default();
+        let tail = self.render(
+            &Doc::concat(parts[index + 1..].to_vec()),
+            indent,
+            width(last_line),
+            0,
+        );
+        head + &middle + &tail
+    }
+
+    fn split_group(&self, group: &Group, indent: usize, used: usize, suffix: usize) -> String {
+        let comments = &group.comments;
+        if group.optional && group.items.len() == 1 && !group.one_tuple && !comments.has_lines() {
+            let content = &group.items[0];
+            // Without the parentheses, their comments go to the first and the last line
+            let before = markers(&comments.open);
+            let after = markers(comments.trailing(0));
+            // Prefer splitting the brackets of the content itself, like the arguments of a call
+            if content.can_omit_parentheses() {
+                let rendered = self.render(
+                    content,
+                    indent,
+                    used + width(&before),
+                    width(&after) + suffix,
+                );
+                let rendered = format!("{before}{rendered}{after}");
+                if self.first_line_fits(&rendered, used) {
+                    return rendered;
+                }
+            }
+            // Parentheses that don't help are left out
+            let flat = content.flat();
+            let splittable = content.is_splittable()
+                || matches!(content, Doc::Chain(operands) if operands.len() > 1);
+            if !splittable && !self.fits(&flat, indent + 4, 0) {
+                return format!("{before}{flat}{after}");
+            }
+        }
+
+        let (open, close) = group.brackets();
+        let inner = indent + 4;
+        let newline = format!("\n{}", " ".repeat(inner));
+        let closing = format!("\n{}{close}", " ".repeat(indent));
+        let lines = |split: &mut String, lines: &[String]| {
+            for line in lines {
+                split.push_str(&newline);
+                split.push_str(line);
+            }
+        };
+        let mut split = format!("{open}{}", markers(&comments.open));
+
+        if group.items.is_empty() {
+            if comments.dangling.is_empty() {
+                return split + close;
+            }
+            lines(&mut split, &comments.dangling);
+            return split + &closing;
+        }
+
+        let magic = group.magic_trailing_comma || group.items.iter().any(Doc::must_split);
+        if !magic && (group.hug || group.items.len() == 1) {
+            let flat = group.flat_items();
+            if self.fits(&flat, inner, 0) {
+                return format!("{split}{newline}{flat}{closing}");
+            }
+        }
+        // A single item only gets a comma if the source has one
+        if group.items.len() == 1 && !group.magic_trailing_comma {
+            let trailing = if group.one_tuple { "," } else { "" };
+            let after = markers(comments.trailing(0));
+            let item = self.render_in_brackets(
+                &group.items[0],
+                inner,
+                inner,
+                trailing.len() + width(&after),
+            );
+            lines(&mut split, comments.leading(0));
+            split.push_str(&format!("{newline}{item}{trailing}{after}"));
+            lines(&mut split, &comments.dangling);
+            return split + &closing;
+        }
+
+        let comma = group.separator == Separator::Comma;
+        for (index, item) in group.items.iter().enumerate() {
+            let after = markers(comments.trailing(index));
+            lines(&mut split, comments.leading(index));
+            split.push_str(&newline);
+            split.push_str(&self.render_in_brackets(
+                item,
+                inner,
+                inner,
+                usize::from(comma) + width(&after),
+            ));
+            if comma {
+                split.push(',');
+            }
+            split.push_str(&after);
+        }
+        lines(&mut split, &comments.dangling);
+        split.push_str(&closing);
+        split
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[&str]) -> Doc {
+        Doc::concat([
+            Doc::text(name),
+            Group::new("(", ")", args.iter().map(|arg| Doc::text(*arg)).collect()).into(),
+        ])
+    }
+
+    #[test]
+    fn test_fits() {
+        let layout = Layout { line_length: 20 };
+        let doc = call("f", &["a", "b"]);
+        assert_eq!(layout.render(&doc, 0, 0, 0), "f(a, b)");
+    }
+
+    #[test]
+    fn test_hug() {
+        let layout = Layout { line_length: 20 };
+        let doc = call("function", &["argument", "other"]);
+        assert_eq!(
+            layout.render(&doc, 0, 0, 0),
+            "function(\n    argument, other\n)"
+        );
+    }
+
+    #[test]
+    fn test_one_per_line() {
+        let layout = Layout { line_length: 12 };
+        let doc = call("f", &["argument", "other"]);
+        assert_eq!(
+            layout.render(&doc, 0, 0, 0),
+            "f(\n    argument,\n    other,\n)"
+        );
+    }
+
+    #[test]
+    fn test_optional_parentheses() {
+        let layout = Layout { line_length: 20 };
+        let chain = Doc::Chain(vec![("", Doc::text("first")), ("+", Doc::text("second"))]);
+        let doc = Doc::concat([Doc::text("value = "), Doc::optional_parentheses(chain)]);
+        assert_eq!(
+            layout.render(&doc, 0, 0, 0),
+            "value = (\n    first + second\n)"
+        );
+
+        let layout = Layout { line_length: 12 };
+        assert_eq!(
+            layout.render(&doc, 0, 0, 0),
+            "value = (\n    first\n    + second\n)"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+//! A Python source formatter built on the RustPython parser.
+//!
+//! The source is parsed into a [concrete syntax tree](rustpython_parser::cst), which keeps the
+//! comments and the brackets the AST throws away. Statements are printed one per line and
+//! expressions are reflowed to the line width the way [black](https://black.readthedocs.io)
+//! does, with the atoms the formatter doesn't touch written by the AST unparser. Comments stay
+//! with the statements they are next to.
+//!
+//! ```
+//! use rustpython_fmt::{format_source, FormatOptions};
+//!
+//! let formatted = format_source("x = { 'a':37,'b':42,\n'c':927}\n", &FormatOptions::default());
+//! assert_eq!(formatted.unwrap(), "x = {\"a\": 37, \"b\": 42, \"c\": 927}\n");
+//! ```
+//!
+//! The formatted code is parsed again and compared with the original AST; a formatter bug
+//! never changes what the code means, it is reported as [`FormatError::NotEquivalent`].
+
+mod diff;
+mod doc;
+mod literals;
+mod printer;
+mod trivia;
+
+pub use diff::unified_diff;
+
+use crate::{literals::LiteralSpelling, printer::Printer, trivia::Trivia};
+use rustpython_ast::{self as ast, fold::Fold};
+use rustpython_parser::{cst::Cst, text_size::TextRange, Mode, Parse, ParseError};
+use std::{convert::Infallible, fmt};
+
+/// How to format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The maximum width of a line, in characters.
+    pub line_length: usize,
+    /// Prefer double quotes for strings, like black. Otherwise the quotes are left alone.
+    pub normalize_quotes: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            line_length: 88,
+            normalize_quotes: true,
+        }
+    }
+}
+
+/// Why a source couldn't be formatted.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The source isn't valid Python.
+    Parse(ParseError),
+    /// The formatted code doesn't parse to the same AST as the source. This is a bug in the
+    /// formatter.
+    NotEquivalent,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Parse(error) => write!(f, "cannot parse: {error}"),
+            FormatError::NotEquivalent => {
+                write!(f, "the formatted code is not equivalent to the source")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Parse(error) => Some(error),
+            FormatError::NotEquivalent => None,
+        }
+    }
+}
+
+impl From<ParseError> for FormatError {
+    fn from(error: ParseError) -> Self {
+        FormatError::Parse(error)
+    }
+}
+
+/// Format a module.
+pub fn format_source(source: &str, options: &FormatOptions) -> Result<String, FormatError> {
+    let cst = Cst::parse(source, Mode::Module, "<fmt>")?;
+    let ast::Mod::Module(module) = cst.ast() else {
+        unreachable!("parsed in module mode");
+    };
+    if module.body.is_empty() && source.trim().is_empty() {
+        return Ok(String::new());
+    }
+
+    let trivia = Trivia::new(&cst);
+    let mut spelling = LiteralSpelling {
+        trivia: &trivia,
+        normalize_quotes: options.normalize_quotes,
+    };
+    let body: Vec<_> = module
+        .body
+        .iter()
+        .map(|stmt| {
+            spelling
+                .fold_stmt(stmt.clone())
+                .unwrap_or_else(|never| match never {})
+        })
+        .collect();
+    let mut formatted = Printer::new(&trivia, options.line_length).module(&body);
+
+    let formatted_ast = ast::Suite::parse(&formatted, "<fmt>")?;
+    if erase(&formatted_ast) != erase(&module.body) {
+        return Err(FormatError::NotEquivalent);
+    }
+
+    if source.contains("\r\n") {
+        formatted = formatted.replace('\n', "\r\n");
+    }
+    Ok(formatted)
+}
+
+/// Clear what two equivalent ASTs may disagree on: the positions and the `u` prefix of
+/// strings.
+fn erase(body: &[ast::Stmt]) -> Vec<ast::Stmt> {
+    body.iter()
+        .map(|stmt| {
+            Erase
+                .fold_stmt(stmt.clone())
+                .unwrap_or_else(|never| match never {})
+        })
+        .collect()
+}
+
+struct Erase;
+
+impl Fold<TextRange> for Erase {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) {}
+
+    fn map_user(&mut self, _user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(TextRange::default())
+    }
+
+    fn fold_expr_constant(
+        &mut self,
+        node: ast::ExprConstant,
+    ) -> Result<ast::ExprConstant, Infallible> {
+        let node = ast::fold::fold_expr_constant(self, node)?;
+        Ok(ast::ExprConstant { kind: None, ..node })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        let formatted = format_source(source, &FormatOptions::default()).unwrap();
+        let again = format_source(&formatted, &FormatOptions::default()).unwrap();
+        assert_eq!(formatted, again, "formatting is not stable");
+        formatted
+    }
+
+    #[test]
+    fn test_simple_statements() {
+        assert_eq!(format("x=1;y =  2\n"), "x = 1\ny = 2\n");
+        assert_eq!(format("print( 'hi' )"), "print(\"hi\")\n");
+        assert_eq!(format("del (a), b\n"), "del (a), b\n");
+        assert_eq!(format("return_ = not(x)\n"), "return_ = not (x)\n");
+        assert_eq!(format("x = (1)\n"), "x = 1\n");
+        assert_eq!(format("x = (yield)\n"), "x = (yield)\n");
+        assert_eq!(format("for (x) in (y): pass\n"), "for x in y:\n    pass\n");
+        assert_eq!(format("a = 0XFF + 1E5 + .5\n"), "a = 0xFF + 1e5 + 0.5\n");
+        assert_eq!(format("x = 1 .real\n"), "x = 1 .real\n");
+        assert_eq!(format("x = i**2 + f(x) ** 2\n"), "x = i**2 + f(x) ** 2\n");
+        assert_eq!(format("x[a+1 :]\n"), "x[a + 1 :]\n");
+        assert_eq!(format("x[1:2, ::3]\n"), "x[1:2, ::3]\n");
+        assert_eq!(format("f = lambda*a, **k: 0\n"), "f = lambda *a, **k: 0\n");
+        assert_eq!(format(""), "");
+        assert_eq!(format("\n\n"), "");
+    }
+
+    #[test]
+    fn test_reflow() {
+        let source = "result = some_function_name(argument_number_one, argument_number_two, argument_number_three)\n";
+        assert_eq!(
+            format(source),
+            "result = some_function_name(\n    argument_number_one, argument_number_two, argument_number_three\n)\n"
+        );
+
+        let source = "def f(a,):\n    return [\n        1, 2\n    ]\n";
+        assert_eq!(format(source), "def f(\n    a,\n):\n    return [1, 2]\n");
+
+        let source = "x = [1, 2,]\n";
+        assert_eq!(format(source), "x = [\n    1,\n    2,\n]\n");
+
+        let source = "message = ('first and rather long part of the message, ' 'second, longer part of the message: {}'.format(value))\n";
+        assert_eq!(
+            format(source),
+            "message = (\n    \"first and rather long part of the message, \"\n    \"second, longer part of the message: {}\".format(value)\n)\n"
+        );
+
+        let options = FormatOptions {
+            line_length: 20,
+            ..FormatOptions::default()
+        };
+        let source = "value = first_operand + second_operand\n";
+        assert_eq!(
+            format_source(source, &options).unwrap(),
+            "value = (\n    first_operand\n    + second_operand\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "import os\n#comment\nx = 1 # trailing\ndef f():\n    pass\n    # end of f\n# top level\n";
+        assert_eq!(
+            format(source),
+            "import os\n\n# comment\nx = 1  # trailing\n\n\ndef f():\n    pass\n    # end of f\n\n\n# top level\n"
+        );
+
+        let source = "if x:\n    pass\n# before else\nelse:\n    pass\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_comments_in_brackets() {
+        // Comments on the line of a bracket or an item go to the end of its line
+        let source = "y = f(first_argument_with_a_long_name, second_argument_with_a_long_name,  # why\n third_argument_with_a_long_name)\n";
+        assert_eq!(
+            format(source),
+            "y = f(\n    first_argument_with_a_long_name,\n    second_argument_with_a_long_name,  # why\n    third_argument_with_a_long_name,\n)\n"
+        );
+        assert_eq!(
+            format("def f(a,  # first\n b):\n    pass\n"),
+            "def f(a, b):  # first\n    pass\n"
+        );
+        assert_eq!(format("x = [  # why\n  1, 2\n]\n"), "x = [1, 2]  # why\n");
+        assert_eq!(format("x = (  # why\n    1\n)\n"), "x = 1  # why\n");
+        let source = "children = [\n    children[0],  # (1\n    body,\n    children[-1]  # )1\n]\n";
+        assert_eq!(
+            format(source),
+            "children = [children[0], body, children[-1]]  # (1  # )1\n"
+        );
+        assert_eq!(
+            format("if (a and  # first\n    b):\n    pass\n"),
+            "if a and b:  # first\n    pass\n"
+        );
+
+        // Comments on lines of their own keep the brackets split
+        let source = "call(\n#short\narg1,\n#but\narg2,\narg3=True)\n";
+        assert_eq!(
+            format(source),
+            "call(\n    # short\n    arg1,\n    # but\n    arg2,\n    arg3=True,\n)\n"
+        );
+        let source = "def f(\n    a,\n    # nothing else\n):\n    pass\n";
+        assert_eq!(format(source), source);
+        let source = "x = [\n    # empty\n]\n";
+        assert_eq!(format(source), source);
+
+        // A comment the formatter can't place keeps the statement as it is
+        let source = "f = lambda: [1,  # one\n  2]\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let source = "\"\"\"Docstring.\"\"\"\nimport os\nclass A:\n\n\n\n    x = 1\n    def f(self): pass\n    @property\n\n    def g(self): pass\nx = 1\n";
+        assert_eq!(
+            format(source),
+            "\"\"\"Docstring.\"\"\"\n\nimport os\n\n\nclass A:\n    x = 1\n\n    def f(self):\n        pass\n\n    @property\n    def g(self):\n        pass\n\n\nx = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let options = FormatOptions {
+            normalize_quotes: false,
+            ..FormatOptions::default()
+        };
+        assert_eq!(format_source("x = 'a'\n", &options).unwrap(), "x = 'a'\n");
+        assert_eq!(
+            format_source("x = 1\r\ny = 2\r\n", &FormatOptions::default()).unwrap(),
+            "x = 1\r\ny = 2\r\n"
+        );
+        assert!(matches!(
+            format_source("x = (", &FormatOptions::default()),
+            Err(FormatError::Parse(_))
+        ));
+    }
+}
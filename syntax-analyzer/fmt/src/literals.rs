@@ -0,0 +1,245 @@
+//! Keep literals the way they were written.
+//!
+//! The AST only keeps the value of a literal, so unparsing it would turn `0xFF` into `255` and
+//! re-escape every string. Instead, each literal is replaced by a name spelling its source
+//! text, which the unparser writes out as is. Only the prefix, quotes and case are normalized.
+//!
+//! The parts of an implicitly concatenated string are separated by [`CONCATENATION`], so the
+//! printer can put them on lines of their own.
+use crate::trivia::Trivia;
+use rustpython_ast::{self as ast, fold::Fold, Identifier};
+use rustpython_parser::{text_size::TextRange, Tok};
+use std::convert::Infallible;
+
+/// Separates the parts of an implicitly concatenated string. It can't occur in Python source.
+pub(crate) const CONCATENATION: char = '\0';
+
+pub(crate) struct LiteralSpelling<'a> {
+    pub trivia: &'a Trivia<'a>,
+    pub normalize_quotes: bool,
+}
+
+impl LiteralSpelling<'_> {
+    fn spell(&self, range: TextRange) -> ast::Expr {
+        let text = self
+            .trivia
+            .tokens_in(range)
+            .map(|(tok, range)| {
+                let text = self.trivia.text(*range);
+                match tok {
+                    Tok::String { .. } => normalize_string(text, self.normalize_quotes),
+                    _ => normalize_number(text),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(&CONCATENATION.to_string());
+        ast::ExprName {
+            id: Identifier::new(text),
+            ctx: ast::ExprContext::Load,
+            range,
+        }
+        .into()
+    }
+}
+
+impl Fold<TextRange> for LiteralSpelling<'_> {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) {}
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(user)
+    }
+
+    fn fold_expr(&mut self, node: ast::Expr) -> Result<ast::Expr, Infallible> {
+        match &node {
+            ast::Expr::Constant(ast::ExprConstant {
+                value:
+                    ast::Constant::Str(_)
+                    | ast::Constant::Bytes(_)
+                    | ast::Constant::Int(_)
+                    | ast::Constant::Float(_)
+                    | ast::Constant::Complex { .. },
+                range,
+                ..
+            })
+            | ast::Expr::JoinedStr(ast::ExprJoinedStr { range, .. }) => Ok(self.spell(*range)),
+            _ => ast::fold::fold_expr(self, node),
+        }
+    }
+}
+
+/// Returns true if `id` is the spelling of a string, as produced by [`LiteralSpelling`].
+pub(crate) fn is_string_spelling(id: &str) -> bool {
+    id.contains(['"', '\''])
+}
+
+/// Normalize the prefix and the quotes of a string literal.
+///
+/// The `u` prefix is removed and the others are lowercased, except for `R`. Double quotes are
+/// preferred, unless they need more escapes than the quotes in the source.
+pub(crate) fn normalize_string(text: &str, normalize_quotes: bool) -> String {
+    let quote_start = text.find(['"', '\'']).unwrap_or(text.len());
+    let (prefix, literal) = text.split_at(quote_start);
+    let prefix: String = prefix
+        .chars()
+        .filter(|c| !matches!(c, 'u' | 'U'))
+        .map(|c| match c {
+            'F' | 'B' => c.to_ascii_lowercase(),
+            _ => c,
+        })
+        .collect();
+    if !normalize_quotes {
+        return prefix + literal;
+    }
+    let requoted = requote(&prefix, literal);
+    prefix + requoted.as_deref().unwrap_or(literal)
+}
+
+fn requote(prefix: &str, literal: &str) -> Option<String> {
+    let raw = prefix.contains(['r', 'R']);
+    let fstring = prefix.contains('f');
+
+    if literal.starts_with("\"\"\"") {
+        return None;
+    }
+    if let Some(body) = literal
+        .strip_prefix("'''")
+        .and_then(|literal| literal.strip_suffix("'''"))
+    {
+        // Only switch when nothing in the body needs a second look
+        return (!body.contains('"') && !body.ends_with('\\'))
+            .then(|| format!("\"\"\"{body}\"\"\""));
+    }
+
+    let orig_quote = literal.chars().next()?;
+    let new_quote = if orig_quote == '"' { '\'' } else { '"' };
+    let body = &literal[1..literal.len() - 1];
+
+    let mut cleaned = String::with_capacity(body.len());
+    let mut requoted = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let escaped = chars.next()?;
+            if raw {
+                cleaned.extend([c, escaped]);
+                requoted.extend([c, escaped]);
+            } else if escaped == new_quote {
+                // An unnecessary escape
+                cleaned.push(escaped);
+                requoted.extend([c, escaped]);
+            } else if escaped == orig_quote {
+                cleaned.extend([c, escaped]);
+                requoted.push(escaped);
+            } else {
+                cleaned.extend([c, escaped]);
+                requoted.extend([c, escaped]);
+            }
+        } else if c == new_quote {
+            if raw {
+                // A raw string can't escape the new quote
+                return None;
+            }
+            cleaned.push(c);
+            requoted.extend(['\\', c]);
+        } else {
+            cleaned.push(c);
+            requoted.push(c);
+        }
+    }
+
+    // Quotes inside the replacement fields of an f-string can't be escaped
+    if fstring && body.contains('{') && (requoted != cleaned || cleaned != body) {
+        return None;
+    }
+
+    let cleaned_escapes = cleaned.matches('\\').count();
+    let requoted_escapes = requoted.matches('\\').count();
+    if requoted_escapes > cleaned_escapes
+        || (requoted_escapes == cleaned_escapes && orig_quote == '"')
+    {
+        Some(format!("{orig_quote}{cleaned}{orig_quote}"))
+    } else {
+        Some(format!("{new_quote}{requoted}{new_quote}"))
+    }
+}
+
+/// Normalize the case of a number literal: lowercase prefixes, exponents and suffixes, and
+/// uppercase hexadecimal digits. Leading and trailing dots get a zero.
+pub(crate) fn normalize_number(text: &str) -> String {
+    let text = text.to_ascii_lowercase();
+    if text.starts_with("0b") || text.starts_with("0o") {
+        text
+    } else if let Some(digits) = text.strip_prefix("0x") {
+        format!("0x{}", digits.to_ascii_uppercase())
+    } else if let Some((mantissa, exponent)) = text.split_once('e') {
+        let exponent = exponent.strip_prefix('+').unwrap_or(exponent);
+        format!("{}e{exponent}", normalize_float(mantissa))
+    } else if let Some(number) = text.strip_suffix('j') {
+        format!("{}j", normalize_float(number))
+    } else {
+        normalize_float(&text)
+    }
+}
+
+fn normalize_float(text: &str) -> String {
+    match text.split_once('.') {
+        Some((before, after)) => {
+            let before = if before.is_empty() { "0" } else { before };
+            let after = if after.is_empty() { "0" } else { after };
+            format!("{before}.{after}")
+        }
+        None => text.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_string() {
+        for (source, expected) in [
+            ("'hello'", "\"hello\""),
+            ("\"hello\"", "\"hello\""),
+            ("'say \"hi\"'", "'say \"hi\"'"),
+            ("'it\\'s'", "\"it's\""),
+            ("\"it\\'s\"", "\"it's\""),
+            ("'\\\"'", "'\"'"),
+            ("U'x'", "\"x\""),
+            ("Rb'\\d'", "Rb\"\\d\""),
+            ("F'{x}'", "f\"{x}\""),
+            ("f'{x[\"a\"]}'", "f'{x[\"a\"]}'"),
+            ("r'\"'", "r'\"'"),
+            ("'''doc'''", "\"\"\"doc\"\"\""),
+            ("'''say \"hi\"'''", "'''say \"hi\"'''"),
+            ("\"\"\"doc\"\"\"", "\"\"\"doc\"\"\""),
+            ("''", "\"\""),
+        ] {
+            assert_eq!(normalize_string(source, true), expected, "{source}");
+        }
+        assert_eq!(normalize_string("U'x'", false), "'x'");
+    }
+
+    #[test]
+    fn test_normalize_number() {
+        for (source, expected) in [
+            ("0XABCDEF", "0xABCDEF"),
+            ("0xdead_beef", "0xDEAD_BEEF"),
+            ("0O17", "0o17"),
+            ("0B1", "0b1"),
+            ("1E5", "1e5"),
+            ("1.5E+10", "1.5e10"),
+            ("1e-3", "1e-3"),
+            ("10J", "10j"),
+            ("1.", "1.0"),
+            (".5", "0.5"),
+            ("1_000", "1_000"),
+        ] {
+            assert_eq!(normalize_number(source), expected, "{source}");
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! `rustpython-fmt [--check] [--diff] [--line-length N] [--skip-string-normalization] [PATH...]`
+//!
+//! Formats the given files in place, and the `.py` files under the given directories. Without
+//! paths, or with `-`, standard input is formatted to standard output.
+//!
+//! The exit code is 0 on success, 1 if `--check` or `--diff` found files that would be
+//! reformatted and 123 if a file couldn't be formatted.
+use rustpython_fmt::{format_source, unified_diff, FormatOptions};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+const USAGE: &str = "\
+usage: rustpython-fmt [OPTIONS] [PATH...]
+
+Format Python source files in place. Without paths, format standard input.
+
+options:
+  --check                      don't write the files, exit with 1 if any would change
+  --diff                       don't write the files, print a diff of the changes
+  -l, --line-length N          the maximum line length (default: 88)
+  -S, --skip-string-normalization
+                               don't normalize the quotes of strings
+  -h, --help                   show this message";
+
+struct Args {
+    check: bool,
+    diff: bool,
+    options: FormatOptions,
+    paths: Vec<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        check: false,
+        diff: false,
+        options: FormatOptions::default(),
+        paths: Vec::new(),
+    };
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--check" => args.check = true,
+            "--diff" => args.diff = true,
+            "-S" | "--skip-string-normalization" => args.options.normalize_quotes = false,
+            "-l" | "--line-length" => {
+                let value = iter.next().ok_or("--line-length needs a value")?;
+                args.options.line_length = parse_line_length(&value)?;
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            _ => {
+                if let Some(value) = arg.strip_prefix("--line-length=") {
+                    args.options.line_length = parse_line_length(value)?;
+                } else if arg.starts_with('-') && arg != "-" {
+                    return Err(format!("unknown option {arg}"));
+                } else {
+                    args.paths.push(PathBuf::from(arg));
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn parse_line_length(value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid line length {value:?}"))
+}
+
+/// The Python files under `path`, sorted.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_owned());
+        return Ok(());
+    }
+    let mut entries: Vec<_> = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|ext| ext == "py") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// What happened to one file.
+enum Outcome {
+    Unchanged,
+    Changed,
+    Failed,
+}
+
+fn format_file(path: &Path, args: &Args) -> Outcome {
+    let stdin = path == Path::new("-");
+    let name = path.display().to_string();
+    let source = if stdin {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map(|_| source)
+    } else {
+        fs::read_to_string(path)
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: cannot read {name}: {error}");
+            return Outcome::Failed;
+        }
+    };
+    let formatted = match format_source(&source, &args.options) {
+        Ok(formatted) => formatted,
+        Err(error) => {
+            eprintln!("error: cannot format {name}: {error}");
+            return Outcome::Failed;
+        }
+    };
+
+    let changed = formatted != source;
+    if args.diff {
+        print!("{}", unified_diff(&source, &formatted, &name, &name));
+    } else if args.check {
+        if changed {
+            eprintln!("would reformat {name}");
+        }
+    } else if stdin {
+        let mut stdout = io::stdout().lock();
+        if let Err(error) = stdout.write_all(formatted.as_bytes()) {
+            eprintln!("error: cannot write the output: {error}");
+            return Outcome::Failed;
+        }
+    } else if changed {
+        if let Err(error) = fs::write(path, &formatted) {
+            eprintln!("error: cannot write {name}: {error}");
+            return Outcome::Failed;
+        }
+        eprintln!("reformatted {name}");
+    }
+    if changed {
+        Outcome::Changed
+    } else {
+        Outcome::Unchanged
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = match parse_args() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.paths.is_empty() {
+        args.paths.push(PathBuf::from("-"));
+    }
+
+    let mut files = Vec::new();
+    for path in &args.paths {
+        if path == Path::new("-") {
+            files.push(path.clone());
+        } else if let Err(error) = collect_files(path, &mut files) {
+            eprintln!("error: cannot read {}: {error}", path.display());
+            return ExitCode::from(123);
+        }
+    }
+
+    let (mut changed, mut failed) = (0, 0);
+    for file in &files {
+        match format_file(file, &args) {
+            Outcome::Unchanged => {}
+            Outcome::Changed => changed += 1,
+            Outcome::Failed => failed += 1,
+        }
+    }
+
+    if failed > 0 {
+        ExitCode::from(123)
+    } else if changed > 0 && (args.check || args.diff) {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,1976 @@
+//! Print a module: statements one per line, with comments and normalized blank lines.
+//!
+//! Expressions are turned into [`Doc`]s to fit them into the line width. Atoms the formatter
+//! has nothing to say about, like lambdas, are written by the [`unparse`] implementation of
+//! [`Expr`]. The comments inside a statement are in brackets, and are attached to the
+//! [`Group`] of their brackets. A statement or clause header with a comment that can't be
+//! attached, like one inside a lambda, is written as it was.
+//!
+//! [`unparse`]: rustpython_ast::Expr#impl-Display-for-Expr<U>
+use crate::{
+    doc::{self, Comments, Doc, Group, Layout, Separator},
+    literals::{is_string_spelling, CONCATENATION},
+    trivia::Trivia,
+};
+use rustpython_ast::{self as ast, Expr, Pattern, Ranged, Stmt};
+use rustpython_parser::{
+    text_size::{TextRange, TextSize},
+    Tok,
+};
+use std::cell::RefCell;
+
+/// Binding strength of operators, as in the unparser.
+mod precedence {
+    pub const TUPLE: u8 = 0;
+    pub const TEST: u8 = 1;
+    pub const OR: u8 = 2;
+    pub const AND: u8 = 3;
+    pub const NOT: u8 = 4;
+    pub const CMP: u8 = 5;
+    pub const BOR: u8 = 6;
+    pub const BXOR: u8 = 7;
+    pub const BAND: u8 = 8;
+    pub const SHIFT: u8 = 9;
+    pub const ARITH: u8 = 10;
+    pub const TERM: u8 = 11;
+    pub const FACTOR: u8 = 12;
+    pub const POWER: u8 = 13;
+    pub const AWAIT: u8 = 14;
+    pub const ATOM: u8 = 15;
+}
+
+const INDENT: usize = 4;
+
+/// What to do with the parentheses around an expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Parens {
+    /// Keep the parentheses from the source.
+    Keep,
+    /// Keep the parentheses from the source, except for one pair that belongs to the parent,
+    /// like the parentheses of a call with a single argument.
+    Owned,
+    /// Remove redundant parentheses. The statement adds them back if it has to be split.
+    Strip,
+    /// Like [`Parens::Strip`], for a value that can be a `yield` without parentheses.
+    Statement,
+}
+
+/// What a line is, to decide how many blank lines go before the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LineKind {
+    /// The first line of a function or class definition, including its decorators and
+    /// comments.
+    Def,
+    /// A decorator after the first one, or the `def` line after the decorators.
+    Decorator,
+    /// `else`, `except` and `finally` clauses.
+    Clause,
+    Import,
+    Docstring,
+    Comment,
+    Other,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    depth: usize,
+    kind: LineKind,
+}
+
+pub(crate) struct Printer<'a> {
+    trivia: &'a Trivia<'a>,
+    layout: Layout,
+    out: String,
+    /// The index of the next comment to print.
+    comment: usize,
+    previous: Option<Line>,
+    /// The depths of the definitions whose bodies are being printed.
+    defs: Vec<usize>,
+    /// For each comment, whether it was attached to the brackets around it.
+    attached: RefCell<Vec<bool>>,
+    /// The source contains the characters that mark comments in rendered lines.
+    has_markers: bool,
+}
+
+impl<'a> Printer<'a> {
+    pub(crate) fn new(trivia: &'a Trivia<'a>, line_length: usize) -> Self {
+        Printer {
+            trivia,
+            layout: Layout { line_length },
+            out: String::new(),
+            comment: 0,
+            previous: None,
+            defs: Vec::new(),
+            attached: RefCell::new(vec![false; trivia.comments().len()]),
+            has_markers: doc::has_markers(trivia.source()),
+        }
+    }
+
+    pub(crate) fn module(mut self, body: &[Stmt]) -> String {
+        if !body.is_empty() {
+            self.suite(body, 0);
+        }
+        self.comments_before(TextSize::of(self.trivia.source()), 0);
+        self.out
+    }
+
+    // Lines
+
+    fn emit(&mut self, depth: usize, kind: LineKind, offset: TextSize, text: &str) {
+        let blank_lines = self.blank_lines(depth, kind, offset);
+        for _ in 0..blank_lines {
+            self.out.push('\n');
+        }
+        for _ in 0..depth * INDENT {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.previous = Some(Line { depth, kind });
+    }
+
+    /// The number of blank lines before a line of `kind` at `offset`, following black.
+    fn blank_lines(&mut self, depth: usize, kind: LineKind, offset: TextSize) -> usize {
+        let mut after_def = false;
+        while self.defs.last().is_some_and(|&def| def >= depth) {
+            self.defs.pop();
+            after_def = true;
+        }
+        let Some(previous) = self.previous else {
+            return 0;
+        };
+        let source = self.trivia.blank_lines_before(offset);
+        let max = if depth == 0 { 2 } else { 1 };
+
+        if previous.depth < depth || previous.kind == LineKind::Decorator {
+            return 0;
+        }
+        match kind {
+            LineKind::Decorator => 0,
+            LineKind::Clause if after_def => 1,
+            LineKind::Clause => source.min(max),
+            // A comment directly above a definition took the blank lines already
+            LineKind::Def if previous.kind == LineKind::Comment && source == 0 && !after_def => 0,
+            LineKind::Def => max,
+            _ if after_def => max,
+            _ if previous.kind == LineKind::Docstring && previous.depth == 0 => 1,
+            _ if previous.kind == LineKind::Import
+                && kind != LineKind::Import
+                && previous.depth == depth =>
+            {
+                source.clamp(1, max)
+            }
+            _ => source.min(max),
+        }
+    }
+
+    fn render(&self, doc: &Doc, depth: usize) -> String {
+        let indent = depth * INDENT;
+        self.layout.render(doc, indent, indent, 0)
+    }
+
+    /// Print a simple statement, or a line like a decorator.
+    fn line(&mut self, range: TextRange, depth: usize, kind: LineKind, doc: &Doc) {
+        let text = self.render(doc, depth);
+        self.formatted(range, depth, kind, &text);
+    }
+
+    /// Print the formatted `text` of `range`, or the source if the comments in it couldn't be
+    /// placed.
+    fn formatted(&mut self, range: TextRange, depth: usize, kind: LineKind, text: &str) {
+        match self.place_comments(range, text) {
+            Some(text) => {
+                self.emit(depth, kind, range.start(), &text);
+                self.skip_comments(range.end());
+            }
+            None => self.verbatim(range, depth, kind),
+        }
+    }
+
+    /// Print `range` as it is in the source, moving its lines to the new indentation.
+    fn verbatim(&mut self, range: TextRange, depth: usize, kind: LineKind) {
+        let column = self.trivia.column(range.start());
+        let indent = depth * INDENT;
+        let mut text = String::new();
+        let mut offset = range.start();
+        for (index, line) in self.trivia.text(range).split_inclusive('\n').enumerate() {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            if index == 0 || self.trivia.in_string(offset) || spaces < column {
+                text.push_str(line);
+            } else {
+                text.push_str(&" ".repeat(spaces - column + indent));
+                text.push_str(&line[spaces..]);
+            }
+            offset += TextSize::of(line);
+        }
+        self.emit(depth, kind, range.start(), &text);
+        self.skip_comments(range.end());
+    }
+
+    // Comments
+
+    /// Mark the comments before `offset` as printed.
+    fn skip_comments(&mut self, offset: TextSize) {
+        let comments = self.trivia.comments();
+        while comments
+            .get(self.comment)
+            .is_some_and(|comment| comment.start() < offset)
+        {
+            self.comment += 1;
+        }
+    }
+
+    /// The comments in `brackets` outside of the `items` between them, which haven't been
+    /// attached to inner brackets yet. A comment on the line of the opening bracket or an item
+    /// stays on it, one on a line of its own stays before the next item.
+    fn bracket_comments(&self, brackets: TextRange, items: &[TextRange]) -> Comments {
+        let mut comments = Comments::default();
+        let all = self.trivia.comments();
+        let mut attached = self.attached.borrow_mut();
+        let start = all.partition_point(|comment| comment.start() < brackets.start());
+        for (index, &comment) in all.iter().enumerate().skip(start) {
+            if comment.start() >= brackets.end() {
+                break;
+            }
+            let next = items.partition_point(|item| item.end() <= comment.start());
+            if attached[index]
+                || items
+                    .get(next)
+                    .is_some_and(|item| item.start() < comment.start())
+            {
+                continue;
+            }
+            attached[index] = true;
+            let text = normalize_comment(self.trivia.text(comment));
+            if self.trivia.is_trailing(comment) {
+                if next == 0 {
+                    comments.open.push(text);
+                } else {
+                    comments.trailing.resize(items.len(), Vec::new());
+                    comments.trailing[next - 1].push(text);
+                }
+            } else if next < items.len() {
+                comments.leading.resize(items.len(), Vec::new());
+                comments.leading[next].push(text);
+            } else {
+                comments.dangling.push(text);
+            }
+        }
+        comments
+    }
+
+    /// Attach the comments between `operands` at `ranges`, which are inside brackets, to the
+    /// operand they end up next to when the chain is split before its operators: a comment
+    /// after an operator goes with the operand after it.
+    fn operand_comments(&self, operands: &mut [(&'static str, Doc)], ranges: &[TextRange]) {
+        let all = self.trivia.comments();
+        let mut attached = self.attached.borrow_mut();
+        for (index, pair) in ranges.windows(2).enumerate() {
+            let gap = TextRange::new(pair[0].end(), pair[1].start());
+            let start = all.partition_point(|comment| comment.start() < gap.start());
+            for (comment_index, &comment) in all.iter().enumerate().skip(start) {
+                if comment.start() >= gap.end() {
+                    break;
+                }
+                // A comment on a line of its own would need to split the chain
+                if attached[comment_index] || !self.trivia.is_trailing(comment) {
+                    continue;
+                }
+                attached[comment_index] = true;
+                let after_operator = self
+                    .trivia
+                    .token_before(comment.start())
+                    .is_some_and(|(tok, range)| *tok != Tok::Rpar && range.end() > gap.start());
+                let (_, operand) = &mut operands[index + usize::from(after_operator)];
+                let text = normalize_comment(self.trivia.text(comment));
+                *operand = Doc::concat([
+                    std::mem::replace(operand, Doc::text("")),
+                    Doc::comment(text),
+                ]);
+            }
+        }
+    }
+
+    /// Move the comments in the rendered `text` of `range` to the ends of their lines. Returns
+    /// `None` if a comment in `range` wasn't attached to its brackets, or can't be placed.
+    fn place_comments(&self, range: TextRange, text: &str) -> Option<String> {
+        if !self.trivia.has_comment(range) {
+            return Some(text.to_owned());
+        }
+        let comments = self.trivia.comments();
+        let start = comments.partition_point(|comment| comment.start() < range.start());
+        let end = comments.partition_point(|comment| comment.start() < range.end());
+        // A comment moved to the end of a line inside a string would become part of it
+        if self.has_markers
+            || self.trivia.has_multiline_string(range)
+            || !self.attached.borrow()[start..end]
+                .iter()
+                .all(|&attached| attached)
+        {
+            return None;
+        }
+        doc::place_comments(text)
+    }
+
+    fn comments_before(&mut self, offset: TextSize, depth: usize) {
+        self.leading_comments(offset, depth, false);
+    }
+
+    /// Print the comments before `offset`. Comments on their own lines directly above a
+    /// definition get the blank lines of the definition.
+    fn leading_comments(&mut self, offset: TextSize, depth: usize, def: bool) {
+        let comments = self.trivia.comments();
+        let end = self.comment
+            + comments[self.comment..].partition_point(|comment| comment.start() < offset);
+
+        let mut attached = end;
+        if def {
+            let mut next = offset;
+            while attached > self.comment {
+                let comment = comments[attached - 1];
+                if self.trivia.is_trailing(comment) || self.trivia.blank_lines_before(next) > 0 {
+                    break;
+                }
+                next = comment.start();
+                attached -= 1;
+            }
+        }
+
+        for (index, &comment) in comments.iter().enumerate().take(end).skip(self.comment) {
+            let kind = if index == attached {
+                LineKind::Def
+            } else {
+                LineKind::Comment
+            };
+            self.comment_line(comment, depth, kind);
+        }
+        self.comment = end;
+    }
+
+    fn comment_line(&mut self, comment: TextRange, depth: usize, kind: LineKind) {
+        let text = normalize_comment(self.trivia.text(comment));
+        if self.previous.is_some() && self.trivia.is_trailing(comment) {
+            self.out.pop();
+            self.out.push_str("  ");
+            self.out.push_str(&text);
+            self.out.push('\n');
+        } else {
+            self.emit(depth, kind, comment.start(), &text);
+            if kind == LineKind::Def {
+                self.previous = Some(Line {
+                    depth,
+                    kind: LineKind::Comment,
+                });
+            }
+        }
+    }
+
+    // Statements
+
+    fn suite(&mut self, body: &[Stmt], depth: usize) {
+        for (index, stmt) in body.iter().enumerate() {
+            self.stmt(stmt, depth, depth == 0 && index == 0);
+        }
+
+        // The comments after the last statement that are indented like it stay in the block
+        let column = self.trivia.column(self.outer_start(&body[0]));
+        let end = body.last().unwrap().end();
+        let limit = self
+            .trivia
+            .token_after(end)
+            .map_or(TextSize::of(self.trivia.source()), |(_, range)| {
+                range.start()
+            });
+        let comments = self.trivia.comments();
+        while let Some(&comment) = comments.get(self.comment) {
+            if comment.start() >= limit
+                || !self.trivia.is_trailing(comment) && self.trivia.column(comment.start()) < column
+            {
+                break;
+            }
+            self.comment_line(comment, depth, LineKind::Comment);
+            self.comment += 1;
+        }
+    }
+
+    /// Where a statement starts, including its decorators.
+    fn outer_start(&self, stmt: &Stmt) -> TextSize {
+        let decorators = match stmt {
+            Stmt::FunctionDef(ast::StmtFunctionDef { decorator_list, .. })
+            | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef { decorator_list, .. })
+            | Stmt::ClassDef(ast::StmtClassDef { decorator_list, .. }) => decorator_list,
+            _ => return stmt.start(),
+        };
+        decorators
+            .first()
+            .map_or(stmt.start(), |decorator| self.decorator_start(decorator))
+    }
+
+    /// The start of the `@` before `decorator`.
+    fn decorator_start(&self, decorator: &Expr) -> TextSize {
+        let mut offset = decorator.start();
+        loop {
+            match self.trivia.token_before(offset) {
+                Some((Tok::At, range)) => return range.start(),
+                Some((Tok::Lpar, range)) => offset = range.start(),
+                _ => return decorator.start(),
+            }
+        }
+    }
+
+    /// The end of the colon before `body`.
+    fn colon_end(&self, body: &[Stmt]) -> TextSize {
+        let start = self.outer_start(&body[0]);
+        self.trivia
+            .token_before(start)
+            .map_or(start, |(_, range)| range.end())
+    }
+
+    /// The start of the keyword of the clause after `offset`, like `else`.
+    fn keyword_after(&self, offset: TextSize) -> TextSize {
+        self.trivia
+            .token_after(offset)
+            .map_or(offset, |(_, range)| range.start())
+    }
+
+    /// Print a clause header and its body.
+    fn block(
+        &mut self,
+        depth: usize,
+        kind: LineKind,
+        keyword: TextSize,
+        header: String,
+        body: &[Stmt],
+    ) {
+        self.comments_before(keyword, depth);
+        let range = TextRange::new(keyword, self.colon_end(body));
+        self.formatted(range, depth, kind, &header);
+        self.suite(body, depth + 1);
+    }
+
+    fn else_block(&mut self, body: &[Stmt], after: TextSize, depth: usize, keyword: &str) {
+        if body.is_empty() {
+            return;
+        }
+        let start = self.keyword_after(after);
+        self.block(depth, LineKind::Clause, start, format!("{keyword}:"), body);
+    }
+
+    fn header(&self, depth: usize, docs: impl IntoIterator<Item = Doc>) -> String {
+        let mut doc = Doc::concat(docs);
+        doc = Doc::concat([doc, Doc::text(":")]);
+        self.render(&doc, depth)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, depth: usize, first_in_module: bool) {
+        let is_def = matches!(
+            stmt,
+            Stmt::FunctionDef(_) | Stmt::AsyncFunctionDef(_) | Stmt::ClassDef(_)
+        );
+        self.leading_comments(self.outer_start(stmt), depth, is_def);
+
+        match stmt {
+            Stmt::FunctionDef(ast::StmtFunctionDef {
+                name,
+                args,
+                body,
+                decorator_list,
+                returns,
+                type_params,
+                range,
+                ..
+            })
+            | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef {
+                name,
+                args,
+                body,
+                decorator_list,
+                returns,
+                type_params,
+                range,
+                ..
+            }) => {
+                let kind = self.decorators(decorator_list, depth);
+                let keyword = if stmt.is_async_function_def_stmt() {
+                    "async def"
+                } else {
+                    "def"
+                };
+                let mut parts = vec![Doc::text(format!("{keyword} {}", name.as_str()))];
+                parts.extend(self.type_params(type_params));
+                // The end of the closing parenthesis, before the arrow or the colon
+                let colon = self.colon_end(body);
+                let after_params = self
+                    .trivia
+                    .find(TextRange::new(range.start(), colon), &Tok::Rarrow)
+                    .map_or(colon - TextSize::new(1), |arrow| arrow.start());
+                let params_end = self
+                    .trivia
+                    .token_before(after_params)
+                    .map_or(colon, |(_, range)| range.end());
+                parts.push(self.parameters(args, params_end));
+                if let Some(returns) = returns {
+                    parts.push(Doc::text(" -> "));
+                    parts.push(self.expr(returns, precedence::TEST));
+                }
+                parts.push(Doc::text(":"));
+                let indent = depth * INDENT;
+                let header = self.layout.left_hand_split(&parts, indent);
+                self.block(depth, kind, range.start(), header, body);
+                self.defs.push(depth);
+            }
+            Stmt::ClassDef(ast::StmtClassDef {
+                name,
+                bases,
+                keywords,
+                body,
+                decorator_list,
+                type_params,
+                range,
+            }) => {
+                let kind = self.decorators(decorator_list, depth);
+                let mut parts = vec![Doc::text(format!("class {}", name.as_str()))];
+                parts.extend(self.type_params(type_params));
+                if !bases.is_empty() || !keywords.is_empty() {
+                    let end = self
+                        .trivia
+                        .token_before(self.colon_end(body) - TextSize::new(1))
+                        .map_or(range.end(), |(_, range)| range.end());
+                    let brackets = self
+                        .trivia
+                        .bracketed(end)
+                        .unwrap_or_else(|| TextRange::new(range.start(), end));
+                    parts.push(self.arguments(bases, keywords, brackets));
+                }
+                let header = self.header(depth, parts);
+                self.block(depth, kind, range.start(), header, body);
+                self.defs.push(depth);
+            }
+            Stmt::Return(ast::StmtReturn { value, range }) => {
+                let doc = match value {
+                    Some(value) => Doc::concat([
+                        Doc::text("return "),
+                        self.optional(value, precedence::TUPLE, Parens::Strip),
+                    ]),
+                    None => Doc::text("return"),
+                };
+                self.line(*range, depth, LineKind::Other, &doc);
+            }
+            Stmt::Delete(ast::StmtDelete { targets, range }) => {
+                let targets = match targets.as_slice() {
+                    [target] => self.optional(target, precedence::TUPLE, Parens::Strip),
+                    targets => Group {
+                        optional: true,
+                        hug: false,
+                        magic_trailing_comma: self.trivia.has_trailing_comma(range.end()),
+                        ..Group::new(
+                            "(",
+                            ")",
+                            targets
+                                .iter()
+                                .map(|target| self.expr(target, precedence::TEST))
+                                .collect(),
+                        )
+                    }
+                    .into(),
+                };
+                let doc = Doc::concat([Doc::text("del "), targets]);
+                self.line(*range, depth, LineKind::Other, &doc);
+            }
+            Stmt::Assign(ast::StmtAssign {
+                targets,
+                value,
+                range,
+                ..
+            }) => {
+                let mut parts = Vec::new();
+                for target in targets {
+                    parts.push(self.expr(target, precedence::TUPLE));
+                    parts.push(Doc::text(" = "));
+                }
+                parts.push(self.optional(value, precedence::TUPLE, Parens::Statement));
+                self.line(*range, depth, LineKind::Other, &Doc::concat(parts));
+            }
+            Stmt::TypeAlias(ast::StmtTypeAlias {
+                name,
+                type_params,
+                value,
+                range,
+            }) => {
+                let mut parts = vec![Doc::text("type "), self.expr(name, precedence::ATOM)];
+                parts.extend(self.type_params(type_params));
+                parts.push(Doc::text(" = "));
+                parts.push(self.optional(value, precedence::TEST, Parens::Strip));
+                self.line(*range, depth, LineKind::Other, &Doc::concat(parts));
+            }
+            Stmt::AugAssign(ast::StmtAugAssign {
+                target,
+                op,
+                value,
+                range,
+            }) => {
+                let doc = Doc::concat([
+                    self.expr(target, precedence::TUPLE),
+                    Doc::text(format!(" {}= ", operator(*op))),
+                    self.optional(value, precedence::TUPLE, Parens::Statement),
+                ]);
+                self.line(*range, depth, LineKind::Other, &doc);
+            }
+            Stmt::AnnAssign(ast::StmtAnnAssign {
+                target,
+                annotation,
+                value,
+                range,
+                ..
+            }) => {
+                let mut parts = vec![
+                    self.expr(target, precedence::TUPLE),
+                    Doc::text(": "),
+                    self.expr(annotation, precedence::TEST),
+                ];
+                if let Some(value) = value {
+                    parts.push(Doc::text(" = "));
+                    parts.push(self.optional(value, precedence::TUPLE, Parens::Statement));
+                }
+                self.line(*range, depth, LineKind::Other, &Doc::concat(parts));
+            }
+            Stmt::For(ast::StmtFor {
+                target,
+                iter,
+                body,
+                orelse,
+                range,
+                ..
+            })
+            | Stmt::AsyncFor(ast::StmtAsyncFor {
+                target,
+                iter,
+                body,
+                orelse,
+                range,
+                ..
+            }) => {
+                let keyword = if stmt.is_async_for_stmt() {
+                    "async for "
+                } else {
+                    "for "
+                };
+                let header = self.header(
+                    depth,
+                    [
+                        Doc::text(keyword),
+                        self.optional(target, precedence::TUPLE, Parens::Strip),
+                        Doc::text(" in "),
+                        self.optional(iter, precedence::TUPLE, Parens::Strip),
+                    ],
+                );
+                self.block(depth, LineKind::Other, range.start(), header, body);
+                self.else_block(orelse, body.last().unwrap().end(), depth, "else");
+            }
+            Stmt::While(ast::StmtWhile {
+                test,
+                body,
+                orelse,
+                range,
+            }) => {
+                let header = self.header(
+                    depth,
+                    [
+                        Doc::text("while "),
+                        self.optional(test, precedence::TEST, Parens::Strip),
+                    ],
+                );
+                self.block(depth, LineKind::Other, range.start(), header, body);
+                self.else_block(orelse, body.last().unwrap().end(), depth, "else");
+            }
+            Stmt::If(stmt) => self.if_stmt(stmt, depth, "if", LineKind::Other),
+            Stmt::With(ast::StmtWith {
+                items, body, range, ..
+            })
+            | Stmt::AsyncWith(ast::StmtAsyncWith {
+                items, body, range, ..
+            }) => {
+                let keyword = if stmt.is_async_with_stmt() {
+                    "async with "
+                } else {
+                    "with "
+                };
+                let items = self.with_items(items, range.start(), self.colon_end(body));
+                let header = self.header(depth, [Doc::text(keyword), items]);
+                self.block(depth, LineKind::Other, range.start(), header, body);
+            }
+            Stmt::Match(ast::StmtMatch {
+                subject,
+                cases,
+                range,
+            }) => {
+                let header = self.header(
+                    depth,
+                    [
+                        Doc::text("match "),
+                        self.optional(subject, precedence::TUPLE, Parens::Strip),
+                    ],
+                );
+                let mut after = subject.end();
+                let mut keywords = Vec::with_capacity(cases.len());
+                for case in cases {
+                    let start = self
+                        .trivia
+                        .find(TextRange::new(after, case.pattern.start()), &Tok::Case)
+                        .map_or(case.pattern.start(), |range| range.start());
+                    keywords.push(start);
+                    after = case.body.last().unwrap().end();
+                }
+                let colon = self
+                    .trivia
+                    .token_before(keywords[0])
+                    .map_or(keywords[0], |(_, range)| range.end());
+                let header_range = TextRange::new(range.start(), colon);
+                self.formatted(header_range, depth, LineKind::Other, &header);
+                for (case, keyword) in cases.iter().zip(keywords) {
+                    let mut parts = vec![Doc::text("case "), self.pattern(&case.pattern)];
+                    if let Some(guard) = &case.guard {
+                        parts.push(Doc::text(" if "));
+                        parts.push(self.optional(guard, precedence::TEST, Parens::Strip));
+                    }
+                    let header = self.header(depth + 1, parts);
+                    self.block(depth + 1, LineKind::Other, keyword, header, &case.body);
+                }
+            }
+            Stmt::Raise(ast::StmtRaise { exc, cause, range }) => {
+                let mut parts = vec![Doc::text("raise")];
+                if let Some(exc) = exc {
+                    parts.push(Doc::text(" "));
+                    parts.push(self.optional(exc, precedence::TEST, Parens::Strip));
+                }
+                if let Some(cause) = cause {
+                    parts.push(Doc::text(" from "));
+                    parts.push(self.optional(cause, precedence::TEST, Parens::Strip));
+                }
+                self.line(*range, depth, LineKind::Other, &Doc::concat(parts));
+            }
+            Stmt::Try(ast::StmtTry {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                range,
+            })
+            | Stmt::TryStar(ast::StmtTryStar {
+                body,
+                handlers,
+                orelse,
+                finalbody,
+                range,
+            }) => {
+                let star = if stmt.is_try_star_stmt() { "*" } else { "" };
+                self.block(
+                    depth,
+                    LineKind::Other,
+                    range.start(),
+                    "try:".to_owned(),
+                    body,
+                );
+                let mut after = body.last().unwrap().end();
+                for handler in handlers {
+                    let ast::ExceptHandler::ExceptHandler(ast::ExceptHandlerExceptHandler {
+                        type_,
+                        name,
+                        body,
+                        range,
+                    }) = handler;
+                    let mut parts = vec![Doc::text(format!("except{star}"))];
+                    if let Some(type_) = type_ {
+                        parts.push(Doc::text(" "));
+                        parts.push(self.expr(type_, precedence::TEST));
+                    }
+                    if let Some(name) = name {
+                        parts.push(Doc::text(format!(" as {}", name.as_str())));
+                    }
+                    let header = self.header(depth, parts);
+                    self.block(depth, LineKind::Clause, range.start(), header, body);
+                    after = body.last().unwrap().end();
+                }
+                self.else_block(orelse, after, depth, "else");
+                if let Some(last) = orelse.last() {
+                    after = last.end();
+                }
+                self.else_block(finalbody, after, depth, "finally");
+            }
+            Stmt::Assert(ast::StmtAssert { test, msg, range }) => {
+                let mut parts = vec![
+                    Doc::text("assert "),
+                    self.optional(test, precedence::TEST, Parens::Strip),
+                ];
+                if let Some(msg) = msg {
+                    parts.push(Doc::text(", "));
+                    parts.push(self.optional(msg, precedence::TEST, Parens::Strip));
+                }
+                self.line(*range, depth, LineKind::Other, &Doc::concat(parts));
+            }
+            Stmt::Import(ast::StmtImport { names, range }) => {
+                let names: Vec<_> = names.iter().map(alias).collect();
+                let doc = Doc::text(format!("import {}", names.join(", ")));
+                self.line(*range, depth, LineKind::Import, &doc);
+            }
+            Stmt::ImportFrom(ast::StmtImportFrom {
+                module,
+                names,
+                level,
+                range,
+            }) => {
+                let dots = ".".repeat(level.map_or(0, |level| level.to_u32() as usize));
+                let module = module.as_ref().map_or("", |module| module.as_str());
+                let names = match names.as_slice() {
+                    [name] if name.name.as_str() == "*" => Doc::text("*"),
+                    names => Group {
+                        optional: true,
+                        hug: false,
+                        magic_trailing_comma: self.trivia.has_trailing_comma(range.end()),
+                        comments: match self.trivia.find(*range, &Tok::Lpar) {
+                            Some(lpar) => {
+                                let ranges: Vec<_> = names.iter().map(|name| name.range).collect();
+                                self.bracket_comments(
+                                    TextRange::new(lpar.start(), range.end()),
+                                    &ranges,
+                                )
+                            }
+                            None => Comments::default(),
+                        },
+                        ..Group::new(
+                            "(",
+                            ")",
+                            names.iter().map(|a| Doc::text(alias(a))).collect(),
+                        )
+                    }
+                    .into(),
+                };
+                let doc = Doc::concat([Doc::text(format!("from {dots}{module} import ")), names]);
+                self.line(*range, depth, LineKind::Import, &doc);
+            }
+            Stmt::Global(ast::StmtGlobal { names, range })
+            | Stmt::Nonlocal(ast::StmtNonlocal { names, range }) => {
+                let keyword = if stmt.is_global_stmt() {
+                    "global"
+                } else {
+                    "nonlocal"
+                };
+                let names: Vec<_> = names.iter().map(|name| name.as_str()).collect();
+                let doc = Doc::text(format!("{keyword} {}", names.join(", ")));
+                self.line(*range, depth, LineKind::Other, &doc);
+            }
+            Stmt::Expr(ast::StmtExpr { value, range }) => {
+                let kind = match value.as_ref() {
+                    Expr::Name(ast::ExprName { id, .. })
+                        if first_in_module && is_string_spelling(id.as_str()) =>
+                    {
+                        LineKind::Docstring
+                    }
+                    _ => LineKind::Other,
+                };
+                let doc = self.expr_with(value, precedence::TUPLE, Parens::Statement);
+                self.line(*range, depth, kind, &doc);
+            }
+            Stmt::Pass(ast::StmtPass { range }) => {
+                self.line(*range, depth, LineKind::Other, &Doc::text("pass"));
+            }
+            Stmt::Break(ast::StmtBreak { range }) => {
+                self.line(*range, depth, LineKind::Other, &Doc::text("break"));
+            }
+            Stmt::Continue(ast::StmtContinue { range }) => {
+                self.line(*range, depth, LineKind::Other, &Doc::text("continue"));
+            }
+            Stmt::Invalid(ast::StmtInvalid { range }) => {
+                self.verbatim(*range, depth, LineKind::Other);
+            }
+        }
+    }
+
+    fn if_stmt(&mut self, stmt: &ast::StmtIf, depth: usize, keyword: &str, kind: LineKind) {
+        let header = self.header(
+            depth,
+            [
+                Doc::text(format!("{keyword} ")),
+                self.optional(&stmt.test, precedence::TEST, Parens::Strip),
+            ],
+        );
+        self.block(depth, kind, stmt.range.start(), header, &stmt.body);
+        match stmt.orelse.as_slice() {
+            [Stmt::If(elif)]
+                if matches!(
+                    self.trivia.token_after(elif.range.start()),
+                    Some((Tok::Elif, _))
+                ) =>
+            {
+                self.if_stmt(elif, depth, "elif", LineKind::Clause);
+            }
+            orelse => {
+                self.else_block(orelse, stmt.body.last().unwrap().end(), depth, "else");
+            }
+        }
+    }
+
+    /// Print the decorators of a definition, returning the kind of the line that follows.
+    fn decorators(&mut self, decorators: &[Expr], depth: usize) -> LineKind {
+        for (index, decorator) in decorators.iter().enumerate() {
+            let start = self.decorator_start(decorator);
+            self.comments_before(start, depth);
+            let doc = Doc::concat([Doc::text("@"), self.expr(decorator, precedence::TEST)]);
+            let kind = if index == 0 {
+                LineKind::Def
+            } else {
+                LineKind::Decorator
+            };
+            self.line(TextRange::new(start, decorator.end()), depth, kind, &doc);
+        }
+        if decorators.is_empty() {
+            LineKind::Def
+        } else {
+            LineKind::Decorator
+        }
+    }
+
+    fn type_params(&self, type_params: &[ast::TypeParam]) -> Option<Doc> {
+        if type_params.is_empty() {
+            return None;
+        }
+        let items = type_params
+            .iter()
+            .map(|type_param| match type_param {
+                ast::TypeParam::TypeVar(ast::TypeParamTypeVar { name, bound, .. }) => match bound {
+                    Some(bound) => Doc::concat([
+                        Doc::text(format!("{}: ", name.as_str())),
+                        self.expr(bound, precedence::TEST),
+                    ]),
+                    None => Doc::text(name.as_str()),
+                },
+                ast::TypeParam::ParamSpec(ast::TypeParamParamSpec { name, .. }) => {
+                    Doc::text(format!("**{}", name.as_str()))
+                }
+                ast::TypeParam::TypeVarTuple(ast::TypeParamTypeVarTuple { name, .. }) => {
+                    Doc::text(format!("*{}", name.as_str()))
+                }
+            })
+            .collect();
+        let end = type_params.last().unwrap().end();
+        let close = self.trivia.token_after(end).map_or(end, |(tok, range)| {
+            if *tok == Tok::Comma {
+                range.end() + TextSize::new(1)
+            } else {
+                range.end()
+            }
+        });
+        let ranges: Vec<_> = type_params.iter().map(Ranged::range).collect();
+        let brackets = self
+            .trivia
+            .token_before(ranges[0].start())
+            .and_then(|(_, open)| Some(open.cover(self.trivia.matching_bracket(*open)?)))
+            .unwrap_or_else(|| TextRange::new(ranges[0].start(), end));
+        Some(
+            Group {
+                magic_trailing_comma: self.trivia.has_trailing_comma(close),
+                comments: self.bracket_comments(brackets, &ranges),
+                ..Group::new("[", "]", items)
+            }
+            .into(),
+        )
+    }
+
+    /// The parameters of a function definition, whose closing parenthesis ends at `end`.
+    fn parameters(&self, args: &ast::Arguments, end: TextSize) -> Doc {
+        let brackets = self
+            .trivia
+            .bracketed(end)
+            .unwrap_or_else(|| TextRange::empty(end));
+        let mut items = Vec::new();
+        let mut ranges = Vec::new();
+        // The range of the `/` or `*` after the last parameter
+        let separator = |ranges: &[TextRange], tok: &Tok| {
+            let after = ranges.last().map_or(brackets.start(), |range| range.end());
+            self.trivia
+                .find(TextRange::new(after, brackets.end()), tok)
+                .unwrap_or_else(|| TextRange::empty(after))
+        };
+        for (index, arg) in args.posonlyargs.iter().chain(&args.args).enumerate() {
+            items.push(self.parameter(&arg.def, "", arg.default.as_deref()));
+            let end = arg
+                .default
+                .as_ref()
+                .map_or(arg.def.end(), |default| default.end());
+            ranges.push(TextRange::new(arg.def.start(), end));
+            if index + 1 == args.posonlyargs.len() {
+                items.push(Doc::text("/"));
+                ranges.push(separator(&ranges, &Tok::Slash));
+            }
+        }
+        if let Some(vararg) = &args.vararg {
+            items.push(self.parameter(vararg, "*", None));
+            ranges.push(self.unpacked(vararg.range()));
+        } else if !args.kwonlyargs.is_empty() {
+            items.push(Doc::text("*"));
+            ranges.push(separator(&ranges, &Tok::Star));
+        }
+        for arg in &args.kwonlyargs {
+            items.push(self.parameter(&arg.def, "", arg.default.as_deref()));
+            let end = arg
+                .default
+                .as_ref()
+                .map_or(arg.def.end(), |default| default.end());
+            ranges.push(TextRange::new(arg.def.start(), end));
+        }
+        if let Some(kwarg) = &args.kwarg {
+            items.push(self.parameter(kwarg, "**", None));
+            ranges.push(self.unpacked(kwarg.range()));
+        }
+        let magic_trailing_comma = !items.is_empty() && self.trivia.has_trailing_comma(end);
+        Group {
+            magic_trailing_comma,
+            comments: self.bracket_comments(brackets, &ranges),
+            ..Group::new("(", ")", items)
+        }
+        .into()
+    }
+
+    fn parameter(&self, arg: &ast::Arg, prefix: &str, default: Option<&Expr>) -> Doc {
+        let mut parts = vec![Doc::text(format!("{prefix}{}", arg.arg.as_str()))];
+        if let Some(annotation) = &arg.annotation {
+            parts.push(Doc::text(": "));
+            parts.push(self.expr(annotation, precedence::TEST));
+        }
+        if let Some(default) = default {
+            let equal = if arg.annotation.is_some() { " = " } else { "=" };
+            parts.push(Doc::text(equal));
+            parts.push(self.expr(default, precedence::TEST));
+        }
+        Doc::concat(parts)
+    }
+
+    fn with_items(&self, items: &[ast::WithItem], start: TextSize, colon: TextSize) -> Doc {
+        let docs: Vec<_> = items
+            .iter()
+            .map(|item| {
+                let context = self.expr(&item.context_expr, precedence::TEST);
+                match &item.optional_vars {
+                    Some(vars) => Doc::concat([
+                        context,
+                        Doc::text(" as "),
+                        self.expr(vars, precedence::TUPLE),
+                    ]),
+                    None => context,
+                }
+            })
+            .collect();
+
+        // `with (a as b, c):`
+        let first = self.trivia.token_after(start + TextSize::new(1));
+        let parenthesized = items.len() > 1 || items[0].optional_vars.is_some();
+        if let Some((_, open)) = first.filter(|(tok, _)| *tok == Tok::Lpar) {
+            let close = self.trivia.matching_bracket(*open);
+            let before_colon = self.trivia.token_before(colon - TextSize::new(1));
+            if let Some(close) = close.filter(|&close| {
+                parenthesized
+                    && Some(close) == before_colon.map(|(_, range)| *range)
+                    && open.start() < items[0].context_expr.start()
+                    && self.trivia.parentheses(items[0].context_expr.range()) == 0
+            }) {
+                let ranges: Vec<_> = items
+                    .iter()
+                    .map(|item| {
+                        let end = item
+                            .optional_vars
+                            .as_ref()
+                            .map_or(item.context_expr.end(), |vars| vars.end());
+                        TextRange::new(item.context_expr.start(), end)
+                    })
+                    .collect();
+                return Group {
+                    hug: false,
+                    magic_trailing_comma: self.trivia.has_trailing_comma(close.end()),
+                    comments: self.bracket_comments(open.cover(close), &ranges),
+                    ..Group::new("(", ")", docs)
+                }
+                .into();
+            }
+        }
+
+        let mut parts = Vec::new();
+        for (index, doc) in docs.into_iter().enumerate() {
+            if index > 0 {
+                parts.push(Doc::text(", "));
+            }
+            parts.push(doc);
+        }
+        Doc::concat(parts)
+    }
+
+    // Expressions
+
+    fn expr(&self, expr: &Expr, level: u8) -> Doc {
+        self.expr_with(expr, level, Parens::Keep)
+    }
+
+    /// An expression in parentheses that are only written if it has to be split.
+    fn optional(&self, expr: &Expr, level: u8, parens: Parens) -> Doc {
+        Doc::optional_parentheses(self.expr_with(expr, level, parens))
+    }
+
+    fn expr_with(&self, expr: &Expr, level: u8, parens: Parens) -> Doc {
+        let source = self
+            .trivia
+            .parentheses(expr.range())
+            .saturating_sub(usize::from(parens == Parens::Owned));
+        let strip = matches!(parens, Parens::Strip | Parens::Statement);
+        let parenthesized = self.trivia.with_parentheses(expr.range(), source);
+        let parenthesize = |doc| {
+            Doc::from(Group {
+                comments: self.bracket_comments(parenthesized, &[expr.range()]),
+                ..Group::new("(", ")", vec![doc])
+            })
+        };
+
+        match expr {
+            Expr::Tuple(tuple) => return self.tuple(tuple, level, strip),
+            Expr::GeneratorExp(_) => return self.expr_inner(expr),
+            Expr::NamedExpr(_) if source > 0 || level > precedence::TEST => {
+                return parenthesize(self.expr_inner(expr));
+            }
+            Expr::NamedExpr(_) => return self.expr_inner(expr),
+            Expr::Yield(_) | Expr::YieldFrom(_) => {
+                return if parens == Parens::Statement && source == 0 {
+                    self.expr_inner(expr)
+                } else {
+                    parenthesize(self.expr_inner(expr))
+                };
+            }
+            _ => {}
+        }
+
+        let doc = self.expr_inner(expr);
+        if level > precedence_of(expr) || (source > 0 && !strip) {
+            return parenthesize(doc);
+        }
+        if source == 0 {
+            return doc;
+        }
+        // Comments keep the stripped parentheses, which are written if the line is split
+        let comments = self.bracket_comments(parenthesized, &[expr.range()]);
+        if comments.is_empty() {
+            doc
+        } else {
+            Group {
+                optional: true,
+                comments,
+                ..Group::new("(", ")", vec![doc])
+            }
+            .into()
+        }
+    }
+
+    fn tuple(&self, tuple: &ast::ExprTuple, level: u8, strip: bool) -> Doc {
+        if tuple.elts.is_empty() {
+            return Doc::text("()");
+        }
+        let items: Vec<_> = tuple
+            .elts
+            .iter()
+            .map(|elt| self.expr(elt, precedence::TEST))
+            .collect();
+        let one_tuple = items.len() == 1;
+        let enclosed = self.trivia.is_enclosed(tuple.range);
+        let comments = if enclosed {
+            let ranges: Vec<_> = tuple.elts.iter().map(Ranged::range).collect();
+            self.bracket_comments(tuple.range, &ranges)
+        } else {
+            Comments::default()
+        };
+        if !enclosed && level == precedence::TUPLE && !strip {
+            let mut parts = Vec::new();
+            for (index, item) in items.into_iter().enumerate() {
+                if index > 0 {
+                    parts.push(Doc::text(", "));
+                }
+                parts.push(item);
+            }
+            if one_tuple {
+                parts.push(Doc::text(","));
+            }
+            return Doc::concat(parts);
+        }
+        Group {
+            optional: !enclosed && level == precedence::TUPLE,
+            one_tuple,
+            hug: false,
+            magic_trailing_comma: !one_tuple && self.trivia.has_trailing_comma(tuple.range.end()),
+            comments,
+            ..Group::new("(", ")", items)
+        }
+        .into()
+    }
+
+    /// An expression, without the parentheses around it.
+    fn expr_inner(&self, expr: &Expr) -> Doc {
+        use precedence::*;
+        match expr {
+            Expr::BoolOp(ast::ExprBoolOp { op, values, .. }) => {
+                let (operator, level) = match op {
+                    ast::BoolOp::And => ("and", AND),
+                    ast::BoolOp::Or => ("or", OR),
+                };
+                let mut operands: Vec<_> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(index, value)| {
+                        let operator = if index == 0 { "" } else { operator };
+                        (operator, self.expr(value, level + 1))
+                    })
+                    .collect();
+                let ranges: Vec<_> = values.iter().map(Ranged::range).collect();
+                self.operand_comments(&mut operands, &ranges);
+                Doc::Chain(operands)
+            }
+            Expr::NamedExpr(ast::ExprNamedExpr { target, value, .. }) => Doc::concat([
+                self.expr(target, ATOM),
+                Doc::text(" := "),
+                self.expr(value, TEST),
+            ]),
+            Expr::BinOp(binop) => self.binop(binop),
+            Expr::UnaryOp(ast::ExprUnaryOp { op, operand, .. }) => {
+                let (operator, level) = match op {
+                    ast::UnaryOp::Invert => ("~", FACTOR),
+                    ast::UnaryOp::Not => ("not ", NOT),
+                    ast::UnaryOp::UAdd => ("+", FACTOR),
+                    ast::UnaryOp::USub => ("-", FACTOR),
+                };
+                Doc::concat([Doc::text(operator), self.expr(operand, level)])
+            }
+            Expr::Lambda(_) => Doc::text(expr.to_string().replace(CONCATENATION, " ")),
+            Expr::IfExp(ast::ExprIfExp {
+                test, body, orelse, ..
+            }) => {
+                let mut operands = vec![
+                    ("", self.expr(body, TEST + 1)),
+                    ("if", self.expr(test, TEST + 1)),
+                    ("else", self.expr(orelse, TEST)),
+                ];
+                self.operand_comments(&mut operands, &[body.range(), test.range(), orelse.range()]);
+                Doc::Chain(operands)
+            }
+            Expr::Dict(ast::ExprDict {
+                keys,
+                values,
+                range,
+            }) => {
+                let unpacked = |value: &Expr| {
+                    (
+                        self.unpacked(value.range()),
+                        Doc::concat([Doc::text("**"), self.expr(value, BOR)]),
+                    )
+                };
+                let items: Vec<_> = keys
+                    .iter()
+                    .zip(values)
+                    .map(|(key, value)| match key {
+                        Some(key) => (
+                            TextRange::new(key.start(), value.end()),
+                            Doc::concat([
+                                self.expr(key, TEST),
+                                Doc::text(": "),
+                                self.expr(value, TEST),
+                            ]),
+                        ),
+                        None => unpacked(value),
+                    })
+                    .chain(values[keys.len().min(values.len())..].iter().map(unpacked))
+                    .collect();
+                self.collection("{", "}", items, *range)
+            }
+            Expr::Set(ast::ExprSet { elts, range }) => {
+                let items = elts
+                    .iter()
+                    .map(|elt| (elt.range(), self.expr(elt, TEST)))
+                    .collect();
+                self.collection("{", "}", items, *range)
+            }
+            Expr::List(ast::ExprList { elts, range, .. }) => {
+                let items = elts
+                    .iter()
+                    .map(|elt| (elt.range(), self.expr(elt, TEST)))
+                    .collect();
+                self.collection("[", "]", items, *range)
+            }
+            Expr::ListComp(ast::ExprListComp {
+                elt,
+                generators,
+                range,
+            }) => {
+                let elt = (elt.range(), self.expr(elt, TEST));
+                self.comprehension("[", "]", elt, generators, *range)
+            }
+            Expr::SetComp(ast::ExprSetComp {
+                elt,
+                generators,
+                range,
+            }) => {
+                let elt = (elt.range(), self.expr(elt, TEST));
+                self.comprehension("{", "}", elt, generators, *range)
+            }
+            Expr::DictComp(ast::ExprDictComp {
+                key,
+                value,
+                generators,
+                range,
+            }) => {
+                let elt = Doc::concat([
+                    self.expr(key, TEST),
+                    Doc::text(": "),
+                    self.expr(value, TEST),
+                ]);
+                let elt = (TextRange::new(key.start(), value.end()), elt);
+                self.comprehension("{", "}", elt, generators, *range)
+            }
+            Expr::GeneratorExp(ast::ExprGeneratorExp {
+                elt,
+                generators,
+                range,
+            }) => {
+                let elt = (elt.range(), self.expr(elt, TEST));
+                self.comprehension("(", ")", elt, generators, *range)
+            }
+            Expr::Await(ast::ExprAwait { value, .. }) => {
+                Doc::concat([Doc::text("await "), self.expr(value, ATOM)])
+            }
+            Expr::Yield(ast::ExprYield { value, .. }) => match value {
+                Some(value) => Doc::concat([Doc::text("yield "), self.expr(value, TUPLE)]),
+                None => Doc::text("yield"),
+            },
+            Expr::YieldFrom(ast::ExprYieldFrom { value, .. }) => {
+                Doc::concat([Doc::text("yield from "), self.expr(value, TEST)])
+            }
+            Expr::Compare(ast::ExprCompare {
+                left,
+                ops,
+                comparators,
+                ..
+            }) => {
+                let mut operands = vec![("", self.expr(left, CMP + 1))];
+                let mut ranges = vec![left.range()];
+                for (op, comparator) in ops.iter().zip(comparators) {
+                    operands.push((op.as_str(), self.expr(comparator, CMP + 1)));
+                    ranges.push(comparator.range());
+                }
+                self.operand_comments(&mut operands, &ranges);
+                chain(operands)
+            }
+            Expr::Call(ast::ExprCall {
+                func,
+                args,
+                keywords,
+                range,
+            }) => {
+                let brackets = self.trivia.bracketed(range.end()).unwrap_or(*range);
+                let arguments = self.arguments(args, keywords, brackets);
+                self.expr(func, ATOM)
+                    .map_last_string(|func| Doc::concat([func, arguments]))
+            }
+            Expr::Attribute(ast::ExprAttribute { value, attr, .. }) => {
+                // `1.real` would be a float
+                let period = match value.as_ref() {
+                    Expr::Name(ast::ExprName { id, .. })
+                        if is_int_spelling(id.as_str())
+                            && self.trivia.parentheses(value.range()) == 0 =>
+                    {
+                        " ."
+                    }
+                    _ => ".",
+                };
+                let attr = Doc::text(format!("{period}{}", attr.as_str()));
+                self.expr(value, ATOM)
+                    .map_last_string(|value| Doc::concat([value, attr]))
+            }
+            Expr::Subscript(ast::ExprSubscript {
+                value,
+                slice,
+                range,
+                ..
+            }) => {
+                let brackets = self.trivia.bracketed(range.end()).unwrap_or(*range);
+                let slice = match slice.as_ref() {
+                    Expr::Tuple(tuple)
+                        if !tuple.elts.is_empty() && !self.trivia.is_enclosed(tuple.range) =>
+                    {
+                        let items: Vec<_> = tuple
+                            .elts
+                            .iter()
+                            .map(|elt| self.slice_item(elt, TEST))
+                            .collect();
+                        let ranges: Vec<_> = tuple.elts.iter().map(Ranged::range).collect();
+                        Group {
+                            one_tuple: items.len() == 1,
+                            magic_trailing_comma: items.len() > 1
+                                && self.trivia.has_trailing_comma(range.end()),
+                            comments: self.bracket_comments(brackets, &ranges),
+                            ..Group::new("[", "]", items)
+                        }
+                    }
+                    slice => Group {
+                        comments: self.bracket_comments(brackets, &[slice.range()]),
+                        ..Group::new("[", "]", vec![self.slice_item(slice, TUPLE)])
+                    },
+                };
+                self.expr(value, ATOM)
+                    .map_last_string(|value| Doc::concat([value, slice.into()]))
+            }
+            Expr::Starred(ast::ExprStarred { value, .. }) => {
+                Doc::concat([Doc::text("*"), self.expr(value, BOR)])
+            }
+            Expr::Name(ast::ExprName { id, .. }) if id.contains(CONCATENATION) => Doc::Chain(
+                id.split(CONCATENATION)
+                    .map(|part| ("", Doc::text(part)))
+                    .collect(),
+            ),
+            Expr::Name(ast::ExprName { id, .. }) => Doc::text(id.as_str()),
+            Expr::Tuple(tuple) => self.tuple(tuple, TUPLE, false),
+            Expr::Slice(slice) => self.slice(slice),
+            Expr::Constant(_) | Expr::JoinedStr(_) | Expr::FormattedValue(_) | Expr::Invalid(_) => {
+                Doc::text(expr.to_string())
+            }
+        }
+    }
+
+    fn binop(&self, binop: &ast::ExprBinOp) -> Doc {
+        let level = operator_precedence(binop.op);
+        if binop.op == ast::Operator::Pow {
+            let left = self.expr(&binop.left, level + 1);
+            let right = self.expr(&binop.right, level);
+            let mut operands = vec![("", left), ("**", right)];
+            self.operand_comments(&mut operands, &[binop.left.range(), binop.right.range()]);
+            let [(_, left), (_, right)] = <[_; 2]>::try_from(operands).unwrap();
+            // `x**2`, but `f(x) ** 2`
+            return if is_simple_power_operand(&binop.left) && is_simple_power_operand(&binop.right)
+            {
+                Doc::concat([left, Doc::text("**"), right])
+            } else {
+                Doc::Chain(vec![("", left), ("**", right)])
+            };
+        }
+
+        // Flatten `a + b - c` into a single chain
+        let mut operands = Vec::new();
+        let mut ranges = Vec::new();
+        let mut current = binop;
+        loop {
+            operands.push((operator(current.op), self.expr(&current.right, level + 1)));
+            ranges.push(current.right.range());
+            match current.left.as_ref() {
+                Expr::BinOp(left)
+                    if operator_precedence(left.op) == level
+                        && left.op != ast::Operator::Pow
+                        && self.trivia.parentheses(left.range) == 0 =>
+                {
+                    current = left;
+                }
+                left => {
+                    operands.push(("", self.expr(left, level)));
+                    ranges.push(left.range());
+                    break;
+                }
+            }
+        }
+        operands.reverse();
+        ranges.reverse();
+        self.operand_comments(&mut operands, &ranges);
+        chain(operands)
+    }
+
+    fn slice_item(&self, expr: &Expr, level: u8) -> Doc {
+        match expr {
+            Expr::Slice(slice) => self.slice(slice),
+            expr => self.expr(expr, level),
+        }
+    }
+
+    /// A slice, with spaces around the colons if its bounds aren't simple, like
+    /// `ham[lower + offset : upper + offset]`.
+    fn slice(&self, slice: &ast::ExprSlice) -> Doc {
+        let bounds = [&slice.lower, &slice.upper, &slice.step];
+        let complex = bounds
+            .iter()
+            .filter_map(|bound| bound.as_deref())
+            .any(|bound| !is_simple_slice_bound(bound));
+        let bound = |bound: &Option<Box<Expr>>| {
+            bound
+                .as_deref()
+                .map(|bound| self.expr(bound, precedence::TEST))
+        };
+        let colon = |colon: &str, before: bool, after: bool| {
+            if complex {
+                format!(
+                    "{}{colon}{}",
+                    if before { " " } else { "" },
+                    if after { " " } else { "" }
+                )
+            } else {
+                colon.to_owned()
+            }
+        };
+
+        let mut parts = Vec::new();
+        let lower = bound(&slice.lower);
+        let has_lower = lower.is_some();
+        parts.extend(lower);
+        match (bound(&slice.upper), bound(&slice.step)) {
+            (None, Some(step)) => {
+                parts.push(Doc::text(colon("::", has_lower, true)));
+                parts.push(step);
+            }
+            (upper, step) => {
+                parts.push(Doc::text(colon(":", has_lower, upper.is_some())));
+                let has_upper = upper.is_some();
+                parts.extend(upper);
+                if let Some(step) = step {
+                    parts.push(Doc::text(colon(":", has_upper, true)));
+                    parts.push(step);
+                } else if slice.step.is_none() && self.has_second_colon(slice) {
+                    parts.push(Doc::text(":"));
+                }
+            }
+        }
+        Doc::concat(parts)
+    }
+
+    /// Returns true for slices like `x[a:b:]`, which keep their second colon.
+    fn has_second_colon(&self, slice: &ast::ExprSlice) -> bool {
+        let colons = self
+            .trivia
+            .tokens_in(slice.range)
+            .filter(|(tok, _)| *tok == Tok::Colon)
+            .count();
+        colons > 1
+            && match &slice.upper {
+                Some(upper) => {
+                    self.trivia.token_after(upper.end()).map(|(tok, _)| tok) == Some(&Tok::Colon)
+                }
+                None => true,
+            }
+    }
+
+    /// A literal collection or the items of a pattern, between the brackets at `range`.
+    fn collection(
+        &self,
+        open: &'static str,
+        close: &'static str,
+        items: Vec<(TextRange, Doc)>,
+        range: TextRange,
+    ) -> Doc {
+        let (ranges, items): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+        let magic_trailing_comma = !items.is_empty() && self.trivia.has_trailing_comma(range.end());
+        Group {
+            hug: false,
+            magic_trailing_comma,
+            comments: self.bracket_comments(range, &ranges),
+            ..Group::new(open, close, items)
+        }
+        .into()
+    }
+
+    /// A comprehension between the brackets at `range`.
+    fn comprehension(
+        &self,
+        open: &'static str,
+        close: &'static str,
+        (elt_range, elt): (TextRange, Doc),
+        generators: &[ast::Comprehension],
+        range: TextRange,
+    ) -> Doc {
+        let mut items = vec![elt];
+        let mut ranges = vec![elt_range];
+        // The range of a clause starts at its keyword
+        let clause = |ranges: &[TextRange], keyword: &Tok, expr: &Expr, end: TextSize| {
+            let after = ranges.last().unwrap().end();
+            let start = self
+                .trivia
+                .find(TextRange::new(after, expr.start()), keyword)
+                .map_or(expr.start(), |keyword| keyword.start());
+            TextRange::new(start, end)
+        };
+        for generator in generators {
+            let keyword = if generator.is_async {
+                "async for "
+            } else {
+                "for "
+            };
+            items.push(Doc::concat([
+                Doc::text(keyword),
+                self.expr(&generator.target, precedence::TUPLE),
+                Doc::text(" in "),
+                self.expr(&generator.iter, precedence::OR),
+            ]));
+            let first = if generator.is_async {
+                Tok::Async
+            } else {
+                Tok::For
+            };
+            ranges.push(clause(
+                &ranges,
+                &first,
+                &generator.target,
+                generator.iter.end(),
+            ));
+            for condition in &generator.ifs {
+                items.push(Doc::concat([
+                    Doc::text("if "),
+                    self.expr(condition, precedence::OR),
+                ]));
+                ranges.push(clause(&ranges, &Tok::If, condition, condition.end()));
+            }
+        }
+        Group {
+            separator: Separator::Space,
+            comments: self.bracket_comments(range, &ranges),
+            ..Group::new(open, close, items)
+        }
+        .into()
+    }
+
+    /// The arguments of a call or a class definition, between the parentheses at `brackets`.
+    fn arguments(&self, args: &[Expr], keywords: &[ast::Keyword], brackets: TextRange) -> Doc {
+        if let ([Expr::GeneratorExp(generator)], []) = (args, keywords) {
+            if !self.trivia.is_enclosed(generator.range) {
+                let elt = (
+                    generator.elt.range(),
+                    self.expr(&generator.elt, precedence::TEST),
+                );
+                return self.comprehension("(", ")", elt, &generator.generators, brackets);
+            }
+        }
+
+        let parens = if args.len() + keywords.len() == 1 {
+            Parens::Owned
+        } else {
+            Parens::Keep
+        };
+        let mut items: Vec<_> = args
+            .iter()
+            .map(|arg| (arg.range(), self.expr_with(arg, precedence::TEST, parens)))
+            .chain(keywords.iter().map(|keyword| {
+                let name = match &keyword.arg {
+                    Some(arg) => format!("{}=", arg.as_str()),
+                    None => "**".to_owned(),
+                };
+                let value = self.expr(&keyword.value, precedence::TEST);
+                (keyword.range(), Doc::concat([Doc::text(name), value]))
+            }))
+            .collect();
+        items.sort_by_key(|(range, _)| range.start());
+        let (ranges, items): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+        Group {
+            magic_trailing_comma: !items.is_empty()
+                && self.trivia.has_trailing_comma(brackets.end()),
+            comments: self.bracket_comments(brackets, &ranges),
+            ..Group::new("(", ")", items)
+        }
+        .into()
+    }
+
+    /// The range of a starred expression or parameter at `range`, including its `*` or `**`.
+    fn unpacked(&self, range: TextRange) -> TextRange {
+        let start = self
+            .trivia
+            .token_before(range.start())
+            .map_or(range.start(), |(_, star)| star.start());
+        TextRange::new(start, range.end())
+    }
+
+    // Patterns
+
+    fn pattern(&self, pattern: &Pattern) -> Doc {
+        let doc = self.pattern_inner(pattern);
+        let parenthesized = match pattern {
+            Pattern::MatchSequence(_) => false,
+            Pattern::MatchClass(ast::PatternMatchClass { patterns, .. }) if patterns.len() == 1 => {
+                self.trivia.parentheses(pattern.range()) > 0
+            }
+            _ => self.trivia.parentheses(pattern.range()) > 0,
+        };
+        if parenthesized {
+            let range = pattern.range();
+            let brackets = self
+                .trivia
+                .with_parentheses(range, self.trivia.parentheses(range));
+            Group {
+                comments: self.bracket_comments(brackets, &[range]),
+                ..Group::new("(", ")", vec![doc])
+            }
+            .into()
+        } else {
+            doc
+        }
+    }
+
+    fn pattern_inner(&self, pattern: &Pattern) -> Doc {
+        match pattern {
+            Pattern::MatchValue(ast::PatternMatchValue { value, .. }) => {
+                self.expr(value, precedence::BOR)
+            }
+            Pattern::MatchSingleton(ast::PatternMatchSingleton { value, .. }) => {
+                Doc::text(value.to_string())
+            }
+            Pattern::MatchSequence(ast::PatternMatchSequence { patterns, range }) => {
+                let items: Vec<_> = patterns.iter().map(|p| self.owned_pattern(p, 0)).collect();
+                let ranges: Vec<_> = patterns.iter().map(Ranged::range).collect();
+                match self.trivia.text(*range).as_bytes().first() {
+                    Some(b'[') if self.trivia.is_enclosed(*range) => {
+                        self.collection("[", "]", ranges.into_iter().zip(items).collect(), *range)
+                    }
+                    Some(b'(') if self.trivia.is_enclosed(*range) => Group {
+                        one_tuple: items.len() == 1,
+                        hug: false,
+                        magic_trailing_comma: items.len() > 1
+                            && self.trivia.has_trailing_comma(range.end()),
+                        comments: self.bracket_comments(*range, &ranges),
+                        ..Group::new("(", ")", items)
+                    }
+                    .into(),
+                    _ => {
+                        let one = items.len() == 1;
+                        let mut parts = Vec::new();
+                        for (index, item) in items.into_iter().enumerate() {
+                            if index > 0 {
+                                parts.push(Doc::text(", "));
+                            }
+                            parts.push(item);
+                        }
+                        if one {
+                            parts.push(Doc::text(","));
+                        }
+                        Doc::concat(parts)
+                    }
+                }
+            }
+            Pattern::MatchMapping(ast::PatternMatchMapping {
+                keys,
+                patterns,
+                rest,
+                range,
+            }) => {
+                let mut items: Vec<_> = keys
+                    .iter()
+                    .zip(patterns)
+                    .map(|(key, pattern)| {
+                        let doc = Doc::concat([
+                            self.expr(key, precedence::BOR),
+                            Doc::text(": "),
+                            self.pattern(pattern),
+                        ]);
+                        (TextRange::new(key.start(), pattern.end()), doc)
+                    })
+                    .collect();
+                if let Some(rest) = rest {
+                    let after = patterns.last().map_or(range.start(), Ranged::end);
+                    let rest_range = self
+                        .trivia
+                        .find(TextRange::new(after, range.end()), &Tok::DoubleStar)
+                        .map_or(TextRange::empty(after), |star| {
+                            let name = self.trivia.token_after(star.end());
+                            star.cover(name.map_or(star, |(_, name)| *name))
+                        });
+                    items.push((rest_range, Doc::text(format!("**{}", rest.as_str()))));
+                }
+                self.collection("{", "}", items, *range)
+            }
+            Pattern::MatchClass(ast::PatternMatchClass {
+                cls,
+                patterns,
+                kwd_attrs,
+                kwd_patterns,
+                range,
+            }) => {
+                let owned = usize::from(patterns.len() + kwd_patterns.len() == 1);
+                let mut items: Vec<_> = patterns
+                    .iter()
+                    .map(|pattern| self.owned_pattern(pattern, owned))
+                    .collect();
+                let mut ranges: Vec<_> = patterns.iter().map(Ranged::range).collect();
+                for (attr, pattern) in kwd_attrs.iter().zip(kwd_patterns) {
+                    items.push(Doc::concat([
+                        Doc::text(format!("{}=", attr.as_str())),
+                        self.pattern(pattern),
+                    ]));
+                    // The range of `attr=pattern` starts at the name before the `=`
+                    let after = ranges.last().map_or(cls.end(), |range| range.end());
+                    let start = self
+                        .trivia
+                        .find(TextRange::new(after, pattern.start()), &Tok::Equal)
+                        .and_then(|equal| self.trivia.token_before(equal.start()))
+                        .map_or(pattern.start(), |(_, name)| name.start());
+                    ranges.push(TextRange::new(start, pattern.end()));
+                }
+                let brackets = self.trivia.bracketed(range.end()).unwrap_or(*range);
+                Doc::concat([
+                    self.expr(cls, precedence::ATOM),
+                    Group {
+                        magic_trailing_comma: !items.is_empty()
+                            && self.trivia.has_trailing_comma(range.end()),
+                        comments: self.bracket_comments(brackets, &ranges),
+                        ..Group::new("(", ")", items)
+                    }
+                    .into(),
+                ])
+            }
+            Pattern::MatchStar(ast::PatternMatchStar { name, .. }) => Doc::text(format!(
+                "*{}",
+                name.as_ref().map_or("_", |name| name.as_str())
+            )),
+            Pattern::MatchAs(ast::PatternMatchAs { pattern, name, .. }) => {
+                let name = name.as_ref().map_or("_", |name| name.as_str());
+                match pattern {
+                    Some(pattern) => {
+                        Doc::concat([self.pattern(pattern), Doc::text(format!(" as {name}"))])
+                    }
+                    None => Doc::text(name),
+                }
+            }
+            Pattern::MatchOr(ast::PatternMatchOr { patterns, .. }) => Doc::Chain(
+                patterns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, pattern)| {
+                        let operator = if index == 0 { "" } else { "|" };
+                        (operator, self.pattern(pattern))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// A pattern whose innermost `owned` pairs of parentheses belong to its parent.
+    fn owned_pattern(&self, pattern: &Pattern, owned: usize) -> Doc {
+        if owned > 0 && self.trivia.parentheses(pattern.range()) <= owned {
+            self.pattern_inner(pattern)
+        } else {
+            self.pattern(pattern)
+        }
+    }
+}
+
+fn precedence_of(expr: &Expr) -> u8 {
+    use precedence::*;
+    match expr {
+        Expr::BoolOp(ast::ExprBoolOp { op, .. }) => match op {
+            ast::BoolOp::And => AND,
+            ast::BoolOp::Or => OR,
+        },
+        Expr::NamedExpr(_) | Expr::Yield(_) | Expr::YieldFrom(_) => TUPLE,
+        Expr::Tuple(ast::ExprTuple { elts, .. }) if !elts.is_empty() => TUPLE,
+        Expr::BinOp(ast::ExprBinOp { op, .. }) => operator_precedence(*op),
+        Expr::UnaryOp(ast::ExprUnaryOp { op, .. }) => match op {
+            ast::UnaryOp::Not => NOT,
+            _ => FACTOR,
+        },
+        Expr::Lambda(_) | Expr::IfExp(_) => TEST,
+        Expr::Await(_) => AWAIT,
+        Expr::Compare(_) => CMP,
+        _ => ATOM,
+    }
+}
+
+/// Operands joined by operators. Implicitly concatenated strings on the left are split one
+/// per line, so the operators go on the line of the last string.
+fn chain(mut operands: Vec<(&'static str, Doc)>) -> Doc {
+    if operands.len() > 1 && operands[0].1.is_concatenation() {
+        let (_, first) = operands.remove(0);
+        first.map_last_string(|last| {
+            operands.insert(0, ("", last));
+            Doc::Chain(operands)
+        })
+    } else {
+        Doc::Chain(operands)
+    }
+}
+
+fn operator_precedence(op: ast::Operator) -> u8 {
+    use precedence::*;
+    match op {
+        ast::Operator::Add | ast::Operator::Sub => ARITH,
+        ast::Operator::Mult
+        | ast::Operator::MatMult
+        | ast::Operator::Div
+        | ast::Operator::Mod
+        | ast::Operator::FloorDiv => TERM,
+        ast::Operator::Pow => POWER,
+        ast::Operator::LShift | ast::Operator::RShift => SHIFT,
+        ast::Operator::BitOr => BOR,
+        ast::Operator::BitXor => BXOR,
+        ast::Operator::BitAnd => BAND,
+    }
+}
+
+fn operator(op: ast::Operator) -> &'static str {
+    match op {
+        ast::Operator::Add => "+",
+        ast::Operator::Sub => "-",
+        ast::Operator::Mult => "*",
+        ast::Operator::MatMult => "@",
+        ast::Operator::Div => "/",
+        ast::Operator::Mod => "%",
+        ast::Operator::Pow => "**",
+        ast::Operator::LShift => "<<",
+        ast::Operator::RShift => ">>",
+        ast::Operator::BitOr => "|",
+        ast::Operator::BitXor => "^",
+        ast::Operator::BitAnd => "&",
+        ast::Operator::FloorDiv => "//",
+    }
+}
+
+fn alias(alias: &ast::Alias) -> String {
+    match &alias.asname {
+        Some(asname) => format!("{} as {}", alias.name.as_str(), asname.as_str()),
+        None => alias.name.as_str().to_owned(),
+    }
+}
+
+/// Returns true for the spelling of an integer, see [`crate::literals`].
+fn is_int_spelling(id: &str) -> bool {
+    let lower = id.to_ascii_lowercase();
+    id.starts_with(|c: char| c.is_ascii_digit())
+        && (lower.starts_with("0x")
+            || lower.starts_with("0o")
+            || lower.starts_with("0b")
+            || id.chars().all(|c| c.is_ascii_digit() || c == '_'))
+}
+
+/// Names, numbers and attribute chains hug the power operator, like `x**2`.
+fn is_simple_power_operand(expr: &Expr) -> bool {
+    match expr {
+        Expr::Name(ast::ExprName { id, .. }) => !is_string_spelling(id.as_str()),
+        Expr::Constant(ast::ExprConstant { value, .. }) => !value.is_str() && !value.is_bytes(),
+        Expr::Attribute(ast::ExprAttribute { value, .. }) => is_simple_power_operand(value),
+        Expr::UnaryOp(ast::ExprUnaryOp {
+            op: ast::UnaryOp::USub,
+            operand,
+            ..
+        }) => is_simple_power_operand(operand),
+        _ => false,
+    }
+}
+
+fn is_simple_slice_bound(expr: &Expr) -> bool {
+    match expr {
+        Expr::Name(_) | Expr::Constant(_) => true,
+        Expr::UnaryOp(ast::ExprUnaryOp { operand, .. }) => is_simple_slice_bound(operand),
+        _ => false,
+    }
+}
+
+/// Add a space after the `#` of a comment, unless it's a shebang or a special comment like
+/// `#:`.
+fn normalize_comment(comment: &str) -> String {
+    let content = comment.trim_end();
+    let content = content.strip_prefix('#').unwrap_or(content);
+    if content.is_empty() || content.starts_with([' ', '!', ':', '#', '\'']) {
+        format!("#{content}")
+    } else {
+        format!("# {content}")
+    }
+}
@@ -0,0 +1,238 @@
+//! What the AST doesn't know about the source: comments, blank lines, brackets and commas.
+use rustpython_parser::{
+    cst::{Cst, CstTokenKind},
+    text_size::{TextRange, TextSize},
+    Tok,
+};
+
+/// The tokens and comments of a parsed source file, indexed by position.
+pub(crate) struct Trivia<'a> {
+    source: &'a str,
+    /// The tokens the parser sees, without newlines, indents and dedents.
+    tokens: Vec<(Tok, TextRange)>,
+    /// For each opening bracket in `tokens`, the index of the matching closing bracket.
+    matching: Vec<Option<usize>>,
+    comments: Vec<TextRange>,
+}
+
+impl<'a> Trivia<'a> {
+    pub(crate) fn new(cst: &'a Cst) -> Self {
+        let mut tokens = Vec::new();
+        let mut comments = Vec::new();
+        for token in cst.root().tokens() {
+            match token.kind() {
+                CstTokenKind::Tok(Tok::Comment(_)) => comments.push(token.range()),
+                CstTokenKind::Tok(
+                    Tok::Newline
+                    | Tok::NonLogicalNewline
+                    | Tok::Indent
+                    | Tok::Dedent
+                    | Tok::EndOfFile,
+                )
+                | CstTokenKind::Whitespace => {}
+                CstTokenKind::Tok(tok) => tokens.push((tok.clone(), token.range())),
+            }
+        }
+
+        let mut matching = vec![None; tokens.len()];
+        let mut open = Vec::new();
+        for (index, (tok, _)) in tokens.iter().enumerate() {
+            match tok {
+                Tok::Lpar | Tok::Lsqb | Tok::Lbrace => open.push(index),
+                Tok::Rpar | Tok::Rsqb | Tok::Rbrace => {
+                    if let Some(start) = open.pop() {
+                        matching[start] = Some(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Trivia {
+            source: cst.source(),
+            tokens,
+            matching,
+            comments,
+        }
+    }
+
+    pub(crate) fn source(&self) -> &'a str {
+        self.source
+    }
+
+    pub(crate) fn text(&self, range: TextRange) -> &'a str {
+        &self.source[range]
+    }
+
+    pub(crate) fn comments(&self) -> &[TextRange] {
+        &self.comments
+    }
+
+    /// The tokens inside `range`.
+    pub(crate) fn tokens_in(&self, range: TextRange) -> impl Iterator<Item = &(Tok, TextRange)> {
+        let start = self.first_at_or_after(range.start());
+        self.tokens[start..]
+            .iter()
+            .take_while(move |(_, r)| r.end() <= range.end())
+    }
+
+    /// The index of the first token starting at or after `offset`.
+    fn first_at_or_after(&self, offset: TextSize) -> usize {
+        self.tokens.partition_point(|(_, r)| r.start() < offset)
+    }
+
+    /// The index of the last token ending at or before `offset`.
+    fn last_before(&self, offset: TextSize) -> Option<usize> {
+        self.tokens
+            .partition_point(|(_, r)| r.end() <= offset)
+            .checked_sub(1)
+    }
+
+    /// The last token ending at or before `offset`.
+    pub(crate) fn token_before(&self, offset: TextSize) -> Option<&(Tok, TextRange)> {
+        self.last_before(offset).map(|index| &self.tokens[index])
+    }
+
+    /// The first token starting at or after `offset`, skipping semicolons.
+    pub(crate) fn token_after(&self, offset: TextSize) -> Option<&(Tok, TextRange)> {
+        self.tokens[self.first_at_or_after(offset)..]
+            .iter()
+            .find(|(tok, _)| *tok != Tok::Semi)
+    }
+
+    /// The first token of the given kind in `range`.
+    pub(crate) fn find(&self, range: TextRange, kind: &Tok) -> Option<TextRange> {
+        self.tokens_in(range)
+            .find(|(tok, _)| tok == kind)
+            .map(|(_, range)| *range)
+    }
+
+    /// The range of the closing bracket matching the opening bracket at `open`.
+    pub(crate) fn matching_bracket(&self, open: TextRange) -> Option<TextRange> {
+        let index = self.first_at_or_after(open.start());
+        let close = self.matching.get(index).copied().flatten()?;
+        Some(self.tokens[close].1)
+    }
+
+    /// The number of parentheses directly around `range`.
+    pub(crate) fn parentheses(&self, range: TextRange) -> usize {
+        let Some(before) = self.last_before(range.start()) else {
+            return 0;
+        };
+        let after = self.first_at_or_after(range.end());
+        (0..=before)
+            .take_while(|&count| {
+                self.tokens[before - count].0 == Tok::Lpar
+                    && self.matching[before - count] == Some(after + count)
+            })
+            .count()
+    }
+
+    /// `range` with the innermost `count` pairs of parentheses around it.
+    pub(crate) fn with_parentheses(&self, range: TextRange, count: usize) -> TextRange {
+        match self.last_before(range.start()) {
+            Some(before) if count > 0 => {
+                let after = self.first_at_or_after(range.end());
+                TextRange::new(
+                    self.tokens[before + 1 - count].1.start(),
+                    self.tokens[after + count - 1].1.end(),
+                )
+            }
+            _ => range,
+        }
+    }
+
+    /// The range from the opening bracket to the closing bracket that ends at `end`.
+    pub(crate) fn bracketed(&self, end: TextSize) -> Option<TextRange> {
+        let close = self.last_before(end)?;
+        let open = (0..close)
+            .rev()
+            .find(|&index| self.matching[index] == Some(close))?;
+        Some(TextRange::new(self.tokens[open].1.start(), end))
+    }
+
+    /// Returns true if `range` starts with an opening bracket that is closed at its end, like
+    /// the range of a parenthesized tuple.
+    pub(crate) fn is_enclosed(&self, range: TextRange) -> bool {
+        let start = self.first_at_or_after(range.start());
+        match (self.tokens.get(start), self.last_before(range.end())) {
+            (Some((Tok::Lpar | Tok::Lsqb | Tok::Lbrace, r)), Some(end)) => {
+                r.start() == range.start()
+                    && self.matching[start] == Some(end)
+                    && self.tokens[end].1.end() == range.end()
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the token ending at `end` is preceded by a comma, or is a comma itself,
+    /// i.e. the collection ending there has a magic trailing comma.
+    pub(crate) fn has_trailing_comma(&self, end: TextSize) -> bool {
+        let Some(last) = self.last_before(end) else {
+            return false;
+        };
+        match &self.tokens[last].0 {
+            Tok::Comma => true,
+            Tok::Rpar | Tok::Rsqb | Tok::Rbrace => {
+                last > 0 && self.tokens[last - 1].0 == Tok::Comma
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if a comment starts inside `range`.
+    pub(crate) fn has_comment(&self, range: TextRange) -> bool {
+        let index = self.comments.partition_point(|r| r.start() < range.start());
+        self.comments
+            .get(index)
+            .is_some_and(|comment| comment.start() < range.end())
+    }
+
+    /// Returns true if a string in `range` spans several lines.
+    pub(crate) fn has_multiline_string(&self, range: TextRange) -> bool {
+        self.tokens_in(range).any(|(tok, range)| {
+            matches!(tok, Tok::String { .. }) && self.source[*range].contains(['\n', '\r'])
+        })
+    }
+
+    /// Returns true if the comment at `comment` is on the same line as the code before it.
+    pub(crate) fn is_trailing(&self, comment: TextRange) -> bool {
+        match self.token_before(comment.start()) {
+            Some((_, range)) => {
+                !self.source[TextRange::new(range.end(), comment.start())].contains(['\n', '\r'])
+            }
+            None => false,
+        }
+    }
+
+    /// The start of the line containing `offset`.
+    pub(crate) fn line_start(&self, offset: TextSize) -> TextSize {
+        let before = &self.source[..usize::from(offset)];
+        TextSize::new(before.rfind(['\n', '\r']).map_or(0, |index| index + 1) as u32)
+    }
+
+    /// The column of `offset`, in characters.
+    pub(crate) fn column(&self, offset: TextSize) -> usize {
+        self.source[TextRange::new(self.line_start(offset), offset)]
+            .chars()
+            .count()
+    }
+
+    /// The number of blank lines directly before the line containing `offset`.
+    pub(crate) fn blank_lines_before(&self, offset: TextSize) -> usize {
+        let before = &self.source[..usize::from(self.line_start(offset))];
+        before
+            .split_inclusive('\n')
+            .rev()
+            .take_while(|line| line.trim().is_empty())
+            .count()
+    }
+
+    /// Returns true if `offset` is inside a string token, like the lines of a docstring.
+    pub(crate) fn in_string(&self, offset: TextSize) -> bool {
+        let index = self.tokens.partition_point(|(_, r)| r.end() <= offset);
+        self.tokens
+            .get(index)
+            .is_some_and(|(tok, range)| matches!(tok, Tok::String { .. }) && range.start() < offset)
+    }
+}
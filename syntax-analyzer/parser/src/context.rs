@@ -1,4 +1,7 @@
-use crate::ast::{self, Expr, ExprContext};
+use crate::{
+    ast::{self, Expr, ExprContext, Ranged, Stmt},
+    parser::{ParseError, ParseErrorType, TargetKind},
+};
 
 pub(crate) fn set_context(expr: Expr, ctx: ExprContext) -> Expr {
     match expr {
@@ -47,6 +50,341 @@ pub(crate) fn set_context(expr: Expr, ctx: ExprContext) -> Expr {
     }
 }
 
+/// Report the targets of assignments, `for` loops, `with` items, `del` statements and
+/// comprehensions in `body` that can't be assigned to, such as literals and calls.
+pub(crate) fn check_assignment_targets(
+    body: &[Stmt],
+    source_path: &str,
+    errors: &mut Vec<ParseError>,
+) {
+    for stmt in body {
+        check_stmt_targets(stmt, source_path, errors);
+    }
+}
+
+fn check_stmt_targets(stmt: &Stmt, source_path: &str, errors: &mut Vec<ParseError>) {
+    let exprs = |exprs: &mut dyn Iterator<Item = &Expr>, errors: &mut Vec<ParseError>| {
+        for expr in exprs {
+            check_expr_targets(expr, source_path, errors);
+        }
+    };
+    match stmt {
+        Stmt::Assign(ast::StmtAssign { targets, value, .. }) => {
+            for target in targets {
+                check_target(target, TargetKind::Assign, source_path, errors);
+            }
+            exprs(&mut targets.iter().chain([&**value]), errors);
+        }
+        Stmt::AugAssign(ast::StmtAugAssign { target, value, .. }) => {
+            check_target(target, TargetKind::AugAssign, source_path, errors);
+            exprs(&mut [&**target, value].into_iter(), errors);
+        }
+        Stmt::AnnAssign(ast::StmtAnnAssign {
+            target,
+            annotation,
+            value,
+            ..
+        }) => {
+            check_target(target, TargetKind::Annotation, source_path, errors);
+            exprs(
+                &mut [&**target, annotation].into_iter().chain(value.as_deref()),
+                errors,
+            );
+        }
+        Stmt::Delete(ast::StmtDelete { targets, .. }) => {
+            for target in targets {
+                check_target(target, TargetKind::Delete, source_path, errors);
+            }
+            exprs(&mut targets.iter(), errors);
+        }
+        Stmt::FunctionDef(ast::StmtFunctionDef {
+            decorator_list,
+            args,
+            returns,
+            body,
+            ..
+        })
+        | Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef {
+            decorator_list,
+            args,
+            returns,
+            body,
+            ..
+        }) => {
+            exprs(
+                &mut decorator_list
+                    .iter()
+                    .chain(defaults(args))
+                    .chain(returns.as_deref()),
+                errors,
+            );
+            check_assignment_targets(body, source_path, errors);
+        }
+        Stmt::ClassDef(ast::StmtClassDef {
+            decorator_list,
+            bases,
+            keywords,
+            body,
+            ..
+        }) => {
+            exprs(
+                &mut decorator_list
+                    .iter()
+                    .chain(bases)
+                    .chain(keywords.iter().map(|keyword| &keyword.value)),
+                errors,
+            );
+            check_assignment_targets(body, source_path, errors);
+        }
+        Stmt::With(ast::StmtWith { items, body, .. })
+        | Stmt::AsyncWith(ast::StmtAsyncWith { items, body, .. }) => {
+            for item in items {
+                check_expr_targets(&item.context_expr, source_path, errors);
+                if let Some(vars) = &item.optional_vars {
+                    check_target(vars, TargetKind::Bind, source_path, errors);
+                    check_expr_targets(vars, source_path, errors);
+                }
+            }
+            check_assignment_targets(body, source_path, errors);
+        }
+        Stmt::For(ast::StmtFor {
+            target,
+            iter,
+            body,
+            orelse,
+            ..
+        })
+        | Stmt::AsyncFor(ast::StmtAsyncFor {
+            target,
+            iter,
+            body,
+            orelse,
+            ..
+        }) => {
+            check_target(target, TargetKind::Bind, source_path, errors);
+            exprs(&mut [&**target, iter].into_iter(), errors);
+            check_assignment_targets(body, source_path, errors);
+            check_assignment_targets(orelse, source_path, errors);
+        }
+        Stmt::While(ast::StmtWhile {
+            test, body, orelse, ..
+        })
+        | Stmt::If(ast::StmtIf {
+            test, body, orelse, ..
+        }) => {
+            check_expr_targets(test, source_path, errors);
+            check_assignment_targets(body, source_path, errors);
+            check_assignment_targets(orelse, source_path, errors);
+        }
+        Stmt::Match(ast::StmtMatch { subject, cases, .. }) => {
+            check_expr_targets(subject, source_path, errors);
+            for case in cases {
+                exprs(&mut case.guard.as_deref().into_iter(), errors);
+                check_assignment_targets(&case.body, source_path, errors);
+            }
+        }
+        Stmt::Try(ast::StmtTry {
+            body,
+            handlers,
+            orelse,
+            finalbody,
+            ..
+        })
+        | Stmt::TryStar(ast::StmtTryStar {
+            body,
+            handlers,
+            orelse,
+            finalbody,
+            ..
+        }) => {
+            check_assignment_targets(body, source_path, errors);
+            for ast::ExceptHandler::ExceptHandler(handler) in handlers {
+                exprs(&mut handler.type_.as_deref().into_iter(), errors);
+                check_assignment_targets(&handler.body, source_path, errors);
+            }
+            check_assignment_targets(orelse, source_path, errors);
+            check_assignment_targets(finalbody, source_path, errors);
+        }
+        Stmt::Return(ast::StmtReturn { value, .. }) => {
+            exprs(&mut value.as_deref().into_iter(), errors);
+        }
+        Stmt::Raise(ast::StmtRaise { exc, cause, .. }) => {
+            exprs(
+                &mut exc.as_deref().into_iter().chain(cause.as_deref()),
+                errors,
+            );
+        }
+        Stmt::Assert(ast::StmtAssert { test, msg, .. }) => {
+            exprs(&mut [&**test].into_iter().chain(msg.as_deref()), errors);
+        }
+        Stmt::TypeAlias(ast::StmtTypeAlias { value, .. })
+        | Stmt::Expr(ast::StmtExpr { value, .. }) => {
+            check_expr_targets(value, source_path, errors);
+        }
+        Stmt::Import(_)
+        | Stmt::ImportFrom(_)
+        | Stmt::Global(_)
+        | Stmt::Nonlocal(_)
+        | Stmt::Pass(_)
+        | Stmt::Break(_)
+        | Stmt::Continue(_)
+        | Stmt::Invalid(_) => {}
+    }
+}
+
+/// The default values of the parameters in `args`.
+fn defaults(args: &ast::Arguments) -> impl Iterator<Item = &Expr> {
+    args.posonlyargs
+        .iter()
+        .chain(&args.args)
+        .chain(&args.kwonlyargs)
+        .filter_map(|arg| arg.default.as_deref())
+}
+
+/// Report the targets of the comprehensions in `expr` that can't be assigned to.
+pub(crate) fn check_expr_targets(expr: &Expr, source_path: &str, errors: &mut Vec<ParseError>) {
+    let mut check = |expr: &Expr| check_expr_targets(expr, source_path, errors);
+    match expr {
+        Expr::ListComp(ast::ExprListComp {
+            elt, generators, ..
+        })
+        | Expr::SetComp(ast::ExprSetComp {
+            elt, generators, ..
+        })
+        | Expr::GeneratorExp(ast::ExprGeneratorExp {
+            elt, generators, ..
+        }) => {
+            check_comprehensions(generators, source_path, errors);
+            check_expr_targets(elt, source_path, errors);
+        }
+        Expr::DictComp(ast::ExprDictComp {
+            key,
+            value,
+            generators,
+            ..
+        }) => {
+            check_comprehensions(generators, source_path, errors);
+            check_expr_targets(key, source_path, errors);
+            check_expr_targets(value, source_path, errors);
+        }
+        Expr::BoolOp(ast::ExprBoolOp { values: elts, .. })
+        | Expr::Set(ast::ExprSet { elts, .. })
+        | Expr::JoinedStr(ast::ExprJoinedStr { values: elts, .. })
+        | Expr::List(ast::ExprList { elts, .. })
+        | Expr::Tuple(ast::ExprTuple { elts, .. }) => elts.iter().for_each(check),
+        Expr::NamedExpr(ast::ExprNamedExpr {
+            target: left,
+            value: right,
+            ..
+        })
+        | Expr::BinOp(ast::ExprBinOp { left, right, .. }) => {
+            check(left);
+            check(right);
+        }
+        Expr::UnaryOp(ast::ExprUnaryOp { operand: value, .. })
+        | Expr::Await(ast::ExprAwait { value, .. })
+        | Expr::YieldFrom(ast::ExprYieldFrom { value, .. })
+        | Expr::Attribute(ast::ExprAttribute { value, .. })
+        | Expr::Starred(ast::ExprStarred { value, .. }) => check(value),
+        Expr::Lambda(ast::ExprLambda { args, body, .. }) => {
+            defaults(args).for_each(&mut check);
+            check(body);
+        }
+        Expr::IfExp(ast::ExprIfExp {
+            test, body, orelse, ..
+        }) => {
+            check(test);
+            check(body);
+            check(orelse);
+        }
+        Expr::Dict(ast::ExprDict { keys, values, .. }) => {
+            keys.iter().flatten().chain(values).for_each(check);
+        }
+        Expr::Yield(ast::ExprYield { value, .. }) => value.as_deref().into_iter().for_each(check),
+        Expr::Compare(ast::ExprCompare {
+            left, comparators, ..
+        }) => [&**left].into_iter().chain(comparators).for_each(check),
+        Expr::Call(ast::ExprCall {
+            func,
+            args,
+            keywords,
+            ..
+        }) => [&**func]
+            .into_iter()
+            .chain(args)
+            .chain(keywords.iter().map(|keyword| &keyword.value))
+            .for_each(check),
+        Expr::FormattedValue(ast::ExprFormattedValue {
+            value, format_spec, ..
+        }) => [&**value]
+            .into_iter()
+            .chain(format_spec.as_deref())
+            .for_each(check),
+        Expr::Subscript(ast::ExprSubscript { value, slice, .. }) => {
+            check(value);
+            check(slice);
+        }
+        Expr::Slice(ast::ExprSlice {
+            lower, upper, step, ..
+        }) => [lower, upper, step]
+            .into_iter()
+            .filter_map(|expr| expr.as_deref())
+            .for_each(check),
+        Expr::Constant(_) | Expr::Name(_) | Expr::Invalid(_) => {}
+    }
+}
+
+fn check_comprehensions(
+    generators: &[ast::Comprehension],
+    source_path: &str,
+    errors: &mut Vec<ParseError>,
+) {
+    for generator in generators {
+        check_target(&generator.target, TargetKind::Bind, source_path, errors);
+        check_expr_targets(&generator.target, source_path, errors);
+        check_expr_targets(&generator.iter, source_path, errors);
+        for condition in &generator.ifs {
+            check_expr_targets(condition, source_path, errors);
+        }
+    }
+}
+
+fn check_target(target: &Expr, kind: TargetKind, source_path: &str, errors: &mut Vec<ParseError>) {
+    // Augmented and annotated assignments only take a single target
+    let unpacking = matches!(
+        kind,
+        TargetKind::Assign | TargetKind::Bind | TargetKind::Delete
+    );
+    let nested = match kind {
+        TargetKind::Delete => TargetKind::Delete,
+        _ => TargetKind::Bind,
+    };
+    match target {
+        Expr::Name(_) | Expr::Attribute(_) | Expr::Subscript(_) | Expr::Invalid(_) => {}
+        Expr::Tuple(ast::ExprTuple { elts, .. }) | Expr::List(ast::ExprList { elts, .. })
+            if unpacking =>
+        {
+            for elt in elts {
+                check_target(elt, nested, source_path, errors);
+            }
+        }
+        Expr::Starred(ast::ExprStarred { value, .. })
+            if matches!(kind, TargetKind::Assign | TargetKind::Bind) =>
+        {
+            check_target(value, nested, source_path, errors)
+        }
+        _ => errors.push(ParseError {
+            error: ParseErrorType::InvalidAssignmentTarget {
+                target: target.python_name(),
+                range: target.range(),
+                kind,
+            },
+            offset: target.start(),
+            source_path: source_path.to_owned(),
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{ast, Parse};
@@ -0,0 +1,483 @@
+//! A lossless concrete syntax tree.
+//!
+//! The AST drops everything that doesn't change the meaning of a program: comments, blank
+//! lines, whitespace and redundant parentheses. The concrete syntax tree keeps all of it. Its
+//! leaves are the tokens produced by the [lexer] together with the whitespace in between, so
+//! writing them out in order reproduces the source byte for byte. Its inner nodes are the AST
+//! nodes, found by their [`TextRange`].
+//!
+//! This makes it possible to rewrite one part of a program without touching the formatting of
+//! the rest.
+//!
+//! # Example
+//!
+//! ```
+//! use rustpython_parser::{ast::Ranged, cst::Cst, Mode};
+//!
+//! let source = "x = (1 +  2)  # three\n";
+//! let cst = Cst::parse(source, Mode::Module, "<embedded>").unwrap();
+//! assert_eq!(cst.to_string(), source);
+//!
+//! let module = cst.ast().as_module().unwrap();
+//! let node = cst.node(module.body[0].range()).unwrap();
+//! assert_eq!(cst.text(node.range()), "x = (1 +  2)");
+//! ```
+//!
+//! [lexer]: crate::lexer
+use crate::{
+    ast::{self, fold::Fold},
+    lexer::{self, LexResult},
+    parser::{parse_tokens, ParseError},
+    text_size::{TextRange, TextSize},
+    token::Tok,
+    Mode,
+};
+use std::{cmp::Reverse, convert::Infallible, fmt};
+
+/// A parsed source file, along with its AST.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cst {
+    source: String,
+    ast: ast::Mod,
+    root: CstNode,
+}
+
+impl Cst {
+    /// Parse the given source code using the specified [`Mode`], keeping all tokens and trivia.
+    pub fn parse(source: &str, mode: Mode, source_path: &str) -> Result<Self, ParseError> {
+        let mut tokens = Vec::new();
+        let lxr = lexer::lex(source, mode).inspect(|result: &LexResult| {
+            if let Ok((tok, range)) = result {
+                tokens.push((tok.clone(), *range));
+            }
+        });
+        let ast = parse_tokens(lxr, mode, source_path)?;
+        let root = CstBuilder::new(source, tokens, &ast).build();
+        Ok(Cst {
+            source: source.to_owned(),
+            ast,
+            root,
+        })
+    }
+
+    /// The source code the tree was parsed from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The AST of the source code.
+    pub fn ast(&self) -> &ast::Mod {
+        &self.ast
+    }
+
+    /// The root node, spanning the whole source.
+    pub fn root(&self) -> &CstNode {
+        &self.root
+    }
+
+    /// The source text of `range`.
+    pub fn text(&self, range: TextRange) -> &str {
+        &self.source[range]
+    }
+
+    /// Find the outermost node with the given range, e.g. the range of an AST node.
+    ///
+    /// Nodes inside a single token, like the values of an f-string, have no node of their own.
+    pub fn node(&self, range: TextRange) -> Option<&CstNode> {
+        let mut node = &self.root;
+        loop {
+            if node.range == range {
+                return Some(node);
+            }
+            node = node
+                .child_nodes()
+                .find(|child| child.range.contains_range(range))?;
+        }
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in self.root.tokens() {
+            f.write_str(self.text(token.range))?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of AST node a [`CstNode`] stands for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CstNodeKind {
+    /// The root of the tree, see [`ast::Mod`].
+    Mod,
+    Stmt,
+    Expr,
+    ExceptHandler,
+    Pattern,
+    TypeParam,
+    Arg,
+    Keyword,
+    Alias,
+    /// Any other AST node with a range.
+    Other,
+}
+
+/// An inner node of the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstNode {
+    kind: CstNodeKind,
+    range: TextRange,
+    children: Vec<CstElement>,
+}
+
+impl CstNode {
+    pub fn kind(&self) -> CstNodeKind {
+        self.kind
+    }
+
+    /// The range of the AST node. Trivia before and after it belongs to its parent.
+    pub fn range(&self) -> TextRange {
+        self.range
+    }
+
+    /// The nodes and tokens directly below this node, in source order.
+    pub fn children(&self) -> &[CstElement] {
+        &self.children
+    }
+
+    /// The nodes directly below this node, in source order.
+    pub fn child_nodes(&self) -> impl Iterator<Item = &CstNode> {
+        self.children.iter().filter_map(|child| match child {
+            CstElement::Node(node) => Some(node),
+            CstElement::Token(_) => None,
+        })
+    }
+
+    /// All tokens in this node and its descendants, in source order.
+    pub fn tokens(&self) -> impl Iterator<Item = &CstToken> {
+        let mut stack = vec![self.children.iter()];
+        std::iter::from_fn(move || loop {
+            match stack.last_mut()?.next() {
+                Some(CstElement::Token(token)) => return Some(token),
+                Some(CstElement::Node(node)) => stack.push(node.children.iter()),
+                None => {
+                    stack.pop();
+                }
+            }
+        })
+    }
+}
+
+/// A child of a [`CstNode`].
+#[derive(Clone, Debug, PartialEq, is_macro::Is)]
+pub enum CstElement {
+    Node(CstNode),
+    Token(CstToken),
+}
+
+/// A leaf of the tree.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CstToken {
+    kind: CstTokenKind,
+    range: TextRange,
+}
+
+impl CstToken {
+    pub fn kind(&self) -> &CstTokenKind {
+        &self.kind
+    }
+
+    pub fn range(&self) -> TextRange {
+        self.range
+    }
+
+    /// Returns true for tokens the parser ignores: whitespace, comments and line breaks that
+    /// don't end a logical line.
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            CstTokenKind::Whitespace
+                | CstTokenKind::Tok(Tok::Comment(_))
+                | CstTokenKind::Tok(Tok::NonLogicalNewline)
+        )
+    }
+}
+
+/// The kind of a [`CstToken`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum CstTokenKind {
+    /// A token produced by the lexer, including comments and non-logical newlines.
+    Tok(Tok),
+    /// Text between two tokens: spaces, tabs, form feeds and line continuations.
+    Whitespace,
+}
+
+/// Records the ranges of all AST nodes, in the order they are folded.
+#[derive(Default)]
+struct NodeCollector {
+    nodes: Vec<(CstNodeKind, TextRange)>,
+    kind: Option<CstNodeKind>,
+}
+
+impl Fold<TextRange> for NodeCollector {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, user: &TextRange) {
+        let kind = self.kind.take().unwrap_or(CstNodeKind::Other);
+        // The module only has a range with `all-nodes-with-ranges`; the root stands for it
+        if kind != CstNodeKind::Mod {
+            self.nodes.push((kind, *user));
+        }
+    }
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(user)
+    }
+
+    fn fold_mod(&mut self, node: ast::Mod) -> Result<ast::Mod, Infallible> {
+        self.kind = Some(CstNodeKind::Mod);
+        ast::fold::fold_mod(self, node)
+    }
+
+    fn fold_stmt(&mut self, node: ast::Stmt) -> Result<ast::Stmt, Infallible> {
+        self.kind = Some(CstNodeKind::Stmt);
+        ast::fold::fold_stmt(self, node)
+    }
+
+    fn fold_expr(&mut self, node: ast::Expr) -> Result<ast::Expr, Infallible> {
+        self.kind = Some(CstNodeKind::Expr);
+        ast::fold::fold_expr(self, node)
+    }
+
+    fn fold_excepthandler(
+        &mut self,
+        node: ast::ExceptHandler,
+    ) -> Result<ast::ExceptHandler, Infallible> {
+        self.kind = Some(CstNodeKind::ExceptHandler);
+        ast::fold::fold_excepthandler(self, node)
+    }
+
+    fn fold_pattern(&mut self, node: ast::Pattern) -> Result<ast::Pattern, Infallible> {
+        self.kind = Some(CstNodeKind::Pattern);
+        ast::fold::fold_pattern(self, node)
+    }
+
+    fn fold_type_param(&mut self, node: ast::TypeParam) -> Result<ast::TypeParam, Infallible> {
+        self.kind = Some(CstNodeKind::TypeParam);
+        ast::fold::fold_type_param(self, node)
+    }
+
+    fn fold_arg(&mut self, node: ast::Arg) -> Result<ast::Arg, Infallible> {
+        self.kind = Some(CstNodeKind::Arg);
+        ast::fold::fold_arg(self, node)
+    }
+
+    fn fold_keyword(&mut self, node: ast::Keyword) -> Result<ast::Keyword, Infallible> {
+        self.kind = Some(CstNodeKind::Keyword);
+        ast::fold::fold_keyword(self, node)
+    }
+
+    fn fold_alias(&mut self, node: ast::Alias) -> Result<ast::Alias, Infallible> {
+        self.kind = Some(CstNodeKind::Alias);
+        ast::fold::fold_alias(self, node)
+    }
+}
+
+struct CstBuilder {
+    tokens: std::iter::Peekable<std::vec::IntoIter<CstToken>>,
+    nodes: std::iter::Peekable<std::vec::IntoIter<(CstNodeKind, TextRange)>>,
+    /// The end of the last token added to the tree.
+    position: TextSize,
+    end: TextSize,
+}
+
+impl CstBuilder {
+    fn new(source: &str, lexed: Vec<(Tok, TextRange)>, ast: &ast::Mod) -> Self {
+        let end = TextSize::of(source);
+
+        // Fill the gaps between tokens, so that the leaves cover the whole source
+        let mut tokens = Vec::with_capacity(lexed.len() * 2);
+        let mut position = TextSize::default();
+        for (tok, range) in lexed {
+            if range.start() > position {
+                tokens.push(CstToken {
+                    kind: CstTokenKind::Whitespace,
+                    range: TextRange::new(position, range.start()),
+                });
+            }
+            position = position.max(range.end());
+            tokens.push(CstToken {
+                kind: CstTokenKind::Tok(tok),
+                range,
+            });
+        }
+        if end > position {
+            tokens.push(CstToken {
+                kind: CstTokenKind::Whitespace,
+                range: TextRange::new(position, end),
+            });
+        }
+
+        let mut collector = NodeCollector::default();
+        collector
+            .fold(ast.clone())
+            .unwrap_or_else(|never| match never {});
+        let mut nodes = collector.nodes;
+        // Parents come before their children, which keep their relative order
+        nodes.sort_by_key(|(kind, range)| {
+            (
+                range.start(),
+                Reverse(range.end()),
+                *kind == CstNodeKind::Other,
+            )
+        });
+        // With `all-nodes-with-ranges`, nodes like `withitem` can have the same range as a typed
+        // node; only the typed one is kept
+        nodes.dedup_by(|(kind, range), (_, prev)| *kind == CstNodeKind::Other && range == prev);
+
+        CstBuilder {
+            tokens: tokens.into_iter().peekable(),
+            nodes: nodes.into_iter().peekable(),
+            position: TextSize::default(),
+            end,
+        }
+    }
+
+    fn build(mut self) -> CstNode {
+        let range = TextRange::up_to(self.end);
+        let mut root = self.build_node(CstNodeKind::Mod, range);
+        // Zero-width tokens at the very end, such as dedents
+        root.children.extend(self.tokens.map(CstElement::Token));
+        root
+    }
+
+    fn build_node(&mut self, kind: CstNodeKind, range: TextRange) -> CstNode {
+        let mut children = Vec::new();
+        loop {
+            let token = self
+                .tokens
+                .peek()
+                .filter(|token| token.range.start() < range.end());
+            let node = self
+                .nodes
+                .peek()
+                .filter(|(_, node)| range.contains_range(*node))
+                .copied();
+            match (node, token) {
+                (Some((_, node)), _) if node.start() < self.position => {
+                    // The node lies within a token, like the values of an f-string
+                    self.nodes.next();
+                }
+                (Some((kind, node)), token)
+                    if node.start() <= token.map_or(range.end(), |token| token.range.start()) =>
+                {
+                    self.nodes.next();
+                    children.push(CstElement::Node(self.build_node(kind, node)));
+                }
+                (_, Some(_)) => {
+                    let token = self.tokens.next().unwrap();
+                    self.position = token.range.end();
+                    children.push(CstElement::Token(token));
+                }
+                _ => break,
+            }
+        }
+        CstNode {
+            kind,
+            range,
+            children,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Ranged;
+
+    fn round_trip(source: &str) -> Cst {
+        let cst = Cst::parse(source, Mode::Module, "<test>").unwrap();
+        assert_eq!(cst.to_string(), source);
+        cst
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for source in [
+            "",
+            "x",
+            "\n\n# only a comment",
+            "x = 1  # comment\n\n\n",
+            "def f(a, /, b=1, *args, c: int = 2, **kwargs) -> None:\n\t\"\"\"Doc.\"\"\"\n\n\treturn (\n\t\ta  +\n\t\tb\n\t)\n",
+            "if x:\n    pass\n    # trailing comment\n  # dedented comment\nelif y :  # why\n    ...\nelse:\n    z = \\\n        1\n",
+            "@decorator\nclass A ( B , metaclass = M ) :\n    x : int = 1 ; y = 2\n",
+            "result = f'{x!r:>{width}} and {y}' 'more', rb'bytes'\n",
+            "match command.split():\n    case [\"go\", direction] if direction:\n        pass\n    case Point(x=0) | {\"k\": _, **rest}:\n        pass\n",
+            "try:\n    import a.b as c\nexcept* (E, F) as e:\n    from . import (d,\n        e)\nfinally:\n    lambda x, *y: x\n",
+            "type Alias[T: int, *Ts, **P] = dict[T, list[Ts]]\r\nx = [i for i in range(3) if i]\r\n",
+            "\u{feff}x = 'unicode: \u{e9}'\n",
+        ] {
+            round_trip(source);
+        }
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let source = "x = [1,  # one\n     2]\n";
+        let cst = round_trip(source);
+        let module = cst.ast().as_module().unwrap();
+        let assign = module.body[0].as_assign_stmt().unwrap();
+
+        let node = cst.node(assign.range()).unwrap();
+        assert_eq!(node.kind(), CstNodeKind::Stmt);
+        assert_eq!(cst.text(node.range()), "x = [1,  # one\n     2]");
+        // The newline ending the statement belongs to the module
+        let last = cst.root().children().last().unwrap().as_token().unwrap();
+        assert_eq!(last.kind(), &CstTokenKind::Tok(Tok::Newline));
+
+        let list = cst.node(assign.value.range()).unwrap();
+        assert_eq!(list.kind(), CstNodeKind::Expr);
+        assert_eq!(list.child_nodes().count(), 2);
+        let trivia: Vec<_> = list
+            .children()
+            .iter()
+            .filter_map(CstElement::as_token)
+            .filter(|token| token.is_trivia())
+            .map(|token| cst.text(token.range()))
+            .collect();
+        assert_eq!(trivia, ["  ", "# one", "\n", "     "]);
+
+        for target in &assign.targets {
+            assert_eq!(cst.node(target.range()).unwrap().kind(), CstNodeKind::Expr);
+        }
+    }
+
+    #[test]
+    fn test_every_node_is_found() {
+        let source = "def f(a, *, b=1):\n    return g(a, key=b)[1:2]\n";
+        let cst = round_trip(source);
+        let module = cst.ast().as_module().unwrap();
+        let def = module.body[0].as_function_def_stmt().unwrap();
+        let ret = def.body[0].as_return_stmt().unwrap();
+        let call = ret.value.as_deref().unwrap().as_subscript_expr().unwrap();
+        let call = call.value.as_call_expr().unwrap();
+
+        assert_eq!(
+            cst.node(def.args.args[0].def.range()).unwrap().kind(),
+            CstNodeKind::Arg
+        );
+        assert_eq!(
+            cst.node(call.keywords[0].range()).unwrap().kind(),
+            CstNodeKind::Keyword
+        );
+        assert_eq!(cst.text(call.range()), "g(a, key=b)");
+        assert!(cst.node(TextRange::new(1.into(), 2.into())).is_none());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(Cst::parse("x = (", Mode::Module, "<test>").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+//! Turn generic parse errors into targeted diagnostics.
+//!
+//! The grammar only knows which token it did not expect. The tokens the parser consumed are
+//! recorded alongside it, so that common mistakes like a missing comma or an unclosed bracket
+//! can be reported the way CPython reports them.
+use crate::{
+    lexer::LexicalErrorType,
+    parser::{ParseError, ParseErrorType},
+    text_size::{TextRange, TextSize},
+    token::Tok,
+};
+
+/// The part of a token that matters for diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Open(char),
+    Close(char),
+    Name,
+    /// The name `print`.
+    Print,
+    /// A literal, or one of the `True`, `False`, `None` and `...` keywords.
+    Atom,
+    Dot,
+    /// A token after which a new statement or clause starts.
+    LineStart,
+    Other,
+}
+
+impl TokenKind {
+    fn new(tok: &Tok) -> Self {
+        match tok {
+            Tok::Lpar => TokenKind::Open('('),
+            Tok::Lsqb => TokenKind::Open('['),
+            Tok::Lbrace => TokenKind::Open('{'),
+            Tok::Rpar => TokenKind::Close('('),
+            Tok::Rsqb => TokenKind::Close('['),
+            Tok::Rbrace => TokenKind::Close('{'),
+            Tok::Name { name } if name == "print" => TokenKind::Print,
+            Tok::Name { .. } => TokenKind::Name,
+            Tok::Int { .. }
+            | Tok::Float { .. }
+            | Tok::Complex { .. }
+            | Tok::String { .. }
+            | Tok::True
+            | Tok::False
+            | Tok::None
+            | Tok::Ellipsis => TokenKind::Atom,
+            Tok::Dot => TokenKind::Dot,
+            Tok::Newline
+            | Tok::Indent
+            | Tok::Dedent
+            | Tok::Semi
+            | Tok::Colon
+            | Tok::StartModule
+            | Tok::StartInteractive
+            | Tok::StartExpression => TokenKind::LineStart,
+            _ => TokenKind::Other,
+        }
+    }
+
+    /// Returns true if an expression can end with this token.
+    fn ends_expression(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Name | TokenKind::Print | TokenKind::Atom | TokenKind::Close(_)
+        )
+    }
+}
+
+/// The tokens fed to the parser, see [`TokenLog::record`].
+#[derive(Default)]
+pub(crate) struct TokenLog {
+    tokens: Vec<(TokenKind, TextRange)>,
+}
+
+impl TokenLog {
+    pub(crate) fn record(&mut self, tok: &Tok, range: TextRange) {
+        self.tokens.push((TokenKind::new(tok), range));
+    }
+
+    /// Replace `error` with a more specific diagnostic, if one applies.
+    pub(crate) fn refine(&self, mut error: ParseError) -> ParseError {
+        let refined = match &error.error {
+            ParseErrorType::Eof | ParseErrorType::Lexical(LexicalErrorType::Eof) => {
+                self.unclosed_bracket(error.offset)
+            }
+            ParseErrorType::UnrecognizedToken(Tok::Newline, expected)
+                if expected.iter().any(|e| e == "\":\"") =>
+            {
+                Some(ParseErrorType::ExpectedColon)
+            }
+            ParseErrorType::UnrecognizedToken(..) => self.unexpected_token(error.offset),
+            _ => None,
+        };
+        if let Some(refined) = refined {
+            if let Some(range) = refined.secondary_range() {
+                error.offset = error.offset.min(range.start());
+            }
+            error.error = refined;
+        }
+        error
+    }
+
+    /// The innermost bracket still open before `offset`.
+    fn unclosed_bracket(&self, offset: TextSize) -> Option<ParseErrorType> {
+        let mut open = Vec::new();
+        for &(kind, range) in self.tokens.iter().take_while(|(_, r)| r.start() < offset) {
+            match kind {
+                TokenKind::Open(c) => open.push((c, range)),
+                TokenKind::Close(_) => {
+                    open.pop();
+                }
+                _ => {}
+            }
+        }
+        let (c, range) = open.pop()?;
+        Some(ParseErrorType::UnclosedBracket(c, range))
+    }
+
+    fn unexpected_token(&self, offset: TextSize) -> Option<ParseErrorType> {
+        let index = self.tokens.iter().position(|(_, r)| r.start() == offset)?;
+        let (kind, range) = self.tokens[index];
+        let previous = index.checked_sub(1)?;
+        let (previous_kind, previous_range) = self.tokens[previous];
+
+        if !matches!(
+            kind,
+            TokenKind::Name | TokenKind::Print | TokenKind::Atom | TokenKind::Open('{')
+        ) {
+            return None;
+        }
+
+        if previous_kind == TokenKind::Print
+            && (previous == 0 || self.tokens[previous - 1].0 == TokenKind::LineStart)
+        {
+            return Some(ParseErrorType::PrintStatement(TextRange::new(
+                previous_range.start(),
+                range.end(),
+            )));
+        }
+
+        if previous_kind.ends_expression() && self.depth(previous) > 0 {
+            let start = self.expression_start(previous)?;
+            return Some(ParseErrorType::MissingComma(TextRange::new(
+                self.tokens[start].1.start(),
+                range.end(),
+            )));
+        }
+        None
+    }
+
+    /// The number of brackets open after the token at `index`.
+    fn depth(&self, index: usize) -> usize {
+        self.tokens[..=index]
+            .iter()
+            .fold(0usize, |depth, (kind, _)| match kind {
+                TokenKind::Open(_) => depth + 1,
+                TokenKind::Close(_) => depth.saturating_sub(1),
+                _ => depth,
+            })
+    }
+
+    /// Walk back from the token at `end` to the first token of the primary expression it ends,
+    /// e.g. `a.b(c)[d]`.
+    fn expression_start(&self, end: usize) -> Option<usize> {
+        let mut index = end;
+        loop {
+            if let TokenKind::Close(_) = self.tokens[index].0 {
+                index = self.matching_open(index)?;
+                if index > 0 && self.tokens[index - 1].0.ends_expression() {
+                    index -= 1;
+                    continue;
+                }
+                return Some(index);
+            }
+            match index.checked_sub(1).map(|i| self.tokens[i].0) {
+                Some(TokenKind::Dot) if index >= 2 => index -= 2,
+                // Adjacent string literals are concatenated
+                Some(TokenKind::Atom) if self.tokens[index].0 == TokenKind::Atom => index -= 1,
+                _ => return Some(index),
+            }
+        }
+    }
+
+    fn matching_open(&self, close: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for index in (0..close).rev() {
+            match self.tokens[index].0 {
+                TokenKind::Close(_) => depth += 1,
+                TokenKind::Open(_) if depth == 0 => return Some(index),
+                TokenKind::Open(_) => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+}
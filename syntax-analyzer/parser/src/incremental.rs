@@ -0,0 +1,400 @@
+//! Incremental re-parsing of a module after a text edit.
+//!
+//! An editor changes a few characters at a time, and parsing a large module again on every
+//! keystroke is slow. [`reparse_program`] takes the tree of the module before an edit and parses
+//! again only the top-level statements the edit touches. The statements before them are reused
+//! as they are and the ranges of the ones after them are moved by the length the edit added or
+//! removed.
+//!
+//! The result is always the same as parsing the new source from scratch. An edit can change how
+//! the code around it is read, for instance by opening a bracket or a string, or by indenting a
+//! line into the body of the statement above it. The touched statements then don't parse on
+//! their own, and the whole module is parsed instead.
+//!
+//! # Example
+//!
+//! ```
+//! use rustpython_parser::{
+//!     ast, incremental::{reparse_program, TextEdit}, text_size::TextRange, Parse,
+//! };
+//!
+//! let source = "x = 1\ny = 2\n";
+//! let suite = ast::Suite::parse(source, "<embedded>").unwrap();
+//!
+//! // Replace the `1` with `100`
+//! let edit = TextEdit::new(TextRange::new(4.into(), 5.into()), "100");
+//! let source = edit.apply(source);
+//! let suite = reparse_program(suite, &edit, &source, "<embedded>").unwrap();
+//! assert_eq!(suite, ast::Suite::parse(&source, "<embedded>").unwrap());
+//! ```
+use crate::{
+    ast::{self, fold::Fold, Ranged},
+    parser::{Parse, ParseError},
+    text_size::{TextLen, TextRange, TextSize},
+};
+use std::convert::Infallible;
+
+/// A change to a source text: the text in `range` is replaced with `replacement`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    /// The range replaced, in the text before the edit.
+    pub range: TextRange,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: TextRange, replacement: impl Into<String>) -> Self {
+        TextEdit {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Insert `text` at `offset`.
+    pub fn insert(offset: TextSize, text: impl Into<String>) -> Self {
+        Self::new(TextRange::empty(offset), text)
+    }
+
+    /// Remove the text in `range`.
+    pub fn delete(range: TextRange) -> Self {
+        Self::new(range, String::new())
+    }
+
+    /// The source text after the edit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range of the edit is not in `source`.
+    pub fn apply(&self, source: &str) -> String {
+        let mut edited = String::with_capacity(
+            source.len() - self.range.len().to_usize() + self.replacement.len(),
+        );
+        edited.push_str(&source[..self.range.start().to_usize()]);
+        edited.push_str(&self.replacement);
+        edited.push_str(&source[self.range.end().to_usize()..]);
+        edited
+    }
+
+    /// The end of the replacement, in the text after the edit.
+    fn new_end(&self) -> TextSize {
+        self.range.start() + self.replacement.text_len()
+    }
+
+    /// Move an offset after the edit to where it is in the text after the edit.
+    fn shift(&self, offset: TextSize) -> TextSize {
+        debug_assert!(offset >= self.range.end());
+        offset - self.range.end() + self.new_end()
+    }
+}
+
+/// Parse a module again after an edit, reusing the statements of its `previous` tree that the
+/// edit didn't touch.
+///
+/// `previous` must be the result of parsing the source the edit was made on, and `source` is
+/// the text after the edit. The result is the same as [`ast::Suite::parse`] of `source`,
+/// errors included.
+pub fn reparse_program(
+    previous: ast::Suite,
+    edit: &TextEdit,
+    source: &str,
+    source_path: &str,
+) -> Result<ast::Suite, ParseError> {
+    let region = Region::find(&previous, edit, source);
+    if region.range == TextRange::up_to(source.text_len()) {
+        return ast::Suite::parse(source, source_path);
+    }
+    let text = &source[region.range];
+    // A line continuation at the end would join the next statement to the last line, and a
+    // byte order mark is only skipped at the start of the whole source
+    if text.trim_end_matches(['\r', '\n']).ends_with('\\') || text.starts_with('\u{feff}') {
+        return ast::Suite::parse(source, source_path);
+    }
+    let Ok(body) = ast::Suite::parse_starts_at(text, source_path, region.range.start()) else {
+        // The edit changes how the statements around it are read
+        return ast::Suite::parse(source, source_path);
+    };
+
+    let mut suite = previous;
+    let after: Vec<_> = suite
+        .drain(region.after..)
+        .map(|stmt| {
+            Shift { edit }
+                .fold_stmt(stmt)
+                .unwrap_or_else(|never| match never {})
+        })
+        .collect();
+    suite.truncate(region.before);
+    suite.extend(body);
+    suite.extend(after);
+    Ok(suite)
+}
+
+/// The part of the source that has to be parsed again after an edit.
+#[derive(Debug, PartialEq, Eq)]
+struct Region {
+    /// The number of statements kept from the start of the module.
+    before: usize,
+    /// The index of the first statement kept after the edit.
+    after: usize,
+    /// The lines to parse, in the text after the edit.
+    range: TextRange,
+}
+
+impl Region {
+    /// The lines from the end of the last statement before the edit to the start of the first
+    /// statement after it. Each statement kept has to be on lines of its own, so that the
+    /// region starts and ends at a logical line in the whole source too.
+    fn find(previous: &[ast::Stmt], edit: &TextEdit, source: &str) -> Self {
+        let mut before = 0;
+        let mut start = TextSize::default();
+        for (index, stmt) in previous.iter().enumerate() {
+            if stmt.end() >= edit.range.start() {
+                break;
+            }
+            // The edit can extend a statement that ends on the line it starts on
+            let Some(line_end) =
+                line_end(source, stmt.end()).filter(|&end| end < edit.range.start())
+            else {
+                break;
+            };
+            if previous
+                .get(index + 1)
+                .is_some_and(|next| statement_start(next) < line_end)
+            {
+                break;
+            }
+            before = index + 1;
+            start = line_end;
+        }
+
+        let mut after = previous.len();
+        let mut end = source.text_len();
+        for (index, stmt) in previous.iter().enumerate().skip(before).rev() {
+            let stmt_start = statement_start(stmt);
+            if stmt_start <= edit.range.end() {
+                break;
+            }
+            let Some(line_start) = line_start(source, edit.shift(stmt_start))
+                .filter(|&line_start| line_start > edit.new_end())
+            else {
+                break;
+            };
+            after = index;
+            end = line_start;
+        }
+
+        Region {
+            before,
+            after,
+            range: TextRange::new(start, end),
+        }
+    }
+}
+
+/// Where a statement starts, including its decorators.
+fn statement_start(stmt: &ast::Stmt) -> TextSize {
+    let decorators = match stmt {
+        ast::Stmt::FunctionDef(ast::StmtFunctionDef { decorator_list, .. })
+        | ast::Stmt::AsyncFunctionDef(ast::StmtAsyncFunctionDef { decorator_list, .. })
+        | ast::Stmt::ClassDef(ast::StmtClassDef { decorator_list, .. }) => decorator_list,
+        _ => return stmt.start(),
+    };
+    decorators
+        .first()
+        .map_or(stmt.start(), |decorator| decorator.start())
+}
+
+/// The start of the line after the end of a statement at `offset`, if the rest of the line is
+/// only a semicolon, whitespace and a comment.
+fn line_end(source: &str, offset: TextSize) -> Option<TextSize> {
+    let rest = &source[offset.to_usize()..];
+    let newline = rest.find(['\n', '\r']).unwrap_or(rest.len());
+    let code = rest[..newline].split('#').next().unwrap_or_default();
+    if !code.chars().all(|c| matches!(c, ' ' | '\t' | '\x0c' | ';')) {
+        return None;
+    }
+    let newline_len = if rest[newline..].starts_with("\r\n") {
+        2
+    } else {
+        rest[newline..].len().min(1)
+    };
+    Some(offset + TextSize::try_from(newline + newline_len).unwrap())
+}
+
+/// The start of the line of a statement at `offset`, if only whitespace and the `@` of a
+/// decorator come before it on the line.
+fn line_start(source: &str, offset: TextSize) -> Option<TextSize> {
+    let line = &source[..offset.to_usize()];
+    let indent = line.len() - line.trim_end_matches([' ', '\t', '\x0c', '@']).len();
+    let start = line.len() - indent;
+    if start == 0 || line[..start].ends_with(['\n', '\r']) {
+        Some(TextSize::try_from(start).unwrap())
+    } else {
+        None
+    }
+}
+
+/// Moves the ranges of the statements after an edit.
+struct Shift<'a> {
+    edit: &'a TextEdit,
+}
+
+impl Fold<TextRange> for Shift<'_> {
+    type TargetU = TextRange;
+    type Error = Infallible;
+    type UserContext = ();
+
+    fn will_map_user(&mut self, _user: &TextRange) {}
+
+    fn map_user(&mut self, user: TextRange, _context: ()) -> Result<TextRange, Infallible> {
+        Ok(TextRange::new(
+            self.edit.shift(user.start()),
+            self.edit.shift(user.end()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edit` to `source` incrementally, and check the result against a full parse.
+    fn reparse(source: &str, edit: &TextEdit) -> Result<ast::Suite, ParseError> {
+        let previous = ast::Suite::parse(source, "<test>").unwrap();
+        let edited = edit.apply(source);
+        let result = reparse_program(previous, edit, &edited, "<test>");
+        assert_eq!(
+            result,
+            ast::Suite::parse(&edited, "<test>"),
+            "{edited:?} differs from a full parse"
+        );
+        result
+    }
+
+    fn region(source: &str, edit: &TextEdit) -> (usize, usize, String) {
+        let previous = ast::Suite::parse(source, "<test>").unwrap();
+        let edited = edit.apply(source);
+        let region = Region::find(&previous, edit, &edited);
+        (region.before, region.after, edited[region.range].to_owned())
+    }
+
+    fn range(start: u32, end: u32) -> TextRange {
+        TextRange::new(start.into(), end.into())
+    }
+
+    #[test]
+    fn test_region() {
+        let source = "a = 1\n\nb = 2  # two\nc = 3\n";
+        // `2` becomes `20`
+        assert_eq!(
+            region(source, &TextEdit::new(range(11, 12), "20")),
+            (1, 2, "\nb = 20  # two\n".to_owned())
+        );
+        // A new statement between `a` and `b`
+        assert_eq!(
+            region(source, &TextEdit::insert(6.into(), "x = 0\n")),
+            (0, 1, "a = 1\nx = 0\n\n".to_owned())
+        );
+        // The comment is edited
+        assert_eq!(
+            region(source, &TextEdit::new(range(16, 19), "2")),
+            (1, 2, "\nb = 2  # 2\n".to_owned())
+        );
+        // Statements sharing a line are parsed again together
+        let source = "a = 1; b = 2\nc = 3\n";
+        assert_eq!(
+            region(source, &TextEdit::new(range(11, 12), "4")),
+            (0, 2, "a = 1; b = 4\n".to_owned())
+        );
+        let source = "@decorator\ndef f(): pass\nx = 1\n";
+        assert_eq!(
+            region(source, &TextEdit::new(range(0, 0), "y = 0\n")),
+            (0, 1, "y = 0\n@decorator\ndef f(): pass\n".to_owned())
+        );
+        // A continued line joins the next one
+        let source = "a = 1 \\\n\nb = 2\n";
+        assert_eq!(region(source, &TextEdit::insert(8.into(), "+ 1")).0, 0);
+    }
+
+    #[test]
+    fn test_ranges_are_moved() {
+        let source = "x = 1\ndef f(a):\n    return a\n";
+        let suite = reparse(source, &TextEdit::new(range(4, 5), "1000")).unwrap();
+        let def = suite[1].as_function_def_stmt().unwrap();
+        assert_eq!(def.range, range(9, 31));
+        assert_eq!(def.body[0].range(), range(23, 31));
+
+        let suite = reparse(source, &TextEdit::delete(range(0, 6))).unwrap();
+        assert_eq!(suite.len(), 1);
+        assert_eq!(suite[0].range(), range(0, 22));
+    }
+
+    #[test]
+    fn test_same_as_full_parse() {
+        let source = "\
+import os
+
+def f(a, b=1):
+    '''Doc.'''
+    return a + b
+
+class A(B):
+    x = [
+        1,
+        2,
+    ]
+
+    @property
+    def y(self):
+        return self.x  # trailing
+
+if os.name:
+    pass
+else:
+    z = f(1); w = 2
+print(f'{z}')
+";
+        let edits = [
+            // Edits inside statements
+            TextEdit::new(range(27, 28), "2"),
+            TextEdit::insert(61.into(), "3,\n        "),
+            TextEdit::new(range(141, 151), "# changed"),
+            TextEdit::insert(source.text_len(), "y = 1\n"),
+            // Edits between statements
+            TextEdit::insert(10.into(), "\n"),
+            TextEdit::insert(45.into(), "# a comment\n"),
+            TextEdit::delete(range(44, 45)),
+            // Edits that change how the code after them is read
+            TextEdit::insert(45.into(), "x = (\n"),
+            TextEdit::insert(45.into(), "x = '''\n"),
+            TextEdit::insert(45.into(), "    y = 1\n"),
+            TextEdit::new(range(152, 161), "elif x:\n    "),
+            TextEdit::insert(152.into(), "else:\n    pass\n"),
+            TextEdit::insert(9.into(), " \\"),
+            TextEdit::delete(range(8, 10)),
+            TextEdit::delete(range(0, source.len() as u32)),
+        ];
+        for edit in &edits {
+            let _ = reparse(source, edit);
+        }
+    }
+
+    #[test]
+    fn test_every_edit() {
+        // Remove and duplicate every character in turn
+        let source = "if x:\n    y = [1,\n  2]\nelse: z = 'a#b'  # c\n@d\nclass C: pass\n";
+        for offset in 0..source.len() as u32 {
+            let _ = reparse(source, &TextEdit::delete(range(offset, offset + 1)));
+            let character = &source[offset as usize..offset as usize + 1];
+            let _ = reparse(source, &TextEdit::insert(offset.into(), character));
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let edit = TextEdit::new(range(1, 3), "xyz");
+        assert_eq!(edit.apply("abcd"), "axyzd");
+        assert_eq!(edit.shift(3.into()), 4.into());
+        assert_eq!(TextEdit::delete(range(0, 2)).shift(3.into()), 1.into());
+    }
+}
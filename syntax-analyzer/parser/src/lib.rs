@@ -9,13 +9,21 @@ pub use rustpython_parser_core::{text_size, Mode};
 mod function;
 // Skip flattening lexer to distinguish from full parser
 mod context;
+#[cfg(feature = "cst")]
+pub mod cst;
+mod diagnostics;
+#[cfg(feature = "incremental")]
+pub mod incremental;
 pub mod lexer;
 mod parser;
 mod soft_keywords;
 mod string;
 mod token;
 
-pub use parser::{parse, parse_starts_at, parse_tokens, Parse, ParseError, ParseErrorType};
+pub use parser::{
+    parse, parse_recovering, parse_starts_at, parse_tokens, parse_tokens_recovering, Parse,
+    ParseError, ParseErrorType, TargetKind,
+};
 pub use string::FStringErrorType;
 pub use token::{StringKind, Tok};
 
@@ -1,5 +1,7 @@
 use crate::{
     ast::{self, OptionalRange, Ranged},
+    context::{check_assignment_targets, check_expr_targets},
+    diagnostics::TokenLog,
     lexer::{self, LexResult, LexicalError, LexicalErrorType},
     python,
     text_size::TextSize,
@@ -7,11 +9,12 @@ use crate::{
     Mode,
 };
 use itertools::Itertools;
-use std::iter;
+use std::{cell::Cell, iter};
 
 use crate::{lexer::Lexer, soft_keywords::SoftKeywordTransformer, text_size::TextRange};
 pub(super) use lalrpop_util::ParseError as LalrpopError;
 
+type LalrpopErrorRecovery = lalrpop_util::ErrorRecovery<TextSize, Tok, LexicalError>;
 
 pub trait Parse
 where
@@ -380,15 +383,210 @@ fn parse_filtered_tokens(
     mode: Mode,
     source_path: &str,
 ) -> Result<ast::Mod, ParseError> {
+    // lalrpop only commits a recovered syntax error once the recovery is done, so a lexical
+    // error that ends the parse while a recovery is pending would hide the syntax error before
+    // it. Ending the token stream at the lexical error instead lets the recovery finish, or fail
+    // with the syntax error it was recovering from.
+    let mut lexical_error = None;
+    let lxr = lxr
+        .into_iter()
+        .map_while(|result| match result {
+            Ok(token) => Some(token),
+            Err(error) => {
+                lexical_error = Some(error);
+                None
+            }
+        })
+        .map(Ok);
+
+    let mut recovered = Vec::new();
+    let mut tokens = TokenLog::default();
+    let result = parse_top(lxr, mode, &mut recovered, &mut tokens);
+
+    // The grammar recovers from syntax errors, but only the first error is reported here.
+    let mut errors: Vec<_> = recovered
+        .into_iter()
+        .map(|recovery| recovery.error)
+        .collect();
+    let ast = match result {
+        Ok(ast) => Some(ast),
+        // Running out of tokens is not an error of its own when the lexer stopped early
+        Err(LalrpopError::UnrecognizedEof { .. }) if lexical_error.is_some() => None,
+        Err(error) => {
+            errors.push(error);
+            None
+        }
+    };
+    errors.extend(lexical_error.map(|error| LalrpopError::User { error }));
+    let first_error = errors
+        .into_iter()
+        .map(|error| tokens.refine(parse_error_from_lalrpop(error, source_path)))
+        .reduce(|first, error| {
+            if error.offset < first.offset {
+                error
+            } else {
+                first
+            }
+        });
+    if let Some(error) = first_error {
+        return Err(error);
+    }
+    let ast = ast.expect("a failed parse has an error");
+    let mut errors = Vec::new();
+    check_module_targets(&ast, source_path, &mut errors);
+    match errors.into_iter().min_by_key(|error| error.offset) {
+        Some(error) => Err(error),
+        None => Ok(ast),
+    }
+}
+
+fn parse_top(
+    lxr: impl IntoIterator<Item = LexResult>,
+    mode: Mode,
+    recovered: &mut Vec<LalrpopErrorRecovery>,
+    tokens: &mut TokenLog,
+) -> Result<ast::Mod, LalrpopError<TextSize, Tok, LexicalError>> {
     let marker_token = (Tok::start_marker(mode), Default::default());
     let lexer = iter::once(Ok(marker_token)).chain(lxr);
-    python::TopParser::new()
-        .parse(
-            lexer
-                .into_iter()
-                .map_ok(|(t, range)| (range.start(), t, range.end())),
-        )
-        .map_err(|e| parse_error_from_lalrpop(e, source_path))
+    python::TopParser::new().parse(
+        recovered,
+        lexer
+            .into_iter()
+            .inspect(|result| {
+                if let Ok((tok, range)) = result {
+                    tokens.record(tok, *range);
+                }
+            })
+            .map_ok(|(t, range)| (range.start(), t, range.end())),
+    )
+}
+
+fn check_module_targets(ast: &ast::Mod, source_path: &str, errors: &mut Vec<ParseError>) {
+    match ast {
+        ast::Mod::Module(ast::ModModule { body, .. })
+        | ast::Mod::Interactive(ast::ModInteractive { body, .. }) => {
+            check_assignment_targets(body, source_path, errors)
+        }
+        ast::Mod::Expression(ast::ModExpression { body, .. }) => {
+            check_expr_targets(body, source_path, errors)
+        }
+        ast::Mod::FunctionType(_) => {}
+    }
+}
+
+/// Parse the given Python source code using the specified [`Mode`], recovering from errors.
+///
+/// Where [`parse`] stops at the first error, this function reports every syntax error in
+/// the source, sorted by offset. A statement that failed to parse is replaced by an
+/// [`ast::Stmt::Invalid`] node and a bracketed expression by an [`ast::Expr::Invalid`] node.
+/// The AST is `None` when the parser could not recover, e.g. at an unexpected end of file.
+///
+/// # Example
+///
+/// ```
+/// use rustpython_parser::{ast, Mode, parse_recovering};
+///
+/// let source = r#"
+/// x = (1 +)
+/// def f(x y):
+///     pass
+/// y = 2
+/// "#;
+/// let (program, errors) = parse_recovering(source, Mode::Module, "<embedded>");
+/// assert_eq!(errors.len(), 2);
+/// let ast::Mod::Module(module) = program.unwrap() else { panic!() };
+/// assert_eq!(module.body.len(), 3);
+/// assert!(module.body[1].is_invalid_stmt());
+/// ```
+pub fn parse_recovering(
+    source: &str,
+    mode: Mode,
+    source_path: &str,
+) -> (Option<ast::Mod>, Vec<ParseError>) {
+    let lxr = lexer::lex(source, mode);
+    parse_tokens_recovering(lxr, mode, source_path)
+}
+
+/// Parse an iterator of [`LexResult`]s using the specified [`Mode`], recovering from errors.
+///
+/// This is the token based version of [`parse_recovering`]. Tokens that fail to lex are
+/// reported and skipped.
+pub fn parse_tokens_recovering(
+    lxr: impl IntoIterator<Item = LexResult>,
+    mode: Mode,
+    source_path: &str,
+) -> (Option<ast::Mod>, Vec<ParseError>) {
+    let lxr = lxr.into_iter();
+    #[cfg(feature = "full-lexer")]
+    let lxr =
+        lxr.filter_ok(|(tok, _)| !matches!(tok, Tok::Comment { .. } | Tok::NonLogicalNewline));
+
+    let mut lexical_errors: Vec<LexicalError> = Vec::new();
+    let truncated = Cell::new(None);
+    let depth = Cell::new(0usize);
+    // A lexer that keeps failing at the same location can't make progress, so the rest of the
+    // source is dropped. The logical line and the blocks that were open there are closed, so
+    // that a recovery from an earlier syntax error can still finish.
+    let lxr = lxr
+        .map_while(|result| match result {
+            Ok((tok, range)) => {
+                match tok {
+                    Tok::Indent => depth.set(depth.get() + 1),
+                    Tok::Dedent => depth.set(depth.get().saturating_sub(1)),
+                    _ => {}
+                }
+                Some(Some(Ok((tok, range))))
+            }
+            Err(error) => {
+                if lexical_errors
+                    .last()
+                    .is_some_and(|last| last.location == error.location)
+                {
+                    truncated.set(Some(error.location));
+                    return None;
+                }
+                lexical_errors.push(error);
+                Some(None)
+            }
+        })
+        .flatten()
+        .chain(
+            iter::once(())
+                .flat_map(|()| truncated.get())
+                .flat_map(|location| closing_tokens(location, depth.get())),
+        );
+
+    let mut recovered = Vec::new();
+    let mut tokens = TokenLog::default();
+    let result = parse_top(lxr, mode, &mut recovered, &mut tokens);
+
+    let mut errors: Vec<_> = lexical_errors
+        .into_iter()
+        .map(|error| LalrpopError::User { error })
+        .chain(recovered.into_iter().map(|recovery| recovery.error))
+        .map(|error| tokens.refine(parse_error_from_lalrpop(error, source_path)))
+        .collect();
+    let ast = match result {
+        Ok(ast) => {
+            check_module_targets(&ast, source_path, &mut errors);
+            Some(ast)
+        }
+        // Running out of tokens is not an error of its own when the source was cut short
+        Err(LalrpopError::UnrecognizedEof { .. }) if truncated.get().is_some() => None,
+        Err(error) => {
+            errors.push(tokens.refine(parse_error_from_lalrpop(error, source_path)));
+            None
+        }
+    };
+    errors.sort_by_key(|error| error.offset);
+    (ast, errors)
+}
+
+/// The tokens that end the input at `location` while `depth` blocks are open.
+fn closing_tokens(location: TextSize, depth: usize) -> impl Iterator<Item = LexResult> {
+    iter::once(Tok::Newline)
+        .chain((0..depth).map(|_| Tok::Dedent))
+        .map(move |tok| Ok((tok, TextRange::empty(location))))
 }
 
 /// Represents represent errors that occur during parsing and are
@@ -404,11 +602,45 @@ pub enum ParseErrorType {
     ExtraToken(Tok),
     /// Parser encountered an invalid token
     InvalidToken,
-    /// Parser encountered an unexpected token
-    UnrecognizedToken(Tok, Option<String>),
+    /// Parser encountered an unexpected token, along with the tokens it expected instead
+    UnrecognizedToken(Tok, Vec<String>),
     // Maps to `User` type from `lalrpop-util`
     /// Parser encountered an error during lexing.
     Lexical(LexicalErrorType),
+    /// A compound statement header is missing its trailing `:`.
+    ExpectedColon,
+    /// A bracket was still open at the end of the input. Holds the bracket and its range.
+    UnclosedBracket(char, TextRange),
+    /// Two expressions inside brackets are not separated by a comma. Holds the range spanning
+    /// both expressions.
+    MissingComma(TextRange),
+    /// A Python 2 style `print` statement. Holds the range of the statement so far.
+    PrintStatement(TextRange),
+    /// The target of an assignment can't be assigned to, e.g. a literal or a function call.
+    InvalidAssignmentTarget {
+        /// A description of the target, see [`ast::Expr::python_name`].
+        target: &'static str,
+        /// The range of the target.
+        range: TextRange,
+        /// How the target is assigned to.
+        kind: TargetKind,
+    },
+}
+
+/// How the target of an [`ParseErrorType::InvalidAssignmentTarget`] error is assigned to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetKind {
+    /// The whole target of an `=` assignment, where `==` may have been meant.
+    Assign,
+    /// Any other binding target: an element of a tuple or list target, the target of a `for`
+    /// loop or the target of `with ... as`.
+    Bind,
+    /// A target of a `del` statement.
+    Delete,
+    /// The target of an augmented assignment like `+=`.
+    AugAssign,
+    /// The target of an annotated assignment.
+    Annotation,
 }
 
 impl std::error::Error for ParseErrorType {}
@@ -437,16 +669,11 @@ fn parse_error_from_lalrpop(
             offset: error.location,
             source_path,
         },
-        LalrpopError::UnrecognizedToken { token, expected } => {
-            // Hacky, but it's how CPython does it. See PyParser_AddToken,
-            // in particular "Only one possible expected token" comment.
-            let expected = (expected.len() == 1).then(|| expected[0].clone());
-            ParseError {
-                error: ParseErrorType::UnrecognizedToken(token.1, expected),
-                offset: token.0,
-                source_path,
-            }
-        }
+        LalrpopError::UnrecognizedToken { token, expected } => ParseError {
+            error: ParseErrorType::UnrecognizedToken(token.1, expected),
+            offset: token.0,
+            source_path,
+        },
         LalrpopError::UnrecognizedEof { location, expected } => {
             // This could be an initial indentation error that we should ignore
             let indent_error = expected == ["Indent"];
@@ -474,15 +701,44 @@ impl std::fmt::Display for ParseErrorType {
             ParseErrorType::ExtraToken(ref tok) => write!(f, "Got extraneous token: {tok:?}"),
             ParseErrorType::InvalidToken => write!(f, "Got invalid token"),
             ParseErrorType::UnrecognizedToken(ref tok, ref expected) => {
+                // Hacky, but it's how CPython does it. See PyParser_AddToken,
+                // in particular "Only one possible expected token" comment.
                 if *tok == Tok::Indent {
                     write!(f, "unexpected indent")
-                } else if expected.as_deref() == Some("Indent") {
+                } else if expected == &["Indent"] {
                     write!(f, "expected an indented block")
                 } else {
                     write!(f, "invalid syntax. Got unexpected token {tok}")
                 }
             }
             ParseErrorType::Lexical(ref error) => write!(f, "{error}"),
+            ParseErrorType::ExpectedColon => write!(f, "expected ':'"),
+            ParseErrorType::UnclosedBracket(bracket, _) => {
+                write!(f, "'{bracket}' was never closed")
+            }
+            ParseErrorType::MissingComma(_) => {
+                write!(f, "invalid syntax. Perhaps you forgot a comma?")
+            }
+            ParseErrorType::PrintStatement(_) => write!(
+                f,
+                "Missing parentheses in call to 'print'. Did you mean print(...)?"
+            ),
+            ParseErrorType::InvalidAssignmentTarget { target, kind, .. } => match kind {
+                TargetKind::Assign => write!(
+                    f,
+                    "cannot assign to {target} here. Maybe you meant '==' instead of '='?"
+                ),
+                TargetKind::Bind => write!(f, "cannot assign to {target}"),
+                TargetKind::Delete => write!(f, "cannot delete {target}"),
+                TargetKind::AugAssign => write!(
+                    f,
+                    "'{target}' is an illegal expression for augmented assignment"
+                ),
+                TargetKind::Annotation if matches!(target, "tuple" | "list") => {
+                    write!(f, "only single target (not {target}) can be annotated")
+                }
+                TargetKind::Annotation => write!(f, "illegal target for annotation"),
+            },
         }
     }
 }
@@ -493,12 +749,24 @@ impl ParseErrorType {
         match self {
             ParseErrorType::Lexical(LexicalErrorType::IndentationError) => true,
             ParseErrorType::UnrecognizedToken(token, expected) => {
-                *token == Tok::Indent || expected.clone() == Some("Indent".to_owned())
+                *token == Tok::Indent || expected == &["Indent"]
             }
             _ => false,
         }
     }
 
+    /// Returns the source range the error refers to besides its offset, if any, e.g. the
+    /// opening bracket of an unclosed bracket.
+    pub fn secondary_range(&self) -> Option<TextRange> {
+        match *self {
+            ParseErrorType::UnclosedBracket(_, range)
+            | ParseErrorType::MissingComma(range)
+            | ParseErrorType::PrintStatement(range)
+            | ParseErrorType::InvalidAssignmentTarget { range, .. } => Some(range),
+            _ => None,
+        }
+    }
+
     /// Returns true if the error is a tab error.
     pub fn is_tab_error(&self) -> bool {
         matches!(
@@ -783,6 +1051,274 @@ with (0 as a, 1 as b,): pass
         }
     }
 
+    #[test]
+    fn test_parse_recovering() {
+        let source = "\
+x = (1 +)
+def f(x y):
+    pass
+y = 2 3
+z = 4
+";
+        let (parse_ast, errors) = parse_recovering(source, Mode::Module, "<test>");
+        let offsets: Vec<u32> = errors.iter().map(|e| e.offset.into()).collect();
+        assert_eq!(offsets, [8, 16, 37]);
+        assert!(matches!(
+            &errors[0].error,
+            ParseErrorType::UnrecognizedToken(Tok::Rpar, expected) if !expected.is_empty()
+        ));
+        assert!(matches!(errors[1].error, ParseErrorType::MissingComma(_)));
+        assert!(matches!(
+            errors[2].error,
+            ParseErrorType::UnrecognizedToken(Tok::Int { .. }, _)
+        ));
+
+        let ast::Mod::Module(module) = parse_ast.unwrap() else {
+            panic!("expected a module");
+        };
+        assert_eq!(module.body.len(), 4);
+        let ast::Stmt::Assign(assign) = &module.body[0] else {
+            panic!("expected an assignment");
+        };
+        assert!(assign.value.is_invalid_expr());
+        assert!(module.body[1].is_invalid_stmt());
+        assert!(module.body[2].is_invalid_stmt());
+        assert!(module.body[3].is_assign_stmt());
+
+        // Without recovery, the first error is reported
+        let error = parse(source, Mode::Module, "<test>").unwrap_err();
+        assert_eq!(error, errors.into_iter().next().unwrap());
+    }
+
+    #[test]
+    fn test_parse_recovering_lexical_error() {
+        let (parse_ast, errors) = parse_recovering("x = 1 $\ny = 1 2\n", Mode::Module, "<test>");
+        assert!(parse_ast.is_some());
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].error, ParseErrorType::Lexical(_)));
+        assert!(matches!(
+            errors[1].error,
+            ParseErrorType::UnrecognizedToken(Tok::Int { .. }, _)
+        ));
+    }
+
+    #[test]
+    fn test_lexical_error_during_recovery() {
+        let source = "def f(:\n    x = 'abc\n";
+        let error = parse(source, Mode::Module, "<test>").unwrap_err();
+        assert_eq!(u32::from(error.offset), 6);
+        assert!(!matches!(error.error, ParseErrorType::Lexical(_)));
+
+        let (parse_ast, errors) = parse_recovering(source, Mode::Module, "<test>");
+        assert!(parse_ast.is_some());
+        let offsets: Vec<u32> = errors.iter().map(|e| e.offset.into()).collect();
+        assert_eq!(offsets[0], 6);
+        assert!(matches!(
+            errors.last().unwrap().error,
+            ParseErrorType::Lexical(_)
+        ));
+    }
+
+    fn parse_error(source: &str) -> ParseError {
+        parse(source, Mode::Module, "<test>").unwrap_err()
+    }
+
+    #[test]
+    fn test_expected_colon() {
+        for source in [
+            "if x\n    pass\n",
+            "def f()\n    pass\n",
+            "while True\n    pass\n",
+        ] {
+            let error = parse_error(source);
+            assert_eq!(error.error, ParseErrorType::ExpectedColon);
+            assert_eq!(
+                error.to_string(),
+                format!("expected ':' at byte offset {}", source.find('\n').unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_unclosed_bracket() {
+        let error = parse_error("x = (1,\ny = [2]\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::UnclosedBracket('(', TextRange::new(4.into(), 5.into()))
+        );
+        assert_eq!(error.offset, TextSize::from(4));
+        assert_eq!(error.error.to_string(), "'(' was never closed");
+
+        let error = parse_error("x = {1: [2]\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::UnclosedBracket('{', TextRange::new(4.into(), 5.into()))
+        );
+    }
+
+    #[test]
+    fn test_missing_comma() {
+        let error = parse_error("x = [a b]\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::MissingComma(TextRange::new(5.into(), 8.into()))
+        );
+        assert_eq!(
+            error.error.to_string(),
+            "invalid syntax. Perhaps you forgot a comma?"
+        );
+
+        let error = parse_error("f(a.b(1)[2] 'c')\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::MissingComma(TextRange::new(2.into(), 15.into()))
+        );
+
+        // Outside of brackets there is nothing to separate
+        let error = parse_error("x = a b\n");
+        assert!(matches!(error.error, ParseErrorType::UnrecognizedToken(..)));
+    }
+
+    #[test]
+    fn test_print_statement() {
+        let error = parse_error("if x:\n    print \"x\"\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::PrintStatement(TextRange::new(10.into(), 19.into()))
+        );
+        assert_eq!(error.offset, TextSize::from(10));
+        assert_eq!(
+            error.error.to_string(),
+            "Missing parentheses in call to 'print'. Did you mean print(...)?"
+        );
+        assert!(parse("print(x)\nprint = 1\n", Mode::Module, "<test>").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let error = parse_error("f() = 1\n");
+        assert_eq!(
+            error.error,
+            ParseErrorType::InvalidAssignmentTarget {
+                target: "function call",
+                range: TextRange::new(0.into(), 3.into()),
+                kind: TargetKind::Assign,
+            }
+        );
+        assert_eq!(
+            error.error.to_string(),
+            "cannot assign to function call here. Maybe you meant '==' instead of '='?"
+        );
+
+        let error = parse_error("for x in y:\n    a, [*b, 1] = z\n");
+        assert_eq!(error.offset, TextSize::from(24));
+        assert_eq!(error.error.to_string(), "cannot assign to literal");
+
+        assert!(parse("a, (b.c, *d[0]) = [e] = f = 1\n", Mode::Module, "<test>").is_ok());
+
+        let (parse_ast, errors) =
+            parse_recovering("1 = x\ny = 2\nNone = z\n", Mode::Module, "<test>");
+        assert!(parse_ast.is_some());
+        let targets: Vec<_> = errors.iter().map(|e| e.error.to_string()).collect();
+        assert_eq!(
+            targets,
+            [
+                "cannot assign to literal here. Maybe you meant '==' instead of '='?",
+                "cannot assign to None here. Maybe you meant '==' instead of '='?",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_for_target() {
+        let error = parse_error("for 1 in x:\n    pass\n");
+        assert_eq!(error.offset, TextSize::from(4));
+        assert_eq!(error.error.to_string(), "cannot assign to literal");
+        assert!(
+            parse_error("async def f():\n    async for a, f() in x:\n        pass\n")
+                .error
+                .to_string()
+                .ends_with("function call")
+        );
+        assert!(parse(
+            "for a, (b.c, *d[0]) in x:\n    pass\n",
+            Mode::Module,
+            "<test>"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_with_target() {
+        let error = parse_error("with a as f():\n    pass\n");
+        assert_eq!(error.offset, TextSize::from(10));
+        assert_eq!(error.error.to_string(), "cannot assign to function call");
+        assert!(parse(
+            "with a as (b, c[0]), d:\n    pass\n",
+            Mode::Module,
+            "<test>"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_delete_target() {
+        let error = parse_error("del f()\n");
+        assert_eq!(error.offset, TextSize::from(4));
+        assert_eq!(error.error.to_string(), "cannot delete function call");
+        let error = parse_error("del a, (b, 1)\n");
+        assert_eq!(error.error.to_string(), "cannot delete literal");
+        assert!(parse("del a, (b.c, [d[0]])\n", Mode::Module, "<test>").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_aug_assign_target() {
+        let error = parse_error("None += 1\n");
+        assert_eq!(error.offset, TextSize::from(0));
+        assert_eq!(
+            error.error.to_string(),
+            "'None' is an illegal expression for augmented assignment"
+        );
+        let error = parse_error("a, b += 1\n");
+        assert_eq!(
+            error.error.to_string(),
+            "'tuple' is an illegal expression for augmented assignment"
+        );
+        assert!(parse("a.b += 1\nc[0] -= 1\n", Mode::Module, "<test>").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_annotation_target() {
+        let error = parse_error("1: int = 2\n");
+        assert_eq!(error.offset, TextSize::from(0));
+        assert_eq!(error.error.to_string(), "illegal target for annotation");
+        let error = parse_error("[a, b]: int\n");
+        assert_eq!(
+            error.error.to_string(),
+            "only single target (not list) can be annotated"
+        );
+        assert!(parse("a: int\n(b): int = 1\nc.d: int\n", Mode::Module, "<test>").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_comprehension_target() {
+        let error = parse_error("[x for f() in y]\n");
+        assert_eq!(error.offset, TextSize::from(7));
+        assert_eq!(error.error.to_string(), "cannot assign to function call");
+        let error = parse_error("def f(a={k: v for k, 1 in d}):\n    pass\n");
+        assert_eq!(error.error.to_string(), "cannot assign to literal");
+        let error = parse_error("x = g(y for y in z for (a, b + c) in y)\n");
+        assert_eq!(error.error.to_string(), "cannot assign to operator");
+        let error = parse("{x async for x.y() in z}", Mode::Expression, "<test>").unwrap_err();
+        assert_eq!(error.error.to_string(), "cannot assign to function call");
+        assert!(parse(
+            "[x for a, (b.c, *d[0]) in y if [e for e in a]]\n",
+            Mode::Module,
+            "<test>"
+        )
+        .is_ok());
+    }
+
     #[test]
     fn test_star_index() {
         let source = "\